@@ -9,6 +9,11 @@ fn main() {
     context_benches(&mut c);
     seq_benches::<MmapRegion>(&mut c);
     par_benches::<MmapRegion>(&mut c);
+    #[cfg(all(target_os = "linux", feature = "uffd"))]
+    {
+        seq_benches::<lucet_runtime::UffdRegion>(&mut c);
+        par_benches::<lucet_runtime::UffdRegion>(&mut c);
+    }
 
     c.final_summary();
 }
@@ -200,6 +200,73 @@ fn drop_instance_with_sparse_heap<R: RegionCreate + 'static>(c: &mut Criterion)
     );
 }
 
+/// Instance reset with a large heap, of which only a few pages were written since instantiation.
+///
+/// This is the common case when serving one request per reset: the initial heap is large, but
+/// each run only touches a small part of it.
+fn reset_instance_with_mostly_clean_heap<R: RegionCreate + 'static>(c: &mut Criterion) {
+    fn body(inst: &mut InstanceHandle) {
+        inst.reset().unwrap();
+    }
+
+    let limits = Limits {
+        heap_memory_size: 1024 * 1024 * 1024,
+        ..Limits::default()
+    };
+
+    let region = R::create(1, &limits).unwrap();
+
+    c.bench_function_over_inputs(
+        &format!("reset_instance_with_mostly_clean_heap ({})", R::TYPE_NAME),
+        move |b, &&heap_kb| {
+            let module = large_sparse_heap_mock(heap_kb, 64);
+            b.iter_batched_ref(
+                || {
+                    let mut inst = region.clone().new_instance(module.clone()).unwrap();
+                    // dirty the first and last page, as a guest touching its stack and data might
+                    let heap = inst.heap_mut();
+                    let len = heap.len();
+                    if len > 0 {
+                        heap[0] = 0xFF;
+                        heap[len - 1] = 0xFF;
+                    }
+                    inst
+                },
+                body,
+                criterion::BatchSize::PerIteration,
+            )
+        },
+        SPARSE_HEAP_SIZES_KB,
+    );
+}
+
+/// Instance reset with a large, dense heap.
+fn reset_instance_with_dense_heap<R: RegionCreate + 'static>(c: &mut Criterion) {
+    fn body(inst: &mut InstanceHandle) {
+        inst.reset().unwrap();
+    }
+
+    let limits = Limits {
+        heap_memory_size: 1024 * 1024 * 1024,
+        ..Limits::default()
+    };
+
+    let region = R::create(1, &limits).unwrap();
+
+    c.bench_function_over_inputs(
+        &format!("reset_instance_with_dense_heap ({})", R::TYPE_NAME),
+        move |b, &&heap_kb| {
+            let module = large_dense_heap_mock(heap_kb);
+            b.iter_batched_ref(
+                || region.clone().new_instance(module.clone()).unwrap(),
+                body,
+                criterion::BatchSize::PerIteration,
+            )
+        },
+        DENSE_HEAP_SIZES_KB,
+    );
+}
+
 /// Run a trivial guest function.
 ///
 /// This is primarily a measurement of the signal handler installation and removal, and the context
@@ -414,6 +481,8 @@ pub fn seq_benches<R: RegionCreate + 'static>(c: &mut Criterion) {
     hello_drop_instance::<R>(c);
     drop_instance_with_dense_heap::<R>(c);
     drop_instance_with_sparse_heap::<R>(c);
+    reset_instance_with_mostly_clean_heap::<R>(c);
+    reset_instance_with_dense_heap::<R>(c);
     run_null::<R>(c);
     run_fib::<R>(c);
     run_hello::<R>(c);
@@ -38,7 +38,7 @@ else
     DYLIB_SUFFIX="so"
 fi
 
-BINS="lucet-objdump lucet-validate lucet-wasi lucetc sightglass spec-test wasmonkey"
+BINS="lucet lucet-objdump lucet-run lucet-validate lucet-wasi lucetc sightglass spec-test wasmonkey"
 LIBS="liblucet_runtime.${DYLIB_SUFFIX}"
 DOCS="sightglass/README.md"
 BUNDLE_DOCS="README.md"
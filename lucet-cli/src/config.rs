@@ -0,0 +1,267 @@
+//! Default options for the subcommands, read from a configuration file.
+//!
+//! The configuration is a TOML file with a table per subcommand. Each key is the long name of an
+//! option of that subcommand, and its value is the option's default:
+//!
+//! ```toml
+//! [compile]
+//! opt-level = "speed"
+//! bindings = ["bindings.json", "more-bindings.json"]
+//! count-instructions = true
+//!
+//! [objdump.verify]
+//! pk = "keys/public.key"
+//!
+//! [sign]
+//! sk = "keys/secret.key"
+//! ```
+//!
+//! A string or number is passed as the option's value, an array passes the option once for each
+//! of its elements, `true` passes the option as a flag, and `false` leaves it out. Options given
+//! on the command line by their long name replace their configured defaults.
+//!
+//! The options are passed to the binary straight through, so their names are the binary's own.
+//! A tool with subcommands of its own, such as `lucet-objdump verify`, has a table for each of
+//! them in the table of the subcommand running it, and their defaults go after the tool's
+//! subcommand.
+
+use anyhow::{format_err, Context, Error};
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+/// The configuration file read when none is given, if it exists in the current directory.
+pub const DEFAULT_CONFIG_FILE: &str = "lucet.toml";
+/// The environment variable naming the configuration file to read when none is given.
+pub const CONFIG_ENV_VAR: &str = "LUCET_CONFIG";
+
+#[derive(Debug, Default)]
+pub struct Config {
+    subcommands: Table,
+}
+
+impl Config {
+    /// Read the configuration from `path`, or else from the file named by `LUCET_CONFIG`, or else
+    /// from `lucet.toml` if it exists. Without any of these, the configuration is empty.
+    pub fn load(path: Option<&Path>) -> Result<Self, Error> {
+        let path = match path {
+            Some(path) => path.to_owned(),
+            None => match std::env::var_os(CONFIG_ENV_VAR) {
+                Some(path) => PathBuf::from(path),
+                None if Path::new(DEFAULT_CONFIG_FILE).is_file() => {
+                    PathBuf::from(DEFAULT_CONFIG_FILE)
+                }
+                None => return Ok(Config::default()),
+            },
+        };
+        let contents = std::fs::read_to_string(&path)
+            .with_context(|| format!("reading configuration file {}", path.display()))?;
+        let subcommands = toml::from_str(&contents)
+            .with_context(|| format!("parsing configuration file {}", path.display()))?;
+        Ok(Config { subcommands })
+    }
+
+    /// The arguments to run the binary of `subcommand` with: `args`, with the configured
+    /// defaults inserted before them, or after the first of them if it is one of the binary's
+    /// own `tool_subcommands`.
+    pub fn args(
+        &self,
+        subcommand: &str,
+        tool_subcommands: &[&str],
+        mut args: Vec<String>,
+    ) -> Result<Vec<String>, Error> {
+        let mut all_args = vec![];
+        let mut section = vec![subcommand];
+        if let Some(first) = args.first() {
+            if let Some(tool_subcommand) = tool_subcommands.iter().find(|sub| **sub == *first) {
+                section.push(*tool_subcommand);
+                all_args.push(args.remove(0));
+            }
+        }
+        all_args.extend(self.default_args(&section, &args)?);
+        all_args.extend(args);
+        Ok(all_args)
+    }
+
+    /// The options configured in the table at `section`, a subcommand and then any subcommands
+    /// of its binary, that `args` does not give, as arguments to pass before `args`.
+    ///
+    /// Arguments after a `--` are passed on to the guest, so they are never mistaken for options.
+    fn default_args(&self, section: &[&str], args: &[String]) -> Result<Vec<String>, Error> {
+        let subcommand = section.join(".");
+        let mut options = &self.subcommands;
+        for name in section {
+            options = match options.get(*name) {
+                None => return Ok(vec![]),
+                Some(Value::Table(table)) => table,
+                Some(_) => {
+                    return Err(format_err!(
+                        "`{}` in the configuration must be a table of options",
+                        subcommand
+                    ))
+                }
+            };
+        }
+        let given = args
+            .iter()
+            .take_while(|arg| *arg != "--")
+            .collect::<Vec<_>>();
+
+        let mut defaults = vec![];
+        for (name, value) in options {
+            let option = format!("--{}", name);
+            let with_value = format!("{}=", option);
+            if given
+                .iter()
+                .any(|arg| **arg == option || arg.starts_with(&with_value))
+            {
+                continue;
+            }
+            match value {
+                Value::Boolean(true) => defaults.push(option),
+                Value::Boolean(false) => (),
+                // the options of a subcommand of the binary
+                Value::Table(_) => (),
+                Value::Array(values) => {
+                    for value in values {
+                        defaults.push(option.clone());
+                        defaults.push(scalar(&subcommand, name, value)?);
+                    }
+                }
+                value => {
+                    defaults.push(option);
+                    defaults.push(scalar(&subcommand, name, value)?);
+                }
+            }
+        }
+        Ok(defaults)
+    }
+}
+
+fn scalar(subcommand: &str, name: &str, value: &Value) -> Result<String, Error> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Float(f) => Ok(f.to_string()),
+        _ => Err(format_err!(
+            "`{}.{}` in the configuration must be a string, a number, a boolean, or an array of \
+             strings or numbers",
+            subcommand,
+            name
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(contents: &str) -> Config {
+        Config {
+            subcommands: toml::from_str(contents).unwrap(),
+        }
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn options_become_arguments() {
+        let config = config(
+            r#"
+            [compile]
+            bindings = ["a.json", "b.json"]
+            count-instructions = true
+            canonicalize-nans = false
+            opt-level = "speed"
+            "#,
+        );
+        assert_eq!(
+            config.default_args(&["compile"], &[]).unwrap(),
+            args(&[
+                "--bindings",
+                "a.json",
+                "--bindings",
+                "b.json",
+                "--count-instructions",
+                "--opt-level",
+                "speed"
+            ])
+        );
+        assert!(config.default_args(&["run"], &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn command_line_replaces_defaults() {
+        let config = config(
+            r#"
+            [run]
+            max-heap-size = "1GiB"
+            entrypoint = "_start"
+            "#,
+        );
+        assert_eq!(
+            config
+                .default_args(&["run"], &args(&["--max-heap-size=2GiB", "guest.so"]))
+                .unwrap(),
+            args(&["--entrypoint", "_start"])
+        );
+        // arguments for the guest are not options
+        assert_eq!(
+            config
+                .default_args(&["run"], &args(&["guest.so", "--", "--entrypoint"]))
+                .unwrap(),
+            args(&["--entrypoint", "_start", "--max-heap-size", "1GiB"])
+        );
+    }
+
+    #[test]
+    fn tool_subcommand_defaults_follow_the_tool_subcommand() {
+        let config = config(
+            r#"
+            [objdump]
+            json = true
+
+            [objdump.verify]
+            pk = "public.key"
+            "#,
+        );
+        let objdump = ["bloat", "diff", "disasm", "stack", "verify"];
+        assert_eq!(
+            config
+                .args("objdump", &objdump, args(&["verify", "guest.so"]))
+                .unwrap(),
+            args(&["verify", "--pk", "public.key", "guest.so"])
+        );
+        assert_eq!(
+            config
+                .args("objdump", &objdump, args(&["stack", "guest.so"]))
+                .unwrap(),
+            args(&["stack", "guest.so"])
+        );
+        // without a subcommand of its own, the tool takes the defaults of its table
+        assert_eq!(
+            config
+                .args("objdump", &objdump, args(&["guest.so"]))
+                .unwrap(),
+            args(&["--json", "guest.so"])
+        );
+        assert_eq!(
+            config.args("run", &[], args(&["guest.so"])).unwrap(),
+            args(&["guest.so"])
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_options() {
+        assert!(config("compile = 1")
+            .default_args(&["compile"], &[])
+            .is_err());
+        assert!(config("[compile]\nbindings = [true]")
+            .default_args(&["compile"], &[])
+            .is_err());
+        assert!(config("[objdump]\nverify = 1")
+            .default_args(&["objdump", "verify"], &[])
+            .is_err());
+    }
+}
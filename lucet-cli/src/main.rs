@@ -0,0 +1,182 @@
+//! `lucet`: a single command for the Lucet toolchain.
+//!
+//! Most subcommands run one of the toolchain's other binaries, passing on their arguments after
+//! the defaults from the configuration file, or after the binary's own subcommand and its
+//! defaults. The arguments are not parsed here, so each subcommand takes the options of its
+//! binary, by the binary's names for them. The binaries are looked for next to `lucet` itself
+//! first, and then on the `PATH`. `lucet sign` is implemented here, as there is no separate
+//! binary for it.
+
+mod config;
+
+#[macro_use]
+extern crate clap;
+
+use crate::config::Config;
+use anyhow::{format_err, Error};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use lucetc::signature;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+/// The subcommands that run other binaries: the subcommand, the binary, the binary's own
+/// subcommands, and what it does.
+const TOOLS: &[(&str, &str, &[&str], &str)] = &[
+    (
+        "compile",
+        "lucetc",
+        &[],
+        "Compile a WebAssembly module to native code, with `lucetc`",
+    ),
+    (
+        "run",
+        "lucet-wasi",
+        &[],
+        "Run a compiled WASI program, with `lucet-wasi`",
+    ),
+    (
+        "invoke",
+        "lucet-run",
+        &[],
+        "Call an exported function of a compiled module, with `lucet-run`",
+    ),
+    (
+        "objdump",
+        "lucet-objdump",
+        &["bloat", "diff", "disasm", "stack", "verify"],
+        "Describe a compiled module, with `lucet-objdump`",
+    ),
+    (
+        "validate",
+        "lucet-validate",
+        &[],
+        "Validate a WebAssembly module against a witx interface, with `lucet-validate`",
+    ),
+];
+
+fn main() {
+    // rebuild if env vars used by app_from_crate! change:
+    let _ = include_str!("../Cargo.toml");
+
+    let matches = app().get_matches();
+    if let Err(err) = run(&matches) {
+        eprintln!("Error: {:#}\n", err);
+        process::exit(1);
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    let app = app_from_crate!()
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("FILE")
+                .help(
+                    "read default options from this file, instead of $LUCET_CONFIG or ./lucet.toml",
+                ),
+        );
+    TOOLS
+        .iter()
+        .map(|(name, _, _, about)| (*name, *about))
+        .chain(std::iter::once((
+            "sign",
+            "Sign a compiled module, or generate a key pair to sign with",
+        )))
+        .fold(app, |app, (name, about)| {
+            // the tool parses its own arguments, including `--help`
+            app.subcommand(
+                SubCommand::with_name(name)
+                    .about(about)
+                    .setting(AppSettings::TrailingVarArg)
+                    .setting(AppSettings::AllowLeadingHyphen)
+                    .setting(AppSettings::DisableHelpFlags)
+                    .setting(AppSettings::DisableVersion)
+                    .arg(
+                        Arg::with_name("args")
+                            .multiple(true)
+                            .allow_hyphen_values(true),
+                    ),
+            )
+        })
+}
+
+fn run(matches: &ArgMatches<'_>) -> Result<(), Error> {
+    let config = Config::load(matches.value_of("config").map(Path::new))?;
+    let (subcommand, sub_matches) = matches.subcommand();
+    let args = sub_matches
+        .and_then(|m| m.values_of("args"))
+        .map(|args| args.map(str::to_owned).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    if subcommand == "sign" {
+        let all_args = config.args(subcommand, &[], args)?;
+        return sign(
+            &sign_app().get_matches_from(std::iter::once("lucet sign".to_owned()).chain(all_args)),
+        );
+    }
+    let (_, tool, tool_subcommands, _) = TOOLS
+        .iter()
+        .find(|(name, _, _, _)| *name == subcommand)
+        .expect("subcommands are either tools or `sign`");
+    let all_args = config.args(subcommand, tool_subcommands, args)?;
+    // `exec` only returns if the tool could not be run
+    let err = Command::new(tool_path(tool)).args(all_args).exec();
+    Err(format_err!("could not run `{}`: {}", tool, err))
+}
+
+/// The path of the binary `tool`: next to this one if it is there, or else found on the `PATH`.
+fn tool_path(tool: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(tool)))
+        .filter(|path| path.is_file())
+        .unwrap_or_else(|| PathBuf::from(tool))
+}
+
+fn sign_app() -> App<'static, 'static> {
+    App::new("lucet sign")
+        .about("Sign a compiled module, or generate a key pair to sign with")
+        .arg(
+            Arg::with_name("sk")
+                .long("sk")
+                .takes_value(true)
+                .value_name("SECRET_KEY")
+                .required(true)
+                .help("the secret key to sign with, or to generate; prefix the path with `raw:` for an unencrypted key"),
+        )
+        .arg(
+            Arg::with_name("keygen")
+                .long("keygen")
+                .requires("pk")
+                .help("generate a key pair instead of signing a module"),
+        )
+        .arg(
+            Arg::with_name("pk")
+                .long("pk")
+                .takes_value(true)
+                .value_name("PUBLIC_KEY")
+                .help("where to write the public key of the generated key pair"),
+        )
+        .arg(
+            Arg::with_name("module")
+                .required_unless("keygen")
+                .conflicts_with("keygen")
+                .help("the compiled module to sign, in place"),
+        )
+}
+
+fn sign(matches: &ArgMatches<'_>) -> Result<(), Error> {
+    let sk_path = matches.value_of("sk").expect("secret key is required");
+    if matches.is_present("keygen") {
+        let pk_path = matches.value_of("pk").expect("--keygen requires --pk");
+        signature::keygen(pk_path, sk_path)?;
+        return Ok(());
+    }
+    let module = matches.value_of("module").expect("module is required");
+    signature::sign_module(module, &signature::sk_from_file(sk_path)?)?;
+    Ok(())
+}
@@ -163,8 +163,8 @@ pub fn yielding_hostcall(vmctx: &Vmctx) {
 macro_rules! killswitch_tests {
     ( $TestRegion:path ) => {
         use lucet_runtime::{
-            lucet_hostcall, Error, Instance, InstanceHandle, KillError, KillSuccess, Limits,
-            Region, RegionCreate, RunResult, TerminationDetails, TrapCode,
+            lucet_hostcall, Error, Instance, InstanceGroup, InstanceHandle, KillError, KillSuccess,
+            Limits, Region, RegionCreate, RunResult, TerminationDetails, TrapCode,
         };
         use lucet_runtime_internals::lock_testpoints::{SyncWaiter, Syncpoint};
         use lucet_runtime_tests::build::test_module_c;
@@ -942,5 +942,121 @@ macro_rules! killswitch_tests {
                 run_onetwothree(&mut inst);
             });
         }
+
+        // Terminating a group stops each of its members wherever it is: in a hostcall, yielded, or
+        // not yet running. The termination is permanent, even across resets.
+        #[test]
+        fn group_terminates_every_member() {
+            test_nonex(|| {
+                let module = mock_killswitch_module();
+                let region = <TestRegion as RegionCreate>::create(3, &Limits::default())
+                    .expect("region can be created");
+                let group = InstanceGroup::new();
+                let new_member = || {
+                    region
+                        .new_instance_builder(module.clone())
+                        .with_group(&group)
+                        .build()
+                        .expect("instance can be created")
+                };
+                let mut in_hostcall = new_member();
+                let mut yielded = new_member();
+                let mut not_started = new_member();
+                assert_eq!(group.len(), 3);
+
+                match yielded.run("run_yielding_hostcall", &[]) {
+                    Ok(RunResult::Yielded(_)) => {}
+                    res => panic!("unexpected result: {:?}", res),
+                }
+
+                let hostcall_testpoint = in_hostcall
+                    .lock_testpoints
+                    .instance_exiting_hostcall_before_domain_change
+                    .wait_at();
+                let guest = thread::Builder::new()
+                    .name("guest".to_owned())
+                    .spawn(move || match in_hostcall.run("run_hostcall", &[]) {
+                        Err(Error::RuntimeTerminated(TerminationDetails::Remote)) => {}
+                        res => panic!("unexpected result: {:?}", res),
+                    })
+                    .expect("can spawn thread to run guest");
+
+                hostcall_testpoint.wait_and_then(|| {
+                    assert_eq!(group.terminate(), 3);
+                });
+                guest.join().expect("guest exits without panic");
+                assert!(group.is_terminated());
+
+                match yielded.resume() {
+                    Err(Error::RuntimeTerminated(TerminationDetails::Remote)) => {}
+                    res => panic!("unexpected result: {:?}", res),
+                }
+                match not_started.run("onetwothree", &[]) {
+                    Err(Error::RuntimeTerminated(TerminationDetails::Remote)) => {}
+                    res => panic!("unexpected result: {:?}", res),
+                }
+
+                // Resetting a member does not let it escape the group's termination.
+                not_started.reset().expect("instance resets");
+                match not_started.run("onetwothree", &[]) {
+                    Err(Error::RuntimeTerminated(TerminationDetails::Remote)) => {}
+                    res => panic!("unexpected result: {:?}", res),
+                }
+
+                // Members leave the group when they are dropped.
+                drop(yielded);
+                assert_eq!(group.len(), 1);
+            })
+        }
+
+        // Terminating a group signals members running guest code, and cancels instances that join
+        // the group after it was terminated.
+        #[test]
+        fn group_terminates_member_in_guest() {
+            test_ex(|| {
+                unsafe {
+                    ENTERING_GUEST = Some(Syncpoint::new());
+                }
+                let in_guest = unsafe { ENTERING_GUEST.as_ref().unwrap().wait_at() };
+
+                let module = mock_killswitch_module();
+                let region = <TestRegion as RegionCreate>::create(2, &Limits::default())
+                    .expect("region can be created");
+                let group = InstanceGroup::new();
+                let mut inst = region
+                    .new_instance_builder(module.clone())
+                    .with_group(&group)
+                    .build()
+                    .expect("instance can be created");
+
+                let t = thread::Builder::new()
+                    .name("guest".to_owned())
+                    .spawn(move || match inst.run("infinite_loop", &[]) {
+                        Err(Error::RuntimeTerminated(TerminationDetails::Remote)) => {}
+                        res => panic!("unexpected result: {:?}", res),
+                    })
+                    .expect("can spawn a thread");
+
+                let terminator = in_guest.wait_and_then(|| {
+                    let group = group.clone();
+                    thread::spawn(move || {
+                        assert_eq!(group.terminate(), 1);
+                    })
+                });
+
+                t.join().unwrap();
+                terminator.join().unwrap();
+
+                let mut late = region
+                    .new_instance_builder(module)
+                    .with_group(&group)
+                    .build()
+                    .expect("instance can be created");
+                match late.run("onetwothree", &[]) {
+                    Err(Error::RuntimeTerminated(TerminationDetails::Remote)) => {}
+                    res => panic!("unexpected result: {:?}", res),
+                }
+            })
+        }
     };
 }
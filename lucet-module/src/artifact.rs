@@ -0,0 +1,147 @@
+use crate::error::Error;
+use crate::functions::FunctionSpec;
+use crate::integrity::ModuleIntegrity;
+use crate::module::{SerializedModule, LUCET_MODULE_SYM};
+use crate::module_data::ModuleData;
+use crate::signature::{ModuleSignature, PublicKey};
+use crate::version_info::VersionInfo;
+use byteorder::{ByteOrder, LittleEndian};
+use memoffset::offset_of;
+use object::{Object, ObjectSection};
+use std::mem::size_of;
+
+/// A module compiled by `lucetc`, read from the bytes of its shared object without loading it.
+///
+/// This is the same information `lucet-objdump` prints: the module's version, the module data with
+/// its imports, exports, heap specification, globals and signatures, the function manifest, and
+/// whether the module is signed.
+///
+/// Pointers in the module are read as they are in the file, before the loader relocates them. In
+/// particular, the addresses in the function manifest are offsets from the start of the shared
+/// object, and `FunctionSpec::traps()` must not be called on them.
+#[derive(Debug)]
+pub struct ModuleArtifact<'a> {
+    bytes: &'a [u8],
+    version: VersionInfo,
+    module_data: ModuleData<'a>,
+    function_manifest: Vec<FunctionSpec>,
+}
+
+impl<'a> ModuleArtifact<'a> {
+    /// Read the module in the shared object `bytes`.
+    pub fn parse(bytes: &'a [u8]) -> Result<Self, Error> {
+        let obj = object::File::parse(bytes).map_err(|e| invalid(e.to_string()))?;
+        let mangled_sym = format!("_{}", LUCET_MODULE_SYM);
+        let module_sym = obj
+            .symbols()
+            .map(|(_, sym)| sym)
+            .find(|sym| {
+                sym.name() == Some(LUCET_MODULE_SYM) || sym.name() == Some(mangled_sym.as_str())
+            })
+            .ok_or_else(|| invalid(format!("`{}` symbol not present", LUCET_MODULE_SYM)))?;
+
+        let read = |addr: u64, len: u64| {
+            obj.sections()
+                .find_map(|section| section.data_range(addr, len).ok().flatten())
+                .ok_or_else(|| invalid(format!("no data at {:#x}, of {} bytes", addr, len)))
+        };
+
+        let serialized_module = read(module_sym.address(), size_of::<SerializedModule>() as u64)?;
+        let version =
+            VersionInfo::read_from(&mut &serialized_module[..]).map_err(Error::IOError)?;
+        if !version.format_supported() {
+            return Err(Error::UnsupportedFormatVersion(version.format_version()));
+        }
+        let field = |offset: usize| LittleEndian::read_u64(&serialized_module[offset..]);
+
+        let module_data = ModuleData::deserialize_format(
+            read(
+                field(offset_of!(SerializedModule, module_data_ptr)),
+                field(offset_of!(SerializedModule, module_data_len)),
+            )?,
+            version.format_version(),
+        )?;
+
+        let function_manifest_ptr = field(offset_of!(SerializedModule, function_manifest_ptr));
+        let function_manifest_len = field(offset_of!(SerializedModule, function_manifest_len));
+        let function_manifest = if function_manifest_ptr != 0 {
+            let spec_size = size_of::<FunctionSpec>();
+            read(
+                function_manifest_ptr,
+                function_manifest_len * spec_size as u64,
+            )?
+            .chunks(spec_size)
+            // the layout `lucetc` writes: `code_addr`, `code_len`, padding, `traps_addr`,
+            // `traps_len`
+            .map(|spec| {
+                FunctionSpec::new(
+                    LittleEndian::read_u64(&spec[0..]),
+                    LittleEndian::read_u32(&spec[8..]),
+                    LittleEndian::read_u64(&spec[16..]),
+                    LittleEndian::read_u64(&spec[24..]),
+                )
+            })
+            .collect()
+        } else {
+            vec![]
+        };
+
+        Ok(ModuleArtifact {
+            bytes,
+            version,
+            module_data,
+            function_manifest,
+        })
+    }
+
+    /// The version of `lucetc` that compiled the module, and of the module format.
+    pub fn version(&self) -> &VersionInfo {
+        &self.version
+    }
+
+    /// The module's metadata, including its imports, exports, heap specification, globals and
+    /// signatures.
+    pub fn module_data(&self) -> &ModuleData<'a> {
+        &self.module_data
+    }
+
+    /// The functions compiled into the module, in the order of `ModuleData::function_info()`.
+    pub fn function_manifest(&self) -> &[FunctionSpec] {
+        &self.function_manifest
+    }
+
+    /// Whether the module carries a signature, which may or may not be valid.
+    pub fn is_signed(&self) -> bool {
+        self.module_data
+            .get_module_signature()
+            .iter()
+            .any(|byte| *byte != 0)
+    }
+
+    /// The ID of the key that signed the module, as `minisign` prints it, if the module is signed.
+    ///
+    /// This is read from the signature without checking it; see `verify_signature()`.
+    pub fn signer_key_id(&self) -> Option<String> {
+        if !self.is_signed() {
+            return None;
+        }
+        // a minisign signature is the 2-byte algorithm, the 8-byte key ID and the signature itself
+        let key_id = LittleEndian::read_u64(&self.module_data.get_module_signature()[2..10]);
+        Some(format!("{:016X}", key_id))
+    }
+
+    /// Check the module's signature against `pk`, as `lucet-runtime` does when loading it.
+    pub fn verify_signature(&self, pk: &PublicKey) -> Result<(), Error> {
+        ModuleSignature::verify_bytes(self.bytes.to_vec(), pk, &self.module_data)
+    }
+
+    /// Check that the shared object has the integrity hash recorded in its module data, as
+    /// `lucet-runtime` does when asked to.
+    pub fn check_integrity(&self) -> Result<(), Error> {
+        ModuleIntegrity::verify_bytes(self.bytes.to_vec(), &self.module_data)
+    }
+}
+
+fn invalid(reason: String) -> Error {
+    Error::InvalidArtifact(reason)
+}
@@ -0,0 +1,30 @@
+/// CustomSection is a custom section of the WebAssembly module that `lucetc` preserved in the
+/// module data: its name and its contents, as they were in the module.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CustomSection<'a> {
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+
+/// OwnedCustomSection is like [`CustomSection`](../struct.CustomSection.html), except it is not
+/// zero-copy deserializable.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct OwnedCustomSection {
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl OwnedCustomSection {
+    pub fn new(name: String, data: Vec<u8>) -> Self {
+        Self { name, data }
+    }
+
+    /// Create a [`CustomSection`](../struct.CustomSection.html) backed by the values in this
+    /// `OwnedCustomSection`.
+    pub fn to_ref<'a>(&'a self) -> CustomSection<'a> {
+        CustomSection {
+            name: &self.name,
+            data: &self.data,
+        }
+    }
+}
@@ -0,0 +1,895 @@
+//! The encoding of module data in module formats 2 to 10.
+//!
+//! Module data starts with a header of the magic bytes `LMOD` and the format version as a `u32`,
+//! followed by sections. Each section is a `u32` identifier, the `u32` length of its contents, the
+//! contents, and zeros up to the next multiple of 8 bytes from the start of the module data.
+//! Sections appear in increasing order of their identifiers, at most once each, and a reader skips
+//! the sections it does not know.
+//!
+//! All integers are little-endian, and nothing is read from aligned memory, so module data can be
+//! read wherever it is loaded. Within a section:
+//!
+//! - an `Option` is a `u8` of 0 for `None`, or of 1 followed by the value;
+//! - a `bool` is a `u8` of 0 or 1;
+//! - a string is its `u32` length in bytes followed by its UTF-8 bytes;
+//! - a list is its `u32` number of elements followed by the elements.
+//!
+//! The layout of each section is documented beside its identifier. Format 3 differs from format 2
+//! in that the initial heap can be compressed, format 4 from format 3 in that it is recorded in
+//! chunks of a size the module chooses, format 5 from format 4 in that a module can have several
+//! linear memories, format 6 from format 5 in that it has a section describing its tables,
+//! format 7 from format 6 in that it has a section for the integrity hash of the shared object,
+//! format 8 from format 7 in that it has a section for preserved custom sections, format 9 from
+//! format 8 in that it has more feature bits, and format 10 from format 9 in that imported globals
+//! record their type.
+
+use crate::{
+    custom_sections::CustomSection,
+    functions::{
+        ExportFunction, FunctionIndex, FunctionMetadata, ImportFunction, UniqueSignatureIndex,
+    },
+    globals::{Global, GlobalDef, GlobalSpec},
+    integrity::INTEGRITY_HASH_BYTES,
+    linear_memory::{check_chunk_size, HeapSpec, LinearMemorySpec, SparseData},
+    module_data::{ModuleData, ModuleFeatures},
+    tables::{ElementSegment, ExportTable, TableSpec},
+    types::{Signature, ValueType},
+    Error,
+};
+use byteorder::{ByteOrder, LittleEndian};
+use minisign::SignatureBones;
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+const MAGIC: &[u8; 4] = b"LMOD";
+
+/// In format 5, a list of linear memories; before, a single one that is the first of the module.
+/// Each is its `HeapSpec` as `reserved_size: u64, guard_size: u64, initial_size: u64,
+/// max_size: Option<u64>`, then its initial contents. From format 4 on, these are the `u32` size
+/// of a chunk, the `u32` number of 4096-byte pages they cover, and a list of chunks, the last of
+/// which may extend past those pages with zeros. Before format 4, they are a list of chunks of a
+/// page each. A chunk is a `u8` kind of 0 for a chunk of zeros, of 1 followed by the bytes of the
+/// chunk, or, from format 3 on, of 2 followed by a `u32` length and that many bytes of a zstd
+/// frame the chunk decompresses from. Absent when the module has no linear memory.
+const MEMORY: u32 = 1;
+/// A list of globals, each a `u8` kind followed by its definition, then `mutable: bool` and its
+/// export names as a list of strings. Kind 0 is a definition with a `u8` type of 0 for `i32`, 1
+/// for `i64`, 2 for `f32` or 3 for `f64`, and the bits of the initial value as a `u64`. Kind 1
+/// is an import of a `module` string and a `field` string, then, from format 10 on, its type as an
+/// `Option`, which is `None` only for module data converted from an earlier format.
+const GLOBALS: u32 = 2;
+/// A list of `FunctionMetadata`, each `signature: u32, name: Option<string>`.
+const FUNCTIONS: u32 = 3;
+/// A list of `ImportFunction`, each `fn_idx: u32, module: string, name: string`.
+const IMPORT_FUNCTIONS: u32 = 4;
+/// A list of `ExportFunction`, each `fn_idx: u32` and a list of names.
+const EXPORT_FUNCTIONS: u32 = 5;
+/// A list of `Signature`, each a list of parameter types and `ret_ty: Option` of a type. A type is
+/// a `u8` of 0 for `i32`, 1 for `i64`, 2 for `f32` or 3 for `f64`.
+const SIGNATURES: u32 = 6;
+/// The module's minisign signature, of `SignatureBones::BYTES` bytes, all zero when the module is
+/// unsigned.
+const MODULE_SIGNATURE: u32 = 7;
+/// The features as bits of a `u32`, from the lowest: `sse3`, `ssse3`, `sse41`, `sse42`, `avx`,
+/// `bmi1`, `bmi2`, `lzcnt`, `popcnt` and `instruction_count`, then, from format 9 on, `simd`,
+/// `threads`, `nan_canonicalization` and `spectre_mitigations`.
+const FEATURES: u32 = 8;
+/// The `u32` index of the start function. Absent when the module has none.
+const START_FUNCTION: u32 = 9;
+/// The names the linear memory is exported under, as a list of strings.
+const EXPORT_MEMORY_NAMES: u32 = 10;
+/// A list of `ExportTable`, each `table_idx: u32` and a list of names.
+const EXPORT_TABLES: u32 = 11;
+/// From format 6 on, a list of `TableSpec`, each `table_idx: u32`, `minimum: u32`,
+/// `maximum: Option<u32>`, a list of element segments, each an `offset: u32` and a list of `u32`
+/// function indices, and the `Option<u32>` function index of each element as a list.
+const TABLES: u32 = 12;
+/// From format 7 on, the SHA-256 hash of the shared object, of `INTEGRITY_HASH_BYTES` bytes, all
+/// zero when it has not been recorded.
+const INTEGRITY_HASH: u32 = 13;
+/// From format 8 on, a list of `CustomSection`, each `name: string`, then the `u32` length of its
+/// data followed by the bytes of the data.
+const CUSTOM_SECTIONS: u32 = 14;
+
+const PAGE_SIZE: usize = 4096;
+
+const CHUNK_ZEROS: u8 = 0;
+const CHUNK_RAW: u8 = 1;
+const CHUNK_ZSTD: u8 = 2;
+
+/// The number of feature bits in the module format `format_version`.
+fn feature_bits(format_version: u16) -> usize {
+    if format_version >= 9 {
+        14
+    } else {
+        10
+    }
+}
+
+/// Encode module data in module format 2 to 10, compressing the chunks of the initial heap that
+/// get smaller if `compress` is `true` and the format supports it.
+///
+/// Before format 4, the initial heap is recorded in pages, whatever its chunk size, before format
+/// 5 only the first linear memory is recorded, before format 6 no table is described, before format
+/// 7 there is no integrity hash, before format 8 no custom section is preserved, before format 9
+/// only the CPU features and instruction counting are recorded, and before format 10 the types of
+/// imported globals are not recorded.
+pub(crate) fn encode(module_data: &ModuleData<'_>, format_version: u16, compress: bool) -> Vec<u8> {
+    assert!(format_version >= 2 && format_version <= 10);
+    let compress = compress && format_version >= 3;
+    let mut w = Writer { buf: vec![] };
+    w.buf.extend_from_slice(MAGIC);
+    w.u32(format_version as u32);
+
+    let linear_memories = module_data.linear_memories();
+    if !linear_memories.is_empty() {
+        w.section(MEMORY, |w| {
+            if format_version >= 5 {
+                w.list(linear_memories, |w, memory| {
+                    write_linear_memory(w, memory, format_version, compress)
+                });
+            } else {
+                write_linear_memory(w, &linear_memories[0], format_version, compress);
+            }
+        });
+    }
+    w.section(GLOBALS, |w| {
+        w.list(module_data.globals_spec(), |w, spec| {
+            match spec.global() {
+                Global::Def(def) => {
+                    w.u8(0);
+                    let (ty, bits) = match *def {
+                        GlobalDef::I32(v) => (ValueType::I32, v as u32 as u64),
+                        GlobalDef::I64(v) => (ValueType::I64, v as u64),
+                        GlobalDef::F32(v) => (ValueType::F32, v.to_bits() as u64),
+                        GlobalDef::F64(v) => (ValueType::F64, v.to_bits()),
+                    };
+                    w.value_type(ty);
+                    w.u64(bits);
+                }
+                Global::Import { module, field } => {
+                    w.u8(1);
+                    w.str(module);
+                    w.str(field);
+                    if format_version >= 10 {
+                        w.option(spec.import_type(), Writer::value_type);
+                    }
+                }
+            }
+            w.bool(spec.is_mutable());
+            w.strs(spec.export_names());
+        })
+    });
+    w.section(FUNCTIONS, |w| {
+        w.list(module_data.function_info(), |w, f| {
+            w.u32(f.signature.as_u32());
+            w.option(f.name, Writer::str);
+        })
+    });
+    w.section(IMPORT_FUNCTIONS, |w| {
+        w.list(module_data.import_functions(), |w, f| {
+            w.u32(f.fn_idx.as_u32());
+            w.str(f.module);
+            w.str(f.name);
+        })
+    });
+    w.section(EXPORT_FUNCTIONS, |w| {
+        w.list(module_data.export_functions(), |w, f| {
+            w.u32(f.fn_idx.as_u32());
+            w.strs(&f.names);
+        })
+    });
+    w.section(SIGNATURES, |w| {
+        w.list(module_data.signatures(), |w, sig| {
+            w.list(&sig.params, |w, ty| w.value_type(*ty));
+            w.option(sig.ret_ty, Writer::value_type);
+        })
+    });
+    w.section(MODULE_SIGNATURE, |w| {
+        w.bytes(module_data.get_module_signature())
+    });
+    w.section(FEATURES, |w| {
+        let bits = module_data.features().bits()[..feature_bits(format_version)]
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (i, (_, set))| bits | (*set as u32) << i);
+        w.u32(bits);
+    });
+    if let Some(start_function) = module_data.get_start_func_id() {
+        w.section(START_FUNCTION, |w| w.u32(start_function.as_u32()));
+    }
+    w.section(EXPORT_MEMORY_NAMES, |w| {
+        w.strs(module_data.export_memory_names())
+    });
+    w.section(EXPORT_TABLES, |w| {
+        w.list(module_data.export_tables(), |w, table| {
+            w.u32(table.table_idx);
+            w.strs(&table.names);
+        })
+    });
+    if format_version >= 6 {
+        w.section(TABLES, |w| {
+            w.list(module_data.tables(), |w, table| {
+                w.u32(table.table_idx);
+                w.u32(table.minimum);
+                w.option(table.maximum, Writer::u32);
+                w.list(&table.segments, |w, segment| {
+                    w.u32(segment.offset);
+                    w.list(&segment.functions, |w, func| w.u32(func.as_u32()));
+                });
+                w.list(&table.elements, |w, func| {
+                    w.option(*func, |w, func| w.u32(func.as_u32()))
+                });
+            })
+        });
+    }
+    if format_version >= 7 {
+        w.section(INTEGRITY_HASH, |w| {
+            w.bytes(module_data.get_integrity_hash())
+        });
+    }
+    if format_version >= 8 {
+        w.section(CUSTOM_SECTIONS, |w| {
+            w.list(module_data.custom_sections(), |w, section| {
+                w.str(section.name);
+                w.u32(list_len(section.data.len()));
+                w.bytes(section.data);
+            })
+        });
+    }
+    w.buf
+}
+
+/// Decode module data in module format 2 to 10, borrowing its strings and uncompressed pages
+/// from `buf`.
+pub(crate) fn decode(buf: &[u8], format_version: u16) -> Result<ModuleData<'_>, Error> {
+    let mut r = Reader { buf, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC {
+        return Err(malformed("missing magic bytes"));
+    }
+    if r.u32()? != format_version as u32 {
+        return Err(malformed("header is of another module format"));
+    }
+
+    let mut linear_memories = vec![];
+    let mut globals_spec = vec![];
+    let mut function_info = vec![];
+    let mut import_functions = vec![];
+    let mut export_functions = vec![];
+    let mut signatures = vec![];
+    let mut module_signature = [0u8; SignatureBones::BYTES];
+    let mut features = ModuleFeatures::none();
+    let mut start_function = None;
+    let mut export_memory_names = vec![];
+    let mut export_tables = vec![];
+    let mut tables = vec![];
+    let mut integrity_hash = [0u8; INTEGRITY_HASH_BYTES];
+    let mut custom_sections = vec![];
+
+    let mut last_id = 0;
+    while !r.is_empty() {
+        let id = r.u32()?;
+        if id <= last_id {
+            return Err(malformed("sections are out of order"));
+        }
+        last_id = id;
+        let len = r.u32()? as usize;
+        let mut s = Reader {
+            buf: r.take(len)?,
+            pos: 0,
+        };
+        r.take(padding(r.pos))?;
+
+        match id {
+            MEMORY => {
+                linear_memories = read_linear_memories(&mut s, format_version)?
+                    .into_iter()
+                    .map(|(memory, _)| memory)
+                    .collect();
+            }
+            GLOBALS => {
+                globals_spec = s.list(|s| {
+                    let mut import_type = None;
+                    let global = match s.u8()? {
+                        0 => {
+                            let ty = s.value_type()?;
+                            let bits = s.u64()?;
+                            Global::Def(match ty {
+                                ValueType::I32 => GlobalDef::I32(bits as u32 as i32),
+                                ValueType::I64 => GlobalDef::I64(bits as i64),
+                                ValueType::F32 => GlobalDef::F32(f32::from_bits(bits as u32)),
+                                ValueType::F64 => GlobalDef::F64(f64::from_bits(bits)),
+                            })
+                        }
+                        1 => {
+                            let global = Global::Import {
+                                module: s.str()?,
+                                field: s.str()?,
+                            };
+                            if format_version >= 10 {
+                                import_type = s.option(Reader::value_type)?;
+                            }
+                            global
+                        }
+                        _ => return Err(malformed("unknown kind of global")),
+                    };
+                    let mutable = s.bool()?;
+                    let spec = GlobalSpec::new(global, s.strs()?).with_mutability(mutable);
+                    Ok(match import_type {
+                        Some(import_type) => spec.with_import_type(import_type),
+                        None => spec,
+                    })
+                })?
+            }
+            FUNCTIONS => {
+                function_info = s.list(|s| {
+                    Ok(FunctionMetadata {
+                        signature: UniqueSignatureIndex::from_u32(s.u32()?),
+                        name: s.option(Reader::str)?,
+                    })
+                })?
+            }
+            IMPORT_FUNCTIONS => {
+                import_functions = s.list(|s| {
+                    Ok(ImportFunction {
+                        fn_idx: FunctionIndex::from_u32(s.u32()?),
+                        module: s.str()?,
+                        name: s.str()?,
+                    })
+                })?
+            }
+            EXPORT_FUNCTIONS => {
+                export_functions = s.list(|s| {
+                    Ok(ExportFunction {
+                        fn_idx: FunctionIndex::from_u32(s.u32()?),
+                        names: s.strs()?,
+                    })
+                })?
+            }
+            SIGNATURES => {
+                signatures = s.list(|s| {
+                    Ok(Signature {
+                        params: s.list(Reader::value_type)?,
+                        ret_ty: s.option(Reader::value_type)?,
+                    })
+                })?
+            }
+            MODULE_SIGNATURE => {
+                module_signature.copy_from_slice(s.take(SignatureBones::BYTES)?);
+            }
+            FEATURES => {
+                let bits = s.u32()?;
+                if bits >> feature_bits(format_version) != 0 {
+                    return Err(malformed("module requires unknown features"));
+                }
+                let bit = |i: u32| bits & (1 << i) != 0;
+                features.sse3 = bit(0);
+                features.ssse3 = bit(1);
+                features.sse41 = bit(2);
+                features.sse42 = bit(3);
+                features.avx = bit(4);
+                features.bmi1 = bit(5);
+                features.bmi2 = bit(6);
+                features.lzcnt = bit(7);
+                features.popcnt = bit(8);
+                features.instruction_count = bit(9);
+                features.simd = bit(10);
+                features.threads = bit(11);
+                features.nan_canonicalization = bit(12);
+                features.spectre_mitigations = bit(13);
+            }
+            START_FUNCTION => start_function = Some(FunctionIndex::from_u32(s.u32()?)),
+            EXPORT_MEMORY_NAMES => export_memory_names = s.strs()?,
+            EXPORT_TABLES => {
+                export_tables = s.list(|s| {
+                    Ok(ExportTable {
+                        table_idx: s.u32()?,
+                        names: s.strs()?,
+                    })
+                })?
+            }
+            TABLES => {
+                tables = s.list(|s| {
+                    Ok(TableSpec {
+                        table_idx: s.u32()?,
+                        minimum: s.u32()?,
+                        maximum: s.option(Reader::u32)?,
+                        segments: s.list(|s| {
+                            Ok(ElementSegment {
+                                offset: s.u32()?,
+                                functions: s.list(|s| s.u32().map(FunctionIndex::from_u32))?,
+                            })
+                        })?,
+                        elements: s.list(|s| s.option(|s| s.u32().map(FunctionIndex::from_u32)))?,
+                    })
+                })?
+            }
+            INTEGRITY_HASH => {
+                integrity_hash.copy_from_slice(s.take(INTEGRITY_HASH_BYTES)?);
+            }
+            CUSTOM_SECTIONS => {
+                custom_sections = s.list(|s| {
+                    let name = s.str()?;
+                    let len = s.u32()? as usize;
+                    Ok(CustomSection {
+                        name,
+                        data: s.take(len)?,
+                    })
+                })?
+            }
+            // sections added later, that this reader can do without
+            _ => continue,
+        }
+        if !s.is_empty() {
+            return Err(malformed("section is longer than its contents"));
+        }
+    }
+
+    Ok(ModuleData::new(
+        None,
+        globals_spec,
+        function_info,
+        import_functions,
+        export_functions,
+        signatures,
+        features,
+        start_function,
+    )
+    .with_linear_memories(linear_memories)
+    .with_module_signature(module_signature)
+    .with_export_memory_names(export_memory_names)
+    .with_export_tables(export_tables)
+    .with_tables(tables)
+    .with_integrity_hash(integrity_hash)
+    .with_custom_sections(custom_sections))
+}
+
+/// Write one linear memory of the `MEMORY` section.
+fn write_linear_memory(
+    w: &mut Writer,
+    memory: &LinearMemorySpec<'_>,
+    format_version: u16,
+    compress: bool,
+) {
+    let heap = &memory.heap;
+    let sparse_data = &memory.initializer;
+    w.u64(heap.reserved_size);
+    w.u64(heap.guard_size);
+    w.u64(heap.initial_size);
+    w.option(heap.max_size, Writer::u64);
+    let chunk_size = if format_version >= 4 {
+        w.u32(sparse_data.chunk_size());
+        w.u32(list_len(sparse_data.len()));
+        sparse_data.chunk_size() as usize
+    } else {
+        PAGE_SIZE
+    };
+    w.list(
+        &to_chunks(sparse_data, chunk_size),
+        |w, chunk| match chunk {
+            None => w.u8(CHUNK_ZEROS),
+            Some(chunk) => match zstd_chunk(chunk, compress) {
+                Some(compressed) => {
+                    w.u8(CHUNK_ZSTD);
+                    w.u32(list_len(compressed.len()));
+                    w.bytes(&compressed);
+                }
+                None => {
+                    w.u8(CHUNK_RAW);
+                    w.bytes(chunk);
+                }
+            },
+        },
+    );
+}
+
+/// Read the linear memories of the `MEMORY` section, each with the number of bytes that each chunk
+/// of its initial heap takes, or `None` for chunks of zeros.
+fn read_linear_memories<'a>(
+    s: &mut Reader<'a>,
+    format_version: u16,
+) -> Result<Vec<(LinearMemorySpec<'a>, Vec<Option<u64>>)>, Error> {
+    if format_version >= 5 {
+        s.list(|s| read_linear_memory(s, format_version))
+    } else {
+        Ok(vec![read_linear_memory(s, format_version)?])
+    }
+}
+
+/// Read one linear memory of the `MEMORY` section.
+fn read_linear_memory<'a>(
+    s: &mut Reader<'a>,
+    format_version: u16,
+) -> Result<(LinearMemorySpec<'a>, Vec<Option<u64>>), Error> {
+    let heap = HeapSpec {
+        reserved_size: s.u64()?,
+        guard_size: s.u64()?,
+        initial_size: s.u64()?,
+        max_size: s.option(Reader::u64)?,
+    };
+    let (chunk_size, page_count) = if format_version >= 4 {
+        let chunk_size = s.u32()?;
+        check_chunk_size(chunk_size)?;
+        (chunk_size, Some(s.u32()? as usize))
+    } else {
+        (PAGE_SIZE as u32, None)
+    };
+    let chunk_len = chunk_size as usize;
+    let mut chunk_sizes = vec![];
+    let chunks = s.list(|s| {
+        let start = s.pos;
+        let chunk = match s.u8()? {
+            CHUNK_ZEROS => None,
+            CHUNK_RAW => Some(Cow::Borrowed(s.take(chunk_len)?)),
+            CHUNK_ZSTD if format_version >= 3 => {
+                let len = s.u32()? as usize;
+                match zstd::block::decompress(s.take(len)?, chunk_len) {
+                    Ok(chunk) if chunk.len() == chunk_len => Some(Cow::Owned(chunk)),
+                    _ => return Err(malformed("chunk does not decompress")),
+                }
+            }
+            _ => return Err(malformed("unknown kind of chunk")),
+        };
+        chunk_sizes.push(chunk.as_ref().map(|_| (s.pos - start) as u64));
+        Ok(chunk)
+    })?;
+    let mut pages = to_pages(chunks, chunk_len);
+    if let Some(page_count) = page_count {
+        if pages.len() < page_count {
+            return Err(malformed("chunks do not cover the initial heap"));
+        }
+        pages.truncate(page_count);
+    }
+    let memory = LinearMemorySpec {
+        heap,
+        initializer: SparseData::from_pages(pages)?.with_chunk_size(chunk_size)?,
+    };
+    Ok((memory, chunk_sizes))
+}
+
+/// The number of bytes that each chunk of the initial heap of each linear memory takes in module
+/// data in module format 2 to 10, or `None` for chunks of zeros.
+pub(crate) fn chunk_sizes(buf: &[u8], format_version: u16) -> Result<Vec<Vec<Option<u64>>>, Error> {
+    let mut r = Reader { buf, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC || r.u32()? != format_version as u32 {
+        return Err(malformed("header is of another module format"));
+    }
+    while !r.is_empty() {
+        let id = r.u32()?;
+        let len = r.u32()? as usize;
+        let mut s = Reader {
+            buf: r.take(len)?,
+            pos: 0,
+        };
+        if id == MEMORY {
+            return Ok(read_linear_memories(&mut s, format_version)?
+                .into_iter()
+                .map(|(_, chunk_sizes)| chunk_sizes)
+                .collect());
+        }
+        r.take(padding(r.pos))?;
+    }
+    Ok(vec![])
+}
+
+/// Replace the signature in encoded module data, leaving every other byte as it is.
+pub(crate) fn patch_module_signature(
+    buf: &[u8],
+    module_signature: &[u8],
+    format_version: u16,
+) -> Result<Vec<u8>, Error> {
+    patch_section(buf, MODULE_SIGNATURE, module_signature, format_version)?
+        .ok_or_else(|| malformed("module data has no module signature section"))
+}
+
+/// Replace the integrity hash in encoded module data, leaving every other byte as it is.
+pub(crate) fn patch_integrity_hash(
+    buf: &[u8],
+    integrity_hash: &[u8],
+    format_version: u16,
+) -> Result<Vec<u8>, Error> {
+    patch_section(buf, INTEGRITY_HASH, integrity_hash, format_version)?
+        .ok_or_else(|| malformed("module data has no integrity hash section"))
+}
+
+/// Replace the contents of the section `id` with `contents`, which must be as long, or return
+/// `None` if there is no such section.
+fn patch_section(
+    buf: &[u8],
+    id: u32,
+    contents: &[u8],
+    format_version: u16,
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut r = Reader { buf, pos: 0 };
+    if r.take(MAGIC.len())? != MAGIC || r.u32()? != format_version as u32 {
+        return Err(malformed("header is of another module format"));
+    }
+    while !r.is_empty() {
+        let section_id = r.u32()?;
+        let len = r.u32()? as usize;
+        if section_id == id {
+            if len != contents.len() {
+                return Err(malformed("patched section has the wrong length"));
+            }
+            let mut patched = buf.to_vec();
+            patched[r.pos..r.pos + len].copy_from_slice(contents);
+            return Ok(Some(patched));
+        }
+        r.take(len)?;
+        r.take(padding(r.pos))?;
+    }
+    Ok(None)
+}
+
+/// Divide the pages of `sparse_data` into chunks of `chunk_size` bytes.
+///
+/// Chunks of zeros are left out when a page is divided, but a page of zeros is kept when the chunks
+/// are pages, so that module data written in an older format reads back exactly the same.
+fn to_chunks<'a>(sparse_data: &'a SparseData<'_>, chunk_size: usize) -> Vec<Option<Cow<'a, [u8]>>> {
+    let pages = sparse_data.pages();
+    if chunk_size == PAGE_SIZE {
+        pages
+            .iter()
+            .map(|page| page.as_deref().map(Cow::from))
+            .collect()
+    } else if chunk_size < PAGE_SIZE {
+        let mut chunks = vec![];
+        for page in pages {
+            match page {
+                None => chunks.extend(std::iter::repeat(None).take(PAGE_SIZE / chunk_size)),
+                Some(page) => {
+                    chunks.extend(page.chunks(chunk_size).map(|c| non_zero(c).map(Cow::from)))
+                }
+            }
+        }
+        chunks
+    } else {
+        pages
+            .chunks(chunk_size / PAGE_SIZE)
+            .map(|group| {
+                if group.iter().all(Option::is_none) {
+                    return None;
+                }
+                let mut chunk = vec![0; chunk_size];
+                for (page, contents) in group.iter().zip(chunk.chunks_mut(PAGE_SIZE)) {
+                    if let Some(page) = page {
+                        contents.copy_from_slice(page);
+                    }
+                }
+                Some(Cow::Owned(chunk))
+            })
+            .collect()
+    }
+}
+
+/// Join or divide `chunks` of `chunk_size` bytes back into pages.
+fn to_pages(chunks: Vec<Option<Cow<'_, [u8]>>>, chunk_size: usize) -> Vec<Option<Cow<'_, [u8]>>> {
+    if chunk_size == PAGE_SIZE {
+        chunks
+    } else if chunk_size < PAGE_SIZE {
+        chunks
+            .chunks(PAGE_SIZE / chunk_size)
+            .map(|group| {
+                if group.iter().all(Option::is_none) {
+                    return None;
+                }
+                let mut page = vec![0; PAGE_SIZE];
+                for (chunk, contents) in group.iter().zip(page.chunks_mut(chunk_size)) {
+                    if let Some(chunk) = chunk {
+                        contents.copy_from_slice(chunk);
+                    }
+                }
+                Some(Cow::Owned(page))
+            })
+            .collect()
+    } else {
+        let mut pages = vec![];
+        for chunk in chunks {
+            match chunk {
+                None => pages.extend(std::iter::repeat(None).take(chunk_size / PAGE_SIZE)),
+                Some(Cow::Borrowed(chunk)) => pages.extend(
+                    chunk
+                        .chunks(PAGE_SIZE)
+                        .map(|page| non_zero(page).map(Cow::Borrowed)),
+                ),
+                Some(Cow::Owned(chunk)) => pages.extend(
+                    chunk
+                        .chunks(PAGE_SIZE)
+                        .map(|page| non_zero(page).map(|page| Cow::Owned(page.to_vec()))),
+                ),
+            }
+        }
+        pages
+    }
+}
+
+fn non_zero(bytes: &[u8]) -> Option<&[u8]> {
+    if bytes.iter().all(|byte| *byte == 0) {
+        None
+    } else {
+        Some(bytes)
+    }
+}
+
+/// The zstd frame of `chunk`, if `compress` is `true` and the frame is smaller than the chunk.
+fn zstd_chunk(chunk: &[u8], compress: bool) -> Option<Vec<u8>> {
+    if !compress {
+        return None;
+    }
+    zstd::block::compress(chunk, zstd::DEFAULT_COMPRESSION_LEVEL)
+        .ok()
+        .filter(|compressed| compressed.len() < chunk.len())
+}
+
+fn malformed(reason: &'static str) -> Error {
+    Error::MalformedModuleData(reason)
+}
+
+/// The number of zeros that pad a section ending at `pos` to a multiple of 8 bytes.
+fn padding(pos: usize) -> usize {
+    (8 - pos % 8) % 8
+}
+
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn section(&mut self, id: u32, contents: impl FnOnce(&mut Writer)) {
+        self.u32(id);
+        let len_pos = self.buf.len();
+        self.u32(0);
+        contents(self);
+        let len = self.buf.len() - len_pos - 4;
+        LittleEndian::write_u32(&mut self.buf[len_pos..], len as u32);
+        let padded_len = self.buf.len() + padding(self.buf.len());
+        self.buf.resize(padded_len, 0);
+    }
+
+    fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    fn u32(&mut self, v: u32) {
+        let mut bytes = [0; 4];
+        LittleEndian::write_u32(&mut bytes, v);
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    fn u64(&mut self, v: u64) {
+        let mut bytes = [0; 8];
+        LittleEndian::write_u64(&mut bytes, v);
+        self.buf.extend_from_slice(&bytes);
+    }
+
+    fn bool(&mut self, v: bool) {
+        self.u8(v as u8);
+    }
+
+    fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+
+    fn str(&mut self, v: &str) {
+        self.u32(list_len(v.len()));
+        self.bytes(v.as_bytes());
+    }
+
+    fn strs(&mut self, v: &[&str]) {
+        self.list(v, |w, s| w.str(s));
+    }
+
+    fn value_type(&mut self, v: ValueType) {
+        self.u8(match v {
+            ValueType::I32 => 0,
+            ValueType::I64 => 1,
+            ValueType::F32 => 2,
+            ValueType::F64 => 3,
+        });
+    }
+
+    fn option<T>(&mut self, v: Option<T>, mut write: impl FnMut(&mut Writer, T)) {
+        match v {
+            None => self.u8(0),
+            Some(v) => {
+                self.u8(1);
+                write(self, v);
+            }
+        }
+    }
+
+    fn list<T>(&mut self, v: &[T], mut write: impl FnMut(&mut Writer, &T)) {
+        self.u32(list_len(v.len()));
+        for elem in v {
+            write(self, elem);
+        }
+    }
+}
+
+fn list_len(len: usize) -> u32 {
+    u32::try_from(len).expect("lists and strings in module data are shorter than 4GiB")
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .buf
+            .get(self.pos..)
+            .and_then(|rest| rest.get(..len))
+            .ok_or_else(|| malformed("unexpected end of module data"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(LittleEndian::read_u32(self.take(4)?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        Ok(LittleEndian::read_u64(self.take(8)?))
+    }
+
+    fn bool(&mut self) -> Result<bool, Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(malformed("invalid bool")),
+        }
+    }
+
+    fn str(&mut self) -> Result<&'a str, Error> {
+        let len = self.u32()? as usize;
+        std::str::from_utf8(self.take(len)?).map_err(|_| malformed("string is not UTF-8"))
+    }
+
+    fn strs(&mut self) -> Result<Vec<&'a str>, Error> {
+        self.list(Reader::str)
+    }
+
+    fn value_type(&mut self) -> Result<ValueType, Error> {
+        match self.u8()? {
+            0 => Ok(ValueType::I32),
+            1 => Ok(ValueType::I64),
+            2 => Ok(ValueType::F32),
+            3 => Ok(ValueType::F64),
+            _ => Err(malformed("unknown value type")),
+        }
+    }
+
+    fn option<T>(
+        &mut self,
+        read: impl FnOnce(&mut Reader<'a>) -> Result<T, Error>,
+    ) -> Result<Option<T>, Error> {
+        match self.u8()? {
+            0 => Ok(None),
+            1 => read(self).map(Some),
+            _ => Err(malformed("invalid option")),
+        }
+    }
+
+    fn list<T>(
+        &mut self,
+        mut read: impl FnMut(&mut Reader<'a>) -> Result<T, Error>,
+    ) -> Result<Vec<T>, Error> {
+        // the length is not trusted to preallocate with
+        let len = self.u32()?;
+        let mut elems = vec![];
+        for _ in 0..len {
+            elems.push(read(self)?);
+        }
+        Ok(elems)
+    }
+}
@@ -5,10 +5,20 @@ use thiserror::Error;
 pub enum Error {
     #[error("Deserialization error")]
     DeserializationError(#[source] bincode::Error),
+    #[error("Invalid module artifact: {0}")]
+    InvalidArtifact(String),
     #[error("I/O error")]
     IOError(#[source] std::io::Error),
     #[error("Sparse data contained a page with length other than 4096")]
     IncorrectPageSize,
+    #[error("Sparse data chunk size {0} is not a power of two from 512 to 65536")]
+    IncorrectChunkSize(u32),
+    #[error("Module integrity hash does not match its shared object")]
+    IntegrityHashMismatch,
+    #[error("Malformed module data: {0}")]
+    MalformedModuleData(&'static str),
+    #[error("Module has no integrity hash")]
+    MissingIntegrityHash,
     #[error("Module signature error")]
     ModuleSignatureError(#[source] minisign::PError),
     #[error("Parse error at {key}::{value:?}")]
@@ -27,6 +37,8 @@ pub enum Error {
     },
     #[error("Serialization error")]
     SerializationError(#[source] bincode::Error),
+    #[error("Unsupported module format version {0}")]
+    UnsupportedFormatVersion(u16),
     #[error("Unknown module for symbol `{module}::{symbol}")]
     UnknownModule { module: String, symbol: String },
     #[error("Unknown symbol `{module}::{symbol}`")]
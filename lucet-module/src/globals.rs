@@ -1,3 +1,4 @@
+use crate::types::ValueType;
 use serde::{Deserialize, Serialize};
 
 /// A WebAssembly global along with its export specification.
@@ -10,16 +11,35 @@ pub struct GlobalSpec<'a> {
     #[serde(borrow)]
     global: Global<'a>,
     export_names: Vec<&'a str>,
+    mutable: bool,
+    /// From module format 10 on, the type of an imported global.
+    #[serde(skip)]
+    import_type: Option<ValueType>,
 }
 
 impl<'a> GlobalSpec<'a> {
+    /// Create a new mutable global.
     pub fn new(global: Global<'a>, export_names: Vec<&'a str>) -> Self {
         Self {
             global,
             export_names,
+            mutable: true,
+            import_type: None,
         }
     }
 
+    /// Set whether the global may be written to after initialization.
+    pub fn with_mutability(mut self, mutable: bool) -> Self {
+        self.mutable = mutable;
+        self
+    }
+
+    /// Set the type of an imported global.
+    pub fn with_import_type(mut self, import_type: ValueType) -> Self {
+        self.import_type = Some(import_type);
+        self
+    }
+
     /// Create a new global definition with an initial value and export names.
     pub fn new_def(init_val: i64, export_names: Vec<&'a str>) -> Self {
         Self::new(Global::Def(GlobalDef::I64(init_val)), export_names)
@@ -41,6 +61,17 @@ impl<'a> GlobalSpec<'a> {
     pub fn is_internal(&self) -> bool {
         self.export_names.is_empty()
     }
+
+    pub fn is_mutable(&self) -> bool {
+        self.mutable
+    }
+
+    /// The type of the global, if it is imported and the module records it.
+    ///
+    /// Modules in formats before 10 do not record the types of their imported globals.
+    pub fn import_type(&self) -> Option<ValueType> {
+        self.import_type
+    }
 }
 
 /// A WebAssembly global is either defined locally, or is defined in relation to a field of another
@@ -65,6 +96,15 @@ pub enum GlobalDef {
 }
 
 impl GlobalDef {
+    pub fn value_type(&self) -> ValueType {
+        match self {
+            GlobalDef::I32(_) => ValueType::I32,
+            GlobalDef::I64(_) => ValueType::I64,
+            GlobalDef::F32(_) => ValueType::F32,
+            GlobalDef::F64(_) => ValueType::F64,
+        }
+    }
+
     pub fn init_val(&self) -> GlobalValue {
         match self {
             GlobalDef::I32(i) => GlobalValue { i_32: *i },
@@ -112,16 +152,33 @@ impl std::fmt::Debug for GlobalValue {
 pub struct OwnedGlobalSpec {
     global: OwnedGlobal,
     export_names: Vec<String>,
+    mutable: bool,
+    import_type: Option<ValueType>,
 }
 
 impl OwnedGlobalSpec {
+    /// Create a new mutable global.
     pub fn new(global: OwnedGlobal, export_names: Vec<String>) -> Self {
         Self {
             global,
             export_names,
+            mutable: true,
+            import_type: None,
         }
     }
 
+    /// Set whether the global may be written to after initialization.
+    pub fn with_mutability(mut self, mutable: bool) -> Self {
+        self.mutable = mutable;
+        self
+    }
+
+    /// Set the type of an imported global.
+    pub fn with_import_type(mut self, import_type: ValueType) -> Self {
+        self.import_type = Some(import_type);
+        self
+    }
+
     /// Create a new global definition with an initial value and export names.
     pub fn new_def(init_val: i64, export_names: Vec<String>) -> Self {
         Self::new(OwnedGlobal::Def(GlobalDef::I64(init_val)), export_names)
@@ -135,10 +192,15 @@ impl OwnedGlobalSpec {
     /// Create a [`GlobalSpec`](../struct.GlobalSpec.html) backed by the values in this
     /// `OwnedGlobalSpec`.
     pub fn to_ref<'a>(&'a self) -> GlobalSpec<'a> {
-        GlobalSpec::new(
+        let spec = GlobalSpec::new(
             self.global.to_ref(),
             self.export_names.iter().map(|x| x.as_str()).collect(),
         )
+        .with_mutability(self.mutable);
+        match self.import_type {
+            Some(import_type) => spec.with_import_type(import_type),
+            None => spec,
+        }
     }
 }
 
@@ -0,0 +1,85 @@
+use crate::error::Error::{self, IOError};
+use crate::signature::RawModuleAndData;
+use crate::ModuleData;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+/// The length of a module's integrity hash, a SHA-256 digest.
+pub const INTEGRITY_HASH_BYTES: usize = 32;
+
+/// The integrity hash of a module: a SHA-256 hash of its shared object, which `lucetc` records in
+/// the module data.
+///
+/// The hash covers every byte of the shared object, with the integrity hash and the module
+/// signature cleared, so it catches artifacts that were corrupted or only partly written, whether
+/// or not they are signed. A module is signed after its hash is recorded, and its signature covers
+/// the hash.
+///
+/// Anyone who can modify a shared object can also recompute its hash, so unlike
+/// [`ModuleSignature`](struct.ModuleSignature.html), this says nothing about where the module
+/// comes from.
+pub struct ModuleIntegrity;
+
+impl ModuleIntegrity {
+    /// Record the integrity hash of the shared object at `path` in its module data.
+    pub fn embed<P: AsRef<Path>>(path: P) -> Result<(), Error> {
+        let raw_module_and_data = RawModuleAndData::from_file(&path).map_err(IOError)?;
+        let integrity_hash = Self::hash(&raw_module_and_data)?;
+        let patched_module_data_bin = ModuleData::patch_integrity_hash(
+            raw_module_and_data.module_data_bin(),
+            &integrity_hash,
+            raw_module_and_data.format_version,
+        )?;
+        raw_module_and_data
+            .write_patched_module_data(&path, &patched_module_data_bin)
+            .map_err(IOError)
+    }
+
+    /// Check that the shared object at `so_path` has the integrity hash recorded in
+    /// `module_data`, its module data.
+    pub fn verify<P: AsRef<Path>>(so_path: P, module_data: &ModuleData<'_>) -> Result<(), Error> {
+        let raw_module_and_data = RawModuleAndData::from_file(&so_path).map_err(IOError)?;
+        Self::verify_raw(&raw_module_and_data, module_data)
+    }
+
+    /// Like `verify()`, for a module already read from its file.
+    pub(crate) fn verify_bytes(
+        obj_bin: Vec<u8>,
+        module_data: &ModuleData<'_>,
+    ) -> Result<(), Error> {
+        let raw_module_and_data = RawModuleAndData::from_bytes(obj_bin).map_err(IOError)?;
+        Self::verify_raw(&raw_module_and_data, module_data)
+    }
+
+    fn verify_raw(
+        raw_module_and_data: &RawModuleAndData,
+        module_data: &ModuleData<'_>,
+    ) -> Result<(), Error> {
+        if !module_data.has_integrity_hash() {
+            return Err(Error::MissingIntegrityHash);
+        }
+        if Self::hash(raw_module_and_data)?[..] != *module_data.get_integrity_hash() {
+            return Err(Error::IntegrityHashMismatch);
+        }
+        Ok(())
+    }
+
+    fn hash(raw_module_and_data: &RawModuleAndData) -> Result<[u8; INTEGRITY_HASH_BYTES], Error> {
+        let format_version = raw_module_and_data.format_version;
+        let cleared_module_data_bin = ModuleData::clear_module_signature(
+            &ModuleData::clear_integrity_hash(
+                raw_module_and_data.module_data_bin(),
+                format_version,
+            )?,
+            format_version,
+        )?;
+        let mut obj_bin = raw_module_and_data.obj_bin.clone();
+        let module_data_offset = raw_module_and_data.module_data_offset;
+        obj_bin[module_data_offset..module_data_offset + raw_module_and_data.module_data_len]
+            .copy_from_slice(&cleared_module_data_bin);
+
+        let mut integrity_hash = [0u8; INTEGRITY_HASH_BYTES];
+        integrity_hash.copy_from_slice(&Sha256::digest(&obj_bin));
+        Ok(integrity_hash)
+    }
+}
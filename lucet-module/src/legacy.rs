@@ -0,0 +1,51 @@
+//! Module data in the formats of earlier versions of `lucetc`.
+//!
+//! Each format keeps only the types whose serialized representation has changed since. The
+//! conversions to and from the current types are in `module_data`, next to the fields they fill.
+
+/// Format 0, of the modules compiled before formats were versioned.
+pub(crate) mod v0 {
+    use crate::{
+        functions::{ExportFunction, FunctionIndex, FunctionMetadata, ImportFunction},
+        globals::Global,
+        linear_memory::LinearMemorySpec,
+        module_data::ModuleFeatures,
+        types::Signature,
+    };
+    use minisign::SignatureBones;
+    use serde::{Deserialize, Serialize};
+    use serde_big_array::big_array;
+
+    big_array! {
+        BigArray;
+        SignatureBones::BYTES,
+    }
+
+    /// Module data had no export names for the linear memory and tables.
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct ModuleData<'a> {
+        #[serde(borrow)]
+        pub linear_memory: Option<LinearMemorySpec<'a>>,
+        #[serde(borrow)]
+        pub globals_spec: Vec<GlobalSpec<'a>>,
+        #[serde(borrow)]
+        pub function_info: Vec<FunctionMetadata<'a>>,
+        #[serde(borrow)]
+        pub import_functions: Vec<ImportFunction<'a>>,
+        #[serde(borrow)]
+        pub export_functions: Vec<ExportFunction<'a>>,
+        pub signatures: Vec<Signature>,
+        #[serde(with = "BigArray")]
+        pub module_signature: [u8; SignatureBones::BYTES],
+        pub features: ModuleFeatures,
+        pub start_function: Option<FunctionIndex>,
+    }
+
+    /// Globals did not record whether they are mutable, and were all treated as mutable.
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct GlobalSpec<'a> {
+        #[serde(borrow)]
+        pub global: Global<'a>,
+        pub export_names: Vec<&'a str>,
+    }
+}
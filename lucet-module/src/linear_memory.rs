@@ -1,5 +1,6 @@
 use crate::Error;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 
 /// Specification of the linear memory of a module
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -103,8 +104,13 @@ impl HeapSpec {
 /// A sparse representation of a Lucet module's initial heap.
 ///
 /// The lifetime parameter exists to support zero-copy deserialization for the `&[u8]` slices
-/// representing non-zero pages. For a variant with owned `Vec<u8>` pages, see
-/// [`OwnedSparseData`](owned/struct.OwnedSparseData.html).
+/// representing non-zero pages. Pages that were compressed in the module are decompressed when
+/// the module data is deserialized, and owned instead. For a variant with owned `Vec<u8>` pages,
+/// see [`OwnedSparseData`](owned/struct.OwnedSparseData.html).
+///
+/// In memory, the initial heap is always divided into host pages. The module records it in chunks
+/// of `chunk_size()` bytes instead: larger chunks suit large contiguous data, and smaller ones
+/// leave out more of the zeros between scattered data.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SparseData<'a> {
     /// Indices into the vector correspond to the offset, in host page (4k) increments, from the
@@ -116,16 +122,49 @@ pub struct SparseData<'a> {
     /// The deserializer of this datastructure does not make sure the 4k invariant holds,
     /// but the constructor on the serializier side does.
     #[serde(borrow)]
-    pages: Vec<Option<&'a [u8]>>,
+    pages: Vec<Option<Cow<'a, [u8]>>>,
+    /// The formats that serialize this type with serde record the initial heap in pages.
+    #[serde(skip, default = "default_chunk_size")]
+    chunk_size: u32,
+}
+
+fn default_chunk_size() -> u32 {
+    SparseData::DEFAULT_CHUNK_SIZE
+}
+
+/// Check that `chunk_size` is a power of two from `SparseData::MIN_CHUNK_SIZE` to
+/// `SparseData::MAX_CHUNK_SIZE`.
+pub(crate) fn check_chunk_size(chunk_size: u32) -> Result<(), Error> {
+    if chunk_size.is_power_of_two()
+        && chunk_size >= SparseData::MIN_CHUNK_SIZE
+        && chunk_size <= SparseData::MAX_CHUNK_SIZE
+    {
+        Ok(())
+    } else {
+        Err(Error::IncorrectChunkSize(chunk_size))
+    }
 }
 
 impl<'a> SparseData<'a> {
+    /// The size of the chunks the initial heap is recorded in, unless set otherwise: a host page.
+    pub const DEFAULT_CHUNK_SIZE: u32 = 4096;
+    /// The smallest chunk size.
+    pub const MIN_CHUNK_SIZE: u32 = 512;
+    /// The largest chunk size, of a WebAssembly page, so that chunks always divide the initial
+    /// heap.
+    pub const MAX_CHUNK_SIZE: u32 = 64 * 1024;
+
     /// Create a new `SparseData` from its constituent pages.
     ///
     /// Entries in the `pages` argument which are `Some` must contain a slice of exactly the host
     /// page size (4096), otherwise this function returns `Error::IncorrectPageSize`. Entries which
     /// are `None` are interpreted as empty pages, which will be zeroed by the runtime.
     pub fn new(pages: Vec<Option<&'a [u8]>>) -> Result<Self, Error> {
+        Self::from_pages(pages.into_iter().map(|page| page.map(Cow::from)).collect())
+    }
+
+    /// Like `new()`, for pages that may be owned.
+    pub(crate) fn from_pages(pages: Vec<Option<Cow<'a, [u8]>>>) -> Result<Self, Error> {
         if !pages.iter().all(|page| match page {
             Some(contents) => contents.len() == 4096,
             None => true,
@@ -133,15 +172,32 @@ impl<'a> SparseData<'a> {
             return Err(Error::IncorrectPageSize);
         }
 
-        Ok(Self { pages })
+        Ok(Self {
+            pages,
+            chunk_size: Self::DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Record the initial heap in chunks of `chunk_size` bytes, which must be a power of two from
+    /// `MIN_CHUNK_SIZE` to `MAX_CHUNK_SIZE`, otherwise this function returns
+    /// `Error::IncorrectChunkSize`.
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Result<Self, Error> {
+        check_chunk_size(chunk_size)?;
+        self.chunk_size = chunk_size;
+        Ok(self)
     }
 
-    pub fn pages(&self) -> &[Option<&'a [u8]>] {
+    /// The size of the chunks the module records the initial heap in.
+    pub fn chunk_size(&self) -> u32 {
+        self.chunk_size
+    }
+
+    pub fn pages(&self) -> &[Option<Cow<'a, [u8]>>] {
         &self.pages
     }
 
-    pub fn get_page(&self, offset: usize) -> &Option<&'a [u8]> {
-        self.pages.get(offset).unwrap_or(&None)
+    pub fn get_page(&self, offset: usize) -> Option<&[u8]> {
+        self.pages.get(offset).and_then(|page| page.as_deref())
     }
 
     pub fn len(&self) -> usize {
@@ -155,6 +211,7 @@ impl<'a> SparseData<'a> {
 /// representing pages. This type is useful when directly building up a value to be serialized.
 pub struct OwnedSparseData {
     pages: Vec<Option<Vec<u8>>>,
+    chunk_size: u32,
 }
 
 impl OwnedSparseData {
@@ -170,7 +227,18 @@ impl OwnedSparseData {
         }) {
             return Err(Error::IncorrectPageSize);
         }
-        Ok(Self { pages })
+        Ok(Self {
+            pages,
+            chunk_size: SparseData::DEFAULT_CHUNK_SIZE,
+        })
+    }
+
+    /// Record the initial heap in chunks of `chunk_size` bytes; see
+    /// [`SparseData::with_chunk_size`](../struct.SparseData.html#method.with_chunk_size).
+    pub fn with_chunk_size(mut self, chunk_size: u32) -> Result<Self, Error> {
+        check_chunk_size(chunk_size)?;
+        self.chunk_size = chunk_size;
+        Ok(self)
     }
 
     /// Create a [`SparseData`](../struct.SparseData.html) backed by the values in this
@@ -185,6 +253,7 @@ impl OwnedSparseData {
                 })
                 .collect(),
         )
+        .and_then(|sparse_data| sparse_data.with_chunk_size(self.chunk_size))
         .expect("SparseData invariant enforced by OwnedSparseData constructor")
     }
 }
@@ -1,11 +1,16 @@
 use crate::{
+    custom_sections::CustomSection,
+    encoding,
     functions::{
         ExportFunction, FunctionIndex, FunctionMetadata, ImportFunction, OwnedFunctionMetadata,
     },
     globals::GlobalSpec,
+    integrity::INTEGRITY_HASH_BYTES,
+    legacy::v0,
     linear_memory::{HeapSpec, LinearMemorySpec, SparseData},
+    tables::{ExportTable, TableSpec},
     types::Signature,
-    Error,
+    Error, MODULE_FORMAT_VERSION,
 };
 use derivative::Derivative;
 use minisign::SignatureBones;
@@ -30,8 +35,9 @@ big_array! {
 #[derive(Derivative, Serialize, Deserialize)]
 #[derivative(Debug)]
 pub struct ModuleData<'a> {
-    #[serde(borrow)]
-    linear_memory: Option<LinearMemorySpec<'a>>,
+    /// Format 1 records only the first linear memory, as an `Option`.
+    #[serde(borrow, with = "first_linear_memory")]
+    linear_memories: Vec<LinearMemorySpec<'a>>,
     #[serde(borrow)]
     globals_spec: Vec<GlobalSpec<'a>>,
     #[serde(borrow)]
@@ -46,6 +52,20 @@ pub struct ModuleData<'a> {
     module_signature: [u8; SignatureBones::BYTES],
     features: ModuleFeatures,
     start_function: Option<FunctionIndex>,
+    #[serde(borrow)]
+    export_memory_names: Vec<&'a str>,
+    #[serde(borrow)]
+    export_tables: Vec<ExportTable<'a>>,
+    /// Format 1 does not record tables.
+    #[serde(skip)]
+    tables: Vec<TableSpec>,
+    /// Formats before 7 do not record an integrity hash.
+    #[serde(skip)]
+    #[derivative(Debug = "ignore")]
+    integrity_hash: [u8; INTEGRITY_HASH_BYTES],
+    /// Formats before 8 do not record custom sections.
+    #[serde(skip)]
+    custom_sections: Vec<CustomSection<'a>>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -60,6 +80,18 @@ pub struct ModuleFeatures {
     pub lzcnt: bool,
     pub popcnt: bool,
     pub instruction_count: bool,
+    /// From module format 9 on, whether the module uses WebAssembly SIMD instructions.
+    #[serde(skip)]
+    pub simd: bool,
+    /// From module format 9 on, whether the module uses WebAssembly shared memories and atomics.
+    #[serde(skip)]
+    pub threads: bool,
+    /// From module format 9 on, whether floating-point NaNs were canonicalized.
+    #[serde(skip)]
+    pub nan_canonicalization: bool,
+    /// From module format 9 on, whether heap accesses were compiled with Spectre mitigations.
+    #[serde(skip)]
+    pub spectre_mitigations: bool,
     _hidden: (),
 }
 
@@ -76,9 +108,42 @@ impl ModuleFeatures {
             lzcnt: false,
             popcnt: false,
             instruction_count: false,
+            simd: false,
+            threads: false,
+            nan_canonicalization: false,
+            spectre_mitigations: false,
             _hidden: (),
         }
     }
+
+    /// The names of the features the module was compiled with, in the order of the bits of the
+    /// features section of the module data.
+    pub fn enabled(&self) -> Vec<&'static str> {
+        self.bits()
+            .iter()
+            .filter(|(_, set)| *set)
+            .map(|(name, _)| *name)
+            .collect()
+    }
+
+    pub(crate) fn bits(&self) -> [(&'static str, bool); 14] {
+        [
+            ("sse3", self.sse3),
+            ("ssse3", self.ssse3),
+            ("sse41", self.sse41),
+            ("sse42", self.sse42),
+            ("avx", self.avx),
+            ("bmi1", self.bmi1),
+            ("bmi2", self.bmi2),
+            ("lzcnt", self.lzcnt),
+            ("popcnt", self.popcnt),
+            ("instruction_count", self.instruction_count),
+            ("simd", self.simd),
+            ("threads", self.threads),
+            ("nan_canonicalization", self.nan_canonicalization),
+            ("spectre_mitigations", self.spectre_mitigations),
+        ]
+    }
 }
 
 impl<'a> ModuleData<'a> {
@@ -93,7 +158,7 @@ impl<'a> ModuleData<'a> {
         start_function: Option<FunctionIndex>,
     ) -> Self {
         Self {
-            linear_memory,
+            linear_memories: linear_memory.into_iter().collect(),
             globals_spec,
             function_info,
             import_functions,
@@ -102,23 +167,76 @@ impl<'a> ModuleData<'a> {
             module_signature: [0u8; SignatureBones::BYTES],
             features,
             start_function,
+            export_memory_names: vec![],
+            export_tables: vec![],
+            tables: vec![],
+            integrity_hash: [0u8; INTEGRITY_HASH_BYTES],
+            custom_sections: vec![],
         }
     }
 
+    /// Set the linear memories of the module, in the order of their WebAssembly indices, replacing
+    /// the one given to `new()`.
+    pub fn with_linear_memories(mut self, linear_memories: Vec<LinearMemorySpec<'a>>) -> Self {
+        self.linear_memories = linear_memories;
+        self
+    }
+
+    /// Set the names the module's linear memory is exported under.
+    pub fn with_export_memory_names(mut self, export_memory_names: Vec<&'a str>) -> Self {
+        self.export_memory_names = export_memory_names;
+        self
+    }
+
+    /// Set the tables the module exports.
+    pub fn with_export_tables(mut self, export_tables: Vec<ExportTable<'a>>) -> Self {
+        self.export_tables = export_tables;
+        self
+    }
+
+    /// Set the descriptions of the module's tables.
+    pub fn with_tables(mut self, tables: Vec<TableSpec>) -> Self {
+        self.tables = tables;
+        self
+    }
+
+    /// Set the custom sections of the WebAssembly module to preserve.
+    pub fn with_custom_sections(mut self, custom_sections: Vec<CustomSection<'a>>) -> Self {
+        self.custom_sections = custom_sections;
+        self
+    }
+
+    pub(crate) fn with_module_signature(
+        mut self,
+        module_signature: [u8; SignatureBones::BYTES],
+    ) -> Self {
+        self.module_signature = module_signature;
+        self
+    }
+
+    pub(crate) fn with_integrity_hash(
+        mut self,
+        integrity_hash: [u8; INTEGRITY_HASH_BYTES],
+    ) -> Self {
+        self.integrity_hash = integrity_hash;
+        self
+    }
+
+    /// The linear memories of the module, in the order of their WebAssembly indices.
+    pub fn linear_memories(&self) -> &[LinearMemorySpec<'a>] {
+        &self.linear_memories
+    }
+
+    /// The heap specification of the first linear memory.
     pub fn heap_spec(&self) -> Option<&HeapSpec> {
-        if let Some(ref linear_memory) = self.linear_memory {
-            Some(&linear_memory.heap)
-        } else {
-            None
-        }
+        self.linear_memories.first().map(|memory| &memory.heap)
     }
 
+    /// The initial contents of the first linear memory.
     pub fn sparse_data(&self) -> Option<&SparseData<'a>> {
-        if let Some(ref linear_memory) = self.linear_memory {
-            Some(&linear_memory.initializer)
-        } else {
-            None
-        }
+        self.linear_memories
+            .first()
+            .map(|memory| &memory.initializer)
     }
 
     pub fn globals_spec(&self) -> &[GlobalSpec<'a>] {
@@ -137,6 +255,44 @@ impl<'a> ModuleData<'a> {
         &self.export_functions
     }
 
+    /// The names the module's linear memory is exported under.
+    pub fn export_memory_names(&self) -> &[&str] {
+        &self.export_memory_names
+    }
+
+    pub fn export_tables(&self) -> &[ExportTable<'_>] {
+        &self.export_tables
+    }
+
+    /// The descriptions of the module's tables, including the function in each of their initial
+    /// elements.
+    ///
+    /// This is empty for modules compiled in a module format before 6.
+    pub fn tables(&self) -> &[TableSpec] {
+        &self.tables
+    }
+
+    /// The description of the table `table_idx`, if the module records it.
+    /// The custom sections of the WebAssembly module that `lucetc` preserved, in the order they
+    /// appear in the module.
+    pub fn custom_sections(&self) -> &[CustomSection<'a>] {
+        &self.custom_sections
+    }
+
+    /// The contents of the first preserved custom section named `name`.
+    pub fn get_custom_section(&self, name: &str) -> Option<&'a [u8]> {
+        self.custom_sections
+            .iter()
+            .find(|section| section.name == name)
+            .map(|section| section.data)
+    }
+
+    pub fn get_table(&self, table_idx: u32) -> Option<&TableSpec> {
+        self.tables
+            .iter()
+            .find(|table| table.table_idx == table_idx)
+    }
+
     // Function index here is a different index space than `get_func_from_idx`, which
     // uses function index as an index into a table of function elements.
     //
@@ -165,44 +321,237 @@ impl<'a> ModuleData<'a> {
         &self.module_signature
     }
 
+    /// The integrity hash `lucetc` recorded for the module's shared object, all zero if there is
+    /// none.
+    pub fn get_integrity_hash(&self) -> &[u8] {
+        &self.integrity_hash
+    }
+
+    /// Whether `lucetc` recorded an integrity hash for the module's shared object.
+    pub fn has_integrity_hash(&self) -> bool {
+        self.integrity_hash.iter().any(|byte| *byte != 0)
+    }
+
     pub fn features(&self) -> &ModuleFeatures {
         &self.features
     }
 
+    /// Replace the signature in module data serialized in the format `format_version`, keeping
+    /// that format.
+    ///
+    /// From format 2 on, only the bytes of the signature section change, so pages compressed by
+    /// another version of zstd stay as they were.
     pub fn patch_module_signature(
         module_data_bin: &'a [u8],
         module_signature: &[u8],
+        format_version: u16,
     ) -> Result<Vec<u8>, Error> {
         assert_eq!(module_signature.len(), SignatureBones::BYTES);
-        let mut module_data = Self::deserialize(module_data_bin)?;
+        if format_version >= 2 && format_version <= MODULE_FORMAT_VERSION {
+            return encoding::patch_module_signature(
+                module_data_bin,
+                module_signature,
+                format_version,
+            );
+        }
+        let mut module_data = Self::deserialize_format(module_data_bin, format_version)?;
         module_data
             .module_signature
             .copy_from_slice(module_signature);
-        let patched_module_data_bin = module_data.serialize()?;
+        let patched_module_data_bin = module_data.serialize_format(format_version)?;
         assert_eq!(patched_module_data_bin.len(), module_data_bin.len());
         Ok(patched_module_data_bin)
     }
 
-    pub fn clear_module_signature(module_data_bin: &'a [u8]) -> Result<Vec<u8>, Error> {
+    pub fn clear_module_signature(
+        module_data_bin: &'a [u8],
+        format_version: u16,
+    ) -> Result<Vec<u8>, Error> {
         let module_signature = vec![0u8; SignatureBones::BYTES];
-        Self::patch_module_signature(module_data_bin, &module_signature)
+        Self::patch_module_signature(module_data_bin, &module_signature, format_version)
     }
 
-    /// Serialize to [`bincode`](https://github.com/TyOverby/bincode).
+    /// Replace the integrity hash in module data serialized in the format `format_version`,
+    /// leaving every other byte as it is.
+    ///
+    /// Only format 7 and later have room for an integrity hash.
+    pub fn patch_integrity_hash(
+        module_data_bin: &'a [u8],
+        integrity_hash: &[u8],
+        format_version: u16,
+    ) -> Result<Vec<u8>, Error> {
+        assert_eq!(integrity_hash.len(), INTEGRITY_HASH_BYTES);
+        if format_version >= 7 && format_version <= MODULE_FORMAT_VERSION {
+            encoding::patch_integrity_hash(module_data_bin, integrity_hash, format_version)
+        } else {
+            Err(Error::UnsupportedFormatVersion(format_version))
+        }
+    }
+
+    /// Clear the integrity hash in module data serialized in the format `format_version`, which
+    /// is left as it is in formats without one.
+    pub fn clear_integrity_hash(
+        module_data_bin: &'a [u8],
+        format_version: u16,
+    ) -> Result<Vec<u8>, Error> {
+        if format_version >= 7 {
+            let integrity_hash = [0u8; INTEGRITY_HASH_BYTES];
+            Self::patch_integrity_hash(module_data_bin, &integrity_hash, format_version)
+        } else {
+            Ok(module_data_bin.to_vec())
+        }
+    }
+
+    /// Serialize in the current module format, `MODULE_FORMAT_VERSION`.
+    ///
+    /// The layout of the format is documented in the `lucet-module` book chapter.
     pub fn serialize(&self) -> Result<Vec<u8>, Error> {
-        bincode::serialize(self).map_err(Error::SerializationError)
+        Ok(encoding::encode(self, MODULE_FORMAT_VERSION, false))
     }
 
-    /// Deserialize from [`bincode`](https://github.com/TyOverby/bincode).
+    /// Serialize in the current module format, compressing with zstd the chunks of the initial
+    /// heap that get smaller for it.
+    ///
+    /// The chunks are decompressed when the module data is deserialized, so a module trades the
+    /// size of its shared object for the time to decompress them when it is loaded.
+    pub fn serialize_compressed(&self) -> Result<Vec<u8>, Error> {
+        Ok(encoding::encode(self, MODULE_FORMAT_VERSION, true))
+    }
+
+    /// Deserialize from the current module format, `MODULE_FORMAT_VERSION`.
     pub fn deserialize(buf: &'a [u8]) -> Result<ModuleData<'a>, Error> {
-        bincode::deserialize(buf).map_err(Error::DeserializationError)
+        encoding::decode(buf, MODULE_FORMAT_VERSION)
+    }
+
+    /// Serialize in the module format `format_version`, one of `MODULE_FORMAT_VERSIONS`.
+    ///
+    /// Formats 1 and 0 are the [`bincode`](https://github.com/TyOverby/bincode) serializations
+    /// of these types and of the ones in `legacy::v0`. Older formats leave out what they cannot
+    /// represent: format 0 makes every global mutable, and drops the export names of the linear
+    /// memory and tables, formats before 5 drop every linear memory but the first, formats before 6
+    /// drop the descriptions of tables, formats before 7 drop the integrity hash, formats before 8
+    /// drop the custom sections, and formats before 10 drop the types of imported globals.
+    pub fn serialize_format(&self, format_version: u16) -> Result<Vec<u8>, Error> {
+        match format_version {
+            10 | 9 | 8 | 7 | 6 | 5 | 4 | 3 | 2 => Ok(encoding::encode(self, format_version, false)),
+            1 => bincode::serialize(self).map_err(Error::SerializationError),
+            0 => bincode::serialize(&self.to_v0()).map_err(Error::SerializationError),
+            _ => Err(Error::UnsupportedFormatVersion(format_version)),
+        }
+    }
+
+    /// Deserialize from the module format `format_version`, one of `MODULE_FORMAT_VERSIONS`.
+    pub fn deserialize_format(buf: &'a [u8], format_version: u16) -> Result<ModuleData<'a>, Error> {
+        match format_version {
+            10 | 9 | 8 | 7 | 6 | 5 | 4 | 3 | 2 => encoding::decode(buf, format_version),
+            1 => bincode::deserialize(buf).map_err(Error::DeserializationError),
+            0 => bincode::deserialize(buf)
+                .map(Self::from_v0)
+                .map_err(Error::DeserializationError),
+            _ => Err(Error::UnsupportedFormatVersion(format_version)),
+        }
+    }
+
+    /// The number of bytes that each chunk of the initial heap of each linear memory takes in
+    /// `buf`, module data in the module format `format_version`, as stored, and so possibly
+    /// compressed, or `None` for a chunk of zeros.
+    ///
+    /// The chunks are of the `chunk_size()` of the memory's initializer.
+    pub fn initial_heap_chunk_sizes(
+        buf: &[u8],
+        format_version: u16,
+    ) -> Result<Vec<Vec<Option<u64>>>, Error> {
+        match format_version {
+            10 | 9 | 8 | 7 | 6 | 5 | 4 | 3 | 2 => encoding::chunk_sizes(buf, format_version),
+            1 | 0 => Ok(ModuleData::deserialize_format(buf, format_version)?
+                .linear_memories()
+                .iter()
+                .map(|memory| {
+                    memory
+                        .initializer
+                        .pages()
+                        .iter()
+                        .map(|page| {
+                            page.as_ref()
+                                .map(|_| bincode::serialized_size(page).unwrap_or(0))
+                        })
+                        .collect()
+                })
+                .collect()),
+            _ => Err(Error::UnsupportedFormatVersion(format_version)),
+        }
+    }
+
+    fn from_v0(module_data: v0::ModuleData<'a>) -> Self {
+        let globals_spec = module_data
+            .globals_spec
+            .into_iter()
+            .map(|spec| GlobalSpec::new(spec.global, spec.export_names))
+            .collect();
+        Self {
+            module_signature: module_data.module_signature,
+            ..Self::new(
+                module_data.linear_memory,
+                globals_spec,
+                module_data.function_info,
+                module_data.import_functions,
+                module_data.export_functions,
+                module_data.signatures,
+                module_data.features,
+                module_data.start_function,
+            )
+        }
+    }
+
+    fn to_v0(&self) -> v0::ModuleData<'_> {
+        let globals_spec = self
+            .globals_spec
+            .iter()
+            .map(|spec| v0::GlobalSpec {
+                global: spec.global().clone(),
+                export_names: spec.export_names().to_vec(),
+            })
+            .collect();
+        v0::ModuleData {
+            linear_memory: self.linear_memories.first().cloned(),
+            globals_spec,
+            function_info: self.function_info.clone(),
+            import_functions: self.import_functions.clone(),
+            export_functions: self.export_functions.clone(),
+            signatures: self.signatures.clone(),
+            module_signature: self.module_signature,
+            features: self.features,
+            start_function: self.start_function,
+        }
+    }
+}
+
+/// (De)serialize the linear memories of module data as the first of them, as format 1 does.
+mod first_linear_memory {
+    use crate::linear_memory::LinearMemorySpec;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        linear_memories: &[LinearMemorySpec<'_>],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        linear_memories.first().serialize(serializer)
+    }
+
+    pub fn deserialize<'a, 'de: 'a, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<LinearMemorySpec<'a>>, D::Error> {
+        Option::<LinearMemorySpec<'a>>::deserialize(deserializer)
+            .map(|linear_memory| linear_memory.into_iter().collect())
     }
 }
 
 use crate::{
+    custom_sections::OwnedCustomSection,
     functions::{OwnedExportFunction, OwnedImportFunction},
     globals::OwnedGlobalSpec,
     linear_memory::{OwnedLinearMemorySpec, OwnedSparseData},
+    tables::OwnedExportTable,
 };
 
 /// The metadata (and some data) for a Lucet module.
@@ -211,7 +560,7 @@ use crate::{
 /// rather than references to support zero-copy deserialization. This type is useful when directly
 /// building up a value to be serialized.
 pub struct OwnedModuleData {
-    linear_memory: Option<OwnedLinearMemorySpec>,
+    linear_memories: Vec<OwnedLinearMemorySpec>,
     globals_spec: Vec<OwnedGlobalSpec>,
     function_info: Vec<OwnedFunctionMetadata>,
     imports: Vec<OwnedImportFunction>,
@@ -219,6 +568,10 @@ pub struct OwnedModuleData {
     signatures: Vec<Signature>,
     features: ModuleFeatures,
     start_function: Option<FunctionIndex>,
+    export_memory_names: Vec<String>,
+    export_tables: Vec<OwnedExportTable>,
+    tables: Vec<TableSpec>,
+    custom_sections: Vec<OwnedCustomSection>,
 }
 
 impl OwnedModuleData {
@@ -233,7 +586,7 @@ impl OwnedModuleData {
         start_function: Option<FunctionIndex>,
     ) -> Self {
         Self {
-            linear_memory,
+            linear_memories: linear_memory.into_iter().collect(),
             globals_spec,
             function_info,
             imports,
@@ -241,18 +594,49 @@ impl OwnedModuleData {
             signatures,
             features,
             start_function,
+            export_memory_names: vec![],
+            export_tables: vec![],
+            tables: vec![],
+            custom_sections: vec![],
         }
     }
 
+    /// Set the linear memories of the module, in the order of their WebAssembly indices, replacing
+    /// the one given to `new()`.
+    pub fn with_linear_memories(mut self, linear_memories: Vec<OwnedLinearMemorySpec>) -> Self {
+        self.linear_memories = linear_memories;
+        self
+    }
+
+    /// Set the names the module's linear memory is exported under.
+    pub fn with_export_memory_names(mut self, export_memory_names: Vec<String>) -> Self {
+        self.export_memory_names = export_memory_names;
+        self
+    }
+
+    /// Set the tables the module exports.
+    pub fn with_export_tables(mut self, export_tables: Vec<OwnedExportTable>) -> Self {
+        self.export_tables = export_tables;
+        self
+    }
+
+    /// Set the descriptions of the module's tables.
+    pub fn with_tables(mut self, tables: Vec<TableSpec>) -> Self {
+        self.tables = tables;
+        self
+    }
+
+    /// Set the custom sections of the WebAssembly module to preserve.
+    pub fn with_custom_sections(mut self, custom_sections: Vec<OwnedCustomSection>) -> Self {
+        self.custom_sections = custom_sections;
+        self
+    }
+
     /// Create a [`ModuleData`](../struct.ModuleData.html) backed by the values in this
     /// `OwnedModuleData`.
     pub fn to_ref<'a>(&'a self) -> ModuleData<'a> {
         ModuleData::new(
-            if let Some(ref owned_linear_memory) = self.linear_memory {
-                Some(owned_linear_memory.to_ref())
-            } else {
-                None
-            },
+            None,
             self.globals_spec.iter().map(|gs| gs.to_ref()).collect(),
             self.function_info
                 .iter()
@@ -264,6 +648,16 @@ impl OwnedModuleData {
             self.features.clone(),
             self.start_function,
         )
+        .with_linear_memories(self.linear_memories.iter().map(|m| m.to_ref()).collect())
+        .with_export_memory_names(
+            self.export_memory_names
+                .iter()
+                .map(|name| name.as_str())
+                .collect(),
+        )
+        .with_export_tables(self.export_tables.iter().map(|t| t.to_ref()).collect())
+        .with_tables(self.tables.clone())
+        .with_custom_sections(self.custom_sections.iter().map(|s| s.to_ref()).collect())
     }
 
     pub fn empty() -> Self {
@@ -279,11 +673,12 @@ impl OwnedModuleData {
         )
     }
 
+    /// Set the heap specification of the first linear memory, adding one if there is none.
     pub fn with_heap_spec(mut self, heap_spec: HeapSpec) -> Self {
-        if let Some(ref mut linear_memory) = self.linear_memory {
+        if let Some(linear_memory) = self.linear_memories.first_mut() {
             linear_memory.heap = heap_spec;
         } else {
-            self.linear_memory = Some(OwnedLinearMemorySpec {
+            self.linear_memories.push(OwnedLinearMemorySpec {
                 heap: heap_spec,
                 initializer: OwnedSparseData::new(vec![]).unwrap(),
             });
@@ -1,6 +1,7 @@
 use crate::error::Error::{self, IOError, ModuleSignatureError};
 use crate::module::{SerializedModule, LUCET_MODULE_SYM};
 use crate::module_data::MODULE_DATA_SYM;
+use crate::version_info::VersionInfo;
 use crate::ModuleData;
 use byteorder::{ByteOrder, LittleEndian};
 use memoffset::offset_of;
@@ -18,15 +19,35 @@ impl ModuleSignature {
         so_path: P,
         pk: &PublicKey,
         module_data: &ModuleData<'_>,
+    ) -> Result<(), Error> {
+        let raw_module_and_data = RawModuleAndData::from_file(&so_path).map_err(IOError)?;
+        Self::verify_raw(raw_module_and_data, pk, module_data)
+    }
+
+    /// Like `verify()`, for a module already read from its file.
+    pub(crate) fn verify_bytes(
+        obj_bin: Vec<u8>,
+        pk: &PublicKey,
+        module_data: &ModuleData<'_>,
+    ) -> Result<(), Error> {
+        let raw_module_and_data = RawModuleAndData::from_bytes(obj_bin).map_err(IOError)?;
+        Self::verify_raw(raw_module_and_data, pk, module_data)
+    }
+
+    fn verify_raw(
+        mut raw_module_and_data: RawModuleAndData,
+        pk: &PublicKey,
+        module_data: &ModuleData<'_>,
     ) -> Result<(), Error> {
         let signature_box: SignatureBox =
             SignatureBones::from_bytes(&module_data.get_module_signature())
                 .map_err(ModuleSignatureError)?
                 .into();
 
-        let mut raw_module_and_data = RawModuleAndData::from_file(&so_path).map_err(IOError)?;
-        let cleared_module_data_bin =
-            ModuleData::clear_module_signature(raw_module_and_data.module_data_bin())?;
+        let cleared_module_data_bin = ModuleData::clear_module_signature(
+            raw_module_and_data.module_data_bin(),
+            raw_module_and_data.format_version,
+        )?;
         raw_module_and_data.patch_module_data(&cleared_module_data_bin);
 
         minisign::verify(
@@ -54,6 +75,7 @@ impl ModuleSignature {
         let patched_module_data_bin = ModuleData::patch_module_signature(
             raw_module_and_data.module_data_bin(),
             &signature_bones.to_bytes(),
+            raw_module_and_data.format_version,
         )?;
         raw_module_and_data
             .write_patched_module_data(&path, &patched_module_data_bin)
@@ -68,17 +90,21 @@ struct SymbolData {
     len: usize,
 }
 
-struct RawModuleAndData {
+pub(crate) struct RawModuleAndData {
     pub obj_bin: Vec<u8>,
     pub module_data_offset: usize,
     pub module_data_len: usize,
+    pub format_version: u16,
 }
 
 impl RawModuleAndData {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, io::Error> {
         let mut obj_bin: Vec<u8> = Vec::new();
         File::open(&path)?.read_to_end(&mut obj_bin)?;
+        Self::from_bytes(obj_bin)
+    }
 
+    pub fn from_bytes(obj_bin: Vec<u8>) -> Result<Self, io::Error> {
         let native_data_symbol_data =
             Self::symbol_data(&obj_bin, LUCET_MODULE_SYM, true)?.ok_or(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -99,10 +125,15 @@ impl RawModuleAndData {
                 + offset_of!(SerializedModule, module_data_len))..],
         ) as usize;
 
+        let version = VersionInfo::read_from(&mut Cursor::new(
+            &obj_bin[(native_data_symbol_data.offset + offset_of!(SerializedModule, version))..],
+        ))?;
+
         Ok(RawModuleAndData {
             obj_bin,
             module_data_offset: module_data_symbol_data.offset,
             module_data_len,
+            format_version: version.format_version(),
         })
     }
 
@@ -1,4 +1,5 @@
-use crate::functions::FunctionPointer;
+use crate::functions::{FunctionIndex, FunctionPointer};
+use serde::{Deserialize, Serialize};
 
 #[repr(C)]
 #[derive(Clone, Debug)]
@@ -11,4 +12,68 @@ impl TableElement {
     pub fn function_pointer(&self) -> FunctionPointer {
         FunctionPointer::from_usize(self.func as usize)
     }
+
+    /// Returns `true` if no function was placed in this element by the module's element
+    /// initializers.
+    ///
+    /// `lucetc` encodes empty elements with a null function pointer and an out-of-bounds signature
+    /// index.
+    pub fn is_empty(&self) -> bool {
+        self.func == 0
+    }
+}
+
+/// TableSpec describes a table of the module: its limits, the element segments that initialize
+/// it, and the function each of its initial elements refers to.
+///
+/// Functions are identified by their index in the module, so their names and signatures can be
+/// found in the module data.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct TableSpec {
+    pub table_idx: u32,
+    /// The initial number of elements.
+    pub minimum: u32,
+    pub maximum: Option<u32>,
+    /// The element segments, in the order the module applies them.
+    pub segments: Vec<ElementSegment>,
+    /// The function in each initial element, or `None` if no segment placed one there.
+    pub elements: Vec<Option<FunctionIndex>>,
+}
+
+impl TableSpec {
+    /// The function in the initial element `elem_idx`, if there is one.
+    pub fn element(&self, elem_idx: u32) -> Option<FunctionIndex> {
+        self.elements.get(elem_idx as usize).copied().flatten()
+    }
+}
+
+/// ElementSegment describes an element segment of a table: the functions it places in the table,
+/// starting at `offset`.
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub struct ElementSegment {
+    pub offset: u32,
+    pub functions: Vec<FunctionIndex>,
+}
+
+/// ExportTable describes an exported table - its index in the module and the names it has been
+/// exported under.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct ExportTable<'a> {
+    pub table_idx: u32,
+    #[serde(borrow)]
+    pub names: Vec<&'a str>,
+}
+
+pub struct OwnedExportTable {
+    pub table_idx: u32,
+    pub names: Vec<String>,
+}
+
+impl OwnedExportTable {
+    pub fn to_ref<'a>(&'a self) -> ExportTable<'a> {
+        ExportTable {
+            table_idx: self.table_idx,
+            names: self.names.iter().map(|x| x.as_str()).collect(),
+        }
+    }
 }
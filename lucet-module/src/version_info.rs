@@ -3,6 +3,68 @@ use std::cmp::min;
 use std::fmt;
 use std::io;
 
+/// The version of the binary format `lucetc` writes modules in: the layout of `SerializedModule`,
+/// and the serialized `ModuleData` it points to.
+///
+/// It is bumped by every change to either of them, and the change is described in
+/// `MODULE_FORMAT_VERSIONS`. Modules record it in the low bits of `VersionInfo::reserved`.
+pub const MODULE_FORMAT_VERSION: u16 = 10;
+
+/// The module formats this version of `lucet-module` can read, newest first, along with how each
+/// differs from the one before it.
+///
+/// `lucet-runtime` loads a module in any of these formats, whichever version of `lucetc` compiled
+/// it. Format 0 covers the modules written before the format was versioned, which leave the low
+/// bits of `reserved` clear.
+pub const MODULE_FORMAT_VERSIONS: &[(u16, &str)] = &[
+    (10, "imported globals record their type"),
+    (
+        9,
+        "module features record whether the module uses SIMD or threads, and whether NaNs were \
+         canonicalized or Spectre mitigations applied",
+    ),
+    (
+        8,
+        "module data records the custom sections of the WebAssembly module lucetc preserved",
+    ),
+    (
+        7,
+        "module data records a hash of the shared object, to check its integrity",
+    ),
+    (
+        6,
+        "module data records the element segments of each table, and the function in each of \
+         its elements",
+    ),
+    (
+        5,
+        "module data records a list of linear memories, rather than at most one",
+    ),
+    (
+        4,
+        "the initial heap is recorded in chunks of a size the module chooses, rather than in \
+         pages",
+    ),
+    (3, "pages of the initial heap may be compressed with zstd"),
+    (
+        2,
+        "module data is a sequence of tagged sections of little-endian fields, rather than \
+         bincode",
+    ),
+    (
+        1,
+        "globals record whether they are mutable; module data records the names the linear \
+         memory and tables are exported under",
+    ),
+    (
+        0,
+        "the format of modules compiled before formats were versioned",
+    ),
+];
+
+/// The bit of `VersionInfo::reserved` every module with version information sets.
+const RESERVED_BIT: u16 = 0x8000;
+
 /// VersionInfo is information about a Lucet module to allow the Lucet runtime to determine if or
 /// how the module can be loaded, if so requested. The information here describes implementation
 /// details in runtime support for `lucetc`-produced modules, and nothing higher level.
@@ -44,11 +106,36 @@ impl VersionInfo {
             major,
             minor,
             patch,
-            reserved: 0x8000,
+            reserved: RESERVED_BIT | MODULE_FORMAT_VERSION,
             version_hash,
         }
     }
 
+    /// Set the module format this version describes, for tools writing modules in an older
+    /// format.
+    pub fn with_format_version(mut self, format_version: u16) -> VersionInfo {
+        assert_eq!(
+            format_version & RESERVED_BIT,
+            0,
+            "format version is too large"
+        );
+        self.reserved = RESERVED_BIT | format_version;
+        self
+    }
+
+    /// The version of the format of the module this version describes.
+    pub fn format_version(&self) -> u16 {
+        self.reserved & !RESERVED_BIT
+    }
+
+    /// Whether this version of `lucet-module` can read modules of this version's format.
+    pub fn format_supported(&self) -> bool {
+        self.valid()
+            && MODULE_FORMAT_VERSIONS
+                .iter()
+                .any(|(version, _)| *version == self.format_version())
+    }
+
     /// A more permissive version check than for version equality. This check will allow an `other`
     /// version that is more specific than `self`, but matches for data that is available.
     pub fn compatible_with(&self, other: &VersionInfo) -> bool {
@@ -100,7 +187,7 @@ impl VersionInfo {
     }
 
     pub fn valid(&self) -> bool {
-        self.reserved == 0x8000
+        self.reserved & RESERVED_BIT != 0
     }
 
     pub fn current(current_hash: &'static [u8]) -> Self {
@@ -115,7 +202,7 @@ impl VersionInfo {
         // pointer - `module_data_ptr`. This pointer would be relocated to somewhere in user space
         // for the embedder of `lucet-runtime`. On x86_64, hopefully, that's userland code in some
         // OS, meaning the pointer will be a pointer to user memory, and will be below
-        // 0x8000_0000_0000_0000. By setting `reserved` to `0x8000`, we set what would be the
+        // 0x8000_0000_0000_0000. By setting the high bit of `reserved`, we set what would be the
         // highest bit in `module_data_ptr` in an old `lucet-runtime` and guarantee a segmentation
         // fault when loading these newer modules with version information.
         VersionInfo::new(
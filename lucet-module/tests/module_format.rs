@@ -0,0 +1,483 @@
+use lucet_module::{
+    CustomSection, ElementSegment, Error, ExportFunction, ExportTable, FunctionIndex,
+    FunctionMetadata, Global, GlobalDef, GlobalSpec, HeapSpec, ImportFunction, LinearMemorySpec,
+    ModuleData, ModuleFeatures, Signature, SparseData, TableSpec, UniqueSignatureIndex, ValueType,
+    INTEGRITY_HASH_BYTES, MODULE_FORMAT_VERSION,
+};
+use minisign::SignatureBones;
+
+/// Module data as `lucetc` wrote it before formats were versioned, with one exported global and an
+/// exported start function.
+fn v0_module_data(module_signature: &[u8]) -> Vec<u8> {
+    let globals_spec = vec![(Global::Def(GlobalDef::I64(7)), vec!["counter"])];
+    let mut bin = bincode::serialize(&(
+        None::<LinearMemorySpec<'_>>,
+        globals_spec,
+        Vec::<FunctionMetadata<'_>>::new(),
+        Vec::<ImportFunction<'_>>::new(),
+        vec![ExportFunction {
+            fn_idx: FunctionIndex::from_u32(0),
+            names: vec!["_start"],
+        }],
+        Vec::<Signature>::new(),
+    ))
+    .unwrap();
+    bin.extend_from_slice(module_signature);
+    bin.extend(
+        bincode::serialize(&(ModuleFeatures::none(), Some(FunctionIndex::from_u32(0)))).unwrap(),
+    );
+    bin
+}
+
+#[test]
+fn read_unversioned_module_data() {
+    let bin = v0_module_data(&[0u8; SignatureBones::BYTES]);
+    let module_data = ModuleData::deserialize_format(&bin, 0).unwrap();
+
+    let global = &module_data.globals_spec()[0];
+    assert_eq!(global.global(), &Global::Def(GlobalDef::I64(7)));
+    assert_eq!(global.export_names(), ["counter"]);
+    assert!(global.is_mutable());
+    assert_eq!(
+        module_data.get_export_func_id("_start"),
+        Some(FunctionIndex::from_u32(0))
+    );
+    assert_eq!(
+        module_data.get_start_func_id(),
+        Some(FunctionIndex::from_u32(0))
+    );
+    assert!(module_data.export_memory_names().is_empty());
+    assert!(module_data.export_tables().is_empty());
+
+    // written back in format 0, the module data is as `lucetc` wrote it
+    assert_eq!(module_data.serialize_format(0).unwrap(), bin);
+}
+
+#[test]
+fn sign_unversioned_module_data() {
+    let module_signature = [0x5au8; SignatureBones::BYTES];
+    let bin = v0_module_data(&[0u8; SignatureBones::BYTES]);
+    let signed = ModuleData::patch_module_signature(&bin, &module_signature, 0).unwrap();
+    assert_eq!(signed, v0_module_data(&module_signature));
+    assert_eq!(ModuleData::clear_module_signature(&signed, 0).unwrap(), bin);
+}
+
+#[test]
+fn current_format_round_trips() {
+    let module_data = ModuleData::new(
+        None,
+        vec![GlobalSpec::new_def(7, vec!["counter"]).with_mutability(false)],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        ModuleFeatures::none(),
+        None,
+    )
+    .with_export_memory_names(vec!["memory"]);
+    let bin = module_data.serialize_format(MODULE_FORMAT_VERSION).unwrap();
+    assert_eq!(bin, module_data.serialize().unwrap());
+
+    let module_data = ModuleData::deserialize_format(&bin, MODULE_FORMAT_VERSION).unwrap();
+    assert!(!module_data.globals_spec()[0].is_mutable());
+    assert_eq!(module_data.export_memory_names(), ["memory"]);
+}
+
+#[test]
+fn reject_unknown_formats() {
+    let bin = v0_module_data(&[0u8; SignatureBones::BYTES]);
+    let format_version = MODULE_FORMAT_VERSION + 1;
+    assert!(matches!(
+        ModuleData::deserialize_format(&bin, format_version),
+        Err(Error::UnsupportedFormatVersion(v)) if v == format_version
+    ));
+}
+
+fn full_module_data<'a>(sparse_data: SparseData<'a>) -> ModuleData<'a> {
+    let mut features = ModuleFeatures::none();
+    features.avx = true;
+    features.instruction_count = true;
+    features.nan_canonicalization = true;
+    ModuleData::new(
+        Some(LinearMemorySpec {
+            heap: HeapSpec::new(
+                4 * 1024 * 1024,
+                4 * 1024 * 1024,
+                64 * 1024,
+                Some(128 * 1024),
+            ),
+            initializer: sparse_data,
+        }),
+        vec![
+            GlobalSpec::new(Global::Def(GlobalDef::I32(-1)), vec![]),
+            GlobalSpec::new(Global::Def(GlobalDef::F32(1.5)), vec!["f"]).with_mutability(false),
+            GlobalSpec::new(Global::Def(GlobalDef::F64(-0.25)), vec!["d", "e"]),
+            GlobalSpec::new_import("env", "g", vec![]).with_import_type(ValueType::I32),
+        ],
+        vec![
+            FunctionMetadata {
+                signature: UniqueSignatureIndex::from_u32(0),
+                name: Some("add"),
+            },
+            FunctionMetadata {
+                signature: UniqueSignatureIndex::from_u32(1),
+                name: None,
+            },
+        ],
+        vec![ImportFunction {
+            fn_idx: FunctionIndex::from_u32(1),
+            module: "env",
+            name: "log",
+        }],
+        vec![ExportFunction {
+            fn_idx: FunctionIndex::from_u32(0),
+            names: vec!["add", "plus"],
+        }],
+        vec![
+            Signature {
+                params: vec![ValueType::I32, ValueType::I64],
+                ret_ty: Some(ValueType::F64),
+            },
+            Signature {
+                params: vec![],
+                ret_ty: None,
+            },
+        ],
+        features,
+        Some(FunctionIndex::from_u32(0)),
+    )
+    .with_export_memory_names(vec!["memory"])
+    .with_export_tables(vec![ExportTable {
+        table_idx: 0,
+        names: vec!["table"],
+    }])
+    .with_tables(vec![TableSpec {
+        table_idx: 0,
+        minimum: 3,
+        maximum: None,
+        segments: vec![ElementSegment {
+            offset: 1,
+            functions: vec![FunctionIndex::from_u32(1), FunctionIndex::from_u32(0)],
+        }],
+        elements: vec![
+            None,
+            Some(FunctionIndex::from_u32(1)),
+            Some(FunctionIndex::from_u32(0)),
+        ],
+    }])
+    .with_custom_sections(vec![
+        CustomSection {
+            name: "abi_version",
+            data: b"1.2",
+        },
+        CustomSection {
+            name: "empty",
+            data: &[],
+        },
+    ])
+}
+
+#[test]
+fn module_data_round_trips() {
+    let page = vec![0xa5u8; 4096];
+    let sparse_data = SparseData::new(vec![None, Some(&page), None]).unwrap();
+    let module_data = full_module_data(sparse_data);
+
+    for &format_version in &[MODULE_FORMAT_VERSION, 1] {
+        let bin = module_data.serialize_format(format_version).unwrap();
+        let read = ModuleData::deserialize_format(&bin, format_version).unwrap();
+        assert_eq!(read.heap_spec(), module_data.heap_spec());
+        let read_pages = read.sparse_data().unwrap();
+        assert_eq!(read_pages.len(), 3);
+        assert_eq!(read_pages.get_page(1), Some(&page[..]));
+        assert_eq!(read_pages.get_page(2), None);
+        assert_eq!(read.globals_spec()[..3], module_data.globals_spec()[..3]);
+        assert_eq!(
+            read.globals_spec()[3].global(),
+            module_data.globals_spec()[3].global()
+        );
+        assert_eq!(read.function_info()[0].name, Some("add"));
+        assert_eq!(read.function_info()[1].name, None);
+        assert_eq!(read.import_functions(), module_data.import_functions());
+        assert_eq!(read.export_functions(), module_data.export_functions());
+        assert_eq!(read.signatures(), module_data.signatures());
+        assert!(read.features().avx && read.features().instruction_count);
+        assert!(!read.features().sse3);
+        assert_eq!(read.get_start_func_id(), Some(FunctionIndex::from_u32(0)));
+        assert_eq!(read.export_memory_names(), ["memory"]);
+        assert_eq!(read.export_tables(), module_data.export_tables());
+        if format_version == MODULE_FORMAT_VERSION {
+            assert_eq!(read.globals_spec()[3].import_type(), Some(ValueType::I32));
+            assert_eq!(read.tables(), module_data.tables());
+            assert_eq!(
+                read.get_table(0).unwrap().element(2),
+                Some(FunctionIndex::from_u32(0))
+            );
+            assert_eq!(read.get_table(0).unwrap().element(0), None);
+            assert_eq!(read.custom_sections(), module_data.custom_sections());
+            assert_eq!(read.get_custom_section("abi_version"), Some(&b"1.2"[..]));
+            assert_eq!(read.get_custom_section("missing"), None);
+            assert_eq!(
+                read.features().enabled(),
+                ["avx", "instruction_count", "nan_canonicalization"]
+            );
+        } else {
+            assert_eq!(read.globals_spec()[3].import_type(), None);
+            assert!(read.tables().is_empty());
+            assert!(read.custom_sections().is_empty());
+            assert!(!read.features().nan_canonicalization);
+        }
+        assert_eq!(read.serialize_format(format_version).unwrap(), bin);
+    }
+
+    // formats before 9 have no room for the features that are not CPU features
+    let bin = module_data.serialize_format(8).unwrap();
+    let read = ModuleData::deserialize_format(&bin, 8).unwrap();
+    assert_eq!(read.features().enabled(), ["avx", "instruction_count"]);
+
+    // formats before 10 do not record the types of imported globals
+    let bin = module_data.serialize_format(9).unwrap();
+    let read = ModuleData::deserialize_format(&bin, 9).unwrap();
+    assert_eq!(read.globals_spec()[3].import_type(), None);
+    assert_eq!(read.serialize_format(9).unwrap(), bin);
+}
+
+#[test]
+fn compressed_pages_round_trip() {
+    let zeros = vec![0u8; 4096];
+    let mut hello = vec![0u8; 4096];
+    hello[..5].copy_from_slice(b"hello");
+    let noise = (0..4096u32)
+        .map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+        .collect::<Vec<_>>();
+    let sparse_data =
+        SparseData::new(vec![Some(&hello), None, Some(&noise), Some(&zeros)]).unwrap();
+    let module_data = full_module_data(sparse_data);
+
+    let bin = module_data.serialize_compressed().unwrap();
+    assert!(bin.len() < module_data.serialize().unwrap().len() - 4096);
+    let read = ModuleData::deserialize(&bin).unwrap();
+    let read_pages = read.sparse_data().unwrap();
+    assert_eq!(read_pages.get_page(0), Some(&hello[..]));
+    assert_eq!(read_pages.get_page(1), None);
+    assert_eq!(read_pages.get_page(2), Some(&noise[..]));
+    assert_eq!(read_pages.get_page(3), Some(&zeros[..]));
+
+    // signing leaves the compressed pages as they are
+    let module_signature = [0x5au8; SignatureBones::BYTES];
+    let signed =
+        ModuleData::patch_module_signature(&bin, &module_signature, MODULE_FORMAT_VERSION).unwrap();
+    assert_eq!(signed.len(), bin.len());
+    let read = ModuleData::deserialize(&signed).unwrap();
+    assert_eq!(read.get_module_signature(), &module_signature[..]);
+    assert_eq!(
+        ModuleData::clear_module_signature(&signed, MODULE_FORMAT_VERSION).unwrap(),
+        bin
+    );
+
+    // format 2 has no compressed pages
+    assert!(matches!(
+        ModuleData::deserialize_format(&bin, 2),
+        Err(Error::MalformedModuleData(_))
+    ));
+}
+
+#[test]
+fn initial_heap_chunk_sizes_are_as_stored() {
+    let mut hello = vec![0u8; 4096];
+    hello[..5].copy_from_slice(b"hello");
+    let module_data = full_module_data(SparseData::new(vec![Some(&hello), None]).unwrap());
+
+    // a kind, then the bytes of the chunk
+    let bin = module_data.serialize().unwrap();
+    assert_eq!(
+        ModuleData::initial_heap_chunk_sizes(&bin, MODULE_FORMAT_VERSION).unwrap(),
+        vec![vec![Some(4097), None]]
+    );
+    // a kind, then the length and bytes of the zstd frame
+    let bin = module_data.serialize_compressed().unwrap();
+    let sizes = ModuleData::initial_heap_chunk_sizes(&bin, MODULE_FORMAT_VERSION).unwrap();
+    assert!(sizes[0][0].unwrap() < 100);
+    assert_eq!(sizes[0][1], None);
+    // the bincode of an `Option` of the page
+    let bin = module_data.serialize_format(1).unwrap();
+    assert_eq!(
+        ModuleData::initial_heap_chunk_sizes(&bin, 1).unwrap(),
+        vec![vec![Some(4105), None]]
+    );
+}
+
+#[test]
+fn patch_integrity_hash() {
+    let module_data = full_module_data(SparseData::new(vec![]).unwrap());
+    let bin = module_data.serialize().unwrap();
+    assert!(!module_data.has_integrity_hash());
+
+    let integrity_hash = [0xa5u8; INTEGRITY_HASH_BYTES];
+    let patched =
+        ModuleData::patch_integrity_hash(&bin, &integrity_hash, MODULE_FORMAT_VERSION).unwrap();
+    assert_eq!(patched.len(), bin.len());
+    let read = ModuleData::deserialize(&patched).unwrap();
+    assert!(read.has_integrity_hash());
+    assert_eq!(read.get_integrity_hash(), &integrity_hash[..]);
+    assert_eq!(
+        ModuleData::clear_integrity_hash(&patched, MODULE_FORMAT_VERSION).unwrap(),
+        bin
+    );
+
+    // format 6 has no room for an integrity hash
+    let bin = module_data.serialize_format(6).unwrap();
+    assert!(matches!(
+        ModuleData::patch_integrity_hash(&bin, &integrity_hash, 6),
+        Err(Error::UnsupportedFormatVersion(6))
+    ));
+    assert_eq!(ModuleData::clear_integrity_hash(&bin, 6).unwrap(), bin);
+}
+
+#[test]
+fn chunk_sizes_round_trip() {
+    let mut scattered = vec![0u8; 4096];
+    scattered[1000] = 1;
+    let filled = vec![0x5au8; 4096];
+    let pages = vec![Some(&scattered[..]), None, Some(&filled[..]), None, None];
+
+    let page_sized = full_module_data(SparseData::new(pages.clone()).unwrap())
+        .serialize()
+        .unwrap();
+    for &chunk_size in &[512, 4096, 8192, 64 * 1024] {
+        let sparse_data = SparseData::new(pages.clone())
+            .unwrap()
+            .with_chunk_size(chunk_size)
+            .unwrap();
+        let module_data = full_module_data(sparse_data);
+        for bin in &[
+            module_data.serialize().unwrap(),
+            module_data.serialize_compressed().unwrap(),
+        ] {
+            let read = ModuleData::deserialize(bin).unwrap();
+            let read_pages = read.sparse_data().unwrap();
+            assert_eq!(read_pages.chunk_size(), chunk_size);
+            assert_eq!(read_pages.len(), 5);
+            assert_eq!(read_pages.get_page(0), Some(&scattered[..]));
+            assert_eq!(read_pages.get_page(1), None);
+            assert_eq!(read_pages.get_page(2), Some(&filled[..]));
+            assert_eq!(read_pages.get_page(3), None);
+            assert_eq!(read_pages.get_page(4), None);
+        }
+        // small chunks leave out the zeros around scattered data
+        if chunk_size == 512 {
+            assert!(module_data.serialize().unwrap().len() < page_sized.len() - 3 * 512);
+        }
+        // older formats record the same heap in pages
+        let bin = module_data.serialize_format(3).unwrap();
+        let read = ModuleData::deserialize_format(&bin, 3).unwrap();
+        assert_eq!(read.sparse_data().unwrap().chunk_size(), 4096);
+        assert_eq!(read.sparse_data().unwrap().get_page(2), Some(&filled[..]));
+    }
+}
+
+#[test]
+fn reject_incorrect_chunk_sizes() {
+    for &chunk_size in &[0, 256, 3000, 128 * 1024] {
+        assert!(matches!(
+            SparseData::new(vec![]).unwrap().with_chunk_size(chunk_size),
+            Err(Error::IncorrectChunkSize(size)) if size == chunk_size
+        ));
+    }
+}
+
+fn linear_memory(initial_pages: u64, sparse_data: SparseData<'_>) -> LinearMemorySpec<'_> {
+    LinearMemorySpec {
+        heap: HeapSpec::new(
+            4 * 1024 * 1024,
+            4 * 1024 * 1024,
+            initial_pages * 64 * 1024,
+            None,
+        ),
+        initializer: sparse_data,
+    }
+}
+
+#[test]
+fn several_linear_memories_round_trip() {
+    let page = vec![0x11u8; 4096];
+    let module_data =
+        full_module_data(SparseData::new(vec![]).unwrap()).with_linear_memories(vec![
+            linear_memory(1, SparseData::new(vec![None, Some(&page)]).unwrap()),
+            linear_memory(
+                2,
+                SparseData::new(vec![Some(&page)])
+                    .unwrap()
+                    .with_chunk_size(512)
+                    .unwrap(),
+            ),
+        ]);
+    assert_eq!(module_data.linear_memories().len(), 2);
+    assert_eq!(module_data.heap_spec().unwrap().initial_size, 64 * 1024);
+
+    let bin = module_data.serialize().unwrap();
+    let read = ModuleData::deserialize(&bin).unwrap();
+    let memories = read.linear_memories();
+    assert_eq!(memories.len(), 2);
+    assert_eq!(memories[0].heap, module_data.linear_memories()[0].heap);
+    assert_eq!(memories[0].initializer.get_page(1), Some(&page[..]));
+    assert_eq!(memories[1].heap.initial_size, 2 * 64 * 1024);
+    assert_eq!(memories[1].initializer.chunk_size(), 512);
+    assert_eq!(memories[1].initializer.get_page(0), Some(&page[..]));
+    assert_eq!(read.sparse_data().unwrap().get_page(1), Some(&page[..]));
+
+    // older formats keep only the first linear memory
+    for &format_version in &[4, 1, 0] {
+        let bin = module_data.serialize_format(format_version).unwrap();
+        let read = ModuleData::deserialize_format(&bin, format_version).unwrap();
+        assert_eq!(read.linear_memories().len(), 1);
+        assert_eq!(read.heap_spec(), module_data.heap_spec());
+    }
+}
+
+#[test]
+fn module_data_is_little_endian_sections() {
+    let module_data = ModuleData::new(
+        None,
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        vec![],
+        ModuleFeatures::none(),
+        Some(FunctionIndex::from_u32(0x0102_0304)),
+    );
+    let bin = module_data.serialize().unwrap();
+    assert_eq!(&bin[..8], b"LMOD\x09\0\0\0");
+    assert_eq!(bin.len() % 8, 0);
+
+    // the start function section, padded to 8 bytes
+    let start: [u8; 16] = [9, 0, 0, 0, 4, 0, 0, 0, 4, 3, 2, 1, 0, 0, 0, 0];
+    assert!(bin.windows(start.len()).any(|w| w == start));
+}
+
+#[test]
+fn skip_unknown_sections() {
+    let module_data = full_module_data(SparseData::new(vec![]).unwrap());
+    let mut bin = module_data.serialize().unwrap();
+    // a section from a later version of `lucetc`, after all the known ones
+    bin.extend_from_slice(&[0xff, 0, 0, 0, 3, 0, 0, 0, 1, 2, 3, 0, 0, 0, 0, 0]);
+    let read = ModuleData::deserialize(&bin).unwrap();
+    assert_eq!(read.export_functions(), module_data.export_functions());
+}
+
+#[test]
+fn reject_malformed_module_data() {
+    let module_data = full_module_data(SparseData::new(vec![]).unwrap());
+    let bin = module_data.serialize().unwrap();
+    for len in &[0, 4, 12, bin.len() - 8] {
+        assert!(matches!(
+            ModuleData::deserialize(&bin[..*len]),
+            Err(Error::MalformedModuleData(_))
+        ));
+    }
+    assert!(matches!(
+        ModuleData::deserialize(&v0_module_data(&[0u8; SignatureBones::BYTES])),
+        Err(Error::MalformedModuleData("missing magic bytes"))
+    ));
+}
@@ -1,4 +1,4 @@
-use lucet_module::VersionInfo;
+use lucet_module::{VersionInfo, MODULE_FORMAT_VERSION};
 
 #[test]
 fn version_equality() {
@@ -16,3 +16,30 @@ fn version_equality() {
     // something running a version `major.minor.patch-commit` rejects a version less specific
     assert!(!precise.compatible_with(&imprecise));
 }
+
+#[test]
+fn format_versions() {
+    let version = VersionInfo::new(0, 1, 2, [0; 8]);
+    assert_eq!(version.format_version(), MODULE_FORMAT_VERSION);
+    assert!(version.valid());
+    assert!(version.format_supported());
+
+    // the format survives being written into a module and read back
+    let mut bytes = vec![];
+    version.write_to(&mut bytes).unwrap();
+    assert_eq!(
+        VersionInfo::read_from(&mut bytes.as_slice()).unwrap(),
+        version
+    );
+
+    // modules written before formats were versioned read as format 0, which is still supported
+    let unversioned = [0, 0, 1, 0, 2, 0, 0x00, 0x80, 0, 0, 0, 0, 0, 0, 0, 0];
+    let unversioned = VersionInfo::read_from(&mut &unversioned[..]).unwrap();
+    assert_eq!(unversioned, version.clone().with_format_version(0));
+    assert_eq!(unversioned.format_version(), 0);
+    assert!(unversioned.format_supported());
+
+    let future = version.with_format_version(MODULE_FORMAT_VERSION + 1);
+    assert!(future.valid());
+    assert!(!future.format_supported());
+}
@@ -0,0 +1,379 @@
+//! What the bytes of a compiled module are spent on, for `lucet-objdump bloat`.
+//!
+//! The size of the shared object is attributed to the code and trap manifest of each guest
+//! function, to the initial heap, to the rest of the module's metadata, to relocations and the
+//! other structures of dynamic linking, and to symbols and unwind information. What is left is
+//! reported as other: ELF headers, alignment padding, and sections `lucetc` does not describe.
+//!
+//! The initial heap is attributed to its segments: the runs of its chunks that are not all zeros,
+//! as the data segments of the WebAssembly module left them, each with the bytes its chunks take
+//! in the module data, as stored.
+
+use crate::ArtifactSummary;
+use byteorder::{ByteOrder, LittleEndian};
+use lucet_module::{
+    Error, FunctionSpec, Module, ModuleData, SerializedModule, TableElement, TrapSite,
+};
+use object::{Object, ObjectSection};
+use serde_json::json;
+use std::mem::size_of;
+
+/// The number of functions listed unless told otherwise, as with `cargo bloat`.
+pub const DEFAULT_LIMIT: usize = 20;
+
+struct Function<'a> {
+    index: usize,
+    name: &'a str,
+    code: u64,
+    traps: u64,
+}
+
+impl Function<'_> {
+    fn size(&self) -> u64 {
+        self.code + self.traps
+    }
+}
+
+/// A run of chunks of the initial heap of a linear memory that are not all zeros.
+#[derive(Debug, PartialEq, Eq)]
+struct HeapSegment {
+    memory: usize,
+    offset: u64,
+    len: u64,
+    /// The bytes its chunks take in the module data.
+    bytes: u64,
+}
+
+/// Print where the bytes of the module in `summary` go: the size of each category, of each
+/// segment of the initial heap, and of the `limit` largest functions, or of every function as
+/// JSON.
+pub fn bloat(
+    summary: &ArtifactSummary<'_>,
+    serialized_module: &SerializedModule,
+    module: &Module<'_>,
+    limit: usize,
+    as_json: bool,
+) -> Result<(), Error> {
+    let file_size = summary.buffer.len() as u64;
+
+    let mut functions = module
+        .function_manifest
+        .iter()
+        .enumerate()
+        .map(|(index, f)| Function {
+            index,
+            name: module
+                .module_data
+                .function_info()
+                .get(index)
+                .and_then(|info| info.name)
+                .unwrap_or("<unnamed>"),
+            code: u64::from(f.code_len()),
+            traps: f.traps_len() * size_of::<TrapSite>() as u64,
+        })
+        .collect::<Vec<_>>();
+    functions.sort_by(|a, b| b.size().cmp(&a.size()).then(a.index.cmp(&b.index)));
+
+    let function_code = functions.iter().map(|f| f.code).sum::<u64>();
+    let trap_manifests = functions.iter().map(|f| f.traps).sum::<u64>();
+    let heap_segments = heap_segments(summary, serialized_module, module)?;
+    let initial_heap = heap_segments
+        .iter()
+        .map(|segment| segment.bytes)
+        .sum::<u64>();
+    let categories = categories(
+        summary,
+        serialized_module,
+        function_code,
+        trap_manifests,
+        initial_heap,
+    );
+
+    if as_json {
+        let description = json!({
+            "file_size": file_size,
+            "categories": categories
+                .iter()
+                .map(|(name, size)| json!({ "name": name, "bytes": size }))
+                .collect::<Vec<_>>(),
+            "initial_heap": heap_segments
+                .iter()
+                .map(|segment| json!({
+                    "memory": segment.memory,
+                    "offset": segment.offset,
+                    "len": segment.len,
+                    "bytes": segment.bytes,
+                }))
+                .collect::<Vec<_>>(),
+            "functions": functions
+                .iter()
+                .map(|f| json!({
+                    "index": f.index,
+                    "name": f.name,
+                    "code": f.code,
+                    "traps": f.traps,
+                    "bytes": f.size(),
+                }))
+                .collect::<Vec<_>>(),
+        });
+        println!("{:#}", description);
+        return Ok(());
+    }
+
+    let percent = |size: u64| 100.0 * size as f64 / file_size.max(1) as f64;
+    println!("File size: {} bytes", file_size);
+    println!();
+    println!("  {:>10} {:>6}  Category", "Bytes", "File");
+    let mut by_size = categories.clone();
+    by_size.sort_by(|(_, a), (_, b)| b.cmp(a));
+    for (name, size) in by_size {
+        println!("  {:>10} {:>5.1}%  {}", size, percent(size), name);
+    }
+
+    if !heap_segments.is_empty() {
+        println!();
+        println!(
+            "  {:>10} {:>6} {:>10}  Initial heap segment",
+            "Bytes", "File", "Length"
+        );
+        for segment in heap_segments.iter() {
+            println!(
+                "  {:>10} {:>5.1}% {:>10}  memory {} at {:#x}",
+                segment.bytes,
+                percent(segment.bytes),
+                segment.len,
+                segment.memory,
+                segment.offset
+            );
+        }
+    }
+
+    println!();
+    println!(
+        "  {:>10} {:>6} {:>10} {:>8}  Function",
+        "Bytes", "File", "Code", "Traps"
+    );
+    for f in functions.iter().take(limit) {
+        println!(
+            "  {:>10} {:>5.1}% {:>10} {:>8}  {} ({})",
+            f.size(),
+            percent(f.size()),
+            f.code,
+            f.traps,
+            f.index,
+            f.name
+        );
+    }
+    if functions.len() > limit {
+        let rest = &functions[limit..];
+        let size = rest.iter().map(Function::size).sum::<u64>();
+        println!(
+            "  {:>10} {:>5.1}% {:>10} {:>8}  and {} more functions",
+            size,
+            percent(size),
+            rest.iter().map(|f| f.code).sum::<u64>(),
+            rest.iter().map(|f| f.traps).sum::<u64>(),
+            rest.len()
+        );
+    }
+    Ok(())
+}
+
+/// The total size in the file of the sections whose names satisfy `pred`.
+fn sections(summary: &ArtifactSummary<'_>, pred: impl Fn(&str) -> bool) -> u64 {
+    summary
+        .obj
+        .sections()
+        .filter(|section| section.name().map_or(false, &pred))
+        .filter_map(|section| section.data().ok())
+        .map(|data| data.len() as u64)
+        .sum()
+}
+
+/// Where the bytes of the file go, by category, ending with what is left of `file_size` as other.
+fn categories(
+    summary: &ArtifactSummary<'_>,
+    serialized_module: &SerializedModule,
+    function_code: u64,
+    trap_manifests: u64,
+    initial_heap: u64,
+) -> Vec<(&'static str, u64)> {
+    let file_size = summary.buffer.len() as u64;
+    let text = sections(summary, |name| name == ".text");
+    let mut categories = vec![
+        ("function code", function_code),
+        ("trap manifests", trap_manifests),
+        ("other code", text.saturating_sub(function_code)),
+        ("initial heap", initial_heap),
+        (
+            "module data",
+            serialized_module
+                .module_data_len
+                .saturating_sub(initial_heap),
+        ),
+        (
+            "function manifest",
+            serialized_module.function_manifest_len * size_of::<FunctionSpec>() as u64,
+        ),
+        ("tables", tables_size(summary, serialized_module)),
+        ("module header", size_of::<SerializedModule>() as u64),
+        (
+            "relocations",
+            sections(summary, |name| name.starts_with(".rel")),
+        ),
+        (
+            "dynamic linking",
+            sections(summary, |name| {
+                [
+                    ".dynsym",
+                    ".dynstr",
+                    ".dynamic",
+                    ".hash",
+                    ".gnu.hash",
+                    ".gnu.version",
+                    ".gnu.version_r",
+                    ".got",
+                    ".got.plt",
+                    ".plt",
+                    ".plt.got",
+                    ".plt.sec",
+                ]
+                .contains(&name)
+            }),
+        ),
+        (
+            "symbols and debug info",
+            sections(summary, |name| {
+                [".symtab", ".strtab", ".shstrtab"].contains(&name) || name.starts_with(".debug")
+            }),
+        ),
+        (
+            "unwind information",
+            sections(summary, |name| name.starts_with(".eh_frame")),
+        ),
+    ];
+    let attributed = categories.iter().map(|(_, size)| size).sum::<u64>();
+    categories.push(("other", file_size.saturating_sub(attributed)));
+    categories
+}
+
+/// The segments of the initial heaps of the linear memories of `module`.
+fn heap_segments(
+    summary: &ArtifactSummary<'_>,
+    serialized_module: &SerializedModule,
+    module: &Module<'_>,
+) -> Result<Vec<HeapSegment>, Error> {
+    let module_data_bytes = summary
+        .read_memory(
+            serialized_module.module_data_ptr,
+            serialized_module.module_data_len,
+        )
+        .unwrap_or(&[]);
+    let chunk_sizes = ModuleData::initial_heap_chunk_sizes(
+        module_data_bytes,
+        serialized_module.version.format_version(),
+    )?;
+    Ok(module
+        .module_data
+        .linear_memories()
+        .iter()
+        .zip(chunk_sizes)
+        .enumerate()
+        .flat_map(|(memory, (spec, chunks))| {
+            segments(memory, u64::from(spec.initializer.chunk_size()), chunks)
+        })
+        .collect())
+}
+
+/// The runs of `chunks` of `chunk_size` bytes, each the bytes it takes or `None` for zeros, of the
+/// initial heap of the linear memory `memory`.
+fn segments(memory: usize, chunk_size: u64, chunks: Vec<Option<u64>>) -> Vec<HeapSegment> {
+    let mut segments = vec![];
+    let mut segment: Option<HeapSegment> = None;
+    for (i, chunk) in chunks.into_iter().enumerate() {
+        match (chunk, segment.as_mut()) {
+            (Some(bytes), Some(segment)) => {
+                segment.len += chunk_size;
+                segment.bytes += bytes;
+            }
+            (Some(bytes), None) => {
+                segment = Some(HeapSegment {
+                    memory,
+                    offset: i as u64 * chunk_size,
+                    len: chunk_size,
+                    bytes,
+                })
+            }
+            (None, _) => segments.extend(segment.take()),
+        }
+    }
+    segments.extend(segment);
+    segments
+}
+
+/// The size of the tables: a slice for each, and their elements.
+fn tables_size(summary: &ArtifactSummary<'_>, serialized_module: &SerializedModule) -> u64 {
+    let slice_size = size_of::<&[TableElement]>();
+    let slices = serialized_module.tables_len * slice_size as u64;
+    let elements = summary
+        .read_memory(serialized_module.tables_ptr, slices)
+        .map(|slices| {
+            slices
+                .chunks_exact(slice_size)
+                // each slice is a pointer and a length
+                .map(|slice| LittleEndian::read_u64(&slice[8..]))
+                .sum::<u64>()
+        })
+        .unwrap_or(0);
+    slices + elements * size_of::<TableElement>() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lucet_module::VersionInfo;
+
+    #[test]
+    fn segments_are_runs_of_chunks() {
+        let segment = |offset, len, bytes| HeapSegment {
+            memory: 1,
+            offset,
+            len,
+            bytes,
+        };
+        assert_eq!(
+            segments(
+                1,
+                4096,
+                vec![None, Some(4097), Some(30), None, None, Some(4097)]
+            ),
+            vec![segment(0x1000, 0x2000, 4127), segment(0x5000, 0x1000, 4097)]
+        );
+        assert_eq!(segments(1, 4096, vec![None, None]), vec![]);
+    }
+
+    #[test]
+    fn categories_sum_to_file_size() {
+        // any shared object has the sections of code, linking, and symbols to attribute
+        let buffer = std::fs::read(std::env::current_exe().unwrap()).unwrap();
+        let obj = object::File::parse(&buffer).unwrap();
+        let summary = ArtifactSummary::new(&buffer, &obj);
+        let serialized_module = SerializedModule {
+            version: VersionInfo::new(0, 7, 0, [0; 8]),
+            module_data_ptr: 0,
+            module_data_len: 4096,
+            tables_ptr: 0,
+            tables_len: 0,
+            function_manifest_ptr: 0,
+            function_manifest_len: 16,
+        };
+        let categories = categories(&summary, &serialized_module, 1000, 160, 1024);
+        assert_eq!(
+            categories.iter().map(|(_, size)| size).sum::<u64>(),
+            buffer.len() as u64
+        );
+        let other = categories.last().unwrap();
+        assert_eq!(other.0, "other");
+        assert!(other.1 > 0, "the categories add up to less than the file");
+    }
+}
@@ -0,0 +1,205 @@
+//! A comparison of two compiled modules, for `lucet-objdump diff`.
+//!
+//! This is meant for reviewing a guest upgrade before rolling it out: it lists the exports and
+//! imports that were added, removed or changed signature, the change in size of each function,
+//! the changes to the heap specifications, and the features that were enabled or disabled.
+
+use lucet_module::{Error, HeapSpec, ModuleArtifact, ModuleData};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Print the differences between the modules in the shared objects `old` and `new`.
+pub fn diff(old: &[u8], new: &[u8]) -> Result<(), Error> {
+    let old = ModuleArtifact::parse(old)?;
+    let new = ModuleArtifact::parse(new)?;
+
+    if old.version().to_string() != new.version().to_string() {
+        println!("Version: {} -> {}", old.version(), new.version());
+    }
+    if old.version().format_version() != new.version().format_version() {
+        println!(
+            "Module format: {} -> {}",
+            old.version().format_version(),
+            new.version().format_version()
+        );
+    }
+
+    compare(
+        "Exports",
+        &exports(old.module_data()),
+        &exports(new.module_data()),
+    );
+    compare(
+        "Imports",
+        &imports(old.module_data()),
+        &imports(new.module_data()),
+    );
+    function_sizes(&old, &new);
+    heap_specs(old.module_data(), new.module_data());
+
+    let old_features = old.module_data().features().enabled();
+    let new_features = new.module_data().features().enabled();
+    if old_features != new_features {
+        println!();
+        println!("Features:");
+        for feature in old_features.iter().filter(|f| !new_features.contains(f)) {
+            println!("  - {}", feature);
+        }
+        for feature in new_features.iter().filter(|f| !old_features.contains(f)) {
+            println!("  + {}", feature);
+        }
+    }
+    Ok(())
+}
+
+/// The signature of each exported function, by export name.
+fn exports(module_data: &ModuleData<'_>) -> BTreeMap<String, String> {
+    module_data
+        .export_functions()
+        .iter()
+        .flat_map(|export| {
+            let signature = module_data.get_signature(export.fn_idx).to_string();
+            export
+                .names
+                .iter()
+                .map(move |name| (name.to_string(), signature.clone()))
+        })
+        .collect()
+}
+
+/// The signature of each imported function, by `module::name`.
+fn imports(module_data: &ModuleData<'_>) -> BTreeMap<String, String> {
+    module_data
+        .import_functions()
+        .iter()
+        .map(|import| {
+            (
+                format!("{}::{}", import.module, import.name),
+                module_data.get_signature(import.fn_idx).to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Print the items only in `old`, only in `new`, and in both with different signatures.
+fn compare(title: &str, old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) {
+    if old == new {
+        return;
+    }
+    println!();
+    println!("{}:", title);
+    for (name, signature) in old.iter().filter(|(name, _)| !new.contains_key(*name)) {
+        println!("  - {}: {}", name, signature);
+    }
+    for (name, signature) in new.iter().filter(|(name, _)| !old.contains_key(*name)) {
+        println!("  + {}: {}", name, signature);
+    }
+    for (name, old_signature) in old.iter() {
+        match new.get(name) {
+            Some(new_signature) if new_signature != old_signature => {
+                println!("  ~ {}: {} -> {}", name, old_signature, new_signature)
+            }
+            _ => (),
+        }
+    }
+}
+
+/// The code size of each named function.
+fn code_sizes<'a>(artifact: &ModuleArtifact<'a>) -> BTreeMap<&'a str, u32> {
+    artifact
+        .module_data()
+        .function_info()
+        .iter()
+        .zip(artifact.function_manifest())
+        .filter_map(|(info, f)| Some((info.name?, f.code_len())))
+        .collect()
+}
+
+fn function_sizes(old: &ModuleArtifact<'_>, new: &ModuleArtifact<'_>) {
+    let old_sizes = code_sizes(old);
+    let new_sizes = code_sizes(new);
+    let names = old_sizes
+        .keys()
+        .chain(new_sizes.keys())
+        .collect::<BTreeSet<_>>();
+
+    let mut lines = vec![];
+    for name in names {
+        match (old_sizes.get(name), new_sizes.get(name)) {
+            (Some(old), None) => lines.push(format!("  - {}: {} bytes", name, old)),
+            (None, Some(new)) => lines.push(format!("  + {}: {} bytes", name, new)),
+            (Some(old), Some(new)) if old != new => lines.push(format!(
+                "  ~ {}: {} -> {} bytes ({:+})",
+                name,
+                old,
+                new,
+                i64::from(*new) - i64::from(*old)
+            )),
+            _ => (),
+        }
+    }
+    let total = |sizes: &BTreeMap<_, u32>| sizes.values().map(|size| i64::from(*size)).sum::<i64>();
+    let (old_total, new_total) = (total(&old_sizes), total(&new_sizes));
+    if lines.is_empty() && old_total == new_total {
+        return;
+    }
+    println!();
+    println!(
+        "Function sizes: {} -> {} bytes ({:+})",
+        old_total,
+        new_total,
+        new_total - old_total
+    );
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+fn heap_specs(old: &ModuleData<'_>, new: &ModuleData<'_>) {
+    let old_heaps = old
+        .linear_memories()
+        .iter()
+        .map(|memory| &memory.heap)
+        .collect::<Vec<_>>();
+    let new_heaps = new
+        .linear_memories()
+        .iter()
+        .map(|memory| &memory.heap)
+        .collect::<Vec<_>>();
+    if old_heaps == new_heaps {
+        return;
+    }
+    println!();
+    println!("Heap specifications:");
+    for i in 0..old_heaps.len().max(new_heaps.len()) {
+        match (old_heaps.get(i), new_heaps.get(i)) {
+            (Some(_), None) => println!("  - memory {}", i),
+            (None, Some(_)) => println!("  + memory {}", i),
+            (Some(old), Some(new)) if old != new => heap_spec(i, old, new),
+            _ => (),
+        }
+    }
+}
+
+fn heap_spec(i: usize, old: &HeapSpec, new: &HeapSpec) {
+    let size = |size: Option<u64>| {
+        size.map(|size| format!("{} bytes", size))
+            .unwrap_or_else(|| "None".to_owned())
+    };
+    let fields = [
+        ("reserved", Some(old.reserved_size), Some(new.reserved_size)),
+        ("guard", Some(old.guard_size), Some(new.guard_size)),
+        ("initial", Some(old.initial_size), Some(new.initial_size)),
+        ("maximum", old.max_size, new.max_size),
+    ];
+    for (field, old, new) in fields.iter() {
+        if old != new {
+            println!(
+                "  ~ memory {} {}: {} -> {}",
+                i,
+                field,
+                size(*old),
+                size(*new)
+            );
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Annotated disassembly of the guest functions of a module, for `lucet-objdump disasm`.
+//!
+//! Each function is listed under its WebAssembly name. Instructions at trap sites are annotated
+//! with their trap code, and calls with the name of the function they call: another guest
+//! function, or a hostcall or other import reached through the PLT or GOT.
+
+use crate::{parse_trap_manifest, ArtifactSummary};
+use byteorder::{ByteOrder, LittleEndian};
+use capstone::arch::x86::{ArchMode, X86OperandType};
+use capstone::arch::ArchOperand;
+use capstone::prelude::*;
+use capstone::Insn;
+use lucet_module::{Module, TrapCode};
+use object::{Object, ObjectSection};
+use std::collections::HashMap;
+
+/// The size of an `Elf64_Rela`.
+const RELA_SIZE: usize = 24;
+/// The size of an `Elf64_Sym`.
+const SYM_SIZE: usize = 24;
+
+pub fn disassemble(
+    summary: &ArtifactSummary<'_>,
+    module: &Module<'_>,
+) -> Result<(), capstone::Error> {
+    let cs = capstone()?;
+    let targets = CallTargets::new(summary, module);
+
+    for (i, f) in module.function_manifest.iter().enumerate() {
+        let name = module
+            .module_data
+            .function_info()
+            .get(i)
+            .and_then(|info| info.name)
+            .unwrap_or("<unnamed>");
+        println!("Function {} ({}):", i, name);
+
+        let start = f.ptr().as_usize() as u64;
+        let code = match summary.read_memory(start, u64::from(f.code_len())) {
+            Some(code) => code,
+            None => {
+                println!("  Failed to read the code at {:#x}", start);
+                continue;
+            }
+        };
+        let traps: HashMap<u32, TrapCode> = parse_trap_manifest(summary, f)
+            .map(|manifest| {
+                manifest
+                    .traps
+                    .iter()
+                    .map(|trap| (trap.offset, trap.code))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for insn in cs.disasm_all(code, start)?.iter() {
+            let mut line = format!(
+                "  {:#010x}: {:8} {}",
+                insn.address(),
+                insn.mnemonic().unwrap_or(""),
+                insn.op_str().unwrap_or("")
+            );
+            if let Some(trap) = traps.get(&((insn.address() - start) as u32)) {
+                line.push_str(&format!("  ; trap: {:?}", trap));
+            }
+            if insn.mnemonic() == Some("call") {
+                if let Some(target) = targets.name(&cs, &insn) {
+                    line.push_str(&format!("  ; {}", target));
+                }
+            }
+            println!("{}", line);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+pub(crate) fn capstone() -> Result<Capstone, capstone::Error> {
+    Capstone::new()
+        .x86()
+        .mode(ArchMode::Mode64)
+        .detail(true)
+        .build()
+}
+
+/// The names of the functions that calls may go to.
+pub(crate) struct CallTargets<'a> {
+    summary: &'a ArtifactSummary<'a>,
+    /// Guest functions, by the address of their code.
+    functions: HashMap<u64, &'a str>,
+    /// Imported symbols, by the address of the GOT slot the dynamic linker fills with them.
+    got_slots: HashMap<u64, &'a str>,
+}
+
+impl<'a> CallTargets<'a> {
+    pub(crate) fn new(summary: &'a ArtifactSummary<'a>, module: &'a Module<'a>) -> Self {
+        let functions = module
+            .function_manifest
+            .iter()
+            .zip(module.module_data.function_info())
+            .filter_map(|(f, info)| Some((f.ptr().as_usize() as u64, info.name?)))
+            .collect();
+        CallTargets {
+            summary,
+            functions,
+            got_slots: got_slots(summary.obj),
+        }
+    }
+
+    /// Name the function `insn` calls, if it can be determined statically.
+    pub(crate) fn name(&self, cs: &Capstone, insn: &Insn<'_>) -> Option<String> {
+        match operand(cs, insn)? {
+            Operand::Direct(target) => self.direct(cs, target),
+            Operand::Slot(slot) => self.got_slots.get(&slot).map(|name| name.to_string()),
+        }
+    }
+
+    fn direct(&self, cs: &Capstone, target: u64) -> Option<String> {
+        if let Some(name) = self.functions.get(&target) {
+            return Some(name.to_string());
+        }
+        if let Some(name) = self.summary.get_symbol_name_for_addr(target) {
+            return Some(name.to_owned());
+        }
+        // otherwise, this is a PLT stub; it jumps through the GOT slot of the symbol it calls,
+        // possibly after an `endbr64`
+        let stub = self.summary.read_memory(target, 16)?;
+        let insns = cs.disasm_count(stub, target, 2).ok()?;
+        let slot = insns.iter().find_map(|insn| match operand(cs, &insn)? {
+            Operand::Slot(slot) => Some(slot),
+            Operand::Direct(_) => None,
+        })?;
+        self.got_slots
+            .get(&slot)
+            .map(|name| format!("{}@plt", name))
+    }
+}
+
+pub(crate) enum Operand {
+    /// A call to an immediate address.
+    Direct(u64),
+    /// A call through the pointer at a RIP-relative address.
+    Slot(u64),
+}
+
+pub(crate) fn operand(cs: &Capstone, insn: &Insn<'_>) -> Option<Operand> {
+    let detail = cs.insn_detail(insn).ok()?;
+    detail
+        .arch_detail()
+        .operands()
+        .into_iter()
+        .find_map(|op| match op {
+            ArchOperand::X86Operand(op) => match op.op_type {
+                X86OperandType::Imm(target) => Some(Operand::Direct(target as u64)),
+                X86OperandType::Mem(mem) if cs.reg_name(mem.base()).as_deref() == Some("rip") => {
+                    let next = insn.address() + insn.bytes().len() as u64;
+                    Some(Operand::Slot(next.wrapping_add(mem.disp() as u64)))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+}
+
+/// Map the GOT slots that dynamic relocations fill to the names of their symbols.
+///
+/// This reads `.rela.plt` and `.rela.dyn` directly, as the `object` crate does not resolve dynamic
+/// relocations, so it only finds them in ELF objects.
+fn got_slots<'a>(obj: &'a object::File<'a>) -> HashMap<u64, &'a str> {
+    let data = |name: &str| {
+        obj.section_by_name(name)
+            .and_then(|section| section.data().ok())
+    };
+    let mut slots = HashMap::new();
+    let (dynsym, dynstr) = match (data(".dynsym"), data(".dynstr")) {
+        (Some(dynsym), Some(dynstr)) => (dynsym, dynstr),
+        _ => return slots,
+    };
+    let symbol_name = |sym_idx: usize| {
+        let sym = dynsym.get(sym_idx * SYM_SIZE..(sym_idx + 1) * SYM_SIZE)?;
+        let name = dynstr.get(LittleEndian::read_u32(&sym[0..]) as usize..)?;
+        let len = name.iter().position(|b| *b == 0)?;
+        std::str::from_utf8(&name[..len]).ok()
+    };
+    for relocations in [".rela.plt", ".rela.dyn"]
+        .iter()
+        .filter_map(|name| data(name))
+    {
+        for rela in relocations.chunks_exact(RELA_SIZE) {
+            let slot = LittleEndian::read_u64(&rela[0..]);
+            let sym_idx = (LittleEndian::read_u64(&rela[8..]) >> 32) as usize;
+            if let Some(name) = symbol_name(sym_idx).filter(|name| !name.is_empty()) {
+                slots.insert(slot, name);
+            }
+        }
+    }
+    slots
+}
@@ -0,0 +1,106 @@
+//! A machine-readable description of a module, for `lucet-objdump --json`.
+//!
+//! The description is read with `ModuleArtifact`, rather than by following the structures of the
+//! shared object as the human-oriented summary does, so it is only produced for objects that
+//! `lucet-module` can read.
+
+use lucet_module::{Error, FunctionIndex, Global, GlobalDef, ModuleArtifact};
+use serde_json::{json, Value};
+
+/// Describe the module in the shared object `bytes`.
+pub fn describe(bytes: &[u8]) -> Result<Value, Error> {
+    let artifact = ModuleArtifact::parse(bytes)?;
+    let module_data = artifact.module_data();
+    let function_manifest = artifact.function_manifest();
+
+    let signature = |fn_idx: FunctionIndex| module_data.get_signature(fn_idx).to_string();
+
+    let functions = module_data
+        .function_info()
+        .iter()
+        .enumerate()
+        .map(|(idx, info)| {
+            json!({
+                "index": idx,
+                "name": info.name,
+                "signature": module_data.signatures()[info.signature.as_u32() as usize].to_string(),
+                "code_size": function_manifest.get(idx).map(|f| f.code_len()),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    let integrity = if !module_data.has_integrity_hash() {
+        "absent"
+    } else if artifact.check_integrity().is_ok() {
+        "valid"
+    } else {
+        "mismatch"
+    };
+
+    Ok(json!({
+        "version": artifact.version().to_string(),
+        "format_version": artifact.version().format_version(),
+        "sizes": {
+            "file": bytes.len(),
+            "code": function_manifest.iter().map(|f| u64::from(f.code_len())).sum::<u64>(),
+            "functions": function_manifest.len(),
+        },
+        "heap": module_data.heap_spec().map(|heap| json!({
+            "reserved_size": heap.reserved_size,
+            "guard_size": heap.guard_size,
+            "initial_size": heap.initial_size,
+            "max_size": heap.max_size,
+        })),
+        "imports": module_data.import_functions().iter().map(|import| json!({
+            "module": import.module,
+            "name": import.name,
+            "function": import.fn_idx.as_u32(),
+            "signature": signature(import.fn_idx),
+        })).collect::<Vec<_>>(),
+        "exports": {
+            "functions": module_data.export_functions().iter().map(|export| json!({
+                "names": export.names,
+                "function": export.fn_idx.as_u32(),
+                "signature": signature(export.fn_idx),
+            })).collect::<Vec<_>>(),
+            "memory": module_data.export_memory_names(),
+            "tables": module_data.export_tables().iter().map(|export| json!({
+                "names": export.names,
+                "table": export.table_idx,
+            })).collect::<Vec<_>>(),
+        },
+        "globals": module_data.globals_spec().iter().map(|spec| {
+            let mut global = match spec.global() {
+                Global::Def(def) => json!({
+                    "kind": "definition",
+                    "type": def.value_type().to_string(),
+                    "value": match *def {
+                        GlobalDef::I32(v) => json!(v),
+                        GlobalDef::I64(v) => json!(v),
+                        GlobalDef::F32(v) => json!(v),
+                        GlobalDef::F64(v) => json!(v),
+                    },
+                }),
+                Global::Import { module, field } => json!({
+                    "kind": "import",
+                    "module": module,
+                    "field": field,
+                }),
+            };
+            global["mutable"] = json!(spec.is_mutable());
+            global["export_names"] = json!(spec.export_names());
+            global
+        }).collect::<Vec<_>>(),
+        "signatures": module_data.signatures().iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        "functions": functions,
+        "start_function": module_data.get_start_func_id().map(|f| f.as_u32()),
+        "features": module_data.features().enabled(),
+        "custom_sections": module_data
+            .custom_sections()
+            .iter()
+            .map(|section| section.name)
+            .collect::<Vec<_>>(),
+        "signed": artifact.is_signed(),
+        "integrity": integrity,
+    }))
+}
@@ -1,8 +1,15 @@
 #![deny(bare_trait_objects)]
 
+mod bloat;
+mod diff;
+mod disasm;
+mod json;
+mod stack;
+mod verify;
+
 use lucet_module::{
-    FunctionSpec, Module, ModuleData, SerializedModule, TableElement, TrapManifest, TrapSite,
-    VersionInfo,
+    FunctionSpec, Global, GlobalDef, Module, ModuleData, SerializedModule, TableElement,
+    TrapManifest, TrapSite, VersionInfo,
 };
 
 use byteorder::{LittleEndian, ReadBytesExt};
@@ -13,6 +20,9 @@ use std::fs::File;
 use std::io::Cursor;
 use std::io::Read;
 use std::mem;
+use std::process;
+
+const WASM_PAGE_SIZE: u64 = 64 * 1024;
 
 #[derive(Debug)]
 struct ArtifactSummary<'a> {
@@ -118,17 +128,106 @@ impl<'a> ArtifactSummary<'a> {
 }
 
 fn main() {
-    let path = env::args().nth(1).unwrap();
-    let mut fd = File::open(path).expect("open");
-    let mut buffer = Vec::new();
-    fd.read_to_end(&mut buffer).expect("read");
+    let mut args = env::args().skip(1).collect::<Vec<_>>();
+    let subcommand = match args.first().map(String::as_str) {
+        Some("bloat") | Some("disasm") | Some("diff") | Some("stack") | Some("verify") => {
+            Some(args.remove(0))
+        }
+        _ => None,
+    };
+
+    if subcommand.as_deref() == Some("diff") {
+        if args.len() != 2 {
+            eprintln!("usage: lucet-objdump diff <old.so> <new.so>");
+            process::exit(2);
+        }
+        if let Err(e) = diff::diff(&read_file(&args[0]), &read_file(&args[1])) {
+            eprintln!("lucet-objdump: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if subcommand.as_deref() == Some("verify") {
+        let (pk_path, path) = match args.as_slice() {
+            [pk, pk_path, path] if pk == "--pk" => (pk_path, path),
+            _ => {
+                eprintln!("usage: lucet-objdump verify --pk <public-key> <module.so>");
+                process::exit(2);
+            }
+        };
+        match verify::verify(&read_file(path), pk_path) {
+            Ok(true) => return,
+            Ok(false) => process::exit(1),
+            Err(e) => {
+                eprintln!("lucet-objdump: {}", e);
+                process::exit(2);
+            }
+        }
+    }
+
+    let mut limit = bloat::DEFAULT_LIMIT;
+    if subcommand.as_deref() == Some("bloat") {
+        if let Some(i) = args.iter().position(|arg| arg == "-n") {
+            args.remove(i);
+            limit = match args.get(i).and_then(|n| n.parse().ok()) {
+                Some(n) => n,
+                None => {
+                    eprintln!("usage: lucet-objdump bloat [--json] [-n <functions>] <module.so>");
+                    process::exit(2);
+                }
+            };
+            args.remove(i);
+        }
+    }
+
+    let as_json = args.iter().any(|arg| arg == "--json");
+    let path = args.iter().find(|arg| *arg != "--json").unwrap();
+    let buffer = read_file(path);
+
+    if as_json && subcommand.is_none() {
+        match json::describe(&buffer) {
+            Ok(description) => println!("{:#}", description),
+            Err(e) => {
+                eprintln!("lucet-objdump: {}", e);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+
     let object = object::File::parse(&buffer).expect("parse");
 
     let mut summary = ArtifactSummary::new(&buffer, &object);
     summary.gather();
+    if subcommand.is_some() {
+        let serialized_module = summary
+            .serialized_module
+            .as_ref()
+            .expect("the `lucet_module` symbol is present");
+        let module = load_module(&summary, serialized_module, &[]);
+        let res = match subcommand.as_deref() {
+            Some("bloat") => bloat::bloat(&summary, serialized_module, &module, limit, as_json)
+                .map_err(|e| e.to_string()),
+            Some("stack") => stack::analyze(&summary, &module).map_err(|e| e.to_string()),
+            _ => disasm::disassemble(&summary, &module).map_err(|e| e.to_string()),
+        };
+        if let Err(e) = res {
+            eprintln!("lucet-objdump: {}", e);
+            process::exit(1);
+        }
+        return;
+    }
     print_summary(summary);
 }
 
+fn read_file(path: &str) -> Vec<u8> {
+    let mut fd = File::open(path).expect("open");
+    let mut buffer = Vec::new();
+    fd.read_to_end(&mut buffer).expect("read");
+    buffer
+}
+
 /// Parse a trap manifest for function `f`, if it has one.
 ///
 /// `parse_trap_manifest` may very understandably be confusing. Why not use `f.traps()`? In
@@ -178,8 +277,11 @@ fn load_module<'b, 'a: 'b>(
         )
         .unwrap();
 
-    let module_data =
-        ModuleData::deserialize(module_data_bytes).expect("ModuleData can be deserialized");
+    let module_data = ModuleData::deserialize_format(
+        module_data_bytes,
+        serialized_module.version.format_version(),
+    )
+    .expect("ModuleData can be deserialized");
 
     let function_manifest_bytes = summary
         .read_memory(
@@ -207,23 +309,46 @@ fn summarize_module<'a, 'b: 'a>(summary: &'a ArtifactSummary<'a>, module: &Modul
     let function_manifest = module.function_manifest;
 
     println!("  Heap Specification:");
-    if let Some(heap_spec) = module_data.heap_spec() {
+    if module_data.linear_memories().is_empty() {
+        println!("  {}", "MISSING".red().bold());
+    }
+    for (i, memory) in module_data.linear_memories().iter().enumerate() {
+        let heap_spec = &memory.heap;
+        if module_data.linear_memories().len() > 1 {
+            println!("  Memory {}:", i);
+        }
         println!("  {:9}: {} bytes", "Reserved", heap_spec.reserved_size);
         println!("  {:9}: {} bytes", "Guard", heap_spec.guard_size);
-        println!("  {:9}: {} bytes", "Initial", heap_spec.initial_size);
+        println!(
+            "  {:9}: {} bytes ({} wasm pages)",
+            "Initial",
+            heap_spec.initial_size,
+            heap_spec.initial_size / WASM_PAGE_SIZE
+        );
         if let Some(max_size) = heap_spec.max_size {
-            println!("  {:9}: {} bytes", "Maximum", max_size);
+            println!(
+                "  {:9}: {} bytes ({} wasm pages)",
+                "Maximum",
+                max_size,
+                max_size / WASM_PAGE_SIZE
+            );
         } else {
             println!("  {:9}: None", "Maximum");
         }
-    } else {
-        println!("  {}", "MISSING".red().bold());
+        if i == 0 && !module_data.export_memory_names().is_empty() {
+            println!(
+                "  {:9}: {}",
+                "Exported",
+                module_data.export_memory_names().join(", ")
+            );
+        }
     }
 
     println!("");
     println!("  Sparse Page Data:");
     if let Some(sparse_page_data) = module_data.sparse_data() {
         println!("  {:6}: {}", "Count", sparse_page_data.pages().len());
+        println!("  {:6}: {}", "Chunks", sparse_page_data.chunk_size());
         let mut allempty = true;
         let mut anyempty = false;
         for (i, page) in sparse_page_data.pages().iter().enumerate() {
@@ -265,14 +390,76 @@ fn summarize_module<'a, 'b: 'a>(summary: &'a ArtifactSummary<'a>, module: &Modul
         println!("  No tables.");
     } else {
         for (i, table) in tables.iter().enumerate() {
-            println!("  Table {}: {:?}", i, table);
+            println!("  Table {}: {} elements", i, table.len());
+            for (elem_idx, elem) in table.iter().enumerate() {
+                if elem.is_empty() {
+                    continue;
+                }
+                let addr = elem.function_pointer().as_usize();
+                println!(
+                    "    [{}]: {:#010x} ({})",
+                    elem_idx,
+                    addr,
+                    summary
+                        .get_symbol_name_for_addr(addr as u64)
+                        .unwrap_or("no symbol")
+                );
+            }
+        }
+    }
+    for table in module_data.tables() {
+        println!(
+            "  Table {} elements: {} initial, {} maximum",
+            table.table_idx,
+            table.minimum,
+            table
+                .maximum
+                .map(|maximum| maximum.to_string())
+                .unwrap_or_else(|| "no".to_owned())
+        );
+        for segment in table.segments.iter() {
+            println!(
+                "    Segment at {}: {} functions",
+                segment.offset,
+                segment.functions.len()
+            );
+        }
+        for (elem_idx, func) in table.elements.iter().enumerate() {
+            if let Some(func) = func {
+                let name = module_data
+                    .function_info()
+                    .get(func.as_u32() as usize)
+                    .and_then(|info| info.name)
+                    .unwrap_or("<unnamed>");
+                println!("    [{}]: function {} ({})", elem_idx, func.as_u32(), name);
+            }
         }
     }
 
+    println!("");
+    println!("Features:");
+    let features = module_data.features().enabled();
+    if features.is_empty() {
+        println!("  No features.");
+    } else {
+        println!("  {}", features.join(", "));
+    }
+
     println!("");
     println!("Signatures:");
     for (i, s) in module_data.signatures().iter().enumerate() {
-        println!("  Signature {}: {}", i, s);
+        let uses = module_data
+            .function_info()
+            .iter()
+            .filter(|info| info.signature.as_u32() as usize == i)
+            .count();
+        println!(
+            "  Signature {}: {} ({} {})",
+            i,
+            s,
+            uses,
+            if uses == 1 { "function" } else { "functions" }
+        );
     }
 
     println!("");
@@ -360,8 +547,35 @@ fn summarize_module<'a, 'b: 'a>(summary: &'a ArtifactSummary<'a>, module: &Modul
     println!("");
     println!("Globals:");
     if module_data.globals_spec().len() > 0 {
-        for global_spec in module_data.globals_spec().iter() {
-            println!("  {:?}", global_spec.global());
+        for (i, global_spec) in module_data.globals_spec().iter().enumerate() {
+            let mutability = if global_spec.is_mutable() {
+                "mutable"
+            } else {
+                "immutable"
+            };
+            match global_spec.global() {
+                Global::Def(def) => {
+                    let value = match *def {
+                        GlobalDef::I32(v) => v.to_string(),
+                        GlobalDef::I64(v) => v.to_string(),
+                        GlobalDef::F32(v) => v.to_string(),
+                        GlobalDef::F64(v) => v.to_string(),
+                    };
+                    println!(
+                        "  Global {}: {} = {}, {}",
+                        i,
+                        def.value_type(),
+                        value,
+                        mutability
+                    );
+                }
+                Global::Import { module, field } => {
+                    println!(
+                        "  Global {}: imported from {}::{}, {}",
+                        i, module, field, mutability
+                    );
+                }
+            }
             for name in global_spec.export_names() {
                 println!("    Exported as: {}", name);
             }
@@ -0,0 +1,451 @@
+//! Worst-case native stack use of the guest functions of a module, for `lucet-objdump stack`.
+//!
+//! The frame size of each function is read from its prologue, and the functions it calls are
+//! found as in the annotated disassembly. The worst-case depth of a function is its frame size
+//! plus the largest worst-case depth of the functions it calls. Indirect calls are assumed to
+//! reach any function in the initial elements of the module's tables.
+//!
+//! Two things cannot be bounded this way: recursion, and the stack used by calls that leave the
+//! module, such as hostcalls. Functions that may reach either are flagged, and their depth only
+//! covers the guest frames that could be bounded. Functions that call each other, directly or
+//! not, are collapsed into one node before depths are computed, and each of them is given the
+//! depth of running every function of the cycle once, plus the deepest call out of it.
+
+use crate::disasm::{self, CallTargets, Operand};
+use crate::ArtifactSummary;
+use capstone::Insn;
+use lucet_module::Module;
+use std::collections::{BTreeSet, HashMap};
+
+/// The size of the return address a call pushes.
+const RETURN_ADDRESS_SIZE: u64 = 8;
+/// The size of a pushed register.
+const PUSH_SIZE: u64 = 8;
+/// The stack probe `lucetc` includes in every module, which uses no stack of its own.
+const STACK_PROBE_SYM: &str = "lucet_probestack";
+
+struct Function<'a> {
+    name: &'a str,
+    frame_size: u64,
+    /// The guest functions called directly, by index.
+    callees: BTreeSet<usize>,
+    indirect_calls: bool,
+    /// The functions outside the module that are called.
+    external_calls: BTreeSet<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Depth {
+    bytes: u64,
+    recursive: bool,
+    external_calls: bool,
+}
+
+pub fn analyze(summary: &ArtifactSummary<'_>, module: &Module<'_>) -> Result<(), capstone::Error> {
+    let cs = disasm::capstone()?;
+    let targets = CallTargets::new(summary, module);
+    let addresses: HashMap<u64, usize> = module
+        .function_manifest
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.ptr().as_usize() as u64, i))
+        .collect();
+
+    let mut functions = vec![];
+    for (i, f) in module.function_manifest.iter().enumerate() {
+        let name = module
+            .module_data
+            .function_info()
+            .get(i)
+            .and_then(|info| info.name)
+            .unwrap_or("<unnamed>");
+        let mut function = Function {
+            name,
+            frame_size: RETURN_ADDRESS_SIZE,
+            callees: BTreeSet::new(),
+            indirect_calls: false,
+            external_calls: BTreeSet::new(),
+        };
+
+        let start = f.ptr().as_usize() as u64;
+        if let Some(code) = summary.read_memory(start, u64::from(f.code_len())) {
+            let insns = cs.disasm_all(code, start)?;
+            function.frame_size = frame_size(insns.iter());
+            for insn in insns.iter().filter(|insn| insn.mnemonic() == Some("call")) {
+                match disasm::operand(&cs, &insn) {
+                    Some(Operand::Direct(target)) if addresses.contains_key(&target) => {
+                        function.callees.insert(addresses[&target]);
+                    }
+                    Some(_) => match targets.name(&cs, &insn) {
+                        Some(ref name) if name == STACK_PROBE_SYM => (),
+                        Some(name) => {
+                            function.external_calls.insert(name);
+                        }
+                        None => {
+                            function.external_calls.insert("<unknown>".to_owned());
+                        }
+                    },
+                    None => function.indirect_calls = true,
+                }
+            }
+        }
+        functions.push(function);
+    }
+
+    let table_functions: BTreeSet<usize> = module
+        .module_data
+        .tables()
+        .iter()
+        .flat_map(|table| table.elements.iter().filter_map(|func| *func))
+        .map(|func| func.as_u32() as usize)
+        .filter(|func| *func < functions.len())
+        .collect();
+    let graph = CallGraph {
+        functions: &functions,
+        table_functions,
+    };
+    let depths = graph.depths();
+
+    println!("Functions:");
+    for (i, (function, depth)) in functions.iter().zip(depths.iter()).enumerate() {
+        println!(
+            "  Function {} ({}): frame {} bytes, worst-case depth {} bytes{}",
+            i,
+            function.name,
+            function.frame_size,
+            depth.bytes,
+            flags(depth)
+        );
+        if function.indirect_calls {
+            println!(
+                "    makes indirect calls, assumed to reach any of {} table functions",
+                graph.table_functions.len()
+            );
+        }
+        for name in function.external_calls.iter() {
+            println!("    calls {}, outside the module", name);
+        }
+    }
+
+    let mut exports = module
+        .module_data
+        .export_functions()
+        .iter()
+        .flat_map(|export| {
+            let depth = depths.get(export.fn_idx.as_u32() as usize).copied();
+            export.names.iter().map(move |name| (*name, depth))
+        })
+        .filter_map(|(name, depth)| Some((name, depth?)))
+        .collect::<Vec<_>>();
+    exports.sort_by(|(_, a), (_, b)| b.bytes.cmp(&a.bytes));
+
+    println!();
+    println!("Exported functions, by worst-case depth:");
+    if exports.is_empty() {
+        println!("  No exported functions.");
+    }
+    for (name, depth) in exports.iter() {
+        println!("  {:>8} bytes: {}{}", depth.bytes, name, flags(depth));
+    }
+    if let Some((name, depth)) = exports.first() {
+        println!();
+        println!(
+            "The guest frames of `{}` may use up to {} bytes of stack{}.",
+            name,
+            depth.bytes,
+            if depth.recursive || depth.external_calls {
+                ", not counting recursion or calls outside the module"
+            } else {
+                ""
+            }
+        );
+    }
+    Ok(())
+}
+
+struct CallGraph<'a> {
+    functions: &'a [Function<'a>],
+    /// The functions indirect calls may reach.
+    table_functions: BTreeSet<usize>,
+}
+
+impl<'a> CallGraph<'a> {
+    fn callees(&self, f: usize) -> impl Iterator<Item = &usize> + '_ {
+        let function = &self.functions[f];
+        let indirect = if function.indirect_calls {
+            Some(self.table_functions.iter())
+        } else {
+            None
+        };
+        function
+            .callees
+            .iter()
+            .chain(indirect.into_iter().flatten())
+    }
+
+    /// The worst-case depth of every function.
+    ///
+    /// Each strongly connected component of the call graph is one node: functions in a cycle, or
+    /// calling themselves, get the depth of the frames of the whole component plus the deepest call
+    /// out of it, and are flagged as recursive.
+    fn depths(&self) -> Vec<Depth> {
+        let components = self.components();
+        let mut component_of = vec![0; self.functions.len()];
+        for (c, members) in components.iter().enumerate() {
+            for &f in members {
+                component_of[f] = c;
+            }
+        }
+
+        let mut depths: Vec<Depth> = Vec::with_capacity(components.len());
+        for (c, members) in components.iter().enumerate() {
+            let mut frames = 0;
+            let mut deepest = Depth {
+                recursive: members.len() > 1,
+                ..Depth::default()
+            };
+            for &f in members {
+                frames += self.functions[f].frame_size;
+                deepest.external_calls |= !self.functions[f].external_calls.is_empty();
+                for &callee in self.callees(f) {
+                    if component_of[callee] == c {
+                        deepest.recursive = true;
+                        continue;
+                    }
+                    // components come after the ones they call
+                    let depth = depths[component_of[callee]];
+                    deepest.bytes = deepest.bytes.max(depth.bytes);
+                    deepest.recursive |= depth.recursive;
+                    deepest.external_calls |= depth.external_calls;
+                }
+            }
+            depths.push(Depth {
+                bytes: frames + deepest.bytes,
+                ..deepest
+            });
+        }
+        component_of.iter().map(|&c| depths[c]).collect()
+    }
+
+    /// The strongly connected components of the call graph, found with Tarjan's algorithm, each
+    /// after the components its functions call.
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut tarjan = Tarjan {
+            index: vec![None; self.functions.len()],
+            lowlink: vec![0; self.functions.len()],
+            on_stack: vec![false; self.functions.len()],
+            stack: vec![],
+            next_index: 0,
+            components: vec![],
+        };
+        for f in 0..self.functions.len() {
+            if tarjan.index[f].is_none() {
+                self.connect(f, &mut tarjan);
+            }
+        }
+        tarjan.components
+    }
+
+    fn connect(&self, f: usize, tarjan: &mut Tarjan) {
+        tarjan.index[f] = Some(tarjan.next_index);
+        tarjan.lowlink[f] = tarjan.next_index;
+        tarjan.next_index += 1;
+        tarjan.stack.push(f);
+        tarjan.on_stack[f] = true;
+
+        for &callee in self.callees(f) {
+            match tarjan.index[callee] {
+                None => {
+                    self.connect(callee, tarjan);
+                    tarjan.lowlink[f] = tarjan.lowlink[f].min(tarjan.lowlink[callee]);
+                }
+                Some(index) if tarjan.on_stack[callee] => {
+                    tarjan.lowlink[f] = tarjan.lowlink[f].min(index);
+                }
+                Some(_) => (),
+            }
+        }
+
+        if Some(tarjan.lowlink[f]) == tarjan.index[f] {
+            let mut component = vec![];
+            loop {
+                let member = tarjan.stack.pop().expect("f is on the stack");
+                tarjan.on_stack[member] = false;
+                component.push(member);
+                if member == f {
+                    break;
+                }
+            }
+            tarjan.components.push(component);
+        }
+    }
+}
+
+/// The state of Tarjan's algorithm over a call graph.
+struct Tarjan {
+    index: Vec<Option<usize>>,
+    lowlink: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    components: Vec<Vec<usize>>,
+}
+
+fn flags(depth: &Depth) -> String {
+    let mut flags = String::new();
+    if depth.recursive {
+        flags.push_str(" [recursive]");
+    }
+    if depth.external_calls {
+        flags.push_str(" [calls outside the module]");
+    }
+    flags
+}
+
+/// The stack a function uses for its own frame, as set up by its prologue: the return address,
+/// the saved registers, and the space it reserves. Frames of a page or more are reserved by
+/// moving their size to `rax` and calling the stack probe before subtracting it from `rsp`.
+fn frame_size<'i>(insns: impl Iterator<Item = Insn<'i>>) -> u64 {
+    let mut size = RETURN_ADDRESS_SIZE;
+    let mut rax = None;
+    for insn in insns {
+        let operands = insn.op_str().unwrap_or("");
+        match (insn.mnemonic().unwrap_or(""), operands) {
+            ("endbr64", _) => (),
+            ("push", _) => size += PUSH_SIZE,
+            ("mov", "rbp, rsp") => (),
+            ("mov", _) if operands.starts_with("eax, ") || operands.starts_with("rax, ") => {
+                rax = immediate(&operands[5..]);
+            }
+            ("call", _) if rax.is_some() => (),
+            ("sub", "rsp, rax") => size += rax.unwrap_or(0),
+            ("sub", _) if operands.starts_with("rsp, ") => match immediate(&operands[5..]) {
+                Some(imm) => size += imm,
+                None => break,
+            },
+            _ => break,
+        }
+    }
+    size
+}
+
+fn immediate(operand: &str) -> Option<u64> {
+    if operand.starts_with("0x") {
+        u64::from_str_radix(&operand[2..], 16).ok()
+    } else {
+        operand.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame_size_of(code: &[u8]) -> u64 {
+        let cs = disasm::capstone().expect("capstone");
+        let insns = cs.disasm_all(code, 0x1000).expect("code disassembles");
+        frame_size(insns.iter())
+    }
+
+    #[test]
+    fn frame_size_counts_pushes_and_reservations() {
+        // push rbp; mov rbp, rsp; push rbx; sub rsp, 0x20; mov eax, 1; ret
+        let code = [
+            0x55, 0x48, 0x89, 0xe5, 0x53, 0x48, 0x83, 0xec, 0x20, 0xb8, 0x01, 0x00, 0x00, 0x00,
+            0xc3,
+        ];
+        assert_eq!(frame_size_of(&code), 8 + 8 + 8 + 0x20);
+        // ret
+        assert_eq!(frame_size_of(&[0xc3]), 8);
+    }
+
+    #[test]
+    fn frame_size_follows_the_stack_probe() {
+        // push rbp; mov rbp, rsp; mov eax, 0x2000; call lucet_probestack; sub rsp, rax; ret
+        let code = [
+            0x55, 0x48, 0x89, 0xe5, 0xb8, 0x00, 0x20, 0x00, 0x00, 0xe8, 0x00, 0x00, 0x00, 0x00,
+            0x48, 0x29, 0xc4, 0xc3,
+        ];
+        assert_eq!(frame_size_of(&code), 8 + 8 + 0x2000);
+    }
+
+    #[test]
+    fn immediates_are_decimal_or_hex() {
+        assert_eq!(immediate("0x20"), Some(0x20));
+        assert_eq!(immediate("32"), Some(32));
+        assert_eq!(immediate("rax"), None);
+        assert_eq!(immediate("0x"), None);
+    }
+
+    fn function(frame_size: u64, callees: &[usize]) -> Function<'static> {
+        Function {
+            name: "f",
+            frame_size,
+            callees: callees.iter().copied().collect(),
+            indirect_calls: false,
+            external_calls: BTreeSet::new(),
+        }
+    }
+
+    fn depth(bytes: u64, recursive: bool) -> Depth {
+        Depth {
+            bytes,
+            recursive,
+            external_calls: false,
+        }
+    }
+
+    #[test]
+    fn depths_collapse_cycles() {
+        let functions = vec![
+            // calls into the cycle of 1 and 2
+            function(16, &[1]),
+            function(32, &[2]),
+            // closes the cycle, and calls 3 out of it
+            function(64, &[1, 3]),
+            function(8, &[]),
+            // calls itself
+            function(24, &[4, 3]),
+            // calls 2 before reaching 1, unlike 0
+            function(16, &[2]),
+        ];
+        let graph = CallGraph {
+            functions: &functions,
+            table_functions: BTreeSet::new(),
+        };
+        assert_eq!(
+            graph.depths(),
+            [
+                depth(16 + 32 + 64 + 8, true),
+                depth(32 + 64 + 8, true),
+                depth(32 + 64 + 8, true),
+                depth(8, false),
+                depth(24 + 8, true),
+                depth(16 + 32 + 64 + 8, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn depths_follow_indirect_and_external_calls() {
+        let mut caller = function(16, &[]);
+        caller.indirect_calls = true;
+        let mut hostcall_user = function(8, &[]);
+        hostcall_user.external_calls.insert("hostcall".to_owned());
+        let functions = vec![caller, hostcall_user, function(40, &[])];
+        let graph = CallGraph {
+            functions: &functions,
+            table_functions: [1, 2].iter().copied().collect(),
+        };
+        let depths = graph.depths();
+        assert_eq!(
+            depths[0],
+            Depth {
+                bytes: 16 + 40,
+                recursive: false,
+                external_calls: true,
+            }
+        );
+        assert_eq!(depths[2], depth(40, false));
+    }
+}
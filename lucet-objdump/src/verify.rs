@@ -0,0 +1,32 @@
+//! Checking the signature of a module, for `lucet-objdump verify`.
+
+use lucet_module::{ModuleArtifact, PublicKey};
+
+/// Check the signature of the module in the shared object `bytes` against the public key in the
+/// file `pk_path`, printing the ID of the key that signed it and the result.
+///
+/// Returns whether the signature is valid.
+pub fn verify(bytes: &[u8], pk_path: &str) -> Result<bool, String> {
+    let pk = PublicKey::from_file(pk_path)
+        .map_err(|e| format!("cannot read public key `{}`: {}", pk_path, e))?;
+    let artifact = ModuleArtifact::parse(bytes).map_err(|e| e.to_string())?;
+
+    let key_id = match artifact.signer_key_id() {
+        Some(key_id) => key_id,
+        None => {
+            println!("Signature: not signed");
+            return Ok(false);
+        }
+    };
+    println!("Signer key ID: {}", key_id);
+    match artifact.verify_signature(&pk) {
+        Ok(()) => {
+            println!("Signature: valid");
+            Ok(true)
+        }
+        Err(e) => {
+            println!("Signature: invalid ({})", e);
+            Ok(false)
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use lucetc::{signature, Lucetc};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// A guest with an initial heap of two segments, a recursive function, and one that traps.
+const GUEST: &str = r#"
+(module
+  (memory 1)
+  (data (i32.const 4096) "hello")
+  (data (i32.const 40960) "world")
+  (func $fib (export "fib") (param i32) (result i32)
+    (if (result i32) (i32.lt_u (local.get 0) (i32.const 2))
+      (then (local.get 0))
+      (else
+        (i32.add
+          (call $fib (i32.sub (local.get 0) (i32.const 1)))
+          (call $fib (i32.sub (local.get 0) (i32.const 2)))))))
+  (func (export "trap")
+    unreachable))
+"#;
+
+/// Compile `wat` with `lucetc` to the shared object `name`.
+fn compile(tmp: &TempDir, name: &str, wat: &str) -> PathBuf {
+    let wat_path = tmp.path().join(name).with_extension("wat");
+    std::fs::write(&wat_path, wat).expect("write guest");
+    let so = tmp.path().join(name).with_extension("so");
+    Lucetc::new(&wat_path)
+        .shared_object_file(&so)
+        .expect("compile guest");
+    so
+}
+
+fn objdump(args: &[&str], module: &Path) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lucet-objdump"))
+        .args(args)
+        .arg(module)
+        .output()
+        .expect("run lucet-objdump")
+}
+
+fn stdout(output: &Output) -> String {
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    String::from_utf8(output.stdout.clone()).expect("output is UTF-8")
+}
+
+#[test]
+fn summary_prints_heap_specs() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = compile(&tmp, "guest", GUEST);
+    let out = stdout(&objdump(&[], &guest));
+    assert!(out.contains("Heap Specification:"));
+    assert!(out.contains("65536 bytes (1 wasm pages)"));
+}
+
+#[test]
+fn json_describes_the_module() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = compile(&tmp, "guest", GUEST);
+    let description: Value =
+        serde_json::from_str(&stdout(&objdump(&["--json"], &guest))).expect("output is JSON");
+    assert_eq!(description["heap"]["initial_size"], 65536);
+    assert_eq!(description["sizes"]["functions"], 2);
+    let exports = description["exports"]["functions"]
+        .as_array()
+        .expect("exported functions");
+    assert_eq!(exports.len(), 2);
+    assert_eq!(exports[0]["names"][0], "fib");
+}
+
+#[test]
+fn disasm_annotates_traps() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = compile(&tmp, "guest", GUEST);
+    let out = stdout(&objdump(&["disasm"], &guest));
+    assert!(out.contains("Function 0 ("));
+    assert!(out.contains("; trap: "));
+}
+
+#[test]
+fn diff_lists_new_exports() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let old = compile(&tmp, "old", GUEST);
+    let new = compile(
+        &tmp,
+        "new",
+        &GUEST.replace(
+            "(func (export \"trap\")",
+            "(func (export \"one\") (result i32) (i32.const 1))\n  (func (export \"trap\")",
+        ),
+    );
+    let out = stdout(&objdump(&["diff", old.to_str().unwrap()], &new));
+    assert!(out.contains("Exports:"));
+    assert!(out.contains("  + one: () -> I32"));
+
+    // identical modules do not differ
+    let out = stdout(&objdump(&["diff", old.to_str().unwrap()], &old));
+    assert_eq!(out, "");
+}
+
+#[test]
+fn verify_checks_the_signature() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = compile(&tmp, "guest", GUEST);
+    let key = |name: &str| tmp.path().join(name).to_str().unwrap().to_owned();
+    signature::keygen(key("pk"), format!("raw:{}", key("sk"))).expect("generate a key pair");
+    signature::keygen(key("other.pk"), format!("raw:{}", key("other.sk")))
+        .expect("generate another key pair");
+    signature::sign_module(
+        &guest,
+        &signature::sk_from_file(format!("raw:{}", key("sk"))).expect("read the secret key"),
+    )
+    .expect("sign the module");
+
+    let output = objdump(&["verify", "--pk", &key("pk")], &guest);
+    assert_eq!(output.status.code(), Some(0));
+    let output = objdump(&["verify", "--pk", &key("other.pk")], &guest);
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn stack_flags_recursion() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = compile(&tmp, "guest", GUEST);
+    let out = stdout(&objdump(&["stack"], &guest));
+    assert!(out.contains("Exported functions, by worst-case depth:"));
+    assert!(out.contains(": fib [recursive]"));
+}
+
+#[test]
+fn bloat_attributes_the_whole_file() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = compile(&tmp, "guest", GUEST);
+    let bloat: Value = serde_json::from_str(&stdout(&objdump(&["bloat", "--json"], &guest)))
+        .expect("output is JSON");
+
+    let categories = bloat["categories"].as_array().expect("categories");
+    let total = categories
+        .iter()
+        .map(|category| category["bytes"].as_u64().unwrap())
+        .sum::<u64>();
+    assert_eq!(Some(total), bloat["file_size"].as_u64());
+
+    // each data segment is in a page of its own
+    let segments = bloat["initial_heap"].as_array().expect("initial heap");
+    assert_eq!(segments.len(), 2);
+    assert_eq!(segments[0]["offset"], 4096);
+    assert_eq!(segments[1]["offset"], 40960);
+    let initial_heap = categories
+        .iter()
+        .find(|category| category["name"] == "initial heap")
+        .expect("initial heap category");
+    assert_eq!(
+        initial_heap["bytes"].as_u64(),
+        Some(
+            segments
+                .iter()
+                .map(|segment| segment["bytes"].as_u64().unwrap())
+                .sum()
+        )
+    );
+}
@@ -0,0 +1,212 @@
+#![deny(bare_trait_objects)]
+
+//! What the runners `lucet-run` and `lucet-wasi` have in common: the options limiting the
+//! instance, calling an export with arguments given on the command line, and the exit codes for
+//! how the guest ended.
+
+use anyhow::{bail, format_err, Error};
+use clap::{Arg, ArgMatches};
+use lucet_module::ValueType;
+use lucet_runtime::{
+    self, KillSwitch, Limits, Module, RunResult, TerminationDetails, UntypedRetVal, Val,
+};
+use std::any::Any;
+use std::thread;
+use std::time::Duration;
+
+/// The exit code when the guest runs out of time, as with `timeout(1)`.
+pub const EXIT_TIMEOUT: i32 = 124;
+/// The exit code when the guest cannot be run, or its instance fails for another reason.
+pub const EXIT_ERROR: i32 = 125;
+/// The exit code when the guest traps, as for a process that aborts.
+pub const EXIT_TRAP: i32 = 134;
+
+/// Parse a size such as `4 GiB`, or a number of bytes.
+pub fn parse_humansized(desc: &str) -> Result<u64, Error> {
+    use human_size::{Byte, ParsingError, Size, SpecificSize};
+    match desc.parse::<Size>() {
+        Ok(s) => {
+            let bytes: SpecificSize<Byte> = s.into();
+            Ok(bytes.value() as u64)
+        }
+        Err(ParsingError::MissingMultiple) => Ok(desc.parse::<u64>()?),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The options for the limits of the instance, and for how long it may run.
+pub fn limit_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("heap_memory_size")
+            .long("max-heap-size")
+            .takes_value(true)
+            .default_value("4 GiB")
+            .help("Maximum heap size (must be a multiple of 4 KiB)"),
+        Arg::with_name("heap_address_space_size")
+            .long("heap-address-space")
+            .takes_value(true)
+            .default_value("8 GiB")
+            .help("Maximum heap address space size (must be a multiple of 4 KiB, and >= `max-heap-size`)"),
+        Arg::with_name("stack_size")
+            .long("stack-size")
+            .takes_value(true)
+            .default_value("8 MiB")
+            .help("Maximum stack size (must be a multiple of 4 KiB)"),
+        Arg::with_name("timeout")
+            .long("timeout")
+            .value_name("SECS")
+            .takes_value(true)
+            .help("Number of seconds the instance will be allowed to run"),
+    ]
+}
+
+/// The limits given with the options of `limit_args()`. The size of the globals is left to be
+/// calculated from the module.
+pub fn limits(matches: &ArgMatches<'_>) -> Result<Limits, Error> {
+    let size = |name: &str| {
+        matches
+            .value_of(name)
+            .ok_or_else(|| format_err!("missing {}", name))
+            .and_then(parse_humansized)
+            .map(|size| size as usize)
+            .map_err(|e| format_err!("Invalid {}: {}", name, e))
+    };
+    Ok(Limits::builder()
+        .with_heap_memory_size(size("heap_memory_size")?)
+        .with_heap_address_space_size(size("heap_address_space_size")?)
+        .with_stack_size(size("stack_size")?)
+        .with_globals_size(0) // calculated from module
+        .build()?)
+}
+
+/// The timeout given with `--timeout`, if any.
+pub fn timeout(matches: &ArgMatches<'_>) -> Result<Option<Duration>, Error> {
+    matches
+        .value_of("timeout")
+        .map(|t| match t.parse::<f64>() {
+            Ok(secs) if secs.is_finite() && secs >= 0.0 => Ok(Duration::from_secs_f64(secs)),
+            _ => Err(format_err!("Invalid timeout: {}", t)),
+        })
+        .transpose()
+}
+
+/// Print `err` and the usage of the command line to stderr, and exit with `EXIT_ERROR`.
+pub fn exit_with_usage(matches: &ArgMatches<'_>, err: Error) -> ! {
+    eprintln!("{}", err);
+    eprintln!("{}", matches.usage());
+    std::process::exit(EXIT_ERROR);
+}
+
+/// Terminate the instance of `kill_switch` once `timeout` has passed.
+pub fn terminate_after(kill_switch: KillSwitch, timeout: Duration) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        // We may hit this line exactly when the guest returns, so sometimes `terminate` can
+        // fail. That's still acceptable, so just ignore the result.
+        kill_switch.terminate().ok();
+    });
+}
+
+/// A call to an exported function.
+pub struct Invocation<'a> {
+    pub func: &'a str,
+    pub args: Vec<Val>,
+    ret_ty: Option<ValueType>,
+}
+
+impl<'a> Invocation<'a> {
+    /// Parse the arguments `args` for the export `func` of `module`, as the types it takes.
+    pub fn parse(module: &dyn Module, func: &'a str, args: &[&str]) -> Result<Self, Error> {
+        let handle = module.get_export_func(func)?;
+        let signature = module.get_signature(handle.id);
+        if args.len() != signature.params.len() {
+            bail!(
+                "its signature is {}, but {} arguments were given",
+                signature,
+                args.len()
+            );
+        }
+        let args = signature
+            .params
+            .iter()
+            .zip(args)
+            .map(|(ty, arg)| parse_val(ty, arg))
+            .collect::<Result<_, _>>()?;
+        Ok(Invocation {
+            func,
+            args,
+            ret_ty: signature.ret_ty,
+        })
+    }
+
+    /// Print what the function returned, as the type it returns.
+    pub fn print_result(&self, ret: &UntypedRetVal) {
+        match self.ret_ty {
+            Some(ValueType::I32) => println!("{}", ret.as_i32()),
+            Some(ValueType::I64) => println!("{}", ret.as_i64()),
+            Some(ValueType::F32) => println!("{}", ret.as_f32()),
+            Some(ValueType::F64) => println!("{}", ret.as_f64()),
+            None => (),
+        }
+    }
+}
+
+/// Parse an argument of type `ty`. Integers can be given unsigned as well, as in `4294967295` for
+/// the i32 `-1`.
+pub fn parse_val(ty: &ValueType, arg: &str) -> Result<Val, Error> {
+    let val = match ty {
+        ValueType::I32 => arg
+            .parse::<i32>()
+            .or_else(|_| arg.parse::<u32>().map(|v| v as i32))
+            .map(Val::I32)
+            .ok(),
+        ValueType::I64 => arg
+            .parse::<i64>()
+            .or_else(|_| arg.parse::<u64>().map(|v| v as i64))
+            .map(Val::I64)
+            .ok(),
+        ValueType::F32 => arg.parse::<f32>().map(Val::F32).ok(),
+        ValueType::F64 => arg.parse::<f64>().map(Val::F64).ok(),
+    };
+    val.ok_or_else(|| format_err!("invalid {} argument: {}", ty, arg))
+}
+
+/// The exit code for the outcome of running the guest, which is its own if it exits, describing
+/// on stderr, after the name of the `runner`, how it ended otherwise.
+///
+/// When a hostcall terminates the guest, the details it provides are given to `provided` to make
+/// the exit code of.
+pub fn exit_code(
+    runner: &str,
+    res: Result<RunResult, lucet_runtime::Error>,
+    provided: impl FnOnce(&dyn Any) -> i32,
+) -> i32 {
+    match res {
+        Ok(RunResult::Returned(_)) => 0,
+        // there is no host to resume the guest with what it expects
+        Ok(RunResult::Yielded(_)) => {
+            eprintln!("{}: the guest yielded, but cannot be resumed", runner);
+            EXIT_ERROR
+        }
+        Ok(RunResult::Exited(status)) => status,
+        Err(lucet_runtime::Error::RuntimeTerminated(TerminationDetails::Provided(any))) => {
+            provided(any.as_ref())
+        }
+        Err(lucet_runtime::Error::RuntimeTerminated(TerminationDetails::Remote)) => {
+            eprintln!("{}: the guest ran out of time", runner);
+            EXIT_TIMEOUT
+        }
+        Err(lucet_runtime::Error::RuntimeFault(details)) => {
+            eprintln!("{}: the guest trapped: {}", runner, details);
+            EXIT_TRAP
+        }
+        Err(lucet_runtime::Error::RuntimeTerminated(TerminationDetails::Signal)) => {
+            eprintln!("{}: the guest was terminated by a signal", runner);
+            EXIT_TRAP
+        }
+        Err(e) => {
+            eprintln!("{}: runtime error: {}", runner, e);
+            EXIT_ERROR
+        }
+    }
+}
@@ -0,0 +1,221 @@
+#![deny(bare_trait_objects)]
+
+//! `lucet-run`: call an exported function of a Lucet module, without assuming WASI.
+//!
+//! The module's imports are resolved against the shared objects given with `--hostcalls`, which
+//! are loaded before it, and against the hostcalls of the runtime itself. The arguments of the
+//! function are parsed as the types it takes, and its result is printed as the type it returns.
+
+#[macro_use]
+extern crate clap;
+
+use anyhow::{bail, format_err, Error};
+use clap::{AppSettings, Arg};
+use lucet_run::{Invocation, EXIT_ERROR};
+use lucet_runtime::{self, DlModule, Limits, MmapRegion, Module, PublicKey, Region, RunResult};
+use std::ffi::{CStr, CString};
+use std::sync::Arc;
+use std::time::Duration;
+
+struct Config<'a> {
+    lucet_module: &'a str,
+    /// The export to call; without one, the exports are listed instead.
+    func: Option<&'a str>,
+    args: Vec<&'a str>,
+    hostcalls: Vec<&'a str>,
+    limits: Limits,
+    timeout: Option<Duration>,
+    pk_path: Option<&'a str>,
+}
+
+fn main() {
+    // No-op, but makes sure the linker doesn't throw away the parts of the runtime that
+    // hostcalls use:
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let matches = app_from_crate!()
+        .setting(AppSettings::AllowNegativeNumbers)
+        .arg(
+            Arg::with_name("lucet_module")
+                .required(true)
+                .help("Path to the `lucetc`-compiled module"),
+        )
+        .arg(
+            Arg::with_name("func")
+                .required(false)
+                .help("The exported function to call; without one, the exported functions are listed"),
+        )
+        .arg(
+            Arg::with_name("args")
+                .required(false)
+                .multiple(true)
+                .help("Arguments to the function, as the i32, i64, f32, or f64 it takes in their place"),
+        )
+        .arg(
+            Arg::with_name("hostcalls")
+                .long("hostcalls")
+                .value_name("SHARED_OBJECT")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("A shared object defining host functions the module imports")
+                .long_help(
+                    "A shared object defining host functions the module imports, under the \
+                     symbol names `lucetc` gives them with its bindings. The shared objects are \
+                     loaded before the module, in the order they are given, and their symbols \
+                     are made available to it.\
+                     \n\n\
+                     Host functions written in Rust should use `#[lucet_hostcall]`; those \
+                     written in C can use the `lucet_vmctx_*` functions of `lucet_vmctx.h`, \
+                     which this runner exports.",
+                ),
+        )
+        .args(&lucet_run::limit_args())
+        .arg(
+            Arg::with_name("verify")
+                .long("--signature-verify")
+                .takes_value(false)
+                .requires("pk_path")
+                .help("Verify the signature of the module")
+        )
+        .arg(
+            Arg::with_name("pk_path")
+                .long("--signature-pk")
+                .takes_value(true)
+                .help("Path to the public key to verify the module signature")
+        )
+        .after_help(
+            "The exit code is 0 when the function returns, the guest's own when a hostcall \
+             exits the instance, 124 when it runs out of time, 134 when it traps, and 125 when \
+             it cannot be run or fails otherwise.",
+        )
+        .get_matches();
+
+    let limits =
+        lucet_run::limits(&matches).unwrap_or_else(|e| lucet_run::exit_with_usage(&matches, e));
+    let timeout =
+        lucet_run::timeout(&matches).unwrap_or_else(|e| lucet_run::exit_with_usage(&matches, e));
+
+    let config = Config {
+        lucet_module: matches.value_of("lucet_module").unwrap(),
+        func: matches.value_of("func"),
+        args: matches
+            .values_of("args")
+            .map(|vals| vals.collect())
+            .unwrap_or_default(),
+        hostcalls: matches
+            .values_of("hostcalls")
+            .map(|vals| vals.collect())
+            .unwrap_or_default(),
+        limits,
+        timeout,
+        pk_path: if matches.is_present("verify") {
+            matches.value_of("pk_path")
+        } else {
+            None
+        },
+    };
+
+    match run(config) {
+        Ok(exitcode) => std::process::exit(exitcode),
+        Err(e) => {
+            eprintln!("lucet-run: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
+}
+
+fn run(config: Config<'_>) -> Result<i32, Error> {
+    for path in config.hostcalls.iter() {
+        load_hostcalls(path)?;
+    }
+    let module = match config.pk_path {
+        Some(pk_path) => DlModule::load_and_verify(
+            config.lucet_module,
+            PublicKey::from_file(pk_path)
+                .map_err(|e| format_err!("cannot read public key {}: {}", pk_path, e))?,
+        )?,
+        None => DlModule::load(config.lucet_module)?,
+    };
+
+    let func = match config.func {
+        Some(func) => func,
+        None => {
+            list_exports(module.as_ref());
+            return Ok(0);
+        }
+    };
+    let invocation = Invocation::parse(module.as_ref(), func, &config.args)
+        .map_err(|e| format_err!("cannot call {}: {}", func, e))?;
+
+    let min_globals_size = module.initial_globals_size();
+    let globals_size = ((min_globals_size + 4096 - 1) / 4096) * 4096;
+    let region = MmapRegion::create(
+        1,
+        &Limits {
+            globals_size,
+            ..config.limits
+        },
+    )?;
+    let mut inst = region
+        .new_instance_builder(module as Arc<dyn Module>)
+        .build()?;
+
+    if let Some(timeout) = config.timeout {
+        lucet_run::terminate_after(inst.kill_switch(), timeout);
+    }
+
+    let res = inst
+        .run_start()
+        .and_then(|()| inst.run(invocation.func, &invocation.args));
+    Ok(match res {
+        Ok(RunResult::Returned(ret)) => {
+            invocation.print_result(&ret);
+            0
+        }
+        res => exit_code(res),
+    })
+}
+
+/// Load the shared object of host functions at `path`, making its symbols available to the
+/// modules loaded after it.
+fn load_hostcalls(path: &str) -> Result<(), Error> {
+    let c_path = CString::new(path)?;
+    // the host functions must stay loaded for as long as a module may call them, so the handle
+    // is never closed
+    let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_GLOBAL) };
+    if handle.is_null() {
+        let err = unsafe { CStr::from_ptr(libc::dlerror()) };
+        bail!(
+            "cannot load hostcalls from {}: {}",
+            path,
+            err.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+/// Print the exported functions of `module`, with their signatures.
+fn list_exports(module: &dyn Module) {
+    for export in module.export_functions() {
+        let signature = module.get_signature(export.fn_idx);
+        for name in export.names.iter() {
+            println!("{}: {}", name, signature);
+        }
+    }
+}
+
+/// The exit code for an outcome of calling the function other than it returning.
+fn exit_code(res: Result<RunResult, lucet_runtime::Error>) -> i32 {
+    lucet_run::exit_code("lucet-run", res, |details| {
+        let details = details
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| details.downcast_ref::<&str>().copied());
+        match details {
+            Some(details) => eprintln!("lucet-run: a hostcall terminated the guest: {}", details),
+            None => eprintln!("lucet-run: a hostcall terminated the guest"),
+        }
+        EXIT_ERROR
+    })
+}
@@ -0,0 +1,96 @@
+use lucet_module::bindings::Bindings;
+use lucetc::{Lucetc, LucetcOpts};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// A guest calling a host function it imports, and one that traps.
+const GUEST: &str = r#"
+(module
+  (import "env" "double" (func $double (param i32) (result i32)))
+  (func (export "double_then_add") (param i32 i32) (result i32)
+    (i32.add (call $double (local.get 0)) (local.get 1)))
+  (func (export "trap")
+    unreachable))
+"#;
+
+const HOSTCALLS: &str = r#"
+#include <stdint.h>
+
+struct lucet_vmctx;
+
+int32_t lucet_run_test_double(struct lucet_vmctx *ctx, int32_t x) { return x * 2; }
+"#;
+
+/// Compile `GUEST` with `lucetc`, binding `env::double` to the host function of `HOSTCALLS`.
+fn guest_so(tmp: &TempDir) -> PathBuf {
+    let wat = tmp.path().join("guest.wat");
+    std::fs::write(&wat, GUEST).expect("write guest");
+    let so = tmp.path().join("guest.so");
+    Lucetc::new(&wat)
+        .with_bindings(
+            Bindings::from_str(r#"{ "env": { "double": "lucet_run_test_double" } }"#)
+                .expect("parse bindings"),
+        )
+        .shared_object_file(&so)
+        .expect("compile guest");
+    so
+}
+
+/// Compile `HOSTCALLS` to a shared object with the C compiler.
+fn hostcalls_so(tmp: &TempDir) -> PathBuf {
+    let c = tmp.path().join("hostcalls.c");
+    std::fs::write(&c, HOSTCALLS).expect("write hostcalls");
+    let so = tmp.path().join("libhostcalls.so");
+    let status = Command::new(std::env::var_os("CC").unwrap_or_else(|| "cc".into()))
+        .args(&["-shared", "-fPIC", "-o"])
+        .arg(&so)
+        .arg(&c)
+        .status()
+        .expect("run the C compiler");
+    assert!(status.success(), "hostcalls compile");
+    so
+}
+
+fn lucet_run(hostcalls: Option<&Path>, args: &[&str]) -> Output {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_lucet-run"));
+    if let Some(hostcalls) = hostcalls {
+        cmd.arg("--hostcalls").arg(hostcalls);
+    }
+    cmd.args(args).output().expect("run lucet-run")
+}
+
+#[test]
+fn calls_hostcalls_from_shared_object() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = guest_so(&tmp);
+    let hostcalls = hostcalls_so(&tmp);
+    let output = lucet_run(
+        Some(&hostcalls),
+        &[guest.to_str().unwrap(), "double_then_add", "20", "-1"],
+    );
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "39\n");
+}
+
+#[test]
+fn missing_hostcalls_exit_125() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = guest_so(&tmp);
+    let output = lucet_run(
+        None,
+        &[guest.to_str().unwrap(), "double_then_add", "20", "-1"],
+    );
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("lucet_run_test_double"));
+}
+
+#[test]
+fn trap_exits_134() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = guest_so(&tmp);
+    let hostcalls = hostcalls_so(&tmp);
+    let output = lucet_run(Some(&hostcalls), &[guest.to_str().unwrap(), "trap"]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_TRAP));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("the guest trapped"));
+}
@@ -50,6 +50,10 @@ enum lucet_terminated_reason {
     lucet_terminated_reason_borrow_error,
     lucet_terminated_reason_provided,
     lucet_terminated_reason_remote,
+    lucet_terminated_reason_nondeterministic,
+    lucet_terminated_reason_replay_diverged,
+    lucet_terminated_reason_host_panic,
+    lucet_terminated_reason_hostcall_rate_exceeded,
 };
 
 enum lucet_trapcode {
@@ -154,11 +158,18 @@ struct lucet_module_addr_details {
     char sym_name[LUCET_MODULE_ADDR_DETAILS_NAME_LEN];
 };
 
+struct lucet_trap_location {
+    bool     in_module_function;
+    uint32_t function_index;
+    uint32_t code_offset;
+};
+
 struct lucet_runtime_faulted {
     bool                             fatal;
     enum lucet_trapcode              trapcode;
     uintptr_t                        rip_addr;
     struct lucet_module_addr_details rip_addr_details;
+    struct lucet_trap_location       trap_location;
 };
 
 struct lucet_terminated {
@@ -176,6 +187,7 @@ union lucet_result_val {
     struct lucet_runtime_faulted faulted;
     struct lucet_terminated      terminated;
     enum lucet_error             errored;
+    int32_t                      exited;
 };
 
 enum lucet_result_tag {
@@ -184,6 +196,7 @@ enum lucet_result_tag {
     lucet_result_tag_faulted,
     lucet_result_tag_terminated,
     lucet_result_tag_errored,
+    lucet_result_tag_exited,
 };
 
 struct lucet_result {
@@ -6,6 +6,14 @@ fn main() {
     cc::Build::new()
         .file("src/context/context_asm.S")
         .compile("context_context_asm");
+    cc::Build::new()
+        .file("src/linker/host_func_asm.S")
+        .compile("linker_host_func_asm");
+    if env::var("CARGO_CFG_TARGET_ARCH").unwrap() == "x86_64" {
+        cc::Build::new()
+            .file("src/region/mpk_asm.S")
+            .compile("region_mpk_asm");
+    }
     cc::Build::new()
         .file("src/instance/siginfo_ext.c")
         .compile("instance_siginfo_ext");
@@ -1,5 +1,6 @@
 use crate::error::Error;
 use crate::module::Module;
+use crate::region::mpk::ProtectionKey;
 use crate::region::RegionInternal;
 use crate::sysdeps::host_page_size;
 use libc::c_void;
@@ -57,6 +58,9 @@ pub struct Slot {
     /// Should not change through the lifetime of the `Alloc`.
     pub limits: Limits,
 
+    /// The memory protection key the heap is tagged with, if the region uses them.
+    pub protection_key: Option<ProtectionKey>,
+
     pub region: Weak<dyn RegionInternal>,
 }
 
@@ -301,18 +305,28 @@ impl Alloc {
             .expect("alloc missing its slot before drop")
     }
 
+    /// Grant the current thread access to the heap, if it is tagged with a memory protection key.
+    fn allow_heap_access(&self) {
+        if let Some(key) = self.slot().protection_key {
+            key.allow_access();
+        }
+    }
+
     /// Return the heap as a byte slice.
     pub unsafe fn heap(&self) -> &[u8] {
+        self.allow_heap_access();
         std::slice::from_raw_parts(self.slot().heap as *mut u8, self.heap_accessible_size)
     }
 
     /// Return the heap as a mutable byte slice.
     pub unsafe fn heap_mut(&mut self) -> &mut [u8] {
+        self.allow_heap_access();
         std::slice::from_raw_parts_mut(self.slot().heap as *mut u8, self.heap_accessible_size)
     }
 
     /// Return the heap as a slice of 32-bit words.
     pub unsafe fn heap_u32(&self) -> &[u32] {
+        self.allow_heap_access();
         assert!(self.slot().heap as usize % 4 == 0, "heap is 4-byte aligned");
         assert!(
             self.heap_accessible_size % 4 == 0,
@@ -323,6 +337,7 @@ impl Alloc {
 
     /// Return the heap as a mutable slice of 32-bit words.
     pub unsafe fn heap_u32_mut(&mut self) -> &mut [u32] {
+        self.allow_heap_access();
         assert!(self.slot().heap as usize % 4 == 0, "heap is 4-byte aligned");
         assert!(
             self.heap_accessible_size % 4 == 0,
@@ -333,6 +348,7 @@ impl Alloc {
 
     /// Return the heap as a slice of 64-bit words.
     pub unsafe fn heap_u64(&self) -> &[u64] {
+        self.allow_heap_access();
         assert!(self.slot().heap as usize % 8 == 0, "heap is 8-byte aligned");
         assert!(
             self.heap_accessible_size % 8 == 0,
@@ -343,6 +359,7 @@ impl Alloc {
 
     /// Return the heap as a mutable slice of 64-bit words.
     pub unsafe fn heap_u64_mut(&mut self) -> &mut [u64] {
+        self.allow_heap_access();
         assert!(self.slot().heap as usize % 8 == 0, "heap is 8-byte aligned");
         assert!(
             self.heap_accessible_size % 8 == 0,
@@ -380,6 +397,24 @@ impl Alloc {
         )
     }
 
+    /// Return the stack as a slice of 64-bit words.
+    ///
+    /// The layout is the same as for [`stack_u64_mut()`](#method.stack_u64_mut).
+    pub unsafe fn stack_u64(&self) -> &[u64] {
+        assert!(
+            self.slot().stack as usize % 8 == 0,
+            "stack is 8-byte aligned"
+        );
+        assert!(
+            self.slot().limits.stack_size % 8 == 0,
+            "stack size is multiple of 8-bytes"
+        );
+        std::slice::from_raw_parts(
+            self.slot().stack as *const u64,
+            self.slot().limits.stack_size / 8,
+        )
+    }
+
     /// Return the globals as a slice.
     pub unsafe fn globals(&self) -> &[GlobalValue] {
         std::slice::from_raw_parts(
@@ -492,6 +527,11 @@ impl Limits {
 
 impl Limits {
     pub fn total_memory_size(&self) -> usize {
+        self.checked_total_memory_size()
+            .expect("total_memory_size doesn't overflow")
+    }
+
+    fn checked_total_memory_size(&self) -> Option<usize> {
         // Memory is laid out as follows:
         // * the instance (up to instance_heap_offset)
         // * the heap, followed by guard pages
@@ -511,38 +551,44 @@ impl Limits {
         ]
         .iter()
         .try_fold(0usize, |acc, &x| acc.checked_add(x))
-        .expect("total_memory_size doesn't overflow")
+    }
+
+    /// Create a builder for limits, starting from the defaults.
+    pub fn builder() -> LimitsBuilder {
+        LimitsBuilder::new()
     }
 
     /// Validate that the limits are aligned to page sizes, and that the stack is not empty.
+    ///
+    /// The error names the first field found to be invalid, and the constraint it violates.
     pub fn validate(&self) -> Result<(), Error> {
-        if self.heap_memory_size % host_page_size() != 0 {
-            return Err(Error::InvalidArgument(
-                "memory size must be a multiple of host page size",
-            ));
-        }
-        if self.heap_address_space_size % host_page_size() != 0 {
-            return Err(Error::InvalidArgument(
-                "address space size must be a multiple of host page size",
-            ));
+        let invalid = |field, constraint| Err(Error::InvalidLimit { field, constraint });
+        let page_aligned = [
+            ("heap_memory_size", self.heap_memory_size),
+            ("heap_address_space_size", self.heap_address_space_size),
+            ("stack_size", self.stack_size),
+            ("globals_size", self.globals_size),
+            ("signal_stack_size", self.signal_stack_size),
+        ];
+        for &(field, size) in page_aligned.iter() {
+            if size % host_page_size() != 0 {
+                return invalid(field, "must be a multiple of the host page size");
+            }
         }
         if self.heap_memory_size > self.heap_address_space_size {
-            return Err(Error::InvalidArgument(
-                "address space size must be at least as large as memory size",
-            ));
-        }
-        if self.stack_size % host_page_size() != 0 {
-            return Err(Error::InvalidArgument(
-                "stack size must be a multiple of host page size",
-            ));
+            return invalid(
+                "heap_address_space_size",
+                "must be at least as large as `heap_memory_size`",
+            );
         }
-        if self.globals_size % host_page_size() != 0 {
-            return Err(Error::InvalidArgument(
-                "globals size must be a multiple of host page size",
-            ));
+        if self.stack_size == 0 {
+            return invalid("stack_size", "must be greater than 0");
         }
-        if self.stack_size <= 0 {
-            return Err(Error::InvalidArgument("stack size must be greater than 0"));
+        if self.checked_total_memory_size().is_none() {
+            return invalid(
+                "heap_address_space_size",
+                "must leave room in the address space for the rest of the slot",
+            );
         }
         if self.signal_stack_size < MINSIGSTKSZ {
             tracing::info!(
@@ -566,15 +612,80 @@ impl Limits {
                 MINSIGSTKSZ,
             );
         }
-        if self.signal_stack_size % host_page_size() != 0 {
-            return Err(Error::InvalidArgument(
-                "signal stack size must be a multiple of host page size",
-            ));
-        }
         Ok(())
     }
 }
 
+/// A builder for [`Limits`](struct.Limits.html), which checks the limits as they are built.
+///
+/// Fields that are not set keep their values from `Limits::default()`:
+///
+/// ```
+/// use lucet_runtime_internals::alloc::Limits;
+///
+/// let limits = Limits::builder()
+///     .with_heap_memory_size(4 * 1024 * 1024)
+///     .with_stack_size(256 * 1024)
+///     .build()
+///     .expect("limits are valid");
+/// assert_eq!(limits.heap_memory_size, 4 * 1024 * 1024);
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct LimitsBuilder {
+    limits: Limits,
+}
+
+impl Default for LimitsBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LimitsBuilder {
+    /// Create a builder starting from `Limits::default()`.
+    pub fn new() -> Self {
+        LimitsBuilder {
+            limits: Limits::default(),
+        }
+    }
+
+    /// Set the maximum size of the heap that can be backed by real memory.
+    pub fn with_heap_memory_size(mut self, heap_memory_size: usize) -> Self {
+        self.limits.heap_memory_size = heap_memory_size;
+        self
+    }
+
+    /// Set the size of the virtual address space reserved for the heap.
+    pub fn with_heap_address_space_size(mut self, heap_address_space_size: usize) -> Self {
+        self.limits.heap_address_space_size = heap_address_space_size;
+        self
+    }
+
+    /// Set the size of the guest stack.
+    pub fn with_stack_size(mut self, stack_size: usize) -> Self {
+        self.limits.stack_size = stack_size;
+        self
+    }
+
+    /// Set the size of the globals region.
+    pub fn with_globals_size(mut self, globals_size: usize) -> Self {
+        self.limits.globals_size = globals_size;
+        self
+    }
+
+    /// Set the size of the signal stack.
+    pub fn with_signal_stack_size(mut self, signal_stack_size: usize) -> Self {
+        self.limits.signal_stack_size = signal_stack_size;
+        self
+    }
+
+    /// Check the limits, returning `Error::InvalidLimit` for the first field that is invalid.
+    pub fn build(self) -> Result<Limits, Error> {
+        self.limits.validate()?;
+        Ok(self.limits)
+    }
+}
+
 pub fn validate_sigstack_size(signal_stack_size: usize) -> Result<(), Error> {
     if signal_stack_size < MINSIGSTKSZ {
         return Err(Error::InvalidArgument(
@@ -13,6 +13,7 @@ macro_rules! alloc_tests {
         use $crate::module::{
             FunctionPointer, GlobalValue, HeapSpec, MockExportBuilder, MockModuleBuilder, Module,
         };
+        use $crate::quota::{Quota, QuotaUsage};
         use $crate::region::{Region, RegionCreate};
         use $crate::sysdeps::host_page_size;
         use $crate::val::Val;
@@ -829,6 +830,103 @@ macro_rules! alloc_tests {
             assert_eq!(region.used_slots(), 0);
         }
 
+        #[test]
+        fn region_stats_work() {
+            let module_a = MockModuleBuilder::new()
+                .with_heap_spec(ONE_PAGE_HEAP)
+                .build();
+            let module_b = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .build();
+            let region = <TestRegion as RegionCreate>::create(3, &LIMITS).expect("region created");
+
+            let stats = region.stats();
+            assert_eq!(stats.capacity, 3);
+            assert_eq!(stats.free_slots, 3);
+            assert_eq!(stats.used_slots, 0);
+            assert_eq!(stats.reserved_bytes, 3 * LIMITS.total_memory_size());
+            let idle_bytes = stats.committed_bytes;
+            assert_eq!(stats.instances_of(&module_a), 0);
+
+            let per_instance = LIMITS.stack_size + LIMITS.globals_size + LIMITS.signal_stack_size;
+            let inst_a1 = region
+                .new_instance(module_a.clone())
+                .expect("new_instance succeeds");
+            let _inst_a2 = region
+                .new_instance(module_a.clone())
+                .expect("new_instance succeeds");
+            let mut inst_b = region
+                .new_instance(module_b.clone())
+                .expect("new_instance succeeds");
+
+            let stats = region.stats();
+            assert_eq!(stats.free_slots, 0);
+            assert_eq!(stats.used_slots, 3);
+            assert_eq!(stats.instances_of(&module_a), 2);
+            assert_eq!(stats.instances_of(&module_b), 1);
+            assert_eq!(stats.module_instances().count(), 2);
+            assert_eq!(
+                stats.committed_bytes,
+                idle_bytes + 3 * (per_instance + ONEPAGE_INITIAL_SIZE as usize)
+            );
+
+            inst_b.grow_memory(1).expect("grow succeeds");
+            assert_eq!(
+                region.stats().committed_bytes,
+                idle_bytes + 3 * (per_instance + ONEPAGE_INITIAL_SIZE as usize) + 64 * 1024
+            );
+
+            drop(inst_a1);
+            drop(inst_b);
+            let stats = region.stats();
+            assert_eq!(stats.used_slots, 1);
+            assert_eq!(stats.instances_of(&module_a), 1);
+            assert_eq!(stats.instances_of(&module_b), 0);
+            assert_eq!(
+                stats.committed_bytes,
+                idle_bytes + per_instance + ONEPAGE_INITIAL_SIZE as usize
+            );
+        }
+
+        #[test]
+        fn occupancy_callback_works() {
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(ONE_PAGE_HEAP)
+                .build();
+            let region = <TestRegion as RegionCreate>::create(3, &LIMITS).expect("region created");
+
+            let events = Arc::new(Mutex::new(vec![]));
+            let events_cb = events.clone();
+            region.set_occupancy_callback(
+                vec![2, 3],
+                Box::new(move |ev| events_cb.lock().unwrap().push((ev.threshold, ev.rising))),
+            );
+
+            let inst1 = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            assert!(events.lock().unwrap().is_empty());
+            let inst2 = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            let inst3 = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            assert_eq!(*events.lock().unwrap(), vec![(2, true), (3, true)]);
+
+            drop(inst3);
+            drop(inst2);
+            assert_eq!(
+                *events.lock().unwrap(),
+                vec![(2, true), (3, true), (3, false), (2, false)]
+            );
+
+            region.clear_occupancy_callback();
+            let _inst2 = region.new_instance(module).expect("new_instance succeeds");
+            assert_eq!(events.lock().unwrap().len(), 4);
+            drop(inst1);
+        }
+
         /// This test exercises the AllocStrategy::Random. In this scenario,
         /// the Region has a single slot which is "randomly" allocated and then dropped.
         #[test]
@@ -1095,9 +1193,10 @@ macro_rules! alloc_tests {
             };
             let res = <TestRegion as RegionCreate>::create(1, &limits);
             match res {
-                Err(Error::InvalidArgument(
-                    "signal stack size must be a multiple of host page size",
-                )) => (),
+                Err(Error::InvalidLimit {
+                    field: "signal_stack_size",
+                    ..
+                }) => (),
                 Err(e) => panic!("unexpected error: {}", e),
                 Ok(_) => panic!("unexpected success"),
             }
@@ -1186,15 +1285,532 @@ macro_rules! alloc_tests {
 
             assert!(res.is_err(), "new_instance fails");
         }
+
+        /// This test shows that a quota limits the number of instances attached to it, and that
+        /// dropping an instance makes room for another.
+        #[test]
+        fn quota_limits_instances() {
+            let region = <TestRegion as RegionCreate>::create(3, &LIMITS).expect("region created");
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(ONE_PAGE_HEAP)
+                .build();
+            let quota = Quota::new(LIMITS_HEAP_MEM_SIZE, 2);
+            let build = || {
+                region
+                    .new_instance_builder(module.clone())
+                    .with_quota(&quota)
+                    .build()
+            };
+
+            let first = build().expect("instance fits in the quota");
+            let _second = build().expect("instance fits in the quota");
+            match build() {
+                Err(Error::QuotaExceeded(_)) => (),
+                res => panic!("unexpected result: {:?}", res.map(|_| ())),
+            }
+            // the slot of the rejected instance was returned to the region
+            assert_eq!(region.free_slots(), 1);
+            assert_eq!(
+                quota.usage(),
+                QuotaUsage {
+                    heap_size: 2 * ONEPAGE_INITIAL_SIZE as usize,
+                    instances: 2,
+                }
+            );
+
+            drop(first);
+            assert_eq!(quota.usage().instances, 1);
+            build().expect("instance fits in the quota again");
+        }
+
+        /// This test shows that a quota limits the total heap size of the instances attached to it,
+        /// and that resetting an instance returns the memory it grew into.
+        #[test]
+        fn quota_limits_heap_growth() {
+            let region = <TestRegion as RegionCreate>::create(2, &LIMITS).expect("region created");
+            let module = MockModuleBuilder::new()
+                .with_heap_spec(THREE_PAGE_MAX_HEAP)
+                .build();
+            // room for both initial heaps, and one more page
+            let quota = Quota::new(3 * THREEPAGE_INITIAL_SIZE as usize, 2);
+            let mut a = region
+                .new_instance_builder(module.clone())
+                .with_quota(&quota)
+                .build()
+                .expect("instance fits in the quota");
+            let mut b = region
+                .new_instance_builder(module)
+                .with_quota(&quota)
+                .build()
+                .expect("instance fits in the quota");
+
+            a.grow_memory(1).expect("growth fits in the quota");
+            match b.grow_memory(1) {
+                Err(Error::QuotaExceeded(_)) => (),
+                res => panic!("unexpected result: {:?}", res),
+            }
+            assert_eq!(b.alloc().heap_len(), THREEPAGE_INITIAL_SIZE as usize);
+            assert_eq!(quota.usage().heap_size, 3 * THREEPAGE_INITIAL_SIZE as usize);
+
+            a.reset().expect("instance resets");
+            assert_eq!(quota.usage().heap_size, 2 * THREEPAGE_INITIAL_SIZE as usize);
+            b.grow_memory(1)
+                .expect("growth fits in the quota after the reset");
+        }
     };
 }
 
+#[cfg(test)]
+mod limits {
+    use crate::alloc::Limits;
+    use crate::error::Error;
+
+    #[test]
+    fn limits_builder_names_invalid_field() {
+        let limits = Limits::builder()
+            .with_heap_memory_size(2 * 1024 * 1024)
+            .with_stack_size(256 * 1024)
+            .build()
+            .expect("limits are valid");
+        assert_eq!(limits.heap_memory_size, 2 * 1024 * 1024);
+        assert_eq!(limits.stack_size, 256 * 1024);
+        assert_eq!(limits.globals_size, Limits::default().globals_size);
+
+        let res = Limits::builder().with_globals_size(4097).build();
+        match res {
+            Err(Error::InvalidLimit {
+                field: "globals_size",
+                constraint: "must be a multiple of the host page size",
+            }) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unexpected success"),
+        }
+
+        let res = Limits::builder()
+            .with_heap_memory_size(2 * 1024 * 1024)
+            .with_heap_address_space_size(1024 * 1024)
+            .build();
+        match res {
+            Err(Error::InvalidLimit {
+                field: "heap_address_space_size",
+                ..
+            }) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unexpected success"),
+        }
+
+        let res = Limits::builder().with_stack_size(0).build();
+        match res {
+            Err(Error::InvalidLimit {
+                field: "stack_size",
+                ..
+            }) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("unexpected success"),
+        }
+    }
+}
+
 #[cfg(test)]
 mod mmap {
     alloc_tests!(crate::region::mmap::MmapRegion);
+
+    use crate::instance::HostPanicPolicy;
+    use crate::region::mmap::SlotAffinity;
+    use crate::region::{mpk, protection_keys_supported, DecommitPolicy, RegionOptions};
+
+    fn dirty_heap(region: &Arc<TestRegion>, module: &Arc<dyn Module>) -> usize {
+        let mut inst = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        let heap = unsafe { inst.alloc_mut().heap_mut() };
+        assert_eq!(heap[0], 0);
+        heap[0] = 0xFF;
+        let stack = unsafe { inst.alloc_mut().stack_mut() };
+        assert_eq!(stack[0], 0);
+        stack[0] = 0xFF;
+        inst.alloc().slot().start as usize
+    }
+
+    /// This test shows that slots released in the background are reused, with their memory reset.
+    #[test]
+    fn background_decommit_works() {
+        let options = RegionOptions::new().with_decommit_policy(DecommitPolicy::Background);
+        let region = TestRegion::create_with_options(2, &LIMITS, &options).expect("region created");
+        let module = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+
+        // more instances than the region holds, so pending slots must be reused
+        for _ in 0..5 {
+            dirty_heap(&region, &module);
+            assert_eq!(region.used_slots(), 0);
+            assert_eq!(region.free_slots(), 2);
+        }
+
+        let _inst1 = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        let _inst2 = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        assert_eq!(region.used_slots(), 2);
+        assert_eq!(region.free_slots(), 0);
+        match region.new_instance(module.clone()) {
+            Err(Error::RegionFull(2)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("region should be full"),
+        }
+    }
+
+    /// This test shows that under `KeepWarm`, a module gets back the slot it last used, with its
+    /// memory reset.
+    #[test]
+    fn keep_warm_reuses_module_slot() {
+        let options = RegionOptions::new().with_decommit_policy(DecommitPolicy::KeepWarm);
+        let region = TestRegion::create_with_options(4, &LIMITS, &options).expect("region created");
+        let module_a = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+        let module_b = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+
+        let (slot_a, slot_b) = {
+            let inst_a = region
+                .new_instance(module_a.clone())
+                .expect("new_instance succeeds");
+            let inst_b = region
+                .new_instance(module_b.clone())
+                .expect("new_instance succeeds");
+            (
+                inst_a.alloc().slot().start as usize,
+                inst_b.alloc().slot().start as usize,
+            )
+        };
+
+        for _ in 0..3 {
+            assert_eq!(dirty_heap(&region, &module_b), slot_b);
+            assert_eq!(dirty_heap(&region, &module_a), slot_a);
+        }
+        assert_eq!(region.free_slots(), 4);
+    }
+
+    /// This test shows that a module with a preferred slot affinity gets back the slot it last
+    /// used, and that other modules avoid that slot while others are free.
+    #[test]
+    fn preferred_affinity_reuses_module_slot() {
+        let region = TestRegion::create(4, &LIMITS).expect("region created");
+        let module_a = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+        let module_b = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+        region
+            .set_slot_affinity(&module_a, SlotAffinity::Preferred)
+            .expect("affinity is valid");
+        assert_eq!(
+            region.slot_affinity(&module_a),
+            Some(SlotAffinity::Preferred)
+        );
+
+        let slot_a = dirty_heap(&region, &module_a);
+        for _ in 0..3 {
+            assert_ne!(dirty_heap(&region, &module_b), slot_a);
+            assert_eq!(dirty_heap(&region, &module_a), slot_a);
+        }
+
+        region.clear_slot_affinity(&module_a);
+        assert_eq!(region.slot_affinity(&module_a), None);
+    }
+
+    /// This test shows that instances of a module pinned to a range of slots are only placed in
+    /// that range.
+    #[test]
+    fn pinned_affinity_restricts_slots() {
+        let region = TestRegion::create(4, &LIMITS).expect("region created");
+        let module = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+        match region.set_slot_affinity(&module, SlotAffinity::Pinned(2..5)) {
+            Err(Error::InvalidArgument(_)) => (),
+            res => panic!("unexpected result: {:?}", res),
+        }
+        region
+            .set_slot_affinity(&module, SlotAffinity::Pinned(1..3))
+            .expect("affinity is valid");
+
+        let _a = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        let _b = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        match region.new_instance(module.clone()) {
+            Err(Error::RegionFull(4)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("pinned slots should be full"),
+        }
+        assert_eq!(region.free_slots(), 2);
+
+        // other modules can still use the rest of the region
+        region
+            .new_instance(MockModuleBuilder::new().build())
+            .expect("new_instance succeeds");
+    }
+
+    /// This test shows that the eviction callback is called when another module takes the slot
+    /// last used by a module with an affinity.
+    #[test]
+    fn eviction_callback_reports_evicted_slot() {
+        let region = TestRegion::create(1, &LIMITS).expect("region created");
+        let module_a = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+        let module_b = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+        region
+            .set_slot_affinity(&module_a, SlotAffinity::Preferred)
+            .expect("affinity is valid");
+        let evictions = Arc::new(Mutex::new(vec![]));
+        let evictions_cb = evictions.clone();
+        region.set_eviction_callback(Box::new(move |eviction| {
+            evictions_cb.lock().unwrap().push(eviction.slot);
+        }));
+
+        dirty_heap(&region, &module_a);
+        dirty_heap(&region, &module_a);
+        assert!(evictions.lock().unwrap().is_empty());
+        dirty_heap(&region, &module_b);
+        assert_eq!(*evictions.lock().unwrap(), vec![0]);
+        // the slot was last used by a module without an affinity
+        dirty_heap(&region, &module_a);
+        assert_eq!(evictions.lock().unwrap().len(), 1);
+    }
+
+    /// This test shows that instances in a region with `mlock` enabled either lock their memory,
+    /// or fail with `Error::MemoryLockFailed` if the process may not lock that much.
+    #[test]
+    fn mlock_instance_memory() {
+        let options = RegionOptions::new().with_mlock(true);
+        let region = TestRegion::create_with_options(1, &LIMITS, &options).expect("region created");
+        let module = MockModuleBuilder::new()
+            .with_heap_spec(THREE_PAGE_MAX_HEAP)
+            .build();
+
+        for _ in 0..2 {
+            let mut inst = match region.new_instance(module.clone()) {
+                Ok(inst) => inst,
+                Err(Error::MemoryLockFailed(_)) => {
+                    // the slot must have been returned to the region
+                    assert_eq!(region.free_slots(), 1);
+                    continue;
+                }
+                Err(e) => panic!("unexpected error: {}", e),
+            };
+            let heap = unsafe { inst.alloc_mut().heap_mut() };
+            assert_eq!(heap[0], 0);
+            heap[0] = 0xFF;
+
+            match inst.grow_memory(1) {
+                Ok(_) => {
+                    let heap = unsafe { inst.alloc_mut().heap_mut() };
+                    assert_eq!(heap.len(), 2 * THREEPAGE_INITIAL_SIZE as usize);
+                    assert_eq!(heap[heap.len() - 1], 0);
+                }
+                Err(Error::MemoryLockFailed(_)) => {
+                    // a failed expansion leaves the heap as it was
+                    let heap = unsafe { inst.alloc_mut().heap_mut() };
+                    assert_eq!(heap.len(), THREEPAGE_INITIAL_SIZE as usize);
+                }
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+        }
+        assert_eq!(region.free_slots(), 1);
+    }
+
+    /// This test shows that a running instance in a region with protection keys may only access
+    /// its own heap, and that the host's access rights are restored when it returns.
+    #[test]
+    fn protection_keys_isolate_heaps() {
+        let options = RegionOptions::new().with_protection_keys(true);
+        if !protection_keys_supported() {
+            match TestRegion::create_with_options(2, &LIMITS, &options) {
+                Err(Error::Unsupported(_)) => return,
+                _ => panic!("region without protection key support must not be created"),
+            }
+        }
+
+        extern "C" fn read_pkru(_vmctx: *const lucet_vmctx) -> u64 {
+            mpk::read_pkru() as u64
+        }
+        let module = MockModuleBuilder::new()
+            .with_heap_spec(THREE_PAGE_MAX_HEAP)
+            .with_export_func(MockExportBuilder::new(
+                "read_pkru",
+                FunctionPointer::from_usize(read_pkru as usize),
+            ))
+            .build();
+        let region = TestRegion::create_with_options(2, &LIMITS, &options).expect("region created");
+        let mut inst_a = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        let mut inst_b = region.new_instance(module).expect("new_instance succeeds");
+        let key_a = inst_a
+            .alloc()
+            .slot()
+            .protection_key
+            .expect("slot has a key");
+        let key_b = inst_b
+            .alloc()
+            .slot()
+            .protection_key
+            .expect("slot has a key");
+        assert_ne!(key_a, key_b);
+
+        // the host may access both heaps
+        unsafe {
+            inst_a.alloc_mut().heap_mut()[0] = 1;
+            inst_b.alloc_mut().heap_mut()[0] = 2;
+        }
+
+        let host_pkru = mpk::read_pkru();
+        let guest_pkru = u64::from(inst_a.run("read_pkru", &[]).unwrap().unwrap_returned()) as u32;
+        assert_eq!(guest_pkru & key_a.bits(), 0);
+        assert_eq!(guest_pkru & key_b.bits(), key_b.bits());
+        assert_eq!(mpk::read_pkru(), host_pkru);
+
+        assert_eq!(unsafe { inst_b.alloc().heap()[0] }, 2);
+    }
+
+    /// This test shows that with heap poisoning, a write into the heap of a dropped instance is
+    /// detected when its slot is reused.
+    #[test]
+    fn heap_poisoning_detects_write_after_drop() {
+        let options = RegionOptions::new().with_heap_poisoning(true);
+        let region = TestRegion::create_with_options(1, &LIMITS, &options).expect("region created");
+        let module = MockModuleBuilder::new()
+            .with_heap_spec(ONE_PAGE_HEAP)
+            .build();
+
+        // reusing a slot whose poison is intact succeeds
+        dirty_heap(&region, &module);
+        let stale_heap = {
+            let mut inst = region
+                .new_instance(module.clone())
+                .expect("new_instance succeeds");
+            assert_eq!(unsafe { inst.alloc().heap()[0] }, 0);
+            unsafe { inst.alloc_mut().heap_mut().as_mut_ptr() }
+        };
+
+        unsafe { *stale_heap.add(100) = 1 };
+        match region.new_instance(module.clone()) {
+            Err(Error::HeapPoisonCorrupted(_)) => (),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("write after drop should be detected"),
+        }
+
+        // the slot is usable again once the corruption has been reported
+        let inst = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        assert_eq!(unsafe { inst.alloc().heap()[100] }, 0);
+    }
+
+    /// This test shows that with heap poisoning, the pages a heap grew into are zeroed when it
+    /// grows into them again after a reset.
+    #[test]
+    fn heap_poisoning_survives_reset() {
+        let options = RegionOptions::new().with_heap_poisoning(true);
+        let region = TestRegion::create_with_options(1, &LIMITS, &options).expect("region created");
+        let module = MockModuleBuilder::new()
+            .with_heap_spec(THREE_PAGE_MAX_HEAP)
+            .build();
+        let mut inst = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        let initial_len = inst.alloc().heap_len();
+
+        inst.alloc_mut()
+            .expand_heap(64 * 1024, module.as_ref())
+            .expect("expand_heap succeeds");
+        unsafe { inst.alloc_mut().heap_mut()[initial_len] = 0xFF };
+
+        inst.reset().expect("reset succeeds");
+        assert_eq!(inst.alloc().heap_len(), initial_len);
+
+        inst.alloc_mut()
+            .expand_heap(64 * 1024, module.as_ref())
+            .expect("expand_heap succeeds");
+        assert_eq!(unsafe { inst.alloc().heap()[initial_len] }, 0);
+    }
+
+    /// This test shows that instances take the host panic policy of their region, unless their
+    /// builder overrides it.
+    #[test]
+    fn region_host_panic_policy() {
+        let options = RegionOptions::new().with_host_panic_policy(HostPanicPolicy::Abort);
+        let region = TestRegion::create_with_options(2, &LIMITS, &options).expect("region created");
+        let module = MockModuleBuilder::new().build();
+
+        let inst = region
+            .new_instance(module.clone())
+            .expect("new_instance succeeds");
+        assert_eq!(inst.host_panic_policy(), HostPanicPolicy::Abort);
+
+        let inst = region
+            .new_instance_builder(module)
+            .with_host_panic_policy(HostPanicPolicy::TerminateInstance)
+            .build()
+            .expect("instance can be built");
+        assert_eq!(inst.host_panic_policy(), HostPanicPolicy::TerminateInstance);
+    }
 }
 
 #[cfg(all(test, target_os = "linux", feature = "uffd"))]
 mod uffd {
     alloc_tests!(crate::region::uffd::UffdRegion);
+
+    use crate::instance::HostPanicPolicy;
+    use crate::region::uffd::WasmPageSizedUffdStrategy;
+    use crate::region::{DecommitPolicy, RegionOptions};
+
+    /// This test shows that a `UffdRegion` cannot be created with the region options it does not
+    /// support, rather than ignoring them.
+    #[test]
+    fn unsupported_options_rejected() {
+        for options in &[
+            RegionOptions::new().with_decommit_policy(DecommitPolicy::Background),
+            RegionOptions::new().with_mlock(true),
+            RegionOptions::new().with_protection_keys(true),
+            RegionOptions::new().with_heap_poisoning(true),
+        ] {
+            match TestRegion::create_with_options(1, &LIMITS, WasmPageSizedUffdStrategy, options) {
+                Err(Error::Unsupported(_)) => (),
+                _ => panic!("`UffdRegion` must reject {:?}", options),
+            }
+        }
+        TestRegion::create_with_options(
+            1,
+            &LIMITS,
+            WasmPageSizedUffdStrategy,
+            &RegionOptions::default(),
+        )
+        .expect("the default options are supported");
+    }
+
+    /// This test shows that instances take the host panic policy of their region.
+    #[test]
+    fn region_host_panic_policy() {
+        let options = RegionOptions::new().with_host_panic_policy(HostPanicPolicy::Abort);
+        let region =
+            TestRegion::create_with_options(1, &LIMITS, WasmPageSizedUffdStrategy, &options)
+                .expect("region created");
+        let inst = region
+            .new_instance(MockModuleBuilder::new().build())
+            .expect("new_instance succeeds");
+        assert_eq!(inst.host_panic_policy(), HostPanicPolicy::Abort);
+    }
 }
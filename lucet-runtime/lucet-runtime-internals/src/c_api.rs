@@ -98,9 +98,22 @@ impl From<&Error> for lucet_error {
             Error::RegionFull(_) => lucet_error::RegionFull,
             Error::ModuleError(_) => lucet_error::Module,
             Error::LimitsExceeded(_) => lucet_error::LimitsExceeded,
+            Error::QuotaExceeded(_) => lucet_error::LimitsExceeded,
+            Error::InvalidLimit { .. } => lucet_error::InvalidArgument,
+            Error::InvalidConfig(_) => lucet_error::InvalidArgument,
             Error::NoLinearMemory(_) => lucet_error::NoLinearMemory,
             Error::SymbolNotFound(_) => lucet_error::SymbolNotFound,
+            Error::ModuleNotFound(_) => lucet_error::InvalidArgument,
             Error::FuncNotFound(_, _) => lucet_error::FuncNotFound,
+            Error::MemoryLockFailed(_) => lucet_error::Internal,
+            Error::SeccompFailed(_) => lucet_error::Internal,
+            Error::WxViolation(_) => lucet_error::Module,
+            Error::HeapPoisonCorrupted(_) => lucet_error::Internal,
+            Error::ImmutableGlobal(_) => lucet_error::InvalidArgument,
+            Error::LinkError(_) => lucet_error::Module,
+            Error::GuestMemoryError(_) => lucet_error::InvalidArgument,
+            Error::CtxNotFound(_) => lucet_error::InvalidArgument,
+            Error::CtxBorrowed(_) => lucet_error::InvalidArgument,
             Error::RuntimeFault(_) => lucet_error::RuntimeFault,
             Error::RuntimeTerminated(_) => lucet_error::RuntimeTerminated,
             Error::DlError(_) => lucet_error::Dl,
@@ -245,7 +258,7 @@ pub mod lucet_result {
     use crate::c_api::{lucet_val, CTerminationDetails, CYieldedVal};
     use crate::error::Error;
     use crate::instance::{RunResult, TerminationDetails};
-    use crate::module::{AddrDetails, TrapCode};
+    use crate::module::{AddrDetails, TrapCode, TrapLocation};
     use libc::{c_uchar, c_void};
     use num_derive::FromPrimitive;
     use std::ffi::CString;
@@ -271,6 +284,10 @@ pub mod lucet_result {
                         },
                     },
                 },
+                Ok(RunResult::Exited(status)) => lucet_result {
+                    tag: lucet_result_tag::Exited,
+                    val: lucet_result_val { exited: status },
+                },
                 // TODO: test this path; currently our C API tests don't include any faulting tests
                 Err(Error::RuntimeFault(details)) => lucet_result {
                     tag: lucet_result_tag::Faulted,
@@ -280,6 +297,7 @@ pub mod lucet_result {
                             trapcode: details.trapcode.into(),
                             rip_addr: details.rip_addr,
                             rip_addr_details: details.rip_addr_details.into(),
+                            trap_location: details.trap_location.into(),
                         },
                     },
                 },
@@ -315,6 +333,22 @@ pub mod lucet_result {
                                 reason: lucet_terminated_reason::Remote,
                                 provided: std::ptr::null_mut(),
                             },
+                            TerminationDetails::Nondeterministic(_) => lucet_terminated {
+                                reason: lucet_terminated_reason::Nondeterministic,
+                                provided: ptr::null_mut(),
+                            },
+                            TerminationDetails::ReplayDiverged(_) => lucet_terminated {
+                                reason: lucet_terminated_reason::ReplayDiverged,
+                                provided: ptr::null_mut(),
+                            },
+                            TerminationDetails::HostPanic(_) => lucet_terminated {
+                                reason: lucet_terminated_reason::HostPanic,
+                                provided: ptr::null_mut(),
+                            },
+                            TerminationDetails::HostcallRateExceeded(_) => lucet_terminated {
+                                reason: lucet_terminated_reason::HostcallRateExceeded,
+                                provided: ptr::null_mut(),
+                            },
                         },
                     },
                 },
@@ -341,6 +375,7 @@ pub mod lucet_result {
         Faulted,
         Terminated,
         Errored,
+        Exited,
     }
 
     #[repr(C)]
@@ -351,6 +386,7 @@ pub mod lucet_result {
         pub fault: lucet_runtime_faulted,
         pub terminated: lucet_terminated,
         pub errored: lucet_error,
+        pub exited: i32,
     }
 
     #[repr(C)]
@@ -369,6 +405,10 @@ pub mod lucet_result {
         BorrowError,
         Provided,
         Remote,
+        Nondeterministic,
+        ReplayDiverged,
+        HostPanic,
+        HostcallRateExceeded,
     }
 
     #[repr(C)]
@@ -384,6 +424,27 @@ pub mod lucet_result {
         pub trapcode: lucet_trapcode,
         pub rip_addr: libc::uintptr_t,
         pub rip_addr_details: lucet_module_addr_details,
+        pub trap_location: lucet_trap_location,
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Default)]
+    pub struct lucet_trap_location {
+        pub in_module_function: bool,
+        pub function_index: u32,
+        pub code_offset: u32,
+    }
+
+    impl From<Option<TrapLocation>> for lucet_trap_location {
+        fn from(location: Option<TrapLocation>) -> Self {
+            location
+                .map(|location| lucet_trap_location {
+                    in_module_function: true,
+                    function_index: location.function_index.as_u32(),
+                    code_offset: location.code_offset,
+                })
+                .unwrap_or_default()
+        }
     }
 
     #[repr(C)]
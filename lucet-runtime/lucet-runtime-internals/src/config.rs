@@ -0,0 +1,334 @@
+//! Process-wide defaults for the limits and regions of instances.
+//!
+//! Embedders that create regions in many places can set the defaults once, with
+//! [`set_defaults()`](fn.set_defaults.html), and then create regions with
+//! [`create_default_region()`](fn.create_default_region.html), rather than threading `Limits`
+//! and `RegionOptions` through each of them:
+//!
+//! ```no_run
+//! use lucet_runtime_internals::alloc::Limits;
+//! use lucet_runtime_internals::config::{self, Defaults};
+//!
+//! let limits = Limits::builder()
+//!     .with_heap_memory_size(64 * 1024 * 1024)
+//!     .build()
+//!     .expect("limits are valid");
+//! config::set_defaults(
+//!     Defaults::new()
+//!         .with_limits(limits)
+//!         .with_env_overrides()
+//!         .expect("environment is valid"),
+//! )
+//! .expect("defaults are valid");
+//!
+//! let region = config::create_default_region(16).expect("region can be created");
+//! ```
+//!
+//! [`Defaults::with_env_overrides()`](struct.Defaults.html#method.with_env_overrides) lets
+//! operators tune the defaults of a deployment without rebuilding the embedder. The environment
+//! is only read when that method is called.
+//!
+//! Changing the defaults does not affect regions that were already created.
+
+use crate::alloc::Limits;
+use crate::error::Error;
+use crate::region::mmap::MmapRegion;
+#[cfg(all(target_os = "linux", feature = "uffd"))]
+use crate::region::uffd::{UffdRegion, WasmPageSizedUffdStrategy};
+use crate::region::{DecommitPolicy, Region, RegionOptions};
+use lazy_static::lazy_static;
+use std::sync::{Arc, RwLock};
+
+/// The type of region created by [`create_default_region()`](fn.create_default_region.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionType {
+    /// An `MmapRegion`. This is the default.
+    Mmap,
+    /// A `UffdRegion` with its default strategy. `UffdRegion` does not support any
+    /// `RegionOptions`, so creating a region fails with `Error::Unsupported` if they are not the
+    /// defaults.
+    #[cfg(all(target_os = "linux", feature = "uffd"))]
+    Uffd,
+}
+
+impl Default for RegionType {
+    fn default() -> Self {
+        RegionType::Mmap
+    }
+}
+
+/// Defaults for the regions created by the process.
+#[derive(Clone, Debug)]
+pub struct Defaults {
+    limits: Limits,
+    heap_guard_size: Option<usize>,
+    region_options: RegionOptions,
+    region_type: RegionType,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Defaults {
+            limits: Limits::default(),
+            heap_guard_size: None,
+            region_options: RegionOptions::default(),
+            region_type: RegionType::default(),
+        }
+    }
+}
+
+impl Defaults {
+    /// Create the built-in defaults: `Limits::default()`, `RegionOptions::default()`, and
+    /// `RegionType::Mmap`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the limits of the instances in new regions.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the size of the guard region following the largest heap an instance can grow to.
+    ///
+    /// The limits then have a `heap_address_space_size` of their `heap_memory_size` plus
+    /// `heap_guard_size`, whether the limits are set before or after the guard size.
+    pub fn with_heap_guard_size(mut self, heap_guard_size: usize) -> Self {
+        self.heap_guard_size = Some(heap_guard_size);
+        self
+    }
+
+    /// Set the options of new regions.
+    pub fn with_region_options(mut self, region_options: RegionOptions) -> Self {
+        self.region_options = region_options;
+        self
+    }
+
+    /// Set the type of new regions.
+    pub fn with_region_type(mut self, region_type: RegionType) -> Self {
+        self.region_type = region_type;
+        self
+    }
+
+    /// Override the defaults with any of the following environment variables that are set:
+    ///
+    /// - `LUCET_HEAP_MEMORY_SIZE`, `LUCET_HEAP_ADDRESS_SPACE_SIZE`, `LUCET_STACK_SIZE`,
+    ///   `LUCET_GLOBALS_SIZE`, and `LUCET_SIGNAL_STACK_SIZE` set the corresponding field of the
+    ///   limits, in bytes, with an optional `K`, `M`, or `G` suffix.
+    /// - `LUCET_HEAP_GUARD_SIZE` sets the heap guard size, as
+    ///   [`with_heap_guard_size()`](#method.with_heap_guard_size) does.
+    /// - `LUCET_REGION_TYPE` sets the region type: `mmap`, or `uffd` if supported.
+    /// - `LUCET_DECOMMIT_POLICY` sets the decommit policy of the region options: `immediate`,
+    ///   `background`, or `keep-warm`.
+    ///
+    /// Returns `Error::InvalidConfig` if a variable cannot be parsed, or `Error::InvalidLimit` if
+    /// the resulting limits are invalid.
+    pub fn with_env_overrides(self) -> Result<Self, Error> {
+        self.with_overrides_from(|name| std::env::var(name).ok())
+    }
+
+    fn with_overrides_from(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Self, Error> {
+        let sizes: [(&str, &mut usize); 5] = [
+            ("LUCET_HEAP_MEMORY_SIZE", &mut self.limits.heap_memory_size),
+            (
+                "LUCET_HEAP_ADDRESS_SPACE_SIZE",
+                &mut self.limits.heap_address_space_size,
+            ),
+            ("LUCET_STACK_SIZE", &mut self.limits.stack_size),
+            ("LUCET_GLOBALS_SIZE", &mut self.limits.globals_size),
+            (
+                "LUCET_SIGNAL_STACK_SIZE",
+                &mut self.limits.signal_stack_size,
+            ),
+        ];
+        for (name, field) in sizes.iter_mut() {
+            if let Some(value) = var(*name) {
+                **field = parse_size(*name, &value)?;
+            }
+        }
+        if let Some(value) = var("LUCET_HEAP_GUARD_SIZE") {
+            let heap_guard_size = parse_size("LUCET_HEAP_GUARD_SIZE", &value)?;
+            self.heap_guard_size = Some(heap_guard_size);
+        }
+        if let Some(value) = var("LUCET_REGION_TYPE") {
+            self.region_type = match value.as_str() {
+                "mmap" => RegionType::Mmap,
+                #[cfg(all(target_os = "linux", feature = "uffd"))]
+                "uffd" => RegionType::Uffd,
+                _ => return Err(invalid("LUCET_REGION_TYPE", &value)),
+            };
+        }
+        if let Some(value) = var("LUCET_DECOMMIT_POLICY") {
+            let decommit_policy = match value.as_str() {
+                "immediate" => DecommitPolicy::Immediate,
+                "background" => DecommitPolicy::Background,
+                "keep-warm" => DecommitPolicy::KeepWarm,
+                _ => return Err(invalid("LUCET_DECOMMIT_POLICY", &value)),
+            };
+            self.region_options = self.region_options.with_decommit_policy(decommit_policy);
+        }
+        self.limits().validate()?;
+        Ok(self)
+    }
+
+    /// The limits of the instances in new regions, including the heap guard size.
+    pub fn limits(&self) -> Limits {
+        let mut limits = self.limits.clone();
+        if let Some(heap_guard_size) = self.heap_guard_size {
+            limits.heap_address_space_size =
+                limits.heap_memory_size.saturating_add(heap_guard_size);
+        }
+        limits
+    }
+
+    /// The options of new regions.
+    pub fn region_options(&self) -> &RegionOptions {
+        &self.region_options
+    }
+
+    /// The type of new regions.
+    pub fn region_type(&self) -> RegionType {
+        self.region_type
+    }
+
+    /// Create a region with these defaults, that can support the given number of instances.
+    pub fn create_region(&self, instance_capacity: usize) -> Result<Arc<dyn Region>, Error> {
+        let region: Arc<dyn Region> = match self.region_type {
+            RegionType::Mmap => MmapRegion::create_with_options(
+                instance_capacity,
+                &self.limits(),
+                &self.region_options,
+            )?,
+            #[cfg(all(target_os = "linux", feature = "uffd"))]
+            RegionType::Uffd => UffdRegion::create_with_options(
+                instance_capacity,
+                &self.limits(),
+                WasmPageSizedUffdStrategy,
+                &self.region_options,
+            )?,
+        };
+        Ok(region)
+    }
+}
+
+lazy_static! {
+    static ref DEFAULTS: RwLock<Defaults> = RwLock::new(Defaults::new());
+}
+
+/// Set the defaults for the regions created by the process, after checking their limits.
+pub fn set_defaults(defaults: Defaults) -> Result<(), Error> {
+    defaults.limits().validate()?;
+    *DEFAULTS.write().unwrap() = defaults;
+    Ok(())
+}
+
+/// The defaults for the regions created by the process.
+pub fn defaults() -> Defaults {
+    DEFAULTS.read().unwrap().clone()
+}
+
+/// Create a region with the process-wide defaults, that can support the given number of
+/// instances.
+pub fn create_default_region(instance_capacity: usize) -> Result<Arc<dyn Region>, Error> {
+    defaults().create_region(instance_capacity)
+}
+
+/// Parse a size in bytes, with an optional `K`, `M`, or `G` suffix.
+fn parse_size(name: &str, value: &str) -> Result<usize, Error> {
+    let trimmed = value.trim();
+    let multiplier = match trimmed.as_bytes().last() {
+        Some(b'k') | Some(b'K') => 1 << 10,
+        Some(b'm') | Some(b'M') => 1 << 20,
+        Some(b'g') | Some(b'G') => 1 << 30,
+        _ => 1,
+    };
+    let digits = if multiplier == 1 {
+        trimmed
+    } else {
+        &trimmed[..trimmed.len() - 1]
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .ok_or_else(|| invalid(name, value))
+}
+
+fn invalid(name: &str, value: &str) -> Error {
+    Error::InvalidConfig(format!("`{}` has invalid value `{}`", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn overrides(vars: &[(&str, &str)]) -> Result<Defaults, Error> {
+        let vars = vars
+            .iter()
+            .map(|&(name, value)| (name.to_owned(), value.to_owned()))
+            .collect::<HashMap<_, _>>();
+        Defaults::new().with_overrides_from(|name| vars.get(name).cloned())
+    }
+
+    #[test]
+    fn env_overrides_set_limits() {
+        let defaults = overrides(&[
+            ("LUCET_HEAP_MEMORY_SIZE", "4M"),
+            ("LUCET_HEAP_GUARD_SIZE", "4G"),
+            ("LUCET_STACK_SIZE", "262144"),
+            ("LUCET_DECOMMIT_POLICY", "keep-warm"),
+        ])
+        .expect("overrides are valid");
+        assert_eq!(defaults.limits().heap_memory_size, 4 << 20);
+        assert_eq!(
+            defaults.limits().heap_address_space_size,
+            (4 << 20) + (4 << 30)
+        );
+        assert_eq!(defaults.limits().stack_size, 256 * 1024);
+        assert_eq!(
+            defaults.limits().globals_size,
+            Limits::default().globals_size
+        );
+        assert_eq!(
+            defaults.region_options().decommit_policy,
+            DecommitPolicy::KeepWarm
+        );
+        assert_eq!(defaults.region_type(), RegionType::Mmap);
+    }
+
+    #[test]
+    fn heap_guard_size_applies_to_later_limits() {
+        let limits = Limits::builder()
+            .with_heap_memory_size(4 << 20)
+            .build()
+            .expect("limits are valid");
+        let defaults = Defaults::new()
+            .with_heap_guard_size(4 << 30)
+            .with_limits(limits);
+        assert_eq!(
+            defaults.limits().heap_address_space_size,
+            (4 << 20) + (4 << 30)
+        );
+    }
+
+    #[test]
+    fn env_overrides_are_checked() {
+        match overrides(&[("LUCET_STACK_SIZE", "lots")]) {
+            Err(Error::InvalidConfig(_)) => (),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        match overrides(&[("LUCET_REGION_TYPE", "malloc")]) {
+            Err(Error::InvalidConfig(_)) => (),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+        match overrides(&[("LUCET_STACK_SIZE", "1000")]) {
+            Err(Error::InvalidLimit {
+                field: "stack_size",
+                ..
+            }) => (),
+            res => panic!("unexpected result: {:?}", res.map(|_| ())),
+        }
+    }
+}
@@ -2,18 +2,25 @@ use std::any::{Any, TypeId};
 use std::cell::{BorrowError, BorrowMutError, Ref, RefCell, RefMut};
 use std::collections::HashMap;
 
-/// A map that holds at most one value of any type.
+/// A map that holds at most one value of any type, plus any number of values of any type under
+/// distinct string keys.
 ///
 /// This is similar to the type provided by the `anymap` crate, but we can get away with simpler
 /// types on the methods due to our more specialized use case.
+///
+/// Each type may also have a reset function, which `reset()` applies to every value of that type.
 #[derive(Default)]
 pub struct CtxMap {
     map: HashMap<TypeId, RefCell<Box<dyn Any>>>,
+    keyed: HashMap<TypeId, HashMap<String, RefCell<Box<dyn Any>>>>,
+    resets: HashMap<TypeId, Box<dyn Fn(&mut dyn Any)>>,
 }
 
 impl CtxMap {
     pub fn clear(&mut self) {
         self.map.clear();
+        self.keyed.clear();
+        self.resets.clear();
     }
 
     pub fn contains<T: Any>(&self) -> bool {
@@ -60,13 +67,110 @@ impl CtxMap {
         })
     }
 
+    pub fn contains_keyed<T: Any>(&self, key: &str) -> bool {
+        self.get_keyed_cell::<T>(key).is_some()
+    }
+
+    pub fn try_get_keyed<T: Any>(&self, key: &str) -> Option<Result<Ref<'_, T>, BorrowError>> {
+        self.get_keyed_cell::<T>(key).map(|x| {
+            x.try_borrow().map(|r| {
+                Ref::map(r, |b| {
+                    b.downcast_ref::<T>()
+                        .expect("value stored with TypeId::of::<T> is always type T")
+                })
+            })
+        })
+    }
+
+    pub fn try_get_keyed_mut<T: Any>(
+        &self,
+        key: &str,
+    ) -> Option<Result<RefMut<'_, T>, BorrowMutError>> {
+        self.get_keyed_cell::<T>(key).map(|x| {
+            x.try_borrow_mut().map(|r| {
+                RefMut::map(r, |b| {
+                    b.downcast_mut::<T>()
+                        .expect("value stored with TypeId::of::<T> is always type T")
+                })
+            })
+        })
+    }
+
+    pub fn insert_keyed<T: Any>(&mut self, key: String, x: T) -> Option<T> {
+        self.keyed
+            .entry(TypeId::of::<T>())
+            .or_insert_with(HashMap::new)
+            .insert(key, RefCell::new(Box::new(x) as Box<dyn Any>))
+            .map(|x_prev| {
+                *(x_prev.into_inner())
+                    .downcast::<T>()
+                    .expect("value stored with TypeId::of::<T> is always type T")
+            })
+    }
+
+    pub fn remove_keyed<T: Any>(&mut self, key: &str) -> Option<T> {
+        let entries = self.keyed.get_mut(&TypeId::of::<T>())?;
+        let x = entries.remove(key)?;
+        if entries.is_empty() {
+            self.keyed.remove(&TypeId::of::<T>());
+        }
+        Some(
+            *(x.into_inner())
+                .downcast::<T>()
+                .expect("value stored with TypeId::of::<T> is always type T"),
+        )
+    }
+
+    /// Set the function that `reset()` applies to values of type `T`, returning `true` if one was
+    /// already set.
+    pub fn set_reset<T: Any>(&mut self, reset: impl Fn(&mut T) + 'static) -> bool {
+        let reset = move |x: &mut dyn Any| {
+            reset(
+                x.downcast_mut::<T>()
+                    .expect("value stored with TypeId::of::<T> is always type T"),
+            )
+        };
+        self.resets
+            .insert(TypeId::of::<T>(), Box::new(reset))
+            .is_some()
+    }
+
+    /// Remove the reset function for values of type `T`, returning `true` if one was set.
+    pub fn remove_reset<T: Any>(&mut self) -> bool {
+        self.resets.remove(&TypeId::of::<T>()).is_some()
+    }
+
+    /// Apply the reset function of each type to all of the values of that type, keyed or not.
+    pub fn reset(&mut self) {
+        for (type_id, reset) in self.resets.iter() {
+            if let Some(cell) = self.map.get_mut(type_id) {
+                reset(&mut **cell.get_mut());
+            }
+            if let Some(entries) = self.keyed.get_mut(type_id) {
+                for cell in entries.values_mut() {
+                    reset(&mut **cell.get_mut());
+                }
+            }
+        }
+    }
+
+    fn get_keyed_cell<T: Any>(&self, key: &str) -> Option<&RefCell<Box<dyn Any>>> {
+        self.keyed
+            .get(&TypeId::of::<T>())
+            .and_then(|entries| entries.get(key))
+    }
+
     pub fn new() -> Self {
         Self::default()
     }
 
     /// Returns `true` if there are any outstanding borrows to any of the values stored in the map.
     pub fn is_any_value_borrowed(&self) -> bool {
-        for cell in self.map.values() {
+        for cell in self
+            .map
+            .values()
+            .chain(self.keyed.values().flat_map(|m| m.values()))
+        {
             // borrow_mut will only fail if there is another borrow (mutable or not) outstanding
             if cell.try_borrow_mut().is_err() {
                 return true;
@@ -20,6 +20,22 @@ pub enum Error {
     #[error("Instance limits exceeded: {0}")]
     LimitsExceeded(String),
 
+    /// Building an instance, or growing its heap, would exceed the aggregate limits of its
+    /// [`Quota`](quota/struct.Quota.html).
+    #[error("Quota exceeded: {0}")]
+    QuotaExceeded(String),
+
+    /// A setting of the process-wide [`config`](config/index.html) could not be parsed.
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    /// A field of [`Limits`](struct.Limits.html) violates one of its constraints.
+    #[error("Invalid limit `{field}`: {constraint}")]
+    InvalidLimit {
+        field: &'static str,
+        constraint: &'static str,
+    },
+
     /// A method call attempted to modify linear memory for an instance that
     /// does not have linear memory
     #[error("No linear memory available: {0}")]
@@ -29,10 +45,57 @@ pub enum Error {
     #[error("Symbol not found: {0}")]
     SymbolNotFound(String),
 
+    /// No module is registered under the name given to a
+    /// [`ModuleRegistry`](module/struct.ModuleRegistry.html).
+    #[error("Module not found: {0}")]
+    ModuleNotFound(String),
+
+    /// The memory of an instance could not be locked, as requested by
+    /// [`RegionOptions::with_mlock()`](struct.RegionOptions.html#method.with_mlock).
+    #[error("Could not lock instance memory: {0}")]
+    MemoryLockFailed(String),
+
+    /// A [`SeccompFilter`](seccomp/struct.SeccompFilter.html) could not be installed.
+    #[error("Could not install seccomp filter: {0}")]
+    SeccompFailed(String),
+
+    /// Memory owned by the runtime is both writable and executable, while
+    /// [enforcement](wx/fn.set_enforcement.html) is enabled.
+    #[error("Memory is both writable and executable: {0}")]
+    WxViolation(String),
+
+    /// The poisoned memory of a freed or reset heap was overwritten before it was reused, as
+    /// detected by [`RegionOptions::with_heap_poisoning()`](struct.RegionOptions.html#method.with_heap_poisoning).
+    #[error("Poisoned heap memory was overwritten: {0}")]
+    HeapPoisonCorrupted(String),
+
+    /// An attempt was made to set a WebAssembly global that was not declared mutable.
+    #[error("Global `{0}` is immutable")]
+    ImmutableGlobal(String),
+
     /// An attempt to look up a WebAssembly function by its table index failed.
     #[error("Function not found: (table {0}, func {1}")]
     FuncNotFound(u32, u32),
 
+    /// An import could not be bound by a [`Linker`](linker/struct.Linker.html), or a linked
+    /// import could not be called.
+    #[error("Link error: {0}")]
+    LinkError(String),
+
+    /// An access to guest memory through [`GuestMemory`](memory/struct.GuestMemory.html) was
+    /// invalid.
+    #[error("Guest memory error: {0}")]
+    GuestMemoryError(#[from] GuestMemoryError),
+
+    /// An embedder context value of the named type (and key, if any) was not present.
+    #[error("Embedder context not found: {0}")]
+    CtxNotFound(String),
+
+    /// An embedder context value of the named type (and key, if any) was already borrowed in a
+    /// way that conflicts with the requested borrow.
+    #[error("Embedder context already borrowed: {0}")]
+    CtxBorrowed(String),
+
     /// An instance aborted due to a runtime fault.
     #[error("Runtime fault: {0:?}")]
     RuntimeFault(FaultDetails),
@@ -116,6 +179,26 @@ pub enum ModuleError {
     ModuleDataError(#[from] lucet_module::Error),
 }
 
+/// Invalid accesses to guest memory.
+#[derive(Debug, Error)]
+pub enum GuestMemoryError {
+    /// A range of guest memory extended past the end of the heap.
+    #[error("{len} bytes at guest address {ptr:#x} are out of bounds")]
+    OutOfBounds { ptr: u32, len: u32 },
+
+    /// A typed guest pointer was not aligned for its pointee type.
+    #[error("guest address {ptr:#x} is not aligned to {align} bytes")]
+    Misaligned { ptr: u32, align: u32 },
+
+    /// No nul terminator was found between a string's guest address and the end of the heap.
+    #[error("string at guest address {ptr:#x} is not nul-terminated")]
+    UnterminatedCStr { ptr: u32 },
+
+    /// A string in guest memory was not valid UTF-8.
+    #[error("invalid UTF-8: {0}")]
+    InvalidUtf8(#[source] std::str::Utf8Error),
+}
+
 #[macro_export]
 macro_rules! lucet_bail {
     ($e:expr) => {
@@ -1,24 +1,38 @@
 pub mod execution;
+mod group;
+mod imported_globals;
 mod siginfo_ext;
 pub mod signals;
 pub mod state;
+mod typed_func;
 
 pub use crate::instance::execution::{KillError, KillState, KillSuccess, KillSwitch};
+pub use crate::instance::group::InstanceGroup;
+pub use crate::instance::imported_globals::ImportedGlobals;
 pub use crate::instance::signals::{signal_handler_none, SignalBehavior, SignalHandler};
 pub use crate::instance::state::State;
+pub use crate::instance::typed_func::TypedFunc;
 
-use crate::alloc::Alloc;
+use crate::instance::group::GroupMembership;
+use crate::instance::siginfo_ext::SiginfoExt;
+
+use crate::alloc::{AddrLocation, Alloc};
 use crate::context::Context;
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
 #[cfg(feature = "concurrent_testpoints")]
 use crate::lock_testpoints::LockTestpoints;
-use crate::module::{self, FunctionHandle, Global, GlobalValue, Module, TrapCode};
-use crate::region::RegionInternal;
+use crate::memory::GuestMemory;
+use crate::module::{
+    self, FunctionHandle, Global, GlobalValue, Module, TableElement, TrapCode, ValueType,
+};
+use crate::quota::{HostcallRateLimit, Quota, QuotaCharge};
+use crate::region::{mpk, RegionInternal};
+use crate::replay::{self, HostcallLog, Interposer};
 use crate::sysdeps::HOST_PAGE_SIZE_EXPECTED;
-use crate::val::{UntypedRetVal, Val};
+use crate::val::{UntypedRetVal, Val, WasmParams, WasmRet, WasmValue};
 use crate::WASM_PAGE_SIZE;
-use libc::{c_void, pthread_self, siginfo_t, uintptr_t};
+use libc::{c_void, pthread_self, siginfo_t, uintptr_t, SIGBUS, SIGSEGV};
 use lucet_module::InstanceRuntimeData;
 use memoffset::offset_of;
 use std::any::Any;
@@ -28,9 +42,13 @@ use std::mem;
 use std::ops::{Deref, DerefMut};
 use std::ptr::{self, NonNull};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 pub const LUCET_INSTANCE_MAGIC: u64 = 746_932_922;
 
+/// The pattern written to every word of the guest stack when measuring its high-water mark.
+const STACK_POISON: u64 = 0xdead_beef_5afe_57ac;
+
 thread_local! {
     /// The host context.
     ///
@@ -264,12 +282,162 @@ pub struct Instance {
     /// The value passed back to the guest when resuming a yielded instance.
     pub(crate) resumed_val: Option<Box<dyn Any + 'static>>,
 
+    /// The largest size the heap has had since the instance was created or last reset.
+    peak_heap_size: usize,
+
+    /// The number of calls to `grow_memory()` since the instance was created or last reset.
+    grow_count: u64,
+
+    /// The CPU time the guest has used since the instance was created or last reset, not
+    /// counting the time accumulating since `cpu_clock_start`.
+    cpu_time: Duration,
+
+    /// The thread CPU time at which the guest last started running, or `None` if it is not
+    /// currently running guest code.
+    cpu_clock_start: Option<Duration>,
+
+    /// Whether the thread CPU clock is read to account for `cpu_time`.
+    cpu_time_accounting: bool,
+
+    /// Whether the guest stack is filled with `STACK_POISON` when the instance is created or
+    /// reset, so that the stack high-water mark can be measured.
+    stack_poisoned: bool,
+
+    /// Whether the instance runs in deterministic mode, in which nondeterministic hostcalls
+    /// terminate it.
+    deterministic: bool,
+
+    /// Whether the instance's hostcalls are being recorded or replayed.
+    hostcall_interposer: Option<Interposer>,
+
+    /// When the module's start function runs.
+    start_policy: StartPolicy,
+
+    /// What happens when one of the instance's hostcalls panics.
+    host_panic_policy: HostPanicPolicy,
+
+    /// The payload of a hostcall panic to resume unwinding once the instance has returned to the
+    /// host, under `HostPanicPolicy::ResumeUnwind`.
+    host_panic_payload: Option<Box<dyn Any + Send>>,
+
+    /// The instance's membership in an `InstanceGroup`, if it belongs to one.
+    group_membership: Option<GroupMembership>,
+
+    /// The resources the instance is charged for by its `Quota`, if it is attached to one.
+    quota_charge: Option<QuotaCharge>,
+
+    /// The limit on how many hostcalls the instance makes per second, if it has one.
+    hostcall_rate_limit: Option<HostcallRateLimit>,
+
+    /// Whether the module's start function has yet to return since the instance was created or
+    /// last reset.
+    start_pending: bool,
+
+    /// The number of hostcalls the guest has made since the instance was created.
+    hostcall_count: u64,
+
+    /// The number of times the guest has yielded since the instance was created.
+    yield_count: u64,
+
     /// `_padding` must be the last member of the structure.
     /// This marks where the padding starts to make the structure exactly 4096 bytes long.
     /// It is also used to compute the size of the structure up to that point, i.e. without padding.
     _padding: (),
 }
 
+/// Statistics about the memory used by an instance, as returned by
+/// [`Instance::memory_stats()`](struct.Instance.html#method.memory_stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryStats {
+    /// The current size of the heap, in bytes.
+    pub heap_size: usize,
+    /// The largest size of the heap, in bytes, since the instance was created or last reset.
+    pub peak_heap_size: usize,
+    /// The number of times the heap was asked to grow, whether by `memory.grow` in the guest or
+    /// by `Instance::grow_memory()`, including requests that failed.
+    pub grow_count: u64,
+    /// The largest number of bytes of the guest stack that have been used, if the runtime measured
+    /// it.
+    pub stack_high_water_mark: Option<usize>,
+    /// The number of globals defined by the module.
+    pub globals_count: usize,
+}
+
+/// When the start function of an instance's module runs, as set by
+/// [`InstanceBuilder::with_start_policy()`](../region/struct.InstanceBuilder.html#method.with_start_policy).
+///
+/// Whatever the policy, no other function of an instance can run until its start function has
+/// returned; if the start function faults or is terminated, the instance must be reset before it
+/// can run again.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StartPolicy {
+    /// The start function only runs when the embedder calls
+    /// [`Instance::run_start()`](struct.Instance.html#method.run_start). Running another
+    /// function first fails with `Error::InstanceNeedsStart`. This is the default.
+    Explicit,
+    /// The start function runs when the instance is built, and whenever it is reset.
+    OnInstantiation,
+    /// The start function runs before the first other function the instance runs after it is
+    /// created or reset.
+    OnFirstRun,
+}
+
+impl Default for StartPolicy {
+    fn default() -> Self {
+        StartPolicy::Explicit
+    }
+}
+
+/// What happens when a hostcall of an instance panics with a payload other than
+/// `TerminationDetails`, as set by
+/// [`RegionOptions::with_host_panic_policy()`](../region/struct.RegionOptions.html#method.with_host_panic_policy)
+/// or
+/// [`InstanceBuilder::with_host_panic_policy()`](../region/struct.InstanceBuilder.html#method.with_host_panic_policy).
+///
+/// This applies to hostcalls defined with `#[lucet_hostcall]` and to host functions bound with a
+/// [`Linker`](../linker/struct.Linker.html). Panics with a `TerminationDetails` payload, such as
+/// those raised by `lucet_hostcall_terminate!`, always terminate the instance with those details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostPanicPolicy {
+    /// Terminate the instance with `TerminationDetails::HostPanic`, and once it has returned to
+    /// the host, resume unwinding the panic from the embedder's call to `Instance::run()`. The
+    /// panic never unwinds through guest frames. The instance can be reset and run again like
+    /// after any other termination. This is the default.
+    ResumeUnwind,
+    /// Terminate the instance with `TerminationDetails::HostPanic`, which carries the panic
+    /// message. The instance can then be reset and run again like after any other termination.
+    TerminateInstance,
+    /// Abort the process, as if the embedder were built with `panic = "abort"`.
+    Abort,
+}
+
+impl Default for HostPanicPolicy {
+    fn default() -> Self {
+        HostPanicPolicy::ResumeUnwind
+    }
+}
+
+/// Statistics about the guest execution caused by a call, as returned by
+/// [`Instance::run_with_stats()`](struct.Instance.html#method.run_with_stats) and
+/// [`Instance::with_run_stats()`](struct.Instance.html#method.with_run_stats).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RunStats {
+    /// The number of instructions the guest executed, if the module is instrumented to count
+    /// them.
+    pub instruction_count: Option<u64>,
+    /// The number of hostcalls the guest made.
+    pub hostcall_count: u64,
+    /// The number of times the guest yielded.
+    pub yield_count: u64,
+    /// The number of bytes the heap grew by.
+    pub heap_growth: usize,
+    /// The wall-clock time the call took.
+    pub duration: Duration,
+    /// The CPU time the guest used, as measured by
+    /// [`Instance::cpu_time()`](struct.Instance.html#method.cpu_time).
+    pub cpu_time: Duration,
+}
+
 /// Users of `Instance` must be very careful about when instances are dropped!
 ///
 /// Typically you will not have to worry about this, as InstanceHandle will robustly handle
@@ -307,24 +475,30 @@ pub enum RunResult {
     /// [reset](struct.Instance.html#method.reset), or dropped. Attempting to run an instance from a
     /// new entrypoint after it has yielded but without first resetting will result in an error.
     Yielded(YieldedVal),
+    /// An instance exited with a status.
+    ///
+    /// This arises when a hostcall invokes [`Vmctx::exit()`](vmctx/struct.Vmctx.html#method.exit).
+    /// An instance that has exited must be [reset](struct.Instance.html#method.reset) before it
+    /// can be run again.
+    Exited(i32),
 }
 
 impl RunResult {
     /// Try to get a return value from a run result, returning `Error::InstanceNotReturned` if the
-    /// instance instead yielded.
+    /// instance instead yielded or exited.
     pub fn returned(self) -> Result<UntypedRetVal, Error> {
         match self {
             RunResult::Returned(rv) => Ok(rv),
-            RunResult::Yielded(_) => Err(Error::InstanceNotReturned),
+            RunResult::Yielded(_) | RunResult::Exited(_) => Err(Error::InstanceNotReturned),
         }
     }
 
     /// Try to get a reference to a return value from a run result, returning
-    /// `Error::InstanceNotReturned` if the instance instead yielded.
+    /// `Error::InstanceNotReturned` if the instance instead yielded or exited.
     pub fn returned_ref(&self) -> Result<&UntypedRetVal, Error> {
         match self {
             RunResult::Returned(rv) => Ok(rv),
-            RunResult::Yielded(_) => Err(Error::InstanceNotReturned),
+            RunResult::Yielded(_) | RunResult::Exited(_) => Err(Error::InstanceNotReturned),
         }
     }
 
@@ -352,20 +526,20 @@ impl RunResult {
     }
 
     /// Try to get a yielded value from a run result, returning `Error::InstanceNotYielded` if the
-    /// instance instead returned.
+    /// instance instead returned or exited.
     pub fn yielded(self) -> Result<YieldedVal, Error> {
         match self {
-            RunResult::Returned(_) => Err(Error::InstanceNotYielded),
             RunResult::Yielded(yv) => Ok(yv),
+            RunResult::Returned(_) | RunResult::Exited(_) => Err(Error::InstanceNotYielded),
         }
     }
 
     /// Try to get a reference to a yielded value from a run result, returning
-    /// `Error::InstanceNotYielded` if the instance instead returned.
+    /// `Error::InstanceNotYielded` if the instance instead returned or exited.
     pub fn yielded_ref(&self) -> Result<&YieldedVal, Error> {
         match self {
-            RunResult::Returned(_) => Err(Error::InstanceNotYielded),
             RunResult::Yielded(yv) => Ok(yv),
+            RunResult::Returned(_) | RunResult::Exited(_) => Err(Error::InstanceNotYielded),
         }
     }
 
@@ -391,6 +565,19 @@ impl RunResult {
     pub fn unwrap_yielded(self) -> YieldedVal {
         self.yielded().unwrap()
     }
+
+    /// Get the status the instance exited with, or `None` if it instead returned or yielded.
+    pub fn exit_status(&self) -> Option<i32> {
+        match self {
+            RunResult::Exited(status) => Some(*status),
+            RunResult::Returned(_) | RunResult::Yielded(_) => None,
+        }
+    }
+
+    /// Returns `true` if the instance exited.
+    pub fn is_exited(&self) -> bool {
+        self.exit_status().is_some()
+    }
 }
 
 /// APIs that are internal, but useful to implementors of extension modules; you probably don't want
@@ -470,6 +657,59 @@ impl Instance {
         self.run_func(func, &args)
     }
 
+    /// Run a function with arguments in the guest context, like
+    /// [`Instance::run()`](struct.Instance.html#method.run), and return statistics about its
+    /// execution along with its result.
+    ///
+    /// ```no_run
+    /// # use lucet_runtime_internals::instance::InstanceHandle;
+    /// # let mut instance: InstanceHandle = unimplemented!();
+    /// let (res, stats) = instance.run_with_stats("handle_request", &[]);
+    /// println!("{} hostcalls in {:?}", stats.hostcall_count, stats.duration);
+    /// ```
+    ///
+    /// The statistics are returned even if the guest faults or is terminated.
+    pub fn run_with_stats(
+        &mut self,
+        entrypoint: &str,
+        args: &[Val],
+    ) -> (Result<RunResult, Error>, RunStats) {
+        self.with_run_stats(|inst| inst.run(entrypoint, args))
+    }
+
+    /// Call `f` with the instance, and return statistics about the guest execution it caused
+    /// along with its result.
+    ///
+    /// This measures any way of running the instance, such as resuming it after a yield:
+    ///
+    /// ```no_run
+    /// # use lucet_runtime_internals::instance::InstanceHandle;
+    /// # let mut instance: InstanceHandle = unimplemented!();
+    /// let (res, stats) = instance.with_run_stats(|inst| inst.resume());
+    /// ```
+    pub fn with_run_stats<R>(&mut self, f: impl FnOnce(&mut Instance) -> R) -> (R, RunStats) {
+        let instruction_count = self.get_instruction_count();
+        let hostcall_count = self.hostcall_count;
+        let yield_count = self.yield_count;
+        let heap_size = self.alloc.heap_len();
+        let cpu_time = self.cpu_time();
+        let start = std::time::Instant::now();
+
+        let res = f(self);
+
+        let stats = RunStats {
+            instruction_count: self
+                .get_instruction_count()
+                .and_then(|after| Some(after.saturating_sub(instruction_count?))),
+            hostcall_count: self.hostcall_count - hostcall_count,
+            yield_count: self.yield_count - yield_count,
+            heap_growth: self.alloc.heap_len().saturating_sub(heap_size),
+            duration: start.elapsed(),
+            cpu_time: self.cpu_time().checked_sub(cpu_time).unwrap_or_default(),
+        };
+        (res, stats)
+    }
+
     /// Run a function with arguments in the guest context from the [WebAssembly function
     /// table](https://webassembly.github.io/spec/core/syntax/modules.html#tables).
     ///
@@ -486,6 +726,59 @@ impl Instance {
         self.run_func(func, &args)
     }
 
+    /// Run the function stored in an element of a [WebAssembly
+    /// table](https://webassembly.github.io/spec/core/syntax/modules.html#tables).
+    ///
+    /// This is the host-side counterpart of `call_indirect`, useful for invoking callbacks that a
+    /// guest registered by storing a `funcref` in its table. The arguments are checked against the
+    /// signature of the function found in the element, and empty or out-of-bounds elements yield
+    /// `Error::FuncNotFound`.
+    ///
+    /// ```no_run
+    /// # use lucet_runtime_internals::instance::InstanceHandle;
+    /// # let instance: InstanceHandle = unimplemented!();
+    /// // the guest stored its callback at index 3 of table 0
+    /// let retval = instance
+    ///     .run_table_entry(0, 3, &[5u64.into()])
+    ///     .unwrap()
+    ///     .unwrap_returned();
+    /// ```
+    ///
+    /// # Safety
+    ///
+    /// The foreign code safety caveat of [`Instance::run()`](struct.Instance.html#method.run)
+    /// applies.
+    pub fn run_table_entry(
+        &mut self,
+        table_idx: u32,
+        elem_idx: u32,
+        args: &[Val],
+    ) -> Result<RunResult, Error> {
+        let func = self.module.get_table_entry(table_idx, elem_idx)?;
+        self.run_func(func, &args)
+    }
+
+    /// Get a statically typed handle to an exported function.
+    ///
+    /// The signature of the function is checked against `Params` and `Ret` once, here, rather than
+    /// each time the function is run:
+    ///
+    /// ```no_run
+    /// # use lucet_runtime_internals::instance::InstanceHandle;
+    /// # let mut instance: InstanceHandle = unimplemented!();
+    /// let add = instance.get_typed_func::<(i32, i64), i64>("add").unwrap();
+    /// assert_eq!(add.call(&mut instance, (1, 2)).unwrap(), 3);
+    /// ```
+    ///
+    /// The handle can be used with this instance and any other instance of the same module.
+    pub fn get_typed_func<Params: WasmParams, Ret: WasmRet>(
+        &self,
+        entrypoint: &str,
+    ) -> Result<TypedFunc<Params, Ret>, Error> {
+        let func = self.module.get_export_func(entrypoint)?;
+        TypedFunc::new(self.module.clone(), func)
+    }
+
     /// Resume execution of an instance that has yielded without providing a value to the guest.
     ///
     /// This should only be used when the guest yielded with
@@ -571,19 +864,84 @@ impl Instance {
             if res.is_yielded() {
                 return Err(Error::StartYielded);
             }
+            self.start_pending = false;
         }
         Ok(())
     }
 
+    /// Get the policy for when the module's start function runs.
+    pub fn start_policy(&self) -> StartPolicy {
+        self.start_policy
+    }
+
+    /// Set the policy for when the module's start function runs.
+    ///
+    /// This is set by
+    /// [`InstanceBuilder::with_start_policy()`](../region/struct.InstanceBuilder.html#method.with_start_policy),
+    /// which also runs the start function if the policy is `StartPolicy::OnInstantiation`.
+    pub(crate) fn set_start_policy(&mut self, policy: StartPolicy) {
+        self.start_policy = policy;
+    }
+
+    /// Get the policy for what happens when one of the instance's hostcalls panics.
+    pub fn host_panic_policy(&self) -> HostPanicPolicy {
+        self.host_panic_policy
+    }
+
+    /// Set the policy for what happens when one of the instance's hostcalls panics.
+    ///
+    /// This is set by
+    /// [`InstanceBuilder::with_host_panic_policy()`](../region/struct.InstanceBuilder.html#method.with_host_panic_policy),
+    /// or from the options of the instance's region.
+    pub(crate) fn set_host_panic_policy(&mut self, policy: HostPanicPolicy) {
+        self.host_panic_policy = policy;
+    }
+
+    /// Handle a panic in a hostcall according to the instance's `HostPanicPolicy`.
+    ///
+    /// `payload` must not be `TerminationDetails`, which terminate the instance whatever the
+    /// policy.
+    pub(crate) unsafe fn host_panicked(&mut self, payload: Box<dyn Any + Send>) -> ! {
+        let msg = if let Some(msg) = payload.downcast_ref::<&'static str>() {
+            (*msg).to_owned()
+        } else if let Some(msg) = payload.downcast_ref::<String>() {
+            msg.clone()
+        } else {
+            "Box<Any>".to_owned()
+        };
+        match self.host_panic_policy {
+            HostPanicPolicy::ResumeUnwind => {
+                // unwinding from the guest stack would run through guest frames, so the panic is
+                // resumed by `swap_and_return()` on the host stack
+                self.host_panic_payload = Some(payload);
+                self.terminate(TerminationDetails::HostPanic(msg))
+            }
+            HostPanicPolicy::TerminateInstance => {
+                self.terminate(TerminationDetails::HostPanic(msg))
+            }
+            HostPanicPolicy::Abort => std::process::abort(),
+        }
+    }
+
     /// Reset the instance's heap and global variables to their initial state.
     ///
+    /// The instance keeps its region slot, so resetting is much cheaper than dropping the instance
+    /// and creating a new one, and suits serving one request per reset. The heap is restored to the
+    /// module's initial size and data, and only the pages that were accessible since the last reset
+    /// need to be cleared. WebAssembly tables are part of the module and cannot be modified by the
+    /// guest, so they need no reset.
+    ///
     /// The WebAssembly `start` section, if present, will need to be re-run with
-    /// [`Instance::run_start()`][run_start] before running any other exported functions.
+    /// [`Instance::run_start()`][run_start] before running any other exported functions, unless
+    /// the instance's [`StartPolicy`](enum.StartPolicy.html) runs it automatically. With
+    /// `StartPolicy::OnInstantiation`, it is run before this returns, and any error it returns is
+    /// returned from this call.
     ///
     /// The embedder contexts present at instance creation or added with
     /// [`Instance::insert_embed_ctx()`](struct.Instance.html#method.insert_embed_ctx) are not
-    /// modified by this call; it is the embedder's responsibility to clear or reset their state if
-    /// necessary.
+    /// modified by this call, unless their type has been designated with
+    /// [`Instance::set_embed_ctx_reset()`](struct.Instance.html#method.set_embed_ctx_reset); it is
+    /// otherwise the embedder's responsibility to clear or reset their state if necessary.
     ///
     /// This will also reinitialize the kill state, which means that any outstanding
     /// [`KillSwitch`](struct.KillSwitch.html) objects will be unable to terminate this instance.
@@ -592,25 +950,41 @@ impl Instance {
     ///
     /// [run_start]: struct.Instance.html#method.run
     pub fn reset(&mut self) -> Result<(), Error> {
+        self.host_panic_payload = None;
         self.alloc.reset_heap(self.module.as_ref())?;
+        self.peak_heap_size = self.alloc.heap_len();
+        if let Some(charge) = &mut self.quota_charge {
+            charge.set_heap_size(self.alloc.heap_len())?;
+        }
+        self.grow_count = 0;
+        self.cpu_time = Duration::default();
+        if self.stack_poisoned {
+            self.poison_stack();
+        }
         let globals = unsafe { self.alloc.globals_mut() };
         let mod_globals = self.module.globals();
         for (i, v) in mod_globals.iter().enumerate() {
             globals[i] = match v.global() {
-                Global::Import { .. } => {
-                    return Err(Error::Unsupported(format!(
-                        "global imports are unsupported; found: {:?}",
-                        v
-                    )));
+                Global::Import { module, field } => {
+                    match self.embed_ctx.try_get::<ImportedGlobals>() {
+                        Some(imported) => imported
+                            .map_err(|_| lucet_format_err!("imported globals are borrowed"))?
+                            .init_val(module, field, v.import_type())?,
+                        None => return Err(imported_globals::unsupplied(module, field)),
+                    }
                 }
                 Global::Def(def) => def.init_val(),
             };
         }
 
+        self.embed_ctx.reset();
+
         if self.module.get_start_func()?.is_some() {
             self.state = State::NotStarted;
+            self.start_pending = true;
         } else {
             self.state = State::Ready;
+            self.start_pending = false;
         }
 
         #[cfg(feature = "concurrent_testpoints")]
@@ -621,6 +995,11 @@ impl Instance {
         {
             self.kill_state = Arc::new(KillState::new());
         }
+        self.update_group_kill_state();
+
+        if self.start_policy == StartPolicy::OnInstantiation {
+            self.run_start()?;
+        }
 
         Ok(())
     }
@@ -629,15 +1008,132 @@ impl Instance {
     ///
     /// On success, returns the number of pages that existed before the call.
     pub fn grow_memory(&mut self, additional_pages: u32) -> Result<u32, Error> {
+        self.grow_count += 1;
         let additional_bytes = additional_pages
             .checked_mul(WASM_PAGE_SIZE)
             .ok_or_else(|| lucet_format_err!("additional pages larger than wasm address space",))?;
-        let orig_len = self
+        let heap_len = self.alloc.heap_len();
+        if let Some(charge) = &mut self.quota_charge {
+            charge.set_heap_size(heap_len + additional_bytes as usize)?;
+        }
+        let orig_len = match self
             .alloc
-            .expand_heap(additional_bytes, self.module.as_ref())?;
+            .expand_heap(additional_bytes, self.module.as_ref())
+        {
+            Ok(orig_len) => orig_len,
+            Err(e) => {
+                if let Some(charge) = &mut self.quota_charge {
+                    // shrinking the charge back cannot fail
+                    let _ = charge.set_heap_size(heap_len);
+                }
+                return Err(e);
+            }
+        };
+        self.peak_heap_size = self.peak_heap_size.max(self.alloc.heap_len());
         Ok(orig_len / WASM_PAGE_SIZE)
     }
 
+    /// Return statistics about the memory used by this instance since it was created or last
+    /// reset.
+    pub fn memory_stats(&self) -> MemoryStats {
+        MemoryStats {
+            heap_size: self.alloc.heap_len(),
+            peak_heap_size: self.peak_heap_size,
+            grow_count: self.grow_count,
+            stack_high_water_mark: if self.stack_poisoned {
+                Some(self.stack_high_water_mark())
+            } else {
+                None
+            },
+            globals_count: self.module.globals().len(),
+        }
+    }
+
+    /// Fill the guest stack with `STACK_POISON`, and measure its high-water mark from then on.
+    ///
+    /// This is called by
+    /// [`InstanceBuilder::with_stack_measurement()`](../region/struct.InstanceBuilder.html#method.with_stack_measurement),
+    /// before the instance first runs.
+    pub(crate) fn poison_stack(&mut self) {
+        for word in unsafe { self.alloc.stack_u64_mut() } {
+            *word = STACK_POISON;
+        }
+        self.stack_poisoned = true;
+    }
+
+    /// The number of bytes at the top of the guest stack that no longer hold `STACK_POISON`.
+    ///
+    /// The stack grows down, so the deepest word the guest has written is the first one from the
+    /// start of the stack that is not poison.
+    fn stack_high_water_mark(&self) -> usize {
+        let stack = unsafe { self.alloc.stack_u64() };
+        let untouched = stack
+            .iter()
+            .take_while(|&&word| word == STACK_POISON)
+            .count();
+        (stack.len() - untouched) * 8
+    }
+
+    /// Return the CPU time the guest has used since the instance was created or last reset.
+    ///
+    /// This is measured with the CPU clock of the thread running the guest, and only counts time
+    /// spent in guest code: time spent in hostcalls, while the instance is yielded, or while the
+    /// thread is descheduled is excluded. Unlike a wall-clock timeout, this is suitable for
+    /// billing guests for the CPU they use.
+    ///
+    /// Reading the clock costs a system call at every hostcall, so it is only read for instances
+    /// built with
+    /// [`InstanceBuilder::with_cpu_time_accounting()`](../region/struct.InstanceBuilder.html#method.with_cpu_time_accounting);
+    /// for other instances, this is always zero.
+    pub fn cpu_time(&self) -> Duration {
+        match self.cpu_clock_start {
+            Some(start) => self.cpu_time + thread_cpu_time().checked_sub(start).unwrap_or_default(),
+            None => self.cpu_time,
+        }
+    }
+
+    /// Check whether the instance runs in deterministic mode.
+    ///
+    /// See
+    /// [`InstanceBuilder::with_deterministic_execution()`](../region/struct.InstanceBuilder.html#method.with_deterministic_execution).
+    pub fn is_deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    pub(crate) fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    pub(crate) fn set_cpu_time_accounting(&mut self, cpu_time_accounting: bool) {
+        self.cpu_time_accounting = cpu_time_accounting;
+    }
+
+    /// Start recording the hostcalls the instance makes, discarding any log that is already being
+    /// recorded or replayed.
+    ///
+    /// See the [`replay`](../replay/index.html) module for what is recorded.
+    pub fn record_hostcalls(&mut self) {
+        self.hostcall_interposer = Some(Interposer::Recording(HostcallLog::new()));
+    }
+
+    /// Replay the hostcalls in `log` rather than running them, discarding any log that is already
+    /// being recorded or replayed.
+    ///
+    /// The instance should be freshly created or reset, and be run with the same arguments as the
+    /// instance that recorded the log.
+    pub fn replay_hostcalls(&mut self, log: HostcallLog) {
+        self.hostcall_interposer = Some(Interposer::replaying(log));
+    }
+
+    /// Stop recording or replaying hostcalls.
+    ///
+    /// Returns the recorded log, or, when replaying, the part of the log that has not been
+    /// replayed yet. Returns `None` if hostcalls were neither recorded nor replayed, or if the
+    /// instance is yielded from within a hostcall.
+    pub fn take_hostcall_log(&mut self) -> Option<HostcallLog> {
+        self.hostcall_interposer.take().map(Interposer::into_log)
+    }
+
     /// Return the WebAssembly heap as a slice of bytes.
     pub fn heap(&self) -> &[u8] {
         unsafe { self.alloc.heap() }
@@ -648,6 +1144,11 @@ impl Instance {
         unsafe { self.alloc.heap_mut() }
     }
 
+    /// Return a bounds-checked view of the WebAssembly heap.
+    pub fn memory(&mut self) -> GuestMemory<'_> {
+        GuestMemory::from_instance_heap(self.heap_mut())
+    }
+
     /// Return the WebAssembly heap as a slice of `u32`s.
     pub fn heap_u32(&self) -> &[u32] {
         unsafe { self.alloc.heap_u32() }
@@ -668,6 +1169,122 @@ impl Instance {
         unsafe { self.alloc.globals_mut() }
     }
 
+    /// Get the current value of the global exported as `name`.
+    pub fn get_global(&self, name: &str) -> Result<Val, Error> {
+        let (idx, ty, _) = self.exported_global(name)?;
+        let value = self.globals()[idx];
+        let val = unsafe {
+            match ty {
+                ValueType::I32 => Val::I32(value.i_32),
+                ValueType::I64 => Val::I64(value.i_64),
+                ValueType::F32 => Val::F32(value.f_32),
+                ValueType::F64 => Val::F64(value.f_64),
+            }
+        };
+        Ok(val)
+    }
+
+    /// Get the current value of the global exported as `name`, as a Rust value of the
+    /// corresponding Wasm type.
+    ///
+    /// Returns `Error::InvalidArgument` if the global's type does not match `T`.
+    pub fn get_global_as<T: WasmValue>(&self, name: &str) -> Result<T, Error> {
+        let val = self.get_global(name)?;
+        if val.value_type() != T::VALUE_TYPE {
+            return Err(Error::InvalidArgument("global type mismatch"));
+        }
+        Ok(T::from_val(&val))
+    }
+
+    /// Set the value of the global exported as `name`.
+    ///
+    /// Returns `Error::ImmutableGlobal` if the global was not declared mutable, and
+    /// `Error::InvalidArgument` if the type of `val` does not match the global's type.
+    pub fn set_global<V: Into<Val>>(&mut self, name: &str, val: V) -> Result<(), Error> {
+        let (idx, ty, mutable) = self.exported_global(name)?;
+        if !mutable {
+            return Err(Error::ImmutableGlobal(name.to_string()));
+        }
+        let val = val.into();
+        if val.value_type() != ty {
+            return Err(Error::InvalidArgument("global type mismatch"));
+        }
+        self.globals_mut()[idx] = match ty {
+            ValueType::I32 => GlobalValue {
+                i_32: i32::from_val(&val),
+            },
+            ValueType::I64 => GlobalValue {
+                i_64: i64::from_val(&val),
+            },
+            ValueType::F32 => GlobalValue {
+                f_32: f32::from_val(&val),
+            },
+            ValueType::F64 => GlobalValue {
+                f_64: f64::from_val(&val),
+            },
+        };
+        Ok(())
+    }
+
+    /// Find the index, type, and mutability of the global exported as `name`.
+    ///
+    /// Imported globals have the type the module records for them, or, for modules in formats
+    /// that do not, the type of the value supplied for them.
+    fn exported_global(&self, name: &str) -> Result<(usize, ValueType, bool), Error> {
+        let (idx, spec) = self
+            .module
+            .globals()
+            .iter()
+            .enumerate()
+            .find(|(_, spec)| spec.export_names().contains(&name))
+            .ok_or_else(|| Error::SymbolNotFound(name.to_string()))?;
+        match spec.global() {
+            Global::Def(def) => Ok((idx, def.value_type(), spec.is_mutable())),
+            Global::Import { module, field } => {
+                let value_type = match spec.import_type() {
+                    Some(import_type) => import_type,
+                    None => self
+                        .embed_ctx
+                        .try_get::<ImportedGlobals>()
+                        .and_then(|imported| imported.ok()?.get(module, field))
+                        .ok_or_else(|| imported_globals::unsupplied(module, field))?
+                        .value_type(),
+                };
+                Ok((idx, value_type, spec.is_mutable()))
+            }
+        }
+    }
+
+    /// Return a bounds-checked view of the linear memory exported as `name`.
+    ///
+    /// Returns `Error::SymbolNotFound` if the module does not export its memory as `name`.
+    pub fn exported_memory(&mut self, name: &str) -> Result<GuestMemory<'_>, Error> {
+        if !self.module.export_memory_names().contains(&name) {
+            return Err(Error::SymbolNotFound(name.to_string()));
+        }
+        Ok(self.memory())
+    }
+
+    /// Return the elements of the table exported as `name`.
+    ///
+    /// The functions in the table can be run with [`run_table_entry()`](#method.run_table_entry),
+    /// passing the index of an element. Only the first table of a module is supported.
+    pub fn exported_table(&self, name: &str) -> Result<&[TableElement], Error> {
+        let table = self
+            .module
+            .export_tables()
+            .iter()
+            .find(|table| table.names.contains(&name))
+            .ok_or_else(|| Error::SymbolNotFound(name.to_string()))?;
+        if table.table_idx != 0 {
+            return Err(Error::Unsupported(format!(
+                "table `{}` is not the first table of the module",
+                name
+            )));
+        }
+        self.module.table_elements()
+    }
+
     /// Check whether a given range in the host address space overlaps with the memory that backs
     /// the instance heap.
     pub fn check_heap<T>(&self, ptr: *const T, len: usize) -> bool {
@@ -701,6 +1318,69 @@ impl Instance {
         self.embed_ctx.remove::<T>()
     }
 
+    /// Designate the context values of type `T` as per-run state, to be reset with `reset` by
+    /// [`Instance::reset()`](struct.Instance.html#method.reset).
+    ///
+    /// `reset` is applied to the unkeyed value of type `T`, if any, and to every keyed value of
+    /// type `T`. Values inserted after this call are reset as well. Returns `true` if a reset
+    /// function for `T` was already set, in which case it is replaced.
+    pub fn set_embed_ctx_reset<T: Any>(&mut self, reset: impl Fn(&mut T) + 'static) -> bool {
+        self.embed_ctx.set_reset(reset)
+    }
+
+    /// Stop resetting the context values of type `T` in
+    /// [`Instance::reset()`](struct.Instance.html#method.reset), returning `true` if they were
+    /// being reset.
+    pub fn remove_embed_ctx_reset<T: Any>(&mut self) -> bool {
+        self.embed_ctx.remove_reset::<T>()
+    }
+
+    /// Get a reference to a context value of a particular type.
+    ///
+    /// Unlike [`get_embed_ctx()`](#method.get_embed_ctx), a missing or conflicting value is
+    /// reported as an `Error` naming the type.
+    pub fn try_get_embed_ctx<T: Any>(&self) -> Result<Ref<'_, T>, Error> {
+        ctx_ref::<T>(self.embed_ctx.try_get::<T>(), None)
+    }
+
+    /// Get a mutable reference to a context value of a particular type.
+    ///
+    /// Unlike [`get_embed_ctx_mut()`](#method.get_embed_ctx_mut), a missing or conflicting value
+    /// is reported as an `Error` naming the type.
+    pub fn try_get_embed_ctx_mut<T: Any>(&self) -> Result<RefMut<'_, T>, Error> {
+        ctx_ref_mut::<T>(self.embed_ctx.try_get_mut::<T>(), None)
+    }
+
+    /// Check whether a context value of a particular type exists under `key`.
+    pub fn contains_keyed_embed_ctx<T: Any>(&self, key: &str) -> bool {
+        self.embed_ctx.contains_keyed::<T>(key)
+    }
+
+    /// Get a reference to the context value of a particular type stored under `key`.
+    pub fn get_keyed_embed_ctx<T: Any>(&self, key: &str) -> Result<Ref<'_, T>, Error> {
+        ctx_ref::<T>(self.embed_ctx.try_get_keyed::<T>(key), Some(key))
+    }
+
+    /// Get a mutable reference to the context value of a particular type stored under `key`.
+    pub fn get_keyed_embed_ctx_mut<T: Any>(&self, key: &str) -> Result<RefMut<'_, T>, Error> {
+        ctx_ref_mut::<T>(self.embed_ctx.try_get_keyed_mut::<T>(key), Some(key))
+    }
+
+    /// Insert a context value under `key`.
+    ///
+    /// Keyed values are separate from the unkeyed value of the same type, and values of different
+    /// types may share a key. If a context value of the same type already existed under `key`, it
+    /// is returned.
+    pub fn insert_keyed_embed_ctx<T: Any>(&mut self, key: impl Into<String>, x: T) -> Option<T> {
+        self.embed_ctx.insert_keyed(key.into(), x)
+    }
+
+    /// Remove the context value of a particular type stored under `key`, returning it if it
+    /// exists.
+    pub fn remove_keyed_embed_ctx<T: Any>(&mut self, key: &str) -> Option<T> {
+        self.embed_ctx.remove_keyed::<T>(key)
+    }
+
     /// Set the handler run when `SIGBUS`, `SIGFPE`, `SIGILL`, or `SIGSEGV` are caught by the
     /// instance thread.
     ///
@@ -800,6 +1480,56 @@ impl Instance {
         KillSwitch::new(Arc::downgrade(&self.kill_state))
     }
 
+    /// Get the [`InstanceGroup`](struct.InstanceGroup.html) the instance belongs to, if any.
+    pub fn group(&self) -> Option<&InstanceGroup> {
+        self.group_membership.as_ref().map(|m| m.group())
+    }
+
+    /// Add the instance to `group`, leaving the group it belonged to, if any.
+    ///
+    /// This is set by
+    /// [`InstanceBuilder::with_group()`](../region/struct.InstanceBuilder.html#method.with_group).
+    pub(crate) fn join_group(&mut self, group: &InstanceGroup) {
+        self.group_membership = Some(group.join(&self.kill_state));
+    }
+
+    /// Get the [`Quota`](../quota/struct.Quota.html) the instance is attached to, if any.
+    pub fn quota(&self) -> Option<&Quota> {
+        self.quota_charge.as_ref().map(|c| c.quota())
+    }
+
+    /// Charge `quota` for the instance and its current heap.
+    ///
+    /// This is called by
+    /// [`InstanceBuilder::with_quota()`](../region/struct.InstanceBuilder.html#method.with_quota).
+    pub(crate) fn attach_quota(&mut self, quota: &Quota) -> Result<(), Error> {
+        self.quota_charge = Some(quota.charge_instance(self.alloc.heap_len())?);
+        Ok(())
+    }
+
+    /// Get the maximum number of hostcalls the instance may make per second, if it is limited.
+    pub fn hostcall_rate_limit(&self) -> Option<u64> {
+        self.hostcall_rate_limit
+            .as_ref()
+            .map(HostcallRateLimit::max_per_second)
+    }
+
+    /// Limit the instance to `max_per_second` hostcalls per second.
+    ///
+    /// This is set by
+    /// [`InstanceBuilder::with_hostcall_rate_limit()`](../region/struct.InstanceBuilder.html#method.with_hostcall_rate_limit).
+    pub(crate) fn set_hostcall_rate_limit(&mut self, max_per_second: u64) {
+        self.hostcall_rate_limit = Some(HostcallRateLimit::new(max_per_second));
+    }
+
+    /// Count a hostcall against the instance's rate limit, returning whether it is within it.
+    pub(crate) fn charge_hostcall(&mut self) -> bool {
+        match &mut self.hostcall_rate_limit {
+            Some(limit) => limit.charge(Instant::now()),
+            None => true,
+        }
+    }
+
     pub fn is_not_started(&self) -> bool {
         self.state.is_not_started()
     }
@@ -825,9 +1555,21 @@ impl Instance {
     // it out of rustdoc.
     #[doc(hidden)]
     pub fn uninterruptable<T, F: FnOnce() -> T>(&mut self, f: F) -> T {
+        self.hostcall_count += 1;
+        self.stop_cpu_clock();
         self.kill_state.begin_hostcall();
+        // the hostcall may only access the heap of its own instance, whatever it does to PKRU
+        let guest_pkru = self
+            .alloc
+            .slot()
+            .protection_key
+            .map(|key| key.restrict_access());
         let res = f();
+        if let Some(pkru) = guest_pkru {
+            mpk::write_pkru(pkru);
+        }
         let stop_reason = self.kill_state.end_hostcall();
+        self.start_cpu_clock();
 
         if let Some(termination_details) = stop_reason {
             // TODO: once we have unwinding, panic here instead so we unwind host frames
@@ -839,6 +1581,38 @@ impl Instance {
         res
     }
 
+    // These are used in the expansion of `#[lucet_hostcall]`, and are kept out of rustdoc for the
+    // same reason as `uninterruptable()`.
+    #[doc(hidden)]
+    #[inline]
+    pub fn is_interposing_hostcalls(&self) -> bool {
+        self.hostcall_interposer.is_some()
+    }
+
+    #[doc(hidden)]
+    pub fn interpose_hostcall<R, F: FnOnce() -> R>(
+        &mut self,
+        name: &'static str,
+        args: Vec<Vec<u8>>,
+        hostcall: F,
+    ) -> R {
+        // the interposer is taken while the hostcall runs, so that hostcalls it makes by calling
+        // back into the guest are not interposed
+        match self.hostcall_interposer.take() {
+            None => hostcall(),
+            Some(Interposer::Recording(mut log)) => {
+                let res = replay::record(self, &mut log, name, args, hostcall);
+                self.hostcall_interposer = Some(Interposer::Recording(log));
+                res.unwrap_or_else(|e| std::panic::resume_unwind(e))
+            }
+            Some(Interposer::Replaying(mut records)) => {
+                let res = replay::replay(self, &mut records, name, args);
+                self.hostcall_interposer = Some(Interposer::Replaying(records));
+                res.unwrap_or_else(|details| panic!(details))
+            }
+        }
+    }
+
     #[inline]
     pub fn get_instruction_count(&self) -> Option<u64> {
         if self.module.is_instruction_count_instrumented() {
@@ -855,6 +1629,13 @@ impl Instance {
 
 // Private API
 impl Instance {
+    /// Let the instance's group, if any, know that the instance has a new `KillState`.
+    fn update_group_kill_state(&self) {
+        if let Some(membership) = &self.group_membership {
+            membership.update(&self.kill_state);
+        }
+    }
+
     fn new(alloc: Alloc, module: Arc<dyn Module>, embed_ctx: CtxMap) -> Self {
         let globals_ptr = alloc.slot().globals as *mut i64;
 
@@ -883,10 +1664,28 @@ impl Instance {
             ensure_sigstack_installed: true,
             entrypoint: None,
             resumed_val: None,
+            peak_heap_size: 0,
+            grow_count: 0,
+            cpu_time: Duration::default(),
+            cpu_clock_start: None,
+            cpu_time_accounting: false,
+            stack_poisoned: false,
+            deterministic: false,
+            hostcall_interposer: None,
+            start_policy: StartPolicy::default(),
+            host_panic_policy: HostPanicPolicy::default(),
+            host_panic_payload: None,
+            group_membership: None,
+            quota_charge: None,
+            hostcall_rate_limit: None,
+            start_pending: false,
+            hostcall_count: 0,
+            yield_count: 0,
             _padding: (),
         };
         inst.set_globals_ptr(globals_ptr);
         inst.set_instruction_count(0);
+        inst.peak_heap_size = inst.alloc.heap_len();
 
         assert_eq!(mem::size_of::<Instance>(), HOST_PAGE_SIZE_EXPECTED);
         let unpadded_size = offset_of!(Instance, _padding);
@@ -933,24 +1732,8 @@ impl Instance {
 
     /// Run a function in guest context at the given entrypoint.
     fn run_func(&mut self, func: FunctionHandle, args: &[Val]) -> Result<RunResult, Error> {
-        let needs_start = self.state.is_not_started() && !func.is_start_func;
-        if needs_start {
-            return Err(Error::InstanceNeedsStart);
-        }
-
-        let is_ready = self.state.is_ready();
-        let is_starting = self.state.is_not_started() && func.is_start_func;
-        let is_non_fatally_faulted = self.state.is_faulted() && !self.state.is_fatal();
-        if !(is_ready || is_starting || is_non_fatally_faulted) {
-            return Err(Error::InvalidArgument(
-                "instance must be ready, starting, or non-fatally faulted",
-            ));
-        }
-        if func.ptr.as_usize() == 0 {
-            return Err(Error::InvalidArgument(
-                "entrypoint function cannot be null; this is probably a malformed module",
-            ));
-        }
+        self.start_on_first_run(func)?;
+        self.check_can_run(func)?;
 
         let sig = self.module.get_signature(func.id);
 
@@ -971,11 +1754,69 @@ impl Instance {
             }
         }
 
-        self.entrypoint = Some(func);
-
         let mut args_with_vmctx = vec![Val::from(self.alloc.slot().heap)];
         args_with_vmctx.extend_from_slice(args);
 
+        self.enter_func(func, &args_with_vmctx)
+    }
+
+    /// Run a function whose signature has already been checked against the type of `args`.
+    pub(crate) fn run_typed_func<Params: WasmParams>(
+        &mut self,
+        func: FunctionHandle,
+        args: Params,
+    ) -> Result<RunResult, Error> {
+        self.start_on_first_run(func)?;
+        self.check_can_run(func)?;
+        let vmctx = Val::from(self.alloc.slot().heap);
+        args.with_vals(vmctx, |args_with_vmctx| {
+            self.enter_func(func, args_with_vmctx)
+        })
+    }
+
+    /// Run the start function before `func`, if the start policy calls for it.
+    fn start_on_first_run(&mut self, func: FunctionHandle) -> Result<(), Error> {
+        if self.start_policy == StartPolicy::OnFirstRun
+            && self.state.is_not_started()
+            && !func.is_start_func
+        {
+            self.run_start()?;
+        }
+        Ok(())
+    }
+
+    /// Check that the instance is in a state where `func` can be run.
+    fn check_can_run(&self, func: FunctionHandle) -> Result<(), Error> {
+        let needs_start = self.start_pending && !func.is_start_func;
+        if needs_start {
+            return Err(Error::InstanceNeedsStart);
+        }
+
+        let is_ready = self.state.is_ready();
+        let is_starting = self.state.is_not_started() && func.is_start_func;
+        let is_non_fatally_faulted = self.state.is_faulted() && !self.state.is_fatal();
+        if !(is_ready || is_starting || is_non_fatally_faulted) {
+            return Err(Error::InvalidArgument(
+                "instance must be ready, starting, or non-fatally faulted",
+            ));
+        }
+        if func.ptr.as_usize() == 0 {
+            return Err(Error::InvalidArgument(
+                "entrypoint function cannot be null; this is probably a malformed module",
+            ));
+        }
+        Ok(())
+    }
+
+    /// Switch to the guest to run `func`, whose arguments have been checked and are preceded by
+    /// the vmctx pointer.
+    fn enter_func(
+        &mut self,
+        func: FunctionHandle,
+        args_with_vmctx: &[Val],
+    ) -> Result<RunResult, Error> {
+        self.entrypoint = Some(func);
+
         let self_ptr = self as *mut _;
         Context::init_with_callback(
             unsafe { self.alloc.stack_u64_mut() },
@@ -983,13 +1824,60 @@ impl Instance {
             execution::exit_guest_region,
             self_ptr,
             func.ptr.as_usize(),
-            &args_with_vmctx,
+            args_with_vmctx,
         )?;
 
         self.install_activator();
         self.swap_and_return()
     }
 
+    /// Start accounting thread CPU time to the guest, if the instance accounts for it.
+    ///
+    /// The clock is read anew each time, as the guest may have moved to another thread while it
+    /// was not running.
+    fn start_cpu_clock(&mut self) {
+        if self.cpu_time_accounting {
+            self.cpu_clock_start = Some(thread_cpu_time());
+        }
+    }
+
+    /// Stop accounting thread CPU time to the guest, adding the time since the clock was started.
+    ///
+    /// The clock is already stopped if the guest left through a hostcall that did not return,
+    /// such as one that yielded or terminated the instance.
+    fn stop_cpu_clock(&mut self) {
+        if let Some(start) = self.cpu_clock_start.take() {
+            self.cpu_time += thread_cpu_time().checked_sub(start).unwrap_or_default();
+        }
+    }
+
+    /// Run a function in this instance while another instance on the current thread is suspended
+    /// in a hostcall.
+    ///
+    /// The thread's host context and current instance belong to the suspended instance, so they
+    /// are saved before switching into this instance and restored once it returns, faults, or
+    /// terminates. From the point of view of the hostcall, this behaves like an ordinary function
+    /// call.
+    pub(crate) fn run_func_nested(
+        &mut self,
+        func: FunctionHandle,
+        args: &[Val],
+    ) -> Result<RunResult, Error> {
+        let outer_instance =
+            CURRENT_INSTANCE.with(|current_instance| current_instance.borrow_mut().take());
+        // `Context` is plain data, so a bitwise copy is enough to preserve the outer host context
+        let outer_host_ctx = HOST_CTX.with(|host_ctx| unsafe { ptr::read(host_ctx.get()) });
+
+        let res = self.run_func(func, args);
+
+        HOST_CTX.with(|host_ctx| unsafe { ptr::write(host_ctx.get(), outer_host_ctx) });
+        CURRENT_INSTANCE.with(|current_instance| {
+            *current_instance.borrow_mut() = outer_instance;
+        });
+
+        res
+    }
+
     /// Prepare the guest so that it will update its execution domain upon entry.
     ///
     /// This mutates the context's registers so that an activation function that will be run after
@@ -1040,8 +1928,18 @@ impl Instance {
                 || (self.state.is_faulted() && !self.state.is_fatal())
                 || self.state.is_yielded()
         );
+        // a yielded instance resumes in the hostcall that yielded, which starts the clock again
+        // once it returns to the guest
+        if !self.state.is_yielded() {
+            self.start_cpu_clock();
+        }
         self.state = State::Running;
 
+        let host_pkru = self
+            .alloc
+            .slot()
+            .protection_key
+            .map(|key| key.restrict_access());
         let res = self.with_current_instance(|i| {
             i.with_signals_on(|i| {
                 HOST_CTX.with(|host_ctx| {
@@ -1053,6 +1951,11 @@ impl Instance {
                 })
             })
         });
+        if let Some(pkru) = host_pkru {
+            // the guest may have left through a signal handler, which runs with a different PKRU
+            mpk::write_pkru(pkru);
+        }
+        self.stop_cpu_clock();
 
         #[cfg(feature = "concurrent_testpoints")]
         self.lock_testpoints
@@ -1102,6 +2005,7 @@ impl Instance {
             {
                 self.kill_state = Arc::new(KillState::default());
             }
+            self.update_group_kill_state();
         }
 
         match st {
@@ -1112,9 +2016,19 @@ impl Instance {
             }
             State::Terminating { details, .. } => {
                 self.state = State::Terminated;
-                Err(Error::RuntimeTerminated(details))
+                if let Some(payload) = self.host_panic_payload.take() {
+                    std::panic::resume_unwind(payload);
+                }
+                match details
+                    .provided_details()
+                    .and_then(|d| d.downcast_ref::<GuestExit>())
+                {
+                    Some(GuestExit(status)) => Ok(RunResult::Exited(*status)),
+                    None => Err(Error::RuntimeTerminated(details)),
+                }
             }
             State::Yielding { val, expecting } => {
+                self.yield_count += 1;
                 self.state = State::Yielded { expecting };
                 Ok(RunResult::Yielded(val))
             }
@@ -1131,6 +2045,15 @@ impl Instance {
                 details.rip_addr_details = self
                     .module
                     .addr_details(details.rip_addr as *const c_void)?;
+                details.trap_location = self
+                    .module
+                    .lookup_trap_location(details.rip_addr as *const c_void);
+                if siginfo.si_signo == SIGSEGV || siginfo.si_signo == SIGBUS {
+                    let fault_addr = siginfo.si_addr_ext();
+                    details.fault_addr = Some(fault_addr as uintptr_t);
+                    details.fault_addr_location =
+                        Some(FaultAddrLocation::classify(&self.alloc, fault_addr));
+                }
 
                 // fill the state back in with the updated details in case fatal handlers need it
                 self.state = State::Faulted {
@@ -1211,6 +2134,58 @@ pub struct FaultDetails {
     pub rip_addr: uintptr_t,
     /// Extra information about the instruction pointer's location, if available.
     pub rip_addr_details: Option<module::AddrDetails>,
+    /// The function and offset of the faulting instruction, if it lies in the module's code.
+    pub trap_location: Option<module::TrapLocation>,
+    /// The address whose access caused the fault, if it was a memory fault.
+    pub fault_addr: Option<uintptr_t>,
+    /// Where the address whose access caused the fault lies, if it was a memory fault.
+    ///
+    /// Faults in the guard pages of the instance's heap or stack are the usual result of guest
+    /// bugs; faults anywhere else suggest that runtime or host memory has been corrupted.
+    pub fault_addr_location: Option<FaultAddrLocation>,
+}
+
+/// Where an address that caused a memory fault lies, relative to the faulting instance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultAddrLocation {
+    /// The accessible part of the instance's heap.
+    Heap,
+    /// The part of the instance's heap address space beyond its current size, including the heap
+    /// guard pages.
+    HeapGuard,
+    /// The instance's stack.
+    Stack,
+    /// The guard page below the instance's stack.
+    StackGuard,
+    /// The instance's globals.
+    Globals,
+    /// The instance's signal stack, or the guard page below it.
+    SigStack,
+    /// Memory owned by the runtime outside of the instance's slot, such as the slot of another
+    /// instance, or the code of a module.
+    OutOfSlot,
+    /// Memory not owned by the runtime.
+    Host,
+}
+
+impl FaultAddrLocation {
+    fn classify(alloc: &Alloc, addr: *const c_void) -> Self {
+        match alloc.addr_location(addr) {
+            AddrLocation::Heap => FaultAddrLocation::Heap,
+            AddrLocation::InaccessibleHeap => FaultAddrLocation::HeapGuard,
+            AddrLocation::Stack => FaultAddrLocation::Stack,
+            AddrLocation::StackGuard => FaultAddrLocation::StackGuard,
+            AddrLocation::Globals => FaultAddrLocation::Globals,
+            AddrLocation::SigStack | AddrLocation::SigStackGuard => FaultAddrLocation::SigStack,
+            AddrLocation::Unknown => {
+                if crate::wx::owner_of(addr as usize).is_some() {
+                    FaultAddrLocation::OutOfSlot
+                } else {
+                    FaultAddrLocation::Host
+                }
+            }
+        }
+    }
 }
 
 impl std::fmt::Display for FaultDetails {
@@ -1229,6 +2204,10 @@ impl std::fmt::Display for FaultDetails {
 
         write!(f, "code at address {:p}", self.rip_addr as *const c_void)?;
 
+        if let Some(ref location) = self.trap_location {
+            write!(f, " ({})", location)?;
+        }
+
         if let Some(ref addr_details) = self.rip_addr_details {
             if let Some(ref fname) = addr_details.file_name {
                 let sname = addr_details.sym_name.as_deref().unwrap_or("<unknown>");
@@ -1245,6 +2224,36 @@ impl std::fmt::Display for FaultDetails {
     }
 }
 
+/// Describe an embedder context value for errors, e.g. "`u32` with key `\"count\"`".
+fn describe_ctx<T: Any>(key: Option<&str>) -> String {
+    match key {
+        Some(key) => format!("`{}` with key {:?}", std::any::type_name::<T>(), key),
+        None => format!("`{}`", std::any::type_name::<T>()),
+    }
+}
+
+pub(crate) fn ctx_ref<'a, T: Any>(
+    r: Option<Result<Ref<'a, T>, BorrowError>>,
+    key: Option<&str>,
+) -> Result<Ref<'a, T>, Error> {
+    match r {
+        Some(Ok(r)) => Ok(r),
+        Some(Err(_)) => Err(Error::CtxBorrowed(describe_ctx::<T>(key))),
+        None => Err(Error::CtxNotFound(describe_ctx::<T>(key))),
+    }
+}
+
+pub(crate) fn ctx_ref_mut<'a, T: Any>(
+    r: Option<Result<RefMut<'a, T>, BorrowMutError>>,
+    key: Option<&str>,
+) -> Result<RefMut<'a, T>, Error> {
+    match r {
+        Some(Ok(r)) => Ok(r),
+        Some(Err(_)) => Err(Error::CtxBorrowed(describe_ctx::<T>(key))),
+        None => Err(Error::CtxNotFound(describe_ctx::<T>(key))),
+    }
+}
+
 /// Information about a terminated guest.
 pub enum TerminationDetails {
     /// Returned when a signal handler terminates the instance.
@@ -1265,8 +2274,26 @@ pub enum TerminationDetails {
     Provided(Box<dyn Any + 'static>),
     /// The instance was terminated by its `KillSwitch`.
     Remote,
+    /// Returned when an instance in deterministic mode calls the named hostcall, which is not
+    /// deterministic.
+    Nondeterministic(&'static str),
+    /// Returned when an instance replaying a [`HostcallLog`](../replay/struct.HostcallLog.html)
+    /// makes a hostcall that does not match the log.
+    ReplayDiverged(String),
+    /// Returned when a hostcall panics and the instance's
+    /// [`HostPanicPolicy`](enum.HostPanicPolicy.html) is `TerminateInstance`, with the panic
+    /// message.
+    HostPanic(String),
+    /// Returned when an instance makes more hostcalls in a second than its
+    /// [rate limit](../region/struct.InstanceBuilder.html#method.with_hostcall_rate_limit)
+    /// allows, with the name of the hostcall over the limit.
+    HostcallRateExceeded(&'static str),
 }
 
+/// The details an instance is terminated with by `Vmctx::exit()`, which are reported as
+/// `RunResult::Exited` rather than as a termination.
+pub(crate) struct GuestExit(pub(crate) i32);
+
 impl TerminationDetails {
     pub fn provide<A: Any + 'static>(details: A) -> Self {
         TerminationDetails::Provided(Box::new(details))
@@ -1299,6 +2326,10 @@ impl PartialEq for TerminationDetails {
             (Signal, Signal) => true,
             (BorrowError(msg1), BorrowError(msg2)) => msg1 == msg2,
             (CtxNotFound, CtxNotFound) => true,
+            (Nondeterministic(name1), Nondeterministic(name2)) => name1 == name2,
+            (ReplayDiverged(msg1), ReplayDiverged(msg2)) => msg1 == msg2,
+            (HostPanic(msg1), HostPanic(msg2)) => msg1 == msg2,
+            (HostcallRateExceeded(name1), HostcallRateExceeded(name2)) => name1 == name2,
             // can't compare `Any`
             _ => false,
         }
@@ -1315,6 +2346,12 @@ impl std::fmt::Debug for TerminationDetails {
             TerminationDetails::YieldTypeMismatch => write!(f, "YieldTypeMismatch"),
             TerminationDetails::Provided(_) => write!(f, "Provided(Any)"),
             TerminationDetails::Remote => write!(f, "Remote"),
+            TerminationDetails::Nondeterministic(name) => write!(f, "Nondeterministic({})", name),
+            TerminationDetails::ReplayDiverged(msg) => write!(f, "ReplayDiverged({})", msg),
+            TerminationDetails::HostPanic(msg) => write!(f, "HostPanic({})", msg),
+            TerminationDetails::HostcallRateExceeded(name) => {
+                write!(f, "HostcallRateExceeded({})", name)
+            }
         }
     }
 }
@@ -1379,3 +2416,14 @@ pub(crate) struct EmptyYieldVal;
 fn default_fatal_handler(inst: &Instance) -> ! {
     panic!("> instance {:p} had fatal error: {}", inst, inst.state);
 }
+
+/// The CPU time used by the current thread.
+fn thread_cpu_time() -> Duration {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // this cannot fail for a valid clock and pointer
+    unsafe { libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut ts) };
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
@@ -459,6 +459,12 @@ impl KillState {
         res
     }
 
+    /// Check whether a `KillSwitch` has terminated the instance while it executes a hostcall, which
+    /// takes effect when the hostcall returns.
+    pub fn terminated_in_hostcall(&self) -> bool {
+        *self.execution_domain.lock().unwrap() == Domain::Terminated
+    }
+
     pub fn schedule(&self, tid: pthread_t) {
         *self.thread_id.lock().unwrap() = Some(tid);
         self.tid_change_notifier.notify_all();
@@ -0,0 +1,129 @@
+//! Groups of instances that are terminated together.
+//!
+//! A [`KillSwitch`] only terminates the execution of its instance that was current when the
+//! switch was created, and is invalidated when that execution finishes. An [`InstanceGroup`]
+//! instead tracks the current `KillState` of each of its members, so that
+//! [`InstanceGroup::terminate()`] stops every member wherever it is: members running guest code
+//! are signalled, members in hostcalls terminate when their hostcall returns, and members that
+//! are not running are cancelled when they next run.
+//!
+//! [`KillSwitch`]: struct.KillSwitch.html
+//! [`InstanceGroup`]: struct.InstanceGroup.html
+//! [`InstanceGroup::terminate()`]: struct.InstanceGroup.html#method.terminate
+
+use crate::instance::execution::{KillState, KillSwitch};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// A handle to a group of instances, such as all of the instances serving one request or tenant,
+/// that are terminated together.
+///
+/// Instances join a group when they are built, with
+/// [`InstanceBuilder::with_group()`](../region/struct.InstanceBuilder.html#method.with_group),
+/// and leave it when they are dropped. The handle can be cloned and sent to other threads; all
+/// clones refer to the same group.
+#[derive(Clone, Default)]
+pub struct InstanceGroup {
+    inner: Arc<GroupInner>,
+}
+
+#[derive(Default)]
+struct GroupInner {
+    terminated: AtomicBool,
+    next_id: AtomicUsize,
+    /// The current `KillState` of each member, by member id.
+    members: Mutex<HashMap<usize, Weak<KillState>>>,
+}
+
+impl InstanceGroup {
+    /// Create an empty group.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Terminate every member of the group, returning the number of members whose termination
+    /// this call initiated.
+    ///
+    /// Termination is permanent: members that are not running when the group is terminated, or
+    /// that are reset afterwards, are cancelled when they next run, and so are instances that join
+    /// the group later. Members that were already terminated by their own `KillSwitch` are not
+    /// counted.
+    pub fn terminate(&self) -> usize {
+        // set the flag before visiting the members, so that a member replacing its `KillState`
+        // concurrently either is visited here or sees the flag; see `GroupMembership::update()`
+        self.inner.terminated.store(true, Ordering::SeqCst);
+        let members = self.inner.members.lock().unwrap();
+        members
+            .values()
+            .filter(|state| KillSwitch::new(Weak::clone(state)).terminate().is_ok())
+            .count()
+    }
+
+    /// Check whether the group has been terminated.
+    pub fn is_terminated(&self) -> bool {
+        self.inner.terminated.load(Ordering::SeqCst)
+    }
+
+    /// The number of instances in the group.
+    pub fn len(&self) -> usize {
+        self.inner.members.lock().unwrap().len()
+    }
+
+    /// Check whether the group has no instances.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub(crate) fn join(&self, kill_state: &Arc<KillState>) -> GroupMembership {
+        let membership = GroupMembership {
+            group: self.clone(),
+            id: self.inner.next_id.fetch_add(1, Ordering::SeqCst),
+        };
+        membership.update(kill_state);
+        membership
+    }
+}
+
+impl std::fmt::Debug for InstanceGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstanceGroup")
+            .field("terminated", &self.is_terminated())
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// An instance's membership in an `InstanceGroup`, which ends when it is dropped.
+pub(crate) struct GroupMembership {
+    group: InstanceGroup,
+    id: usize,
+}
+
+impl GroupMembership {
+    pub(crate) fn group(&self) -> &InstanceGroup {
+        &self.group
+    }
+
+    /// Record the instance's new `KillState`, terminating it if the group has been terminated.
+    pub(crate) fn update(&self, kill_state: &Arc<KillState>) {
+        let state = Arc::downgrade(kill_state);
+        self.group
+            .inner
+            .members
+            .lock()
+            .unwrap()
+            .insert(self.id, Weak::clone(&state));
+        if self.group.is_terminated() {
+            // the result does not matter: if the group's `terminate()` got to the new state
+            // first, it is already cancelled
+            let _ = KillSwitch::new(state).terminate();
+        }
+    }
+}
+
+impl Drop for GroupMembership {
+    fn drop(&mut self) {
+        self.group.inner.members.lock().unwrap().remove(&self.id);
+    }
+}
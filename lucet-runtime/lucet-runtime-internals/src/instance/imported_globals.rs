@@ -0,0 +1,81 @@
+//! Values for the globals a module imports.
+//!
+//! Toolchains commonly emit modules that import globals such as `__stack_pointer` or
+//! `__memory_base`. `lucetc` gives each imported global a slot in the instance's globals, like
+//! any other global, and the runtime fills the slot when the instance is created or reset with
+//! the value supplied by
+//! [`InstanceBuilder::with_global_import()`](../region/struct.InstanceBuilder.html#method.with_global_import).
+
+use crate::error::Error;
+use crate::module::{GlobalValue, ValueType};
+use crate::val::{Val, WasmValue};
+use std::collections::HashMap;
+
+/// The values supplied for the imported globals of an instance.
+///
+/// Instances built with imported globals carry this value in their embedder context.
+#[derive(Clone, Debug, Default)]
+pub struct ImportedGlobals {
+    values: HashMap<(String, String), Val>,
+}
+
+impl ImportedGlobals {
+    /// Get the value supplied for the imported global `module::field`.
+    pub fn get(&self, module: &str, field: &str) -> Option<Val> {
+        self.values
+            .get(&(module.to_owned(), field.to_owned()))
+            .copied()
+    }
+
+    pub(crate) fn insert(&mut self, module: String, field: String, val: Val) {
+        self.values.insert((module, field), val);
+    }
+
+    /// The initial value of the imported global `module::field`, whose type is `import_type` if
+    /// the module records it.
+    ///
+    /// Returns `Error::LinkError` if no value was supplied for it, or if the value is of another
+    /// type.
+    pub(crate) fn init_val(
+        &self,
+        module: &str,
+        field: &str,
+        import_type: Option<ValueType>,
+    ) -> Result<GlobalValue, Error> {
+        let val = self
+            .get(module, field)
+            .ok_or_else(|| unsupplied(module, field))?;
+        if let Some(import_type) = import_type {
+            if val.value_type() != import_type {
+                return Err(Error::LinkError(format!(
+                    "imported global `{}::{}` is of type {}, but was supplied a value of type {}",
+                    module,
+                    field,
+                    import_type,
+                    val.value_type()
+                )));
+            }
+        }
+        Ok(match val.value_type() {
+            ValueType::I32 => GlobalValue {
+                i_32: i32::from_val(&val),
+            },
+            ValueType::I64 => GlobalValue {
+                i_64: i64::from_val(&val),
+            },
+            ValueType::F32 => GlobalValue {
+                f_32: f32::from_val(&val),
+            },
+            ValueType::F64 => GlobalValue {
+                f_64: f64::from_val(&val),
+            },
+        })
+    }
+}
+
+pub(crate) fn unsupplied(module: &str, field: &str) -> Error {
+    Error::LinkError(format!(
+        "no value supplied for imported global `{}::{}`",
+        module, field
+    ))
+}
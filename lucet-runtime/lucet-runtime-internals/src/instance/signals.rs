@@ -315,6 +315,9 @@ extern "C" fn handle_signal(signum: c_int, siginfo_ptr: *mut siginfo_t, ucontext
                             // Details set to `None` here: have to wait until `verify_trap_safety` to
                             // fill in these details, because access may not be signal safe.
                             rip_addr_details: None,
+                            trap_location: None,
+                            fault_addr: None,
+                            fault_addr_location: None,
                         },
                         siginfo,
                         context: ctx.into(),
@@ -0,0 +1,62 @@
+use crate::error::Error;
+use crate::instance::Instance;
+use crate::module::{FunctionHandle, Module, ModuleInternal};
+use crate::val::{WasmParams, WasmRet};
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+/// A handle to an exported function whose signature has been checked against `Params` and `Ret`.
+///
+/// Typed handles are created with
+/// [`Instance::get_typed_func()`](struct.Instance.html#method.get_typed_func). Calling them checks
+/// neither the arguments nor the signature again, and passes the arguments without building a
+/// vector of `Val`s.
+pub struct TypedFunc<Params, Ret> {
+    module: Arc<dyn Module>,
+    func: FunctionHandle,
+    _params_ret: PhantomData<fn(Params) -> Ret>,
+}
+
+impl<Params, Ret> Clone for TypedFunc<Params, Ret> {
+    fn clone(&self) -> Self {
+        TypedFunc {
+            module: self.module.clone(),
+            func: self.func,
+            _params_ret: PhantomData,
+        }
+    }
+}
+
+impl<Params: WasmParams, Ret: WasmRet> TypedFunc<Params, Ret> {
+    pub(crate) fn new(module: Arc<dyn Module>, func: FunctionHandle) -> Result<Self, Error> {
+        let sig = module.get_signature(func.id);
+        if sig.params != Params::value_types() || sig.ret_ty != Ret::RET_TY {
+            return Err(Error::InvalidArgument(
+                "typed function signature does not match the exported function",
+            ));
+        }
+        Ok(TypedFunc {
+            module,
+            func,
+            _params_ret: PhantomData,
+        })
+    }
+
+    /// Run the function in `instance`, which must be an instance of the module the handle was
+    /// created from.
+    ///
+    /// Faults and terminations are returned as errors just like
+    /// [`Instance::run()`](struct.Instance.html#method.run). If the function yields, the instance
+    /// is left suspended and this returns `Error::InstanceNotReturned`.
+    pub fn call(&self, instance: &mut Instance, args: Params) -> Result<Ret, Error> {
+        let same_module = &*instance.module as *const dyn Module as *const u8
+            == &*self.module as *const dyn Module as *const u8;
+        if !same_module {
+            return Err(Error::InvalidArgument(
+                "typed function used with an instance of a different module",
+            ));
+        }
+        let retval = instance.run_typed_func(self.func, args)?.returned()?;
+        Ok(Ret::from_retval(retval))
+    }
+}
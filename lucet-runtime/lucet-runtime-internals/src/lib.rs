@@ -16,16 +16,24 @@ pub mod test_helpers;
 
 pub mod alloc;
 pub mod c_api;
+pub mod config;
 pub mod context;
 pub mod embed_ctx;
 pub mod instance;
+pub mod linker;
 #[cfg(feature = "concurrent_testpoints")]
 pub mod lock_testpoints;
+pub mod memory;
 pub mod module;
+pub mod quota;
 pub mod region;
+pub mod replay;
+#[cfg(all(target_os = "linux", target_arch = "x86_64", feature = "seccomp"))]
+pub mod seccomp;
 pub mod sysdeps;
 pub mod val;
 pub mod vmctx;
+pub mod wx;
 
 /// The size of a page in WebAssembly heaps.
 pub const WASM_PAGE_SIZE: u32 = 64 * 1024;
@@ -0,0 +1,448 @@
+//! Linking the imports of an instance to host functions and to the exports of other instances.
+//!
+//! Lucet modules call their imported functions through native symbols that are resolved when the
+//! module's shared object is loaded. A [`Linker`](struct.Linker.html) adds a layer on top of that
+//! mechanism so that guests can be split into separately compiled modules that call each other:
+//! when an instance is built with
+//! [`InstanceBuilder::with_linker()`](../region/struct.InstanceBuilder.html#method.with_linker),
+//! its imports are bound to the exports of other instances, and their names and signatures are
+//! checked up front.
+//!
+//! The native symbol for each linked import is then a hostcall that forwards the call to the
+//! linked instance with
+//! [`Vmctx::call_linked_import()`](../vmctx/struct.Vmctx.html#method.call_linked_import):
+//!
+//! ```no_run
+//! use lucet_runtime_macros::lucet_hostcall;
+//! use lucet_runtime_internals::lucet_hostcall_terminate;
+//! use lucet_runtime_internals::vmctx::Vmctx;
+//!
+//! #[lucet_hostcall]
+//! #[no_mangle]
+//! pub fn math_add(vmctx: &Vmctx, x: u64, y: u64) -> u64 {
+//!     match vmctx.call_linked_import("math", "add", &[x.into(), y.into()]) {
+//!         Ok(retval) => retval.into(),
+//!         Err(e) => lucet_hostcall_terminate!(format!("linked call failed: {}", e)),
+//!     }
+//! }
+//! ```
+//!
+//! Imports can also be bound directly to Rust closures with
+//! [`Linker::func()`](struct.Linker.html#method.func), in which case no symbol needs to exist for
+//! them at all. The runtime rewrites the module's entries for those imports to point at
+//! trampolines that dispatch to the closure registered for the running instance. Modules that rely
+//! on this must be loaded with
+//! [`DlModule::load_with_lazy_imports()`](../module/struct.DlModule.html#method.load_with_lazy_imports),
+//! as their imports are not defined when the shared object is loaded:
+//!
+//! ```no_run
+//! use lucet_runtime_internals::alloc::Limits;
+//! use lucet_runtime_internals::linker::Linker;
+//! use lucet_runtime_internals::module::DlModule;
+//! use lucet_runtime_internals::region::{mmap::MmapRegion, Region, RegionCreate};
+//! use lucet_runtime_internals::vmctx::Vmctx;
+//!
+//! let module = DlModule::load_with_lazy_imports("/my/lucet/module.so").unwrap();
+//! let region = MmapRegion::create(1, &Limits::default()).unwrap();
+//!
+//! let mut linker = Linker::new();
+//! linker.func("env", "add", |_vmctx: &Vmctx, x: u64, y: u64| x + y);
+//!
+//! let inst = region
+//!     .new_instance_builder(module)
+//!     .with_linker(&linker)
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Imports used as elements of a table are resolved when the shared object is loaded, so they
+//! cannot be bound to host functions.
+//!
+//! Imports that a linker does not resolve are left to the dynamic linker. Loading a module, or,
+//! for lazily-bound imports, creating an instance, fails with `DlError::UnsatisfiedImport` naming
+//! the first import that has no symbol. To find every import an embedder fails to provide before
+//! creating any instances, declare the imports served by native hostcalls with
+//! [`Linker::hostcall()`](struct.Linker.html#method.hostcall) and call
+//! [`Linker::check()`](struct.Linker.html#method.check):
+//!
+//! ```no_run
+//! use lucet_runtime_internals::linker::Linker;
+//! use lucet_runtime_internals::module::DlModule;
+//! use lucet_runtime_internals::vmctx::Vmctx;
+//!
+//! let module = DlModule::load_with_lazy_imports("/my/lucet/module.so").unwrap();
+//!
+//! let mut linker = Linker::new();
+//! linker.func("env", "add", |_vmctx: &Vmctx, x: u64, y: u64| x + y);
+//! // provided by a `#[lucet_hostcall]` function `hostcall_log` in the executable
+//! linker.hostcall("env", "log", "hostcall_log").unwrap();
+//!
+//! for import in linker.unresolved_imports(module.as_ref()) {
+//!     eprintln!("unresolved import: {}", import);
+//! }
+//! ```
+
+mod host_func;
+
+pub(crate) use host_func::HostFuncTrampolines;
+pub use host_func::{HostFunc, IntoHostFunc};
+
+use crate::error::Error;
+use crate::instance::{InstanceHandle, InstanceInternal};
+use crate::module::{
+    FunctionHandle, FunctionIndex, Global, ImportFunction, Module, ModuleInternal, Signature,
+};
+use crate::val::{UntypedRetVal, Val};
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// An instance that can be shared between a `Linker` and the instances linked against it.
+pub type SharedInstance = Arc<Mutex<InstanceHandle>>;
+
+/// A set of host functions and named instances that can satisfy the imports of other instances.
+#[derive(Clone, Default)]
+pub struct Linker {
+    instances: HashMap<String, SharedInstance>,
+    funcs: HashMap<(String, String), (Signature, HostFunc, bool)>,
+    hostcalls: HashSet<(String, String)>,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Make the exports of `instance` available to importers under the module name `module_name`.
+    ///
+    /// If an instance was already registered under that name, it is replaced.
+    pub fn instance(&mut self, module_name: &str, instance: SharedInstance) -> &mut Self {
+        self.instances.insert(module_name.to_owned(), instance);
+        self
+    }
+
+    /// Define the import `module_name::field` as a host function.
+    ///
+    /// The closure takes a `&Vmctx` followed by the arguments of the import, and its Wasm
+    /// signature is derived from the argument and return types; see
+    /// [`IntoHostFunc`](trait.IntoHostFunc.html). If a host function was already defined for the
+    /// import, it is replaced. Host functions take precedence over instances registered under the
+    /// same module name.
+    ///
+    /// The closure is called like a hostcall: it may terminate the instance with
+    /// `lucet_hostcall_terminate!`, and must not hold borrows of the heap across calls that yield.
+    /// Like hostcalls not declared `deterministic`, it terminates instances running with
+    /// deterministic execution instead of being called; see
+    /// [`deterministic_func()`](#method.deterministic_func).
+    pub fn func<Params, Ret, F>(&mut self, module_name: &str, field: &str, func: F) -> &mut Self
+    where
+        F: IntoHostFunc<Params, Ret>,
+    {
+        self.insert_func(module_name, field, func, false)
+    }
+
+    /// Define the import `module_name::field` as a host function, like
+    /// [`func()`](#method.func), that is also called by instances running with
+    /// [deterministic execution](../region/struct.InstanceBuilder.html#method.with_deterministic_execution).
+    ///
+    /// The closure must give the same results for the same arguments and instance state on every
+    /// run, for example by not reading clocks, randomness, or anything outside the instance.
+    pub fn deterministic_func<Params, Ret, F>(
+        &mut self,
+        module_name: &str,
+        field: &str,
+        func: F,
+    ) -> &mut Self
+    where
+        F: IntoHostFunc<Params, Ret>,
+    {
+        self.insert_func(module_name, field, func, true)
+    }
+
+    fn insert_func<Params, Ret, F>(
+        &mut self,
+        module_name: &str,
+        field: &str,
+        func: F,
+        deterministic: bool,
+    ) -> &mut Self
+    where
+        F: IntoHostFunc<Params, Ret>,
+    {
+        let (sig, func) = func.into_host_func();
+        self.funcs.insert(
+            (module_name.to_owned(), field.to_owned()),
+            (sig, func, deterministic),
+        );
+        self
+    }
+
+    /// Declare that the import `module_name::field` is provided by the native hostcall `symbol`,
+    /// such as a `#[lucet_hostcall]` function exported by the executable.
+    ///
+    /// This does not change how the import is resolved; it only stops
+    /// [`unresolved_imports()`](#method.unresolved_imports) from reporting it. The symbol is looked
+    /// up among those visible to the executable, and `Error::SymbolNotFound` is returned if it is
+    /// not defined. The hostcall's signature cannot be checked.
+    pub fn hostcall(
+        &mut self,
+        module_name: &str,
+        field: &str,
+        symbol: &str,
+    ) -> Result<&mut Self, Error> {
+        let c_symbol = CString::new(symbol)
+            .map_err(|_| Error::InvalidArgument("hostcall symbols cannot contain nul bytes"))?;
+        if unsafe { libc::dlsym(libc::RTLD_DEFAULT, c_symbol.as_ptr()) }.is_null() {
+            return Err(Error::SymbolNotFound(symbol.to_owned()));
+        }
+        self.hostcalls
+            .insert((module_name.to_owned(), field.to_owned()));
+        Ok(self)
+    }
+
+    /// Find every import of `module` that neither the registered host functions and instances,
+    /// nor the declared hostcalls, provide.
+    ///
+    /// Function imports are unresolved if nothing provides them, or if the host function or export
+    /// that does has a different signature. Global imports are always unresolved, as their values
+    /// are supplied to each instance with
+    /// [`InstanceBuilder::with_global_import()`](../region/struct.InstanceBuilder.html#method.with_global_import)
+    /// rather than by a linker. Lucet modules cannot import memories or tables, so there are none
+    /// to check.
+    pub fn unresolved_imports(&self, module: &dyn Module) -> Vec<UnresolvedImport> {
+        let mut unresolved = vec![];
+        for import in module.import_functions() {
+            if let Err(reason) = self.check_func_import(module, import) {
+                unresolved.push(UnresolvedImport {
+                    module: import.module.to_owned(),
+                    field: import.name.to_owned(),
+                    kind: ImportKind::Function,
+                    reason,
+                });
+            }
+        }
+        for global in module.globals() {
+            if let Global::Import { module, field } = global.global() {
+                unresolved.push(UnresolvedImport {
+                    module: (*module).to_owned(),
+                    field: (*field).to_owned(),
+                    kind: ImportKind::Global,
+                    reason: "global imports are supplied per instance, not by a linker".to_owned(),
+                });
+            }
+        }
+        unresolved
+    }
+
+    /// Check that every import of `module` is provided, returning `Error::LinkError` listing the
+    /// unresolved ones otherwise.
+    ///
+    /// See [`unresolved_imports()`](#method.unresolved_imports).
+    pub fn check(&self, module: &dyn Module) -> Result<(), Error> {
+        let unresolved = self.unresolved_imports(module);
+        if unresolved.is_empty() {
+            Ok(())
+        } else {
+            let unresolved = unresolved
+                .iter()
+                .map(|import| import.to_string())
+                .collect::<Vec<_>>();
+            Err(Error::LinkError(format!(
+                "unresolved imports: {}",
+                unresolved.join("; ")
+            )))
+        }
+    }
+
+    fn check_func_import(
+        &self,
+        module: &dyn Module,
+        import: &ImportFunction<'_>,
+    ) -> Result<(), String> {
+        let key = (import.module.to_owned(), import.name.to_owned());
+        let import_sig = module.get_signature(import.fn_idx);
+        if let Some((sig, _, _)) = self.funcs.get(&key) {
+            if import_sig != sig {
+                return Err(format!(
+                    "imported as {}, but the host function is {}",
+                    import_sig, sig
+                ));
+            }
+            return Ok(());
+        }
+        if self.hostcalls.contains(&key) {
+            return Ok(());
+        }
+        let instance = self
+            .instances
+            .get(import.module)
+            .ok_or_else(|| "no host function, hostcall, or instance provides it".to_owned())?;
+        let inst = instance
+            .lock()
+            .map_err(|_| format!("the instance linked as `{}` is poisoned", import.module))?;
+        let func = inst.module().get_export_func(import.name).map_err(|_| {
+            format!(
+                "the instance linked as `{}` does not export it",
+                import.module
+            )
+        })?;
+        let export_sig = inst.module().get_signature(func.id);
+        if import_sig != export_sig {
+            return Err(format!(
+                "imported as {}, but exported as {}",
+                import_sig, export_sig
+            ));
+        }
+        Ok(())
+    }
+
+    /// Resolve the imports of `module` against the registered host functions and instances.
+    ///
+    /// Only imports that have a host function, or that come from module names with a registered
+    /// instance, are resolved; the others are left to native symbol resolution as usual. Resolved
+    /// imports must have signatures identical to the host function or export, otherwise this
+    /// returns `Error::LinkError`.
+    ///
+    /// Binding an import to a host function redirects calls to it for every instance of `module`,
+    /// so instances of the same module should be built with linkers that define the same host
+    /// functions.
+    pub fn resolve(&self, module: &dyn Module) -> Result<LinkedImports, Error> {
+        let mut imports = HashMap::new();
+        let mut host_funcs = HashMap::new();
+        for import in module.import_functions() {
+            let import_sig = module.get_signature(import.fn_idx);
+            if let Some((sig, func, deterministic)) = self
+                .funcs
+                .get(&(import.module.to_owned(), import.name.to_owned()))
+            {
+                if import_sig != sig {
+                    return Err(Error::LinkError(format!(
+                        "signature mismatch for `{}::{}`: imported as {}, defined as {}",
+                        import.module, import.name, import_sig, sig
+                    )));
+                }
+                module.bind_host_func_import(import.fn_idx)?;
+                host_funcs.insert(import.fn_idx, (func.clone(), *deterministic));
+                continue;
+            }
+            let instance = match self.instances.get(import.module) {
+                Some(instance) => instance,
+                None => continue,
+            };
+            let inst = instance.lock().map_err(|_| {
+                Error::LinkError(format!(
+                    "instance linked as `{}` is poisoned",
+                    import.module
+                ))
+            })?;
+            let func = inst.module().get_export_func(import.name).map_err(|_| {
+                Error::LinkError(format!(
+                    "`{}` does not export `{}`",
+                    import.module, import.name
+                ))
+            })?;
+            let export_sig = inst.module().get_signature(func.id);
+            if import_sig != export_sig {
+                return Err(Error::LinkError(format!(
+                    "signature mismatch for `{}::{}`: imported as {}, exported as {}",
+                    import.module, import.name, import_sig, export_sig
+                )));
+            }
+            imports.insert(
+                (import.module.to_owned(), import.name.to_owned()),
+                LinkedImport {
+                    instance: instance.clone(),
+                    func,
+                },
+            );
+        }
+        Ok(LinkedImports {
+            imports,
+            host_funcs,
+        })
+    }
+}
+
+/// An import that a `Linker` does not provide, as reported by
+/// [`Linker::unresolved_imports()`](struct.Linker.html#method.unresolved_imports).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnresolvedImport {
+    /// The module name of the import.
+    pub module: String,
+    /// The field name of the import.
+    pub field: String,
+    /// What kind of item is imported.
+    pub kind: ImportKind,
+    /// Why the import is unresolved.
+    pub reason: String,
+}
+
+impl fmt::Display for UnresolvedImport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            ImportKind::Function => "function",
+            ImportKind::Global => "global",
+        };
+        write!(
+            f,
+            "{} `{}::{}`: {}",
+            kind, self.module, self.field, self.reason
+        )
+    }
+}
+
+/// The kinds of items a module can import.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImportKind {
+    Function,
+    Global,
+}
+
+/// The imports of an instance that were bound by a `Linker`.
+///
+/// Instances built with a linker carry this value in their embedder context.
+pub struct LinkedImports {
+    imports: HashMap<(String, String), LinkedImport>,
+    host_funcs: HashMap<FunctionIndex, (HostFunc, bool)>,
+}
+
+struct LinkedImport {
+    instance: SharedInstance,
+    func: FunctionHandle,
+}
+
+impl LinkedImports {
+    /// Returns `true` if the import `module::field` was bound to another instance.
+    pub fn contains(&self, module: &str, field: &str) -> bool {
+        self.imports
+            .contains_key(&(module.to_owned(), field.to_owned()))
+    }
+
+    /// Get the host function bound to an imported function, and whether it was defined as
+    /// deterministic.
+    pub(crate) fn host_func(&self, fn_idx: FunctionIndex) -> Option<(HostFunc, bool)> {
+        self.host_funcs.get(&fn_idx).cloned()
+    }
+
+    /// Run the export bound to `module::field` on its instance.
+    ///
+    /// This must only be called from a hostcall, as the linked instance is run nested inside the
+    /// currently-running one.
+    pub(crate) fn call(
+        &self,
+        module: &str,
+        field: &str,
+        args: &[Val],
+    ) -> Result<UntypedRetVal, Error> {
+        let import = self
+            .imports
+            .get(&(module.to_owned(), field.to_owned()))
+            .ok_or_else(|| Error::SymbolNotFound(format!("{}::{}", module, field)))?;
+        // never block here: the linked instance may be the one making this call
+        let mut inst = import.instance.try_lock().map_err(|_| {
+            Error::LinkError(format!("instance linked as `{}` is already in use", module))
+        })?;
+        inst.run_func_nested(import.func, args)?.returned()
+    }
+}
@@ -0,0 +1,293 @@
+//! Runtime-provided trampolines that let guests call host functions registered with a `Linker`.
+//!
+//! Calls to an imported function go through the module's global offset table. To bind an import
+//! to a host function, the module points that entry at a trampoline from `HostFuncTrampolines`;
+//! the trampoline records which import was called and jumps to `lucet_host_func_dispatch` (see
+//! `host_func_asm.S`), which spills the argument registers and calls `lucet_host_func_call`. That
+//! function finds the closure in the running instance's `LinkedImports`, decodes the arguments
+//! according to the import's signature, and runs it like a hostcall.
+
+use crate::error::Error;
+use crate::instance::InstanceInternal;
+use crate::instance::TerminationDetails;
+use crate::linker::LinkedImports;
+use crate::module::{FunctionIndex, ModuleInternal, Signature, ValueType};
+use crate::val::{
+    UntypedRetVal, UntypedRetValInternal, Val, WasmRet, WasmValue, __m128_as_f32, __m128_as_f64,
+};
+use crate::vmctx::{lucet_vmctx, Vmctx, VmctxInternal};
+use crate::wx;
+use libc::c_void;
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+use std::arch::x86_64::__m128;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::Arc;
+
+/// A host function in untyped form, as stored by a `Linker`.
+///
+/// The closure receives the arguments the guest passed, which match the import's signature, and
+/// returns a value of the import's return type.
+pub type HostFunc = Arc<dyn Fn(&Vmctx, &[Val]) -> UntypedRetVal + Send + Sync + 'static>;
+
+/// Closures that can be registered as host functions with
+/// [`Linker::func()`](struct.Linker.html#method.func).
+///
+/// This is implemented for closures taking a `&Vmctx` followed by up to eight `WasmValue`
+/// arguments, and returning either `()` or a `WasmValue`. The Wasm signature of the host function
+/// is derived from those types.
+pub trait IntoHostFunc<Params, Ret> {
+    fn into_host_func(self) -> (Signature, HostFunc);
+}
+
+macro_rules! impl_into_host_func {
+    ( $( $param:ident ),* ) => {
+        impl<F, Ret, $( $param ),*> IntoHostFunc<( $( $param, )* ), Ret> for F
+        where
+            F: Fn(&Vmctx, $( $param ),*) -> Ret + Send + Sync + 'static,
+            Ret: WasmRet,
+            $( $param: WasmValue, )*
+        {
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn into_host_func(self) -> (Signature, HostFunc) {
+                let sig = Signature {
+                    params: vec![$( <$param as WasmValue>::VALUE_TYPE ),*],
+                    ret_ty: Ret::RET_TY,
+                };
+                let func: HostFunc = Arc::new(move |vmctx: &Vmctx, args: &[Val]| {
+                    let mut args = args.iter();
+                    $(
+                        let $param = <$param as WasmValue>::from_val(
+                            args.next().expect("arguments match the host function signature"),
+                        );
+                    )*
+                    self(vmctx, $( $param ),*).into_retval()
+                });
+                (sig, func)
+            }
+        }
+    };
+}
+
+impl_into_host_func!();
+impl_into_host_func!(A1);
+impl_into_host_func!(A1, A2);
+impl_into_host_func!(A1, A2, A3);
+impl_into_host_func!(A1, A2, A3, A4);
+impl_into_host_func!(A1, A2, A3, A4, A5);
+impl_into_host_func!(A1, A2, A3, A4, A5, A6);
+impl_into_host_func!(A1, A2, A3, A4, A5, A6, A7);
+impl_into_host_func!(A1, A2, A3, A4, A5, A6, A7, A8);
+
+extern "C" {
+    fn lucet_host_func_dispatch();
+}
+
+/// The argument registers spilled by `lucet_host_func_dispatch`.
+#[repr(C)]
+struct HostFuncArgs {
+    gp: [u64; 6],
+    fp: [__m128; 8],
+}
+
+/// Each trampoline is `movabs $fn_idx, %r11; movabs $lucet_host_func_dispatch, %rax; jmp *%rax`,
+/// padded to a multiple of 16 bytes.
+const TRAMPOLINE_SIZE: usize = 32;
+
+/// Executable stubs for the imported functions of a module.
+pub(crate) struct HostFuncTrampolines {
+    mem: *mut c_void,
+    len: usize,
+    fn_indices: Vec<FunctionIndex>,
+}
+
+// the memory is immutable once the trampolines are written
+unsafe impl Send for HostFuncTrampolines {}
+unsafe impl Sync for HostFuncTrampolines {}
+
+impl HostFuncTrampolines {
+    /// Write a trampoline for each of the given imported functions.
+    pub(crate) fn new(fn_indices: Vec<FunctionIndex>) -> Result<Self, Error> {
+        let page_size = host_page_size();
+        let size = fn_indices.len().max(1) * TRAMPOLINE_SIZE;
+        let len = (size + page_size - 1) / page_size * page_size;
+        let mem = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                MapFlags::MAP_PRIVATE | MapFlags::MAP_ANON,
+                -1,
+                0,
+            )?
+        };
+
+        let dispatch = lucet_host_func_dispatch as usize as u64;
+        for (i, fn_idx) in fn_indices.iter().enumerate() {
+            let mut code = [0u8; TRAMPOLINE_SIZE];
+            code[0..2].copy_from_slice(&[0x49, 0xbb]);
+            code[2..10].copy_from_slice(&(fn_idx.as_u32() as u64).to_le_bytes());
+            code[10..12].copy_from_slice(&[0x48, 0xb8]);
+            code[12..20].copy_from_slice(&dispatch.to_le_bytes());
+            code[20..22].copy_from_slice(&[0xff, 0xe0]);
+            // fill the rest with `int3`
+            for b in code[22..].iter_mut() {
+                *b = 0xcc;
+            }
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    code.as_ptr(),
+                    (mem as *mut u8).add(i * TRAMPOLINE_SIZE),
+                    TRAMPOLINE_SIZE,
+                );
+            }
+        }
+
+        let trampolines = HostFuncTrampolines {
+            mem,
+            len,
+            fn_indices,
+        };
+        nix::errno::Errno::result(unsafe {
+            libc::mprotect(
+                mem,
+                len,
+                (ProtFlags::PROT_READ | ProtFlags::PROT_EXEC).bits(),
+            )
+        })?;
+        wx::register(mem as usize, len, "host function trampolines");
+        Ok(trampolines)
+    }
+
+    /// Get the trampoline for an imported function, if it has one.
+    pub(crate) fn get(&self, fn_idx: FunctionIndex) -> Option<*const c_void> {
+        self.fn_indices
+            .iter()
+            .position(|idx| *idx == fn_idx)
+            .map(|i| unsafe { (self.mem as *const u8).add(i * TRAMPOLINE_SIZE) as *const c_void })
+    }
+}
+
+impl Drop for HostFuncTrampolines {
+    fn drop(&mut self) {
+        wx::unregister(self.mem as usize);
+        unsafe {
+            munmap(self.mem, self.len).expect("host function trampolines can be unmapped");
+        }
+    }
+}
+
+fn host_page_size() -> usize {
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    assert!(page_size > 0, "page size can be determined");
+    page_size as usize
+}
+
+/// Reads arguments in the order the System V calling convention assigns them.
+struct ArgReader<'a> {
+    args: &'a HostFuncArgs,
+    // the vmctx is always the first integer argument
+    next_gp: usize,
+    next_fp: usize,
+    stack_args: *const u64,
+}
+
+impl<'a> ArgReader<'a> {
+    unsafe fn next_stack(&mut self) -> u64 {
+        let val = *self.stack_args;
+        self.stack_args = self.stack_args.add(1);
+        val
+    }
+
+    unsafe fn next_gp(&mut self) -> u64 {
+        if self.next_gp < self.args.gp.len() {
+            self.next_gp += 1;
+            self.args.gp[self.next_gp - 1]
+        } else {
+            self.next_stack()
+        }
+    }
+
+    unsafe fn next_val(&mut self, ty: &ValueType) -> Val {
+        match ty {
+            ValueType::I32 => Val::I32(self.next_gp() as i32),
+            ValueType::I64 => Val::I64(self.next_gp() as i64),
+            ValueType::F32 if self.next_fp < self.args.fp.len() => {
+                self.next_fp += 1;
+                Val::F32(__m128_as_f32(self.args.fp[self.next_fp - 1]))
+            }
+            ValueType::F64 if self.next_fp < self.args.fp.len() => {
+                self.next_fp += 1;
+                Val::F64(__m128_as_f64(self.args.fp[self.next_fp - 1]))
+            }
+            ValueType::F32 => Val::F32(f32::from_bits(self.next_stack() as u32)),
+            ValueType::F64 => Val::F64(f64::from_bits(self.next_stack())),
+        }
+    }
+}
+
+/// Called by `lucet_host_func_dispatch` when guest code calls an import bound to a host function.
+#[no_mangle]
+unsafe extern "C" fn lucet_host_func_call(
+    fn_idx: u64,
+    args: *mut HostFuncArgs,
+    stack_args: *const u64,
+) -> u64 {
+    let vmctx_raw = (*args).gp[0] as *const lucet_vmctx;
+    let fn_idx = FunctionIndex::from_u32(fn_idx as u32);
+    let vmctx = Vmctx::from_raw(vmctx_raw);
+    vmctx.instance_mut().uninterruptable(|| {
+        let res = catch_unwind(AssertUnwindSafe(|| {
+            let vmctx = Vmctx::from_raw(vmctx_raw);
+            vmctx.charge_hostcall("host function");
+            call_host_func(&vmctx, fn_idx, &*args, stack_args)
+        }));
+        match res {
+            Ok(retval) => {
+                (*args).fp[0] = retval.fp();
+                retval.gp()
+            }
+            Err(e) => match e.downcast::<TerminationDetails>() {
+                Ok(details) => Vmctx::from_raw(vmctx_raw).terminate_no_unwind(*details),
+                Err(e) => Vmctx::from_raw(vmctx_raw).host_panicked(e),
+            },
+        }
+    })
+}
+
+unsafe fn call_host_func(
+    vmctx: &Vmctx,
+    fn_idx: FunctionIndex,
+    args: &HostFuncArgs,
+    stack_args: *const u64,
+) -> UntypedRetVal {
+    // clone the closure out of the embedder context so that it is free to borrow the context itself
+    let func = match vmctx.instance().embed_ctx.try_get::<LinkedImports>() {
+        Some(Ok(linked)) => linked.host_func(fn_idx),
+        Some(Err(_)) => panic!(TerminationDetails::BorrowError("host function")),
+        None => None,
+    };
+    let (func, deterministic) = func.unwrap_or_else(|| {
+        panic!(TerminationDetails::provide(format!(
+            "imported function {} is not linked to a host function in this instance",
+            fn_idx.as_u32()
+        )))
+    });
+    if !deterministic {
+        vmctx.nondeterministic("host function");
+    }
+
+    let sig = vmctx.instance().module().get_signature(fn_idx);
+    let mut reader = ArgReader {
+        args,
+        next_gp: 1,
+        next_fp: 0,
+        stack_args,
+    };
+    let vals = sig
+        .params
+        .iter()
+        .map(|ty| reader.next_val(ty))
+        .collect::<Vec<_>>();
+
+    func(vmctx, &vals)
+}
@@ -0,0 +1,390 @@
+//! Checked access to the linear memory of an instance.
+//!
+//! Hostcalls are handed guest pointers as plain `u32` offsets into the instance heap. Rather than
+//! slicing `Vmctx::heap()` by hand and repeating the same bounds arithmetic in every hostcall,
+//! [`GuestMemory`](struct.GuestMemory.html) offers helpers that validate the offsets and lengths
+//! they are given, and return an error instead of panicking when they fall outside the heap.
+//!
+//! [`WasmPtr`](struct.WasmPtr.html) and [`WasmSlice`](struct.WasmSlice.html) go a step further
+//! and carry the pointee type, so that they can be used directly as hostcall arguments and
+//! dereferenced against a `GuestMemory` with alignment as well as bounds checks.
+
+use crate::error::{Error, GuestMemoryError};
+use crate::val::{Val, WasmValue};
+use lucet_module::ValueType;
+use std::cell::RefMut;
+use std::ffi::CStr;
+use std::fmt;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+/// Types that can be copied in and out of guest memory byte-for-byte.
+///
+/// # Safety
+///
+/// Implementors must be `Copy` types without padding for which every bit pattern is a valid
+/// value, such as the primitive integer and floating point types, or `#[repr(C)]` structs made up
+/// only of such fields.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ( $( $ty:ty ),* ) => {
+        $(
+            unsafe impl Pod for $ty {}
+        )*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+enum Heap<'a> {
+    Instance(&'a mut [u8]),
+    Vmctx(RefMut<'a, [u8]>),
+}
+
+impl<'a> Deref for Heap<'a> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Heap::Instance(heap) => heap,
+            Heap::Vmctx(heap) => heap,
+        }
+    }
+}
+
+impl<'a> DerefMut for Heap<'a> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Heap::Instance(heap) => heap,
+            Heap::Vmctx(heap) => heap,
+        }
+    }
+}
+
+/// A view of an instance's linear memory with bounds-checked accessors.
+///
+/// Obtained from [`Instance::memory()`](../instance/struct.Instance.html#method.memory) or
+/// [`Vmctx::memory()`](../vmctx/struct.Vmctx.html#method.memory). The view holds a mutable borrow
+/// of the heap for as long as it lives, so it must be dropped before the heap can grow or the
+/// instance can yield.
+pub struct GuestMemory<'a> {
+    heap: Heap<'a>,
+}
+
+impl<'a> GuestMemory<'a> {
+    pub(crate) fn from_instance_heap(heap: &'a mut [u8]) -> Self {
+        GuestMemory {
+            heap: Heap::Instance(heap),
+        }
+    }
+
+    pub(crate) fn from_vmctx_heap(heap: RefMut<'a, [u8]>) -> Self {
+        GuestMemory {
+            heap: Heap::Vmctx(heap),
+        }
+    }
+
+    /// The current size of the memory, in bytes.
+    pub fn len(&self) -> usize {
+        self.heap.len()
+    }
+
+    /// Whether the memory is empty, e.g. for an instance without linear memory.
+    pub fn is_empty(&self) -> bool {
+        self.heap.is_empty()
+    }
+
+    /// Return the whole memory as a slice of bytes.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.heap
+    }
+
+    /// Return the whole memory as a mutable slice of bytes.
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        &mut self.heap
+    }
+
+    /// Check that `len` bytes starting at guest address `ptr` are within the memory, returning
+    /// the corresponding range of host offsets.
+    pub fn check_range(&self, ptr: u32, len: u32) -> Result<std::ops::Range<usize>, Error> {
+        let start = ptr as usize;
+        let end = start
+            .checked_add(len as usize)
+            .filter(|end| *end <= self.heap.len())
+            .ok_or(GuestMemoryError::OutOfBounds { ptr, len })?;
+        Ok(start..end)
+    }
+
+    /// Borrow `len` bytes of memory starting at guest address `ptr`.
+    pub fn read_bytes(&self, ptr: u32, len: u32) -> Result<&[u8], Error> {
+        let range = self.check_range(ptr, len)?;
+        Ok(&self.heap[range])
+    }
+
+    /// Mutably borrow `len` bytes of memory starting at guest address `ptr`.
+    pub fn bytes_mut(&mut self, ptr: u32, len: u32) -> Result<&mut [u8], Error> {
+        let range = self.check_range(ptr, len)?;
+        Ok(&mut self.heap[range])
+    }
+
+    /// Copy `bytes` into memory starting at guest address `ptr`.
+    ///
+    /// Nothing is written if any part of the destination is out of bounds.
+    pub fn write_bytes(&mut self, ptr: u32, bytes: &[u8]) -> Result<(), Error> {
+        let len = bytes.len();
+        if len > std::u32::MAX as usize {
+            return Err(GuestMemoryError::OutOfBounds {
+                ptr,
+                len: std::u32::MAX,
+            }
+            .into());
+        }
+        self.bytes_mut(ptr, len as u32)?.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Borrow the nul-terminated string starting at guest address `ptr`.
+    ///
+    /// The terminator must be found before the end of the memory.
+    pub fn read_cstr(&self, ptr: u32) -> Result<&CStr, Error> {
+        let start = self.check_range(ptr, 0)?.start;
+        let rest = &self.heap[start..];
+        let nul = rest
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or(GuestMemoryError::UnterminatedCStr { ptr })?;
+        Ok(CStr::from_bytes_with_nul(&rest[..=nul]).expect("slice ends at the first nul byte"))
+    }
+
+    /// Borrow the UTF-8 string of `len` bytes starting at guest address `ptr`.
+    pub fn read_str(&self, ptr: u32, len: u32) -> Result<&str, Error> {
+        let bytes = self.read_bytes(ptr, len)?;
+        std::str::from_utf8(bytes).map_err(|e| GuestMemoryError::InvalidUtf8(e).into())
+    }
+
+    /// Read a value of type `T` stored at guest address `ptr`.
+    ///
+    /// Guest memory makes no alignment guarantees, so the value is read unaligned.
+    pub fn read_pod<T: Pod>(&self, ptr: u32) -> Result<T, Error> {
+        let bytes = self.read_bytes(ptr, std::mem::size_of::<T>() as u32)?;
+        Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const T) })
+    }
+
+    /// Write a value of type `T` to guest address `ptr`.
+    pub fn write_pod<T: Pod>(&mut self, ptr: u32, val: T) -> Result<(), Error> {
+        let bytes = self.bytes_mut(ptr, std::mem::size_of::<T>() as u32)?;
+        unsafe { std::ptr::write_unaligned(bytes.as_mut_ptr() as *mut T, val) };
+        Ok(())
+    }
+}
+
+/// A typed pointer into guest memory.
+///
+/// A `WasmPtr<T>` has the same representation as the `u32` guest address it wraps, so it can be
+/// used in place of a `u32` argument in a `#[lucet_hostcall]` function:
+///
+/// ```ignore
+/// #[lucet_hostcall]
+/// #[no_mangle]
+/// pub fn increment(vmctx: &Vmctx, counter: WasmPtr<u64>) {
+///     let mut memory = vmctx.memory();
+///     let count = counter.read(&memory).unwrap_or_else(|e| lucet_hostcall_terminate!(e));
+///     counter.write(&mut memory, count + 1).unwrap();
+/// }
+/// ```
+#[repr(transparent)]
+pub struct WasmPtr<T> {
+    offset: u32,
+    _ty: PhantomData<fn() -> T>,
+}
+
+impl<T> WasmPtr<T> {
+    /// Create a pointer to guest address `offset`.
+    pub fn new(offset: u32) -> Self {
+        WasmPtr {
+            offset,
+            _ty: PhantomData,
+        }
+    }
+
+    /// The guest address of this pointer.
+    pub fn offset(self) -> u32 {
+        self.offset
+    }
+
+    pub fn is_null(self) -> bool {
+        self.offset == 0
+    }
+
+    /// Reinterpret this pointer as pointing to a value of a different type.
+    pub fn cast<U>(self) -> WasmPtr<U> {
+        WasmPtr::new(self.offset)
+    }
+}
+
+impl<T: Pod> WasmPtr<T> {
+    /// Check that a `T` can be accessed through this pointer, returning the range of host
+    /// offsets it occupies.
+    fn check(self, memory: &GuestMemory<'_>) -> Result<std::ops::Range<usize>, Error> {
+        check_align::<T>(self.offset)?;
+        memory.check_range(self.offset, std::mem::size_of::<T>() as u32)
+    }
+
+    /// Borrow the value this pointer points to.
+    pub fn deref<'m>(self, memory: &'m GuestMemory<'_>) -> Result<&'m T, Error> {
+        let range = self.check(memory)?;
+        // The heap is page-aligned, so an aligned guest address is an aligned host address.
+        Ok(unsafe { &*(memory.as_slice()[range].as_ptr() as *const T) })
+    }
+
+    /// Mutably borrow the value this pointer points to.
+    pub fn deref_mut<'m>(self, memory: &'m mut GuestMemory<'_>) -> Result<&'m mut T, Error> {
+        let range = self.check(memory)?;
+        Ok(unsafe { &mut *(memory.as_slice_mut()[range].as_mut_ptr() as *mut T) })
+    }
+
+    /// Read the value this pointer points to.
+    pub fn read(self, memory: &GuestMemory<'_>) -> Result<T, Error> {
+        self.deref(memory).map(|v| *v)
+    }
+
+    /// Write a value to the location this pointer points to.
+    pub fn write(self, memory: &mut GuestMemory<'_>, val: T) -> Result<(), Error> {
+        *self.deref_mut(memory)? = val;
+        Ok(())
+    }
+}
+
+impl<T> Clone for WasmPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for WasmPtr<T> {}
+
+impl<T> PartialEq for WasmPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T> Eq for WasmPtr<T> {}
+
+impl<T> fmt::Debug for WasmPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WasmPtr({:#x})", self.offset)
+    }
+}
+
+unsafe impl<T: 'static> Pod for WasmPtr<T> {}
+
+impl<T> From<WasmPtr<T>> for Val {
+    fn from(ptr: WasmPtr<T>) -> Val {
+        Val::GuestPtr(ptr.offset)
+    }
+}
+
+impl<T: 'static> WasmValue for WasmPtr<T> {
+    const VALUE_TYPE: ValueType = ValueType::I32;
+
+    fn from_val(val: &Val) -> Self {
+        WasmPtr::new(u32::from_val(val))
+    }
+}
+
+/// A typed slice of guest memory, made up of a [`WasmPtr`](struct.WasmPtr.html) to its first
+/// element and its length in elements.
+///
+/// When a `#[lucet_hostcall]` function takes a `WasmSlice<T>` argument, the raw hostcall takes
+/// two `u32` arguments in its place: the guest address of the slice, followed by its length.
+pub struct WasmSlice<T> {
+    ptr: WasmPtr<T>,
+    len: u32,
+}
+
+impl<T> WasmSlice<T> {
+    pub fn new(ptr: WasmPtr<T>, len: u32) -> Self {
+        WasmSlice { ptr, len }
+    }
+
+    /// Create a slice from the raw guest address and length passed to a hostcall.
+    pub fn from_raw(offset: u32, len: u32) -> Self {
+        WasmSlice::new(WasmPtr::new(offset), len)
+    }
+
+    pub fn ptr(&self) -> WasmPtr<T> {
+        self.ptr
+    }
+
+    /// The number of elements in the slice.
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<T: Pod> WasmSlice<T> {
+    fn check(&self, memory: &GuestMemory<'_>) -> Result<std::ops::Range<usize>, Error> {
+        check_align::<T>(self.ptr.offset)?;
+        let bytes = (std::mem::size_of::<T>() as u32)
+            .checked_mul(self.len)
+            .ok_or(GuestMemoryError::OutOfBounds {
+                ptr: self.ptr.offset,
+                len: std::u32::MAX,
+            })?;
+        memory.check_range(self.ptr.offset, bytes)
+    }
+
+    /// Borrow the elements of the slice.
+    pub fn as_slice<'m>(&self, memory: &'m GuestMemory<'_>) -> Result<&'m [T], Error> {
+        let range = self.check(memory)?;
+        let ptr = memory.as_slice()[range].as_ptr() as *const T;
+        Ok(unsafe { std::slice::from_raw_parts(ptr, self.len as usize) })
+    }
+
+    /// Mutably borrow the elements of the slice.
+    pub fn as_slice_mut<'m>(&self, memory: &'m mut GuestMemory<'_>) -> Result<&'m mut [T], Error> {
+        let range = self.check(memory)?;
+        let ptr = memory.as_slice_mut()[range].as_mut_ptr() as *mut T;
+        Ok(unsafe { std::slice::from_raw_parts_mut(ptr, self.len as usize) })
+    }
+
+    /// Copy the elements of the slice out of guest memory.
+    pub fn to_vec(&self, memory: &GuestMemory<'_>) -> Result<Vec<T>, Error> {
+        self.as_slice(memory).map(|s| s.to_vec())
+    }
+}
+
+impl WasmSlice<u8> {
+    /// Borrow the slice as a UTF-8 string.
+    pub fn as_str<'m>(&self, memory: &'m GuestMemory<'_>) -> Result<&'m str, Error> {
+        memory.read_str(self.ptr.offset, self.len)
+    }
+}
+
+impl<T> Clone for WasmSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for WasmSlice<T> {}
+
+impl<T> fmt::Debug for WasmSlice<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "WasmSlice({:#x}, len {})", self.ptr.offset, self.len)
+    }
+}
+
+fn check_align<T>(offset: u32) -> Result<(), Error> {
+    let align = std::mem::align_of::<T>() as u32;
+    if offset % align != 0 {
+        return Err(GuestMemoryError::Misaligned { ptr: offset, align }.into());
+    }
+    Ok(())
+}
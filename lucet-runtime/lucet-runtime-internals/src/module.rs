@@ -1,12 +1,17 @@
 mod dl;
 mod mock;
+mod registry;
 mod sparse_page_data;
+mod static_module;
 
 pub use crate::module::dl::{DlError, DlModule};
 pub use crate::module::mock::{MockExportBuilder, MockModuleBuilder};
+pub use crate::module::registry::{ModuleHandle, ModuleRegistry};
+pub use crate::module::static_module::StaticModule;
 pub use lucet_module::{
-    FunctionHandle, FunctionIndex, FunctionPointer, FunctionSpec, Global, GlobalSpec, GlobalValue,
-    HeapSpec, Signature, TableElement, TrapCode, TrapManifest, ValueType,
+    CustomSection, ExportFunction, ExportTable, FunctionHandle, FunctionIndex, FunctionPointer,
+    FunctionSpec, Global, GlobalDef, GlobalSpec, GlobalValue, HeapSpec, ImportFunction,
+    SerializedModule, Signature, TableElement, TableSpec, TrapCode, TrapManifest, ValueType,
 };
 
 use crate::alloc::Limits;
@@ -16,9 +21,9 @@ use libc::c_void;
 /// Details about a program address.
 ///
 /// It is possible to determine whether an address lies within the module code if the module is
-/// loaded from a shared object. Statically linked modules are not resolvable. Best effort is made
-/// to resolve the symbol the address is found inside, and the file that symbol is found in. See
-/// `dladdr(3)` for more details.
+/// loaded from a shared object, or if it is a [`StaticModule`](struct.StaticModule.html) with a
+/// function manifest covering the address. Best effort is made to resolve the symbol the address
+/// is found inside, and the file that symbol is found in. See `dladdr(3)` for more details.
 #[derive(Clone, Debug)]
 pub struct AddrDetails {
     pub in_module_code: bool,
@@ -26,15 +31,107 @@ pub struct AddrDetails {
     pub sym_name: Option<String>,
 }
 
+/// The location of a faulting instruction in the WebAssembly function it was compiled from.
+///
+/// This is derived from the module's function manifest, so it is available even when the module
+/// was loaded without symbols.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrapLocation {
+    /// The index of the function in the module.
+    pub function_index: FunctionIndex,
+    /// The name of the function, if the module records one.
+    pub function_name: Option<String>,
+    /// The offset of the instruction from the start of the function's compiled code.
+    pub code_offset: u32,
+}
+
+impl std::fmt::Display for TrapLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.function_name {
+            Some(name) => write!(f, "{}", name)?,
+            None => write!(f, "function #{}", self.function_index.as_u32())?,
+        }
+        write!(f, "+{:#x}", self.code_offset)
+    }
+}
+
+/// The kind of item a module exports, along with where to find it in the module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportKind {
+    /// A function, with its index in the module.
+    Function(FunctionIndex),
+    /// A global, with its index in the module's globals.
+    Global(usize),
+    /// The linear memory of the module.
+    Memory,
+    /// A table, with its index in the module.
+    Table(u32),
+}
+
+/// An item exported by a module, and the name it is exported under.
+///
+/// An item exported under several names appears once for each name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+}
+
 /// The read-only parts of a Lucet program, including its code and initial heap configuration.
 ///
 /// Types that implement this trait are suitable for use with
 /// [`Region::new_instance()`](trait.Region.html#method.new_instance).
 pub trait Module: ModuleInternal {
+    /// Get the custom sections of the WebAssembly module that `lucetc` was asked to preserve, in
+    /// the order they appear in the module.
+    ///
+    /// This is empty for modules compiled before custom sections could be preserved.
+    fn custom_sections(&self) -> &[CustomSection<'_>];
+
+    /// Get the contents of the first preserved custom section named `name`.
+    fn custom_section(&self, name: &str) -> Option<&[u8]> {
+        self.custom_sections()
+            .iter()
+            .find(|section| section.name == name)
+            .map(|section| section.data)
+    }
+
     /// Calculate the initial size in bytes of the module's Wasm globals.
     fn initial_globals_size(&self) -> usize {
         self.globals().len() * std::mem::size_of::<u64>()
     }
+
+    /// List the functions, globals, memory, and tables the module exports.
+    fn exports(&self) -> Vec<Export> {
+        let export = |name: &str, kind| Export {
+            name: name.to_owned(),
+            kind,
+        };
+        let functions = self.export_functions().iter().flat_map(|f| {
+            f.names
+                .iter()
+                .map(move |name| export(*name, ExportKind::Function(f.fn_idx)))
+        });
+        let globals = self.globals().iter().enumerate().flat_map(|(idx, g)| {
+            g.export_names()
+                .iter()
+                .map(move |name| export(*name, ExportKind::Global(idx)))
+        });
+        let memories = self
+            .export_memory_names()
+            .iter()
+            .map(|name| export(*name, ExportKind::Memory));
+        let tables = self.export_tables().iter().flat_map(|t| {
+            t.names
+                .iter()
+                .map(move |name| export(*name, ExportKind::Table(t.table_idx)))
+        });
+        functions
+            .chain(globals)
+            .chain(memories)
+            .chain(tables)
+            .collect()
+    }
 }
 
 pub trait ModuleInternal: Send + Sync {
@@ -59,18 +156,104 @@ pub trait ModuleInternal: Send + Sync {
     /// Get the table elements from the module.
     fn table_elements(&self) -> Result<&[TableElement], Error>;
 
+    /// Get the functions the module imports, along with the module and field names they are
+    /// imported from.
+    fn import_functions(&self) -> &[ImportFunction<'_>];
+
+    /// Get the functions the module exports, along with the names they are exported under.
+    fn export_functions(&self) -> &[ExportFunction<'_>];
+
+    /// Get the names the module's linear memory is exported under.
+    fn export_memory_names(&self) -> &[&str];
+
+    /// Get the tables the module exports, along with the names they are exported under.
+    fn export_tables(&self) -> &[ExportTable<'_>];
+
+    /// Get the descriptions of the module's tables: their element segments, and the function in
+    /// each of their initial elements.
+    ///
+    /// This is empty for modules compiled before tables were described.
+    fn tables(&self) -> &[TableSpec];
+
+    /// Get the function the module placed in an element of a WebAssembly table, as it was
+    /// compiled.
+    ///
+    /// Its name, if the module records one, is given by `function_name()`. Returns `None` for
+    /// elements the module left empty, and for modules compiled before tables were described.
+    fn table_entry_function(&self, table_id: u32, elem_idx: u32) -> Option<FunctionIndex> {
+        self.tables()
+            .iter()
+            .find(|table| table.table_idx == table_id)
+            .and_then(|table| table.element(elem_idx))
+    }
+
+    /// Route guest calls to the imported function `fn_idx` through the runtime, so that they can
+    /// be served by host functions registered with a [`Linker`](../linker/struct.Linker.html).
+    ///
+    /// This applies to every instance of the module, not only the one being linked.
+    fn bind_host_func_import(&self, _fn_idx: FunctionIndex) -> Result<(), Error> {
+        Err(Error::Unsupported(
+            "this module type does not support binding imports to host functions".to_owned(),
+        ))
+    }
+
+    /// Check that the imported functions of the module can all be called by a new instance,
+    /// because they are either bound to host functions or defined by native symbols.
+    ///
+    /// Modules whose imports are resolved when they are loaded always pass.
+    fn check_imports(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
     fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error>;
 
     fn get_func_from_idx(&self, table_id: u32, func_id: u32) -> Result<FunctionHandle, Error>;
 
     fn get_start_func(&self) -> Result<Option<FunctionHandle>, Error>;
 
+    /// Look up the function stored in an element of a WebAssembly table.
+    ///
+    /// Unlike `get_func_from_idx()`, this never panics on elements the guest left empty, or on
+    /// elements that do not point into this module's function manifest; those are reported as
+    /// errors instead.
+    fn get_table_entry(&self, table_id: u32, elem_idx: u32) -> Result<FunctionHandle, Error> {
+        if table_id != 0 {
+            return Err(Error::FuncNotFound(table_id, elem_idx));
+        }
+        let element = self
+            .table_elements()?
+            .get(elem_idx as usize)
+            .ok_or(Error::FuncNotFound(table_id, elem_idx))?;
+        if element.is_empty() {
+            return Err(Error::FuncNotFound(table_id, elem_idx));
+        }
+        let ptr = element.function_pointer();
+        self.function_manifest()
+            .iter()
+            .position(|fn_spec| fn_spec.ptr() == ptr)
+            .map(|fn_id| FunctionHandle {
+                ptr,
+                id: FunctionIndex::from_u32(fn_id as u32),
+                is_start_func: false,
+            })
+            .ok_or_else(|| {
+                lucet_incorrect_module!(
+                    "table {} element {} does not refer to a function in the module",
+                    table_id,
+                    elem_idx
+                )
+            })
+    }
+
     fn function_manifest(&self) -> &[FunctionSpec];
 
     fn addr_details(&self, addr: *const c_void) -> Result<Option<AddrDetails>, Error>;
 
     fn get_signature(&self, fn_id: FunctionIndex) -> &Signature;
 
+    /// Get the human-friendly name of a function, if the module records one.
+    fn function_name(&self, fn_id: FunctionIndex) -> Option<&str>;
+
     fn function_handle_from_ptr(&self, ptr: FunctionPointer) -> FunctionHandle {
         let id = self
             .function_manifest()
@@ -103,6 +286,25 @@ pub trait ModuleInternal: Send + Sync {
         None
     }
 
+    /// Look up the function containing an instruction pointer, and the instruction's offset in
+    /// it.
+    ///
+    /// Unlike `lookup_trapcode()`, this allocates, so it must not be called from a signal handler.
+    fn lookup_trap_location(&self, rip: *const c_void) -> Option<TrapLocation> {
+        self.function_manifest()
+            .iter()
+            .enumerate()
+            .find_map(|(fn_id, fn_spec)| {
+                let code_offset = fn_spec.relative_addr(rip as u64)?;
+                let function_index = FunctionIndex::from_u32(fn_id as u32);
+                Some(TrapLocation {
+                    function_index,
+                    function_name: self.function_name(function_index).map(|s| s.to_owned()),
+                    code_offset,
+                })
+            })
+    }
+
     /// Check that the specifications of the WebAssembly module are valid given certain `Limit`s.
     ///
     /// Returns a `Result<(), Error>` rather than a boolean in order to provide a richer accounting
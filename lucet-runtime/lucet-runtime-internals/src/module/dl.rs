@@ -1,17 +1,24 @@
 use crate::error::Error;
-use crate::module::{AddrDetails, GlobalSpec, HeapSpec, Module, ModuleInternal, TableElement};
+use crate::linker::HostFuncTrampolines;
+use crate::module::{
+    AddrDetails, CustomSection, ExportFunction, ExportTable, GlobalSpec, HeapSpec, ImportFunction,
+    Module, ModuleInternal, TableElement, TableSpec,
+};
+use crate::wx;
 use libc::c_void;
 use libloading::Library;
 use lucet_module::{
-    FunctionHandle, FunctionIndex, FunctionSpec, ModuleData, ModuleFeatures, ModuleSignature,
-    PublicKey, SerializedModule, Signature, VersionInfo, LUCET_MODULE_SYM,
+    FunctionHandle, FunctionIndex, FunctionSpec, ModuleData, ModuleFeatures, ModuleIntegrity,
+    ModuleSignature, PublicKey, SerializedModule, Signature, VersionInfo, LUCET_MODULE_SYM,
+    MODULE_FORMAT_VERSIONS,
 };
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
+use std::fs::File;
 use std::mem::MaybeUninit;
 use std::path::Path;
 use std::slice;
 use std::slice::from_raw_parts;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 
 use raw_cpuid::CpuId;
@@ -30,6 +37,109 @@ pub enum DlError {
         #[source]
         std::io::Error,
     ),
+    #[error("Loading into a new namespace: {0}")]
+    Namespace(String),
+    #[error("Loading: unsatisfied import `{module}::{field}` (undefined symbol `{symbol}`)")]
+    UnsatisfiedImport {
+        module: String,
+        field: String,
+        symbol: String,
+    },
+}
+
+/// The imported functions of `module_data` whose native symbols are not defined by the
+/// executable, or by the libraries it has loaded.
+fn undefined_imports(module_data: &ModuleData<'_>) -> Vec<FunctionIndex> {
+    module_data
+        .import_functions()
+        .iter()
+        .filter(|import| {
+            let symbol = module_data
+                .function_info()
+                .get(import.fn_idx.as_u32() as usize)
+                .and_then(|info| info.name)
+                .and_then(|name| CString::new(name).ok());
+            match symbol {
+                Some(symbol) => {
+                    unsafe { libc::dlsym(libc::RTLD_DEFAULT, symbol.as_ptr()) }.is_null()
+                }
+                None => true,
+            }
+        })
+        .map(|import| import.fn_idx)
+        .collect()
+}
+
+/// How the dynamic linker opens the shared object of a module.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum OpenMode {
+    /// Resolve all imports from the executable when the module is loaded.
+    Now,
+    /// Resolve imports from the executable when they are first called, checking that those not
+    /// bound to host functions are defined when an instance is created.
+    LazyImports,
+    /// Load the module into a new link-map namespace, resolving imports lazily.
+    Isolated,
+}
+
+/// How the shared object of a module is checked against its module data once it is loaded.
+pub(crate) enum Verify {
+    /// Verify the module signature with a public key.
+    Signature(PublicKey),
+    /// Compare the hash of the shared object with the integrity hash `lucetc` recorded.
+    Integrity,
+}
+
+/// Open a shared object with `dlopen()`, binding its imports as `flags` say.
+fn open_with(abs_so_path: &Path, flags: libc::c_int) -> Result<Library, Error> {
+    libloading::os::unix::Library::open(Some(abs_so_path.as_os_str()), flags | libc::RTLD_LOCAL)
+        .map(Library::from)
+        .map_err(|e| DlError::Loading(e).into())
+}
+
+/// Open a shared object with `dlmopen()` in a new link-map namespace.
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+fn open_in_new_namespace(abs_so_path: &Path) -> Result<Library, Error> {
+    use std::os::unix::ffi::OsStrExt;
+
+    extern "C" {
+        fn dlmopen(
+            lmid: libc::c_long,
+            filename: *const libc::c_char,
+            flags: libc::c_int,
+        ) -> *mut c_void;
+    }
+    // from `<dlfcn.h>`
+    const LM_ID_NEWLM: libc::c_long = -1;
+
+    let filename = CString::new(abs_so_path.as_os_str().as_bytes())
+        .map_err(|_| Error::InvalidArgument("module path contains a nul byte"))?;
+    let handle = unsafe {
+        dlmopen(
+            LM_ID_NEWLM,
+            filename.as_ptr(),
+            libc::RTLD_LAZY | libc::RTLD_LOCAL,
+        )
+    };
+    if handle.is_null() {
+        let msg = unsafe { libc::dlerror() };
+        let msg = if msg.is_null() {
+            "unknown dlmopen() error".to_string()
+        } else {
+            unsafe { CStr::from_ptr(msg) }
+                .to_string_lossy()
+                .into_owned()
+        };
+        return Err(DlError::Namespace(msg).into());
+    }
+    Ok(unsafe { libloading::os::unix::Library::from_raw(handle) }.into())
+}
+
+#[cfg(not(all(target_os = "linux", target_env = "gnu")))]
+fn open_in_new_namespace(_abs_so_path: &Path) -> Result<Library, Error> {
+    Err(Error::Unsupported(
+        "loading modules into a new namespace requires glibc's dlmopen()".to_string(),
+    ))
 }
 
 fn check_feature_support(module_features: &ModuleFeatures) -> Result<(), Error> {
@@ -95,17 +205,33 @@ fn check_feature_support(module_features: &ModuleFeatures) -> Result<(), Error>
 
 /// A Lucet module backed by a dynamically-loaded shared object.
 pub struct DlModule {
-    /// A handle to the loaded object.
+    /// A handle to the loaded object, or `None` for a module linked into the executable.
     ///
     /// This is never used after initialization, but we can't let the library close until we're done
     /// with this module.
-    _lib: Library,
+    _lib: Option<Library>,
+
+    /// For modules loaded from memory, the anonymous file holding the shared object.
+    ///
+    /// The dynamic linker identifies the library by its `/proc/self/fd` path, so the descriptor
+    /// must stay open, and its number unused by any other module, until the library is closed.
+    _memfd: Option<File>,
 
     /// Base address of the dynamically-loaded module
     fbase: *const c_void,
 
     /// Metadata decoded from inside the module
     module: lucet_module::Module<'static>,
+
+    /// Trampolines for imports bound to `Linker` host functions, created on first use
+    host_func_trampolines: Mutex<Option<HostFuncTrampolines>>,
+
+    /// Imports the executable does not define a symbol for, and that are not yet bound to host
+    /// functions
+    undefined_imports: Mutex<Vec<FunctionIndex>>,
+
+    /// The starts of the loaded segments registered for W^X audits
+    wx_segments: Vec<usize>,
 }
 
 // for the one raw pointer only
@@ -115,25 +241,138 @@ unsafe impl Sync for DlModule {}
 impl DlModule {
     /// Create a module, loading code from a shared object on the filesystem.
     pub fn load<P: AsRef<Path>>(so_path: P) -> Result<Arc<Self>, Error> {
-        Self::load_and_maybe_verify(so_path, None)
+        Self::load_and_maybe_verify(so_path, None, OpenMode::Now)
     }
 
     /// Create a module, loading code from a shared object on the filesystem
     /// and verifying it using a public key if one has been supplied.
     pub fn load_and_verify<P: AsRef<Path>>(so_path: P, pk: PublicKey) -> Result<Arc<Self>, Error> {
-        Self::load_and_maybe_verify(so_path, Some(pk))
+        Self::load_and_maybe_verify(so_path, Some(Verify::Signature(pk)), OpenMode::Now)
+    }
+
+    /// Create a module, loading code from a shared object on the filesystem and checking that
+    /// it has the integrity hash `lucetc` recorded in it.
+    ///
+    /// This catches shared objects that were corrupted or only partly written, without the
+    /// key management signatures need, but unlike
+    /// [`DlModule::load_and_verify()`](#method.load_and_verify), it does not guard against
+    /// a module being tampered with. Modules compiled before `lucetc` recorded integrity hashes
+    /// fail to load this way.
+    pub fn load_and_check_integrity<P: AsRef<Path>>(so_path: P) -> Result<Arc<Self>, Error> {
+        Self::load_and_maybe_verify(so_path, Some(Verify::Integrity), OpenMode::Now)
+    }
+
+    /// Create a module, loading code from a shared object on the filesystem without requiring
+    /// that its imported functions are defined by the current executable.
+    ///
+    /// This is for modules whose imports are provided by host functions registered with a
+    /// [`Linker`](../linker/struct.Linker.html). Imports that are not linked are still resolved
+    /// from the executable, but only when they are first called. Creating an instance fails with
+    /// `DlError::UnsatisfiedImport` if an import is neither bound to a host function nor defined by
+    /// the executable.
+    pub fn load_with_lazy_imports<P: AsRef<Path>>(so_path: P) -> Result<Arc<Self>, Error> {
+        Self::load_and_maybe_verify(so_path, None, OpenMode::LazyImports)
+    }
+
+    /// Create a module, loading code from a shared object on the filesystem into its own
+    /// link-map namespace with `dlmopen()`.
+    ///
+    /// Symbols of a module loaded this way never collide with those of the executable or of other
+    /// modules, so modules that define the same symbols, or many versions of the same module, can
+    /// be loaded at once. The same shared object can even be loaded more than once, and each load
+    /// gets its own copy of the code and data.
+    ///
+    /// Because the namespace does not contain the executable, the module's imports cannot resolve
+    /// to symbols the executable defines. As with
+    /// [`DlModule::load_with_lazy_imports()`](#method.load_with_lazy_imports), imports are resolved
+    /// lazily, and they should all be provided by host functions registered with a
+    /// [`Linker`](../linker/struct.Linker.html); calling any other import aborts the process.
+    /// The exception is the runtime's own `lucet_vmctx_*` functions, which modules call to grow
+    /// their memory, among other things: the module's references to them are pointed at the
+    /// executable's definitions when it is loaded, and loading fails with
+    /// `Error::SymbolNotFound` if the executable does not export one of them.
+    ///
+    /// glibc supports only a small, fixed number of namespaces per process (16, including the
+    /// default one). Once they are exhausted, loading fails with `DlError::Namespace`. This is only
+    /// supported on Linux with glibc; other platforms return `Error::Unsupported`.
+    pub fn load_isolated<P: AsRef<Path>>(so_path: P) -> Result<Arc<Self>, Error> {
+        Self::load_and_maybe_verify(so_path, None, OpenMode::Isolated)
+    }
+
+    /// Create a module, loading code from a shared object in memory rather than on the
+    /// filesystem.
+    ///
+    /// This is for modules that are fetched or generated at runtime, and that should never be
+    /// written to disk. On Linux, the bytes are copied into an anonymous file created with
+    /// `memfd_create()`, which the dynamic linker then loads; other platforms return
+    /// `Error::Unsupported`.
+    pub fn load_from_bytes(so_bytes: &[u8]) -> Result<Arc<Self>, Error> {
+        Self::load_from_bytes_and_maybe_verify(so_bytes, None)
+    }
+
+    /// Create a module, loading code from a shared object in memory and verifying it using a
+    /// public key.
+    ///
+    /// See [`DlModule::load_from_bytes()`](#method.load_from_bytes).
+    pub fn load_from_bytes_and_verify(so_bytes: &[u8], pk: PublicKey) -> Result<Arc<Self>, Error> {
+        Self::load_from_bytes_and_maybe_verify(so_bytes, Some(Verify::Signature(pk)))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn load_from_bytes_and_maybe_verify(
+        so_bytes: &[u8],
+        verify: Option<Verify>,
+    ) -> Result<Arc<Self>, Error> {
+        use nix::sys::memfd::{memfd_create, MemFdCreateFlag};
+        use std::io::Write;
+        use std::os::unix::io::{AsRawFd, FromRawFd};
+
+        let name = CString::new("lucet-module").expect("name has no nul bytes");
+        let fd = memfd_create(&name, MemFdCreateFlag::MFD_CLOEXEC)?;
+        let mut memfd = unsafe { File::from_raw_fd(fd) };
+        memfd.write_all(so_bytes).map_err(DlError::Io)?;
+
+        // the anonymous file has no name on the filesystem, so it must be opened through procfs
+        let fd_path = format!("/proc/self/fd/{}", memfd.as_raw_fd());
+        Self::load_from_path(Path::new(&fd_path), verify, OpenMode::Now, Some(memfd))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn load_from_bytes_and_maybe_verify(
+        _so_bytes: &[u8],
+        _verify: Option<Verify>,
+    ) -> Result<Arc<Self>, Error> {
+        Err(Error::Unsupported(
+            "loading modules from memory requires memfd_create()".to_string(),
+        ))
     }
 
     fn load_and_maybe_verify<P: AsRef<Path>>(
         so_path: P,
-        pk: Option<PublicKey>,
+        verify: Option<Verify>,
+        mode: OpenMode,
+    ) -> Result<Arc<Self>, Error> {
+        let abs_so_path = so_path.as_ref().canonicalize().map_err(DlError::Io)?;
+        Self::load_from_path(&abs_so_path, verify, mode, None)
+    }
+
+    fn load_from_path(
+        abs_so_path: &Path,
+        verify: Option<Verify>,
+        mode: OpenMode,
+        memfd: Option<File>,
     ) -> Result<Arc<Self>, Error> {
         // Load the dynamic library. The undefined symbols corresponding to the lucet_syscall_
         // functions will be provided by the current executable.  We trust our wasm->dylib compiler
         // to make sure these function calls are the way the dylib can touch memory outside of its
         // stack and heap.
-        let abs_so_path = so_path.as_ref().canonicalize().map_err(DlError::Io)?;
-        let lib = Library::new(abs_so_path.as_os_str()).map_err(DlError::Loading)?;
+        //
+        // The imports are first bound lazily, so that those without a symbol can be named from the
+        // module data rather than reported by the dynamic linker.
+        let lib = match mode {
+            OpenMode::Now | OpenMode::LazyImports => open_with(abs_so_path, libc::RTLD_LAZY)?,
+            OpenMode::Isolated => open_in_new_namespace(abs_so_path)?,
+        };
 
         let serialized_module_ptr = unsafe {
             lib.get::<*const SerializedModule>(LUCET_MODULE_SYM.as_bytes())
@@ -142,9 +381,41 @@ impl DlModule {
                 })?
         };
 
-        let serialized_module: &SerializedModule =
+        let serialized_module: &'static SerializedModule =
             unsafe { serialized_module_ptr.as_ref().unwrap() };
 
+        let module = Self::from_serialized(
+            serialized_module,
+            verify.map(|verify| (abs_so_path, verify)),
+            Some(lib),
+            memfd,
+        )?;
+        match mode {
+            OpenMode::Now => {
+                *module.undefined_imports.lock().unwrap() =
+                    undefined_imports(&module.module.module_data);
+                module.check_imports()?;
+                // opening the library again binds all of its imports now, and closing that
+                // handle leaves the library loaded by the first
+                open_with(abs_so_path, libc::RTLD_NOW)?;
+            }
+            OpenMode::LazyImports => {
+                *module.undefined_imports.lock().unwrap() =
+                    undefined_imports(&module.module.module_data);
+            }
+            OpenMode::Isolated => unsafe { resolve_runtime_symbols(module.fbase)? },
+        }
+        Ok(Arc::new(module))
+    }
+
+    /// Create a module from the `lucet_module` structure of a module whose code is already
+    /// mapped, either by loading `lib` or by linking it into the executable.
+    pub(crate) fn from_serialized(
+        serialized_module: &'static SerializedModule,
+        verify: Option<(&Path, Verify)>,
+        lib: Option<Library>,
+        memfd: Option<File>,
+    ) -> Result<Self, Error> {
         let module_version = serialized_module.version.clone();
 
         let runtime_version =
@@ -152,11 +423,13 @@ impl DlModule {
 
         if !module_version.valid() {
             return Err(lucet_incorrect_module!("reserved bit is not set. This module is likely too old for this lucet-runtime to load."));
-        } else if !runtime_version.compatible_with(&module_version) {
+        } else if !module_version.format_supported() {
             return Err(lucet_incorrect_module!(
-                "version mismatch. module has version {}, while this runtime is version {}",
+                "unsupported module format {}. module has version {}, while this runtime is version {} and supports formats {:?}",
+                module_version.format_version(),
                 module_version,
                 runtime_version,
+                MODULE_FORMAT_VERSIONS.iter().map(|(version, _)| version).collect::<Vec<_>>(),
             ));
         }
 
@@ -173,14 +446,20 @@ impl DlModule {
                 serialized_module.module_data_len as usize,
             )
         };
-        let module_data = ModuleData::deserialize(module_data_slice)?;
+        let module_data =
+            ModuleData::deserialize_format(module_data_slice, module_version.format_version())?;
 
         check_feature_support(module_data.features())?;
 
-        // If a public key has been provided, verify the module signature
+        // If a public key has been provided, verify the module signature, or check its integrity
+        // hash if asked to
         // The TOCTOU issue is unavoidable without reimplenting `dlopen(3)`
-        if let Some(pk) = pk {
-            ModuleSignature::verify(so_path, &pk, &module_data)?;
+        match verify {
+            Some((so_path, Verify::Signature(pk))) => {
+                ModuleSignature::verify(so_path, &pk, &module_data)?
+            }
+            Some((so_path, Verify::Integrity)) => ModuleIntegrity::verify(so_path, &module_data)?,
+            None => (),
         }
 
         let fbase = if let Some(dli) =
@@ -212,8 +491,20 @@ impl DlModule {
             &[]
         };
 
-        Ok(Arc::new(DlModule {
+        // the segments of a module linked into the executable belong to the executable
+        let segments = if lib.is_some() {
+            loaded_segment_ranges(fbase)
+        } else {
+            vec![]
+        };
+        wx::enforce(&segments, "module")?;
+        for &(start, len) in &segments {
+            wx::register(start, len, "module");
+        }
+
+        Ok(DlModule {
             _lib: lib,
+            _memfd: memfd,
             fbase,
             module: lucet_module::Module {
                 version: module_version,
@@ -221,11 +512,26 @@ impl DlModule {
                 tables,
                 function_manifest,
             },
-        }))
+            host_func_trampolines: Mutex::new(None),
+            undefined_imports: Mutex::new(vec![]),
+            wx_segments: segments.iter().map(|&(start, _)| start).collect(),
+        })
+    }
+}
+
+impl Drop for DlModule {
+    fn drop(&mut self) {
+        for &start in &self.wx_segments {
+            wx::unregister(start);
+        }
     }
 }
 
-impl Module for DlModule {}
+impl Module for DlModule {
+    fn custom_sections(&self) -> &[CustomSection<'_>] {
+        self.module.module_data.custom_sections()
+    }
+}
 
 impl ModuleInternal for DlModule {
     fn is_instruction_count_instrumented(&self) -> bool {
@@ -242,7 +548,7 @@ impl ModuleInternal for DlModule {
 
     fn get_sparse_page_data(&self, page: usize) -> Option<&[u8]> {
         if let Some(ref sparse_data) = self.module.module_data.sparse_data() {
-            *sparse_data.get_page(page)
+            sparse_data.get_page(page)
         } else {
             None
         }
@@ -263,6 +569,26 @@ impl ModuleInternal for DlModule {
         }
     }
 
+    fn import_functions(&self) -> &[ImportFunction<'_>] {
+        self.module.module_data.import_functions()
+    }
+
+    fn export_functions(&self) -> &[ExportFunction<'_>] {
+        self.module.module_data.export_functions()
+    }
+
+    fn export_memory_names(&self) -> &[&str] {
+        self.module.module_data.export_memory_names()
+    }
+
+    fn export_tables(&self) -> &[ExportTable<'_>] {
+        self.module.module_data.export_tables()
+    }
+
+    fn tables(&self) -> &[TableSpec] {
+        self.module.module_data.tables()
+    }
+
     fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error> {
         self.module
             .module_data
@@ -308,6 +634,14 @@ impl ModuleInternal for DlModule {
 
     fn addr_details(&self, addr: *const c_void) -> Result<Option<AddrDetails>, Error> {
         if let Some(dli) = dladdr(addr) {
+            let in_module_code = if self._lib.is_some() {
+                dli.dli_fbase as *const c_void == self.fbase
+            } else {
+                // the module shares its object with the executable, so only its functions count
+                self.function_manifest()
+                    .iter()
+                    .any(|spec| spec.contains(addr as u64))
+            };
             let file_name = if dli.dli_fname.is_null() {
                 None
             } else {
@@ -319,7 +653,7 @@ impl ModuleInternal for DlModule {
                 Some(unsafe { CStr::from_ptr(dli.dli_sname).to_owned().into_string()? })
             };
             Ok(Some(AddrDetails {
-                in_module_code: dli.dli_fbase as *const c_void == self.fbase,
+                in_module_code,
                 file_name,
                 sym_name,
             }))
@@ -331,6 +665,351 @@ impl ModuleInternal for DlModule {
     fn get_signature(&self, fn_id: FunctionIndex) -> &Signature {
         self.module.module_data.get_signature(fn_id)
     }
+
+    fn function_name(&self, fn_id: FunctionIndex) -> Option<&str> {
+        self.module
+            .module_data
+            .function_info()
+            .get(fn_id.as_u32() as usize)
+            .and_then(|info| info.name)
+    }
+
+    fn bind_host_func_import(&self, fn_idx: FunctionIndex) -> Result<(), Error> {
+        if self._lib.is_none() {
+            // the static linker has already resolved the imports of a module in the executable
+            return Err(Error::Unsupported(
+                "host functions cannot be bound to the imports of a static module".to_string(),
+            ));
+        }
+        let symbol = self
+            .module
+            .module_data
+            .function_info()
+            .get(fn_idx.as_u32() as usize)
+            .and_then(|info| info.name)
+            .ok_or_else(|| {
+                lucet_incorrect_module!("imported function {} has no symbol", fn_idx.as_u32())
+            })?;
+
+        let mut trampolines = self.host_func_trampolines.lock().unwrap();
+        if trampolines.is_none() {
+            let fn_indices = self.import_functions().iter().map(|f| f.fn_idx).collect();
+            *trampolines = Some(HostFuncTrampolines::new(fn_indices)?);
+        }
+        let trampoline = trampolines
+            .as_ref()
+            .and_then(|t| t.get(fn_idx))
+            .ok_or_else(|| {
+                Error::InvalidArgument("host functions can only be bound to imported functions")
+            })?;
+
+        if unsafe { patch_got(self.fbase, symbol, trampoline)? } == 0 {
+            return Err(lucet_format_err!(
+                "no relocations found for imported function `{}`",
+                symbol
+            ));
+        }
+        self.undefined_imports
+            .lock()
+            .unwrap()
+            .retain(|&undefined| undefined != fn_idx);
+        Ok(())
+    }
+
+    fn check_imports(&self) -> Result<(), Error> {
+        let undefined_imports = self.undefined_imports.lock().unwrap();
+        let import = match undefined_imports.first() {
+            Some(&fn_idx) => self
+                .import_functions()
+                .iter()
+                .find(|import| import.fn_idx == fn_idx)
+                .expect("undefined imports are imports"),
+            None => return Ok(()),
+        };
+        Err(DlError::UnsatisfiedImport {
+            module: import.module.to_owned(),
+            field: import.name.to_owned(),
+            symbol: self.function_name(import.fn_idx).unwrap_or("").to_owned(),
+        }
+        .into())
+    }
+}
+
+/// Point the global offset table entries for `symbol` in the shared object loaded at `fbase` at
+/// `target`, returning the number of entries that were rewritten.
+///
+/// Guest calls to imported functions go through the procedure linkage table, so once the entries
+/// are rewritten the dynamic linker is never asked to resolve `symbol`.
+unsafe fn patch_got(
+    fbase: *const c_void,
+    symbol: &str,
+    target: *const c_void,
+) -> Result<usize, Error> {
+    patch_got_with(fbase, |name| {
+        if name.to_bytes() == symbol.as_bytes() {
+            Ok(Some(target))
+        } else {
+            Ok(None)
+        }
+    })
+}
+
+/// The prefix of the functions the runtime defines for modules to call, such as
+/// `lucet_vmctx_grow_memory`.
+const RUNTIME_SYMBOL_PREFIX: &[u8] = b"lucet_vmctx_";
+
+/// Point the global offset table entries for the runtime functions a module calls at their
+/// definitions in the executable.
+///
+/// The dynamic linker resolves these itself for modules loaded into the default namespace, but
+/// from a new namespace it cannot see the executable's symbols.
+unsafe fn resolve_runtime_symbols(fbase: *const c_void) -> Result<(), Error> {
+    patch_got_with(fbase, |name| {
+        if !name.to_bytes().starts_with(RUNTIME_SYMBOL_PREFIX) {
+            return Ok(None);
+        }
+        let target = libc::dlsym(libc::RTLD_DEFAULT, name.as_ptr());
+        if target.is_null() {
+            Err(Error::SymbolNotFound(format!(
+                "`{}`, which the module calls, is not exported by the executable",
+                name.to_string_lossy()
+            )))
+        } else {
+            Ok(Some(target as *const c_void))
+        }
+    })
+    .map(drop)
+}
+
+/// Point the global offset table entries of the shared object loaded at `fbase` at the targets
+/// `target_of` returns for their symbols, returning the number of entries that were rewritten.
+///
+/// Entries for which `target_of` returns `None` are left as they are.
+#[cfg(target_os = "linux")]
+unsafe fn patch_got_with<F>(fbase: *const c_void, mut target_of: F) -> Result<usize, Error>
+where
+    F: FnMut(&CStr) -> Result<Option<*const c_void>, Error>,
+{
+    use nix::sys::mman::ProtFlags;
+
+    const DT_NULL: i64 = 0;
+    const DT_PLTRELSZ: i64 = 2;
+    const DT_STRTAB: i64 = 5;
+    const DT_SYMTAB: i64 = 6;
+    const DT_RELA: i64 = 7;
+    const DT_RELASZ: i64 = 8;
+    const DT_JMPREL: i64 = 23;
+    const R_X86_64_GLOB_DAT: u64 = 6;
+    const R_X86_64_JUMP_SLOT: u64 = 7;
+
+    #[repr(C)]
+    struct Elf64Dyn {
+        d_tag: i64,
+        d_val: u64,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct Elf64Rela {
+        r_offset: u64,
+        r_info: u64,
+        r_addend: i64,
+    }
+
+    #[allow(dead_code)]
+    #[repr(C)]
+    struct Elf64Sym {
+        st_name: u32,
+        st_info: u8,
+        st_other: u8,
+        st_shndx: u16,
+        st_value: u64,
+        st_size: u64,
+    }
+
+    let segments = find_loaded_segments(fbase)
+        .ok_or_else(|| lucet_format_err!("could not find program headers for module"))?;
+    let dynamic = segments
+        .dynamic
+        .ok_or_else(|| lucet_format_err!("module has no dynamic section"))?;
+
+    // glibc relocates these addresses in place when loading the object, but other loaders leave
+    // them relative to the load address
+    let base = segments.base;
+    let addr = |ptr: u64| {
+        if (ptr as usize) < base {
+            base + ptr as usize
+        } else {
+            ptr as usize
+        }
+    };
+
+    let (mut strtab, mut symtab) = (0, 0);
+    let (mut rela, mut relasz, mut jmprel, mut pltrelsz) = (0, 0, 0, 0);
+    let mut entry = dynamic as *const Elf64Dyn;
+    while (*entry).d_tag != DT_NULL {
+        let val = (*entry).d_val;
+        match (*entry).d_tag {
+            DT_STRTAB => strtab = addr(val),
+            DT_SYMTAB => symtab = addr(val),
+            DT_RELA => rela = addr(val),
+            DT_RELASZ => relasz = val as usize,
+            DT_JMPREL => jmprel = addr(val),
+            DT_PLTRELSZ => pltrelsz = val as usize,
+            _ => (),
+        }
+        entry = entry.add(1);
+    }
+    if strtab == 0 || symtab == 0 {
+        return Err(lucet_format_err!("module has no dynamic symbol table"));
+    }
+
+    let relocs = [(rela, relasz), (jmprel, pltrelsz)];
+    let mut patched = 0;
+    for (start, size) in relocs.iter().filter(|(start, _)| *start != 0) {
+        let relas = slice::from_raw_parts(
+            *start as *const Elf64Rela,
+            size / std::mem::size_of::<Elf64Rela>(),
+        );
+        for rela in relas {
+            let r_type = rela.r_info & 0xffff_ffff;
+            if r_type != R_X86_64_JUMP_SLOT && r_type != R_X86_64_GLOB_DAT {
+                continue;
+            }
+            let sym = &*(symtab as *const Elf64Sym).add((rela.r_info >> 32) as usize);
+            let name = CStr::from_ptr((strtab + sym.st_name as usize) as *const libc::c_char);
+            let target = match target_of(name)? {
+                Some(target) => target,
+                None => continue,
+            };
+
+            let slot = (base + rela.r_offset as usize) as *mut *const c_void;
+            // entries covered by RELRO are read-only once the object has been loaded
+            let in_relro = segments
+                .relro
+                .map(|(start, len)| (slot as usize) >= start && (slot as usize) < start + len)
+                .unwrap_or(false);
+            if in_relro {
+                let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+                let page = ((slot as usize) & !(page_size - 1)) as *mut c_void;
+                mprotect(
+                    page,
+                    page_size,
+                    ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                )?;
+                *slot = target;
+                mprotect(page, page_size, ProtFlags::PROT_READ)?;
+            } else {
+                *slot = target;
+            }
+            patched += 1;
+        }
+    }
+    Ok(patched)
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn patch_got_with<F>(_fbase: *const c_void, _target_of: F) -> Result<usize, Error>
+where
+    F: FnMut(&CStr) -> Result<Option<*const c_void>, Error>,
+{
+    Err(Error::Unsupported(
+        "host functions are only supported on Linux".to_owned(),
+    ))
+}
+
+#[cfg(target_os = "linux")]
+struct LoadedSegments {
+    /// The address that the object's virtual addresses are relative to
+    base: usize,
+    dynamic: Option<usize>,
+    relro: Option<(usize, usize)>,
+    /// The start and length of each loadable segment
+    loads: Vec<(usize, usize)>,
+}
+
+/// The start and length of each loadable segment of the object containing `fbase`.
+#[cfg(target_os = "linux")]
+fn loaded_segment_ranges(fbase: *const c_void) -> Vec<(usize, usize)> {
+    find_loaded_segments(fbase)
+        .map(|segments| segments.loads)
+        .unwrap_or_default()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn loaded_segment_ranges(_fbase: *const c_void) -> Vec<(usize, usize)> {
+    vec![]
+}
+
+/// Find the program headers of the loaded object containing `fbase`.
+#[cfg(target_os = "linux")]
+fn find_loaded_segments(fbase: *const c_void) -> Option<LoadedSegments> {
+    const PT_LOAD: u32 = 1;
+    const PT_DYNAMIC: u32 = 2;
+    const PT_GNU_RELRO: u32 = 0x6474_e552;
+
+    struct Search {
+        fbase: usize,
+        found: Option<LoadedSegments>,
+    }
+
+    unsafe extern "C" fn callback(
+        info: *mut libc::dl_phdr_info,
+        _size: libc::size_t,
+        data: *mut c_void,
+    ) -> libc::c_int {
+        let search = &mut *(data as *mut Search);
+        let info = &*info;
+        let base = info.dlpi_addr as usize;
+        let phdrs = slice::from_raw_parts(info.dlpi_phdr, info.dlpi_phnum as usize);
+        let contains_fbase = phdrs.iter().any(|phdr| {
+            let start = base + phdr.p_vaddr as usize;
+            phdr.p_type == PT_LOAD
+                && search.fbase >= start
+                && search.fbase < start + phdr.p_memsz as usize
+        });
+        if !contains_fbase {
+            return 0;
+        }
+        let mut segments = LoadedSegments {
+            base,
+            dynamic: None,
+            relro: None,
+            loads: vec![],
+        };
+        for phdr in phdrs {
+            match phdr.p_type {
+                PT_LOAD => segments
+                    .loads
+                    .push((base + phdr.p_vaddr as usize, phdr.p_memsz as usize)),
+                PT_DYNAMIC => segments.dynamic = Some(base + phdr.p_vaddr as usize),
+                PT_GNU_RELRO => {
+                    segments.relro = Some((base + phdr.p_vaddr as usize, phdr.p_memsz as usize))
+                }
+                _ => (),
+            }
+        }
+        search.found = Some(segments);
+        1
+    }
+
+    let mut search = Search {
+        fbase: fbase as usize,
+        found: None,
+    };
+    unsafe {
+        libc::dl_iterate_phdr(Some(callback), &mut search as *mut Search as *mut c_void);
+    }
+    search.found
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn mprotect(
+    addr: *mut c_void,
+    length: usize,
+    prot: nix::sys::mman::ProtFlags,
+) -> nix::Result<()> {
+    nix::errno::Errno::result(libc::mprotect(addr, length, prot.bits())).map(drop)
 }
 
 // TODO: PR to nix or libloading?
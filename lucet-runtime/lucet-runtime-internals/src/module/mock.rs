@@ -1,9 +1,12 @@
 use crate::error::Error;
-use crate::module::{AddrDetails, GlobalSpec, HeapSpec, Module, ModuleInternal, TableElement};
+use crate::module::{
+    AddrDetails, CustomSection, ExportFunction, ExportTable, GlobalSpec, HeapSpec, ImportFunction,
+    Module, ModuleInternal, TableElement, TableSpec,
+};
 use libc::c_void;
 use lucet_module::owned::{
-    OwnedExportFunction, OwnedFunctionMetadata, OwnedGlobalSpec, OwnedImportFunction,
-    OwnedLinearMemorySpec, OwnedModuleData, OwnedSparseData,
+    OwnedCustomSection, OwnedExportFunction, OwnedExportTable, OwnedFunctionMetadata,
+    OwnedGlobalSpec, OwnedImportFunction, OwnedLinearMemorySpec, OwnedModuleData, OwnedSparseData,
 };
 use lucet_module::{
     FunctionHandle, FunctionIndex, FunctionPointer, FunctionSpec, ModuleData, ModuleFeatures,
@@ -30,7 +33,10 @@ pub struct MockModuleBuilder {
     function_info: Vec<OwnedFunctionMetadata>,
     imports: Vec<OwnedImportFunction>,
     exports: Vec<OwnedExportFunction>,
+    export_memory_names: Vec<String>,
+    export_table_names: Vec<String>,
     signatures: Vec<Signature>,
+    custom_sections: Vec<OwnedCustomSection>,
 }
 
 impl MockModuleBuilder {
@@ -81,6 +87,20 @@ impl MockModuleBuilder {
         self
     }
 
+    pub fn with_exported_immutable_global(
+        mut self,
+        idx: u32,
+        init_val: i64,
+        export_name: &str,
+    ) -> Self {
+        self.globals.insert(
+            idx as usize,
+            OwnedGlobalSpec::new_def(init_val, vec![export_name.to_string()])
+                .with_mutability(false),
+        );
+        self
+    }
+
     pub fn with_import(mut self, idx: u32, import_module: &str, import_field: &str) -> Self {
         self.globals.insert(
             idx as usize,
@@ -111,6 +131,23 @@ impl MockModuleBuilder {
         self
     }
 
+    pub fn with_exported_memory(mut self, export_name: &str) -> Self {
+        self.export_memory_names.push(export_name.to_string());
+        self
+    }
+
+    /// Export the table made of the elements given to `with_table_element()`.
+    pub fn with_exported_table(mut self, export_name: &str) -> Self {
+        self.export_table_names.push(export_name.to_string());
+        self
+    }
+
+    pub fn with_custom_section(mut self, name: &str, data: &[u8]) -> Self {
+        self.custom_sections
+            .push(OwnedCustomSection::new(name.to_string(), data.to_vec()));
+        self
+    }
+
     pub fn with_table_element(mut self, idx: u32, element: &TableElement) -> Self {
         self.table_elements.insert(idx as usize, element.clone());
         self
@@ -175,6 +212,29 @@ impl MockModuleBuilder {
         self
     }
 
+    pub fn with_import_func(
+        mut self,
+        import_module: &str,
+        import_field: &str,
+        sig: Signature,
+    ) -> Self {
+        let sig_idx = self.record_sig(sig);
+        let fn_idx = FunctionIndex::from_u32(self.function_manifest.len() as u32);
+        self.function_info.push(OwnedFunctionMetadata {
+            signature: sig_idx,
+            name: None,
+        });
+        self.imports.push(OwnedImportFunction {
+            fn_idx,
+            module: import_module.to_string(),
+            name: import_field.to_string(),
+        });
+        // imported functions have no code in the module itself
+        self.function_manifest
+            .push(FunctionSpec::new(0u64, 0u32, 0u64, 0u64));
+        self
+    }
+
     pub fn with_table_func(mut self, table_idx: u32, func_idx: u32, func: FunctionPointer) -> Self {
         self.func_table.insert((table_idx, func_idx), func);
         self
@@ -236,7 +296,17 @@ impl MockModuleBuilder {
             self.signatures,
             ModuleFeatures::none(),
             self.start_func.map(|x| x.0),
-        );
+        )
+        .with_export_memory_names(self.export_memory_names)
+        .with_export_tables(if self.export_table_names.is_empty() {
+            vec![]
+        } else {
+            vec![OwnedExportTable {
+                table_idx: 0,
+                names: self.export_table_names,
+            }]
+        })
+        .with_custom_sections(self.custom_sections);
         let serialized_module_data = owned_module_data
             .to_ref()
             .serialize()
@@ -271,7 +341,11 @@ pub struct MockModule {
 unsafe impl Send for MockModule {}
 unsafe impl Sync for MockModule {}
 
-impl Module for MockModule {}
+impl Module for MockModule {
+    fn custom_sections(&self) -> &[CustomSection<'_>] {
+        self.module_data.custom_sections()
+    }
+}
 
 impl ModuleInternal for MockModule {
     fn is_instruction_count_instrumented(&self) -> bool {
@@ -288,7 +362,7 @@ impl ModuleInternal for MockModule {
 
     fn get_sparse_page_data(&self, page: usize) -> Option<&[u8]> {
         if let Some(ref sparse_data) = self.module_data.sparse_data() {
-            *sparse_data.get_page(page)
+            sparse_data.get_page(page)
         } else {
             None
         }
@@ -302,6 +376,26 @@ impl ModuleInternal for MockModule {
         Ok(&self.table_elements)
     }
 
+    fn import_functions(&self) -> &[ImportFunction<'_>] {
+        self.module_data.import_functions()
+    }
+
+    fn export_functions(&self) -> &[ExportFunction<'_>] {
+        self.module_data.export_functions()
+    }
+
+    fn export_memory_names(&self) -> &[&str] {
+        self.module_data.export_memory_names()
+    }
+
+    fn export_tables(&self) -> &[ExportTable<'_>] {
+        self.module_data.export_tables()
+    }
+
+    fn tables(&self) -> &[TableSpec] {
+        self.module_data.tables()
+    }
+
     fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error> {
         let ptr = *self
             .export_funcs
@@ -325,6 +419,11 @@ impl ModuleInternal for MockModule {
         Ok(self.start_func)
     }
 
+    fn get_table_entry(&self, table_id: u32, elem_idx: u32) -> Result<FunctionHandle, Error> {
+        // mock tables are populated through `with_table_func()`, not table elements
+        self.get_func_from_idx(table_id, elem_idx)
+    }
+
     fn function_manifest(&self) -> &[FunctionSpec] {
         &self.function_manifest
     }
@@ -338,6 +437,13 @@ impl ModuleInternal for MockModule {
     fn get_signature(&self, fn_id: FunctionIndex) -> &Signature {
         self.module_data.get_signature(fn_id)
     }
+
+    fn function_name(&self, fn_id: FunctionIndex) -> Option<&str> {
+        self.module_data
+            .function_info()
+            .get(fn_id.as_u32() as usize)
+            .and_then(|info| info.name)
+    }
 }
 
 pub struct MockExportBuilder {
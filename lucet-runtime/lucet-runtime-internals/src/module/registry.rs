@@ -0,0 +1,119 @@
+use crate::error::Error;
+use crate::instance::{Instance, InstanceInternal};
+use crate::module::Module;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// A named, replaceable reference to the module that new instances should use.
+///
+/// Cloning a handle yields another reference to the same slot, so a module replaced through one
+/// handle is seen by all of them. Instances hold their own `Arc` to the module they were created
+/// from, so replacing a module never affects instances that already exist: they keep running the
+/// old code, which is unloaded once the last of them is dropped.
+#[derive(Clone)]
+pub struct ModuleHandle {
+    name: Arc<str>,
+    current: Arc<RwLock<Arc<dyn Module>>>,
+}
+
+impl ModuleHandle {
+    /// Create a handle that is not part of any registry.
+    pub fn new(name: &str, module: Arc<dyn Module>) -> Self {
+        ModuleHandle {
+            name: name.into(),
+            current: Arc::new(RwLock::new(module)),
+        }
+    }
+
+    /// The name of the module.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The module that new instances should be created from.
+    ///
+    /// Pass this to [`Region::new_instance()`](../region/trait.Region.html#method.new_instance)
+    /// or [`Region::new_instance_builder()`](../region/trait.Region.html#method.new_instance_builder).
+    pub fn current(&self) -> Arc<dyn Module> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Atomically make `module` the one new instances are created from, returning the module it
+    /// replaces.
+    pub fn replace(&self, module: Arc<dyn Module>) -> Arc<dyn Module> {
+        std::mem::replace(&mut *self.current.write().unwrap(), module)
+    }
+
+    /// Check whether `instance` was created from the current module, rather than from one that
+    /// has since been replaced.
+    pub fn is_current(&self, instance: &Instance) -> bool {
+        // compare only data pointers; vtable pointers for the same type may differ
+        let current = &**self.current.read().unwrap() as *const dyn Module as *const ();
+        current == instance.module() as *const dyn Module as *const ()
+    }
+}
+
+/// A set of modules identified by name, each of which can be replaced while instances of it are
+/// running.
+///
+/// This allows guest code to be upgraded without downtime: once a new version of module `X` is
+/// loaded, [`replace()`](#method.replace) it in the registry, and every instance created from
+/// then on runs the new version, while existing instances run the old version to completion.
+#[derive(Default)]
+pub struct ModuleRegistry {
+    modules: RwLock<HashMap<String, ModuleHandle>>,
+}
+
+impl ModuleRegistry {
+    /// Create an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a module to the registry under `name`, returning its handle.
+    ///
+    /// Returns `Error::InvalidArgument` if a module is already registered under `name`; use
+    /// [`replace()`](#method.replace) to change it.
+    pub fn register(&self, name: &str, module: Arc<dyn Module>) -> Result<ModuleHandle, Error> {
+        let mut modules = self.modules.write().unwrap();
+        if modules.contains_key(name) {
+            return Err(Error::InvalidArgument(
+                "a module is already registered under this name",
+            ));
+        }
+        let handle = ModuleHandle::new(name, module);
+        modules.insert(name.to_owned(), handle.clone());
+        Ok(handle)
+    }
+
+    /// Get the handle for the module registered under `name`.
+    pub fn get(&self, name: &str) -> Option<ModuleHandle> {
+        self.modules.read().unwrap().get(name).cloned()
+    }
+
+    /// Get the module new instances of `name` should be created from.
+    pub fn current(&self, name: &str) -> Option<Arc<dyn Module>> {
+        self.get(name).map(|handle| handle.current())
+    }
+
+    /// Atomically replace the module registered under `name`, returning the module it replaces.
+    ///
+    /// Returns `Error::ModuleNotFound` if no module is registered under `name`.
+    pub fn replace(&self, name: &str, module: Arc<dyn Module>) -> Result<Arc<dyn Module>, Error> {
+        self.get(name)
+            .map(|handle| handle.replace(module))
+            .ok_or_else(|| Error::ModuleNotFound(name.to_owned()))
+    }
+
+    /// Remove the module registered under `name` from the registry, returning its handle.
+    ///
+    /// Handles already given out keep working, but are no longer reachable through the registry.
+    pub fn remove(&self, name: &str) -> Option<ModuleHandle> {
+        self.modules.write().unwrap().remove(name)
+    }
+
+    /// The names of the registered modules, in no particular order.
+    pub fn names(&self) -> Vec<String> {
+        self.modules.read().unwrap().keys().cloned().collect()
+    }
+}
@@ -0,0 +1,155 @@
+use crate::error::Error;
+use crate::module::dl::DlModule;
+use crate::module::{
+    AddrDetails, CustomSection, ExportFunction, ExportTable, FunctionHandle, FunctionIndex,
+    FunctionSpec, GlobalSpec, HeapSpec, ImportFunction, Module, ModuleInternal, TableElement,
+    TableSpec,
+};
+use libc::c_void;
+use lucet_module::{SerializedModule, Signature};
+use std::sync::Arc;
+
+/// A Lucet module linked directly into the executable, for environments that do not permit
+/// loading code at runtime.
+///
+/// Compile the module to an object file rather than a shared object with `lucetc --emit obj`,
+/// archive it with `ar rcs libguest.a guest.o`, and link the archive into the embedder, for
+/// example by printing `cargo:rustc-link-lib=static=guest` from a build script. The module is
+/// then registered with the runtime using
+/// [`lucet_static_module!`](../macro.lucet_static_module.html), which never calls `dlopen()`.
+///
+/// Every module defines a `lucet_module` symbol, so to link several modules into the same
+/// executable, give each a distinct prefix with `lucetc --symbol-prefix`.
+///
+/// The imports of a static module are resolved by the static linker, so they must be provided by
+/// `extern "C"` functions in the executable; host functions registered with a
+/// [`Linker`](../linker/struct.Linker.html) cannot be bound to them.
+pub struct StaticModule {
+    inner: DlModule,
+}
+
+impl StaticModule {
+    /// Create a module from the `lucet_module` symbol of a module linked into the executable.
+    ///
+    /// Prefer [`lucet_static_module!`](../macro.lucet_static_module.html), which declares the
+    /// symbol and calls this function.
+    ///
+    /// # Safety
+    ///
+    /// `serialized_module` must be the `lucet_module` symbol defined by an object produced by
+    /// `lucetc`, and the rest of that object must be linked into the executable.
+    pub unsafe fn from_serialized(
+        serialized_module: &'static SerializedModule,
+    ) -> Result<Arc<Self>, Error> {
+        let inner = DlModule::from_serialized(serialized_module, None, None, None)?;
+        Ok(Arc::new(StaticModule { inner }))
+    }
+}
+
+/// Register a module linked into the executable, returning a
+/// `Result<Arc<StaticModule>, Error>`.
+///
+/// The argument is the name of the module's `lucet_module` symbol, including any prefix given
+/// with `lucetc --symbol-prefix`:
+///
+/// ```ignore
+/// use lucet_runtime::lucet_static_module;
+///
+/// let module = lucet_static_module!(guest_lucet_module).expect("module is valid");
+/// ```
+#[macro_export]
+macro_rules! lucet_static_module {
+    () => {
+        $crate::lucet_static_module!(lucet_module)
+    };
+    ( $sym:ident ) => {{
+        extern "C" {
+            static $sym: $crate::module::SerializedModule;
+        }
+        unsafe { $crate::module::StaticModule::from_serialized(&$sym) }
+    }};
+}
+
+impl Module for StaticModule {
+    fn custom_sections(&self) -> &[CustomSection<'_>] {
+        self.inner.custom_sections()
+    }
+}
+
+impl ModuleInternal for StaticModule {
+    fn is_instruction_count_instrumented(&self) -> bool {
+        self.inner.is_instruction_count_instrumented()
+    }
+
+    fn heap_spec(&self) -> Option<&HeapSpec> {
+        self.inner.heap_spec()
+    }
+
+    fn globals(&self) -> &[GlobalSpec<'_>] {
+        self.inner.globals()
+    }
+
+    fn get_sparse_page_data(&self, page: usize) -> Option<&[u8]> {
+        self.inner.get_sparse_page_data(page)
+    }
+
+    fn sparse_page_data_len(&self) -> usize {
+        self.inner.sparse_page_data_len()
+    }
+
+    fn table_elements(&self) -> Result<&[TableElement], Error> {
+        self.inner.table_elements()
+    }
+
+    fn import_functions(&self) -> &[ImportFunction<'_>] {
+        self.inner.import_functions()
+    }
+
+    fn export_functions(&self) -> &[ExportFunction<'_>] {
+        self.inner.export_functions()
+    }
+
+    fn export_memory_names(&self) -> &[&str] {
+        self.inner.export_memory_names()
+    }
+
+    fn export_tables(&self) -> &[ExportTable<'_>] {
+        self.inner.export_tables()
+    }
+
+    fn tables(&self) -> &[TableSpec] {
+        self.inner.tables()
+    }
+
+    fn bind_host_func_import(&self, fn_idx: FunctionIndex) -> Result<(), Error> {
+        self.inner.bind_host_func_import(fn_idx)
+    }
+
+    fn get_export_func(&self, sym: &str) -> Result<FunctionHandle, Error> {
+        self.inner.get_export_func(sym)
+    }
+
+    fn get_func_from_idx(&self, table_id: u32, func_id: u32) -> Result<FunctionHandle, Error> {
+        self.inner.get_func_from_idx(table_id, func_id)
+    }
+
+    fn get_start_func(&self) -> Result<Option<FunctionHandle>, Error> {
+        self.inner.get_start_func()
+    }
+
+    fn function_manifest(&self) -> &[FunctionSpec] {
+        self.inner.function_manifest()
+    }
+
+    fn addr_details(&self, addr: *const c_void) -> Result<Option<AddrDetails>, Error> {
+        self.inner.addr_details(addr)
+    }
+
+    fn get_signature(&self, fn_id: FunctionIndex) -> &Signature {
+        self.inner.get_signature(fn_id)
+    }
+
+    fn function_name(&self, fn_id: FunctionIndex) -> Option<&str> {
+        self.inner.function_name(fn_id)
+    }
+}
@@ -0,0 +1,195 @@
+//! Quotas on the resources used by a set of instances together.
+//!
+//! [`Limits`](../alloc/struct.Limits.html) bound the resources of each instance on its own, and a
+//! region's capacity bounds the number of instances in it. A [`Quota`](struct.Quota.html) instead
+//! bounds the total heap memory and number of instances of every instance it is attached to,
+//! across regions, so that each tenant of a shared process can be given its own budget.
+//!
+//! Instances are attached to a quota when they are built, with
+//! [`InstanceBuilder::with_quota()`](../region/struct.InstanceBuilder.html#method.with_quota).
+//! Building an instance that would exceed the quota fails with `Error::QuotaExceeded`, as does
+//! growing the heap of an attached instance beyond it, in which case the guest's `memory.grow`
+//! returns `-1`. The resources of an instance are returned to its quota when it is dropped, and
+//! the heap memory it grew into when it is reset.
+//!
+//! The rate at which a single instance makes hostcalls is limited separately, with
+//! [`InstanceBuilder::with_hostcall_rate_limit()`](../region/struct.InstanceBuilder.html#method.with_hostcall_rate_limit).
+//! An instance that makes more hostcalls in a second than the limit is terminated with
+//! `TerminationDetails::HostcallRateExceeded` by the first hostcall over it.
+
+use crate::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A budget of heap memory and instances shared by every instance attached to it.
+///
+/// The quota can be cloned and sent to other threads; all clones refer to the same budget.
+#[derive(Clone, Debug)]
+pub struct Quota {
+    inner: Arc<QuotaInner>,
+}
+
+#[derive(Debug)]
+struct QuotaInner {
+    max_heap_size: usize,
+    max_instances: usize,
+    usage: Mutex<QuotaUsage>,
+}
+
+/// The resources in use by the instances attached to a `Quota`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct QuotaUsage {
+    /// The total size of the heaps of the instances, in bytes.
+    pub heap_size: usize,
+    /// The number of instances.
+    pub instances: usize,
+}
+
+impl Quota {
+    /// Create a quota for at most `max_instances` instances, whose heaps are at most
+    /// `max_heap_size` bytes in total.
+    pub fn new(max_heap_size: usize, max_instances: usize) -> Self {
+        Quota {
+            inner: Arc::new(QuotaInner {
+                max_heap_size,
+                max_instances,
+                usage: Mutex::new(QuotaUsage::default()),
+            }),
+        }
+    }
+
+    /// The maximum total size of the heaps of the attached instances, in bytes.
+    pub fn max_heap_size(&self) -> usize {
+        self.inner.max_heap_size
+    }
+
+    /// The maximum number of attached instances.
+    pub fn max_instances(&self) -> usize {
+        self.inner.max_instances
+    }
+
+    /// The resources currently in use by the attached instances.
+    pub fn usage(&self) -> QuotaUsage {
+        *self.inner.usage.lock().unwrap()
+    }
+
+    /// Charge the quota for a new instance with a heap of `heap_size` bytes.
+    pub(crate) fn charge_instance(&self, heap_size: usize) -> Result<QuotaCharge, Error> {
+        let mut usage = self.inner.usage.lock().unwrap();
+        if usage.instances >= self.inner.max_instances {
+            return Err(Error::QuotaExceeded(format!(
+                "{} instances are already attached to a quota of {}",
+                usage.instances, self.inner.max_instances
+            )));
+        }
+        self.check_heap_growth(&usage, heap_size)?;
+        usage.instances += 1;
+        usage.heap_size += heap_size;
+        Ok(QuotaCharge {
+            quota: self.clone(),
+            heap_size,
+        })
+    }
+
+    fn check_heap_growth(&self, usage: &QuotaUsage, additional: usize) -> Result<(), Error> {
+        match usage.heap_size.checked_add(additional) {
+            Some(total) if total <= self.inner.max_heap_size => Ok(()),
+            _ => Err(Error::QuotaExceeded(format!(
+                "{} more bytes of heap would exceed the quota of {} bytes, of which {} are in use",
+                additional, self.inner.max_heap_size, usage.heap_size
+            ))),
+        }
+    }
+}
+
+/// The resources an instance has been charged to its `Quota` for, which are returned when this is
+/// dropped.
+#[derive(Debug)]
+pub(crate) struct QuotaCharge {
+    quota: Quota,
+    heap_size: usize,
+}
+
+impl QuotaCharge {
+    pub(crate) fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    /// Change the size of the instance's heap that is charged to the quota, failing if it grows
+    /// beyond the quota.
+    pub(crate) fn set_heap_size(&mut self, heap_size: usize) -> Result<(), Error> {
+        let mut usage = self.quota.inner.usage.lock().unwrap();
+        if heap_size > self.heap_size {
+            self.quota
+                .check_heap_growth(&usage, heap_size - self.heap_size)?;
+        }
+        usage.heap_size = usage.heap_size - self.heap_size + heap_size;
+        self.heap_size = heap_size;
+        Ok(())
+    }
+}
+
+impl Drop for QuotaCharge {
+    fn drop(&mut self) {
+        let mut usage = self.quota.inner.usage.lock().unwrap();
+        usage.instances -= 1;
+        usage.heap_size -= self.heap_size;
+    }
+}
+
+/// The hostcalls an instance has made in the current second, counted against its limit.
+#[derive(Clone, Debug)]
+pub(crate) struct HostcallRateLimit {
+    max_per_second: u64,
+    window_start: Option<Instant>,
+    calls: u64,
+}
+
+impl HostcallRateLimit {
+    pub(crate) fn new(max_per_second: u64) -> Self {
+        HostcallRateLimit {
+            max_per_second,
+            window_start: None,
+            calls: 0,
+        }
+    }
+
+    pub(crate) fn max_per_second(&self) -> u64 {
+        self.max_per_second
+    }
+
+    /// Count a hostcall made at `now`, returning whether it is within the limit.
+    ///
+    /// The calls are counted in windows of a second from the first call of each window, rather
+    /// than over a sliding second, so up to twice the limit can be made across two windows.
+    pub(crate) fn charge(&mut self, now: Instant) -> bool {
+        match self.window_start {
+            Some(start) if now.duration_since(start) < Duration::from_secs(1) => (),
+            _ => {
+                self.window_start = Some(now);
+                self.calls = 0;
+            }
+        }
+        self.calls += 1;
+        self.calls <= self.max_per_second
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hostcall_rate_limit_windows() {
+        let start = Instant::now();
+        let mut limit = HostcallRateLimit::new(2);
+        assert!(limit.charge(start));
+        assert!(limit.charge(start + Duration::from_millis(10)));
+        assert!(!limit.charge(start + Duration::from_millis(20)));
+        assert!(!limit.charge(start + Duration::from_millis(999)));
+        // a new window starts a second after the first call of the last one
+        assert!(limit.charge(start + Duration::from_secs(1)));
+        assert!(limit.charge(start + Duration::from_millis(1500)));
+        assert!(!limit.charge(start + Duration::from_millis(1999)));
+    }
+}
@@ -1,13 +1,25 @@
 pub mod mmap;
+pub mod mpk;
 
 #[cfg(all(target_os = "linux", feature = "uffd"))]
 pub mod uffd;
 
+mod stats;
+
+pub use self::mpk::protection_keys_supported;
+pub(crate) use self::stats::module_id;
+pub use self::stats::{OccupancyEvent, RegionAccounting, RegionStats};
+
 use crate::alloc::{Alloc, AllocStrategy, Limits, Slot};
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
-use crate::instance::InstanceHandle;
+use crate::instance::{
+    HostPanicPolicy, ImportedGlobals, InstanceGroup, InstanceHandle, StartPolicy,
+};
+use crate::linker::Linker;
 use crate::module::Module;
+use crate::quota::Quota;
+use crate::val::Val;
 use std::any::Any;
 use std::sync::Arc;
 
@@ -47,6 +59,32 @@ pub trait Region: RegionInternal {
 
     /// Return the total instance slot capacity of the region.
     fn capacity(&self) -> usize;
+
+    /// Return a snapshot of the region's occupancy and memory use, including the number of
+    /// instances of each module.
+    fn stats(&self) -> RegionStats {
+        self.accounting().stats(self.capacity(), self.get_limits())
+    }
+
+    /// Set a callback to run whenever the number of used slots in the region reaches one of
+    /// `thresholds` from below, or falls below it from above.
+    ///
+    /// The callback runs on the thread that created or dropped the instance, after the region's
+    /// own bookkeeping is done, so it may call `Region::stats()`. It replaces any previously set
+    /// callback.
+    fn set_occupancy_callback(
+        &self,
+        thresholds: Vec<usize>,
+        callback: Box<dyn Fn(OccupancyEvent) + Send + Sync>,
+    ) {
+        self.accounting()
+            .set_occupancy_callback(Some((thresholds, Arc::from(callback))));
+    }
+
+    /// Remove the callback set by `Region::set_occupancy_callback()`, if any.
+    fn clear_occupancy_callback(&self) {
+        self.accounting().set_occupancy_callback(None);
+    }
 }
 
 /// A `RegionInternal` is a collection of `Slot`s which are managed as a whole.
@@ -71,6 +109,14 @@ pub trait RegionInternal: Send + Sync {
     /// Get the runtime memory size limits
     fn get_limits(&self) -> &Limits;
 
+    /// Get the `HostPanicPolicy` of new instances, unless their builder overrides it.
+    fn host_panic_policy(&self) -> HostPanicPolicy {
+        HostPanicPolicy::default()
+    }
+
+    /// Get the bookkeeping used to implement `Region::stats()`.
+    fn accounting(&self) -> &RegionAccounting;
+
     fn as_dyn_internal(&self) -> &dyn RegionInternal;
 }
 
@@ -87,6 +133,121 @@ pub trait RegionCreate: Region {
     fn create(instance_capacity: usize, limits: &Limits) -> Result<Arc<Self>, Error>;
 }
 
+/// What a region does with the memory of an instance's slot when the instance is dropped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecommitPolicy {
+    /// Release the slot's pages back to the operating system before the slot can be reused.
+    ///
+    /// This is the default, and keeps the resident memory of a region proportional to the number
+    /// of live instances, at the cost of doing the work on the thread that drops the instance and
+    /// page-faulting the memory back in for the next instance.
+    Immediate,
+    /// Make the slot's memory inaccessible immediately, and release its pages on a background
+    /// thread owned by the region.
+    ///
+    /// The slot becomes available again once its pages are released, or sooner if the region
+    /// would otherwise be full, in which case the instantiating thread releases them itself.
+    Background,
+    /// Zero the slot's memory in place without releasing its pages, so that the next instance in
+    /// the slot does not page-fault when it first touches them.
+    ///
+    /// New instances prefer a slot last used by an instance of the same module, whose heap is
+    /// most likely to already be the right size.
+    KeepWarm,
+}
+
+impl Default for DecommitPolicy {
+    fn default() -> Self {
+        DecommitPolicy::Immediate
+    }
+}
+
+/// Options for a region beyond its capacity and [`Limits`](struct.Limits.html).
+///
+/// Not every region supports every option; see the documentation of the region's constructor.
+#[derive(Clone, Debug, Default)]
+pub struct RegionOptions {
+    pub(crate) decommit_policy: DecommitPolicy,
+    pub(crate) mlock: bool,
+    pub(crate) protection_keys: bool,
+    pub(crate) heap_poisoning: bool,
+    pub(crate) host_panic_policy: HostPanicPolicy,
+}
+
+impl RegionOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set what the region does with the memory of dropped instances.
+    ///
+    /// Defaults to `DecommitPolicy::Immediate`.
+    pub fn with_decommit_policy(mut self, decommit_policy: DecommitPolicy) -> Self {
+        self.decommit_policy = decommit_policy;
+        self
+    }
+
+    /// Set whether the accessible heap and stack pages of each instance are locked into memory
+    /// with `mlock()`, so that the instance never takes a major page fault on them.
+    ///
+    /// Locking requires either the `CAP_IPC_LOCK` capability, or a `RLIMIT_MEMLOCK` large enough
+    /// for the heaps and stacks of all instances in the process; creating or growing an instance
+    /// beyond that fails with `Error::MemoryLockFailed`.
+    ///
+    /// Defaults to `false`.
+    pub fn with_mlock(mut self, mlock: bool) -> Self {
+        self.mlock = mlock;
+        self
+    }
+
+    /// Set whether the heap of each instance is tagged with a memory protection key, so that
+    /// while one instance runs, neither it nor its hostcalls can access the heaps of the other
+    /// instances in the region.
+    ///
+    /// Protection keys are only available on some CPUs and kernels; check
+    /// [`protection_keys_supported()`](fn.protection_keys_supported.html) before enabling them,
+    /// as creating a region with them enabled otherwise fails with `Error::Unsupported`. See the
+    /// [`mpk`](mpk/index.html) module for the extent of the isolation they provide. Only
+    /// `MmapRegion` supports protection keys, and only on x86-64 Linux; `UffdRegion` rejects
+    /// them with `Error::Unsupported`.
+    ///
+    /// Defaults to `false`.
+    pub fn with_protection_keys(mut self, protection_keys: bool) -> Self {
+        self.protection_keys = protection_keys;
+        self
+    }
+
+    /// Set whether heap memory is filled with a poison pattern when it is released, and checked
+    /// for modifications before it is reused, to catch embedders that write through pointers into
+    /// the heaps of dropped or reset instances.
+    ///
+    /// The heap of a dropped instance stays mapped and writable until its slot is reused, at which
+    /// point creating the new instance fails with `Error::HeapPoisonCorrupted` if any of it was
+    /// written. The pages a heap grew into are poisoned when the instance is reset, and checked
+    /// when the heap grows into them again.
+    ///
+    /// This keeps the pages of free slots resident and makes creating and dropping instances
+    /// slower, so it is meant for debugging. Only `MmapRegion` supports heap poisoning;
+    /// `UffdRegion` rejects it with `Error::Unsupported`.
+    ///
+    /// Defaults to `false`.
+    pub fn with_heap_poisoning(mut self, heap_poisoning: bool) -> Self {
+        self.heap_poisoning = heap_poisoning;
+        self
+    }
+
+    /// Set what happens when a hostcall of an instance in the region panics; see
+    /// [`HostPanicPolicy`](../instance/enum.HostPanicPolicy.html). Individual instances can
+    /// override this with
+    /// [`InstanceBuilder::with_host_panic_policy()`](struct.InstanceBuilder.html#method.with_host_panic_policy).
+    ///
+    /// Defaults to `HostPanicPolicy::ResumeUnwind`.
+    pub fn with_host_panic_policy(mut self, host_panic_policy: HostPanicPolicy) -> Self {
+        self.host_panic_policy = host_panic_policy;
+        self
+    }
+}
+
 /// A builder for instances; created by
 /// [`Region::new_instance_builder()`](trait.Region.html#method.new_instance_builder).
 pub struct InstanceBuilder<'a> {
@@ -95,6 +256,16 @@ pub struct InstanceBuilder<'a> {
     embed_ctx: CtxMap,
     heap_memory_size_limit: usize,
     alloc_strategy: AllocStrategy,
+    linker: Option<Linker>,
+    imported_globals: ImportedGlobals,
+    measure_stack: bool,
+    deterministic: bool,
+    cpu_time_accounting: bool,
+    start_policy: StartPolicy,
+    host_panic_policy: HostPanicPolicy,
+    group: Option<InstanceGroup>,
+    quota: Option<Quota>,
+    hostcall_rate_limit: Option<u64>,
 }
 
 impl<'a> InstanceBuilder<'a> {
@@ -105,6 +276,16 @@ impl<'a> InstanceBuilder<'a> {
             embed_ctx: CtxMap::default(),
             heap_memory_size_limit: region.get_limits().heap_memory_size,
             alloc_strategy: AllocStrategy::Linear,
+            linker: None,
+            imported_globals: ImportedGlobals::default(),
+            measure_stack: false,
+            deterministic: false,
+            cpu_time_accounting: false,
+            start_policy: StartPolicy::default(),
+            host_panic_policy: region.host_panic_policy(),
+            group: None,
+            quota: None,
+            hostcall_rate_limit: None,
         }
     }
 
@@ -137,13 +318,190 @@ impl<'a> InstanceBuilder<'a> {
         self
     }
 
+    /// Add an embedder context to the built instance under `key`.
+    ///
+    /// Any number of context values of a particular type may exist in the instance, as long as
+    /// they have different keys. If a context value of the same type already exists under `key`,
+    /// it is replaced by the new value.
+    pub fn with_keyed_embed_ctx<T: Any>(mut self, key: impl Into<String>, ctx: T) -> Self {
+        self.embed_ctx.insert_keyed(key.into(), ctx);
+        self
+    }
+
+    /// Reset the embedder contexts of type `T` with `reset` whenever the built instance is reset.
+    ///
+    /// See [`Instance::set_embed_ctx_reset()`](struct.Instance.html#method.set_embed_ctx_reset).
+    pub fn with_embed_ctx_reset<T: Any>(mut self, reset: impl Fn(&mut T) + 'static) -> Self {
+        self.embed_ctx.set_reset(reset);
+        self
+    }
+
+    /// Bind the imports of the built instance to the exports of the instances in a `Linker`.
+    ///
+    /// This call is optional. The imports are resolved when the instance is built; if any of them
+    /// cannot be satisfied, building fails with `Error::LinkError`.
+    pub fn with_linker(mut self, linker: &Linker) -> Self {
+        self.linker = Some(linker.clone());
+        self
+    }
+
+    /// Supply `val` as the value of the global the module imports as `module::field`.
+    ///
+    /// Every global the module imports must be supplied with a value of its type, otherwise
+    /// building fails with `Error::LinkError`. The global is set to `val` when the instance is
+    /// created and whenever it is reset. Modules in formats before 10 do not record the types of
+    /// their imported globals, so for them the global has the Wasm type of `val`, and it is up to
+    /// the embedder to supply a value of the right type.
+    pub fn with_global_import<V: Into<Val>>(mut self, module: &str, field: &str, val: V) -> Self {
+        self.imported_globals
+            .insert(module.to_owned(), field.to_owned(), val.into());
+        self
+    }
+
+    /// Measure how much of the guest stack the built instance uses.
+    ///
+    /// This call is optional. When enabled, the whole guest stack is filled with a known pattern
+    /// when the instance is created or reset, and
+    /// [`Instance::memory_stats()`](../instance/struct.Instance.html#method.memory_stats) reports
+    /// the deepest point the stack has reached as `stack_high_water_mark`. Use this to choose a
+    /// `Limits::stack_size` that fits a module, rather than guessing.
+    ///
+    /// Filling the stack touches every one of its pages, which costs time when instances are
+    /// created and defeats the lazy paging of regions like `UffdRegion`, so this is best left off
+    /// outside of profiling.
+    pub fn with_stack_measurement(mut self, measure: bool) -> Self {
+        self.measure_stack = measure;
+        self
+    }
+
+    /// Run the built instance in deterministic mode.
+    ///
+    /// This call is optional. Together with modules compiled with `lucetc --canonicalize-nans`,
+    /// deterministic mode ensures that running an instance twice with the same inputs produces
+    /// the same results:
+    ///
+    /// - Hostcalls defined with `#[lucet_hostcall]` terminate the instance with
+    ///   `TerminationDetails::Nondeterministic` instead of running, unless they are declared with
+    ///   `#[lucet_hostcall(deterministic)]`. So do host functions bound with `Linker::func()`
+    ///   rather than `Linker::deterministic_func()`, and deterministic hostcalls that call
+    ///   [`Vmctx::nondeterministic()`](../vmctx/struct.Vmctx.html#method.nondeterministic) for
+    ///   some of their arguments.
+    /// - Hostcalls that have deterministic alternatives, such as the clock and random number
+    ///   hostcalls of `lucet-wasi`, can check
+    ///   [`Vmctx::is_deterministic()`](../vmctx/struct.Vmctx.html#method.is_deterministic) to use
+    ///   them.
+    ///
+    /// The runtime cannot see into host functions that guests call directly, without the
+    /// attribute, such as those written in C: they run unchecked, so the guarantee only holds for
+    /// modules whose host functions are all defined with the attribute or bound with a `Linker`.
+    ///
+    /// Instances only yield at explicit calls to `Vmctx::yield_*()`, so the sequence of yields,
+    /// and of values resumed with, is part of the inputs of a deterministic instance. The one source
+    /// of nondeterminism the runtime cannot exclude is termination by a `KillSwitch`, which
+    /// depends on timing.
+    pub fn with_deterministic_execution(mut self, deterministic: bool) -> Self {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Account for the CPU time the built instance's guest code uses.
+    ///
+    /// This call is optional. By default, the thread CPU clock is not read, and
+    /// [`Instance::cpu_time()`](../instance/struct.Instance.html#method.cpu_time) is always zero;
+    /// reading it costs a system call on every entry to and exit from guest code.
+    pub fn with_cpu_time_accounting(mut self, cpu_time_accounting: bool) -> Self {
+        self.cpu_time_accounting = cpu_time_accounting;
+        self
+    }
+
+    /// Set when the module's start function runs.
+    ///
+    /// This call is optional. By default, the start function only runs when
+    /// [`Instance::run_start()`](../instance/struct.Instance.html#method.run_start) is called;
+    /// see [`StartPolicy`](../instance/enum.StartPolicy.html) for the alternatives. With
+    /// `StartPolicy::OnInstantiation`, building fails with the error returned by the start function,
+    /// if any.
+    pub fn with_start_policy(mut self, start_policy: StartPolicy) -> Self {
+        self.start_policy = start_policy;
+        self
+    }
+
+    /// Set what happens when a hostcall of the instance panics.
+    ///
+    /// This call is optional. By default, the instance uses the policy of its region, set by
+    /// [`RegionOptions::with_host_panic_policy()`](struct.RegionOptions.html#method.with_host_panic_policy);
+    /// see [`HostPanicPolicy`](../instance/enum.HostPanicPolicy.html) for the alternatives.
+    pub fn with_host_panic_policy(mut self, host_panic_policy: HostPanicPolicy) -> Self {
+        self.host_panic_policy = host_panic_policy;
+        self
+    }
+
+    /// Add the built instance to an [`InstanceGroup`](../instance/struct.InstanceGroup.html), so
+    /// that it is terminated along with the rest of the group.
+    ///
+    /// This call is optional. If the group has already been terminated, the instance is cancelled
+    /// when it first runs, and building fails with `Error::RuntimeTerminated` if the start policy
+    /// is `StartPolicy::OnInstantiation`.
+    pub fn with_group(mut self, group: &InstanceGroup) -> Self {
+        self.group = Some(group.clone());
+        self
+    }
+
+    /// Attach the built instance to a [`Quota`](../quota/struct.Quota.html), which limits the
+    /// resources it uses together with the other instances attached to the quota.
+    ///
+    /// This call is optional. Building fails with `Error::QuotaExceeded` if the quota has no room
+    /// for another instance with the module's initial heap.
+    pub fn with_quota(mut self, quota: &Quota) -> Self {
+        self.quota = Some(quota.clone());
+        self
+    }
+
+    /// Limit the built instance to `max_per_second` hostcalls in each second.
+    ///
+    /// This call is optional. By default, the instance may make any number of hostcalls. The
+    /// hostcall that goes over the limit terminates the instance with
+    /// `TerminationDetails::HostcallRateExceeded` instead of running; this counts hostcalls defined
+    /// with `#[lucet_hostcall]` and host functions bound with a
+    /// [`Linker`](../linker/struct.Linker.html).
+    pub fn with_hostcall_rate_limit(mut self, max_per_second: u64) -> Self {
+        self.hostcall_rate_limit = Some(max_per_second);
+        self
+    }
+
     /// Build the instance.
-    pub fn build(self) -> Result<InstanceHandle, Error> {
-        self.region.new_instance_with(
+    pub fn build(mut self) -> Result<InstanceHandle, Error> {
+        if let Some(linker) = self.linker.take() {
+            let linked = linker.resolve(self.module.as_ref())?;
+            self.embed_ctx.insert(linked);
+        }
+        self.module.check_imports()?;
+        self.embed_ctx.insert(self.imported_globals);
+        let mut inst = self.region.new_instance_with(
             self.module,
             self.embed_ctx,
             self.heap_memory_size_limit,
             self.alloc_strategy,
-        )
+        )?;
+        if let Some(quota) = &self.quota {
+            inst.attach_quota(quota)?;
+        }
+        if self.measure_stack {
+            inst.poison_stack();
+        }
+        inst.set_deterministic(self.deterministic);
+        inst.set_cpu_time_accounting(self.cpu_time_accounting);
+        inst.set_start_policy(self.start_policy);
+        inst.set_host_panic_policy(self.host_panic_policy);
+        if let Some(max_per_second) = self.hostcall_rate_limit {
+            inst.set_hostcall_rate_limit(max_per_second);
+        }
+        if let Some(group) = &self.group {
+            inst.join_group(group);
+        }
+        if self.start_policy == StartPolicy::OnInstantiation {
+            inst.run_start()?;
+        }
+        Ok(inst)
     }
 }
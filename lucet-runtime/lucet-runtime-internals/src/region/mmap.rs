@@ -1,16 +1,59 @@
 use crate::alloc::{instance_heap_offset, Alloc, AllocStrategy, Limits, Slot};
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
-use crate::instance::{new_instance_handle, Instance, InstanceHandle};
+use crate::instance::{new_instance_handle, HostPanicPolicy, Instance, InstanceHandle};
 use crate::module::Module;
-use crate::region::{Region, RegionCreate, RegionInternal};
+use crate::region::mpk::{self, ProtectionKeys};
+use crate::region::{
+    module_id, DecommitPolicy, Region, RegionAccounting, RegionCreate, RegionInternal,
+    RegionOptions,
+};
 use crate::sysdeps::host_page_size;
+use crate::wx;
 use libc::c_void;
-#[cfg(not(target_os = "linux"))]
 use libc::memset;
 use nix::sys::mman::{madvise, mmap, munmap, MapFlags, MmapAdvise, ProtFlags};
+use std::collections::HashMap;
+use std::ops::Range;
 use std::ptr;
-use std::sync::{Arc, RwLock, Weak};
+use std::sync::{Arc, Condvar, Mutex, RwLock, Weak};
+use std::thread::{self, JoinHandle};
+
+/// The byte that released heap memory is filled with when heap poisoning is enabled.
+const HEAP_POISON: u8 = 0xa5;
+
+/// Where an `MmapRegion` places the instances of a module, as set by
+/// [`MmapRegion::set_slot_affinity()`](struct.MmapRegion.html#method.set_slot_affinity).
+///
+/// Slots are identified by their index, from `0` to the capacity of the region. By default, the
+/// region's `AllocStrategy` picks any free slot, so instances of a module rarely reuse the pages,
+/// or the cache and TLB entries, that earlier instances warmed up.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SlotAffinity {
+    /// Prefer a free slot last used by an instance of the module, falling back to the region's
+    /// `AllocStrategy` among the slots that are not preferred by another module.
+    Preferred,
+    /// Only place instances of the module in the slots with indices in the range, preferring
+    /// those last used by an instance of the module. Creating an instance fails with
+    /// `Error::RegionFull` if none of them are free, even if other slots are. Other modules may
+    /// still use the slots of the range.
+    Pinned(Range<usize>),
+}
+
+/// A free slot that was last used by a module with a slot affinity being given to an instance of
+/// another module, as passed to the callback registered with
+/// [`MmapRegion::set_eviction_callback()`](struct.MmapRegion.html#method.set_eviction_callback).
+#[derive(Clone)]
+pub struct SlotEviction {
+    /// The index of the slot.
+    pub slot: usize,
+    /// The module that last used the slot.
+    pub evicted: Arc<dyn Module>,
+    /// The module of the new instance in the slot.
+    pub module: Arc<dyn Module>,
+}
+
+type EvictionCallback = Arc<dyn Fn(&SlotEviction) + Send + Sync>;
 
 /// A [`Region`](../trait.Region.html) backed by `mmap`.
 ///
@@ -55,14 +98,37 @@ use std::sync::{Arc, RwLock, Weak};
 /// ```
 pub struct MmapRegion {
     capacity: usize,
-    freelist: RwLock<Vec<Slot>>,
+    freelist: Arc<RwLock<Vec<Slot>>>,
     limits: Limits,
     min_heap_alignment: usize,
+    accounting: RegionAccounting,
+    decommit_policy: DecommitPolicy,
+    mlock: bool,
+    /// The memory protection keys the slots' heaps are tagged with, if enabled
+    protection_keys: Option<ProtectionKeys>,
+    /// For `DecommitPolicy::Background`, the thread releasing the pages of dropped slots
+    decommitter: Option<Decommitter>,
+    /// The module last instantiated in each free slot
+    warm_slots: Mutex<HashMap<usize, usize>>,
+    /// The index of each slot, by the address of its start
+    slot_indices: RwLock<HashMap<usize, usize>>,
+    /// The slot affinity of each module that has one, by module id
+    affinities: Mutex<HashMap<usize, (Arc<dyn Module>, SlotAffinity)>>,
+    eviction_callback: Mutex<Option<EvictionCallback>>,
+    heap_poisoning: bool,
+    host_panic_policy: HostPanicPolicy,
+    /// If heap poisoning is enabled, the range of each slot's heap that is filled with poison
+    poisoned_heaps: Mutex<HashMap<usize, (usize, usize)>>,
 }
 
 impl Region for MmapRegion {
     fn free_slots(&self) -> usize {
-        self.freelist.read().unwrap().len()
+        let pending = self
+            .decommitter
+            .as_ref()
+            .map(|d| d.pending_len())
+            .unwrap_or(0);
+        self.freelist.read().unwrap().len() + pending
     }
 
     fn used_slots(&self) -> usize {
@@ -91,9 +157,17 @@ impl RegionInternal for MmapRegion {
         let slot;
         {
             let mut free_slot_vector = self.freelist.write().unwrap();
-            let slot_index = alloc_strategy.next(free_slot_vector.len(), self.capacity)?;
+            if free_slot_vector.is_empty() {
+                // rather than failing while slots are waiting to be released in the background,
+                // release one of them now
+                if let Some(pending) = self.decommitter.as_ref().and_then(|d| d.take_pending()) {
+                    free_slot_vector.push(pending);
+                }
+            }
+            let slot_index = self.select_slot(&module, &free_slot_vector, &mut alloc_strategy)?;
             slot = free_slot_vector.swap_remove(slot_index);
         }
+        self.take_warm_slot(&slot, &module);
 
         assert_eq!(
             slot.heap as usize % host_page_size(),
@@ -101,6 +175,23 @@ impl RegionInternal for MmapRegion {
             "heap must be page-aligned"
         );
 
+        if self.heap_poisoning {
+            let res = self.unpoison_heap(&slot, 0, slot.limits.heap_address_space_size);
+            // the heap is made accessible again by the `reset` call in `new_instance_handle`
+            unsafe {
+                mprotect(
+                    slot.heap,
+                    slot.limits.heap_address_space_size,
+                    ProtFlags::PROT_NONE,
+                )
+                .expect("mprotect() call succeeds");
+            }
+            if let Err(e) = res {
+                self.freelist.write().unwrap().push(slot);
+                return Err(e);
+            }
+        }
+
         for (ptr, len) in [
             // make the stack read/writable
             (slot.stack, limits.stack_size),
@@ -117,7 +208,24 @@ impl RegionInternal for MmapRegion {
             };
         }
 
-        // note: the initial heap will be made read/writable when `new_instance_handle` calls `reset`
+        if self.mlock {
+            if let Err(e) = lock_memory(slot.stack, limits.stack_size) {
+                for (ptr, len) in slot_sections(&slot, 0).iter() {
+                    unsafe {
+                        mprotect(*ptr, *len, ProtFlags::PROT_NONE)
+                            .expect("mprotect() call succeeds");
+                    }
+                }
+                self.freelist.write().unwrap().push(slot);
+                return Err(e);
+            }
+        }
+
+        // note: the initial heap will be made read/writable (and locked, if configured) when
+        // `new_instance_handle` calls `reset`
+
+        self.accounting
+            .instance_created(slot.start as usize, module.clone(), self.capacity);
 
         let inst_ptr = slot.start as *mut Instance;
 
@@ -155,74 +263,117 @@ impl RegionInternal for MmapRegion {
             panic!("heap is not page-aligned");
         }
 
-        // clear and disable access to the heap, stack, globals, and sigstack
-        for (ptr, len) in [
-            // We don't ever shrink the heap, so we only need to zero up until the accessible size
-            (slot.heap, alloc.heap_accessible_size),
-            (slot.stack, slot.limits.stack_size),
-            (slot.globals, slot.limits.globals_size),
-            (slot.sigstack, slot.limits.signal_stack_size),
-        ]
-        .iter()
-        {
-            // eprintln!("setting none {:p}[{:x}]", *ptr, len);
-            unsafe {
-                // MADV_DONTNEED is not guaranteed to clear pages on non-Linux systems
-                #[cfg(not(target_os = "linux"))]
-                {
-                    mprotect(*ptr, *len, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
-                        .expect("mprotect succeeds during drop");
-                    memset(*ptr, 0, *len);
+        if self.mlock {
+            // locked pages cannot be released with `madvise()`
+            for (ptr, len) in slot_sections(&slot, alloc.heap_accessible_size).iter() {
+                unsafe {
+                    munlock(*ptr, *len).expect("munlock succeeds during drop");
                 }
-                mprotect(*ptr, *len, ProtFlags::PROT_NONE).expect("mprotect succeeds during drop");
-                madvise(*ptr, *len, MmapAdvise::MADV_DONTNEED)
-                    .expect("madvise succeeds during drop");
             }
         }
 
-        self.freelist.write().unwrap().push(slot);
+        if let Some(module) = self.accounting.slot_module_id(slot.start as usize) {
+            self.warm_slots
+                .lock()
+                .unwrap()
+                .insert(slot.start as usize, module);
+        }
+        self.accounting
+            .instance_dropped(slot.start as usize, self.capacity);
+
+        let heap_accessible_size = if self.heap_poisoning {
+            self.poison_freed_heap(&slot, alloc.heap_accessible_size);
+            // the poisoned heap stays writable, so that writes through stale pointers land in it
+            0
+        } else {
+            alloc.heap_accessible_size
+        };
+
+        match self.decommit_policy {
+            DecommitPolicy::Immediate => {
+                decommit_slot(&slot, heap_accessible_size);
+                self.freelist.write().unwrap().push(slot);
+            }
+            DecommitPolicy::Background => {
+                // disable access right away, but leave the expensive part for later
+                for (ptr, len) in slot_sections(&slot, heap_accessible_size).iter() {
+                    unsafe {
+                        mprotect(*ptr, *len, ProtFlags::PROT_NONE)
+                            .expect("mprotect succeeds during drop");
+                    }
+                }
+                self.decommitter
+                    .as_ref()
+                    .expect("background decommit policy has a decommitter")
+                    .push(slot, heap_accessible_size);
+            }
+            DecommitPolicy::KeepWarm => {
+                scrub_slot(&slot, heap_accessible_size);
+                self.freelist.write().unwrap().push(slot);
+            }
+        }
     }
 
     fn expand_heap(&self, slot: &Slot, start: u32, len: u32) -> Result<(), Error> {
+        let ptr = (slot.heap as usize + start as usize) as *mut c_void;
         unsafe {
             mprotect(
-                (slot.heap as usize + start as usize) as *mut c_void,
+                ptr,
                 len as usize,
                 ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
             )?;
         }
+        if self.mlock {
+            if let Err(e) = lock_memory(ptr, len as usize) {
+                // the expansion failed, so the guest must not be able to access the new pages
+                unsafe { mprotect(ptr, len as usize, ProtFlags::PROT_NONE)? };
+                return Err(e);
+            }
+        }
+        if self.heap_poisoning {
+            self.unpoison_heap(slot, start as usize, start as usize + len as usize)?;
+        }
+        self.accounting
+            .heap_resized(slot.start as usize, start as usize + len as usize);
         Ok(())
     }
 
     fn reset_heap(&self, alloc: &mut Alloc, module: &dyn Module) -> Result<(), Error> {
         let heap = alloc.slot().heap;
 
+        let initial_size = module
+            .heap_spec()
+            .map(|h| h.initial_size as usize)
+            .unwrap_or(0);
+
         if alloc.heap_accessible_size > 0 {
-            // zero the whole heap, if any of it is currently accessible
-            let heap_size = alloc.slot().limits.heap_address_space_size;
+            // zero the accessible part of the heap; the heap never shrinks, so no page beyond it
+            // can have been written since the last reset
+            let accessible_size = alloc.heap_accessible_size;
 
             unsafe {
-                // `mprotect()` and `madvise()` are sufficient to zero a page on Linux,
-                // but not necessarily on all POSIX operating systems, and on macOS in particular.
+                // `madvise()` is sufficient to zero a page on Linux, but not necessarily on all
+                // POSIX operating systems, and on macOS in particular.
                 #[cfg(not(target_os = "linux"))]
-                {
+                memset(heap, 0, accessible_size);
+                if self.mlock {
+                    munlock(heap, accessible_size)?;
+                }
+                madvise(heap, accessible_size, MmapAdvise::MADV_DONTNEED)?;
+                if self.heap_poisoning && accessible_size > initial_size {
+                    self.poison_reset_heap(alloc.slot(), initial_size, accessible_size);
+                }
+                // only the pages the heap grew into need to become inaccessible again
+                if accessible_size > initial_size {
                     mprotect(
-                        heap,
-                        alloc.heap_accessible_size,
-                        ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+                        (heap as usize + initial_size) as *mut c_void,
+                        accessible_size - initial_size,
+                        ProtFlags::PROT_NONE,
                     )?;
-                    memset(heap, 0, alloc.heap_accessible_size);
                 }
-                mprotect(heap, heap_size, ProtFlags::PROT_NONE)?;
-                madvise(heap, heap_size, MmapAdvise::MADV_DONTNEED)?;
             }
         }
 
-        let initial_size = module
-            .heap_spec()
-            .map(|h| h.initial_size as usize)
-            .unwrap_or(0);
-
         // reset the heap to the initial size, and mprotect those pages appropriately
         if initial_size > 0 {
             unsafe {
@@ -235,6 +386,11 @@ impl RegionInternal for MmapRegion {
         }
         alloc.heap_accessible_size = initial_size;
         alloc.heap_inaccessible_size = alloc.slot().limits.heap_address_space_size - initial_size;
+        self.accounting
+            .heap_resized(alloc.slot().start as usize, initial_size);
+        if self.mlock {
+            lock_memory(heap, initial_size)?;
+        }
 
         // Initialize the heap using the module sparse page data. There cannot be more pages in the
         // sparse page data than will fit in the initial heap size.
@@ -273,6 +429,14 @@ impl RegionInternal for MmapRegion {
         &self.limits
     }
 
+    fn host_panic_policy(&self) -> HostPanicPolicy {
+        self.host_panic_policy
+    }
+
+    fn accounting(&self) -> &RegionAccounting {
+        &self.accounting
+    }
+
     fn as_dyn_internal(&self) -> &dyn RegionInternal {
         self
     }
@@ -280,7 +444,9 @@ impl RegionInternal for MmapRegion {
 
 impl Drop for MmapRegion {
     fn drop(&mut self) {
-        for slot in self.freelist.get_mut().unwrap().drain(0..) {
+        // let the decommitter finish, so that all of the slots are back on the freelist
+        self.decommitter.take();
+        for slot in self.freelist.write().unwrap().drain(0..) {
             Self::free_slot(slot);
         }
     }
@@ -301,22 +467,7 @@ impl MmapRegion {
     /// The region is returned in an `Arc`, because any instances created from it carry a reference
     /// back to the region.
     pub fn create(instance_capacity: usize, limits: &Limits) -> Result<Arc<Self>, Error> {
-        limits.validate()?;
-
-        let region = Arc::new(MmapRegion {
-            capacity: instance_capacity,
-            freelist: RwLock::new(Vec::with_capacity(instance_capacity)),
-            limits: limits.clone(),
-            min_heap_alignment: 0, // No constaints on heap alignment by default
-        });
-        {
-            let mut freelist = region.freelist.write().unwrap();
-            for _ in 0..instance_capacity {
-                freelist.push(MmapRegion::create_slot(&region)?);
-            }
-        }
-
-        Ok(region)
+        Self::create_with_options(instance_capacity, limits, &RegionOptions::default())
     }
 
     /// Create a new `MmapRegion` that can support a given number instances, each subject to the
@@ -330,8 +481,6 @@ impl MmapRegion {
         limits: &Limits,
         heap_alignment: usize,
     ) -> Result<Arc<Self>, Error> {
-        limits.validate()?;
-
         let is_power_of_2 = (heap_alignment & (heap_alignment - 1)) == 0;
 
         if !is_power_of_2 {
@@ -340,23 +489,307 @@ impl MmapRegion {
             ));
         }
 
+        Self::create_inner(
+            instance_capacity,
+            limits,
+            heap_alignment,
+            &RegionOptions::default(),
+        )
+    }
+
+    /// Create a new `MmapRegion` that can support a given number instances, each subject to the
+    /// same runtime limits, and configured by `options`.
+    ///
+    /// The region is returned in an `Arc`, because any instances created from it carry a reference
+    /// back to the region.
+    pub fn create_with_options(
+        instance_capacity: usize,
+        limits: &Limits,
+        options: &RegionOptions,
+    ) -> Result<Arc<Self>, Error> {
+        // No constaints on heap alignment by default
+        Self::create_inner(instance_capacity, limits, 0, options)
+    }
+
+    fn create_inner(
+        instance_capacity: usize,
+        limits: &Limits,
+        min_heap_alignment: usize,
+        options: &RegionOptions,
+    ) -> Result<Arc<Self>, Error> {
+        limits.validate()?;
+
+        let freelist = Arc::new(RwLock::new(Vec::with_capacity(instance_capacity)));
+        let decommitter = match options.decommit_policy {
+            DecommitPolicy::Background => Some(Decommitter::spawn(freelist.clone())),
+            DecommitPolicy::Immediate | DecommitPolicy::KeepWarm => None,
+        };
+
+        let protection_keys = if options.protection_keys {
+            Some(ProtectionKeys::allocate(instance_capacity)?)
+        } else {
+            None
+        };
+
         let region = Arc::new(MmapRegion {
             capacity: instance_capacity,
-            freelist: RwLock::new(Vec::with_capacity(instance_capacity)),
+            freelist,
             limits: limits.clone(),
-            min_heap_alignment: heap_alignment,
+            min_heap_alignment,
+            accounting: RegionAccounting::default(),
+            decommit_policy: options.decommit_policy,
+            mlock: options.mlock,
+            protection_keys,
+            decommitter,
+            warm_slots: Mutex::new(HashMap::new()),
+            slot_indices: RwLock::new(HashMap::new()),
+            affinities: Mutex::new(HashMap::new()),
+            eviction_callback: Mutex::new(None),
+            heap_poisoning: options.heap_poisoning,
+            host_panic_policy: options.host_panic_policy,
+            poisoned_heaps: Mutex::new(HashMap::new()),
         });
         {
             let mut freelist = region.freelist.write().unwrap();
-            for _ in 0..instance_capacity {
-                freelist.push(MmapRegion::create_slot(&region)?);
+            let mut slot_indices = region.slot_indices.write().unwrap();
+            for index in 0..instance_capacity {
+                let slot = MmapRegion::create_slot(&region, index)?;
+                slot_indices.insert(slot.start as usize, index);
+                freelist.push(slot);
             }
         }
 
         Ok(region)
     }
 
-    fn create_slot(region: &Arc<MmapRegion>) -> Result<Slot, Error> {
+    /// Poison the accessible heap of a dropped instance, leaving all of the slot's poisoned heap
+    /// read/writable.
+    fn poison_freed_heap(&self, slot: &Slot, heap_accessible_size: usize) {
+        // this may run on a different thread than the instance did
+        if let Some(key) = slot.protection_key {
+            key.allow_access();
+        }
+        let mut poisoned_heaps = self.poisoned_heaps.lock().unwrap();
+        // pages poisoned by a reset begin where the accessible heap ends
+        let end = match poisoned_heaps.get(&(slot.start as usize)) {
+            Some(&(_, end)) => end.max(heap_accessible_size),
+            None => heap_accessible_size,
+        };
+        if end == 0 {
+            return;
+        }
+        unsafe {
+            mprotect(slot.heap, end, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
+                .expect("mprotect succeeds during drop");
+            memset(slot.heap, HEAP_POISON as i32, heap_accessible_size);
+        }
+        poisoned_heaps.insert(slot.start as usize, (0, end));
+    }
+
+    /// Poison the part of a heap that is about to become inaccessible because its instance is
+    /// being reset.
+    fn poison_reset_heap(&self, slot: &Slot, start: usize, end: usize) {
+        if let Some(key) = slot.protection_key {
+            key.allow_access();
+        }
+        unsafe {
+            memset(
+                (slot.heap as usize + start) as *mut c_void,
+                HEAP_POISON as i32,
+                end - start,
+            );
+        }
+        let mut poisoned_heaps = self.poisoned_heaps.lock().unwrap();
+        let range = poisoned_heaps
+            .entry(slot.start as usize)
+            .or_insert((start, end));
+        *range = (start, range.1.max(end));
+    }
+
+    /// Check and zero the poisoned part of a slot's heap between `start` and `end`, which is about
+    /// to be reused.
+    ///
+    /// Heaps are only ever reused from the beginning of their poisoned range, so whatever remains
+    /// poisoned past `end` is left in place. The checked memory is left read/writable.
+    fn unpoison_heap(&self, slot: &Slot, start: usize, end: usize) -> Result<(), Error> {
+        let (poison_start, poison_end) = {
+            let mut poisoned_heaps = self.poisoned_heaps.lock().unwrap();
+            let range = match poisoned_heaps.get(&(slot.start as usize)) {
+                Some(&range) => range,
+                None => return Ok(()),
+            };
+            debug_assert!(
+                start <= range.0,
+                "heap is reused from the start of its poison"
+            );
+            if end >= range.1 {
+                poisoned_heaps.remove(&(slot.start as usize));
+            } else if end > range.0 {
+                poisoned_heaps.insert(slot.start as usize, (end, range.1));
+            }
+            (range.0, end.min(range.1))
+        };
+        if poison_end <= poison_start {
+            return Ok(());
+        }
+        if let Some(key) = slot.protection_key {
+            key.allow_access();
+        }
+        let ptr = (slot.heap as usize + poison_start) as *mut c_void;
+        let len = poison_end - poison_start;
+        unsafe {
+            // poison left by a reset is inaccessible
+            mprotect(ptr, len, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)?;
+            let heap = std::slice::from_raw_parts(ptr as *const u8, len);
+            let res = match heap.iter().position(|&byte| byte != HEAP_POISON) {
+                Some(offset) => Err(Error::HeapPoisonCorrupted(format!(
+                    "heap offset {:#x} of the slot at {:p} was written after it was released",
+                    poison_start + offset,
+                    slot.start
+                ))),
+                None => Ok(()),
+            };
+            memset(ptr, 0, len);
+            res
+        }
+    }
+
+    /// Choose the free slot for a new instance of `module`, returning its position in
+    /// `free_slots`.
+    ///
+    /// Modules with a slot affinity, and all modules under `DecommitPolicy::KeepWarm`, prefer a
+    /// slot last used by an instance of the same module. Otherwise, `alloc_strategy` picks among
+    /// the slots allowed by the module's affinity, preferring slots that are not warm for another
+    /// module with an affinity.
+    fn select_slot(
+        &self,
+        module: &Arc<dyn Module>,
+        free_slots: &[Slot],
+        alloc_strategy: &mut AllocStrategy,
+    ) -> Result<usize, Error> {
+        let affinity = self.slot_affinity(module);
+        let slot_indices = self.slot_indices.read().unwrap();
+        let candidates = free_slots
+            .iter()
+            .enumerate()
+            .filter(|(_, slot)| match &affinity {
+                Some(SlotAffinity::Pinned(range)) => {
+                    range.contains(&slot_indices[&(slot.start as usize)])
+                }
+                _ => true,
+            })
+            .map(|(pos, slot)| (pos, slot.start as usize))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(Error::RegionFull(self.capacity));
+        }
+
+        let warm_slots = self.warm_slots.lock().unwrap();
+        if affinity.is_some() || self.decommit_policy == DecommitPolicy::KeepWarm {
+            let id = module_id(module);
+            if let Some(&(pos, _)) = candidates
+                .iter()
+                .find(|(_, start)| warm_slots.get(start) == Some(&id))
+            {
+                return Ok(pos);
+            }
+        }
+
+        let affinities = self.affinities.lock().unwrap();
+        let cold = candidates
+            .iter()
+            .filter(|(_, start)| match warm_slots.get(start) {
+                Some(owner) => !affinities.contains_key(owner),
+                None => true,
+            })
+            .collect::<Vec<_>>();
+        if cold.is_empty() {
+            Ok(candidates[alloc_strategy.next(candidates.len(), self.capacity)?].0)
+        } else {
+            Ok(cold[alloc_strategy.next(cold.len(), self.capacity)?].0)
+        }
+    }
+
+    /// Forget the module that last used `slot`, now that it holds an instance of `module`,
+    /// reporting an eviction if the slot was warm for another module with an affinity.
+    fn take_warm_slot(&self, slot: &Slot, module: &Arc<dyn Module>) {
+        let evicted = match self
+            .warm_slots
+            .lock()
+            .unwrap()
+            .remove(&(slot.start as usize))
+        {
+            Some(owner) if owner != module_id(module) => owner,
+            _ => return,
+        };
+        let evicted = match self.affinities.lock().unwrap().get(&evicted) {
+            Some((evicted, _)) => evicted.clone(),
+            None => return,
+        };
+        let callback = self.eviction_callback.lock().unwrap().clone();
+        if let Some(callback) = callback {
+            callback(&SlotEviction {
+                slot: self.slot_indices.read().unwrap()[&(slot.start as usize)],
+                evicted,
+                module: module.clone(),
+            });
+        }
+    }
+
+    /// Set where the region places new instances of `module`, replacing its current affinity,
+    /// if any.
+    ///
+    /// The region keeps a reference to the module until its affinity is cleared with
+    /// [`clear_slot_affinity()`](#method.clear_slot_affinity). Returns `Error::InvalidArgument` if
+    /// a pinned range is empty or extends beyond the capacity of the region.
+    pub fn set_slot_affinity(
+        &self,
+        module: &Arc<dyn Module>,
+        affinity: SlotAffinity,
+    ) -> Result<(), Error> {
+        if let SlotAffinity::Pinned(range) = &affinity {
+            if range.start >= range.end || range.end > self.capacity {
+                return Err(Error::InvalidArgument(
+                    "pinned slot range is empty or beyond the capacity of the region",
+                ));
+            }
+        }
+        self.affinities
+            .lock()
+            .unwrap()
+            .insert(module_id(module), (module.clone(), affinity));
+        Ok(())
+    }
+
+    /// Get the slot affinity of `module`, if it has one.
+    pub fn slot_affinity(&self, module: &Arc<dyn Module>) -> Option<SlotAffinity> {
+        self.affinities
+            .lock()
+            .unwrap()
+            .get(&module_id(module))
+            .map(|(_, affinity)| affinity.clone())
+    }
+
+    /// Remove the slot affinity of `module`, if it has one.
+    pub fn clear_slot_affinity(&self, module: &Arc<dyn Module>) {
+        self.affinities.lock().unwrap().remove(&module_id(module));
+    }
+
+    /// Set a callback that the region calls when it places an instance in a free slot that was
+    /// last used by another module with a slot affinity, replacing any existing callback.
+    ///
+    /// The callback runs on the thread creating the instance, before the instance is created, so
+    /// it should return quickly and must not create instances in this region.
+    pub fn set_eviction_callback(&self, callback: Box<dyn Fn(&SlotEviction) + Send + Sync>) {
+        *self.eviction_callback.lock().unwrap() = Some(Arc::from(callback));
+    }
+
+    /// Remove the callback set by `set_eviction_callback()`, if any.
+    pub fn clear_eviction_callback(&self) {
+        *self.eviction_callback.lock().unwrap() = None;
+    }
+
+    fn create_slot(region: &Arc<MmapRegion>, index: usize) -> Result<Slot, Error> {
         // get the chunk of virtual memory that the `Slot` will manage
         let mem = if region.min_heap_alignment == 0 {
             unsafe {
@@ -404,6 +837,29 @@ impl MmapRegion {
             region.limits.total_memory_size()
         );
 
+        // the key stays on the heap pages as their protection changes, so they only need tagging
+        // once
+        let protection_key = region
+            .protection_keys
+            .as_ref()
+            .map(|keys| keys.key_for_slot(index));
+        if let Some(key) = protection_key {
+            unsafe {
+                mpk::tag_memory(
+                    heap as *mut c_void,
+                    region.limits.heap_address_space_size,
+                    ProtFlags::PROT_NONE,
+                    key,
+                )?
+            };
+        }
+
+        wx::register(
+            mem as usize,
+            region.limits.total_memory_size(),
+            "instance slot",
+        );
+
         Ok(Slot {
             start: mem,
             heap: heap as *mut c_void,
@@ -411,6 +867,7 @@ impl MmapRegion {
             globals: globals as *mut c_void,
             sigstack: sigstack as *mut c_void,
             limits: region.limits.clone(),
+            protection_key,
             region: Arc::downgrade(region) as Weak<dyn RegionInternal>,
         })
     }
@@ -421,11 +878,135 @@ impl MmapRegion {
         //     slot.start,
         //     slot.limits.total_memory_size()
         // );
+        wx::unregister(slot.start as usize);
         let res = unsafe { munmap(slot.start, slot.limits.total_memory_size()) };
         res.expect("munmap succeeded");
     }
 }
 
+/// The sections of a slot that are made accessible to an instance, given the accessible size of
+/// its heap.
+fn slot_sections(slot: &Slot, heap_accessible_size: usize) -> [(*mut c_void, usize); 4] {
+    [
+        // We don't ever shrink the heap, so we only need to zero up until the accessible size
+        (slot.heap, heap_accessible_size),
+        (slot.stack, slot.limits.stack_size),
+        (slot.globals, slot.limits.globals_size),
+        (slot.sigstack, slot.limits.signal_stack_size),
+    ]
+}
+
+/// Clear and disable access to the heap, stack, globals, and sigstack of a slot, releasing their
+/// pages.
+fn decommit_slot(slot: &Slot, heap_accessible_size: usize) {
+    for (ptr, len) in slot_sections(slot, heap_accessible_size).iter() {
+        unsafe {
+            // MADV_DONTNEED is not guaranteed to clear pages on non-Linux systems
+            #[cfg(not(target_os = "linux"))]
+            {
+                mprotect(*ptr, *len, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)
+                    .expect("mprotect succeeds during drop");
+                memset(*ptr, 0, *len);
+            }
+            mprotect(*ptr, *len, ProtFlags::PROT_NONE).expect("mprotect succeeds during drop");
+            madvise(*ptr, *len, MmapAdvise::MADV_DONTNEED).expect("madvise succeeds during drop");
+        }
+    }
+}
+
+/// Clear and disable access to the heap, stack, globals, and sigstack of a slot, keeping their
+/// pages resident.
+fn scrub_slot(slot: &Slot, heap_accessible_size: usize) {
+    // this may run on the decommitter thread, which has not necessarily touched the heap before
+    if let Some(key) = slot.protection_key {
+        key.allow_access();
+    }
+    for (ptr, len) in slot_sections(slot, heap_accessible_size).iter() {
+        unsafe {
+            // all of these sections are read/writable while the instance is alive
+            memset(*ptr, 0, *len);
+            mprotect(*ptr, *len, ProtFlags::PROT_NONE).expect("mprotect succeeds during drop");
+        }
+    }
+}
+
+#[derive(Default)]
+struct DecommitQueue {
+    slots: Vec<(Slot, usize)>,
+    shutdown: bool,
+}
+
+/// A thread that releases the pages of dropped slots for `DecommitPolicy::Background`, and then
+/// returns the slots to the freelist.
+struct Decommitter {
+    queue: Arc<(Mutex<DecommitQueue>, Condvar)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Decommitter {
+    fn spawn(freelist: Arc<RwLock<Vec<Slot>>>) -> Self {
+        let queue = Arc::new((Mutex::new(DecommitQueue::default()), Condvar::new()));
+        let thread_queue = queue.clone();
+        let thread = thread::Builder::new()
+            .name("mmap region decommitter".into())
+            .spawn(move || {
+                let (lock, cvar) = &*thread_queue;
+                loop {
+                    let (slot, heap_accessible_size) = {
+                        let mut queue = lock.lock().unwrap();
+                        loop {
+                            if let Some(pending) = queue.slots.pop() {
+                                break pending;
+                            }
+                            if queue.shutdown {
+                                return;
+                            }
+                            queue = cvar.wait(queue).unwrap();
+                        }
+                    };
+                    decommit_slot(&slot, heap_accessible_size);
+                    freelist.write().unwrap().push(slot);
+                }
+            })
+            .expect("error spawning mmap region decommitter");
+        Decommitter {
+            queue,
+            thread: Some(thread),
+        }
+    }
+
+    fn push(&self, slot: Slot, heap_accessible_size: usize) {
+        let (lock, cvar) = &*self.queue;
+        lock.lock()
+            .unwrap()
+            .slots
+            .push((slot, heap_accessible_size));
+        cvar.notify_one();
+    }
+
+    /// Take a slot that is waiting to be released, and release it on the current thread.
+    fn take_pending(&self) -> Option<Slot> {
+        let (slot, heap_accessible_size) = self.queue.0.lock().unwrap().slots.pop()?;
+        decommit_slot(&slot, heap_accessible_size);
+        Some(slot)
+    }
+
+    fn pending_len(&self) -> usize {
+        self.queue.0.lock().unwrap().slots.len()
+    }
+}
+
+impl Drop for Decommitter {
+    fn drop(&mut self) {
+        let (lock, cvar) = &*self.queue;
+        lock.lock().unwrap().shutdown = true;
+        cvar.notify_one();
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("join on mmap region decommitter");
+        }
+    }
+}
+
 // Note alignment must be a power of 2
 // Offset must be a multiple of 4Kb (page size)
 unsafe fn mmap_aligned(
@@ -486,6 +1067,47 @@ unsafe fn mmap_aligned(
     Ok(aligned as *mut c_void)
 }
 
+/// Lock a range of memory with `mlock()`, describing why the lock failed if it does.
+fn lock_memory(ptr: *mut c_void, len: usize) -> Result<(), Error> {
+    if len == 0 {
+        return Ok(());
+    }
+    let errno = match nix::errno::Errno::result(unsafe { libc::mlock(ptr, len) }) {
+        Ok(_) => return Ok(()),
+        Err(nix::Error::Sys(errno)) => errno,
+        Err(e) => return Err(e.into()),
+    };
+    let reason = match errno {
+        nix::errno::Errno::EPERM => {
+            "the process lacks the CAP_IPC_LOCK capability, and RLIMIT_MEMLOCK is 0".to_owned()
+        }
+        nix::errno::Errno::ENOMEM | nix::errno::Errno::EAGAIN => {
+            let mut rlimit = libc::rlimit {
+                rlim_cur: 0,
+                rlim_max: 0,
+            };
+            let limit = if unsafe { libc::getrlimit(libc::RLIMIT_MEMLOCK, &mut rlimit) } == 0 {
+                format!("{} bytes", rlimit.rlim_cur)
+            } else {
+                "unknown".to_owned()
+            };
+            format!(
+                "locking {} more bytes would exceed RLIMIT_MEMLOCK ({})",
+                len, limit
+            )
+        }
+        errno => format!("mlock() failed: {}", errno),
+    };
+    Err(Error::MemoryLockFailed(reason))
+}
+
+unsafe fn munlock(addr: *mut c_void, length: libc::size_t) -> nix::Result<()> {
+    if length == 0 {
+        return Ok(());
+    }
+    nix::errno::Errno::result(libc::munlock(addr, length)).map(drop)
+}
+
 // TODO: remove this once `nix` PR https://github.com/nix-rust/nix/pull/991 is merged
 unsafe fn mprotect(addr: *mut c_void, length: libc::size_t, prot: ProtFlags) -> nix::Result<()> {
     nix::errno::Errno::result(libc::mprotect(addr, length, prot.bits())).map(drop)
@@ -0,0 +1,216 @@
+//! Memory protection keys, which isolate the heaps of the instances in a region from each other.
+//!
+//! Each slot's heap is tagged with one of a small number of keys allocated by the region. While
+//! an instance runs, including during its hostcalls, the thread's PKRU register denies access to
+//! every other key of the region, so that stray accesses to a sibling instance's heap fault
+//! instead of silently succeeding. PKRU is restricted again on entry to each hostcall and restored
+//! when it returns, and the heap accessors of other instances do not lift the restriction. Outside
+//! of guest execution, the heap accessors of an instance grant the current thread access to that
+//! instance's key.
+//!
+//! Protection keys are only supported on x86-64 Linux.
+//!
+//! The hardware provides only 15 usable keys per process, so in regions with more slots than
+//! keys, some slots share a key and are not isolated from each other.
+
+use crate::error::Error;
+use crate::instance::CURRENT_INSTANCE;
+use libc::c_void;
+use nix::sys::mman::ProtFlags;
+
+/// The number of keys usable by the process; key 0 is the default key of all other memory.
+const MAX_KEYS: usize = 15;
+
+/// Check whether the CPU and the operating system support memory protection keys.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub fn protection_keys_supported() -> bool {
+    use std::arch::x86_64::__cpuid_count;
+    const CPUID_ECX_PKU: u32 = 1 << 3;
+    const CPUID_ECX_OSPKE: u32 = 1 << 4;
+    // leaf 7 is only valid if the maximum supported leaf is at least 7
+    let max_leaf = unsafe { __cpuid_count(0, 0) }.eax;
+    if max_leaf < 7 {
+        return false;
+    }
+    let ecx = unsafe { __cpuid_count(7, 0) }.ecx;
+    ecx & CPUID_ECX_PKU != 0 && ecx & CPUID_ECX_OSPKE != 0
+}
+
+/// Check whether the CPU and the operating system support memory protection keys.
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+pub fn protection_keys_supported() -> bool {
+    false
+}
+
+/// The protection key of a slot's heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtectionKey {
+    key: u32,
+    /// The access- and write-disable bits of every key allocated by the slot's region.
+    region_keys: u32,
+}
+
+impl ProtectionKey {
+    /// The PKRU bits that disable access to and writes through this key.
+    pub(crate) fn bits(self) -> u32 {
+        key_bits(self.key)
+    }
+
+    /// Grant the current thread access to memory tagged with this key, unless it is running an
+    /// instance.
+    ///
+    /// A running instance and its hostcalls keep the access `restrict_access()` gave them, so
+    /// that a hostcall reaching for the heap of another instance in the region faults.
+    pub(crate) fn allow_access(self) {
+        // a borrowed `CURRENT_INSTANCE` means the runtime is switching to or from an instance
+        let running =
+            CURRENT_INSTANCE.with(|current| current.try_borrow().map_or(true, |c| c.is_some()));
+        if running {
+            return;
+        }
+        let pkru = read_pkru();
+        if pkru & self.bits() != 0 {
+            write_pkru(pkru & !self.bits());
+        }
+    }
+
+    /// Deny the current thread access to every other key of the region, returning the previous
+    /// value of PKRU so that it can be restored when the guest or hostcall returns.
+    pub(crate) fn restrict_access(self) -> u32 {
+        let host_pkru = read_pkru();
+        write_pkru((host_pkru | self.region_keys) & !self.bits());
+        host_pkru
+    }
+}
+
+/// The keys allocated by a region, which are freed when it is dropped.
+pub(crate) struct ProtectionKeys {
+    keys: Vec<u32>,
+}
+
+impl ProtectionKeys {
+    /// Allocate as many keys as can usefully be assigned to `slots` slots.
+    pub(crate) fn allocate(slots: usize) -> Result<Self, Error> {
+        if !protection_keys_supported() {
+            return Err(Error::Unsupported(
+                "memory protection keys are not supported by this CPU or operating system"
+                    .to_owned(),
+            ));
+        }
+        let mut keys = ProtectionKeys { keys: vec![] };
+        while keys.keys.len() < slots.min(MAX_KEYS) {
+            match pkey_alloc() {
+                Some(key) => keys.keys.push(key),
+                None => break,
+            }
+        }
+        if keys.keys.is_empty() {
+            return Err(Error::Unsupported(
+                "no memory protection keys are available".to_owned(),
+            ));
+        }
+        Ok(keys)
+    }
+
+    /// The key for the slot with the given index.
+    pub(crate) fn key_for_slot(&self, index: usize) -> ProtectionKey {
+        ProtectionKey {
+            key: self.keys[index % self.keys.len()],
+            region_keys: self.keys.iter().fold(0, |bits, &key| bits | key_bits(key)),
+        }
+    }
+}
+
+impl Drop for ProtectionKeys {
+    fn drop(&mut self) {
+        for &key in &self.keys {
+            pkey_free(key);
+        }
+    }
+}
+
+/// Tag a range of memory with a protection key, setting its protection at the same time.
+///
+/// Later calls to `mprotect()` on the range leave the key in place.
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+pub(crate) unsafe fn tag_memory(
+    addr: *mut c_void,
+    len: usize,
+    prot: ProtFlags,
+    key: ProtectionKey,
+) -> Result<(), Error> {
+    nix::errno::Errno::result(libc::syscall(
+        libc::SYS_pkey_mprotect,
+        addr,
+        len,
+        prot.bits(),
+        key.key,
+    ))?;
+    Ok(())
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+pub(crate) unsafe fn tag_memory(
+    _addr: *mut c_void,
+    _len: usize,
+    _prot: ProtFlags,
+    _key: ProtectionKey,
+) -> Result<(), Error> {
+    unreachable!("protection keys are never allocated on this platform")
+}
+
+fn key_bits(key: u32) -> u32 {
+    0b11 << (2 * key)
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn pkey_alloc() -> Option<u32> {
+    // allow access from the allocating thread; other threads are granted access as needed
+    let key = unsafe { libc::syscall(libc::SYS_pkey_alloc, 0, 0) };
+    if key < 0 {
+        None
+    } else {
+        Some(key as u32)
+    }
+}
+
+#[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+fn pkey_free(key: u32) {
+    unsafe { libc::syscall(libc::SYS_pkey_free, key) };
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn pkey_alloc() -> Option<u32> {
+    None
+}
+
+#[cfg(not(all(target_os = "linux", target_arch = "x86_64")))]
+fn pkey_free(_key: u32) {}
+
+#[cfg(target_arch = "x86_64")]
+extern "C" {
+    fn lucet_rdpkru() -> u32;
+    fn lucet_wrpkru(pkru: u32);
+}
+
+/// Read the current thread's PKRU register; only valid if protection keys are supported.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn read_pkru() -> u32 {
+    unsafe { lucet_rdpkru() }
+}
+
+/// Write the current thread's PKRU register; only valid if protection keys are supported.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn write_pkru(pkru: u32) {
+    unsafe { lucet_wrpkru(pkru) }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) fn read_pkru() -> u32 {
+    unreachable!("protection keys are never allocated on this platform")
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+pub(crate) fn write_pkru(_pkru: u32) {
+    unreachable!("protection keys are never allocated on this platform")
+}
@@ -0,0 +1,197 @@
+use crate::alloc::{instance_heap_offset, Limits};
+use crate::module::Module;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// A snapshot of the occupancy and memory use of a region, as returned by
+/// [`Region::stats()`](trait.Region.html#method.stats).
+#[derive(Clone)]
+pub struct RegionStats {
+    /// The total number of instance slots in the region.
+    pub capacity: usize,
+    /// The number of slots not currently holding an instance.
+    pub free_slots: usize,
+    /// The number of slots currently holding an instance.
+    pub used_slots: usize,
+    /// The bytes of virtual address space reserved for all of the region's slots.
+    pub reserved_bytes: usize,
+    /// The bytes of memory currently accessible to the region's instances: the instance metadata
+    /// of every slot, plus the accessible heap, stack, globals, and signal stack of every used
+    /// slot.
+    ///
+    /// Regions that back memory lazily, such as `UffdRegion`, may use less physical memory than
+    /// this.
+    pub committed_bytes: usize,
+    module_instances: Vec<(Arc<dyn Module>, usize)>,
+}
+
+impl RegionStats {
+    /// The number of instances of `module` currently in the region.
+    pub fn instances_of(&self, module: &Arc<dyn Module>) -> usize {
+        self.module_instances
+            .iter()
+            .find(|(m, _)| same_module(m, module))
+            .map(|(_, count)| *count)
+            .unwrap_or(0)
+    }
+
+    /// Iterate over each module with instances in the region, along with its number of instances.
+    pub fn module_instances(&self) -> impl Iterator<Item = (&Arc<dyn Module>, usize)> {
+        self.module_instances.iter().map(|(m, count)| (m, *count))
+    }
+}
+
+/// The direction and threshold of a change in region occupancy, passed to the callback registered
+/// with [`Region::set_occupancy_callback()`](trait.Region.html#method.set_occupancy_callback).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OccupancyEvent {
+    /// The threshold that was crossed, in used slots.
+    pub threshold: usize,
+    /// The number of used slots after the change.
+    pub used_slots: usize,
+    /// The total number of slots in the region.
+    pub capacity: usize,
+    /// `true` if occupancy rose to reach the threshold, `false` if it fell below it.
+    pub rising: bool,
+}
+
+pub(crate) type OccupancyCallback = Arc<dyn Fn(OccupancyEvent) + Send + Sync>;
+
+struct SlotUsage {
+    module: Arc<dyn Module>,
+    heap_accessible_size: usize,
+}
+
+/// Bookkeeping shared by region implementations to support
+/// [`Region::stats()`](trait.Region.html#method.stats).
+///
+/// Regions call into this as instances are created, resized, and dropped; slots are identified by
+/// the address of their start.
+#[derive(Default)]
+pub struct RegionAccounting {
+    slots: Mutex<HashMap<usize, SlotUsage>>,
+    occupancy: Mutex<Option<(Vec<usize>, OccupancyCallback)>>,
+}
+
+impl RegionAccounting {
+    pub(crate) fn instance_created(
+        &self,
+        slot_start: usize,
+        module: Arc<dyn Module>,
+        capacity: usize,
+    ) {
+        let used = {
+            let mut slots = self.slots.lock().unwrap();
+            slots.insert(
+                slot_start,
+                SlotUsage {
+                    module,
+                    heap_accessible_size: 0,
+                },
+            );
+            slots.len()
+        };
+        self.notify(used - 1, used, capacity);
+    }
+
+    pub(crate) fn instance_dropped(&self, slot_start: usize, capacity: usize) {
+        let used = {
+            let mut slots = self.slots.lock().unwrap();
+            if slots.remove(&slot_start).is_none() {
+                return;
+            }
+            slots.len()
+        };
+        self.notify(used + 1, used, capacity);
+    }
+
+    /// The identity of the module instantiated in a slot, as given by `module_id()`.
+    pub(crate) fn slot_module_id(&self, slot_start: usize) -> Option<usize> {
+        self.slots
+            .lock()
+            .unwrap()
+            .get(&slot_start)
+            .map(|usage| module_id(&usage.module))
+    }
+
+    pub(crate) fn heap_resized(&self, slot_start: usize, heap_accessible_size: usize) {
+        if let Some(usage) = self.slots.lock().unwrap().get_mut(&slot_start) {
+            usage.heap_accessible_size = heap_accessible_size;
+        }
+    }
+
+    pub(crate) fn set_occupancy_callback(
+        &self,
+        occupancy: Option<(Vec<usize>, OccupancyCallback)>,
+    ) {
+        *self.occupancy.lock().unwrap() = occupancy;
+    }
+
+    pub(crate) fn stats(&self, capacity: usize, limits: &Limits) -> RegionStats {
+        let slots = self.slots.lock().unwrap();
+        let per_instance = limits.stack_size + limits.globals_size + limits.signal_stack_size;
+        let mut committed_bytes = capacity * instance_heap_offset();
+        let mut module_instances: Vec<(Arc<dyn Module>, usize)> = vec![];
+        for usage in slots.values() {
+            committed_bytes += per_instance + usage.heap_accessible_size;
+            match module_instances
+                .iter_mut()
+                .find(|(m, _)| same_module(m, &usage.module))
+            {
+                Some((_, count)) => *count += 1,
+                None => module_instances.push((usage.module.clone(), 1)),
+            }
+        }
+        RegionStats {
+            capacity,
+            free_slots: capacity - slots.len(),
+            used_slots: slots.len(),
+            reserved_bytes: capacity * limits.total_memory_size(),
+            committed_bytes,
+            module_instances,
+        }
+    }
+
+    fn notify(&self, old_used: usize, new_used: usize, capacity: usize) {
+        let (events, callback) = {
+            let occupancy = self.occupancy.lock().unwrap();
+            let (thresholds, callback) = match &*occupancy {
+                Some(occupancy) => occupancy,
+                None => return,
+            };
+            let events = thresholds
+                .iter()
+                .filter_map(|&threshold| {
+                    let rising = if old_used < threshold && threshold <= new_used {
+                        true
+                    } else if new_used < threshold && threshold <= old_used {
+                        false
+                    } else {
+                        return None;
+                    };
+                    Some(OccupancyEvent {
+                        threshold,
+                        used_slots: new_used,
+                        capacity,
+                        rising,
+                    })
+                })
+                .collect::<Vec<_>>();
+            (events, callback.clone())
+        };
+        // run the callback without holding the lock, so that it may inspect the region
+        for event in events {
+            callback(event);
+        }
+    }
+}
+
+/// An identity for a module that is stable for as long as the module is alive.
+pub(crate) fn module_id(module: &Arc<dyn Module>) -> usize {
+    // use only the data pointer; vtable pointers for the same type may differ
+    &**module as *const dyn Module as *const () as usize
+}
+
+fn same_module(a: &Arc<dyn Module>, b: &Arc<dyn Module>) -> bool {
+    module_id(a) == module_id(b)
+}
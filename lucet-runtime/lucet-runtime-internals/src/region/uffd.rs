@@ -1,15 +1,20 @@
 use crate::alloc::{instance_heap_offset, AddrLocation, Alloc, AllocStrategy, Limits, Slot};
 use crate::embed_ctx::CtxMap;
 use crate::error::Error;
-use crate::instance::{new_instance_handle, Instance, InstanceHandle, InstanceInternal};
+use crate::instance::{
+    new_instance_handle, HostPanicPolicy, Instance, InstanceHandle, InstanceInternal,
+};
 use crate::module::Module;
-use crate::region::{Region, RegionCreate, RegionInternal};
+use crate::region::{
+    DecommitPolicy, Region, RegionAccounting, RegionCreate, RegionInternal, RegionOptions,
+};
 use crate::sysdeps::host_page_size;
+use crate::wx;
 use crate::WASM_PAGE_SIZE;
 use crate::{lucet_bail, lucet_ensure, lucet_format_err};
 use libc::c_void;
 use nix::poll;
-use nix::sys::mman::{madvise, mmap, munmap, MapFlags, MmapAdvise, ProtFlags};
+use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::ptr;
 use std::sync::{Arc, Mutex, Weak};
@@ -41,6 +46,10 @@ use userfaultfd::{IoctlFlags, Uffd, UffdBuilder};
 /// the backing physical memory. This ends up causing the guest thread to raise a SIGBUS, which is
 /// treated as a fatal error by the Lucet signal handler.
 ///
+/// Since pages are only backed when they are touched, a `UffdRegion` always releases the memory of
+/// a dropped instance immediately, as with
+/// [`DecommitPolicy::Immediate`](../enum.DecommitPolicy.html#variant.Immediate).
+///
 /// [userfaultfd]: http://man7.org/linux/man-pages/man2/userfaultfd.2.html
 pub struct UffdRegion {
     uffd: Arc<Uffd>,
@@ -50,6 +59,8 @@ pub struct UffdRegion {
     instance_capacity: usize,
     handler: Option<JoinHandle<Result<(), Error>>>,
     handler_pipe: RawFd,
+    accounting: RegionAccounting,
+    host_panic_policy: HostPanicPolicy,
 }
 
 // the start pointer prevents these from auto-deriving
@@ -235,6 +246,9 @@ impl RegionInternal for UffdRegion {
             }
         }
 
+        self.accounting
+            .instance_created(slot.start as usize, module.clone(), self.capacity());
+
         let inst_ptr = slot.start as *mut Instance;
 
         // upgrade the slot's weak region pointer so the region can't get dropped while the instance
@@ -272,32 +286,33 @@ impl RegionInternal for UffdRegion {
             panic!("heap is not page-aligned");
         }
 
-        // set dontneed for everything past the `Instance` page
+        // discard everything past the `Instance` page
         let ptr = (slot.start as usize + instance_heap_offset()) as *mut c_void;
         let len = slot.limits.total_memory_size() - instance_heap_offset();
-        // eprintln!("setting none {:p}[{:x}]", ptr, len);
         unsafe {
-            madvise(ptr, len, MmapAdvise::MADV_DONTNEED).expect("madvise succeeds during drop");
+            self.discard_range(ptr, len)
+                .expect("discarding slot memory succeeds during drop");
         }
 
+        self.accounting
+            .instance_dropped(slot.start as usize, self.capacity());
         self.freelist.lock().unwrap().push(slot);
     }
 
-    fn expand_heap(&self, _slot: &Slot, _start: u32, _len: u32) -> Result<(), Error> {
+    fn expand_heap(&self, slot: &Slot, start: u32, len: u32) -> Result<(), Error> {
         // the actual work of heap expansion for UFFD is done in the worker thread; we just need the
         // `Alloc` to validate the new limits and update the metadata
+        self.accounting
+            .heap_resized(slot.start as usize, start as usize + len as usize);
         Ok(())
     }
 
     fn reset_heap(&self, alloc: &mut Alloc, module: &dyn Module) -> Result<(), Error> {
-        // zero the heap, if any of it is currently accessible
+        // zero the heap, if any of it is currently accessible; the heap never shrinks, so no page
+        // beyond the accessible size can have been populated
         if alloc.heap_accessible_size > 0 {
             unsafe {
-                madvise(
-                    alloc.slot().heap,
-                    alloc.heap_accessible_size,
-                    MmapAdvise::MADV_DONTNEED,
-                )?;
+                self.discard_range(alloc.slot().heap, alloc.heap_accessible_size)?;
             }
         }
 
@@ -308,6 +323,8 @@ impl RegionInternal for UffdRegion {
             .unwrap_or(0);
         alloc.heap_accessible_size = initial_size;
         alloc.heap_inaccessible_size = alloc.slot().limits.heap_address_space_size - initial_size;
+        self.accounting
+            .heap_resized(alloc.slot().start as usize, initial_size);
         Ok(())
     }
 
@@ -315,6 +332,14 @@ impl RegionInternal for UffdRegion {
         &self.limits
     }
 
+    fn host_panic_policy(&self) -> HostPanicPolicy {
+        self.host_panic_policy
+    }
+
+    fn accounting(&self) -> &RegionAccounting {
+        &self.accounting
+    }
+
     fn as_dyn_internal(&self) -> &dyn RegionInternal {
         self
     }
@@ -350,6 +375,7 @@ impl Drop for UffdRegion {
         nix::unistd::close(self.handler_pipe).expect("close handler exit pipe");
 
         let total_region_size = self.instance_capacity * self.limits.total_memory_size();
+        wx::unregister(self.start as usize);
         unsafe {
             munmap(self.start, total_region_size).expect("unmapping region");
         }
@@ -361,6 +387,37 @@ impl Drop for UffdRegion {
 }
 
 impl UffdRegion {
+    /// Create a new `UffdRegion` that can support a given number of instances, each subject to the
+    /// same runtime limits, and configured by `options`.
+    ///
+    /// Of the options, `UffdRegion` only supports the host panic policy. It pages heaps in by
+    /// itself, so it does not support a decommit policy other than the default, locking memory,
+    /// memory protection keys, or heap poisoning; asking for any of them fails with
+    /// `Error::Unsupported`.
+    pub fn create_with_options(
+        instance_capacity: usize,
+        limits: &Limits,
+        strategy: impl UffdStrategy,
+        options: &RegionOptions,
+    ) -> Result<Arc<Self>, Error> {
+        let unsupported = [
+            (
+                options.decommit_policy != DecommitPolicy::default(),
+                "a decommit policy",
+            ),
+            (options.mlock, "locking memory"),
+            (options.protection_keys, "memory protection keys"),
+            (options.heap_poisoning, "heap poisoning"),
+        ];
+        if let Some((_, what)) = unsupported.iter().find(|(requested, _)| *requested) {
+            return Err(Error::Unsupported(format!(
+                "`UffdRegion` does not support {}",
+                what
+            )));
+        }
+        UffdRegion::create_inner(instance_capacity, limits, strategy, options)
+    }
+
     /// Create a new `UffdRegion` that can support a given number of instances, each subject to the
     /// same runtime limits.
     ///
@@ -373,6 +430,20 @@ impl UffdRegion {
         instance_capacity: usize,
         limits: &Limits,
         strategy: impl UffdStrategy,
+    ) -> Result<Arc<Self>, Error> {
+        UffdRegion::create_inner(
+            instance_capacity,
+            limits,
+            strategy,
+            &RegionOptions::default(),
+        )
+    }
+
+    fn create_inner(
+        instance_capacity: usize,
+        limits: &Limits,
+        strategy: impl UffdStrategy,
+        options: &RegionOptions,
     ) -> Result<Arc<Self>, Error> {
         if instance_capacity == 0 {
             return Err(Error::InvalidArgument(
@@ -406,6 +477,7 @@ impl UffdRegion {
                 0,
             )?
         };
+        wx::register(start as usize, total_region_size, "instance slots");
 
         // register the memory region with uffd and verify the required ioctls are supported
         let ioctls = uffd
@@ -463,6 +535,8 @@ impl UffdRegion {
             instance_capacity,
             handler: Some(handler),
             handler_pipe,
+            accounting: RegionAccounting::default(),
+            host_panic_policy: options.host_panic_policy,
         });
 
         {
@@ -475,6 +549,37 @@ impl UffdRegion {
         Ok(region)
     }
 
+    /// Discard the contents of a range of the region by mapping fresh anonymous memory over it, and
+    /// register the new mapping so that its pages are again populated by the handler on demand.
+    ///
+    /// Replacing the mapping releases the populated pages along with the page tables that mapped
+    /// them, so repeatedly resetting a large heap that is mostly untouched leaves nothing behind to
+    /// be walked by the next reset, unlike `madvise(MADV_DONTNEED)`.
+    ///
+    /// Safety: the range must lie within a slot that no instance is running in.
+    unsafe fn discard_range(&self, ptr: *mut c_void, len: usize) -> Result<(), Error> {
+        let remapped = mmap(
+            ptr,
+            len,
+            ProtFlags::PROT_READ | ProtFlags::PROT_WRITE,
+            MapFlags::MAP_ANONYMOUS
+                | MapFlags::MAP_PRIVATE
+                | MapFlags::MAP_NORESERVE
+                | MapFlags::MAP_FIXED,
+            0,
+            0,
+        )?;
+        lucet_ensure!(
+            remapped == ptr,
+            "fixed mapping was placed at {:p}",
+            remapped
+        );
+        self.uffd
+            .register(ptr, len)
+            .map_err(|e| Error::InternalError(e.into()))?;
+        Ok(())
+    }
+
     fn create_slot(region: &Arc<UffdRegion>, index: usize) -> Result<Slot, Error> {
         // get the memory from the offset into the overall region
         let start =
@@ -501,6 +606,7 @@ impl UffdRegion {
             globals: globals as *mut c_void,
             sigstack: sigstack as *mut c_void,
             limits: region.limits.clone(),
+            protection_key: None,
             region: Arc::downgrade(region) as Weak<dyn RegionInternal>,
         })
     }
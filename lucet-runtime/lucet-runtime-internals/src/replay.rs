@@ -0,0 +1,385 @@
+//! Recording the hostcalls made by an instance, and replaying them against another instance.
+//!
+//! An instance that [records its hostcalls](../instance/struct.Instance.html#method.record_hostcalls)
+//! logs the arguments of each hostcall it makes, along with the value it returned and the changes
+//! it made to the heap. The log can be saved with [`HostcallLog::to_bytes()`], and
+//! [replayed](../instance/struct.Instance.html#method.replay_hostcalls) against a fresh instance of
+//! the same module: rather than running, each hostcall then checks that it was called with the
+//! recorded arguments, applies the recorded changes to the heap, and returns the recorded value.
+//! This reproduces an execution without the services the hostcalls depended on.
+//!
+//! If the replayed instance makes a hostcall that does not match the next one in the log, it is
+//! terminated with `TerminationDetails::ReplayDiverged`. A hostcall that terminated the recorded
+//! instance terminates the replayed one with a [`ReplayedTermination`].
+//!
+//! Only hostcalls defined with `#[lucet_hostcall]` are recorded. Hostcalls made while another
+//! hostcall is running, by calling back into the guest, are neither recorded nor replayed, as
+//! replaying the outer hostcall does not call back into the guest. Likewise, values a hostcall
+//! yields are not replayed; the replayed hostcall returns immediately instead. Changes hostcalls
+//! make to globals are not recorded.
+//!
+//! On Linux, the pages a hostcall writes are found with the soft-dirty bits of the page tables,
+//! which the kernel sets on each page written after they are cleared. The bits can only be cleared
+//! for the whole process, so only one hostcall in the process is tracked this way at a time, and
+//! while it runs, the first write to any page of the process takes a minor fault. Other hostcalls,
+//! and all hostcalls on kernels without soft-dirty bits, compare the heap to a copy taken before
+//! they ran.
+//!
+//! [`HostcallLog::to_bytes()`]: struct.HostcallLog.html#method.to_bytes
+//! [`ReplayedTermination`]: struct.ReplayedTermination.html
+
+use crate::error::Error;
+use crate::instance::{Instance, TerminationDetails};
+use crate::sysdeps::host_page_size;
+use crate::WASM_PAGE_SIZE;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::panic::{self, AssertUnwindSafe};
+
+/// The hostcalls made by an instance, in the order it made them.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostcallLog {
+    records: Vec<HostcallRecord>,
+}
+
+impl HostcallLog {
+    /// Create an empty log.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The recorded hostcalls.
+    pub fn records(&self) -> &[HostcallRecord] {
+        &self.records
+    }
+
+    /// Serialize the log, so that it can be replayed elsewhere.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        bincode::serialize(self).map_err(|e| {
+            Error::InternalError(anyhow::Error::new(e).context("serializing hostcall log"))
+        })
+    }
+
+    /// Deserialize a log produced by [`to_bytes()`](#method.to_bytes).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        bincode::deserialize(bytes).map_err(|e| {
+            Error::InternalError(anyhow::Error::new(e).context("deserializing hostcall log"))
+        })
+    }
+}
+
+/// A single hostcall made by an instance.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostcallRecord {
+    /// The name of the hostcall.
+    pub name: String,
+    /// The raw bytes of each argument of the hostcall, not including the `vmctx`.
+    pub args: Vec<Vec<u8>>,
+    /// The raw bytes of the value the hostcall returned, or `None` if it terminated the instance.
+    pub result: Option<Vec<u8>>,
+    /// The size of the heap when the hostcall finished, in bytes.
+    pub heap_len: usize,
+    /// The parts of the heap the hostcall changed.
+    pub heap_writes: Vec<HeapWrite>,
+}
+
+/// A range of the heap that a hostcall changed, and its contents afterwards.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeapWrite {
+    /// The offset of the range in the heap.
+    pub offset: usize,
+    /// The new contents of the range.
+    pub bytes: Vec<u8>,
+}
+
+/// The details an instance is terminated with when it replays a hostcall that terminated the
+/// recorded instance.
+///
+/// The original details cannot be recorded, as they may be of any type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayedTermination {
+    /// The name of the hostcall.
+    pub hostcall: String,
+}
+
+/// What an instance does with the hostcalls it makes.
+pub(crate) enum Interposer {
+    Recording(HostcallLog),
+    Replaying(VecDeque<HostcallRecord>),
+}
+
+impl Interposer {
+    pub(crate) fn replaying(log: HostcallLog) -> Self {
+        Interposer::Replaying(log.records.into())
+    }
+
+    /// The recorded log, or the records that are yet to be replayed.
+    pub(crate) fn into_log(self) -> HostcallLog {
+        match self {
+            Interposer::Recording(log) => log,
+            Interposer::Replaying(records) => HostcallLog {
+                records: records.into_iter().collect(),
+            },
+        }
+    }
+}
+
+/// The raw bytes of a hostcall argument.
+///
+/// This is used in the expansion of `#[lucet_hostcall]`, on the arguments of the raw hostcall,
+/// which have FFI-safe types.
+#[doc(hidden)]
+pub fn raw_bytes<T>(val: &T) -> Vec<u8> {
+    unsafe {
+        std::slice::from_raw_parts(val as *const T as *const u8, std::mem::size_of::<T>()).to_vec()
+    }
+}
+
+/// Run a hostcall, recording it in `log`.
+pub(crate) fn record<R>(
+    inst: &mut Instance,
+    log: &mut HostcallLog,
+    name: &'static str,
+    args: Vec<Vec<u8>>,
+    hostcall: impl FnOnce() -> R,
+) -> std::thread::Result<R> {
+    let watch = HeapWatch::start(inst.heap());
+    let res = panic::catch_unwind(AssertUnwindSafe(hostcall));
+    let heap_after = inst.heap();
+    log.records.push(HostcallRecord {
+        name: name.to_owned(),
+        args,
+        result: res.as_ref().ok().map(raw_bytes),
+        heap_len: heap_after.len(),
+        heap_writes: watch.writes(heap_after),
+    });
+    res
+}
+
+/// How the heap is watched for the writes of a hostcall being recorded.
+enum HeapWatch {
+    /// The soft-dirty bits were cleared before the hostcall, and no other hostcall clears them
+    /// until the guard is dropped.
+    #[cfg(target_os = "linux")]
+    SoftDirty(std::sync::MutexGuard<'static, ()>),
+    /// A copy of the heap from before the hostcall.
+    Snapshot(Vec<u8>),
+}
+
+impl HeapWatch {
+    fn start(heap: &[u8]) -> Self {
+        #[cfg(target_os = "linux")]
+        {
+            if let Some(guard) = soft_dirty::clear() {
+                return HeapWatch::SoftDirty(guard);
+            }
+        }
+        HeapWatch::Snapshot(heap.to_vec())
+    }
+
+    /// The ranges of `heap` that the hostcall changed, at the granularity of host pages.
+    fn writes(self, heap: &[u8]) -> Vec<HeapWrite> {
+        match self {
+            #[cfg(target_os = "linux")]
+            HeapWatch::SoftDirty(_guard) => match soft_dirty::dirty_pages(heap) {
+                Ok(dirty) => heap_writes(heap, |index, _| dirty[index]),
+                // without the bits, every page may have been written
+                Err(_) => heap_writes(heap, |_, _| true),
+            },
+            HeapWatch::Snapshot(before) => changed_pages(&before, heap),
+        }
+    }
+}
+
+/// Replay the next hostcall in `records`, which must match the call to `name` with `args`.
+pub(crate) fn replay<R>(
+    inst: &mut Instance,
+    records: &mut VecDeque<HostcallRecord>,
+    name: &'static str,
+    args: Vec<Vec<u8>>,
+) -> Result<R, TerminationDetails> {
+    let record = records.pop_front().ok_or_else(|| {
+        diverged(format!(
+            "the guest called `{}` after the last recorded hostcall",
+            name
+        ))
+    })?;
+    if record.name != name {
+        return Err(diverged(format!(
+            "the guest called `{}`, but `{}` was recorded",
+            name, record.name
+        )));
+    }
+    if record.args != args {
+        return Err(diverged(format!(
+            "`{}` was called with different arguments than were recorded",
+            name
+        )));
+    }
+
+    let heap_len = inst.heap().len();
+    if record.heap_len > heap_len {
+        let grow_pages = (record.heap_len - heap_len) / WASM_PAGE_SIZE as usize;
+        inst.grow_memory(grow_pages as u32).map_err(|e| {
+            diverged(format!(
+                "the heap could not grow as it did during `{}`: {}",
+                name, e
+            ))
+        })?;
+    }
+    let heap = inst.heap_mut();
+    for write in &record.heap_writes {
+        heap.get_mut(write.offset..write.offset + write.bytes.len())
+            .ok_or_else(|| {
+                diverged(format!(
+                    "`{}` wrote beyond the end of the heap when it was recorded",
+                    name
+                ))
+            })?
+            .copy_from_slice(&write.bytes);
+    }
+
+    match record.result {
+        None => Err(TerminationDetails::provide(ReplayedTermination {
+            hostcall: record.name,
+        })),
+        Some(bytes) if bytes.len() == std::mem::size_of::<R>() => {
+            Ok(unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const R) })
+        }
+        Some(_) => Err(diverged(format!(
+            "`{}` returned a value of a different size when it was recorded",
+            name
+        ))),
+    }
+}
+
+fn diverged(msg: String) -> TerminationDetails {
+    TerminationDetails::ReplayDiverged(msg)
+}
+
+/// The ranges of `after` that differ from `before`, at the granularity of host pages, where any
+/// part of `after` beyond the end of `before` is compared to zeroes.
+fn changed_pages(before: &[u8], after: &[u8]) -> Vec<HeapWrite> {
+    let page_size = host_page_size();
+    let zeroes = vec![0; page_size];
+    heap_writes(after, |index, page| {
+        let offset = index * page_size;
+        let old = before
+            .get(offset..offset + page.len())
+            .unwrap_or(&zeroes[..page.len()]);
+        page != old
+    })
+}
+
+/// The host pages of `heap` for which `written` holds, given the index and contents of each page,
+/// as ranges of adjacent pages.
+fn heap_writes(heap: &[u8], written: impl Fn(usize, &[u8]) -> bool) -> Vec<HeapWrite> {
+    let page_size = host_page_size();
+    let mut writes: Vec<HeapWrite> = vec![];
+    for (i, page) in heap.chunks(page_size).enumerate() {
+        let offset = i * page_size;
+        if !written(i, page) {
+            continue;
+        }
+        match writes.last_mut() {
+            // extend the previous write if this page is adjacent to it
+            Some(last) if last.offset + last.bytes.len() == offset => {
+                last.bytes.extend_from_slice(page)
+            }
+            _ => writes.push(HeapWrite {
+                offset,
+                bytes: page.to_vec(),
+            }),
+        }
+    }
+    writes
+}
+
+/// Dirty-page tracking with the soft-dirty bits of Linux page table entries; see
+/// `Documentation/admin-guide/mm/soft-dirty.rst` in the kernel sources.
+#[cfg(target_os = "linux")]
+mod soft_dirty {
+    use crate::sysdeps::host_page_size;
+    use lazy_static::lazy_static;
+    use std::fs::{File, OpenOptions};
+    use std::io::{self, Read, Seek, SeekFrom, Write};
+    use std::sync::{Mutex, MutexGuard};
+
+    /// The bit of a `/proc/self/pagemap` entry that is set if the page is soft-dirty.
+    const PAGEMAP_SOFT_DIRTY: u64 = 1 << 55;
+
+    lazy_static! {
+        /// Held while a hostcall is tracked, as clearing the bits affects the whole process.
+        static ref TRACKING: Mutex<()> = Mutex::new(());
+        /// Whether the kernel keeps soft-dirty bits, and lets this process clear them.
+        static ref SUPPORTED: bool = probe().unwrap_or(false);
+    }
+
+    /// Clear the soft-dirty bits of the process, returning the guard to hold until the tracked
+    /// hostcall returns. Returns `None` if another hostcall is being tracked, or if the bits are
+    /// not available.
+    pub(super) fn clear() -> Option<MutexGuard<'static, ()>> {
+        let guard = TRACKING.try_lock().ok()?;
+        if !*SUPPORTED {
+            return None;
+        }
+        clear_refs().ok()?;
+        Some(guard)
+    }
+
+    /// Whether each host page of `mem` has been written since the bits were last cleared.
+    pub(super) fn dirty_pages(mem: &[u8]) -> io::Result<Vec<bool>> {
+        let page_size = host_page_size();
+        let first_page = mem.as_ptr() as usize / page_size;
+        let pages = (mem.len() + page_size - 1) / page_size;
+        let mut entries = vec![0u8; pages * 8];
+        let mut pagemap = File::open("/proc/self/pagemap")?;
+        pagemap.seek(SeekFrom::Start(first_page as u64 * 8))?;
+        pagemap.read_exact(&mut entries)?;
+        Ok(entries
+            .chunks_exact(8)
+            .map(|entry| {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(entry);
+                u64::from_ne_bytes(bytes) & PAGEMAP_SOFT_DIRTY != 0
+            })
+            .collect())
+    }
+
+    fn clear_refs() -> io::Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .open("/proc/self/clear_refs")?
+            .write_all(b"4")
+    }
+
+    /// Check that a page written after the bits are cleared, and only such a page, is dirty.
+    ///
+    /// Kernels built without soft-dirty bits accept the request to clear them, but never set them.
+    fn probe() -> io::Result<bool> {
+        let page_size = host_page_size();
+        let page = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                page_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if page == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let mem = unsafe { std::slice::from_raw_parts_mut(page as *mut u8, page_size) };
+        let res = (|| -> io::Result<bool> {
+            unsafe { std::ptr::write_volatile(&mut mem[0], 1) };
+            clear_refs()?;
+            let clean = dirty_pages(mem)? == [false];
+            unsafe { std::ptr::write_volatile(&mut mem[0], 2) };
+            Ok(clean && dirty_pages(mem)? == [true])
+        })();
+        unsafe { libc::munmap(page, page_size) };
+        res
+    }
+}
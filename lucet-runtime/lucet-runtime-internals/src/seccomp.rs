@@ -0,0 +1,238 @@
+//! Restricting the system calls available to threads that run guest code.
+//!
+//! Guest code is only as isolated as the code `lucetc` generates for it. As defense in depth, a
+//! [`SeccompFilter`](struct.SeccompFilter.html) can be installed on each worker thread before it
+//! runs any instances, so that code that escapes the sandbox still cannot issue arbitrary system
+//! calls:
+//!
+//! ```no_run
+//! use lucet_runtime_internals::seccomp::SeccompFilter;
+//!
+//! std::thread::spawn(|| {
+//!     SeccompFilter::new()
+//!         // syscalls made by the embedder's hostcalls
+//!         .allow_syscalls(&[libc::SYS_read, libc::SYS_write])
+//!         .install()
+//!         .expect("filter can be installed");
+//!     // create and run instances
+//! });
+//! ```
+//!
+//! Filters apply to the thread that installs them and to any threads it later spawns, and cannot
+//! be removed once installed. Hostcalls run on the same thread as the guest, so the filter must
+//! allow every syscall the embedder's hostcalls make.
+
+use crate::error::Error;
+use libc::c_long;
+use std::collections::BTreeSet;
+
+/// The syscalls the runtime itself needs to run instances: handling signals and the alternate
+/// signal stack, growing and resetting heaps, synchronizing with kill switches, measuring CPU
+/// time, and exiting.
+const RUNTIME_SYSCALLS: &[c_long] = &[
+    libc::SYS_brk,
+    libc::SYS_clock_gettime,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    libc::SYS_futex,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_madvise,
+    libc::SYS_mmap,
+    libc::SYS_mprotect,
+    libc::SYS_mremap,
+    libc::SYS_munmap,
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sched_yield,
+    libc::SYS_sigaltstack,
+    libc::SYS_tgkill,
+];
+
+/// What happens when a thread makes a syscall its filter does not allow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeccompAction {
+    /// Kill the whole process, as if by an uncatchable `SIGSYS`. This is the default.
+    KillProcess,
+    /// Kill only the offending thread.
+    KillThread,
+    /// Deliver `SIGSYS` to the thread, which the embedder may handle.
+    Trap,
+    /// Fail the syscall with the given `errno`, without running it.
+    Errno(u16),
+}
+
+impl Default for SeccompAction {
+    fn default() -> Self {
+        SeccompAction::KillProcess
+    }
+}
+
+impl SeccompAction {
+    fn ret_value(self) -> u32 {
+        match self {
+            SeccompAction::KillProcess => SECCOMP_RET_KILL_PROCESS,
+            SeccompAction::KillThread => SECCOMP_RET_KILL_THREAD,
+            SeccompAction::Trap => SECCOMP_RET_TRAP,
+            SeccompAction::Errno(errno) => SECCOMP_RET_ERRNO | errno as u32,
+        }
+    }
+}
+
+/// A seccomp-bpf filter that allows a fixed set of syscalls.
+///
+/// The filter always allows the syscalls the runtime needs to run instances; use
+/// [`allow_syscall()`](#method.allow_syscall) to add the ones needed by the embedder.
+#[derive(Clone, Debug)]
+pub struct SeccompFilter {
+    allowed: BTreeSet<c_long>,
+    default_action: SeccompAction,
+}
+
+impl Default for SeccompFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SeccompFilter {
+    /// Create a filter allowing only the syscalls the runtime needs.
+    pub fn new() -> Self {
+        SeccompFilter {
+            allowed: RUNTIME_SYSCALLS.iter().cloned().collect(),
+            default_action: SeccompAction::default(),
+        }
+    }
+
+    /// Allow a syscall, given by its number, such as `libc::SYS_write`.
+    pub fn allow_syscall(mut self, syscall: c_long) -> Self {
+        self.allowed.insert(syscall);
+        self
+    }
+
+    /// Allow each of the given syscalls.
+    pub fn allow_syscalls(mut self, syscalls: &[c_long]) -> Self {
+        self.allowed.extend(syscalls.iter().cloned());
+        self
+    }
+
+    /// Set what happens when a syscall that is not allowed is made.
+    pub fn with_default_action(mut self, action: SeccompAction) -> Self {
+        self.default_action = action;
+        self
+    }
+
+    /// The syscalls the filter allows, in ascending order.
+    pub fn allowed_syscalls(&self) -> impl Iterator<Item = c_long> + '_ {
+        self.allowed.iter().cloned()
+    }
+
+    /// Install the filter on the current thread.
+    ///
+    /// This also sets the thread's `no_new_privs` attribute, which seccomp requires of
+    /// unprivileged processes.
+    pub fn install(&self) -> Result<(), Error> {
+        let program = self.compile();
+        let prog = SockFprog {
+            len: program.len() as u16,
+            filter: program.as_ptr(),
+        };
+        unsafe {
+            if libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                return Err(Error::SeccompFailed(format!(
+                    "could not set no_new_privs: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+            if libc::prctl(
+                libc::PR_SET_SECCOMP,
+                SECCOMP_MODE_FILTER,
+                &prog as *const SockFprog,
+                0,
+                0,
+            ) != 0
+            {
+                return Err(Error::SeccompFailed(format!(
+                    "could not install filter: {}",
+                    std::io::Error::last_os_error()
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Compile the filter to a BPF program over `struct seccomp_data`.
+    fn compile(&self) -> Vec<SockFilter> {
+        let deny = self.default_action.ret_value();
+        let mut program = vec![
+            // syscall numbers are only meaningful for the architecture they were made on; this
+            // module is only built for x86-64
+            stmt(BPF_LD | BPF_W | BPF_ABS, SECCOMP_DATA_ARCH_OFFSET),
+            jump(BPF_JMP | BPF_JEQ | BPF_K, AUDIT_ARCH_X86_64, 1, 0),
+            stmt(BPF_RET | BPF_K, deny),
+            stmt(BPF_LD | BPF_W | BPF_ABS, SECCOMP_DATA_NR_OFFSET),
+            // reject the x32 ABI, whose syscall numbers alias the x86_64 ones
+            jump(BPF_JMP | BPF_JGE | BPF_K, X32_SYSCALL_BIT, 0, 1),
+            stmt(BPF_RET | BPF_K, deny),
+        ];
+        for &syscall in &self.allowed {
+            program.push(jump(BPF_JMP | BPF_JEQ | BPF_K, syscall as u32, 0, 1));
+            program.push(stmt(BPF_RET | BPF_K, SECCOMP_RET_ALLOW));
+        }
+        program.push(stmt(BPF_RET | BPF_K, deny));
+        program
+    }
+}
+
+// Definitions from `linux/filter.h`, `linux/seccomp.h`, and `linux/audit.h`.
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SockFilter {
+    code: u16,
+    jt: u8,
+    jf: u8,
+    k: u32,
+}
+
+#[repr(C)]
+struct SockFprog {
+    len: u16,
+    filter: *const SockFilter,
+}
+
+const BPF_LD: u16 = 0x00;
+const BPF_W: u16 = 0x00;
+const BPF_ABS: u16 = 0x20;
+const BPF_JMP: u16 = 0x05;
+const BPF_JEQ: u16 = 0x10;
+const BPF_JGE: u16 = 0x30;
+const BPF_K: u16 = 0x00;
+const BPF_RET: u16 = 0x06;
+
+const SECCOMP_MODE_FILTER: libc::c_ulong = 2;
+const SECCOMP_RET_KILL_PROCESS: u32 = 0x8000_0000;
+const SECCOMP_RET_KILL_THREAD: u32 = 0x0000_0000;
+const SECCOMP_RET_TRAP: u32 = 0x0003_0000;
+const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+
+const SECCOMP_DATA_NR_OFFSET: u32 = 0;
+const SECCOMP_DATA_ARCH_OFFSET: u32 = 4;
+
+const AUDIT_ARCH_X86_64: u32 = 0xc000_003e;
+const X32_SYSCALL_BIT: u32 = 0x4000_0000;
+
+fn stmt(code: u16, k: u32) -> SockFilter {
+    SockFilter {
+        code,
+        jt: 0,
+        jf: 0,
+        k,
+    }
+}
+
+fn jump(code: u16, k: u32, jt: u8, jf: u8) -> SockFilter {
+    SockFilter { code, jt, jf, k }
+}
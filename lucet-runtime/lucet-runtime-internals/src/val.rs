@@ -95,6 +95,129 @@ impl_from_scalars!({
     F64: f64
 });
 
+/// Rust types that are passed to and from Wasm functions as a single Wasm value.
+pub trait WasmValue: Into<Val> + Copy {
+    /// The Wasm type that values of this type are passed as.
+    const VALUE_TYPE: ValueType;
+
+    /// Reinterpret a `Val` of the corresponding Wasm type.
+    fn from_val(val: &Val) -> Self;
+}
+
+macro_rules! impl_wasm_value_ints {
+    ( { $( $ty:ty : $value_type:ident ),* } ) => {
+        $(
+            impl WasmValue for $ty {
+                const VALUE_TYPE: ValueType = ValueType::$value_type;
+
+                fn from_val(val: &Val) -> $ty {
+                    val_to_stack(val) as $ty
+                }
+            }
+        )*
+    };
+}
+
+impl_wasm_value_ints!({
+    u32: I32,
+    i32: I32,
+    u64: I64,
+    i64: I64
+});
+
+impl WasmValue for f32 {
+    const VALUE_TYPE: ValueType = ValueType::F32;
+
+    fn from_val(val: &Val) -> f32 {
+        f32::from_bits(val_to_stack(val) as u32)
+    }
+}
+
+impl WasmValue for f64 {
+    const VALUE_TYPE: ValueType = ValueType::F64;
+
+    fn from_val(val: &Val) -> f64 {
+        f64::from_bits(val_to_stack(val))
+    }
+}
+
+/// The result of a Wasm function: either `()` or a single `WasmValue`.
+pub trait WasmRet {
+    /// The Wasm return type of functions returning this type.
+    const RET_TY: Option<ValueType>;
+
+    fn into_retval(self) -> UntypedRetVal;
+
+    fn from_retval(retval: UntypedRetVal) -> Self;
+}
+
+impl WasmRet for () {
+    const RET_TY: Option<ValueType> = None;
+
+    fn into_retval(self) -> UntypedRetVal {
+        UntypedRetVal::default()
+    }
+
+    fn from_retval(_retval: UntypedRetVal) {}
+}
+
+macro_rules! impl_wasm_ret {
+    ( $( $ty:ty ),* ) => {
+        $(
+            impl WasmRet for $ty {
+                const RET_TY: Option<ValueType> = Some(<$ty as WasmValue>::VALUE_TYPE);
+
+                fn into_retval(self) -> UntypedRetVal {
+                    self.into()
+                }
+
+                fn from_retval(retval: UntypedRetVal) -> $ty {
+                    retval.into()
+                }
+            }
+        )*
+    };
+}
+
+impl_wasm_ret!(u32, i32, u64, i64, f32, f64);
+
+/// The arguments of a Wasm function: a tuple of up to eight `WasmValue`s.
+pub trait WasmParams {
+    /// The Wasm types of the arguments.
+    fn value_types() -> Vec<ValueType>;
+
+    /// Call `f` with the arguments as `Val`s, preceded by `first`.
+    ///
+    /// The values are stored on the stack rather than in a `Vec`.
+    fn with_vals<R, F: FnOnce(&[Val]) -> R>(self, first: Val, f: F) -> R;
+}
+
+macro_rules! impl_wasm_params {
+    ( $( $param:ident ),* ) => {
+        impl<$( $param: WasmValue ),*> WasmParams for ( $( $param, )* ) {
+            fn value_types() -> Vec<ValueType> {
+                vec![$( <$param as WasmValue>::VALUE_TYPE ),*]
+            }
+
+            #[allow(non_snake_case)]
+            fn with_vals<R, F: FnOnce(&[Val]) -> R>(self, first: Val, f: F) -> R {
+                let ( $( $param, )* ) = self;
+                f(&[first, $( $param.into() ),*])
+            }
+        }
+    };
+}
+
+impl_wasm_params!();
+impl_wasm_params!(A1);
+impl_wasm_params!(A1, A2);
+impl_wasm_params!(A1, A2, A3);
+impl_wasm_params!(A1, A2, A3, A4);
+impl_wasm_params!(A1, A2, A3, A4, A5);
+impl_wasm_params!(A1, A2, A3, A4, A5, A6);
+impl_wasm_params!(A1, A2, A3, A4, A5, A6, A7);
+impl_wasm_params!(A1, A2, A3, A4, A5, A6, A7, A8);
+
 /// Register representation of `Val`.
 ///
 /// When mapping `Val`s to x86_64 registers, we map floating point
@@ -9,9 +9,12 @@ use crate::alloc::instance_heap_offset;
 use crate::context::Context;
 use crate::error::Error;
 use crate::instance::{
-    EmptyYieldVal, Instance, InstanceInternal, State, TerminationDetails, YieldedVal,
-    CURRENT_INSTANCE, HOST_CTX,
+    ctx_ref, ctx_ref_mut, EmptyYieldVal, GuestExit, Instance, InstanceInternal, State,
+    TerminationDetails, YieldedVal, CURRENT_INSTANCE, HOST_CTX,
 };
+use crate::linker::LinkedImports;
+use crate::memory::GuestMemory;
+use crate::val::{UntypedRetVal, Val};
 use lucet_module::{FunctionHandle, GlobalValue};
 use std::any::Any;
 use std::borrow::{Borrow, BorrowMut};
@@ -167,6 +170,15 @@ impl Vmctx {
         RefMut::map(r, |b| b.borrow_mut())
     }
 
+    /// Return a bounds-checked view of the WebAssembly heap.
+    ///
+    /// The view mutably borrows the heap, so if the heap is already borrowed by `heap()`,
+    /// `heap_mut()`, or another `memory()` view, the instance will terminate with
+    /// `TerminationDetails::BorrowError`.
+    pub fn memory(&self) -> GuestMemory<'_> {
+        GuestMemory::from_vmctx_heap(self.heap_mut())
+    }
+
     /// Check whether the heap has grown, and replace the heap view if it has.
     ///
     /// This handles the case where the length of the heap is modified by a call to
@@ -197,6 +209,45 @@ impl Vmctx {
         self.instance().check_heap(ptr, len)
     }
 
+    /// Check whether the instance runs in deterministic mode.
+    pub fn is_deterministic(&self) -> bool {
+        self.instance().is_deterministic()
+    }
+
+    /// Declare that the running hostcall, named `hostcall`, is not deterministic.
+    ///
+    /// If the instance runs in deterministic mode, this terminates it with
+    /// `TerminationDetails::Nondeterministic`; otherwise it does nothing. Hostcalls defined with
+    /// `#[lucet_hostcall]` call this before running, unless they are declared `deterministic`;
+    /// deterministic hostcalls that are only nondeterministic for some arguments may call it
+    /// themselves.
+    pub fn nondeterministic(&self, hostcall: &'static str) {
+        if self.is_deterministic() {
+            panic!(TerminationDetails::Nondeterministic(hostcall));
+        }
+    }
+
+    /// Count a call to the hostcall named `hostcall` against the instance's hostcall rate limit.
+    ///
+    /// If the instance has made more hostcalls in the current second than
+    /// [its limit](../region/struct.InstanceBuilder.html#method.with_hostcall_rate_limit), this
+    /// terminates it with `TerminationDetails::HostcallRateExceeded`. Hostcalls defined with
+    /// `#[lucet_hostcall]` and host functions bound with a `Linker` call this before running.
+    #[doc(hidden)]
+    pub fn charge_hostcall(&self, hostcall: &'static str) {
+        if !unsafe { self.instance_mut() }.charge_hostcall() {
+            panic!(TerminationDetails::HostcallRateExceeded(hostcall));
+        }
+    }
+
+    /// Check whether a `KillSwitch` has terminated the instance during the running hostcall.
+    ///
+    /// The instance only stops once the hostcall returns, so hostcalls that may block for a long
+    /// time, such as those that wait for I/O, can check this periodically to return early.
+    pub fn termination_requested(&self) -> bool {
+        self.instance().kill_state.terminated_in_hostcall()
+    }
+
     /// Check whether a context value of a particular type exists.
     pub fn contains_embed_ctx<T: Any>(&self) -> bool {
         self.instance().contains_embed_ctx::<T>()
@@ -232,6 +283,70 @@ impl Vmctx {
         }
     }
 
+    /// Get a reference to a context value of a particular type.
+    ///
+    /// Unlike [`get_embed_ctx()`](#method.get_embed_ctx), a missing or conflicting value is
+    /// returned as an `Error` naming the type rather than terminating the instance.
+    pub fn try_get_embed_ctx<T: Any>(&self) -> Result<Ref<'_, T>, Error> {
+        ctx_ref::<T>(self.instance().embed_ctx.try_get::<T>(), None)
+    }
+
+    /// Get a mutable reference to a context value of a particular type.
+    ///
+    /// Unlike [`get_embed_ctx_mut()`](#method.get_embed_ctx_mut), a missing or conflicting value
+    /// is returned as an `Error` naming the type rather than terminating the instance.
+    pub fn try_get_embed_ctx_mut<T: Any>(&self) -> Result<RefMut<'_, T>, Error> {
+        ctx_ref_mut::<T>(self.instance().embed_ctx.try_get_mut::<T>(), None)
+    }
+
+    /// Check whether a context value of a particular type exists under `key`.
+    pub fn contains_keyed_embed_ctx<T: Any>(&self, key: &str) -> bool {
+        self.instance().contains_keyed_embed_ctx::<T>(key)
+    }
+
+    /// Get a reference to the context value of a particular type stored under `key`.
+    ///
+    /// If such a context does not exist, the instance will terminate with
+    /// `TerminationDetails::CtxNotFound`.
+    ///
+    /// If the context is already mutably borrowed by `get_keyed_embed_ctx_mut`, the instance will
+    /// terminate with `TerminationDetails::BorrowError`.
+    pub fn get_keyed_embed_ctx<T: Any>(&self, key: &str) -> Ref<'_, T> {
+        match self.instance().embed_ctx.try_get_keyed::<T>(key) {
+            Some(Ok(t)) => t,
+            Some(Err(_)) => panic!(TerminationDetails::BorrowError("get_keyed_embed_ctx")),
+            None => panic!(TerminationDetails::CtxNotFound),
+        }
+    }
+
+    /// Get a mutable reference to the context value of a particular type stored under `key`.
+    ///
+    /// If such a context does not exist, the instance will terminate with
+    /// `TerminationDetails::CtxNotFound`.
+    ///
+    /// If the context is already borrowed by some other use of `get_keyed_embed_ctx` or
+    /// `get_keyed_embed_ctx_mut`, the instance will terminate with
+    /// `TerminationDetails::BorrowError`.
+    pub fn get_keyed_embed_ctx_mut<T: Any>(&self, key: &str) -> RefMut<'_, T> {
+        match self.instance().embed_ctx.try_get_keyed_mut::<T>(key) {
+            Some(Ok(t)) => t,
+            Some(Err(_)) => panic!(TerminationDetails::BorrowError("get_keyed_embed_ctx_mut")),
+            None => panic!(TerminationDetails::CtxNotFound),
+        }
+    }
+
+    /// Get a reference to the context value of a particular type stored under `key`, returning
+    /// an `Error` naming the type and key if it is missing or already mutably borrowed.
+    pub fn try_get_keyed_embed_ctx<T: Any>(&self, key: &str) -> Result<Ref<'_, T>, Error> {
+        self.instance().get_keyed_embed_ctx::<T>(key)
+    }
+
+    /// Get a mutable reference to the context value of a particular type stored under `key`,
+    /// returning an `Error` naming the type and key if it is missing or already borrowed.
+    pub fn try_get_keyed_embed_ctx_mut<T: Any>(&self, key: &str) -> Result<RefMut<'_, T>, Error> {
+        self.instance().get_keyed_embed_ctx_mut::<T>(key)
+    }
+
     /// Terminate this guest and return to the host context without unwinding.
     ///
     /// This is almost certainly not what you want to use to terminate an instance from a hostcall,
@@ -241,6 +356,16 @@ impl Vmctx {
         self.instance_mut().terminate(details)
     }
 
+    /// Handle a panic caught at the boundary of a hostcall, according to the instance's
+    /// [`HostPanicPolicy`](../instance/enum.HostPanicPolicy.html).
+    ///
+    /// This is used in the expansion of `#[lucet_hostcall]`, for panics whose payload is not
+    /// `TerminationDetails`.
+    #[doc(hidden)]
+    pub unsafe fn host_panicked(&self, payload: Box<dyn Any + Send>) -> ! {
+        self.instance_mut().host_panicked(payload)
+    }
+
     /// Grow the guest memory by the given number of WebAssembly pages.
     ///
     /// On success, returns the number of pages that existed before the call.
@@ -329,6 +454,42 @@ impl Vmctx {
             .get_func_from_idx(table_idx, func_idx)
     }
 
+    /// Call an import that a [`Linker`](../linker/struct.Linker.html) bound to the export of
+    /// another instance.
+    ///
+    /// The linked instance runs on the current thread until its export returns. If it faults or
+    /// terminates, the error is returned here and this instance keeps running; it is also an error
+    /// for the linked export to yield. Calls fail with `Error::LinkError` rather than blocking if
+    /// the linked instance is already in use.
+    ///
+    /// If the embedder context holding the linked imports is mutably borrowed, the instance will
+    /// terminate with `TerminationDetails::BorrowError`.
+    pub fn call_linked_import(
+        &self,
+        module: &str,
+        field: &str,
+        args: &[Val],
+    ) -> Result<UntypedRetVal, Error> {
+        match self.instance().embed_ctx.try_get::<LinkedImports>() {
+            Some(Ok(linked)) => linked.call(module, field, args),
+            Some(Err(_)) => panic!(TerminationDetails::BorrowError("call_linked_import")),
+            None => Err(Error::LinkError(
+                "instance was not built with a linker".to_owned(),
+            )),
+        }
+    }
+
+    /// Exit the instance with the given status, returning
+    /// [`RunResult::Exited`](../enum.RunResult.html#variant.Exited) to where the instance was run
+    /// or resumed.
+    ///
+    /// This is the equivalent of a process exiting: it is not an error, but like termination, it
+    /// unwinds to the enclosing hostcall body, and the instance must be reset before it can run
+    /// again.
+    pub fn exit(&self, status: i32) -> ! {
+        panic!(TerminationDetails::provide(GuestExit(status)))
+    }
+
     /// Suspend the instance, returning an empty
     /// [`RunResult::Yielded`](../enum.RunResult.html#variant.Yielded) to where the instance was run
     /// or resumed.
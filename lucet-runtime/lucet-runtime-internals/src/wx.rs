@@ -0,0 +1,207 @@
+//! Auditing the memory owned by the runtime for mappings that are both writable and executable.
+//!
+//! The runtime keeps track of the memory it maps: the segments of loaded modules, the trampolines
+//! for host functions, and the slots of regions. [`audit()`](fn.audit.html) checks all of it
+//! against the current mappings of the process, and reports any that are writable and executable
+//! at the same time.
+//!
+//! When [enforcement](fn.set_enforcement.html) is enabled, loading a module whose segments end up
+//! writable and executable fails with `Error::WxViolation`, for deployments where such memory is
+//! not permitted at all.
+//!
+//! Auditing requires `/proc/self/maps`, so it is only supported on Linux.
+
+use crate::error::Error;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// A mapping owned by the runtime that is both writable and executable.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    /// The start of the mapping.
+    pub start: usize,
+    /// The end of the mapping, exclusive.
+    pub end: usize,
+    /// What the runtime uses the memory for, such as `"module"`.
+    pub owner: &'static str,
+    /// The file backing the mapping, if any.
+    pub path: Option<String>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} mapping {:#x}-{:#x}",
+            self.owner, self.start, self.end
+        )?;
+        if let Some(path) = &self.path {
+            write!(f, " ({})", path)?;
+        }
+        Ok(())
+    }
+}
+
+static ENFORCEMENT: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    /// The start of each range owned by the runtime, mapped to its length, its owner, and the
+    /// number of times it has been registered.
+    static ref OWNED: Mutex<BTreeMap<usize, (usize, &'static str, usize)>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Set whether modules are rejected if loading them leaves memory that is both writable and
+/// executable.
+///
+/// This applies to all modules loaded by the process after the call. Defaults to `false`.
+pub fn set_enforcement(enabled: bool) {
+    ENFORCEMENT.store(enabled, Ordering::SeqCst);
+}
+
+/// Check whether modules are rejected if loading them leaves memory that is both writable and
+/// executable.
+pub fn enforcement_enabled() -> bool {
+    ENFORCEMENT.load(Ordering::SeqCst)
+}
+
+/// Find every mapping owned by the runtime that is both writable and executable.
+pub fn audit() -> Result<Vec<Violation>, Error> {
+    let owned = OWNED
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(&start, &(len, owner, _))| (start, len, owner))
+        .collect::<Vec<_>>();
+    violations(&owned)
+}
+
+/// Record that the runtime owns a range of memory, so that it is included in audits.
+pub(crate) fn register(start: usize, len: usize, owner: &'static str) {
+    let mut owned = OWNED.lock().unwrap();
+    // the same object may be loaded by more than one module
+    owned.entry(start).or_insert((len, owner, 0)).2 += 1;
+}
+
+/// Record that the runtime no longer owns a range registered with `register()`.
+pub(crate) fn unregister(start: usize) {
+    let mut owned = OWNED.lock().unwrap();
+    if let Some(entry) = owned.get_mut(&start) {
+        entry.2 -= 1;
+        if entry.2 == 0 {
+            owned.remove(&start);
+        }
+    }
+}
+
+/// What the runtime uses the range containing `addr` for, if it owns one.
+pub(crate) fn owner_of(addr: usize) -> Option<&'static str> {
+    let owned = OWNED.lock().unwrap();
+    owned
+        .range(..=addr)
+        .next_back()
+        .filter(|(&start, &(len, _, _))| addr < start + len)
+        .map(|(_, &(_, owner, _))| owner)
+}
+
+/// Check ranges that are about to be registered, if enforcement is enabled.
+pub(crate) fn enforce(ranges: &[(usize, usize)], owner: &'static str) -> Result<(), Error> {
+    if !enforcement_enabled() {
+        return Ok(());
+    }
+    let ranges = ranges
+        .iter()
+        .map(|&(start, len)| (start, len, owner))
+        .collect::<Vec<_>>();
+    let found = violations(&ranges)?;
+    if found.is_empty() {
+        Ok(())
+    } else {
+        let found = found.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        Err(Error::WxViolation(found.join(", ")))
+    }
+}
+
+/// Find the writable and executable mappings of the process that overlap the given ranges.
+#[cfg(target_os = "linux")]
+fn violations(ranges: &[(usize, usize, &'static str)]) -> Result<Vec<Violation>, Error> {
+    let maps = std::fs::read_to_string("/proc/self/maps")
+        .map_err(|e| Error::InternalError(anyhow::Error::new(e).context("reading mappings")))?;
+    let mut found = vec![];
+    for line in maps.lines() {
+        // start-end perms offset dev inode [path]
+        let mut fields = line.split_whitespace();
+        let (range, perms) = match (fields.next(), fields.next()) {
+            (Some(range), Some(perms)) => (range, perms),
+            _ => continue,
+        };
+        if !(perms.contains('w') && perms.contains('x')) {
+            continue;
+        }
+        let mut bounds = range
+            .split('-')
+            .map(|bound| usize::from_str_radix(bound, 16));
+        let (start, end) = match (bounds.next(), bounds.next()) {
+            (Some(Ok(start)), Some(Ok(end))) => (start, end),
+            _ => return Err(lucet_format_err!("malformed mapping: {}", line)),
+        };
+        let path = fields.nth(3).map(|path| path.to_owned());
+        for &(owned_start, len, owner) in ranges {
+            if start < owned_start + len && owned_start < end {
+                found.push(Violation {
+                    start,
+                    end,
+                    owner,
+                    path: path.clone(),
+                });
+                break;
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn violations(_ranges: &[(usize, usize, &'static str)]) -> Result<Vec<Violation>, Error> {
+    Err(Error::Unsupported(
+        "auditing memory mappings is only supported on Linux".to_owned(),
+    ))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use nix::sys::mman::{mmap, munmap, MapFlags, ProtFlags};
+
+    #[test]
+    fn audit_finds_writable_executable_memory() {
+        let len = 4096;
+        let mem = unsafe {
+            mmap(
+                std::ptr::null_mut(),
+                len,
+                ProtFlags::PROT_READ | ProtFlags::PROT_WRITE | ProtFlags::PROT_EXEC,
+                MapFlags::MAP_PRIVATE | MapFlags::MAP_ANON,
+                -1,
+                0,
+            )
+            .expect("mmap succeeds")
+        };
+        let start = mem as usize;
+
+        register(start, len, "test");
+        let found = audit().unwrap();
+        let violation = found
+            .iter()
+            .find(|v| v.start <= start && start < v.end)
+            .expect("mapping is reported");
+        assert_eq!(violation.owner, "test");
+        unregister(start);
+        assert!(!audit().unwrap().iter().any(|v| v.owner == "test"));
+
+        unsafe { munmap(mem, len).expect("munmap succeeds") };
+    }
+}
@@ -22,11 +22,39 @@ use syn::spanned::Spanned;
 /// }
 /// ```
 ///
+/// Arguments of type `WasmSlice<T>` are passed to the exported hostcall as two `u32` arguments, the
+/// guest address of the slice followed by its length in elements, and are reassembled before the
+/// impl hostcall is called:
+///
+/// ```ignore
+/// #[lucet_hostcall]
+/// #[no_mangle]
+/// pub fn print(vmctx: &Vmctx, msg: WasmSlice<u8>) {
+///     println!("{}", msg.as_str(&vmctx.memory()).unwrap());
+/// }
+/// ```
+///
+/// Hostcalls terminate instances running in deterministic mode rather than run, unless they are
+/// marked `deterministic`. Mark the hostcalls whose results depend only on their arguments, the
+/// instance, and the inputs its embedder gives it; those that read a clock, for instance, must
+/// not be:
+///
+/// ```ignore
+/// #[lucet_hostcall(deterministic)]
+/// #[no_mangle]
+/// pub fn add(vmctx: &Vmctx, x: u64, y: u64) -> u64 {
+///     x + y
+/// }
+/// ```
+///
+/// A deterministic hostcall that is only nondeterministic for some arguments can call
+/// `Vmctx::nondeterministic()` for those.
+///
 /// Note that `lucet-runtime` must be a dependency of any crate where this attribute is used, and it
 /// may not be renamed (this restriction may be lifted once [this
 /// issue](https://github.com/rust-lang/rust/issues/54363) is resolved).
 #[proc_macro_attribute]
-pub fn lucet_hostcall(_attr: TokenStream, item: TokenStream) -> TokenStream {
+pub fn lucet_hostcall(attr: TokenStream, item: TokenStream) -> TokenStream {
     // determine whether we need to import from `lucet_runtime_internals`; this is useful if we want
     // to define a hostcall for a target (or tests, more concretely) that doesn't depend on
     // `lucet-runtime`
@@ -38,6 +66,19 @@ pub fn lucet_hostcall(_attr: TokenStream, item: TokenStream) -> TokenStream {
         }
     };
 
+    let deterministic = match syn::parse_macro_input!(attr as syn::AttributeArgs).as_slice() {
+        [] => false,
+        [syn::NestedMeta::Meta(syn::Meta::Path(path))] if path.is_ident("deterministic") => true,
+        [arg, ..] => {
+            return syn::Error::new(
+                arg.span(),
+                "the only argument `lucet_hostcall` accepts is `deterministic`",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
     let mut hostcall = syn::parse_macro_input!(item as syn::ItemFn);
     let hostcall_ident = hostcall.sig.ident.clone();
 
@@ -68,27 +109,43 @@ pub fn lucet_hostcall(_attr: TokenStream, item: TokenStream) -> TokenStream {
     };
 
     // replace the first argument to the raw hostcall with the vmctx pointer
-    if let Some(arg0) = raw_sig.inputs.iter_mut().next() {
-        let lucet_vmctx: syn::FnArg = syn::parse_quote!(vmctx_raw: *const #vmctx_mod::lucet_vmctx);
-        *arg0 = lucet_vmctx;
+    let lucet_vmctx: syn::FnArg = syn::parse_quote!(vmctx_raw: *const #vmctx_mod::lucet_vmctx);
+    let mut raw_inputs = syn::punctuated::Punctuated::<syn::FnArg, syn::Token![,]>::new();
+    if !hostcall.sig.inputs.is_empty() {
+        raw_inputs.push(lucet_vmctx);
     }
 
-    // the args after the first to provide to the hostcall impl
-    let impl_args = hostcall
-        .sig
-        .inputs
-        .iter()
-        .skip(1)
-        .map(|arg| match arg {
+    // the args after the first to provide to the hostcall impl; `WasmSlice` args are passed to
+    // the raw hostcall as a separate guest address and length, and reassembled here
+    let mut impl_args = vec![];
+    // the raw args after the first, as they are recorded when hostcalls are interposed
+    let mut recorded_args = vec![];
+    for (i, arg) in hostcall.sig.inputs.iter().enumerate().skip(1) {
+        match arg {
             syn::FnArg::Receiver(_) => {
                 // this case is an error, but we produce some valid rust code anyway so that the
                 // compiler can produce a more meaningful error message at a later point
                 let s = syn::Token![self](arg.span());
-                quote!(#s)
+                raw_inputs.push(arg.clone());
+                impl_args.push(quote!(#s));
             }
-            syn::FnArg::Typed(syn::PatType { pat, .. }) => quote!(#pat),
-        })
-        .collect::<Vec<_>>();
+            syn::FnArg::Typed(syn::PatType { ty, .. }) if is_wasm_slice(ty) => {
+                let ptr = quote::format_ident!("slice_ptr_{}", i);
+                let len = quote::format_ident!("slice_len_{}", i);
+                raw_inputs.push(syn::parse_quote!(#ptr: u32));
+                raw_inputs.push(syn::parse_quote!(#len: u32));
+                impl_args.push(quote!(<#ty>::from_raw(#ptr, #len)));
+                recorded_args.push(quote!(#ptr));
+                recorded_args.push(quote!(#len));
+            }
+            syn::FnArg::Typed(syn::PatType { pat, .. }) => {
+                raw_inputs.push(arg.clone());
+                impl_args.push(quote!(#pat));
+                recorded_args.push(quote!(#pat));
+            }
+        }
+    }
+    raw_sig.inputs = raw_inputs;
 
     let termination_details = if from_internals {
         quote! { lucet_runtime_internals::instance::TerminationDetails }
@@ -96,6 +153,23 @@ pub fn lucet_hostcall(_attr: TokenStream, item: TokenStream) -> TokenStream {
         quote! { lucet_runtime::TerminationDetails }
     };
 
+    let replay_mod = if from_internals {
+        quote! { lucet_runtime_internals::replay }
+    } else {
+        quote! { lucet_runtime::replay }
+    };
+
+    let hostcall_name = hostcall_ident.to_string();
+    let determinism_check = if deterministic {
+        quote! {}
+    } else {
+        quote! { #vmctx_mod::Vmctx::from_raw(vmctx_raw).nondeterministic(#hostcall_name); }
+    };
+
+    let call_impl = quote! {
+        #hostcall_ident(&#vmctx_mod::Vmctx::from_raw(vmctx_raw), #(#impl_args),*)
+    };
+
     let raw_hostcall = quote! {
         #(#attrs)*
         #vis
@@ -106,7 +180,16 @@ pub fn lucet_hostcall(_attr: TokenStream, item: TokenStream) -> TokenStream {
             let vmctx = #vmctx_mod::Vmctx::from_raw(vmctx_raw);
             #vmctx_mod::VmctxInternal::instance_mut(&vmctx).uninterruptable(|| {
                 let res = std::panic::catch_unwind(move || {
-                    #hostcall_ident(&#vmctx_mod::Vmctx::from_raw(vmctx_raw), #(#impl_args),*)
+                    #vmctx_mod::Vmctx::from_raw(vmctx_raw).charge_hostcall(#hostcall_name);
+                    #determinism_check
+                    let interpose_vmctx = #vmctx_mod::Vmctx::from_raw(vmctx_raw);
+                    let inst = #vmctx_mod::VmctxInternal::instance_mut(&interpose_vmctx);
+                    if inst.is_interposing_hostcalls() {
+                        let args = vec![#(#replay_mod::raw_bytes(&#recorded_args)),*];
+                        inst.interpose_hostcall(#hostcall_name, args, move || #call_impl)
+                    } else {
+                        #call_impl
+                    }
                 });
                 match res {
                     Ok(res) => res,
@@ -115,7 +198,7 @@ pub fn lucet_hostcall(_attr: TokenStream, item: TokenStream) -> TokenStream {
                             Ok(details) => {
                                 #vmctx_mod::Vmctx::from_raw(vmctx_raw).terminate_no_unwind(*details)
                             },
-                            Err(e) => std::panic::resume_unwind(e),
+                            Err(e) => #vmctx_mod::Vmctx::from_raw(vmctx_raw).host_panicked(e),
                         }
                     }
                 }
@@ -124,3 +207,15 @@ pub fn lucet_hostcall(_attr: TokenStream, item: TokenStream) -> TokenStream {
     };
     raw_hostcall.into()
 }
+
+/// Whether the type of a hostcall argument is a `WasmSlice`, which takes up two raw arguments.
+fn is_wasm_slice(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(syn::TypePath { path, .. }) => path
+            .segments
+            .last()
+            .map(|seg| seg.ident == "WasmSlice")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
@@ -0,0 +1,12 @@
+(module
+  (memory 1)
+  (table 3 anyfunc)
+  ;; element 2 is left empty
+  (elem (i32.const 0) $add_2 $mul_f64_2)
+  (func $add_2 (param i64 i64) (result i64)
+    (i64.add (get_local 0) (get_local 1))
+  )
+  (func $mul_f64_2 (param f64 f64) (result f64)
+    (f64.mul (get_local 0) (get_local 1))
+  )
+)
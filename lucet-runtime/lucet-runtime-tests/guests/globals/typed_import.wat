@@ -0,0 +1,6 @@
+(module
+  (global $x (import "env" "x") i32)
+  (func $get_x (export "get_x") (result i32)
+    (get_global $x)
+  )
+)
@@ -0,0 +1,13 @@
+#include <stddef.h>
+#include <stdint.h>
+
+extern void hostcall_test_func_typed_ptrs(uint64_t *counter, const char *msg, size_t msg_len);
+
+static uint64_t counter = 41;
+
+int main(void)
+{
+    char msg[] = "typed pointers";
+    hostcall_test_func_typed_ptrs(&counter, msg, sizeof(msg) - 1);
+    return (int) counter;
+}
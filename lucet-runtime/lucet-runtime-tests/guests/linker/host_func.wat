@@ -0,0 +1,11 @@
+(module
+  (import "env" "add" (func $add (param i64 i64) (result i64)))
+  (import "env" "scale" (func $scale (param f64) (result f64)))
+  (memory 1)
+  (func $add_then_double (export "add_then_double") (param i64 i64) (result i64)
+    (i64.mul (call $add (get_local 0) (get_local 1)) (i64.const 2))
+  )
+  (func $call_scale (export "scale") (param f64) (result f64)
+    (call $scale (get_local 0))
+  )
+)
@@ -1,8 +1,11 @@
 use anyhow::Error;
 use lucet_module::bindings::Bindings;
-use lucet_runtime_internals::module::DlModule;
+use lucet_module::SerializedModule;
+use lucet_runtime_internals::module::{DlModule, StaticModule};
 use lucet_wasi_sdk::{CompileOpts, Link, LinkOpt, LinkOpts};
 use lucetc::{Lucetc, LucetcOpts};
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tempfile::TempDir;
@@ -63,6 +66,67 @@ pub fn wasm_test<P, Q>(wasm_file: P, bindings_file: Q) -> Result<Arc<DlModule>,
 where
     P: AsRef<Path>,
     Q: AsRef<Path>,
+{
+    wasm_test_with_loader(wasm_file, bindings_file, |so_file| DlModule::load(so_file))
+}
+
+/// Like `test_module_wasm`, but the imports of the module do not need to be defined when it is
+/// loaded.
+pub fn test_module_wasm_with_lazy_imports(
+    dir: &str,
+    wasmfile: &str,
+) -> Result<Arc<DlModule>, Error> {
+    let wasm_path = guest_file(dir, wasmfile);
+    let bindings_path = guest_file(dir, "bindings.json");
+    wasm_test_with_loader(wasm_path, bindings_path, |so_file| {
+        DlModule::load_with_lazy_imports(so_file)
+    })
+}
+
+/// Like `test_module_wasm_with_lazy_imports`, but the module is loaded into its own link-map
+/// namespace.
+pub fn test_module_wasm_isolated(dir: &str, wasmfile: &str) -> Result<Arc<DlModule>, Error> {
+    let wasm_path = guest_file(dir, wasmfile);
+    let bindings_path = guest_file(dir, "bindings.json");
+    wasm_test_with_loader(wasm_path, bindings_path, |so_file| {
+        DlModule::load_isolated(so_file)
+    })
+}
+
+/// Like `test_module_wasm`, but the module is created as a `StaticModule`.
+///
+/// A test cannot link a guest into its own executable, so the code is mapped by opening the
+/// shared object, which is never closed, and the module is created from its `lucet_module` symbol
+/// just as `lucet_static_module!` would.
+pub fn test_module_wasm_static(dir: &str, wasmfile: &str) -> Result<Arc<StaticModule>, Error> {
+    let workdir = TempDir::new().expect("create working directory");
+    let bindings = Bindings::from_file(guest_file(dir, "bindings.json"))?;
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(guest_file(dir, wasmfile))
+        .with_bindings(bindings)
+        .shared_object_file(so_file.clone())?;
+
+    let so_path = CString::new(so_file.as_os_str().as_bytes()).expect("path has no nul bytes");
+    let serialized_module = unsafe {
+        let lib = libc::dlopen(so_path.as_ptr(), libc::RTLD_NOW);
+        assert!(!lib.is_null(), "test module can be opened");
+        let sym = libc::dlsym(lib, b"lucet_module\0".as_ptr() as *const libc::c_char);
+        assert!(!sym.is_null(), "test module defines `lucet_module`");
+        &*(sym as *const SerializedModule)
+    };
+    let module = unsafe { StaticModule::from_serialized(serialized_module)? };
+    Ok(module)
+}
+
+fn wasm_test_with_loader<P, Q, L>(
+    wasm_file: P,
+    bindings_file: Q,
+    load: L,
+) -> Result<Arc<DlModule>, Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+    L: FnOnce(PathBuf) -> Result<Arc<DlModule>, lucet_runtime_internals::error::Error>,
 {
     let workdir = TempDir::new().expect("create working directory");
 
@@ -74,7 +138,7 @@ where
 
     native_build.shared_object_file(so_file.clone())?;
 
-    let dlmodule = DlModule::load(so_file)?;
+    let dlmodule = load(so_file)?;
 
     Ok(dlmodule)
 }
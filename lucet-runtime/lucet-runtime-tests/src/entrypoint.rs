@@ -1,4 +1,4 @@
-use crate::build::test_module_wasm;
+use crate::build::{test_module_wasm, test_module_wasm_static};
 use crate::helpers::{MockExportBuilder, MockModuleBuilder};
 use lucet_module::{lucet_signature, FunctionPointer};
 use lucet_runtime_internals::module::Module;
@@ -9,6 +9,10 @@ pub fn wat_calculator_module() -> Arc<dyn Module> {
     test_module_wasm("entrypoint", "calculator.wat").expect("build and load module")
 }
 
+pub fn static_calculator_module() -> Arc<dyn Module> {
+    test_module_wasm_static("entrypoint", "calculator.wat").expect("build and create module")
+}
+
 pub fn mock_calculator_module() -> Arc<dyn Module> {
     extern "C" fn add_2(_vmctx: *const lucet_vmctx, arg0: u64, arg1: u64) -> u64 {
         arg0 + arg1
@@ -234,8 +238,10 @@ macro_rules! entrypoint_tests {
                 };
                 use std::sync::Arc;
                 use $TestRegion as TestRegion;
-                use $crate::build::test_module_c;
-                use $crate::entrypoint::{mock_calculator_module, wat_calculator_module};
+                use $crate::build::{test_module_c, test_module_wasm};
+                use $crate::entrypoint::{
+                    mock_calculator_module, static_calculator_module, wat_calculator_module,
+                };
 
                 #[test]
                 fn mock_calc_add_2() {
@@ -247,6 +253,11 @@ macro_rules! entrypoint_tests {
                     calc_add_2(wat_calculator_module());
                 }
 
+                #[test]
+                fn static_calc_add_2() {
+                    calc_add_2(static_calculator_module());
+                }
+
                 fn calc_add_2(module: Arc<dyn Module>) {
                     let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
                     let mut inst = region
@@ -315,6 +326,11 @@ macro_rules! entrypoint_tests {
                     calc_mul_2(wat_calculator_module())
                 }
 
+                #[test]
+                fn static_calc_mul_2() {
+                    calc_mul_2(static_calculator_module())
+                }
+
                 fn calc_mul_2(module: Arc<dyn Module>) {
                     let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
                     let mut inst = region
@@ -665,6 +681,137 @@ macro_rules! entrypoint_tests {
                     assert_eq!(u64::from(retval), 1800);
                 }
 
+                #[test]
+                fn run_table_entry() {
+                    let module = test_module_wasm("entrypoint", "table.wat")
+                        .expect("module compiled and loaded");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let retval = inst
+                        .run_table_entry(0, 0, &[123u64.into(), 456u64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(u64::from(retval), 123u64 + 456);
+
+                    let retval = inst
+                        .run_table_entry(0, 1, &[1.5f64.into(), 4.0f64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(f64::from(retval), 6.0);
+                }
+
+                #[test]
+                fn run_table_entry_typecheck() {
+                    let module = test_module_wasm("entrypoint", "table.wat")
+                        .expect("module compiled and loaded");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    match inst.run_table_entry(0, 0, &[1.5f64.into(), 4.0f64.into()]) {
+                        Err(Error::InvalidArgument(err)) => {
+                            assert_eq!(err, "entrypoint function signature mismatch")
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
+                #[test]
+                fn run_table_entry_missing() {
+                    let module = test_module_wasm("entrypoint", "table.wat")
+                        .expect("module compiled and loaded");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    // element 2 is within the table, but empty
+                    match inst.run_table_entry(0, 2, &[]) {
+                        Err(Error::FuncNotFound(0, 2)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    // element 3 is past the end of the table
+                    match inst.run_table_entry(0, 3, &[]) {
+                        Err(Error::FuncNotFound(0, 3)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    // there is only one table
+                    match inst.run_table_entry(1, 0, &[]) {
+                        Err(Error::FuncNotFound(1, 0)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
+                #[test]
+                fn mock_typed_func() {
+                    typed_func(mock_calculator_module())
+                }
+
+                #[test]
+                fn wat_typed_func() {
+                    typed_func(wat_calculator_module())
+                }
+
+                fn typed_func(module: Arc<dyn Module>) {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let add_2 = inst
+                        .get_typed_func::<(u64, u64), u64>("add_2")
+                        .expect("typed function signature matches");
+                    assert_eq!(add_2.call(&mut inst, (123, 456)).expect("instance runs"), 579);
+                    assert_eq!(add_2.call(&mut inst, (1, 2)).expect("instance runs"), 3);
+
+                    let add_f32_2 = inst
+                        .get_typed_func::<(f32, f32), f32>("add_f32_2")
+                        .expect("typed function signature matches");
+                    assert_eq!(add_f32_2.call(&mut inst, (1.5, 2.25)).expect("instance runs"), 3.75);
+                }
+
+                #[test]
+                fn typed_func_signature_mismatch() {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let inst = region
+                        .new_instance(mock_calculator_module())
+                        .expect("instance can be created");
+
+                    match inst.get_typed_func::<(u64, u64), f32>("add_2") {
+                        Err(Error::InvalidArgument(_)) => (),
+                        Err(e) => panic!("unexpected error: {}", e),
+                        Ok(_) => panic!("typed function with the wrong return type was created"),
+                    }
+                    match inst.get_typed_func::<(u64,), u64>("add_2") {
+                        Err(Error::InvalidArgument(_)) => (),
+                        Err(e) => panic!("unexpected error: {}", e),
+                        Ok(_) => panic!("typed function with the wrong arguments was created"),
+                    }
+                }
+
+                #[test]
+                fn typed_func_other_module() {
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let inst = region
+                        .new_instance(mock_calculator_module())
+                        .expect("instance can be created");
+                    let mut other = region
+                        .new_instance(mock_calculator_module())
+                        .expect("instance can be created");
+
+                    let add_2 = inst
+                        .get_typed_func::<(u64, u64), u64>("add_2")
+                        .expect("typed function signature matches");
+                    match add_2.call(&mut other, (1, 2)) {
+                        Err(Error::InvalidArgument(_)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
                 const TEST_REGION_INIT_VAL: libc::c_int = 123;
                 const TEST_REGION_SIZE: libc::size_t = 4;
 
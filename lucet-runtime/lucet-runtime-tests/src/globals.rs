@@ -8,7 +8,7 @@ macro_rules! globals_tests {
                 use $crate::helpers::{MockExportBuilder, MockModuleBuilder};
                 use lucet_module::{lucet_signature, FunctionPointer, GlobalValue};
                 use lucet_runtime::vmctx::{lucet_vmctx};
-                use lucet_runtime::{Error, Limits, Module, Region, RegionCreate};
+                use lucet_runtime::{Error, Limits, Module, Region, RegionCreate, Val};
                 use std::sync::Arc;
                 use $TestRegion as TestRegion;
 
@@ -53,16 +53,65 @@ macro_rules! globals_tests {
                 }
 
                 #[test]
-                fn reject_import() {
+                fn reject_unsupplied_import() {
                     let module = mock_import_module();
                     let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
                     match region.new_instance(module) {
                         Ok(_) => panic!("instance creation should not succeed"),
-                        Err(Error::Unsupported(_)) => (),
+                        Err(Error::LinkError(msg)) => assert!(msg.contains("something::else")),
                         Err(e) => panic!("unexpected error: {}", e),
                     }
                 }
 
+                #[test]
+                fn imported_globals_are_supplied() {
+                    let module = MockModuleBuilder::new()
+                        .with_exported_import(0, "env", "__stack_pointer", "sp")
+                        .with_global(1, 7)
+                        .build();
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_global_import("env", "__stack_pointer", 1024i32)
+                        .build()
+                        .expect("instance can be created");
+
+                    assert_eq!(unsafe { inst.globals()[0].i_32 }, 1024);
+                    assert_eq!(unsafe { inst.globals()[1].i_64 }, 7);
+                    assert_eq!(inst.get_global_as::<i32>("sp").expect("global is exported"), 1024);
+
+                    inst.set_global("sp", 512i32).expect("global is mutable");
+                    assert_eq!(inst.get_global_as::<i32>("sp").expect("global is exported"), 512);
+                    inst.reset().expect("instance resets");
+                    assert_eq!(inst.get_global_as::<i32>("sp").expect("global is exported"), 1024);
+                }
+
+                #[test]
+                fn imported_global_types_are_checked() {
+                    let module =
+                        test_module_wasm("globals", "typed_import.wat").expect("module compiled and loaded");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    match region
+                        .new_instance_builder(module.clone())
+                        .with_global_import("env", "x", 42i64)
+                        .build()
+                    {
+                        Ok(_) => panic!("instance creation should not succeed"),
+                        Err(Error::LinkError(msg)) => {
+                            assert!(msg.contains("`env::x` is of type I32"), "unexpected error: {}", msg)
+                        }
+                        Err(e) => panic!("unexpected error: {}", e),
+                    }
+
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_global_import("env", "x", 42i32)
+                        .build()
+                        .expect("instance can be created");
+                    let retval = inst.run("get_x", &[]).expect("instance runs").unwrap_returned();
+                    assert_eq!(i32::from(retval), 42);
+                }
+
                 fn mock_globals_module() -> Arc<dyn Module> {
                     extern "C" {
                         fn lucet_vmctx_get_globals(vmctx: *const lucet_vmctx) -> *mut GlobalValue;
@@ -178,6 +227,138 @@ macro_rules! globals_tests {
                         .unwrap_returned();
                     assert_eq!(i64::from(retval), 666);
                 }
+
+                #[test]
+                fn get_global_by_name() {
+                    let module =
+                        test_module_wasm("globals", "definition.wat").expect("module compiled and loaded");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    assert_eq!(inst.get_global_as::<i32>("z").expect("global is exported"), 6);
+                    match inst.get_global("z").expect("global is exported") {
+                        Val::I32(6) => (),
+                        v => panic!("unexpected global value: {:?}", v),
+                    }
+                }
+
+                #[test]
+                fn set_immutable_global_fails() {
+                    let module =
+                        test_module_wasm("globals", "definition.wat").expect("module compiled and loaded");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    match inst.set_global("z", 7i32) {
+                        Err(Error::ImmutableGlobal(name)) => assert_eq!(name, "z"),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    assert_eq!(inst.get_global_as::<i32>("z").expect("global is exported"), 6);
+                }
+
+                fn mock_exported_globals_module() -> Arc<dyn Module> {
+                    MockModuleBuilder::new()
+                        .with_exported_global(0, -1, "counter")
+                        .with_exported_immutable_global(1, 420, "limit")
+                        .build()
+                }
+
+                #[test]
+                fn set_global_by_name() {
+                    let module = mock_exported_globals_module();
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    assert_eq!(inst.get_global_as::<i64>("counter").expect("global is exported"), -1);
+                    inst.set_global("counter", 666i64).expect("global is mutable");
+                    assert_eq!(inst.get_global_as::<i64>("counter").expect("global is exported"), 666);
+                    assert_eq!(unsafe { inst.globals()[0].i_64 }, 666);
+
+                    match inst.set_global("limit", 0i64) {
+                        Err(Error::ImmutableGlobal(_)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    assert_eq!(inst.get_global_as::<i64>("limit").expect("global is exported"), 420);
+                }
+
+                #[test]
+                fn exports_are_listed() {
+                    use lucet_runtime::{Export, ExportKind};
+
+                    let module = MockModuleBuilder::new()
+                        .with_global(0, 1)
+                        .with_exported_global(1, 2, "counter")
+                        .with_exported_memory("memory")
+                        .with_exported_table("table")
+                        .build();
+                    let exports = module.exports();
+                    assert_eq!(exports.len(), 3);
+                    assert!(exports.contains(&Export { name: "counter".to_owned(), kind: ExportKind::Global(1) }));
+                    assert!(exports.contains(&Export { name: "memory".to_owned(), kind: ExportKind::Memory }));
+                    assert!(exports.contains(&Export { name: "table".to_owned(), kind: ExportKind::Table(0) }));
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+                    assert!(inst.exported_table("table").expect("table is exported").is_empty());
+                    match inst.exported_table("memory") {
+                        Err(Error::SymbolNotFound(_)) => (),
+                        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+                    }
+                    let mut memory = inst.exported_memory("memory").expect("memory is exported");
+                    memory.write_pod(0, 42u32).expect("write is in bounds");
+                    assert_eq!(inst.heap_u32()[0], 42);
+                    match inst.exported_memory("table") {
+                        Err(Error::SymbolNotFound(_)) => (),
+                        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+                    }
+                }
+
+                #[test]
+                fn custom_sections_are_preserved() {
+                    let module = MockModuleBuilder::new()
+                        .with_custom_section("abi_version", b"2")
+                        .with_custom_section("producers", b"lucet")
+                        .with_custom_section("abi_version", b"3")
+                        .build();
+                    let names = module
+                        .custom_sections()
+                        .iter()
+                        .map(|section| section.name)
+                        .collect::<Vec<_>>();
+                    assert_eq!(names, ["abi_version", "producers", "abi_version"]);
+                    assert_eq!(module.custom_section("abi_version"), Some(&b"2"[..]));
+                    assert_eq!(module.custom_section("missing"), None);
+                }
+
+                #[test]
+                fn global_by_name_errors() {
+                    let module = mock_exported_globals_module();
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    match inst.get_global("nonexistent") {
+                        Err(Error::SymbolNotFound(_)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    match inst.get_global_as::<f64>("counter") {
+                        Err(Error::InvalidArgument(_)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    match inst.set_global("counter", 1i32) {
+                        Err(Error::InvalidArgument(_)) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
             }
         )*
     };
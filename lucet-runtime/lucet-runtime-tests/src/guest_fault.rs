@@ -210,8 +210,8 @@ macro_rules! guest_fault_tests {
                 use lucet_runtime::vmctx::{lucet_vmctx, Vmctx};
                 use lucet_runtime::{
                     lucet_hostcall, lucet_hostcall_terminate, lucet_internal_ensure_linked, DlModule,
-                    Error, FaultDetails, Instance, Limits, Region, RegionCreate, SignalBehavior, TerminationDetails,
-                    TrapCode,
+                    Error, FaultAddrLocation, FaultDetails, Instance, Limits, Region, RegionCreate, SignalBehavior,
+                    TerminationDetails, TrapCode,
                 };
                 use nix::sys::mman::{mmap, MapFlags, ProtFlags};
                 use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
@@ -443,6 +443,14 @@ macro_rules! guest_fault_tests {
                         match inst.run("oob", &[]) {
                             Err(Error::RuntimeFault(details)) => {
                                 assert_eq!(details.trapcode, Some(TrapCode::HeapOutOfBounds));
+                                let location = details.trap_location.expect("fault is in module code");
+                                assert_eq!(location.function_index.as_u32(), 2);
+                                assert_eq!(location.function_name.as_deref(), Some("oob"));
+                                assert_eq!(location.code_offset, 29);
+                                assert_eq!(
+                                    details.fault_addr_location,
+                                    Some(FaultAddrLocation::HeapGuard)
+                                );
                             }
                             res => panic!("unexpected result: {:?}", res),
                         }
@@ -6,7 +6,7 @@ macro_rules! host_tests {
         use lucet_runtime::vmctx::{lucet_vmctx, Vmctx};
         use lucet_runtime::{
             lucet_hostcall, lucet_hostcall_terminate, DlModule, Error, Limits, Region,
-            TerminationDetails, TrapCode,
+            TerminationDetails, TrapCode, WasmPtr, WasmSlice,
         };
         use std::cell::RefCell;
         use std::ops::Deref;
@@ -48,6 +48,28 @@ macro_rules! host_tests {
             }
         }
 
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_test_func_typed_ptrs(
+            vmctx: &Vmctx,
+            counter: WasmPtr<u64>,
+            msg: WasmSlice<u8>,
+        ) {
+            let mut memory = vmctx.memory();
+            let count = counter
+                .read(&memory)
+                .unwrap_or_else(|e| lucet_hostcall_terminate!(e.to_string()));
+            counter
+                .write(&mut memory, count + 1)
+                .unwrap_or_else(|e| lucet_hostcall_terminate!(e.to_string()));
+            let msg = msg
+                .as_str(&memory)
+                .unwrap_or_else(|e| lucet_hostcall_terminate!(e.to_string()))
+                .to_string();
+            drop(memory);
+            *vmctx.get_embed_ctx_mut::<String>() = msg;
+        }
+
         #[lucet_hostcall]
         #[allow(unreachable_code)]
         #[no_mangle]
@@ -66,6 +88,18 @@ macro_rules! host_tests {
             heap[0] == other_heap[0]
         }
 
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_keyed_embed_ctx(vmctx: &Vmctx) {
+            *vmctx.get_keyed_embed_ctx_mut::<u32>("requests") += 1;
+            *vmctx.get_keyed_embed_ctx_mut::<u32>("bytes") += 100;
+            // the unkeyed value of the same type is separate from the keyed ones
+            *vmctx.get_embed_ctx_mut::<u32>() += 10;
+            if let Err(e) = vmctx.try_get_keyed_embed_ctx::<u32>("missing") {
+                *vmctx.get_embed_ctx_mut::<String>() = e.to_string();
+            }
+        }
+
         #[lucet_hostcall]
         #[no_mangle]
         pub fn hostcall_missing_embed_ctx(vmctx: &Vmctx) -> bool {
@@ -102,6 +136,51 @@ macro_rules! host_tests {
             vmctx.yield_expecting_val()
         }
 
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_spin_50ms(_vmctx: &Vmctx) {
+            let start = std::time::Instant::now();
+            while start.elapsed() < std::time::Duration::from_millis(50) {}
+        }
+
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_exit(vmctx: &Vmctx, status: i32) {
+            vmctx.exit(status);
+        }
+
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_panic(_vmctx: &Vmctx, code: u32) {
+            panic!("hostcall_panic: {}", code);
+        }
+
+        static HOSTCALL_COUNTER: std::sync::atomic::AtomicU64 =
+            std::sync::atomic::AtomicU64::new(1);
+
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_write_counter(vmctx: &Vmctx, offset: u32) -> u64 {
+            let count = HOSTCALL_COUNTER.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            vmctx.heap_mut()[offset as usize] = count as u8;
+            count
+        }
+
+        #[lucet_hostcall]
+        #[no_mangle]
+        pub fn hostcall_nondeterministic(_vmctx: &Vmctx) -> u64 {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos() as u64
+        }
+
+        #[lucet_hostcall(deterministic)]
+        #[no_mangle]
+        pub fn hostcall_deterministic(_vmctx: &Vmctx, x: u64) -> u64 {
+            x * 2
+        }
+
         #[lucet_hostcall]
         #[no_mangle]
         pub fn hostcall_yields_5(vmctx: &Vmctx) {
@@ -190,9 +269,10 @@ macro_rules! host_tests {
                 use lazy_static::lazy_static;
                 use libc::c_void;
                 use lucet_runtime::vmctx::{lucet_vmctx, Vmctx};
+                use lucet_runtime::replay::HostcallLog;
                 use lucet_runtime::{
-                    lucet_hostcall, lucet_hostcall_terminate, DlModule, Error, Limits, Region,
-                    RegionCreate, TerminationDetails, TrapCode,
+                    lucet_hostcall, lucet_hostcall_terminate, DlModule, Error, HostPanicPolicy, Limits,
+                    ModuleRegistry, Region, RegionCreate, TerminationDetails, TrapCode,
                 };
                 use std::sync::{Arc, Mutex};
                 use $crate::build::test_module_c;
@@ -251,6 +331,29 @@ macro_rules! host_tests {
                     assert!(*inst.get_embed_ctx::<bool>().unwrap().unwrap());
                 }
 
+                #[test]
+                fn run_typed_ptrs() {
+                    let module = test_module_c("host", "typed_ptrs.c").expect("build and load module");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_embed_ctx(String::new())
+                        .build()
+                        .expect("instance can be created");
+
+                    let retval = inst
+                        .run("main", &[0u32.into(), 0i32.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+
+                    assert_eq!(u32::from(retval), 42);
+                    assert_eq!(
+                        inst.get_embed_ctx::<String>().unwrap().unwrap().as_str(),
+                        "typed pointers"
+                    );
+                }
+
                 #[test]
                 fn run_hostcall_error() {
                     let module = test_module_c("host", "hostcall_error.c").expect("build and load module");
@@ -385,6 +488,157 @@ macro_rules! host_tests {
                     }
                 }
 
+                #[test]
+                fn run_hostcall_keyed_embed_ctx() {
+                    extern "C" {
+                        fn hostcall_keyed_embed_ctx(vmctx: *const lucet_vmctx);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_keyed_embed_ctx(vmctx);
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_embed_ctx(0u32)
+                        .with_embed_ctx(String::new())
+                        .with_keyed_embed_ctx("requests", 0u32)
+                        .with_keyed_embed_ctx("bytes", 0u32)
+                        .build()
+                        .expect("instance can be created");
+
+                    inst.run("f", &[]).expect("instance runs");
+                    inst.run("f", &[]).expect("instance runs");
+
+                    assert_eq!(*inst.get_keyed_embed_ctx::<u32>("requests").unwrap(), 2);
+                    assert_eq!(*inst.get_keyed_embed_ctx::<u32>("bytes").unwrap(), 200);
+                    assert_eq!(*inst.get_embed_ctx::<u32>().unwrap().unwrap(), 20);
+                    assert_eq!(
+                        inst.get_embed_ctx::<String>().unwrap().unwrap().as_str(),
+                        "Embedder context not found: `u32` with key \"missing\""
+                    );
+
+                    assert_eq!(inst.remove_keyed_embed_ctx::<u32>("requests"), Some(2));
+                    assert!(!inst.contains_keyed_embed_ctx::<u32>("requests"));
+                    assert!(inst.contains_keyed_embed_ctx::<u32>("bytes"));
+                    assert!(inst.contains_embed_ctx::<u32>());
+
+                    match inst.run("f", &[]) {
+                        Err(Error::RuntimeTerminated(details)) => {
+                            assert_eq!(details, TerminationDetails::CtxNotFound);
+                        }
+                        res => {
+                            panic!("unexpected result: {:?}", res);
+                        }
+                    }
+                }
+
+                #[test]
+                fn try_get_embed_ctx_errors() {
+                    let module = MockModuleBuilder::new().build();
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let inst = region
+                        .new_instance_builder(module)
+                        .with_embed_ctx(5u64)
+                        .build()
+                        .expect("instance can be created");
+
+                    match inst.try_get_embed_ctx::<bool>() {
+                        Err(Error::CtxNotFound(name)) => assert_eq!(name, "`bool`"),
+                        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+                    }
+
+                    let _borrow = inst.try_get_embed_ctx_mut::<u64>().expect("ctx exists");
+                    match inst.try_get_embed_ctx::<u64>() {
+                        Err(Error::CtxBorrowed(name)) => assert_eq!(name, "`u64`"),
+                        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+                    }
+                }
+
+                #[test]
+                fn reset_designated_embed_ctx() {
+                    let module = MockModuleBuilder::new().build();
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_embed_ctx(5u64)
+                        .with_embed_ctx(true)
+                        .with_keyed_embed_ctx("a", 1u64)
+                        .with_embed_ctx_reset(|x: &mut u64| *x = 0)
+                        .build()
+                        .expect("instance can be created");
+
+                    inst.reset().expect("instance resets");
+                    assert_eq!(*inst.get_embed_ctx::<u64>().unwrap().unwrap(), 0);
+                    assert_eq!(*inst.get_keyed_embed_ctx::<u64>("a").unwrap(), 0);
+                    // types without a reset function are left alone
+                    assert!(*inst.get_embed_ctx::<bool>().unwrap().unwrap());
+
+                    *inst.get_embed_ctx_mut::<bool>().unwrap().unwrap() = false;
+                    assert!(!inst.set_embed_ctx_reset(|x: &mut bool| *x = true));
+                    assert!(inst.remove_embed_ctx_reset::<u64>());
+                    *inst.get_embed_ctx_mut::<u64>().unwrap().unwrap() = 7;
+
+                    inst.reset().expect("instance resets");
+                    assert!(*inst.get_embed_ctx::<bool>().unwrap().unwrap());
+                    assert_eq!(*inst.get_embed_ctx::<u64>().unwrap().unwrap(), 7);
+                }
+
+                #[test]
+                fn registry_replaces_module_for_new_instances() {
+                    extern "C" fn version_1(_vmctx: *const lucet_vmctx) -> u64 {
+                        1
+                    }
+                    extern "C" fn version_2(_vmctx: *const lucet_vmctx) -> u64 {
+                        2
+                    }
+                    let module_with = |f: usize| {
+                        MockModuleBuilder::new()
+                            .with_export_func(MockExportBuilder::new(
+                                "version",
+                                FunctionPointer::from_usize(f),
+                            ))
+                            .build()
+                    };
+
+                    let registry = ModuleRegistry::new();
+                    let handle = registry
+                        .register("guest", module_with(version_1 as usize))
+                        .expect("module can be registered");
+                    assert!(registry.register("guest", module_with(version_2 as usize)).is_err());
+
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let mut old_inst = region
+                        .new_instance(handle.current())
+                        .expect("instance can be created");
+
+                    registry
+                        .replace("guest", module_with(version_2 as usize))
+                        .expect("module can be replaced");
+                    match registry.replace("other", module_with(version_2 as usize)) {
+                        Err(Error::ModuleNotFound(name)) => assert_eq!(name, "other"),
+                        res => panic!("unexpected result replacing an unregistered module: {:?}", res.map(|_| ())),
+                    }
+                    let mut new_inst = region
+                        .new_instance(registry.current("guest").unwrap())
+                        .expect("instance can be created");
+
+                    assert!(!handle.is_current(&old_inst));
+                    assert!(handle.is_current(&new_inst));
+                    let old_retval = old_inst.run("version", &[]).unwrap().unwrap_returned();
+                    assert_eq!(u64::from(old_retval), 1u64);
+                    let new_retval = new_inst.run("version", &[]).unwrap().unwrap_returned();
+                    assert_eq!(u64::from(new_retval), 2u64);
+                }
+
                 #[test]
                 fn run_hostcall_multiple_vmctx() {
                     extern "C" {
@@ -414,6 +668,162 @@ macro_rules! host_tests {
                     assert_eq!(bool::from(retval), true);
                 }
 
+                #[test]
+                fn cpu_time_excludes_hostcalls() {
+                    extern "C" {
+                        fn hostcall_spin_50ms(vmctx: *const lucet_vmctx);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_spin_50ms(vmctx);
+                        let start = std::time::Instant::now();
+                        while start.elapsed() < std::time::Duration::from_millis(10) {}
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module.clone())
+                        .with_cpu_time_accounting(true)
+                        .build()
+                        .expect("instance can be created");
+                    assert_eq!(inst.cpu_time(), std::time::Duration::default());
+
+                    inst.run("f", &[]).expect("instance runs");
+                    let cpu_time = inst.cpu_time();
+                    assert!(cpu_time > std::time::Duration::default());
+                    // the guest spins for 10ms of wall-clock time, which bounds its CPU time
+                    assert!(cpu_time < std::time::Duration::from_millis(50));
+
+                    inst.reset().expect("instance resets");
+                    assert_eq!(inst.cpu_time(), std::time::Duration::default());
+
+                    // without accounting, the clock is never read
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+                    inst.run("f", &[]).expect("instance runs");
+                    assert_eq!(inst.cpu_time(), std::time::Duration::default());
+                }
+
+                #[test]
+                fn deterministic_mode_rejects_nondeterministic_hostcalls() {
+                    extern "C" {
+                        fn hostcall_nondeterministic(vmctx: *const lucet_vmctx) -> u64;
+                        fn hostcall_deterministic(vmctx: *const lucet_vmctx, x: u64) -> u64;
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) -> u64 {
+                        hostcall_nondeterministic(vmctx)
+                    }
+
+                    unsafe extern "C" fn g(vmctx: *const lucet_vmctx) -> u64 {
+                        hostcall_deterministic(vmctx, 21)
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .with_export_func(MockExportBuilder::new(
+                            "g",
+                            FunctionPointer::from_usize(g as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module.clone())
+                        .expect("instance can be created");
+                    assert!(!inst.is_deterministic());
+                    inst.run("f", &[]).expect("instance runs");
+
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_deterministic_execution(true)
+                        .build()
+                        .expect("instance can be created");
+                    assert!(inst.is_deterministic());
+                    // only hostcalls declared deterministic are called
+                    let retval = inst.run("g", &[]).expect("instance runs").unwrap_returned();
+                    assert_eq!(u64::from(retval), 42);
+                    match inst.run("f", &[]) {
+                        Err(Error::RuntimeTerminated(details)) => {
+                            assert_eq!(
+                                details,
+                                TerminationDetails::Nondeterministic("hostcall_nondeterministic")
+                            );
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
+                #[test]
+                fn replay_reproduces_recorded_hostcalls() {
+                    extern "C" {
+                        fn hostcall_write_counter(vmctx: *const lucet_vmctx, offset: u32) -> u64;
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx, offset: u32) -> u64 {
+                        hostcall_write_counter(vmctx, offset) * 1000
+                            + hostcall_write_counter(vmctx, offset + 1)
+                    }
+
+                    const HEAP_SPEC: HeapSpec = HeapSpec {
+                        reserved_size: 4 * 1024 * 1024,
+                        guard_size: 4 * 1024 * 1024,
+                        initial_size: 64 * 1024,
+                        max_size: Some(64 * 1024),
+                    };
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .with_heap_spec(HEAP_SPEC)
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let mut recorded = region
+                        .new_instance(module.clone())
+                        .expect("instance can be created");
+                    recorded.record_hostcalls();
+                    let retval = u64::from(
+                        recorded.run("f", &[8u32.into()]).expect("instance runs").unwrap_returned(),
+                    );
+                    let log = recorded.take_hostcall_log().expect("hostcalls were recorded");
+                    assert_eq!(log.records().len(), 2);
+                    assert_eq!(log.records()[0].name, "hostcall_write_counter");
+                    let log = HostcallLog::from_bytes(&log.to_bytes().expect("log serializes"))
+                        .expect("log deserializes");
+
+                    // the counter has moved on, but the replayed instance sees the recorded values
+                    let mut replayed = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+                    replayed.replay_hostcalls(log.clone());
+                    let replayed_retval = u64::from(
+                        replayed.run("f", &[8u32.into()]).expect("instance runs").unwrap_returned(),
+                    );
+                    assert_eq!(replayed_retval, retval);
+                    assert_eq!(&replayed.heap()[8..10], &recorded.heap()[8..10]);
+                    assert_eq!(replayed.take_hostcall_log(), Some(HostcallLog::new()));
+
+                    replayed.reset().expect("instance resets");
+                    replayed.replay_hostcalls(log);
+                    match replayed.run("f", &[16u32.into()]) {
+                        Err(Error::RuntimeTerminated(TerminationDetails::ReplayDiverged(_))) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
                 #[test]
                 fn run_hostcall_yields_5() {
                     extern "C" {
@@ -447,6 +857,248 @@ macro_rules! host_tests {
                     );
                 }
 
+                #[test]
+                fn run_stats_count_hostcalls_and_yields() {
+                    extern "C" {
+                        fn hostcall_write_counter(vmctx: *const lucet_vmctx, offset: u32) -> u64;
+                        fn hostcall_yields_5(vmctx: *const lucet_vmctx);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_write_counter(vmctx, 0);
+                        hostcall_write_counter(vmctx, 1);
+                        hostcall_yields_5(vmctx);
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let (res, stats) = inst.run_with_stats("f", &[]);
+                    assert!(res.expect("instance runs").is_yielded());
+                    assert_eq!(stats.instruction_count, None);
+                    assert_eq!(stats.hostcall_count, 3);
+                    assert_eq!(stats.yield_count, 1);
+                    assert_eq!(stats.heap_growth, 0);
+
+                    let (res, stats) = inst.with_run_stats(|inst| inst.resume());
+                    assert!(res.expect("instance resumes").is_returned());
+                    assert_eq!(stats.hostcall_count, 0);
+                    assert_eq!(stats.yield_count, 0);
+                }
+
+                #[test]
+                fn run_hostcall_exit() {
+                    extern "C" {
+                        fn hostcall_exit(vmctx: *const lucet_vmctx, status: i32);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_exit(vmctx, 42);
+                        panic!("hostcall_exit returned");
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let res = inst.run("f", &[]).expect("exiting is not an error");
+                    assert_eq!(res.exit_status(), Some(42));
+                    assert!(inst.is_terminated());
+
+                    // like a terminated instance, an exited one must be reset to run again
+                    assert!(inst.run("f", &[]).is_err());
+                    inst.reset().expect("instance resets");
+                    let res = inst.run("f", &[]).expect("exiting is not an error");
+                    assert!(res.is_exited());
+                }
+
+                #[test]
+                fn run_hostcall_panic_terminates() {
+                    extern "C" {
+                        fn hostcall_panic(vmctx: *const lucet_vmctx, code: u32);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_panic(vmctx, 7);
+                        panic!("hostcall_panic returned");
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_host_panic_policy(HostPanicPolicy::TerminateInstance)
+                        .build()
+                        .expect("instance can be created");
+                    assert_eq!(inst.host_panic_policy(), HostPanicPolicy::TerminateInstance);
+
+                    match inst.run("f", &[]) {
+                        Err(Error::RuntimeTerminated(TerminationDetails::HostPanic(msg))) => {
+                            assert_eq!(msg, "hostcall_panic: 7");
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+
+                    // the instance can be reset and run again, which panics in the same way
+                    inst.reset().expect("instance resets");
+                    match inst.run("f", &[]) {
+                        Err(Error::RuntimeTerminated(TerminationDetails::HostPanic(_))) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
+                #[test]
+                fn run_hostcall_panic_resumes_unwind() {
+                    extern "C" {
+                        fn hostcall_panic(vmctx: *const lucet_vmctx, code: u32);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_panic(vmctx, 7);
+                        panic!("hostcall_panic returned");
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+                    assert_eq!(inst.host_panic_policy(), HostPanicPolicy::ResumeUnwind);
+
+                    // the panic reaches the embedder's call to `run()` with its original payload
+                    let payload = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        inst.run("f", &[])
+                    }))
+                    .expect_err("the hostcall panic unwinds out of `run()`");
+                    assert_eq!(
+                        payload.downcast_ref::<String>().map(String::as_str),
+                        Some("hostcall_panic: 7")
+                    );
+                    assert!(inst.is_terminated());
+
+                    // the instance can be reset and run again, which panics in the same way
+                    inst.reset().expect("instance resets");
+                    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                        inst.run("f", &[])
+                    }))
+                    .is_err());
+                }
+
+                #[test]
+                fn hostcall_rate_limit_terminates() {
+                    extern "C" {
+                        fn hostcall_nondeterministic(vmctx: *const lucet_vmctx) -> u64;
+                    }
+
+                    unsafe extern "C" fn three_calls(vmctx: *const lucet_vmctx) {
+                        for _ in 0..3 {
+                            hostcall_nondeterministic(vmctx);
+                        }
+                    }
+
+                    unsafe extern "C" fn one_call(vmctx: *const lucet_vmctx) {
+                        hostcall_nondeterministic(vmctx);
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "three_calls",
+                            FunctionPointer::from_usize(three_calls as usize),
+                        ))
+                        .with_export_func(MockExportBuilder::new(
+                            "one_call",
+                            FunctionPointer::from_usize(one_call as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_hostcall_rate_limit(3)
+                        .build()
+                        .expect("instance can be created");
+                    assert_eq!(inst.hostcall_rate_limit(), Some(3));
+
+                    inst.run("three_calls", &[]).expect("instance runs within the limit");
+                    inst.reset().expect("instance resets");
+                    // the second run is in the same second, where the limit is already used up
+                    match inst.run("one_call", &[]) {
+                        Err(Error::RuntimeTerminated(TerminationDetails::HostcallRateExceeded(name))) => {
+                            assert_eq!(name, "hostcall_nondeterministic");
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
+                #[test]
+                fn run_hostcall_terminate_ignores_panic_policy() {
+                    extern "C" {
+                        fn hostcall_test_func_hostcall_error(vmctx: *const lucet_vmctx);
+                    }
+
+                    unsafe extern "C" fn f(vmctx: *const lucet_vmctx) {
+                        hostcall_test_func_hostcall_error(vmctx);
+                    }
+
+                    let module = MockModuleBuilder::new()
+                        .with_export_func(MockExportBuilder::new(
+                            "f",
+                            FunctionPointer::from_usize(f as usize),
+                        ))
+                        .build();
+
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_host_panic_policy(HostPanicPolicy::Abort)
+                        .build()
+                        .expect("instance can be created");
+
+                    // terminating with `TerminationDetails` is not a panic in the sense of the policy
+                    match inst.run("f", &[]) {
+                        Err(Error::RuntimeTerminated(term)) => {
+                            assert_eq!(
+                                *term
+                                    .provided_details()
+                                    .expect("user provided termination reason")
+                                    .downcast_ref::<&'static str>()
+                                    .expect("error was static str"),
+                                super::ERROR_MESSAGE
+                            );
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
                 #[test]
                 fn run_hostcall_yield_expects_5() {
                     extern "C" {
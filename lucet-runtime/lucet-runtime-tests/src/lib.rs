@@ -4,6 +4,7 @@ pub mod globals;
 pub mod guest_fault;
 pub mod helpers;
 pub mod host;
+pub mod linker;
 pub mod memory;
 pub mod stack;
 pub mod start;
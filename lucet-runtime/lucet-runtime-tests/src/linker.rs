@@ -0,0 +1,390 @@
+use crate::build::{
+    test_module_wasm, test_module_wasm_isolated, test_module_wasm_with_lazy_imports,
+};
+use crate::helpers::{MockExportBuilder, MockModuleBuilder};
+use lucet_module::{lucet_signature, FunctionPointer, Signature};
+use lucet_runtime_internals::module::Module;
+use lucet_runtime_internals::vmctx::{lucet_vmctx, Vmctx};
+use std::sync::Arc;
+
+/// A module exporting `add`, to be linked into other instances as `math`.
+pub fn mock_math_module() -> Arc<dyn Module> {
+    extern "C" fn add_2(_vmctx: *const lucet_vmctx, arg0: u64, arg1: u64) -> u64 {
+        arg0 + arg1
+    }
+
+    MockModuleBuilder::new()
+        .with_export_func(
+            MockExportBuilder::new("add", FunctionPointer::from_usize(add_2 as usize))
+                .with_sig(lucet_signature!((I64, I64) -> I64)),
+        )
+        .build()
+}
+
+/// A module importing `math::add` with the given signature, and exporting `add_via_link`, which
+/// calls it through the linker.
+///
+/// `add_via_link` returns `u64::MAX` if the linked call fails.
+pub fn mock_client_module(import_sig: Signature) -> Arc<dyn Module> {
+    extern "C" fn add_via_link(vmctx: *const lucet_vmctx, arg0: u64, arg1: u64) -> u64 {
+        let vmctx = unsafe { Vmctx::from_raw(vmctx as *mut lucet_vmctx) };
+        match vmctx.call_linked_import("math", "add", &[arg0.into(), arg1.into()]) {
+            Ok(retval) => retval.into(),
+            Err(_) => std::u64::MAX,
+        }
+    }
+
+    MockModuleBuilder::new()
+        .with_import_func("math", "add", import_sig)
+        .with_export_func(
+            MockExportBuilder::new(
+                "add_via_link",
+                FunctionPointer::from_usize(add_via_link as usize),
+            )
+            .with_sig(lucet_signature!((I64, I64) -> I64)),
+        )
+        .build()
+}
+
+/// A module importing the global `env::counter`, which a linker cannot provide.
+pub fn mock_global_import_module() -> Arc<dyn Module> {
+    MockModuleBuilder::new()
+        .with_import(0, "env", "counter")
+        .build()
+}
+
+/// A module whose `env::add` and `env::scale` imports have no symbols, for use with host functions.
+pub fn host_func_module() -> Arc<dyn Module> {
+    test_module_wasm_with_lazy_imports("linker", "host_func.wat").expect("build and load module")
+}
+
+/// The error from loading a module whose only import, `env::add`, has no symbol, with its imports
+/// resolved when it is loaded.
+pub fn one_import_module_load_error() -> String {
+    match test_module_wasm("linker", "one_import.wat") {
+        Ok(_) => panic!("module with an undefined import loads"),
+        Err(e) => e.to_string(),
+    }
+}
+
+/// Like `one_import_module_load_error`, but the module is loaded with its imports resolved lazily.
+pub fn one_import_module() -> Arc<dyn Module> {
+    test_module_wasm_with_lazy_imports("linker", "one_import.wat").expect("build and load module")
+}
+
+/// Like `host_func_module`, but loaded into its own link-map namespace.
+pub fn isolated_host_func_module() -> Arc<dyn Module> {
+    test_module_wasm_isolated("linker", "host_func.wat").expect("build and load module")
+}
+
+#[macro_export]
+macro_rules! linker_tests {
+    ( $( $region_id:ident => $TestRegion:path ),* ) => {
+        /// A native hostcall to declare to linkers; it is never called.
+        #[no_mangle]
+        pub extern "C" fn linker_tests_math_add(
+            _vmctx: *const lucet_runtime::vmctx::lucet_vmctx,
+            x: u64,
+            y: u64,
+        ) -> u64 {
+            x + y
+        }
+
+        $(
+            mod $region_id {
+                use lucet_module::lucet_signature;
+                use lucet_runtime::vmctx::Vmctx;
+                use lucet_runtime::{
+                    lucet_hostcall_terminate, Error, ImportKind, Limits, Linker, Region, RegionCreate,
+                    TerminationDetails,
+                };
+                use std::sync::{Arc, Mutex};
+                use $TestRegion as TestRegion;
+                use $crate::linker::{
+                    host_func_module, isolated_host_func_module, mock_client_module,
+                    mock_global_import_module, mock_math_module, one_import_module,
+                    one_import_module_load_error,
+                };
+
+                #[test]
+                fn call_linked_import() {
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let math = region
+                        .new_instance(mock_math_module())
+                        .expect("math instance can be created");
+                    let mut linker = Linker::new();
+                    linker.instance("math", Arc::new(Mutex::new(math)));
+
+                    let mut inst = region
+                        .new_instance_builder(mock_client_module(lucet_signature!((I64, I64) -> I64)))
+                        .with_linker(&linker)
+                        .build()
+                        .expect("client instance can be linked");
+
+                    let retval = inst
+                        .run("add_via_link", &[123u64.into(), 456u64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(u64::from(retval), 123u64 + 456);
+                }
+
+                #[test]
+                fn link_signature_mismatch() {
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let math = region
+                        .new_instance(mock_math_module())
+                        .expect("math instance can be created");
+                    let mut linker = Linker::new();
+                    linker.instance("math", Arc::new(Mutex::new(math)));
+
+                    match region
+                        .new_instance_builder(mock_client_module(lucet_signature!((I32, I32) -> I32)))
+                        .with_linker(&linker)
+                        .build()
+                    {
+                        Err(Error::LinkError(_)) => (),
+                        Err(e) => panic!("unexpected error: {}", e),
+                        Ok(_) => panic!("instance with mismatched import should not link"),
+                    }
+                }
+
+                #[test]
+                fn unresolved_imports_are_reported() {
+                    let client = mock_client_module(lucet_signature!((I64, I64) -> I64));
+                    let mut linker = Linker::new();
+
+                    let unresolved = linker.unresolved_imports(client.as_ref());
+                    assert_eq!(unresolved.len(), 1);
+                    assert_eq!(unresolved[0].module, "math");
+                    assert_eq!(unresolved[0].field, "add");
+                    assert_eq!(unresolved[0].kind, ImportKind::Function);
+                    match linker.check(client.as_ref()) {
+                        Err(Error::LinkError(msg)) => assert!(msg.contains("math::add")),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+
+                    match linker.hostcall("math", "add", "linker_tests_no_such_hostcall") {
+                        Err(Error::SymbolNotFound(sym)) => assert_eq!(sym, "linker_tests_no_such_hostcall"),
+                        res => panic!("unexpected result: {:?}", res.map(|_| ())),
+                    }
+                    assert_eq!(linker.unresolved_imports(client.as_ref()).len(), 1);
+                    linker
+                        .hostcall("math", "add", "linker_tests_math_add")
+                        .expect("hostcall symbol is defined");
+                    assert!(linker.unresolved_imports(client.as_ref()).is_empty());
+                    linker.check(client.as_ref()).expect("all imports are provided");
+
+                    let unresolved = linker.unresolved_imports(mock_global_import_module().as_ref());
+                    assert_eq!(unresolved.len(), 1);
+                    assert_eq!(unresolved[0].kind, ImportKind::Global);
+                }
+
+                #[test]
+                fn unresolved_imports_check_signatures() {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let math = region
+                        .new_instance(mock_math_module())
+                        .expect("math instance can be created");
+                    let mut linker = Linker::new();
+                    linker.instance("math", Arc::new(Mutex::new(math)));
+
+                    let client = mock_client_module(lucet_signature!((I64, I64) -> I64));
+                    assert!(linker.unresolved_imports(client.as_ref()).is_empty());
+
+                    let mismatched = mock_client_module(lucet_signature!((I32, I32) -> I32));
+                    let unresolved = linker.unresolved_imports(mismatched.as_ref());
+                    assert_eq!(unresolved.len(), 1);
+                    assert!(unresolved[0].reason.contains("exported as"));
+                }
+
+                #[test]
+                fn undefined_imports_are_named() {
+                    let message = one_import_module_load_error();
+                    assert!(
+                        message.ends_with("unsatisfied import `env::add` (undefined symbol `linker_test_add`)"),
+                        "unexpected error: {}",
+                        message
+                    );
+                }
+
+                #[test]
+                fn undefined_lazy_imports_are_named() {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let module = one_import_module();
+                    match region.new_instance(module.clone()) {
+                        Err(e) => assert!(
+                            e.to_string().ends_with("unsatisfied import `env::add` (undefined symbol `linker_test_add`)"),
+                            "unexpected error: {}",
+                            e
+                        ),
+                        Ok(_) => panic!("instance with an undefined import should not be created"),
+                    }
+
+                    let mut linker = Linker::new();
+                    linker.func("env", "add", |_vmctx: &Vmctx, x: i64, y: i64| x + y);
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_linker(&linker)
+                        .build()
+                        .expect("instance can be linked");
+                    let retval = inst
+                        .run("add_then_double", &[3i64.into(), 4i64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(i64::from(retval), 14);
+                }
+
+                #[test]
+                fn call_import_without_linker() {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(mock_client_module(lucet_signature!((I64, I64) -> I64)))
+                        .expect("instance can be created");
+
+                    let retval = inst
+                        .run("add_via_link", &[1u64.into(), 2u64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(u64::from(retval), std::u64::MAX);
+                }
+
+                #[test]
+                fn call_host_func() {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut linker = Linker::new();
+                    linker
+                        .func("env", "add", |_vmctx: &Vmctx, x: i64, y: i64| x + y)
+                        .func("env", "scale", |_vmctx: &Vmctx, x: f64| x * 2.5);
+
+                    let mut inst = region
+                        .new_instance_builder(host_func_module())
+                        .with_linker(&linker)
+                        .build()
+                        .expect("instance can be linked");
+
+                    let retval = inst
+                        .run("add_then_double", &[3i64.into(), 4i64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(i64::from(retval), 14);
+
+                    let retval = inst
+                        .run("scale", &[2.0f64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(f64::from(retval), 5.0);
+                }
+
+                #[cfg(all(target_os = "linux", target_env = "gnu"))]
+                #[test]
+                fn call_host_func_isolated() {
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let mut add_linker = Linker::new();
+                    add_linker.func("env", "add", |_vmctx: &Vmctx, x: i64, y: i64| x + y);
+                    let mut mul_linker = Linker::new();
+                    mul_linker.func("env", "add", |_vmctx: &Vmctx, x: i64, y: i64| x * y);
+
+                    // two copies of the same module, each bound to different host functions
+                    let mut add_inst = region
+                        .new_instance_builder(isolated_host_func_module())
+                        .with_linker(&add_linker)
+                        .build()
+                        .expect("instance can be linked");
+                    let mut mul_inst = region
+                        .new_instance_builder(isolated_host_func_module())
+                        .with_linker(&mul_linker)
+                        .build()
+                        .expect("instance can be linked");
+
+                    let retval = add_inst
+                        .run("add_then_double", &[3i64.into(), 4i64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(i64::from(retval), 14);
+
+                    let retval = mul_inst
+                        .run("add_then_double", &[3i64.into(), 4i64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(i64::from(retval), 24);
+                }
+
+                #[test]
+                fn deterministic_mode_calls_deterministic_host_funcs() {
+                    let region = <TestRegion as RegionCreate>::create(2, &Limits::default()).expect("region can be created");
+                    let mut linker = Linker::new();
+                    linker.func("env", "add", |_vmctx: &Vmctx, x: i64, y: i64| x + y);
+                    let mut inst = region
+                        .new_instance_builder(host_func_module())
+                        .with_linker(&linker)
+                        .with_deterministic_execution(true)
+                        .build()
+                        .expect("instance can be linked");
+                    match inst.run("add_then_double", &[3i64.into(), 4i64.into()]) {
+                        Err(Error::RuntimeTerminated(details)) => {
+                            assert_eq!(details, TerminationDetails::Nondeterministic("host function"));
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+
+                    let mut linker = Linker::new();
+                    linker.deterministic_func("env", "add", |_vmctx: &Vmctx, x: i64, y: i64| x + y);
+                    let mut inst = region
+                        .new_instance_builder(host_func_module())
+                        .with_linker(&linker)
+                        .with_deterministic_execution(true)
+                        .build()
+                        .expect("instance can be linked");
+                    let retval = inst
+                        .run("add_then_double", &[3i64.into(), 4i64.into()])
+                        .expect("instance runs")
+                        .unwrap_returned();
+                    assert_eq!(i64::from(retval), 14);
+                }
+
+                #[test]
+                fn host_func_signature_mismatch() {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut linker = Linker::new();
+                    linker.func("env", "add", |_vmctx: &Vmctx, x: i32, y: i32| x + y);
+
+                    match region
+                        .new_instance_builder(host_func_module())
+                        .with_linker(&linker)
+                        .build()
+                    {
+                        Err(Error::LinkError(_)) => (),
+                        Err(e) => panic!("unexpected error: {}", e),
+                        Ok(_) => panic!("instance with mismatched import should not link"),
+                    }
+                }
+
+                #[test]
+                fn host_func_terminate() {
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut linker = Linker::new();
+                    linker.func("env", "add", |_vmctx: &Vmctx, _x: i64, _y: i64| -> i64 {
+                        lucet_hostcall_terminate!("add is not allowed");
+                    });
+
+                    let mut inst = region
+                        .new_instance_builder(one_import_module())
+                        .with_linker(&linker)
+                        .build()
+                        .expect("instance can be linked");
+
+                    match inst.run("add_then_double", &[3i64.into(), 4i64.into()]) {
+                        Err(Error::RuntimeTerminated(TerminationDetails::Provided(details))) => {
+                            assert_eq!(
+                                *details.downcast_ref::<&'static str>().unwrap(),
+                                "add is not allowed"
+                            );
+                        }
+                        Err(e) => panic!("unexpected error: {}", e),
+                        Ok(_) => panic!("instance should have terminated"),
+                    }
+                }
+            }
+        )*
+    };
+}
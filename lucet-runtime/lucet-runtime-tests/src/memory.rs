@@ -4,7 +4,10 @@ macro_rules! memory_tests {
         $(
             mod $region_id {
                 use lazy_static::lazy_static;
-                use lucet_runtime::{DlModule, Limits, Region, RegionCreate};
+                use lucet_runtime::{
+                    DlModule, Error, GuestMemoryError, Limits, Region, RegionCreate, WasmPtr,
+                    WasmSlice, WASM_PAGE_SIZE,
+                };
                 use std::sync::Mutex;
                 use $TestRegion as TestRegion;
                 use $crate::build::test_module_wasm;
@@ -49,6 +52,171 @@ macro_rules! memory_tests {
                     // guest then puts the result of the current memory call in heap[4] (indexed by bytes)
                     assert_eq!(heap[1], 5);
                 }
+
+                #[cfg(all(target_os = "linux", target_env = "gnu"))]
+                #[test]
+                fn grow_memory_hostcall_isolated() {
+                    // the runtime functions for memory growth are not visible from the module's
+                    // namespace, so loading must resolve them
+                    let module = $crate::build::test_module_wasm_isolated("memory", "grow_memory.wat")
+                        .expect("compile and load grow_memory.wasm");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    inst.run("main", &[]).expect("instance runs");
+
+                    let heap = inst.heap_u32();
+                    assert_eq!(heap[0], 4);
+                    assert_eq!(heap[1], 5);
+                }
+
+                #[test]
+                fn memory_stats() {
+                    let module = test_module_wasm("memory", "grow_memory.wat")
+                        .expect("compile and load grow_memory.wasm");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let page = WASM_PAGE_SIZE as usize;
+                    let stats = inst.memory_stats();
+                    assert_eq!(stats.heap_size, 4 * page);
+                    assert_eq!(stats.peak_heap_size, 4 * page);
+                    assert_eq!(stats.grow_count, 0);
+                    assert_eq!(stats.globals_count, 0);
+
+                    inst.run("main", &[]).expect("instance runs");
+                    inst.grow_memory(2).expect("memory can grow");
+
+                    let stats = inst.memory_stats();
+                    assert_eq!(stats.heap_size, 7 * page);
+                    assert_eq!(stats.peak_heap_size, 7 * page);
+                    assert_eq!(stats.grow_count, 2);
+
+                    inst.reset().expect("instance resets");
+
+                    let stats = inst.memory_stats();
+                    assert_eq!(stats.heap_size, 4 * page);
+                    assert_eq!(stats.peak_heap_size, 4 * page);
+                    assert_eq!(stats.grow_count, 0);
+                }
+
+                #[test]
+                fn guest_memory_accessors() {
+                    let module = test_module_wasm("memory", "current_memory.wat")
+                        .expect("compile and load current_memory.wasm");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let mut memory = inst.memory();
+                    assert_eq!(memory.len(), 4 * WASM_PAGE_SIZE as usize);
+
+                    memory.write_bytes(16, b"hello\0").expect("write is in bounds");
+                    assert_eq!(memory.read_bytes(16, 5).expect("read is in bounds"), b"hello");
+                    assert_eq!(memory.read_str(16, 5).expect("string is valid"), "hello");
+                    assert_eq!(
+                        memory.read_cstr(16).expect("string is terminated").to_bytes(),
+                        b"hello"
+                    );
+
+                    memory.write_pod(33, 0xdead_beef_u32).expect("write is in bounds");
+                    assert_eq!(memory.read_pod::<u32>(33).expect("read is in bounds"), 0xdead_beef);
+
+                    drop(memory);
+                    assert_eq!(&inst.heap()[16..21], b"hello");
+                }
+
+                #[test]
+                fn guest_memory_errors() {
+                    let module = test_module_wasm("memory", "current_memory.wat")
+                        .expect("compile and load current_memory.wasm");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let mut memory = inst.memory();
+                    let end = memory.len() as u32;
+
+                    match memory.read_bytes(end - 4, 8) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::OutOfBounds { ptr, len })) => {
+                            assert_eq!((ptr, len), (end - 4, 8));
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    match memory.read_pod::<u64>(std::u32::MAX) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::OutOfBounds { .. })) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    match memory.write_bytes(end - 1, b"ab") {
+                        Err(Error::GuestMemoryError(GuestMemoryError::OutOfBounds { .. })) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    assert_eq!(memory.read_bytes(end - 1, 1).expect("read is in bounds"), &[0]);
+
+                    memory.write_bytes(end - 3, b"abc").expect("write is in bounds");
+                    match memory.read_cstr(end - 3) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::UnterminatedCStr { .. })) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+
+                    memory.write_bytes(0, &[0xff, 0xfe]).expect("write is in bounds");
+                    match memory.read_str(0, 2) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::InvalidUtf8(_))) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
+
+                #[test]
+                fn typed_guest_pointers() {
+                    let module = test_module_wasm("memory", "current_memory.wat")
+                        .expect("compile and load current_memory.wasm");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+
+                    let mut memory = inst.memory();
+                    let end = memory.len() as u32;
+
+                    let ptr = WasmPtr::<u32>::new(64);
+                    ptr.write(&mut memory, 7).expect("pointer is valid");
+                    assert_eq!(ptr.read(&memory).expect("pointer is valid"), 7);
+                    *ptr.deref_mut(&mut memory).expect("pointer is valid") += 1;
+                    assert_eq!(memory.read_pod::<u32>(64).expect("read is in bounds"), 8);
+
+                    match WasmPtr::<u32>::new(66).read(&memory) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::Misaligned { ptr, align })) => {
+                            assert_eq!((ptr, align), (66, 4));
+                        }
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    match WasmPtr::<u64>::new(end - 4).read(&memory) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::OutOfBounds { .. })) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+
+                    let slice = WasmSlice::<u32>::new(ptr, 3);
+                    slice
+                        .as_slice_mut(&mut memory)
+                        .expect("slice is valid")
+                        .copy_from_slice(&[1, 2, 3]);
+                    assert_eq!(slice.to_vec(&memory).expect("slice is valid"), vec![1, 2, 3]);
+
+                    match WasmSlice::<u32>::from_raw(end - 8, 3).as_slice(&memory) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::OutOfBounds { .. })) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                    match WasmSlice::<u64>::from_raw(0, std::u32::MAX).as_slice(&memory) {
+                        Err(Error::GuestMemoryError(GuestMemoryError::OutOfBounds { .. })) => (),
+                        res => panic!("unexpected result: {:?}", res),
+                    }
+                }
             }
         )*
     };
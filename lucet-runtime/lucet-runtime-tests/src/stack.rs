@@ -240,6 +240,42 @@ macro_rules! stack_tests {
                         true,
                     );
                 }
+
+                #[test]
+                fn stack_high_water_mark_grows_with_recursion() {
+                    let module = stack_testcase(64).expect("generate stack_testcase 64");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance_builder(module)
+                        .with_stack_measurement(true)
+                        .build()
+                        .expect("instance can be created");
+
+                    assert_eq!(inst.memory_stats().stack_high_water_mark, Some(0));
+
+                    inst.run("localpalooza", &[10i32.into()]).expect("instance runs");
+                    let shallow = inst.memory_stats().stack_high_water_mark.expect("stack is measured");
+                    assert!(shallow > 0);
+
+                    inst.run("localpalooza", &[100i32.into()]).expect("instance runs");
+                    let deep = inst.memory_stats().stack_high_water_mark.expect("stack is measured");
+                    assert!(deep > shallow);
+                    assert!(deep <= Limits::default().stack_size);
+
+                    inst.reset().expect("instance resets");
+                    assert_eq!(inst.memory_stats().stack_high_water_mark, Some(0));
+                }
+
+                #[test]
+                fn stack_high_water_mark_not_measured_by_default() {
+                    let module = stack_testcase(3).expect("generate stack_testcase 3");
+                    let region = <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                    let mut inst = region
+                        .new_instance(module)
+                        .expect("instance can be created");
+                    inst.run("localpalooza", &[1i32.into()]).expect("instance runs");
+                    assert_eq!(inst.memory_stats().stack_high_water_mark, None);
+                }
             }
         )*
     };
@@ -3,7 +3,7 @@ macro_rules! start_tests {
     ( $( $region_id:ident => $TestRegion:path ),* ) => {
         $(
             mod $region_id {
-                use lucet_runtime::{DlModule, Error, Limits, Region, RegionCreate};
+                use lucet_runtime::{DlModule, Error, Limits, Region, RegionCreate, StartPolicy};
                 use std::sync::Arc;
                 use $TestRegion as TestRegion;
                 use $crate::build::test_module_wasm;
@@ -80,6 +80,57 @@ macro_rules! start_tests {
                     });
                 }
 
+                #[test]
+                fn start_on_instantiation() {
+                    test_nonex(|| {
+                        let module = test_module_wasm("start", "start_and_call.wat")
+                            .expect("module compiled and loaded");
+                        let region =
+                            <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                        let mut inst = region
+                            .new_instance_builder(module)
+                            .with_start_policy(StartPolicy::OnInstantiation)
+                            .build()
+                            .expect("instance can be created");
+
+                        inst.run("main", &[]).expect("instance runs");
+                        assert_eq!(inst.heap_u32()[0], 17);
+
+                        // the start function runs again as part of the reset
+                        inst.reset().expect("instance resets");
+                        match inst.run_start().unwrap_err() {
+                            Error::StartAlreadyRun => (),
+                            e => panic!("unexpected error: {}", e),
+                        }
+                        inst.run("main", &[]).expect("instance runs again");
+                        assert_eq!(inst.heap_u32()[0], 17);
+                    });
+                }
+
+                #[test]
+                fn start_on_first_run() {
+                    test_nonex(|| {
+                        let module = test_module_wasm("start", "start_and_call.wat")
+                            .expect("module compiled and loaded");
+                        let region =
+                            <TestRegion as RegionCreate>::create(1, &Limits::default()).expect("region can be created");
+                        let mut inst = region
+                            .new_instance_builder(module)
+                            .with_start_policy(StartPolicy::OnFirstRun)
+                            .build()
+                            .expect("instance can be created");
+
+                        assert!(inst.is_not_started());
+                        inst.run("main", &[]).expect("instance runs");
+                        assert_eq!(inst.heap_u32()[0], 17);
+
+                        inst.reset().expect("instance resets");
+                        assert!(inst.is_not_started());
+                        inst.run("main", &[]).expect("instance runs again");
+                        assert_eq!(inst.heap_u32()[0], 17);
+                    });
+                }
+
                 #[test]
                 fn no_start_without_reset() {
                     test_nonex(|| {
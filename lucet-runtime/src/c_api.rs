@@ -419,26 +419,26 @@ pub fn ensure_linked() {
     });
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 pub unsafe extern "C" fn lucet_vmctx_get_heap(vmctx: &Vmctx) -> *mut u8 {
     vmctx.instance().alloc().slot().heap as *mut u8
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 pub unsafe extern "C" fn lucet_vmctx_get_globals(vmctx: &Vmctx) -> *mut i64 {
     vmctx.instance().alloc().slot().globals as *mut i64
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 /// Get the number of WebAssembly pages currently in the heap.
 pub unsafe extern "C" fn lucet_vmctx_current_memory(vmctx: &Vmctx) -> u32 {
     vmctx.instance().alloc().heap_len() as u32 / WASM_PAGE_SIZE
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 /// Grows the guest heap by the given number of WebAssembly pages.
 ///
@@ -451,7 +451,7 @@ pub unsafe extern "C" fn lucet_vmctx_grow_memory(vmctx: &Vmctx, additional_pages
     }
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 /// Check if a memory region is inside the instance heap.
 pub unsafe extern "C" fn lucet_vmctx_check_heap(
@@ -462,7 +462,7 @@ pub unsafe extern "C" fn lucet_vmctx_check_heap(
     vmctx.instance().check_heap(ptr, len)
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 pub unsafe extern "C" fn lucet_vmctx_get_func_from_idx(
     vmctx: &Vmctx,
@@ -477,13 +477,13 @@ pub unsafe extern "C" fn lucet_vmctx_get_func_from_idx(
         .unwrap_or(std::ptr::null())
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 pub unsafe extern "C" fn lucet_vmctx_terminate(_vmctx: &Vmctx, details: *mut c_void) {
     lucet_hostcall_terminate!(CTerminationDetails { details });
 }
 
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 /// Get the delegate object for the current instance.
 ///
@@ -499,7 +499,7 @@ pub unsafe extern "C" fn lucet_vmctx_get_delegate(vmctx: &Vmctx) -> *mut c_void
 }
 
 /// TODO: C implementations of hostcalls are highly questionable
-#[lucet_hostcall]
+#[lucet_hostcall(deterministic)]
 #[no_mangle]
 pub unsafe extern "C" fn lucet_vmctx_yield(vmctx: &Vmctx, val: *mut c_void) -> *mut c_void {
     vmctx
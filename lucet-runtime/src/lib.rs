@@ -394,6 +394,12 @@
 //! lucet-runtime = { version = "0.6.1", default-features = false }
 //! lucet-runtime-internals = { version = "0.6.1", default-features = false }
 //! ```
+//!
+//! ## Restricting Syscalls
+//!
+//! With the `seccomp` feature enabled on x86-64 Linux, [`SeccompFilter`](struct.SeccompFilter.html) can
+//! install a seccomp-bpf filter on the threads that run guest code, limiting them to the syscalls
+//! that the runtime and the embedder's hostcalls need.
 
 #![deny(bare_trait_objects)]
 
@@ -403,27 +409,47 @@ extern crate self as lucet_runtime;
 
 pub mod c_api;
 
-pub use lucet_module::{PublicKey, TrapCode};
-pub use lucet_runtime_internals::alloc::{AllocStrategy, Limits, DEFAULT_SIGNAL_STACK_SIZE};
-pub use lucet_runtime_internals::error::Error;
+pub use lucet_module::{CustomSection, PublicKey, TrapCode};
+pub use lucet_runtime_internals::alloc::{
+    AllocStrategy, Limits, LimitsBuilder, DEFAULT_SIGNAL_STACK_SIZE,
+};
+pub use lucet_runtime_internals::config;
+pub use lucet_runtime_internals::error::{Error, GuestMemoryError};
 pub use lucet_runtime_internals::instance::signals::{
     install_lucet_signal_handler, remove_lucet_signal_handler,
 };
 pub use lucet_runtime_internals::instance::{
-    FaultDetails, Instance, InstanceHandle, KillError, KillSuccess, KillSwitch, RunResult,
-    SignalBehavior, TerminationDetails, YieldedVal,
+    FaultAddrLocation, FaultDetails, HostPanicPolicy, ImportedGlobals, Instance, InstanceGroup,
+    InstanceHandle, KillError, KillSuccess, KillSwitch, MemoryStats, RunResult, RunStats,
+    SignalBehavior, StartPolicy, TerminationDetails, TypedFunc, YieldedVal,
+};
+pub use lucet_runtime_internals::linker::{
+    ImportKind, IntoHostFunc, LinkedImports, Linker, SharedInstance, UnresolvedImport,
 };
 #[allow(deprecated)]
 pub use lucet_runtime_internals::lucet_hostcalls;
-pub use lucet_runtime_internals::module::{DlModule, Module};
-pub use lucet_runtime_internals::region::mmap::MmapRegion;
+pub use lucet_runtime_internals::memory::{GuestMemory, Pod, WasmPtr, WasmSlice};
+pub use lucet_runtime_internals::module::{
+    DlModule, Export, ExportKind, Module, ModuleHandle, ModuleRegistry, StaticModule, TrapLocation,
+};
+pub use lucet_runtime_internals::quota::{Quota, QuotaUsage};
+pub use lucet_runtime_internals::region::mmap::{MmapRegion, SlotAffinity, SlotEviction};
 #[cfg(all(target_os = "linux", feature = "uffd"))]
 pub use lucet_runtime_internals::region::uffd::{
     HostPageSizedUffdStrategy, UffdRegion, UffdStrategy, WasmPageSizedUffdStrategy,
 };
-pub use lucet_runtime_internals::region::{InstanceBuilder, Region, RegionCreate};
-pub use lucet_runtime_internals::val::{UntypedRetVal, Val};
-pub use lucet_runtime_internals::{lucet_hostcall, lucet_hostcall_terminate, WASM_PAGE_SIZE};
+pub use lucet_runtime_internals::region::{
+    protection_keys_supported, DecommitPolicy, InstanceBuilder, OccupancyEvent, Region,
+    RegionCreate, RegionOptions, RegionStats,
+};
+pub use lucet_runtime_internals::replay;
+#[cfg(all(target_os = "linux", target_arch = "x86_64", feature = "seccomp"))]
+pub use lucet_runtime_internals::seccomp::{SeccompAction, SeccompFilter};
+pub use lucet_runtime_internals::val::{UntypedRetVal, Val, WasmParams, WasmRet, WasmValue};
+pub use lucet_runtime_internals::wx;
+pub use lucet_runtime_internals::{
+    lucet_hostcall, lucet_hostcall_terminate, lucet_static_module, WASM_PAGE_SIZE,
+};
 
 pub mod vmctx {
     //! Functions for manipulating instances from hostcalls.
@@ -103,6 +103,9 @@ pub fn check_instruction_count() {
                 RunResult::Yielded(_) => {
                     panic!("instruction counting test runner doesn't support yielding");
                 }
+                RunResult::Exited(_) => {
+                    panic!("instruction counting test runner doesn't support exiting");
+                }
             },
             "instruction count for test case {} is incorrect",
             wasm_path.display()
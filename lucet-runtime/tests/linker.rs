@@ -0,0 +1,12 @@
+use lucet_runtime_tests::linker_tests;
+
+cfg_if::cfg_if! {
+    if #[cfg(all(target_os = "linux", feature = "uffd"))] {
+        linker_tests!(
+            mmap => lucet_runtime::MmapRegion,
+            uffd => lucet_runtime::UffdRegion
+        );
+    } else {
+        linker_tests!(mmap => lucet_runtime::MmapRegion);
+    }
+}
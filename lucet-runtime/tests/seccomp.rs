@@ -0,0 +1,38 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64", feature = "seccomp"))]
+
+use lucet_runtime::vmctx::lucet_vmctx;
+use lucet_runtime::{Limits, MmapRegion, Region, RegionCreate, SeccompAction, SeccompFilter};
+use lucet_runtime_tests::helpers::{FunctionPointer, MockExportBuilder, MockModuleBuilder};
+
+#[test]
+fn guest_syscalls_are_filtered() {
+    extern "C" fn getppid(_vmctx: *const lucet_vmctx) -> u64 {
+        unsafe { libc::syscall(libc::SYS_getppid) as u64 }
+    }
+
+    let module = MockModuleBuilder::new()
+        .with_export_func(MockExportBuilder::new(
+            "getppid",
+            FunctionPointer::from_usize(getppid as usize),
+        ))
+        .build();
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+
+    // the filter stays on the thread that installs it, so keep it off the test harness threads
+    std::thread::spawn(move || {
+        SeccompFilter::new()
+            .with_default_action(SeccompAction::Errno(libc::EPERM as u16))
+            .install()
+            .expect("filter can be installed");
+        let mut inst = region
+            .new_instance(module)
+            .expect("instance can be created");
+        let retval = inst
+            .run("getppid", &[])
+            .expect("instance runs")
+            .unwrap_returned();
+        assert_eq!(u64::from(retval), -1i64 as u64);
+    })
+    .join()
+    .expect("sandboxed thread succeeds");
+}
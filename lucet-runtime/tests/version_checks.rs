@@ -1,4 +1,7 @@
-use lucet_runtime::{DlModule, Error};
+use lucet_module::{ModuleArtifact, VersionInfo};
+use lucet_runtime::{DlModule, Error, Limits, MmapRegion, Region};
+use lucetc::Lucetc;
+use tempfile::TempDir;
 
 #[test]
 pub fn reject_old_modules() {
@@ -20,6 +23,66 @@ pub fn reject_old_modules() {
     }
 }
 
+#[cfg(target_os = "linux")]
+#[test]
+pub fn reject_old_modules_from_bytes() {
+    let bytes = std::fs::read("./tests/version_checks/old_module.so").unwrap();
+
+    // getting as far as the version check means the module was loaded from memory
+    let err = DlModule::load_from_bytes(&bytes).err().unwrap();
+
+    if let Error::ModuleError(e) = err {
+        let msg = format!("{}", e);
+        assert!(msg.contains("reserved bit is not set"));
+    } else {
+        panic!("unexpected error loading module: {}", err);
+    }
+}
+
+#[test]
+pub fn load_modules_from_other_releases() {
+    let workdir = TempDir::new().expect("create working directory");
+    let wat = workdir.path().join("answer.wat");
+    std::fs::write(
+        &wat,
+        r#"(module (func (export "answer") (result i32) (i32.const 42)))"#,
+    )
+    .unwrap();
+    let so_file = workdir.path().join("answer.so");
+    Lucetc::new(&wat)
+        .shared_object_file(&so_file)
+        .expect("compile module");
+
+    // stamp the module as compiled by another release of `lucetc`, in the same format
+    let mut bytes = std::fs::read(&so_file).unwrap();
+    let version = ModuleArtifact::parse(&bytes).unwrap().version().clone();
+    let older =
+        VersionInfo::new(0, 6, 1, *b"0123abcd").with_format_version(version.format_version());
+    let (mut current, mut stamp) = (vec![], vec![]);
+    version.write_to(&mut current).unwrap();
+    older.write_to(&mut stamp).unwrap();
+    let offsets = bytes
+        .windows(current.len())
+        .enumerate()
+        .filter(|(_, window)| *window == current.as_slice())
+        .map(|(offset, _)| offset)
+        .collect::<Vec<_>>();
+    assert_eq!(offsets.len(), 1, "the version is recorded once");
+    bytes[offsets[0]..offsets[0] + stamp.len()].copy_from_slice(&stamp);
+    std::fs::write(&so_file, &bytes).unwrap();
+
+    let module = DlModule::load(&so_file).expect("module of a supported format loads");
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let mut inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+    let retval = inst
+        .run("answer", &[])
+        .expect("instance runs")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 42);
+}
+
 #[test]
 fn ensure_linked() {
     lucet_runtime::lucet_internal_ensure_linked();
@@ -0,0 +1,20 @@
+//! W^X enforcement applies to every module the process loads, so its test has a binary of its
+//! own, where no other test loads modules while enforcement is enabled.
+#![cfg(target_os = "linux")]
+
+use lucet_runtime::{Limits, MmapRegion, Region, RegionCreate};
+use lucet_runtime_tests::build::test_module_c;
+
+#[test]
+fn load_module_with_wx_enforcement() {
+    lucet_runtime::wx::set_enforcement(true);
+    let module = test_module_c("host", "trivial.c");
+    lucet_runtime::wx::set_enforcement(false);
+    let module = module.expect("module without writable code can be loaded");
+
+    let region = MmapRegion::create(1, &Limits::default()).expect("region can be created");
+    let _inst = region
+        .new_instance(module)
+        .expect("instance can be created");
+    assert_eq!(lucet_runtime::wx::audit().expect("audit succeeds"), vec![]);
+}
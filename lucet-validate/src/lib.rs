@@ -23,19 +23,29 @@ pub enum Error {
     ImportNotFound { module: String, field: String },
     #[error("Export not found: {field}")]
     ExportNotFound { field: String },
-    #[error("Import type error: for {module}::{field}, expected {expected:?}, got {got:?}")]
+    #[error("Import type error: for {module}::{field}, witx expects {expected}, module has {got}")]
     ImportTypeError {
         module: String,
         field: String,
         expected: FuncSignature,
         got: FuncSignature,
     },
-    #[error("Export type error: for {field}, expected {expected:?}, got {got:?}")]
+    #[error("Export type error: for {field}, expected {expected}, module has {got}")]
     ExportTypeError {
         field: String,
         expected: FuncSignature,
         got: FuncSignature,
     },
+    #[error("{} interface mismatches: {}", .0.len(), display_all(.0))]
+    Mismatches(Vec<Error>),
+}
+
+fn display_all(errors: &[Error]) -> String {
+    errors
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 impl From<wasmparser::BinaryReaderError> for Error {
@@ -80,41 +90,70 @@ impl Validator {
         self
     }
 
+    /// Validate the module against the witx interface.
+    ///
+    /// If the module has several mismatches with the interface, they are all returned as
+    /// `Error::Mismatches`; see [`diagnose()`](#method.diagnose) to get them as a list.
     pub fn validate(&self, module_contents: &[u8]) -> Result<(), Error> {
+        let mut mismatches = self.diagnose(module_contents)?;
+        match mismatches.len() {
+            0 => Ok(()),
+            1 => Err(mismatches.remove(0)),
+            _ => Err(Error::Mismatches(mismatches)),
+        }
+    }
+
+    /// Find every mismatch between the module and the witx interface, rather than stopping at the
+    /// first.
+    ///
+    /// Returns an error only if the module is not valid WebAssembly. Each mismatch names the
+    /// import or export it concerns, and type errors carry both the signature the interface
+    /// expects and the one the module has.
+    pub fn diagnose(&self, module_contents: &[u8]) -> Result<Vec<Error>, Error> {
         wasmparser::validate(module_contents, None)?;
 
         let moduletype = ModuleType::parse_wasm(module_contents)?;
 
+        let mut mismatches = vec![];
         for import in moduletype.imports() {
-            let func = self
-                .witx_module(&import.module)?
-                .func(&Id::new(&import.field))
-                .ok_or_else(|| Error::ImportNotFound {
-                    module: import.module.clone(),
-                    field: import.field.clone(),
-                })?;
-            let spec_type = FuncSignature::from(func.core_type());
-            if spec_type != import.ty {
-                return Err(Error::ImportTypeError {
-                    module: import.module,
-                    field: import.field,
-                    got: import.ty,
-                    expected: spec_type,
-                });
+            if let Err(e) = self.check_import(import) {
+                mismatches.push(e);
             }
         }
 
         if self.wasi_exe {
-            self.check_wasi_start_func(&moduletype)?;
+            if let Err(e) = self.check_wasi_start_func(&moduletype) {
+                mismatches.push(e);
+            }
         }
 
-        Ok(())
+        Ok(mismatches)
     }
 
     pub fn doc(&self) -> &Document {
         &self.witx
     }
 
+    fn check_import(&self, import: ImportFunc) -> Result<(), Error> {
+        let func = self
+            .witx_module(&import.module)?
+            .func(&Id::new(&import.field))
+            .ok_or_else(|| Error::ImportNotFound {
+                module: import.module.clone(),
+                field: import.field.clone(),
+            })?;
+        let spec_type = FuncSignature::from(func.core_type());
+        if spec_type != import.ty {
+            return Err(Error::ImportTypeError {
+                module: import.module,
+                field: import.field,
+                got: import.ty,
+                expected: spec_type,
+            });
+        }
+        Ok(())
+    }
+
     fn witx_module(&self, module: &str) -> Result<Rc<Module>, Error> {
         self.witx
             .module(&Id::new(module))
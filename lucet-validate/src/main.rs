@@ -2,12 +2,18 @@
 extern crate clap;
 use clap::Arg;
 use lucet_validate::{self, Validator};
+use serde_json::{json, Value};
 use std::fs::File;
 use std::io::{self, Read};
 use std::path::{Path, PathBuf};
 use std::process;
 use thiserror::Error;
 
+/// The exit code when the module is not valid WebAssembly, or does not match the interfaces.
+const EXIT_INVALID: i32 = 1;
+/// The exit code when the module or the witx files cannot be read.
+const EXIT_ERROR: i32 = 2;
+
 pub fn main() {
     // rebuild if env vars used by app_from_crate! change:
     let _ = include_str!("../Cargo.toml");
@@ -34,12 +40,24 @@ pub fn main() {
                 .long("wasi-exe")
                 .help("validate exports of WASI executable"),
         )
+        .arg(
+            Arg::with_name("json")
+                .takes_value(false)
+                .required(false)
+                .long("json")
+                .help("print every mismatch as JSON, for CI annotations"),
+        )
         .arg(
             Arg::with_name("verbose")
                 .short("v")
                 .takes_value(false)
                 .required(false),
         )
+        .after_help(
+            "The exit code is 0 when the module matches the interfaces, 1 when it is not valid \
+             WebAssembly or does not match them, and 2 when the module or the witx files cannot \
+             be read; with --json too.",
+        )
         .get_matches();
 
     let module_path = matches
@@ -47,15 +65,40 @@ pub fn main() {
         .map(Path::new)
         .expect("module arg required");
 
-    match run(
-        &module_path,
-        &matches
-            .values_of("witx")
-            .expect("witx path required")
-            .map(PathBuf::from)
-            .collect::<Vec<PathBuf>>(),
-        matches.is_present("wasi-exe"),
-    ) {
+    let witx_paths = matches
+        .values_of("witx")
+        .expect("witx path required")
+        .map(PathBuf::from)
+        .collect::<Vec<PathBuf>>();
+
+    if matches.is_present("json") {
+        let (report, exit_code) =
+            match diagnose(&module_path, &witx_paths, matches.is_present("wasi-exe")) {
+                Ok(mismatches) => (
+                    json!({
+                        "valid": mismatches.is_empty(),
+                        "diagnostics": mismatches.iter().map(diagnostic).collect::<Vec<_>>(),
+                    }),
+                    if mismatches.is_empty() {
+                        0
+                    } else {
+                        EXIT_INVALID
+                    },
+                ),
+                Err(e) => (
+                    json!({
+                        "valid": false,
+                        "error": error_diagnostic(&e),
+                        "diagnostics": [],
+                    }),
+                    exit_code(&e),
+                ),
+            };
+        println!("{:#}", report);
+        process::exit(exit_code);
+    }
+
+    match run(&module_path, &witx_paths, matches.is_present("wasi-exe")) {
         Ok(()) => {
             if matches.is_present("verbose") {
                 println!("validated successfully")
@@ -74,21 +117,104 @@ pub fn main() {
             } else {
                 println!("{}", e);
             }
-            process::exit(-1);
+            process::exit(exit_code(&e));
         }
     }
 }
 
 fn run(module_path: &Path, witx_paths: &[PathBuf], wasi_exe: bool) -> Result<(), Error> {
+    let module_contents = read_module(module_path)?;
+    let validator = Validator::load(witx_paths)?.with_wasi_exe(wasi_exe);
+    validator.validate(&module_contents)?;
+
+    Ok(())
+}
+
+fn diagnose(
+    module_path: &Path,
+    witx_paths: &[PathBuf],
+    wasi_exe: bool,
+) -> Result<Vec<lucet_validate::Error>, Error> {
+    let module_contents = read_module(module_path)?;
+    let validator = Validator::load(witx_paths)?.with_wasi_exe(wasi_exe);
+    Ok(validator.diagnose(&module_contents)?)
+}
+
+fn read_module(module_path: &Path) -> Result<Vec<u8>, Error> {
     let mut module_contents = Vec::new();
     let mut file = File::open(module_path).map_err(|e| Error::Io(module_path.into(), e))?;
     file.read_to_end(&mut module_contents)
         .map_err(|e| Error::Io(module_path.into(), e))?;
+    Ok(module_contents)
+}
 
-    let validator = Validator::load(witx_paths)?.with_wasi_exe(wasi_exe);
-    validator.validate(&module_contents)?;
+/// The exit code for a module that could not be validated because of `e`.
+fn exit_code(e: &Error) -> i32 {
+    match e {
+        Error::Validate(_) => EXIT_INVALID,
+        Error::Witx(_) | Error::Io(..) => EXIT_ERROR,
+    }
+}
 
-    Ok(())
+/// Describe an error that stopped validation as JSON: a file that cannot be read, witx that cannot
+/// be loaded, or a module that cannot be parsed.
+fn error_diagnostic(e: &Error) -> Value {
+    match e {
+        Error::Io(path, _) => json!({
+            "kind": "io",
+            "path": path.display().to_string(),
+            "message": e.to_string(),
+        }),
+        Error::Witx(witx) => json!({
+            "kind": "witx_load",
+            "message": e.to_string(),
+            "report": witx.report(),
+        }),
+        Error::Validate(e) => diagnostic(e),
+    }
+}
+
+/// Describe a mismatch as JSON, with the import or export it concerns and the signatures
+/// involved.
+fn diagnostic(e: &lucet_validate::Error) -> Value {
+    use lucet_validate::Error::*;
+    let mut diagnostic = match e {
+        WasmValidation(_, offset) => json!({ "kind": "parse", "offset": offset }),
+        Unsupported(_) => json!({ "kind": "unsupported" }),
+        ModuleNotFound(module) => json!({ "kind": "module_not_found", "module": module }),
+        ImportNotFound { module, field } => {
+            json!({ "kind": "import_not_found", "module": module, "field": field })
+        }
+        ExportNotFound { field } => json!({ "kind": "export_not_found", "field": field }),
+        ImportTypeError {
+            module,
+            field,
+            expected,
+            got,
+        } => json!({
+            "kind": "import_type",
+            "module": module,
+            "field": field,
+            "expected": expected.to_string(),
+            "got": got.to_string(),
+        }),
+        ExportTypeError {
+            field,
+            expected,
+            got,
+        } => json!({
+            "kind": "export_type",
+            "field": field,
+            "expected": expected.to_string(),
+            "got": got.to_string(),
+        }),
+        Mismatches(mismatches) => json!({
+            "kind": "mismatches",
+            "diagnostics": mismatches.iter().map(diagnostic).collect::<Vec<_>>(),
+        }),
+    };
+    diagnostic["message"] = json!(e.to_string());
+    diagnostic
 }
 
 #[derive(Debug, Error)]
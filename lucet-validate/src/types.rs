@@ -1,4 +1,5 @@
 use crate::AtomType;
+use std::fmt;
 use witx::CoreFuncType;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -7,6 +8,23 @@ pub struct FuncSignature {
     pub ret: Option<AtomType>,
 }
 
+impl fmt::Display for FuncSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let atom = |atom: &AtomType| match atom {
+            AtomType::I32 => "i32",
+            AtomType::I64 => "i64",
+            AtomType::F32 => "f32",
+            AtomType::F64 => "f64",
+        };
+        let args = self.args.iter().map(atom).collect::<Vec<_>>();
+        write!(f, "({})", args.join(", "))?;
+        match &self.ret {
+            Some(ret) => write!(f, " -> {}", atom(ret)),
+            None => write!(f, " -> ()"),
+        }
+    }
+}
+
 impl From<CoreFuncType> for FuncSignature {
     fn from(m: CoreFuncType) -> FuncSignature {
         FuncSignature {
@@ -0,0 +1,70 @@
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+use tempfile::TempDir;
+
+const WASI_WITX: &str = "../wasi/phases/snapshot/witx/wasi_snapshot_preview1.witx";
+
+/// Run `lucet-validate --json` on `module` against the WASI witx, returning its exit code and
+/// report.
+fn validate_json(module: &Path) -> (Option<i32>, Value) {
+    let output = Command::new(env!("CARGO_BIN_EXE_lucet-validate"))
+        .arg("--json")
+        .arg(module)
+        .arg(WASI_WITX)
+        .output()
+        .expect("run lucet-validate");
+    let report = serde_json::from_slice(&output.stdout).expect("report is JSON");
+    (output.status.code(), report)
+}
+
+fn write_wat(tmp: &TempDir, wat: &str) -> std::path::PathBuf {
+    let path = tmp.path().join("module.wasm");
+    std::fs::write(&path, wabt::wat2wasm(wat).expect("wat2wasm")).expect("write module");
+    path
+}
+
+#[test]
+fn json_valid_module_exits_0() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let module = write_wat(
+        &tmp,
+        r#"(module (import "wasi_snapshot_preview1" "fd_close" (func (param i32) (result i32))))"#,
+    );
+    let (code, report) = validate_json(&module);
+    assert_eq!(code, Some(0));
+    assert_eq!(report["valid"], true);
+}
+
+#[test]
+fn json_mismatch_exits_1() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let module = write_wat(
+        &tmp,
+        r#"(module (import "wasi_snapshot_preview1" "fd_close" (func (param i64) (result i32))))"#,
+    );
+    let (code, report) = validate_json(&module);
+    assert_eq!(code, Some(1));
+    assert_eq!(report["valid"], false);
+    assert_eq!(report["diagnostics"][0]["kind"], "import_type");
+    assert_eq!(report["diagnostics"][0]["field"], "fd_close");
+}
+
+#[test]
+fn json_unparseable_module_exits_1() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let module = tmp.path().join("module.wasm");
+    std::fs::write(&module, b"\0asm\x01\0\0\0\x7f").expect("write module");
+    let (code, report) = validate_json(&module);
+    assert_eq!(code, Some(1));
+    assert_eq!(report["error"]["kind"], "parse");
+}
+
+#[test]
+fn json_missing_module_exits_2() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let (code, report) = validate_json(&tmp.path().join("missing.wasm"));
+    assert_eq!(code, Some(2));
+    assert_eq!(report["valid"], false);
+    assert_eq!(report["error"]["kind"], "io");
+}
@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod lucet_validate_tests {
-    use lucet_validate::Validator;
+    use lucet_validate::{Error, Validator};
     use std::fs;
     use std::path::Path;
 
@@ -58,4 +58,42 @@ mod lucet_validate_tests {
                 .unwrap_or_else(|_| panic!("validate {:?}", entry_path));
         }
     }
+
+    #[test]
+    fn diagnose_reports_every_mismatch() {
+        let validator = Validator::new(lucet_wasi::witx_document(), true);
+        let module = wabt::wat2wasm(
+            r#"
+            (module
+              (import "wasi_snapshot_preview1" "fd_close" (func (param i64) (result i32)))
+              (import "wasi_snapshot_preview1" "no_such_func" (func)))
+            "#,
+        )
+        .expect("wat2wasm");
+
+        let mismatches = validator.diagnose(&module).expect("module is valid wasm");
+        assert_eq!(mismatches.len(), 3, "mismatches: {:?}", mismatches);
+        match &mismatches[0] {
+            Error::ImportTypeError {
+                field,
+                expected,
+                got,
+                ..
+            } => {
+                assert_eq!(field, "fd_close");
+                assert_eq!(expected.to_string(), "(i32) -> i32");
+                assert_eq!(got.to_string(), "(i64) -> i32");
+            }
+            e => panic!("unexpected mismatch: {}", e),
+        }
+        assert!(
+            matches!(&mismatches[1], Error::ImportNotFound { field, .. } if field == "no_such_func")
+        );
+        assert!(matches!(&mismatches[2], Error::ExportNotFound { field } if field == "_start"));
+
+        match validator.validate(&module) {
+            Err(Error::Mismatches(all)) => assert_eq!(all.len(), 3),
+            res => panic!("unexpected result: {:?}", res),
+        }
+    }
 }
@@ -40,8 +40,12 @@ pub fn bindings(args: TokenStream) -> TokenStream {
         &config.ctx_name,
         &config.constructor,
         &quote!(wasi_common::wasi),
-        &quote!(), // pre_hook,
-        &quote!(), // post_hook,
+        &quote!(crate::trace::enter(vmctx);),
+        &quote!(crate::trace::exit(vmctx, hostcall_name, &hostcall_args, &r);),
+        &lucet_wiggle::generate::Features::default(),
+        // the implementations refuse deterministic instances where they depend on the host, as
+        // `poll_oneoff` and socket I/O do
+        &lucet_wiggle::generate::Deterministic::all(&doc),
     ));
 
     TokenStream::from(ts)
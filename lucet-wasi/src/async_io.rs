@@ -0,0 +1,181 @@
+//! Blocking socket I/O that yields to the embedder instead.
+//!
+//! When a context is built with
+//! [`WasiCtxBuilder::async_io()`](struct.WasiCtxBuilder.html#method.async_io), the operations of
+//! the guest that would block on a socket — `fd_read()`, `fd_write()`, `sock_recv()`,
+//! `sock_send()`, `sock_accept()`, and `poll_oneoff()` — do not block the thread. The instance
+//! yields an [`IoWait`](struct.IoWait.html) instead, which the embedder waits on in its own event
+//! loop before resuming the instance, when the operation is tried again:
+//!
+//! ```no_run
+//! # use lucet_runtime::{InstanceHandle, RunResult};
+//! # fn drive(inst: &mut InstanceHandle) -> Result<(), lucet_runtime::Error> {
+//! let mut res = inst.run("_start", &[])?;
+//! while let RunResult::Yielded(val) = res {
+//!     let wait = val
+//!         .downcast_ref::<lucet_wasi::IoWait>()
+//!         .expect("the guest waits for I/O");
+//!     // an embedder with an executor registers `wait.fds()` with it instead
+//!     wait.block().expect("poll succeeds");
+//!     res = inst.resume()?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Sockets the guest has made nonblocking with `fd_fdstat_set_flags()` still fail with `Again`,
+//! and the files of the host still block, as `connect()` does. A `poll_oneoff()` also waits on the
+//! pipes and terminals behind the standard streams, so the file descriptors of an `IoWait` are not
+//! always sockets.
+
+use crate::ctx::WasiCtx;
+use crate::runtime::types;
+use lucet_runtime::vmctx::Vmctx;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::time::Duration;
+
+/// What a file descriptor of the host is waited on for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interest {
+    Readable,
+    Writable,
+}
+
+/// The I/O a guest waits for, which its instance yields instead of blocking.
+///
+/// The instance is resumed with `resume()` once any of the file descriptors is ready, or the
+/// timeout has passed; resuming it earlier only makes it yield again.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IoWait {
+    fds: Vec<(RawFd, Interest)>,
+    timeout: Option<Duration>,
+}
+
+impl IoWait {
+    pub(crate) fn new(fds: Vec<(RawFd, Interest)>, timeout: Option<Duration>) -> Self {
+        IoWait { fds, timeout }
+    }
+
+    /// The file descriptors of the host to wait on, which belong to the guest's sockets and
+    /// standard streams.
+    pub fn fds(&self) -> &[(RawFd, Interest)] {
+        &self.fds
+    }
+
+    /// How long to wait at most, or `None` to wait until a file descriptor is ready.
+    pub fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    /// Block the thread until the wait is over, for embedders without an event loop of their own.
+    pub fn block(&self) -> io::Result<()> {
+        poll_fds(&self.fds, self.timeout.map_or(-1, timeout_ms))?;
+        Ok(())
+    }
+}
+
+/// `timeout` in whole milliseconds, rounded up so that it has passed when a poll times out.
+pub(crate) fn timeout_ms(timeout: Duration) -> i32 {
+    let ms = (timeout.as_nanos() + 999_999) / 1_000_000;
+    std::cmp::min(ms, i32::max_value() as u128) as i32
+}
+
+/// Poll the file descriptors `fds` of the host, for at most `timeout_ms` milliseconds or forever
+/// if it is negative, returning the index of each one that is ready with its event.
+///
+/// A poll interrupted by a signal returns no events, as if it had timed out.
+pub(crate) fn poll_fds(
+    fds: &[(RawFd, Interest)],
+    timeout_ms: i32,
+) -> io::Result<Vec<(usize, types::EventFdReadwrite)>> {
+    let mut pollfds = fds
+        .iter()
+        .map(|(fd, interest)| libc::pollfd {
+            fd: *fd,
+            events: match interest {
+                Interest::Readable => libc::POLLIN,
+                Interest::Writable => libc::POLLOUT,
+            },
+            revents: 0,
+        })
+        .collect::<Vec<_>>();
+    let res = unsafe {
+        libc::poll(
+            pollfds.as_mut_ptr(),
+            pollfds.len() as libc::nfds_t,
+            timeout_ms,
+        )
+    };
+    if res < 0 {
+        let err = io::Error::last_os_error();
+        if err.kind() == io::ErrorKind::Interrupted {
+            return Ok(vec![]);
+        }
+        return Err(err);
+    }
+    let mut ready = vec![];
+    for (i, pollfd) in pollfds.iter().enumerate() {
+        if pollfd.revents == 0 {
+            continue;
+        }
+        let mut nbytes: libc::c_int = 0;
+        if pollfd.events == libc::POLLIN {
+            unsafe { libc::ioctl(pollfd.fd, libc::FIONREAD, &mut nbytes) };
+        }
+        let flags = if pollfd.revents & libc::POLLHUP != 0 {
+            types::Eventrwflags::FD_READWRITE_HANGUP
+        } else {
+            types::Eventrwflags::EMPTY_FLAGS
+        };
+        ready.push((
+            i,
+            types::EventFdReadwrite {
+                nbytes: nbytes.max(0) as types::Filesize,
+                flags,
+            },
+        ));
+    }
+    Ok(ready)
+}
+
+/// The file descriptor of the host that the instance waits on instead of blocking on the guest's
+/// socket `fd`, if it yields on it.
+fn yields_on(vmctx: &Vmctx, fd: types::Fd) -> Result<Option<RawFd>, types::Errno> {
+    let wasi = vmctx.get_embed_ctx::<WasiCtx>();
+    if !wasi.async_io() {
+        return Ok(None);
+    }
+    let sockets = wasi.sockets();
+    if sockets.is_nonblocking(fd)? {
+        Ok(None)
+    } else {
+        sockets.raw_fd(fd).map(Some)
+    }
+}
+
+/// Run the operation `op` on the guest's socket `fd`, which is passed whether it must not block.
+///
+/// If the instance yields on the socket, `op` fails with `Again` rather than blocking, and it is
+/// tried again each time the instance is resumed. Instances in deterministic mode are terminated
+/// instead, as what a socket reads and when it is ready depend on the network.
+pub(crate) fn socket_op<T>(
+    vmctx: &Vmctx,
+    fd: types::Fd,
+    interest: Interest,
+    mut op: impl FnMut(bool) -> Result<T, types::Errno>,
+) -> Result<T, types::Errno> {
+    vmctx.nondeterministic("socket I/O");
+    let raw_fd = match yields_on(vmctx, fd)? {
+        Some(raw_fd) => raw_fd,
+        None => return op(false),
+    };
+    loop {
+        match op(true) {
+            Err(types::Errno::Again) => {
+                vmctx.yield_val(IoWait::new(vec![(raw_fd, interest)], None));
+            }
+            res => return res,
+        }
+    }
+}
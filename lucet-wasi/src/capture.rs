@@ -0,0 +1,188 @@
+//! Capturing the output a guest writes to stdout or stderr.
+//!
+//! By default these go to files, or to the host's own stdio with `inherit_stdio()`. A server that
+//! runs guests on behalf of many requests can instead route each guest's output into an
+//! [`OutputCapture`](struct.OutputCapture.html), and attach it to the logs of the request:
+//!
+//! ```no_run
+//! # use lucet_wasi::{OutputCapture, WasiCtxBuilder};
+//! let stdout = OutputCapture::buffer();
+//! let stderr = OutputCapture::ring_buffer(64 * 1024);
+//! let ctx = WasiCtxBuilder::new()
+//!     .capture_stdout(stdout.clone())
+//!     .capture_stderr(stderr.clone())
+//!     .build()
+//!     .unwrap();
+//! // ... run the guest with `ctx` ...
+//! println!("guest wrote {:?}", String::from_utf8_lossy(&stdout.take()));
+//! ```
+//!
+//! A guest that runs for a long time can have its output streamed instead, with
+//! [`WasiCtxBuilder::stream_output()`](struct.WasiCtxBuilder.html#method.stream_output), which
+//! hands each write to a callback as it is made, along with the instance and the stream:
+//!
+//! ```no_run
+//! # use lucet_wasi::WasiCtxBuilder;
+//! let ctx = WasiCtxBuilder::new()
+//!     .stream_output("request-42", |instance, stream, data| {
+//!         eprint!("[{} {:?}] {}", instance, stream, String::from_utf8_lossy(data));
+//!     })
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// The standard stream that a guest wrote to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// A destination for the output of a guest.
+///
+/// The capture can be cloned and sent to other threads; all clones refer to the same destination,
+/// so the embedder can keep a clone to read the output that the guest writes to another.
+#[derive(Clone)]
+pub struct OutputCapture {
+    inner: Arc<Mutex<Sink>>,
+}
+
+enum Sink {
+    Buffer(Vec<u8>),
+    Ring {
+        buf: VecDeque<u8>,
+        capacity: usize,
+        discarded: u64,
+    },
+    Callback(Box<dyn FnMut(&[u8]) + Send>),
+}
+
+impl OutputCapture {
+    /// Capture the output in a buffer that grows to hold all of it.
+    pub fn buffer() -> Self {
+        Self::new(Sink::Buffer(vec![]))
+    }
+
+    /// Capture the last `capacity` bytes of the output, discarding the oldest bytes when more
+    /// are written.
+    pub fn ring_buffer(capacity: usize) -> Self {
+        Self::new(Sink::Ring {
+            buf: VecDeque::with_capacity(capacity),
+            capacity,
+            discarded: 0,
+        })
+    }
+
+    /// Call `f` with the bytes of each write, as soon as the guest makes it.
+    ///
+    /// The guest is blocked in its write until `f` returns, and the bytes are not kept.
+    pub fn callback<F>(f: F) -> Self
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        Self::new(Sink::Callback(Box::new(f)))
+    }
+
+    fn new(sink: Sink) -> Self {
+        OutputCapture {
+            inner: Arc::new(Mutex::new(sink)),
+        }
+    }
+
+    /// A copy of the captured output, which is always empty for a callback.
+    pub fn contents(&self) -> Vec<u8> {
+        match &*self.inner.lock().unwrap() {
+            Sink::Buffer(buf) => buf.clone(),
+            Sink::Ring { buf, .. } => buf.iter().copied().collect(),
+            Sink::Callback(_) => vec![],
+        }
+    }
+
+    /// Remove and return the captured output, which is always empty for a callback.
+    pub fn take(&self) -> Vec<u8> {
+        match &mut *self.inner.lock().unwrap() {
+            Sink::Buffer(buf) => std::mem::replace(buf, vec![]),
+            Sink::Ring { buf, .. } => buf.drain(..).collect(),
+            Sink::Callback(_) => vec![],
+        }
+    }
+
+    /// The number of bytes a ring buffer has discarded to make room for newer output, which is
+    /// always 0 for the other kinds of capture.
+    pub fn discarded(&self) -> u64 {
+        match &*self.inner.lock().unwrap() {
+            Sink::Ring { discarded, .. } => *discarded,
+            _ => 0,
+        }
+    }
+
+    /// Capture the bytes of a write by the guest.
+    pub(crate) fn write(&self, data: &[u8]) {
+        match &mut *self.inner.lock().unwrap() {
+            Sink::Buffer(buf) => buf.extend_from_slice(data),
+            Sink::Ring {
+                buf,
+                capacity,
+                discarded,
+            } => {
+                // only the tail of a write larger than the whole buffer can be kept
+                let kept = &data[data.len().saturating_sub(*capacity)..];
+                let overflow = (buf.len() + kept.len()).saturating_sub(*capacity);
+                buf.drain(..overflow);
+                buf.extend(kept);
+                *discarded += (overflow + data.len() - kept.len()) as u64;
+            }
+            Sink::Callback(f) => f(data),
+        }
+    }
+}
+
+impl std::fmt::Debug for OutputCapture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match &*self.inner.lock().unwrap() {
+            Sink::Buffer(_) => "buffer",
+            Sink::Ring { .. } => "ring_buffer",
+            Sink::Callback(_) => "callback",
+        };
+        f.debug_struct("OutputCapture")
+            .field("kind", &kind)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ring_buffer_keeps_the_tail() {
+        let ring = OutputCapture::ring_buffer(4);
+        ring.write(b"ab");
+        ring.write(b"cde");
+        assert_eq!(ring.contents(), b"bcde");
+        assert_eq!(ring.discarded(), 1);
+        ring.write(b"0123456");
+        assert_eq!(ring.take(), b"3456");
+        assert_eq!(ring.discarded(), 8);
+        assert!(ring.contents().is_empty());
+    }
+
+    #[test]
+    fn callback_sees_each_write() {
+        let writes = Arc::new(Mutex::new(vec![]));
+        let capture = {
+            let writes = writes.clone();
+            OutputCapture::callback(move |data| writes.lock().unwrap().push(data.to_vec()))
+        };
+        capture.write(b"hello, ");
+        capture.write(b"world");
+        assert_eq!(
+            *writes.lock().unwrap(),
+            vec![b"hello, ".to_vec(), b"world".to_vec()]
+        );
+        assert!(capture.contents().is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+//! Clocks that embedders can give guests in place of the host's.
+//!
+//! By default, `clock_time_get()` and `clock_res_get()` read the clocks of the host, so a guest
+//! can learn the host's wall-clock time and how long it has been running. A context built with
+//! [`WasiCtxBuilder::clock()`](../struct.WasiCtxBuilder.html#method.clock) reads a
+//! [`WasiClock`](trait.WasiClock.html) instead, such as one of the implementations here, or one
+//! the embedder provides:
+//!
+//! ```no_run
+//! # use lucet_wasi::{FixedClock, WasiCtxBuilder};
+//! // midnight on January 1st, 2020, forever
+//! let ctx = WasiCtxBuilder::new()
+//!     .clock(FixedClock::new(1_577_836_800_000_000_000))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! The clock is only read by the clock hostcalls: the timeouts of `poll_oneoff()` still wait for
+//! the host's time to pass. Instances in deterministic mode read their
+//! [`DeterministicEnv`](../struct.DeterministicEnv.html) instead.
+
+use crate::runtime::types;
+use std::cell::Cell;
+
+/// A source of the time for the clocks of a guest.
+pub trait WasiClock: 'static {
+    /// The time of the clock `id`, in nanoseconds.
+    ///
+    /// For `Clockid::Realtime`, this is the time since the Unix epoch; the other clocks only have
+    /// to be consistent with themselves.
+    fn time(&self, id: types::Clockid) -> Result<types::Timestamp, types::Errno>;
+
+    /// The resolution of the clock `id`, in nanoseconds.
+    fn resolution(&self, id: types::Clockid) -> Result<types::Timestamp, types::Errno>;
+}
+
+/// A clock that never changes: every clock reads the same time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedClock {
+    time: u64,
+}
+
+impl FixedClock {
+    /// Create a clock that always reads `time`, in nanoseconds.
+    pub fn new(time: u64) -> Self {
+        FixedClock { time }
+    }
+}
+
+impl WasiClock for FixedClock {
+    fn time(&self, _id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        Ok(self.time)
+    }
+
+    fn resolution(&self, _id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        Ok(1)
+    }
+}
+
+/// The host's clocks, with the realtime clock moved by a fixed offset.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OffsetClock {
+    offset: i64,
+}
+
+impl OffsetClock {
+    /// Create a clock whose realtime clock is `offset` nanoseconds ahead of the host's, or behind
+    /// it if `offset` is negative.
+    pub fn new(offset: i64) -> Self {
+        OffsetClock { offset }
+    }
+}
+
+impl WasiClock for OffsetClock {
+    fn time(&self, id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        let time = host_time(id)?;
+        match id {
+            types::Clockid::Realtime if self.offset < 0 => {
+                Ok(time.saturating_sub(self.offset.wrapping_neg() as u64))
+            }
+            types::Clockid::Realtime => Ok(time.saturating_add(self.offset as u64)),
+            _ => Ok(time),
+        }
+    }
+
+    fn resolution(&self, id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        host_resolution(id)
+    }
+}
+
+/// The host's clocks, running faster or slower than the host's from when they are first read.
+#[derive(Debug)]
+pub struct ScaledClock {
+    scale: f64,
+    /// The time each clock was first read at, by `clock_index()`.
+    origins: [Cell<Option<u64>>; 4],
+}
+
+impl ScaledClock {
+    /// Create a clock that advances by `scale` nanoseconds for each nanosecond of host time.
+    ///
+    /// Each clock starts at the host's time, when it is first read.
+    pub fn new(scale: f64) -> Self {
+        assert!(
+            scale.is_finite() && scale >= 0.0,
+            "clock scale must be finite and non-negative"
+        );
+        ScaledClock {
+            scale,
+            origins: Default::default(),
+        }
+    }
+}
+
+impl WasiClock for ScaledClock {
+    fn time(&self, id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        let time = host_time(id)?;
+        let origin = &self.origins[clock_index(id)];
+        let start = origin.get().unwrap_or(time);
+        origin.set(Some(start));
+        let elapsed = (time.saturating_sub(start) as f64 * self.scale) as u64;
+        Ok(start.saturating_add(elapsed))
+    }
+
+    fn resolution(&self, id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        let resolution = host_resolution(id)? as f64 * self.scale;
+        Ok((resolution as u64).max(1))
+    }
+}
+
+/// A clock that only reveals how much time has passed: every clock reads the host's monotonic
+/// time since this clock was created, so the realtime clock starts at the Unix epoch.
+#[derive(Debug)]
+pub struct MonotonicClock {
+    start: u64,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MonotonicClock {
+    /// Create a clock that reads zero now.
+    pub fn new() -> Self {
+        MonotonicClock {
+            start: host_time(types::Clockid::Monotonic).expect("monotonic clock can be read"),
+        }
+    }
+}
+
+impl WasiClock for MonotonicClock {
+    fn time(&self, _id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        Ok(host_time(types::Clockid::Monotonic)?.saturating_sub(self.start))
+    }
+
+    fn resolution(&self, _id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
+        host_resolution(types::Clockid::Monotonic)
+    }
+}
+
+fn clock_index(id: types::Clockid) -> usize {
+    match id {
+        types::Clockid::Realtime => 0,
+        types::Clockid::Monotonic => 1,
+        types::Clockid::ProcessCputimeId => 2,
+        types::Clockid::ThreadCputimeId => 3,
+    }
+}
+
+fn host_clock(id: types::Clockid) -> libc::clockid_t {
+    match id {
+        types::Clockid::Realtime => libc::CLOCK_REALTIME,
+        types::Clockid::Monotonic => libc::CLOCK_MONOTONIC,
+        types::Clockid::ProcessCputimeId => libc::CLOCK_PROCESS_CPUTIME_ID,
+        types::Clockid::ThreadCputimeId => libc::CLOCK_THREAD_CPUTIME_ID,
+    }
+}
+
+fn timespec_nanos(res: libc::c_int, ts: libc::timespec) -> Result<u64, types::Errno> {
+    if res != 0 {
+        return Err(types::Errno::Inval);
+    }
+    Ok((ts.tv_sec as u64)
+        .saturating_mul(1_000_000_000)
+        .saturating_add(ts.tv_nsec as u64))
+}
+
+/// The host's time for the clock `id`, in nanoseconds.
+fn host_time(id: types::Clockid) -> Result<u64, types::Errno> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let res = unsafe { libc::clock_gettime(host_clock(id), &mut ts) };
+    timespec_nanos(res, ts)
+}
+
+/// The host's resolution for the clock `id`, in nanoseconds.
+fn host_resolution(id: types::Clockid) -> Result<u64, types::Errno> {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    let res = unsafe { libc::clock_getres(host_clock(id), &mut ts) };
+    timespec_nanos(res, ts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offset_clock_only_moves_realtime() {
+        let clock = OffsetClock::new(-3_600_000_000_000);
+        let realtime = clock.time(types::Clockid::Realtime).unwrap();
+        let host = host_time(types::Clockid::Realtime).unwrap();
+        assert!(realtime < host - 3_500_000_000_000);
+        let monotonic = clock.time(types::Clockid::Monotonic).unwrap();
+        assert!(monotonic <= host_time(types::Clockid::Monotonic).unwrap());
+    }
+
+    #[test]
+    fn scaled_clock_starts_at_host_time() {
+        let stopped = ScaledClock::new(0.0);
+        let first = stopped.time(types::Clockid::Monotonic).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert_eq!(stopped.time(types::Clockid::Monotonic).unwrap(), first);
+
+        let fast = ScaledClock::new(10.0);
+        let first = fast.time(types::Clockid::Monotonic).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(fast.time(types::Clockid::Monotonic).unwrap() - first >= 100_000_000);
+    }
+
+    #[test]
+    fn monotonic_clock_hides_realtime() {
+        let clock = MonotonicClock::new();
+        // less than a day since the epoch
+        assert!(clock.time(types::Clockid::Realtime).unwrap() < 86_400_000_000_000);
+    }
+}
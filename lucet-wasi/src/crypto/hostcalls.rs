@@ -0,0 +1,771 @@
+//! The wasi-crypto hostcalls, which read their arguments from the guest and keep its handles.
+
+use super::{
+    constant_time_eq, optional, CryptoErrno, CryptoOptions, SymmetricState, WasiCryptoCtx,
+};
+use lucet_runtime::lucet_hostcall;
+use lucet_runtime::vmctx::Vmctx;
+use lucet_wiggle::runtime::LucetMemory;
+use lucet_wiggle::{GuestError, GuestPtr, GuestSlice};
+use std::collections::HashMap;
+
+/// Run a wasi-crypto function with the state of the instance, returning its errno.
+fn crypto_hostcall(
+    vmctx: &Vmctx,
+    f: impl FnOnce(&mut WasiCryptoCtx, &Guest<'_, '_>) -> Result<(), CryptoErrno>,
+) -> u32 {
+    if !vmctx.contains_embed_ctx::<WasiCryptoCtx>() {
+        return CryptoErrno::NotImplemented as u32;
+    }
+    let memory = LucetMemory::new(vmctx);
+    let mut crypto = vmctx.get_embed_ctx_mut::<WasiCryptoCtx>();
+    match f(&mut crypto, &Guest(&memory)) {
+        Ok(()) => 0,
+        Err(e) => e as u32,
+    }
+}
+
+/// The memory of the guest, which arguments are copied in and out of.
+struct Guest<'a, 'b>(&'a LucetMemory<'b>);
+
+impl<'a, 'b> Guest<'a, 'b> {
+    fn bytes(&self, ptr: u32, len: u32) -> Result<Vec<u8>, CryptoErrno> {
+        let slice = GuestPtr::<u8>::new(self.0, ptr)
+            .as_array(len)
+            .as_slice()
+            .map_err(guest_errno)?;
+        Ok(slice.to_vec())
+    }
+
+    /// Borrow the guest buffer `ptr`, which is `len` bytes long, for the host to write to.
+    fn bytes_mut(&self, ptr: u32, len: u32) -> Result<GuestSlice<'a, u8>, CryptoErrno> {
+        GuestPtr::<u8>::new(self.0, ptr)
+            .as_array(len)
+            .as_slice()
+            .map_err(guest_errno)
+    }
+
+    fn str(&self, ptr: u32, len: u32) -> Result<String, CryptoErrno> {
+        let s = GuestPtr::<str>::new(self.0, (ptr, len))
+            .as_str()
+            .map_err(guest_errno)?;
+        Ok((&*s).to_owned())
+    }
+
+    /// Copy `data` to the guest buffer `ptr`, which is `len` bytes long.
+    fn write_bytes(&self, ptr: u32, len: u32, data: &[u8]) -> Result<(), CryptoErrno> {
+        if data.len() > len as usize {
+            return Err(CryptoErrno::Overflow);
+        }
+        let mut slice = GuestPtr::<u8>::new(self.0, ptr)
+            .as_array(data.len() as u32)
+            .as_slice()
+            .map_err(guest_errno)?;
+        slice.copy_from_slice(data);
+        Ok(())
+    }
+
+    /// Check that a word can be stored at `ptr`, so that a handle is not lost when it is invalid.
+    fn out_ptr(&self, ptr: u32) -> Result<GuestPtr<'a, u32>, CryptoErrno> {
+        let ptr = GuestPtr::<u32>::new(self.0, ptr);
+        ptr.read().map_err(guest_errno)?;
+        Ok(ptr)
+    }
+}
+
+fn guest_errno(_e: GuestError) -> CryptoErrno {
+    CryptoErrno::GuestError
+}
+
+fn write_u32(out: GuestPtr<'_, u32>, value: u32) -> Result<(), CryptoErrno> {
+    out.write(value).map_err(guest_errno)
+}
+
+fn close<T>(table: &mut HashMap<u32, T>, handle: u32) -> Result<(), CryptoErrno> {
+    table
+        .remove(&handle)
+        .map(|_| ())
+        .ok_or(CryptoErrno::InvalidHandle)
+}
+
+impl WasiCryptoCtx {
+    fn options_or_default(&self, options: u32) -> Result<CryptoOptions, CryptoErrno> {
+        Ok(optional(&self.options, options)?
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn give_array_output(&mut self, data: Vec<u8>) -> Result<u32, CryptoErrno> {
+        let handle = self.new_handle()?;
+        self.array_outputs.insert(handle, data);
+        Ok(handle)
+    }
+}
+
+// common
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_options_open(vmctx: &Vmctx, algorithm_type: u32, options_out: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        // signatures, symmetric, and key exchange
+        if algorithm_type > 2 {
+            return Err(CryptoErrno::UnsupportedFeature);
+        }
+        let options_out = guest.out_ptr(options_out)?;
+        let handle = crypto.new_handle()?;
+        crypto.options.insert(handle, CryptoOptions::default());
+        write_u32(options_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_options_set(
+    vmctx: &Vmctx,
+    options: u32,
+    name: u32,
+    name_len: u32,
+    value: u32,
+    value_len: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let name = guest.str(name, name_len)?;
+        let value = guest.bytes(value, value_len)?;
+        crypto
+            .options
+            .get_mut(&options)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .set(name, value);
+        Ok(())
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_options_close(vmctx: &Vmctx, options: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| close(&mut crypto.options, options))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_array_output_len(vmctx: &Vmctx, output: u32, len_out: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let len_out = guest.out_ptr(len_out)?;
+        let len = crypto
+            .array_outputs
+            .get(&output)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .len();
+        write_u32(len_out, len as u32)
+    })
+}
+
+/// Copy an array output to the guest, and close it.
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_array_output_pull(
+    vmctx: &Vmctx,
+    output: u32,
+    buf: u32,
+    buf_len: u32,
+    size_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let size_out = guest.out_ptr(size_out)?;
+        let data = crypto
+            .array_outputs
+            .get(&output)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        guest.write_bytes(buf, buf_len, data)?;
+        let len = data.len();
+        crypto.array_outputs.remove(&output);
+        write_u32(size_out, len as u32)
+    })
+}
+
+// symmetric
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_key_generate(
+    vmctx: &Vmctx,
+    alg: u32,
+    alg_len: u32,
+    options: u32,
+    key_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let alg = guest.str(alg, alg_len)?;
+        let key_out = guest.out_ptr(key_out)?;
+        let options = crypto.options_or_default(options)?;
+        let key = crypto.provider.symmetric_key_generate(&alg, &options)?;
+        let handle = crypto.new_handle()?;
+        crypto.symmetric_keys.insert(handle, key);
+        write_u32(key_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_key_import(
+    vmctx: &Vmctx,
+    alg: u32,
+    alg_len: u32,
+    raw: u32,
+    raw_len: u32,
+    key_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let alg = guest.str(alg, alg_len)?;
+        let raw = guest.bytes(raw, raw_len)?;
+        let key_out = guest.out_ptr(key_out)?;
+        let key = crypto.provider.symmetric_key_import(&alg, &raw)?;
+        let handle = crypto.new_handle()?;
+        crypto.symmetric_keys.insert(handle, key);
+        write_u32(key_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_key_export(vmctx: &Vmctx, key: u32, output_out: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let output_out = guest.out_ptr(output_out)?;
+        let raw = crypto
+            .symmetric_keys
+            .get(&key)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .export()?;
+        let handle = crypto.give_array_output(raw)?;
+        write_u32(output_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_key_close(vmctx: &Vmctx, key: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| close(&mut crypto.symmetric_keys, key))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_state_open(
+    vmctx: &Vmctx,
+    alg: u32,
+    alg_len: u32,
+    key: u32,
+    options: u32,
+    state_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let alg = guest.str(alg, alg_len)?;
+        let state_out = guest.out_ptr(state_out)?;
+        let options = crypto.options_or_default(options)?;
+        let key = optional(&crypto.symmetric_keys, key)?;
+        if let Some(key) = key {
+            if key.algorithm() != alg {
+                return Err(CryptoErrno::InvalidKey);
+            }
+        }
+        let state =
+            crypto
+                .provider
+                .symmetric_state_open(&alg, key.map(|key| key.as_ref()), &options)?;
+        let handle = crypto.new_handle()?;
+        crypto.symmetric_states.insert(handle, state);
+        write_u32(state_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_state_absorb(
+    vmctx: &Vmctx,
+    state: u32,
+    data: u32,
+    data_len: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let data = guest.bytes(data, data_len)?;
+        crypto
+            .symmetric_states
+            .get_mut(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .absorb(&data)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_state_squeeze(
+    vmctx: &Vmctx,
+    state: u32,
+    out: u32,
+    out_len: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let mut out = guest.bytes_mut(out, out_len)?;
+        crypto
+            .symmetric_states
+            .get_mut(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .squeeze(&mut out)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_state_squeeze_tag(
+    vmctx: &Vmctx,
+    state: u32,
+    tag_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let tag_out = guest.out_ptr(tag_out)?;
+        let tag = crypto
+            .symmetric_states
+            .get_mut(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .squeeze_tag()?;
+        let handle = crypto.new_handle()?;
+        crypto.symmetric_tags.insert(handle, tag);
+        write_u32(tag_out, handle)
+    })
+}
+
+/// Encrypt or decrypt with a symmetric state, as `f` does, from the guest buffer `data` into the
+/// guest buffer `out`, each a pointer and a length.
+fn symmetric_crypt(
+    vmctx: &Vmctx,
+    state: u32,
+    (out, out_len): (u32, u32),
+    (data, data_len): (u32, u32),
+    size_out: u32,
+    f: fn(&mut dyn SymmetricState, &mut [u8], &[u8]) -> Result<usize, CryptoErrno>,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let size_out = guest.out_ptr(size_out)?;
+        // the input is copied, as it may overlap the output
+        let data = guest.bytes(data, data_len)?;
+        let state = crypto
+            .symmetric_states
+            .get_mut(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        let size = {
+            let mut out = guest.bytes_mut(out, out_len)?;
+            f(state.as_mut(), &mut out, &data)?
+        };
+        write_u32(size_out, size as u32)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_state_encrypt(
+    vmctx: &Vmctx,
+    state: u32,
+    out: u32,
+    out_len: u32,
+    data: u32,
+    data_len: u32,
+    size_out: u32,
+) -> u32 {
+    symmetric_crypt(
+        vmctx,
+        state,
+        (out, out_len),
+        (data, data_len),
+        size_out,
+        |state, out, data| state.encrypt(out, data),
+    )
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_state_decrypt(
+    vmctx: &Vmctx,
+    state: u32,
+    out: u32,
+    out_len: u32,
+    data: u32,
+    data_len: u32,
+    size_out: u32,
+) -> u32 {
+    symmetric_crypt(
+        vmctx,
+        state,
+        (out, out_len),
+        (data, data_len),
+        size_out,
+        |state, out, data| state.decrypt(out, data),
+    )
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_state_close(vmctx: &Vmctx, state: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| {
+        close(&mut crypto.symmetric_states, state)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_tag_len(vmctx: &Vmctx, tag: u32, len_out: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let len_out = guest.out_ptr(len_out)?;
+        let len = crypto
+            .symmetric_tags
+            .get(&tag)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .len();
+        write_u32(len_out, len as u32)
+    })
+}
+
+/// Copy a tag to the guest, and close it.
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_tag_pull(
+    vmctx: &Vmctx,
+    tag: u32,
+    buf: u32,
+    buf_len: u32,
+    size_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let size_out = guest.out_ptr(size_out)?;
+        let data = crypto
+            .symmetric_tags
+            .get(&tag)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        guest.write_bytes(buf, buf_len, data)?;
+        let len = data.len();
+        crypto.symmetric_tags.remove(&tag);
+        write_u32(size_out, len as u32)
+    })
+}
+
+/// Check a tag against the one the guest expects, in constant time.
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_tag_verify(
+    vmctx: &Vmctx,
+    tag: u32,
+    expected: u32,
+    expected_len: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let expected = guest.bytes(expected, expected_len)?;
+        let tag = crypto
+            .symmetric_tags
+            .get(&tag)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        if constant_time_eq(tag, &expected) {
+            Ok(())
+        } else {
+            Err(CryptoErrno::InvalidTag)
+        }
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_symmetric_tag_close(vmctx: &Vmctx, tag: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| close(&mut crypto.symmetric_tags, tag))
+}
+
+// signatures
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_keypair_generate(
+    vmctx: &Vmctx,
+    alg: u32,
+    alg_len: u32,
+    options: u32,
+    keypair_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let alg = guest.str(alg, alg_len)?;
+        let keypair_out = guest.out_ptr(keypair_out)?;
+        let options = crypto.options_or_default(options)?;
+        let keypair = crypto.provider.signature_keypair_generate(&alg, &options)?;
+        let handle = crypto.new_handle()?;
+        crypto.keypairs.insert(handle, keypair);
+        write_u32(keypair_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_keypair_import(
+    vmctx: &Vmctx,
+    alg: u32,
+    alg_len: u32,
+    encoded: u32,
+    encoded_len: u32,
+    keypair_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let alg = guest.str(alg, alg_len)?;
+        let encoded = guest.bytes(encoded, encoded_len)?;
+        let keypair_out = guest.out_ptr(keypair_out)?;
+        let keypair = crypto.provider.signature_keypair_import(&alg, &encoded)?;
+        let handle = crypto.new_handle()?;
+        crypto.keypairs.insert(handle, keypair);
+        write_u32(keypair_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_keypair_publickey(
+    vmctx: &Vmctx,
+    keypair: u32,
+    publickey_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let publickey_out = guest.out_ptr(publickey_out)?;
+        let publickey = crypto
+            .keypairs
+            .get(&keypair)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .publickey()?;
+        let handle = crypto.new_handle()?;
+        crypto.publickeys.insert(handle, publickey);
+        write_u32(publickey_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_keypair_close(vmctx: &Vmctx, keypair: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| close(&mut crypto.keypairs, keypair))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_publickey_import(
+    vmctx: &Vmctx,
+    alg: u32,
+    alg_len: u32,
+    raw: u32,
+    raw_len: u32,
+    publickey_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let alg = guest.str(alg, alg_len)?;
+        let raw = guest.bytes(raw, raw_len)?;
+        let publickey_out = guest.out_ptr(publickey_out)?;
+        let publickey = crypto.provider.signature_publickey_import(&alg, &raw)?;
+        let handle = crypto.new_handle()?;
+        crypto.publickeys.insert(handle, publickey);
+        write_u32(publickey_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_publickey_export(
+    vmctx: &Vmctx,
+    publickey: u32,
+    output_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let output_out = guest.out_ptr(output_out)?;
+        let raw = crypto
+            .publickeys
+            .get(&publickey)
+            .ok_or(CryptoErrno::InvalidHandle)?
+            .export()?;
+        let handle = crypto.give_array_output(raw)?;
+        write_u32(output_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_publickey_close(vmctx: &Vmctx, publickey: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| close(&mut crypto.publickeys, publickey))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_state_open(vmctx: &Vmctx, keypair: u32, state_out: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let state_out = guest.out_ptr(state_out)?;
+        if !crypto.keypairs.contains_key(&keypair) {
+            return Err(CryptoErrno::InvalidHandle);
+        }
+        let handle = crypto.new_handle()?;
+        crypto.signature_states.insert(handle, (keypair, vec![]));
+        write_u32(state_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_state_update(
+    vmctx: &Vmctx,
+    state: u32,
+    data: u32,
+    data_len: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let data = guest.bytes(data, data_len)?;
+        let (_, msg) = crypto
+            .signature_states
+            .get_mut(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        msg.extend_from_slice(&data);
+        Ok(())
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_state_sign(vmctx: &Vmctx, state: u32, output_out: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let output_out = guest.out_ptr(output_out)?;
+        let (keypair, msg) = crypto
+            .signature_states
+            .get(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        // the key pair may have been closed since the state was opened
+        let signature = crypto
+            .keypairs
+            .get(keypair)
+            .ok_or(CryptoErrno::Closed)?
+            .sign(msg)?;
+        let handle = crypto.give_array_output(signature)?;
+        write_u32(output_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_state_close(vmctx: &Vmctx, state: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| {
+        close(&mut crypto.signature_states, state)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_verification_state_open(
+    vmctx: &Vmctx,
+    publickey: u32,
+    state_out: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let state_out = guest.out_ptr(state_out)?;
+        if !crypto.publickeys.contains_key(&publickey) {
+            return Err(CryptoErrno::InvalidHandle);
+        }
+        let handle = crypto.new_handle()?;
+        crypto
+            .verification_states
+            .insert(handle, (publickey, vec![]));
+        write_u32(state_out, handle)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_verification_state_update(
+    vmctx: &Vmctx,
+    state: u32,
+    data: u32,
+    data_len: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let data = guest.bytes(data, data_len)?;
+        let (_, msg) = crypto
+            .verification_states
+            .get_mut(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        msg.extend_from_slice(&data);
+        Ok(())
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_verification_state_verify(
+    vmctx: &Vmctx,
+    state: u32,
+    signature: u32,
+    signature_len: u32,
+) -> u32 {
+    crypto_hostcall(vmctx, |crypto, guest| {
+        let signature = guest.bytes(signature, signature_len)?;
+        let (publickey, msg) = crypto
+            .verification_states
+            .get(&state)
+            .ok_or(CryptoErrno::InvalidHandle)?;
+        crypto
+            .publickeys
+            .get(publickey)
+            .ok_or(CryptoErrno::Closed)?
+            .verify(msg, &signature)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_crypto_signature_verification_state_close(vmctx: &Vmctx, state: u32) -> u32 {
+    crypto_hostcall(vmctx, |crypto, _| {
+        close(&mut crypto.verification_states, state)
+    })
+}
+
+/// The addresses of the hostcalls.
+pub(super) fn hostcalls() -> Vec<*const extern "C" fn()> {
+    vec![
+        lucet_wasi_crypto_options_open as _,
+        lucet_wasi_crypto_options_set as _,
+        lucet_wasi_crypto_options_close as _,
+        lucet_wasi_crypto_array_output_len as _,
+        lucet_wasi_crypto_array_output_pull as _,
+        lucet_wasi_crypto_symmetric_key_generate as _,
+        lucet_wasi_crypto_symmetric_key_import as _,
+        lucet_wasi_crypto_symmetric_key_export as _,
+        lucet_wasi_crypto_symmetric_key_close as _,
+        lucet_wasi_crypto_symmetric_state_open as _,
+        lucet_wasi_crypto_symmetric_state_absorb as _,
+        lucet_wasi_crypto_symmetric_state_squeeze as _,
+        lucet_wasi_crypto_symmetric_state_squeeze_tag as _,
+        lucet_wasi_crypto_symmetric_state_encrypt as _,
+        lucet_wasi_crypto_symmetric_state_decrypt as _,
+        lucet_wasi_crypto_symmetric_state_close as _,
+        lucet_wasi_crypto_symmetric_tag_len as _,
+        lucet_wasi_crypto_symmetric_tag_pull as _,
+        lucet_wasi_crypto_symmetric_tag_verify as _,
+        lucet_wasi_crypto_symmetric_tag_close as _,
+        lucet_wasi_crypto_signature_keypair_generate as _,
+        lucet_wasi_crypto_signature_keypair_import as _,
+        lucet_wasi_crypto_signature_keypair_publickey as _,
+        lucet_wasi_crypto_signature_keypair_close as _,
+        lucet_wasi_crypto_signature_publickey_import as _,
+        lucet_wasi_crypto_signature_publickey_export as _,
+        lucet_wasi_crypto_signature_publickey_close as _,
+        lucet_wasi_crypto_signature_state_open as _,
+        lucet_wasi_crypto_signature_state_update as _,
+        lucet_wasi_crypto_signature_state_sign as _,
+        lucet_wasi_crypto_signature_state_close as _,
+        lucet_wasi_crypto_signature_verification_state_open as _,
+        lucet_wasi_crypto_signature_verification_state_update as _,
+        lucet_wasi_crypto_signature_verification_state_verify as _,
+        lucet_wasi_crypto_signature_verification_state_close as _,
+    ]
+}
+
+/// Make sure the wasi-crypto hostcalls are linked, like `export_wasi_funcs()` does for the
+/// others.
+pub(crate) fn init() {
+    for func in hostcalls() {
+        assert_ne!(func, std::ptr::null(), "hostcall address is not null");
+    }
+}
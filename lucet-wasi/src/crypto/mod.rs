@@ -0,0 +1,372 @@
+//! An implementation of the symmetric and signature APIs of wasi-crypto, with which guests use
+//! keys without holding them.
+//!
+//! Keys, and the states of operations on them, live in the host: the guest only sees handles to
+//! them, and raw key material only crosses into the guest when it is exported, which a provider
+//! can refuse. The operations are implemented by a [`CryptoProvider`](trait.CryptoProvider.html),
+//! which is [`RingProvider`](struct.RingProvider.html) unless the embedder provides its own, and
+//! which is added to the instance in a [`WasiCryptoCtx`](struct.WasiCryptoCtx.html) embedder
+//! context:
+//!
+//! ```no_run
+//! # use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+//! # use lucet_wasi::{RingProvider, WasiCryptoCtx};
+//! # let module = DlModule::load("sign.so").unwrap();
+//! # let region = MmapRegion::create(1, &Limits::default()).unwrap();
+//! let mut inst = region
+//!     .new_instance_builder(module)
+//!     .with_embed_ctx(WasiCryptoCtx::new(RingProvider::new()))
+//!     .build()
+//!     .unwrap();
+//! ```
+//!
+//! Guests compiled with [`crypto_bindings()`](fn.crypto_bindings.html) can import the functions
+//! of the `lucet_wasi_crypto_common`, `lucet_wasi_crypto_symmetric`, and
+//! `lucet_wasi_crypto_signatures` modules, which return a `CryptoErrno`, or 0 on success.
+//! They follow the proposal, with these simplifications, which is why they are not in the
+//! proposal's `wasi_ephemeral_crypto_*` modules: a guest built for the proposal fails to link
+//! rather than calling them with the wrong arguments.
+//!
+//! - An optional handle, such as the key and options of `symmetric_state_open()`, is passed as
+//!   `u32::MAX` when it is absent.
+//! - Signatures are passed as bytes rather than handles: `signature_state_sign()` returns an
+//!   array output of the signature, and `signature_verification_state_verify()` takes a pointer
+//!   and length.
+//! - Key pairs are imported from, and public keys exported to, the raw encodings of the
+//!   provider, and key pairs cannot be exported at all.
+//!
+//! The functions fail with `CryptoErrno::NotImplemented` in an instance without a
+//! `WasiCryptoCtx`.
+
+mod hostcalls;
+mod ring_provider;
+
+pub use ring_provider::RingProvider;
+
+use lucet_module::bindings::Bindings;
+use std::any::Any;
+use std::collections::HashMap;
+
+/// The errors of the wasi-crypto functions, with the values the guest sees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum CryptoErrno {
+    /// A pointer or length from the guest is invalid.
+    GuestError = 1,
+    NotImplemented = 2,
+    UnsupportedFeature = 3,
+    /// The operation is not allowed, such as exporting a key the provider keeps secret.
+    ProhibitedOperation = 4,
+    UnsupportedEncoding = 5,
+    UnsupportedAlgorithm = 6,
+    UnsupportedOption = 7,
+    InvalidKey = 8,
+    InvalidLength = 9,
+    VerificationFailed = 10,
+    RngError = 11,
+    AlgorithmFailure = 12,
+    InvalidSignature = 13,
+    Closed = 14,
+    InvalidHandle = 15,
+    /// An output does not fit in the buffer the guest gave for it.
+    Overflow = 16,
+    InternalError = 17,
+    TooManyHandles = 18,
+    KeyNotSupported = 19,
+    KeyRequired = 20,
+    InvalidTag = 21,
+    /// The operation is not one that the algorithm of the state supports.
+    InvalidOperation = 22,
+    NonceRequired = 23,
+    InvalidNonce = 24,
+    OptionNotSet = 25,
+}
+
+/// The options that a guest sets for an operation, by name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CryptoOptions {
+    values: HashMap<String, Vec<u8>>,
+}
+
+impl CryptoOptions {
+    /// The value of the option `name`, if the guest set it.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.values.get(name).map(Vec::as_slice)
+    }
+
+    pub(crate) fn set(&mut self, name: String, value: Vec<u8>) {
+        self.values.insert(name, value);
+    }
+}
+
+/// The implementation of the cryptographic operations of guests.
+///
+/// The algorithms are named as in wasi-crypto, such as `"HMAC/SHA-256"` or `"Ed25519"`; a
+/// provider fails with `CryptoErrno::UnsupportedAlgorithm` on those it does not implement.
+pub trait CryptoProvider: 'static {
+    /// Generate a symmetric key for the algorithm `alg`.
+    fn symmetric_key_generate(
+        &mut self,
+        alg: &str,
+        options: &CryptoOptions,
+    ) -> Result<Box<dyn SymmetricKey>, CryptoErrno>;
+
+    /// Import the raw bytes of a symmetric key for the algorithm `alg`.
+    fn symmetric_key_import(
+        &mut self,
+        alg: &str,
+        raw: &[u8],
+    ) -> Result<Box<dyn SymmetricKey>, CryptoErrno>;
+
+    /// Start an operation of the algorithm `alg`, with a key if it needs one.
+    fn symmetric_state_open(
+        &mut self,
+        alg: &str,
+        key: Option<&dyn SymmetricKey>,
+        options: &CryptoOptions,
+    ) -> Result<Box<dyn SymmetricState>, CryptoErrno>;
+
+    /// Generate a key pair for the signature algorithm `alg`.
+    fn signature_keypair_generate(
+        &mut self,
+        alg: &str,
+        options: &CryptoOptions,
+    ) -> Result<Box<dyn SignatureKeypair>, CryptoErrno>;
+
+    /// Import a key pair for the signature algorithm `alg`, in the encoding of the provider.
+    fn signature_keypair_import(
+        &mut self,
+        alg: &str,
+        encoded: &[u8],
+    ) -> Result<Box<dyn SignatureKeypair>, CryptoErrno>;
+
+    /// Import the raw bytes of a public key for the signature algorithm `alg`.
+    fn signature_publickey_import(
+        &mut self,
+        alg: &str,
+        raw: &[u8],
+    ) -> Result<Box<dyn SignaturePublicKey>, CryptoErrno>;
+}
+
+/// A symmetric key, which stays in the host unless it is exported.
+pub trait SymmetricKey: 'static {
+    /// The algorithm the key is for.
+    fn algorithm(&self) -> &str;
+
+    /// The raw bytes of the key, for the guest, or `CryptoErrno::ProhibitedOperation` if the
+    /// provider does not let keys leave the host.
+    fn export(&self) -> Result<Vec<u8>, CryptoErrno>;
+
+    /// The key as `Any`, so that its provider can downcast it to its own type.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// An operation of a symmetric algorithm, such as a hash, a MAC, or an AEAD cipher.
+///
+/// Each algorithm supports some of the methods, which fail with `CryptoErrno::InvalidOperation`
+/// unless it does.
+pub trait SymmetricState: 'static {
+    /// Add data to the state: the message of a hash or MAC, or the associated data of a cipher.
+    fn absorb(&mut self, _data: &[u8]) -> Result<(), CryptoErrno> {
+        Err(CryptoErrno::InvalidOperation)
+    }
+
+    /// Fill `out` with the output of a hash.
+    fn squeeze(&mut self, _out: &mut [u8]) -> Result<(), CryptoErrno> {
+        Err(CryptoErrno::InvalidOperation)
+    }
+
+    /// The authentication tag of a MAC.
+    fn squeeze_tag(&mut self) -> Result<Vec<u8>, CryptoErrno> {
+        Err(CryptoErrno::InvalidOperation)
+    }
+
+    /// Encrypt `data` into `out`, followed by its tag, returning the number of bytes written.
+    fn encrypt(&mut self, _out: &mut [u8], _data: &[u8]) -> Result<usize, CryptoErrno> {
+        Err(CryptoErrno::InvalidOperation)
+    }
+
+    /// Decrypt and authenticate `data`, which ends with its tag, into `out`, returning the number
+    /// of bytes written.
+    fn decrypt(&mut self, _out: &mut [u8], _data: &[u8]) -> Result<usize, CryptoErrno> {
+        Err(CryptoErrno::InvalidOperation)
+    }
+}
+
+/// A key pair for signing, whose secret key never leaves the host.
+pub trait SignatureKeypair: 'static {
+    /// The public key of the pair.
+    fn publickey(&self) -> Result<Box<dyn SignaturePublicKey>, CryptoErrno>;
+
+    /// Sign the message `msg`.
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, CryptoErrno>;
+}
+
+/// A public key for verifying signatures.
+pub trait SignaturePublicKey: 'static {
+    /// The raw bytes of the key.
+    fn export(&self) -> Result<Vec<u8>, CryptoErrno>;
+
+    /// Check that `signature` is a signature of `msg`, failing with
+    /// `CryptoErrno::VerificationFailed` if it is not.
+    fn verify(&self, msg: &[u8], signature: &[u8]) -> Result<(), CryptoErrno>;
+}
+
+/// The wasi-crypto state of an instance, to add to it as an embedder context.
+pub struct WasiCryptoCtx {
+    provider: Box<dyn CryptoProvider>,
+    /// The last handle given to the guest; handles of all kinds are numbered together, so that
+    /// one kind is never mistaken for another.
+    last_handle: u32,
+    options: HashMap<u32, CryptoOptions>,
+    array_outputs: HashMap<u32, Vec<u8>>,
+    symmetric_keys: HashMap<u32, Box<dyn SymmetricKey>>,
+    symmetric_states: HashMap<u32, Box<dyn SymmetricState>>,
+    symmetric_tags: HashMap<u32, Vec<u8>>,
+    keypairs: HashMap<u32, Box<dyn SignatureKeypair>>,
+    publickeys: HashMap<u32, Box<dyn SignaturePublicKey>>,
+    /// The key pair and message of each signature state, which is signed at once.
+    signature_states: HashMap<u32, (u32, Vec<u8>)>,
+    /// The public key and message of each verification state.
+    verification_states: HashMap<u32, (u32, Vec<u8>)>,
+}
+
+impl WasiCryptoCtx {
+    /// Create the state of an instance whose cryptographic operations are implemented by
+    /// `provider`.
+    pub fn new(provider: impl CryptoProvider) -> Self {
+        WasiCryptoCtx {
+            provider: Box::new(provider),
+            last_handle: 0,
+            options: HashMap::new(),
+            array_outputs: HashMap::new(),
+            symmetric_keys: HashMap::new(),
+            symmetric_states: HashMap::new(),
+            symmetric_tags: HashMap::new(),
+            keypairs: HashMap::new(),
+            publickeys: HashMap::new(),
+            signature_states: HashMap::new(),
+            verification_states: HashMap::new(),
+        }
+    }
+
+    /// A handle that has not been given to the guest.
+    fn new_handle(&mut self) -> Result<u32, CryptoErrno> {
+        // `u32::MAX` is the absent handle
+        if self.last_handle == u32::MAX - 1 {
+            return Err(CryptoErrno::TooManyHandles);
+        }
+        self.last_handle += 1;
+        Ok(self.last_handle)
+    }
+}
+
+/// Take the value of the optional `handle` from `table`.
+fn optional<T>(table: &HashMap<u32, T>, handle: u32) -> Result<Option<&T>, CryptoErrno> {
+    if handle == u32::MAX {
+        return Ok(None);
+    }
+    table
+        .get(&handle)
+        .map(Some)
+        .ok_or(CryptoErrno::InvalidHandle)
+}
+
+/// Compare two byte strings in time that depends only on their lengths.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The functions of each wasi-crypto module.
+const FUNCS: &[(&str, &[&str])] = &[
+    (
+        "lucet_wasi_crypto_common",
+        &[
+            "options_open",
+            "options_set",
+            "options_close",
+            "array_output_len",
+            "array_output_pull",
+        ],
+    ),
+    (
+        "lucet_wasi_crypto_symmetric",
+        &[
+            "symmetric_key_generate",
+            "symmetric_key_import",
+            "symmetric_key_export",
+            "symmetric_key_close",
+            "symmetric_state_open",
+            "symmetric_state_absorb",
+            "symmetric_state_squeeze",
+            "symmetric_state_squeeze_tag",
+            "symmetric_state_encrypt",
+            "symmetric_state_decrypt",
+            "symmetric_state_close",
+            "symmetric_tag_len",
+            "symmetric_tag_pull",
+            "symmetric_tag_verify",
+            "symmetric_tag_close",
+        ],
+    ),
+    (
+        "lucet_wasi_crypto_signatures",
+        &[
+            "signature_keypair_generate",
+            "signature_keypair_import",
+            "signature_keypair_publickey",
+            "signature_keypair_close",
+            "signature_publickey_import",
+            "signature_publickey_export",
+            "signature_publickey_close",
+            "signature_state_open",
+            "signature_state_update",
+            "signature_state_sign",
+            "signature_state_close",
+            "signature_verification_state_open",
+            "signature_verification_state_update",
+            "signature_verification_state_verify",
+            "signature_verification_state_close",
+        ],
+    ),
+];
+
+/// The bindings for the wasi-crypto functions, which guests that import them must be compiled
+/// with, in addition to [`bindings()`](../fn.bindings.html).
+pub fn crypto_bindings() -> Bindings {
+    let bindings = FUNCS
+        .iter()
+        .map(|(module, names)| {
+            let funcs = names
+                .iter()
+                .map(|name| ((*name).to_owned(), format!("lucet_wasi_crypto_{}", name)))
+                .collect();
+            ((*module).to_owned(), funcs)
+        })
+        .collect();
+    Bindings::new(bindings)
+}
+
+pub(crate) use hostcalls::init;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings_cover_every_hostcall() {
+        let count: usize = crypto_bindings()
+            .hash_map()
+            .values()
+            .map(|funcs| funcs.len())
+            .sum();
+        assert_eq!(count, hostcalls::hostcalls().len());
+    }
+
+    #[test]
+    fn constant_time_eq_compares_lengths() {
+        assert!(constant_time_eq(b"tag", b"tag"));
+        assert!(!constant_time_eq(b"tag", b"tab"));
+        assert!(!constant_time_eq(b"tag", b"tags"));
+    }
+}
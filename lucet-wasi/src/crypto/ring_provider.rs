@@ -0,0 +1,467 @@
+//! The default provider of wasi-crypto, implemented with `ring`.
+
+use super::{
+    CryptoErrno, CryptoOptions, CryptoProvider, SignatureKeypair, SignaturePublicKey, SymmetricKey,
+    SymmetricState,
+};
+use ring::rand::SecureRandom;
+use ring::signature::KeyPair;
+use ring::{aead, digest, hmac, rand, signature};
+use std::any::Any;
+
+/// A provider of the algorithms of `ring`:
+///
+/// - the hashes `"SHA-256"` and `"SHA-512"`;
+/// - the MACs `"HMAC/SHA-256"` and `"HMAC/SHA-512"`;
+/// - the AEAD ciphers `"AES-128-GCM"`, `"AES-256-GCM"`, and `"CHACHA20-POLY1305"`, whose nonce is
+///   the `"nonce"` option of the state, and which each encrypt or decrypt once, so that a nonce
+///   is never reused;
+/// - the signatures `"Ed25519"` and `"ECDSA_P256_SHA256"`, whose key pairs are imported from
+///   PKCS#8 and whose public keys are in their raw encodings.
+///
+/// Symmetric keys cannot be exported to the guest unless `allow_key_export()` is called.
+#[derive(Debug)]
+pub struct RingProvider {
+    rng: rand::SystemRandom,
+    key_export: bool,
+}
+
+impl Default for RingProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RingProvider {
+    /// Create a provider that keeps symmetric keys in the host.
+    pub fn new() -> Self {
+        RingProvider {
+            rng: rand::SystemRandom::new(),
+            key_export: false,
+        }
+    }
+
+    /// Let the guest export the raw bytes of its symmetric keys.
+    pub fn allow_key_export(mut self) -> Self {
+        self.key_export = true;
+        self
+    }
+}
+
+#[derive(Clone, Copy)]
+enum SymmetricAlg {
+    Hash(&'static digest::Algorithm),
+    Hmac(hmac::Algorithm),
+    Aead(&'static aead::Algorithm),
+}
+
+impl SymmetricAlg {
+    fn from_name(name: &str) -> Result<Self, CryptoErrno> {
+        match name {
+            "SHA-256" => Ok(SymmetricAlg::Hash(&digest::SHA256)),
+            "SHA-512" => Ok(SymmetricAlg::Hash(&digest::SHA512)),
+            "HMAC/SHA-256" => Ok(SymmetricAlg::Hmac(hmac::HMAC_SHA256)),
+            "HMAC/SHA-512" => Ok(SymmetricAlg::Hmac(hmac::HMAC_SHA512)),
+            "AES-128-GCM" => Ok(SymmetricAlg::Aead(&aead::AES_128_GCM)),
+            "AES-256-GCM" => Ok(SymmetricAlg::Aead(&aead::AES_256_GCM)),
+            "CHACHA20-POLY1305" => Ok(SymmetricAlg::Aead(&aead::CHACHA20_POLY1305)),
+            _ => Err(CryptoErrno::UnsupportedAlgorithm),
+        }
+    }
+
+    /// The length of the keys that are generated for the algorithm.
+    fn key_len(self) -> Result<usize, CryptoErrno> {
+        match self {
+            SymmetricAlg::Hash(_) => Err(CryptoErrno::KeyNotSupported),
+            SymmetricAlg::Hmac(alg) => Ok(alg.digest_algorithm().output_len),
+            SymmetricAlg::Aead(alg) => Ok(alg.key_len()),
+        }
+    }
+}
+
+struct RingSymmetricKey {
+    alg: String,
+    raw: Vec<u8>,
+    exportable: bool,
+}
+
+impl SymmetricKey for RingSymmetricKey {
+    fn algorithm(&self) -> &str {
+        &self.alg
+    }
+
+    fn export(&self) -> Result<Vec<u8>, CryptoErrno> {
+        if self.exportable {
+            Ok(self.raw.clone())
+        } else {
+            Err(CryptoErrno::ProhibitedOperation)
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+struct HashState(digest::Context);
+
+impl SymmetricState for HashState {
+    fn absorb(&mut self, data: &[u8]) -> Result<(), CryptoErrno> {
+        self.0.update(data);
+        Ok(())
+    }
+
+    fn squeeze(&mut self, out: &mut [u8]) -> Result<(), CryptoErrno> {
+        let digest = self.0.clone().finish();
+        let digest = digest.as_ref();
+        if out.len() > digest.len() {
+            return Err(CryptoErrno::InvalidLength);
+        }
+        let len = out.len();
+        out.copy_from_slice(&digest[..len]);
+        Ok(())
+    }
+}
+
+struct HmacState(hmac::Context);
+
+impl SymmetricState for HmacState {
+    fn absorb(&mut self, data: &[u8]) -> Result<(), CryptoErrno> {
+        self.0.update(data);
+        Ok(())
+    }
+
+    fn squeeze_tag(&mut self) -> Result<Vec<u8>, CryptoErrno> {
+        Ok(self.0.clone().sign().as_ref().to_vec())
+    }
+}
+
+struct AeadState {
+    key: aead::LessSafeKey,
+    /// The nonce, until it is used.
+    nonce: Option<[u8; aead::NONCE_LEN]>,
+    aad: Vec<u8>,
+}
+
+impl AeadState {
+    fn nonce(&mut self) -> Result<aead::Nonce, CryptoErrno> {
+        let nonce = self.nonce.take().ok_or(CryptoErrno::NonceRequired)?;
+        Ok(aead::Nonce::assume_unique_for_key(nonce))
+    }
+}
+
+impl SymmetricState for AeadState {
+    fn absorb(&mut self, data: &[u8]) -> Result<(), CryptoErrno> {
+        self.aad.extend_from_slice(data);
+        Ok(())
+    }
+
+    fn encrypt(&mut self, out: &mut [u8], data: &[u8]) -> Result<usize, CryptoErrno> {
+        let len = data.len() + self.key.algorithm().tag_len();
+        if out.len() < len {
+            return Err(CryptoErrno::Overflow);
+        }
+        let nonce = self.nonce()?;
+        let mut in_out = data.to_vec();
+        self.key
+            .seal_in_place_append_tag(nonce, aead::Aad::from(&self.aad), &mut in_out)
+            .map_err(|_| CryptoErrno::AlgorithmFailure)?;
+        out[..len].copy_from_slice(&in_out);
+        Ok(len)
+    }
+
+    fn decrypt(&mut self, out: &mut [u8], data: &[u8]) -> Result<usize, CryptoErrno> {
+        let len = data
+            .len()
+            .checked_sub(self.key.algorithm().tag_len())
+            .ok_or(CryptoErrno::InvalidLength)?;
+        if out.len() < len {
+            return Err(CryptoErrno::Overflow);
+        }
+        let nonce = self.nonce()?;
+        let mut in_out = data.to_vec();
+        let plaintext = self
+            .key
+            .open_in_place(nonce, aead::Aad::from(&self.aad), &mut in_out)
+            .map_err(|_| CryptoErrno::InvalidTag)?;
+        out[..len].copy_from_slice(plaintext);
+        Ok(len)
+    }
+}
+
+enum RingKeypair {
+    Ed25519(signature::Ed25519KeyPair),
+    EcdsaP256(signature::EcdsaKeyPair, rand::SystemRandom),
+}
+
+impl SignatureKeypair for RingKeypair {
+    fn publickey(&self) -> Result<Box<dyn SignaturePublicKey>, CryptoErrno> {
+        let (alg, raw): (&'static dyn signature::VerificationAlgorithm, &[u8]) = match self {
+            RingKeypair::Ed25519(kp) => (&signature::ED25519, kp.public_key().as_ref()),
+            RingKeypair::EcdsaP256(kp, _) => (
+                &signature::ECDSA_P256_SHA256_FIXED,
+                kp.public_key().as_ref(),
+            ),
+        };
+        Ok(Box::new(RingPublicKey {
+            alg,
+            raw: raw.to_vec(),
+        }))
+    }
+
+    fn sign(&self, msg: &[u8]) -> Result<Vec<u8>, CryptoErrno> {
+        match self {
+            RingKeypair::Ed25519(kp) => Ok(kp.sign(msg).as_ref().to_vec()),
+            RingKeypair::EcdsaP256(kp, rng) => kp
+                .sign(rng, msg)
+                .map(|sig| sig.as_ref().to_vec())
+                .map_err(|_| CryptoErrno::AlgorithmFailure),
+        }
+    }
+}
+
+struct RingPublicKey {
+    alg: &'static dyn signature::VerificationAlgorithm,
+    raw: Vec<u8>,
+}
+
+impl SignaturePublicKey for RingPublicKey {
+    fn export(&self) -> Result<Vec<u8>, CryptoErrno> {
+        Ok(self.raw.clone())
+    }
+
+    fn verify(&self, msg: &[u8], sig: &[u8]) -> Result<(), CryptoErrno> {
+        signature::UnparsedPublicKey::new(self.alg, &self.raw)
+            .verify(msg, sig)
+            .map_err(|_| CryptoErrno::VerificationFailed)
+    }
+}
+
+impl RingProvider {
+    fn keypair_from_pkcs8(&self, alg: &str, pkcs8: &[u8]) -> Result<RingKeypair, CryptoErrno> {
+        match alg {
+            "Ed25519" => signature::Ed25519KeyPair::from_pkcs8(pkcs8)
+                .map(RingKeypair::Ed25519)
+                .map_err(|_| CryptoErrno::InvalidKey),
+            "ECDSA_P256_SHA256" => signature::EcdsaKeyPair::from_pkcs8(
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                pkcs8,
+            )
+            .map(|kp| RingKeypair::EcdsaP256(kp, self.rng.clone()))
+            .map_err(|_| CryptoErrno::InvalidKey),
+            _ => Err(CryptoErrno::UnsupportedAlgorithm),
+        }
+    }
+}
+
+impl CryptoProvider for RingProvider {
+    fn symmetric_key_generate(
+        &mut self,
+        alg: &str,
+        _options: &CryptoOptions,
+    ) -> Result<Box<dyn SymmetricKey>, CryptoErrno> {
+        let mut raw = vec![0; SymmetricAlg::from_name(alg)?.key_len()?];
+        self.rng.fill(&mut raw).map_err(|_| CryptoErrno::RngError)?;
+        Ok(Box::new(RingSymmetricKey {
+            alg: alg.to_owned(),
+            raw,
+            exportable: self.key_export,
+        }))
+    }
+
+    fn symmetric_key_import(
+        &mut self,
+        alg: &str,
+        raw: &[u8],
+    ) -> Result<Box<dyn SymmetricKey>, CryptoErrno> {
+        let sym_alg = SymmetricAlg::from_name(alg)?;
+        let key_len = sym_alg.key_len()?;
+        // HMAC keys can be of any length, but cipher keys cannot
+        if let SymmetricAlg::Aead(_) = sym_alg {
+            if raw.len() != key_len {
+                return Err(CryptoErrno::InvalidKey);
+            }
+        }
+        Ok(Box::new(RingSymmetricKey {
+            alg: alg.to_owned(),
+            raw: raw.to_vec(),
+            exportable: self.key_export,
+        }))
+    }
+
+    fn symmetric_state_open(
+        &mut self,
+        alg: &str,
+        key: Option<&dyn SymmetricKey>,
+        options: &CryptoOptions,
+    ) -> Result<Box<dyn SymmetricState>, CryptoErrno> {
+        let key = match key {
+            Some(key) => Some(
+                key.as_any()
+                    .downcast_ref::<RingSymmetricKey>()
+                    .ok_or(CryptoErrno::InvalidKey)?,
+            ),
+            None => None,
+        };
+        match (SymmetricAlg::from_name(alg)?, key) {
+            (SymmetricAlg::Hash(alg), None) => Ok(Box::new(HashState(digest::Context::new(alg)))),
+            (SymmetricAlg::Hash(_), Some(_)) => Err(CryptoErrno::KeyNotSupported),
+            (SymmetricAlg::Hmac(alg), Some(key)) => Ok(Box::new(HmacState(
+                hmac::Context::with_key(&hmac::Key::new(alg, &key.raw)),
+            ))),
+            (SymmetricAlg::Aead(alg), Some(key)) => {
+                let unbound =
+                    aead::UnboundKey::new(alg, &key.raw).map_err(|_| CryptoErrno::InvalidKey)?;
+                let nonce = options.get("nonce").ok_or(CryptoErrno::NonceRequired)?;
+                let mut fixed = [0; aead::NONCE_LEN];
+                if nonce.len() != fixed.len() {
+                    return Err(CryptoErrno::InvalidNonce);
+                }
+                fixed.copy_from_slice(nonce);
+                Ok(Box::new(AeadState {
+                    key: aead::LessSafeKey::new(unbound),
+                    nonce: Some(fixed),
+                    aad: vec![],
+                }))
+            }
+            (_, None) => Err(CryptoErrno::KeyRequired),
+        }
+    }
+
+    fn signature_keypair_generate(
+        &mut self,
+        alg: &str,
+        _options: &CryptoOptions,
+    ) -> Result<Box<dyn SignatureKeypair>, CryptoErrno> {
+        let pkcs8 = match alg {
+            "Ed25519" => signature::Ed25519KeyPair::generate_pkcs8(&self.rng),
+            "ECDSA_P256_SHA256" => signature::EcdsaKeyPair::generate_pkcs8(
+                &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+                &self.rng,
+            ),
+            _ => return Err(CryptoErrno::UnsupportedAlgorithm),
+        }
+        .map_err(|_| CryptoErrno::RngError)?;
+        Ok(Box::new(self.keypair_from_pkcs8(alg, pkcs8.as_ref())?))
+    }
+
+    fn signature_keypair_import(
+        &mut self,
+        alg: &str,
+        encoded: &[u8],
+    ) -> Result<Box<dyn SignatureKeypair>, CryptoErrno> {
+        Ok(Box::new(self.keypair_from_pkcs8(alg, encoded)?))
+    }
+
+    fn signature_publickey_import(
+        &mut self,
+        alg: &str,
+        raw: &[u8],
+    ) -> Result<Box<dyn SignaturePublicKey>, CryptoErrno> {
+        let (alg, len): (&'static dyn signature::VerificationAlgorithm, _) = match alg {
+            "Ed25519" => (&signature::ED25519, 32),
+            // an uncompressed point
+            "ECDSA_P256_SHA256" => (&signature::ECDSA_P256_SHA256_FIXED, 65),
+            _ => return Err(CryptoErrno::UnsupportedAlgorithm),
+        };
+        if raw.len() != len {
+            return Err(CryptoErrno::InvalidKey);
+        }
+        Ok(Box::new(RingPublicKey {
+            alg,
+            raw: raw.to_vec(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nonce_options() -> CryptoOptions {
+        let mut options = CryptoOptions::default();
+        options.set("nonce".to_owned(), vec![7; aead::NONCE_LEN]);
+        options
+    }
+
+    #[test]
+    fn aead_round_trip() {
+        let mut provider = RingProvider::new();
+        let key = provider
+            .symmetric_key_generate("AES-256-GCM", &CryptoOptions::default())
+            .unwrap();
+        assert_eq!(key.export(), Err(CryptoErrno::ProhibitedOperation));
+
+        let mut state = provider
+            .symmetric_state_open("AES-256-GCM", Some(key.as_ref()), &nonce_options())
+            .unwrap();
+        state.absorb(b"header").unwrap();
+        let mut ciphertext = [0; 4 + 16];
+        assert_eq!(state.encrypt(&mut ciphertext, b"data"), Ok(20));
+        // the nonce cannot be used again
+        assert_eq!(
+            state.encrypt(&mut ciphertext, b"data"),
+            Err(CryptoErrno::NonceRequired)
+        );
+
+        let mut state = provider
+            .symmetric_state_open("AES-256-GCM", Some(key.as_ref()), &nonce_options())
+            .unwrap();
+        state.absorb(b"header").unwrap();
+        let mut plaintext = [0; 4];
+        assert_eq!(state.decrypt(&mut plaintext, &ciphertext), Ok(4));
+        assert_eq!(&plaintext, b"data");
+
+        let mut state = provider
+            .symmetric_state_open("AES-256-GCM", Some(key.as_ref()), &nonce_options())
+            .unwrap();
+        assert_eq!(
+            state.decrypt(&mut plaintext, &ciphertext),
+            Err(CryptoErrno::InvalidTag)
+        );
+    }
+
+    #[test]
+    fn states_check_their_keys() {
+        let mut provider = RingProvider::new().allow_key_export();
+        let options = CryptoOptions::default();
+        let key = provider
+            .symmetric_key_import("HMAC/SHA-256", b"secret")
+            .unwrap();
+        assert_eq!(key.export().unwrap(), b"secret");
+        assert_eq!(
+            provider
+                .symmetric_state_open("SHA-256", Some(key.as_ref()), &options)
+                .err(),
+            Some(CryptoErrno::KeyNotSupported)
+        );
+        assert_eq!(
+            provider
+                .symmetric_state_open("HMAC/SHA-256", None, &options)
+                .err(),
+            Some(CryptoErrno::KeyRequired)
+        );
+        assert_eq!(
+            provider.symmetric_key_import("AES-128-GCM", b"short").err(),
+            Some(CryptoErrno::InvalidKey)
+        );
+    }
+
+    #[test]
+    fn ecdsa_sign_and_verify() {
+        let mut provider = RingProvider::new();
+        let keypair = provider
+            .signature_keypair_generate("ECDSA_P256_SHA256", &CryptoOptions::default())
+            .unwrap();
+        let signature = keypair.sign(b"message").unwrap();
+        let raw = keypair.publickey().unwrap().export().unwrap();
+        let publickey = provider
+            .signature_publickey_import("ECDSA_P256_SHA256", &raw)
+            .unwrap();
+        assert_eq!(publickey.verify(b"message", &signature), Ok(()));
+        assert_eq!(
+            publickey.verify(b"massage", &signature),
+            Err(CryptoErrno::VerificationFailed)
+        );
+    }
+}
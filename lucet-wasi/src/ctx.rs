@@ -0,0 +1,1183 @@
+//! The WASI context of an instance, which extends that of `wasi-common` with per-preopen
+//! capabilities.
+
+use crate::capture::{OutputCapture, OutputStream};
+use crate::clocks::WasiClock;
+use crate::deterministic::{DeterministicEnv, SeededRng};
+use crate::runtime::types;
+use crate::sockets::{Socket, SocketTable};
+use crate::temp_dir::TempDir;
+use crate::trace::{HostcallTrace, Tracer};
+use crate::vfs::{gather, VirtualFs};
+use rand::RngCore;
+use std::borrow::Borrow;
+use std::cell::{Cell, RefCell, RefMut};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::ops::Deref;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use wasi_common::wasi::wasi_snapshot_preview1::WasiSnapshotPreview1;
+use wasi_common::WasiCtxBuilderError;
+
+/// What a guest may do in a preopened directory, and in everything it opens through it.
+///
+/// A directory added with
+/// [`WasiCtxBuilder::preopened_dir()`](struct.WasiCtxBuilder.html#method.preopened_dir) has every
+/// capability; the capabilities of one added with
+/// [`preopened_dir_with_caps()`](struct.WasiCtxBuilder.html#method.preopened_dir_with_caps) can be
+/// narrowed:
+///
+/// ```no_run
+/// # use lucet_wasi::{PreopenCaps, WasiCtxBuilder};
+/// # use std::fs::File;
+/// let assets = File::open("/srv/assets").unwrap();
+/// let ctx = WasiCtxBuilder::new()
+///     .preopened_dir_with_caps(assets, "/assets", PreopenCaps::new().read_only())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PreopenCaps {
+    write: bool,
+    create: bool,
+    follow_symlinks: bool,
+}
+
+impl Default for PreopenCaps {
+    fn default() -> Self {
+        PreopenCaps {
+            write: true,
+            create: true,
+            follow_symlinks: true,
+        }
+    }
+}
+
+impl PreopenCaps {
+    /// Create the capabilities of a directory preopened with `preopened_dir()`, which are all of
+    /// them.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Do not allow the guest to change anything in the directory: files can only be opened for
+    /// reading, and nothing can be created, removed, renamed, or have its size or timestamps
+    /// changed.
+    pub fn read_only(mut self) -> Self {
+        self.write = false;
+        self.create = false;
+        self
+    }
+
+    /// Do not allow the guest to create files, directories, links, or symbolic links in the
+    /// directory, or to rename anything into it. Existing files can still be written to and
+    /// removed.
+    pub fn no_create(mut self) -> Self {
+        self.create = false;
+        self
+    }
+
+    /// Do not follow a symbolic link that is the last component of a path in the directory, even
+    /// if the guest asks to, so that opening or inspecting it fails or applies to the link
+    /// itself.
+    ///
+    /// Symbolic links in the other components of a path are still followed, within the
+    /// directory.
+    pub fn no_symlink_follow(mut self) -> Self {
+        self.follow_symlinks = false;
+        self
+    }
+
+    /// Whether files in the directory can be changed.
+    pub fn can_write(&self) -> bool {
+        self.write
+    }
+
+    /// Whether files and directories can be created in the directory.
+    pub fn can_create(&self) -> bool {
+        self.create
+    }
+
+    /// Whether a symbolic link in the last component of a path is followed.
+    pub fn follows_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    /// The rights that file descriptors in the directory lose.
+    fn denied_rights(&self) -> types::Rights {
+        use types::Rights as R;
+        let mut denied = types::Rights::EMPTY_FLAGS;
+        if !self.create {
+            denied = denied
+                | R::PATH_CREATE_DIRECTORY
+                | R::PATH_CREATE_FILE
+                | R::PATH_LINK_TARGET
+                | R::PATH_RENAME_TARGET
+                | R::PATH_SYMLINK;
+        }
+        if !self.write {
+            denied = denied
+                | R::FD_WRITE
+                | R::FD_ALLOCATE
+                | R::FD_FILESTAT_SET_SIZE
+                | R::FD_FILESTAT_SET_TIMES
+                | R::PATH_LINK_SOURCE
+                | R::PATH_RENAME_SOURCE
+                | R::PATH_FILESTAT_SET_SIZE
+                | R::PATH_FILESTAT_SET_TIMES
+                | R::PATH_REMOVE_DIRECTORY
+                | R::PATH_UNLINK_FILE;
+        }
+        denied
+    }
+}
+
+/// How a guest may use symbolic links and absolute paths, in every directory it has.
+///
+/// By default, a guest can follow and create symbolic links, and cannot use absolute paths. A
+/// context for a less trusted guest can narrow this with
+/// [`WasiCtxBuilder::path_policy()`](struct.WasiCtxBuilder.html#method.path_policy):
+///
+/// ```no_run
+/// # use lucet_wasi::{PathPolicy, WasiCtxBuilder};
+/// let ctx = WasiCtxBuilder::new()
+///     .path_policy(PathPolicy::new().no_symlink_follow().no_symlink_create())
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PathPolicy {
+    follow_symlinks: bool,
+    create_symlinks: bool,
+    absolute_paths: bool,
+}
+
+impl Default for PathPolicy {
+    fn default() -> Self {
+        PathPolicy {
+            follow_symlinks: true,
+            create_symlinks: true,
+            absolute_paths: false,
+        }
+    }
+}
+
+impl PathPolicy {
+    /// Create the default policy.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Do not follow a symbolic link that is the last component of a path, even if the guest asks
+    /// to, as `PreopenCaps::no_symlink_follow()` does for every directory: opening one fails with
+    /// `Errno::Loop`, and inspecting one applies to the link itself.
+    pub fn no_symlink_follow(mut self) -> Self {
+        self.follow_symlinks = false;
+        self
+    }
+
+    /// Do not allow the guest to create symbolic links, so that `path_symlink()` fails with
+    /// `Errno::Perm`.
+    pub fn no_symlink_create(mut self) -> Self {
+        self.create_symlinks = false;
+        self
+    }
+
+    /// Resolve an absolute path relative to the directory it is looked up in, as if its leading
+    /// slashes were removed, instead of refusing it with `Errno::Notcapable`.
+    ///
+    /// The path still cannot lead out of the directory, and the contents of symbolic links are
+    /// not affected.
+    pub fn allow_absolute_paths(mut self) -> Self {
+        self.absolute_paths = true;
+        self
+    }
+
+    /// Whether a symbolic link in the last component of a path may be followed.
+    pub fn follows_symlinks(&self) -> bool {
+        self.follow_symlinks
+    }
+
+    /// Whether the guest can create symbolic links.
+    pub fn can_create_symlinks(&self) -> bool {
+        self.create_symlinks
+    }
+
+    /// Whether absolute paths are resolved rather than refused.
+    pub fn allows_absolute_paths(&self) -> bool {
+        self.absolute_paths
+    }
+}
+
+/// Limits on the I/O of a guest over the lifetime of its context, to contain a guest that abuses a
+/// host it shares with others.
+///
+/// Unlike `WasiCtxBuilder::max_open_fds()`, which bounds how many files are open at once, these
+/// are totals that closing files does not give back. A hostcall that would go over one fails with
+/// `Errno::Dquot`, without doing any of its I/O:
+///
+/// ```no_run
+/// # use lucet_wasi::{WasiCtxBuilder, WasiQuota};
+/// let ctx = WasiCtxBuilder::new()
+///     .quota(WasiQuota::new().max_bytes_written(1 << 20).max_files_opened(100))
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// `max_file_bytes_written()` and `max_files_created()` only count what reaches the filesystem of
+/// the host, to keep a guest from filling its disk through a writable preopened directory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WasiQuota {
+    max_bytes_written: Option<u64>,
+    max_files_opened: Option<u64>,
+    max_file_bytes_written: Option<u64>,
+    max_files_created: Option<u64>,
+}
+
+impl WasiQuota {
+    /// Create a quota with no limits.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the bytes the guest writes to files, sockets, and its standard streams, with
+    /// `fd_write()`, `fd_pwrite()`, and `sock_send()`.
+    pub fn max_bytes_written(mut self, max: u64) -> Self {
+        self.max_bytes_written = Some(max);
+        self
+    }
+
+    /// Limit how many times the guest opens a file or directory with `path_open()`.
+    pub fn max_files_opened(mut self, max: u64) -> Self {
+        self.max_files_opened = Some(max);
+        self
+    }
+
+    /// Limit the bytes the guest writes to files of the host with `fd_write()` and `fd_pwrite()`,
+    /// and reserves for them with `fd_allocate()`, which counts in full.
+    pub fn max_file_bytes_written(mut self, max: u64) -> Self {
+        self.max_file_bytes_written = Some(max);
+        self
+    }
+
+    /// Limit how many files, directories, and symbolic links the guest creates in the
+    /// directories of the host.
+    pub fn max_files_created(mut self, max: u64) -> Self {
+        self.max_files_created = Some(max);
+        self
+    }
+}
+
+/// Where one of the standard streams of a guest goes.
+///
+/// Each stream is `Null` unless the builder is told otherwise, so that a guest cannot reach the
+/// host's terminal by accident.
+#[derive(Debug)]
+pub enum StdioPolicy {
+    /// The corresponding stream of the host process.
+    Inherit,
+    /// The null device, which reads as empty and discards writes.
+    Null,
+    /// A file, typically one end of a pipe whose other end the host manages, as created by
+    /// `input_pipe()` or `output_pipe()`.
+    Pipe(File),
+}
+
+impl Default for StdioPolicy {
+    fn default() -> Self {
+        StdioPolicy::Null
+    }
+}
+
+impl StdioPolicy {
+    /// Create a pipe for the guest to read from as stdin, returning its policy and the end that
+    /// the host writes to.
+    pub fn input_pipe() -> io::Result<(Self, File)> {
+        let (read, write) = pipe()?;
+        Ok((StdioPolicy::Pipe(read), write))
+    }
+
+    /// Create a pipe for the guest to write to as stdout or stderr, returning its policy and the
+    /// end that the host reads from.
+    pub fn output_pipe() -> io::Result<(Self, File)> {
+        let (read, write) = pipe()?;
+        Ok((StdioPolicy::Pipe(write), read))
+    }
+
+    /// Take the policy to build a context with, leaving a file behind as `Null` since it can only
+    /// be given to one context.
+    fn take(&mut self) -> Self {
+        match self {
+            StdioPolicy::Inherit => StdioPolicy::Inherit,
+            _ => std::mem::replace(self, StdioPolicy::Null),
+        }
+    }
+}
+
+/// Create a pipe, returning its read and write ends, which are closed on exec.
+fn pipe() -> io::Result<(File, File)> {
+    let mut fds = [0; 2];
+    #[cfg(target_os = "linux")]
+    let res = unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) };
+    // without `pipe2()`, the ends are marked right after they are created
+    #[cfg(not(target_os = "linux"))]
+    let res = unsafe {
+        let res = libc::pipe(fds.as_mut_ptr());
+        if res == 0 {
+            for fd in &fds {
+                libc::fcntl(*fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            }
+        }
+        res
+    };
+    if res != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) })
+}
+
+/// A builder for a `WasiCtx`.
+///
+/// This wraps the builder of `wasi-common`, whose methods it shares.
+pub struct WasiCtxBuilder {
+    inner: wasi_common::WasiCtxBuilder,
+    /// The capabilities of each preopened directory, in the order they were added.
+    preopen_caps: Vec<PreopenCaps>,
+    stdin: StdioPolicy,
+    stdout: StdioPolicy,
+    stderr: StdioPolicy,
+    stdout_capture: Option<OutputCapture>,
+    stderr_capture: Option<OutputCapture>,
+    sockets: Vec<Socket>,
+    connect_allowlist: Vec<SocketAddr>,
+    clock: Option<Box<dyn WasiClock>>,
+    rng: Option<Box<dyn RngCore>>,
+    deterministic_env: Option<DeterministicEnv>,
+    max_open_fds: Option<usize>,
+    path_policy: PathPolicy,
+    async_io: bool,
+    quota: WasiQuota,
+    read_only_fs: bool,
+    tracer: Option<Tracer>,
+    max_args_env_size: Option<usize>,
+    temp_dir: Option<TempDir>,
+}
+
+impl Default for WasiCtxBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WasiCtxBuilder {
+    /// Create a builder for a context with no arguments, environment variables, or preopened
+    /// directories.
+    pub fn new() -> Self {
+        WasiCtxBuilder {
+            inner: wasi_common::WasiCtxBuilder::new(),
+            preopen_caps: vec![],
+            stdin: StdioPolicy::Null,
+            stdout: StdioPolicy::Null,
+            stderr: StdioPolicy::Null,
+            stdout_capture: None,
+            stderr_capture: None,
+            sockets: vec![],
+            connect_allowlist: vec![],
+            clock: None,
+            rng: None,
+            deterministic_env: None,
+            max_open_fds: None,
+            path_policy: PathPolicy::new(),
+            async_io: false,
+            quota: WasiQuota::new(),
+            read_only_fs: false,
+            tracer: None,
+            max_args_env_size: None,
+            temp_dir: None,
+        }
+    }
+
+    /// Add arguments to the command-line arguments list.
+    pub fn args<S: AsRef<[u8]>>(&mut self, args: impl IntoIterator<Item = S>) -> &mut Self {
+        self.inner.args(args);
+        self
+    }
+
+    /// Add an argument to the command-line arguments list.
+    pub fn arg<S: AsRef<[u8]>>(&mut self, arg: S) -> &mut Self {
+        self.inner.arg(arg);
+        self
+    }
+
+    /// Inherit the command-line arguments from the host process.
+    pub fn inherit_args(&mut self) -> &mut Self {
+        self.inner.inherit_args();
+        self
+    }
+
+    /// Inherit stdin, stdout, and stderr from the host process.
+    pub fn inherit_stdio(&mut self) -> &mut Self {
+        self.stdin_policy(StdioPolicy::Inherit)
+            .stdout_policy(StdioPolicy::Inherit)
+            .stderr_policy(StdioPolicy::Inherit)
+    }
+
+    /// Set where stdin comes from.
+    pub fn stdin_policy(&mut self, policy: StdioPolicy) -> &mut Self {
+        self.stdin = policy;
+        self
+    }
+
+    /// Set where stdout goes.
+    pub fn stdout_policy(&mut self, policy: StdioPolicy) -> &mut Self {
+        self.stdout = policy;
+        self
+    }
+
+    /// Set where stderr goes.
+    pub fn stderr_policy(&mut self, policy: StdioPolicy) -> &mut Self {
+        self.stderr = policy;
+        self
+    }
+
+    /// Inherit the environment variables from the host process.
+    pub fn inherit_env(&mut self) -> &mut Self {
+        self.inner.inherit_env();
+        self
+    }
+
+    /// Inherit only the environment variables of the host process whose names match one of
+    /// `patterns`.
+    ///
+    /// A pattern is either the name of a variable, or a prefix followed by `*`, so that `["PATH",
+    /// "LC_*"]` inherits `PATH` and the locale variables.
+    pub fn inherit_env_matching<S: AsRef<str>>(
+        &mut self,
+        patterns: impl IntoIterator<Item = S>,
+    ) -> &mut Self {
+        let patterns = patterns.into_iter().collect::<Vec<_>>();
+        let matches = |name: &[u8]| {
+            patterns.iter().any(|pattern| {
+                let pattern = pattern.as_ref().as_bytes();
+                match pattern.split_last() {
+                    Some((b'*', prefix)) => name.starts_with(prefix),
+                    _ => name == pattern,
+                }
+            })
+        };
+        for (k, v) in std::env::vars_os() {
+            if matches(k.as_bytes()) {
+                self.inner.env(k.as_bytes(), v.as_bytes());
+            }
+        }
+        self
+    }
+
+    /// Limit the total size of the command-line arguments and environment variables, counting
+    /// each with the NUL byte that terminates it, as `args_sizes_get()` and `environ_sizes_get()`
+    /// report them.
+    ///
+    /// Over the limit, the guest cannot read its arguments or environment, which fails with
+    /// `Errno::TooBig` as `execve()` would with `E2BIG`.
+    pub fn max_args_env_size(&mut self, max: usize) -> &mut Self {
+        self.max_args_env_size = Some(max);
+        self
+    }
+
+    /// Add an entry to the environment.
+    pub fn env<S: AsRef<[u8]>>(&mut self, k: S, v: S) -> &mut Self {
+        self.inner.env(k, v);
+        self
+    }
+
+    /// Add entries to the environment.
+    pub fn envs<K: AsRef<[u8]>, V: AsRef<[u8]>, T: Borrow<(K, V)>>(
+        &mut self,
+        envs: impl IntoIterator<Item = T>,
+    ) -> &mut Self {
+        self.inner.envs(envs);
+        self
+    }
+
+    /// Provide a file to use as stdin.
+    pub fn stdin(&mut self, file: File) -> &mut Self {
+        self.stdin_policy(StdioPolicy::Pipe(file))
+    }
+
+    /// Provide a file to use as stdout.
+    pub fn stdout(&mut self, file: File) -> &mut Self {
+        self.stdout_policy(StdioPolicy::Pipe(file))
+    }
+
+    /// Provide a file to use as stderr.
+    pub fn stderr(&mut self, file: File) -> &mut Self {
+        self.stderr_policy(StdioPolicy::Pipe(file))
+    }
+
+    /// Capture what the guest writes to stdout, instead of writing it to a file.
+    ///
+    /// Keep a clone of `capture` to read the output.
+    pub fn capture_stdout(&mut self, capture: OutputCapture) -> &mut Self {
+        self.stdout_capture = Some(capture);
+        self
+    }
+
+    /// Capture what the guest writes to stderr, instead of writing it to a file.
+    ///
+    /// Keep a clone of `capture` to read the output.
+    pub fn capture_stderr(&mut self, capture: OutputCapture) -> &mut Self {
+        self.stderr_capture = Some(capture);
+        self
+    }
+
+    /// Call `f` with each write the guest makes to stdout or stderr as soon as it makes it, along
+    /// with `instance`, which identifies the instance to the embedder, and the stream.
+    ///
+    /// This replaces the captures of both streams with `OutputCapture::callback()`s, so the guest
+    /// is blocked in its write until `f` returns. A stream that is renumbered keeps its identity,
+    /// so writes to stdout renumbered over stderr are still reported as `OutputStream::Stdout`.
+    pub fn stream_output<F>(&mut self, instance: impl Into<String>, f: F) -> &mut Self
+    where
+        F: FnMut(&str, OutputStream, &[u8]) + Send + 'static,
+    {
+        let instance: Arc<str> = Arc::from(instance.into());
+        let f = Arc::new(Mutex::new(f));
+        let capture = |stream| {
+            let (instance, f) = (instance.clone(), f.clone());
+            OutputCapture::callback(move |data| {
+                let mut f = f.lock().unwrap();
+                (*f)(&*instance, stream, data)
+            })
+        };
+        self.capture_stdout(capture(OutputStream::Stdout))
+            .capture_stderr(capture(OutputStream::Stderr))
+    }
+
+    /// Preopen a directory with every capability, at `guest_path` in the guest.
+    pub fn preopened_dir<P: AsRef<Path>>(&mut self, dir: File, guest_path: P) -> &mut Self {
+        self.preopened_dir_with_caps(dir, guest_path, PreopenCaps::new())
+    }
+
+    /// Preopen a directory with the given capabilities, at `guest_path` in the guest.
+    pub fn preopened_dir_with_caps<P: AsRef<Path>>(
+        &mut self,
+        dir: File,
+        guest_path: P,
+        caps: PreopenCaps,
+    ) -> &mut Self {
+        self.inner.preopened_dir(dir, guest_path);
+        self.preopen_caps.push(caps);
+        self
+    }
+
+    /// Preopen the host directory at `host_path` with every capability, at `guest_path` in the
+    /// guest, for guests that expect their files at fixed absolute paths.
+    ///
+    /// This fails if the host directory cannot be opened, or is not a directory.
+    pub fn map_dir<G: AsRef<Path>, H: AsRef<Path>>(
+        &mut self,
+        guest_path: G,
+        host_path: H,
+    ) -> io::Result<&mut Self> {
+        let dir = File::open(host_path.as_ref())?;
+        if !dir.metadata()?.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a directory", host_path.as_ref().display()),
+            ));
+        }
+        Ok(self.preopened_dir(dir, guest_path))
+    }
+
+    /// Provide the guest with an empty directory of its own at `guest_path`, which is created in
+    /// the temporary directory of the host, and deleted with everything in it when the context is
+    /// dropped along with its instance.
+    ///
+    /// The files in it can take up at most `max_bytes` in all, and writes that would go over fail
+    /// with `Errno::Dquot`. This fails if the directory cannot be created, or if the context has
+    /// one already.
+    pub fn temp_dir<P: AsRef<Path>>(
+        &mut self,
+        guest_path: P,
+        max_bytes: u64,
+    ) -> io::Result<&mut Self> {
+        if self.temp_dir.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the context has a temporary directory already",
+            ));
+        }
+        // the preopens follow stdio, in the order they are added
+        let preopen = types::Fd::from(3 + self.preopen_caps.len() as u32);
+        let temp_dir = TempDir::create(preopen, max_bytes)?;
+        let dir = File::open(temp_dir.path())?;
+        self.temp_dir = Some(temp_dir);
+        Ok(self.preopened_dir(dir, guest_path))
+    }
+
+    /// Give the guest a socket, such as a listener to accept connections on.
+    ///
+    /// The sockets are given file descriptors from `FIRST_SOCKET_FD` on, in the order they are
+    /// added.
+    pub fn preopened_socket(&mut self, socket: impl Into<Socket>) -> &mut Self {
+        self.sockets.push(socket.into());
+        self
+    }
+
+    /// Allow the guest to connect to `addr`, or to any port of its IP address if its port is 0.
+    pub fn allow_connect(&mut self, addr: SocketAddr) -> &mut Self {
+        self.connect_allowlist.push(addr);
+        self
+    }
+
+    /// Read the clocks of the guest from `clock`, instead of from the host.
+    pub fn clock(&mut self, clock: impl WasiClock) -> &mut Self {
+        self.clock = Some(Box::new(clock));
+        self
+    }
+
+    /// Generate the random bytes of the guest with `rng`, instead of the host's secure generator.
+    pub fn rng(&mut self, rng: impl RngCore + 'static) -> &mut Self {
+        self.rng = Some(Box::new(rng));
+        self
+    }
+
+    /// Generate the random bytes of the guest with a `SeededRng` seeded with `seed`, so that they
+    /// are the same in every run.
+    pub fn random_seed(&mut self, seed: u64) -> &mut Self {
+        self.rng(SeededRng::new(seed))
+    }
+
+    /// Serve the clocks and random bytes of instances in deterministic mode from `env`, if the
+    /// instance has no `DeterministicEnv` of its own as an embedder context.
+    ///
+    /// Without one, they are served from `DeterministicEnv::default()`.
+    pub fn deterministic_env(&mut self, env: DeterministicEnv) -> &mut Self {
+        self.deterministic_env = Some(env);
+        self
+    }
+
+    /// Limit the number of file descriptors that the guest can have open at once, including its
+    /// stdio, preopens, and sockets, so that opening a file or socket beyond it fails with
+    /// `Errno::Mfile`.
+    ///
+    /// Files of a `VirtualFs`, which use no file descriptors of the host, do not count.
+    pub fn max_open_fds(&mut self, max_open_fds: usize) -> &mut Self {
+        self.max_open_fds = Some(max_open_fds);
+        self
+    }
+
+    /// Set how the guest may use symbolic links and absolute paths.
+    pub fn path_policy(&mut self, policy: PathPolicy) -> &mut Self {
+        self.path_policy = policy;
+        self
+    }
+
+    /// Make every directory the guest has read-only, whatever the capabilities it was preopened
+    /// with, as if it were on a read-only filesystem.
+    ///
+    /// Every operation that would change a file or directory fails with `Errno::Rofs`: opening a
+    /// file to create, truncate, or write to it, creating, linking, renaming, or removing
+    /// anything, and changing the size or timestamps of a file. The standard streams and sockets
+    /// can still be written to.
+    pub fn read_only_fs(&mut self) -> &mut Self {
+        self.read_only_fs = true;
+        self
+    }
+
+    /// Limit the I/O of the guest over the lifetime of the context.
+    pub fn quota(&mut self, quota: WasiQuota) -> &mut Self {
+        self.quota = quota;
+        self
+    }
+
+    /// Make the instance yield an [`IoWait`](struct.IoWait.html) to the embedder whenever the
+    /// guest would block on a socket, rather than blocking the thread.
+    pub fn async_io(&mut self) -> &mut Self {
+        self.async_io = true;
+        self
+    }
+
+    /// Trace the WASI calls of the guest, handing each to `sink` as it returns.
+    ///
+    /// The arguments are traced as the raw values the guest passed, so pointers are addresses in
+    /// its memory. `proc_exit()` does not return, so it is not traced.
+    pub fn trace(&mut self, sink: impl Fn(&HostcallTrace<'_>) + 'static) -> &mut Self {
+        self.tracer = Some(Tracer::new(Box::new(sink)));
+        self
+    }
+
+    /// Trace the WASI calls of the guest to `out`, one per line, as `strace` would.
+    pub fn trace_to(&mut self, out: impl Write + 'static) -> &mut Self {
+        self.tracer = Some(Tracer::to_writer(out));
+        self
+    }
+
+    /// Build the context.
+    pub fn build(&mut self) -> Result<WasiCtx, WasiCtxBuilderError> {
+        let mut stdio_fds = HashMap::new();
+        for (fd, policy) in (0..).zip([&self.stdin, &self.stdout, &self.stderr].iter()) {
+            match policy {
+                StdioPolicy::Inherit => stdio_fds.insert(fd, fd as RawFd),
+                StdioPolicy::Pipe(file) => stdio_fds.insert(fd, file.as_raw_fd()),
+                StdioPolicy::Null => None,
+            };
+        }
+        // the streams of `inner` are the null device unless they are set
+        match self.stdin.take() {
+            StdioPolicy::Inherit => self.inner.inherit_stdin(),
+            StdioPolicy::Null => &mut self.inner,
+            StdioPolicy::Pipe(file) => self.inner.stdin(file),
+        };
+        match self.stdout.take() {
+            StdioPolicy::Inherit => self.inner.inherit_stdout(),
+            StdioPolicy::Null => &mut self.inner,
+            StdioPolicy::Pipe(file) => self.inner.stdout(file),
+        };
+        match self.stderr.take() {
+            StdioPolicy::Inherit => self.inner.inherit_stderr(),
+            StdioPolicy::Null => &mut self.inner,
+            StdioPolicy::Pipe(file) => self.inner.stderr(file),
+        };
+        let inner = self.inner.build()?;
+        let args_env_too_big = self.max_args_env_size.map_or(false, |max| {
+            let (_, args_size) = inner.args_sizes_get().expect("arguments have a size");
+            let (_, env_size) = inner.environ_sizes_get().expect("environment has a size");
+            args_size as usize + env_size as usize > max
+        });
+        let mut nofollow = HashSet::new();
+        // the preopens follow stdio, in the order they were added
+        for (fd, caps) in (3..).zip(self.preopen_caps.iter()) {
+            let fd = types::Fd::from(fd);
+            let denied = caps.denied_rights();
+            if denied != types::Rights::EMPTY_FLAGS {
+                let fdstat = inner
+                    .fd_fdstat_get(fd)
+                    .expect("preopened directories follow stdio");
+                inner
+                    .fd_fdstat_set_rights(
+                        fd,
+                        fdstat.fs_rights_base & !denied,
+                        fdstat.fs_rights_inheriting & !denied,
+                    )
+                    .expect("rights can be dropped");
+            }
+            if !caps.follows_symlinks() {
+                nofollow.insert(u32::from(fd));
+            }
+        }
+        let captures: HashMap<u32, OutputCapture> = (1..)
+            .zip([&self.stdout_capture, &self.stderr_capture].iter())
+            .filter_map(|(fd, capture)| capture.as_ref().map(|capture| (fd, capture.clone())))
+            .collect();
+        // writes to a captured stream never block, so it is not polled
+        for fd in captures.keys() {
+            stdio_fds.remove(fd);
+        }
+        let open_fds = 3 + self.preopen_caps.len() + self.sockets.len();
+        Ok(WasiCtx {
+            inner,
+            nofollow: RefCell::new(nofollow),
+            captures: RefCell::new(captures),
+            stdio_fds: RefCell::new(stdio_fds),
+            stdio: RefCell::new((0..3).collect()),
+            sockets: RefCell::new(SocketTable::new(
+                std::mem::replace(&mut self.sockets, vec![]),
+                self.connect_allowlist.clone(),
+            )),
+            clock: self.clock.take(),
+            rng: self.rng.take().map(RefCell::new),
+            deterministic_env: self.deterministic_env.take().unwrap_or_default(),
+            open_fds: Cell::new(open_fds),
+            highest_fd: Cell::new(2 + self.preopen_caps.len() as u32),
+            renumbered_fds: RefCell::new(None),
+            max_open_fds: self.max_open_fds,
+            path_policy: self.path_policy,
+            async_io: self.async_io,
+            quota: self.quota,
+            read_only_fs: self.read_only_fs,
+            tracer: self.tracer.take(),
+            args_env_too_big,
+            temp_dir: self.temp_dir.take(),
+            bytes_written: Cell::new(0),
+            files_opened: Cell::new(0),
+            file_bytes_written: Cell::new(0),
+            files_created: Cell::new(0),
+        })
+    }
+}
+
+/// The WASI context of an instance, to add to it as an embedder context.
+///
+/// This dereferences to the context of `wasi-common`, which implements the hostcalls. It is a
+/// distinct type from `wasi_common::WasiCtx`, so the hostcalls do not find a context of
+/// `wasi-common` added to an instance directly; build this one with
+/// [`WasiCtxBuilder`](struct.WasiCtxBuilder.html), which has the same methods as the builder of
+/// `wasi-common`.
+pub struct WasiCtx {
+    inner: wasi_common::WasiCtx,
+    /// The file descriptors in directories preopened with `PreopenCaps::no_symlink_follow()`.
+    nofollow: RefCell<HashSet<u32>>,
+    /// The captures that writes to stdout and stderr go to, by the file descriptors that now
+    /// refer to them.
+    captures: RefCell<HashMap<u32, OutputCapture>>,
+    /// The file descriptors of the host behind the standard streams that are not the null device,
+    /// which `poll_oneoff()` polls, by the file descriptors that now refer to them.
+    stdio_fds: RefCell<HashMap<u32, RawFd>>,
+    /// The file descriptors that refer to the standard streams, which are not files.
+    stdio: RefCell<HashSet<u32>>,
+    sockets: RefCell<SocketTable>,
+    clock: Option<Box<dyn WasiClock>>,
+    rng: Option<RefCell<Box<dyn RngCore>>>,
+    deterministic_env: DeterministicEnv,
+    /// The number of file descriptors of the host that the guest has open.
+    open_fds: Cell<usize>,
+    /// The highest file descriptor of the host that has been opened.
+    highest_fd: Cell<u32>,
+    /// The guest's file descriptors, numbered apart from those of the host once the guest has
+    /// renumbered a preopen, which `wasi-common` refuses to, if the instance has no `VirtualFs`
+    /// to number them.
+    renumbered_fds: RefCell<Option<VirtualFs>>,
+    max_open_fds: Option<usize>,
+    path_policy: PathPolicy,
+    async_io: bool,
+    quota: WasiQuota,
+    read_only_fs: bool,
+    /// The I/O the guest has done, counted against `quota`.
+    bytes_written: Cell<u64>,
+    files_opened: Cell<u64>,
+    file_bytes_written: Cell<u64>,
+    files_created: Cell<u64>,
+    tracer: Option<Tracer>,
+    /// Whether the arguments and environment are over `WasiCtxBuilder::max_args_env_size()`.
+    args_env_too_big: bool,
+    temp_dir: Option<TempDir>,
+}
+
+impl WasiCtx {
+    /// Create a context with the given command-line arguments, and nothing else.
+    pub fn new<S: AsRef<[u8]>>(
+        args: impl IntoIterator<Item = S>,
+    ) -> Result<Self, WasiCtxBuilderError> {
+        WasiCtxBuilder::new().args(args).build()
+    }
+
+    /// Give the guest a socket while it runs, returning its file descriptor, which the embedder
+    /// must tell the guest about.
+    ///
+    /// This counts towards the limit set with `WasiCtxBuilder::max_open_fds()`, but is not refused
+    /// by it.
+    pub fn grant_socket(&self, socket: impl Into<Socket>) -> types::Fd {
+        self.open_fds.set(self.open_fds.get() + 1);
+        self.sockets.borrow_mut().insert(socket.into())
+    }
+
+    /// The directory of the host that was created for the guest with
+    /// `WasiCtxBuilder::temp_dir()`, which is deleted when the context is dropped.
+    pub fn temp_dir(&self) -> Option<&Path> {
+        self.temp_dir.as_ref().map(TempDir::path)
+    }
+
+    /// The temporary directory of the guest, if the host file descriptor `fd` refers to a file in
+    /// it.
+    pub(crate) fn temp_dir_containing(&self, fd: types::Fd) -> Option<&TempDir> {
+        self.temp_dir
+            .as_ref()
+            .filter(|temp_dir| temp_dir.contains_fd(fd))
+    }
+
+    /// The number of file descriptors the guest has open, not counting those of a `VirtualFs`.
+    pub fn open_fds(&self) -> usize {
+        self.open_fds.get()
+    }
+
+    /// The number of bytes the guest has written, counted against `WasiQuota::max_bytes_written()`.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written.get()
+    }
+
+    /// The number of files the guest has opened, counted against `WasiQuota::max_files_opened()`.
+    pub fn files_opened(&self) -> u64 {
+        self.files_opened.get()
+    }
+
+    /// The number of bytes the guest has written to files of the host, counted against
+    /// `WasiQuota::max_file_bytes_written()`.
+    pub fn file_bytes_written(&self) -> u64 {
+        self.file_bytes_written.get()
+    }
+
+    /// The number of files the guest has created on the host, counted against
+    /// `WasiQuota::max_files_created()`.
+    pub fn files_created(&self) -> u64 {
+        self.files_created.get()
+    }
+
+    /// Turn tracing of the guest's WASI calls off or back on, which only has an effect if the
+    /// context was built to trace them.
+    pub fn set_tracing(&self, enabled: bool) {
+        if let Some(tracer) = &self.tracer {
+            tracer.set_enabled(enabled);
+        }
+    }
+
+    /// Whether the guest's WASI calls are traced right now.
+    pub fn is_tracing(&self) -> bool {
+        self.tracer.as_ref().map_or(false, Tracer::enabled)
+    }
+
+    /// Check that the guest can write another `len` bytes within its quota.
+    pub(crate) fn check_write_quota(&self, len: u64) -> Result<(), types::Errno> {
+        match self.quota.max_bytes_written {
+            Some(max) if self.bytes_written.get().saturating_add(len) > max => {
+                Err(types::Errno::Dquot)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn wrote(&self, len: u64) {
+        self.bytes_written.set(self.bytes_written.get() + len);
+    }
+
+    /// Check that the guest can read its arguments and environment.
+    pub(crate) fn check_args_env_size(&self) -> Result<(), types::Errno> {
+        if self.args_env_too_big {
+            Err(types::Errno::TooBig)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check that the guest can open another file within its quota.
+    pub(crate) fn check_open_quota(&self) -> Result<(), types::Errno> {
+        match self.quota.max_files_opened {
+            Some(max) if self.files_opened.get() >= max => Err(types::Errno::Dquot),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn opened_file(&self) {
+        self.files_opened.set(self.files_opened.get() + 1);
+    }
+
+    /// Check that the guest can write another `len` bytes to files of the host within its quota.
+    pub(crate) fn check_file_write_quota(&self, len: u64) -> Result<(), types::Errno> {
+        match self.quota.max_file_bytes_written {
+            Some(max) if self.file_bytes_written.get().saturating_add(len) > max => {
+                Err(types::Errno::Dquot)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn wrote_to_file(&self, len: u64) {
+        self.file_bytes_written
+            .set(self.file_bytes_written.get() + len);
+    }
+
+    /// Check that the guest can create another file on the host within its quota.
+    pub(crate) fn check_create_quota(&self) -> Result<(), types::Errno> {
+        match self.quota.max_files_created {
+            Some(max) if self.files_created.get() >= max => Err(types::Errno::Dquot),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn created_file(&self) {
+        self.files_created.set(self.files_created.get() + 1);
+    }
+
+    /// Check that the guest can open another file descriptor.
+    pub(crate) fn check_fd_limit(&self) -> Result<(), types::Errno> {
+        match self.max_open_fds {
+            Some(max) if self.open_fds.get() >= max => Err(types::Errno::Mfile),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn close_socket(&self, fd: types::Fd) -> Result<(), types::Errno> {
+        self.sockets.borrow_mut().close(fd)?;
+        self.open_fds.set(self.open_fds.get() - 1);
+        Ok(())
+    }
+
+    /// Move the socket `from` over the socket `to`, closing it.
+    pub(crate) fn renumber_socket(
+        &self,
+        from: types::Fd,
+        to: types::Fd,
+    ) -> Result<(), types::Errno> {
+        self.sockets.borrow_mut().renumber(from, to)?;
+        if from != to {
+            self.open_fds.set(self.open_fds.get() - 1);
+        }
+        Ok(())
+    }
+
+    /// The clock the guest reads instead of the host's, if it has one.
+    pub(crate) fn clock(&self) -> Option<&dyn WasiClock> {
+        self.clock.as_deref()
+    }
+
+    /// The generator of the guest's random bytes, if it does not use the host's.
+    pub(crate) fn rng(&self) -> Option<RefMut<Box<dyn RngCore>>> {
+        self.rng.as_ref().map(RefCell::borrow_mut)
+    }
+
+    /// The clocks and random bytes of the guest in deterministic mode, if the instance has no
+    /// `DeterministicEnv` of its own.
+    pub(crate) fn deterministic_env(&self) -> &DeterministicEnv {
+        &self.deterministic_env
+    }
+
+    pub(crate) fn path_policy(&self) -> PathPolicy {
+        self.path_policy
+    }
+
+    pub(crate) fn read_only_fs(&self) -> bool {
+        self.read_only_fs
+    }
+
+    pub(crate) fn async_io(&self) -> bool {
+        self.async_io
+    }
+
+    pub(crate) fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// The highest file descriptor of the host that has been opened, which may since be closed.
+    pub(crate) fn highest_fd(&self) -> u32 {
+        self.highest_fd.get()
+    }
+
+    /// The table that numbers the guest's file descriptors once it has renumbered a preopen,
+    /// without a `VirtualFs`.
+    pub(crate) fn renumbered_fds(&self) -> &RefCell<Option<VirtualFs>> {
+        &self.renumbered_fds
+    }
+
+    /// Check whether `fd` is a socket.
+    pub(crate) fn is_socket(&self, fd: types::Fd) -> bool {
+        self.sockets.borrow().contains(fd)
+    }
+
+    pub(crate) fn sockets(&self) -> RefMut<SocketTable> {
+        self.sockets.borrow_mut()
+    }
+
+    /// The lookup flags to resolve a path relative to `dirfd` with, in place of those the guest
+    /// asked for.
+    pub(crate) fn lookupflags(
+        &self,
+        dirfd: types::Fd,
+        flags: types::Lookupflags,
+    ) -> types::Lookupflags {
+        if !self.path_policy.follows_symlinks()
+            || self.nofollow.borrow().contains(&u32::from(dirfd))
+        {
+            flags & !types::Lookupflags::SYMLINK_FOLLOW
+        } else {
+            flags
+        }
+    }
+
+    /// Record that `fd` was opened relative to `dirfd`, so has the same capabilities.
+    pub(crate) fn fd_opened(&self, dirfd: types::Fd, fd: types::Fd) {
+        self.open_fds.set(self.open_fds.get() + 1);
+        self.highest_fd
+            .set(std::cmp::max(self.highest_fd.get(), u32::from(fd)));
+        let mut nofollow = self.nofollow.borrow_mut();
+        if nofollow.contains(&u32::from(dirfd)) {
+            nofollow.insert(u32::from(fd));
+        }
+        if let Some(temp_dir) = &self.temp_dir {
+            temp_dir.fd_opened(dirfd, fd);
+        }
+    }
+
+    /// Write to `fd` if it is captured, returning `None` if it is not.
+    pub(crate) fn write_captured(
+        &self,
+        fd: types::Fd,
+        ciovs: &types::CiovecArray<'_>,
+    ) -> Option<Result<types::Size, types::Errno>> {
+        let capture = self.captures.borrow().get(&u32::from(fd)).cloned()?;
+        Some(self.fd_fdstat_get(fd).and_then(|fdstat| {
+            if !fdstat.fs_rights_base.contains(&types::Rights::FD_WRITE) {
+                return Err(types::Errno::Notcapable);
+            }
+            let data = gather(ciovs)?;
+            capture.write(&data);
+            Ok(data.len() as types::Size)
+        }))
+    }
+
+    /// Check whether `fd` refers to one of the standard streams, wherever it has been renumbered.
+    pub(crate) fn is_stdio(&self, fd: types::Fd) -> bool {
+        self.stdio.borrow().contains(&u32::from(fd))
+    }
+
+    /// The file descriptor of the host behind `fd`, if it is a standard stream that can be polled.
+    pub(crate) fn stdio_fd(&self, fd: types::Fd) -> Option<RawFd> {
+        self.stdio_fds.borrow().get(&u32::from(fd)).copied()
+    }
+
+    /// Record that `fd` was closed.
+    pub(crate) fn fd_closed(&self, fd: types::Fd) {
+        self.open_fds.set(self.open_fds.get() - 1);
+        self.nofollow.borrow_mut().remove(&u32::from(fd));
+        self.captures.borrow_mut().remove(&u32::from(fd));
+        self.stdio_fds.borrow_mut().remove(&u32::from(fd));
+        self.stdio.borrow_mut().remove(&u32::from(fd));
+        if let Some(temp_dir) = &self.temp_dir {
+            temp_dir.fd_closed(fd);
+        }
+    }
+
+    /// Record that `from` was renumbered to `to`.
+    pub(crate) fn fd_renumbered(&self, from: types::Fd, to: types::Fd) {
+        let (from, to) = (u32::from(from), u32::from(to));
+        if from != to {
+            // the file that `to` referred to was closed
+            self.open_fds.set(self.open_fds.get() - 1);
+        }
+        let mut nofollow = self.nofollow.borrow_mut();
+        nofollow.remove(&to);
+        if nofollow.remove(&from) {
+            nofollow.insert(to);
+        }
+        let mut captures = self.captures.borrow_mut();
+        captures.remove(&to);
+        if let Some(capture) = captures.remove(&from) {
+            captures.insert(to, capture);
+        }
+        let mut stdio_fds = self.stdio_fds.borrow_mut();
+        stdio_fds.remove(&to);
+        if let Some(raw_fd) = stdio_fds.remove(&from) {
+            stdio_fds.insert(to, raw_fd);
+        }
+        let mut stdio = self.stdio.borrow_mut();
+        stdio.remove(&to);
+        if stdio.remove(&from) {
+            stdio.insert(to);
+        }
+        if let Some(temp_dir) = &self.temp_dir {
+            temp_dir.fd_renumbered(types::Fd::from(from), types::Fd::from(to));
+        }
+    }
+}
+
+impl Deref for WasiCtx {
+    type Target = wasi_common::WasiCtx;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
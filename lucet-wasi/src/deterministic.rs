@@ -0,0 +1,116 @@
+use rand::RngCore;
+use std::cell::Cell;
+
+/// The clocks and random number generator that the WASI hostcalls of instances in deterministic
+/// mode use in place of the host's.
+///
+/// Give one to the `WasiCtxBuilder` with `WasiCtxBuilder::deterministic_env()`, or add one as an
+/// embedder context, alongside the `WasiCtx`, to an instance built with
+/// `InstanceBuilder::with_deterministic_execution()`, which then takes its place. Instances with
+/// neither use `DeterministicEnv::default()`.
+///
+/// Every clock reads the same virtual time, which starts at the configured start time and
+/// advances by a fixed step each time the guest reads it, so that intervals measured by the guest
+/// are never empty but only depend on how often it reads the clock. Random bytes come from a
+/// pseudorandom generator seeded with the given seed. `poll_oneoff()`, which waits for real time
+/// to pass, terminates the instance with `TerminationDetails::Nondeterministic`.
+#[derive(Debug)]
+pub struct DeterministicEnv {
+    time: Cell<u64>,
+    clock_step: u64,
+    rng_state: Cell<u64>,
+}
+
+impl Default for DeterministicEnv {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl DeterministicEnv {
+    /// Create an environment whose random numbers are generated from `seed`.
+    ///
+    /// The clocks start at zero and advance by one microsecond per read.
+    pub fn new(seed: u64) -> Self {
+        DeterministicEnv {
+            time: Cell::new(0),
+            clock_step: 1_000,
+            rng_state: Cell::new(seed),
+        }
+    }
+
+    /// Set the time, in nanoseconds, that the clocks read first.
+    pub fn with_start_time(self, start_time: u64) -> Self {
+        self.time.set(start_time);
+        self
+    }
+
+    /// Set the number of nanoseconds the clocks advance by each time they are read.
+    pub fn with_clock_step(mut self, clock_step: u64) -> Self {
+        self.clock_step = clock_step;
+        self
+    }
+
+    /// The resolution of the clocks, in nanoseconds.
+    pub(crate) fn clock_resolution(&self) -> u64 {
+        self.clock_step.max(1)
+    }
+
+    /// Read the clocks, advancing them.
+    pub(crate) fn read_clock(&self) -> u64 {
+        let time = self.time.get();
+        self.time.set(time.wrapping_add(self.clock_step));
+        time
+    }
+
+    /// Fill `buf` with pseudorandom bytes.
+    pub(crate) fn fill_random(&self, buf: &mut [u8]) {
+        let mut rng = SeededRng::new(self.rng_state.get());
+        rng.fill_bytes(buf);
+        self.rng_state.set(rng.state);
+    }
+}
+
+/// A pseudorandom number generator for `WasiCtxBuilder::rng()`, which produces the same bytes for
+/// the same seed in every build and on every platform.
+///
+/// This is a SplitMix64 generator, which is fast but not cryptographically secure, so it must
+/// only be given to guests whose randomness does not need to be secret, such as in tests and
+/// reproducible simulations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    /// Create a generator seeded with `seed`.
+    pub fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        self.next_u64() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
@@ -1,19 +1,60 @@
 #![deny(bare_trait_objects)]
 
+mod async_io;
 pub mod c_api;
+mod capture;
+mod clocks;
+#[cfg(feature = "crypto")]
+mod crypto;
+mod ctx;
+mod deterministic;
+#[cfg(feature = "nn")]
+mod nn;
 pub mod runtime;
+mod snapshot0;
+mod sockets;
+mod temp_dir;
+mod trace;
+mod vfs;
 
+pub use async_io::{Interest, IoWait};
+pub use capture::{OutputCapture, OutputStream};
+pub use clocks::{FixedClock, MonotonicClock, OffsetClock, ScaledClock, WasiClock};
+#[cfg(feature = "crypto")]
+pub use crypto::{
+    crypto_bindings, CryptoErrno, CryptoOptions, CryptoProvider, RingProvider, SignatureKeypair,
+    SignaturePublicKey, SymmetricKey, SymmetricState, WasiCryptoCtx,
+};
+pub use ctx::{PathPolicy, PreopenCaps, StdioPolicy, WasiCtx, WasiCtxBuilder, WasiQuota};
+pub use deterministic::{DeterministicEnv, SeededRng};
+pub use lucet_wiggle::runtime::TraceArg;
+#[cfg(feature = "nn")]
+pub use nn::{
+    nn_bindings, ExecutionTarget, GraphEncoding, NnBackend, NnErrno, NnExecutionContext, NnGraph,
+    Tensor, TensorType, WasiNnCtx,
+};
 pub use runtime::*;
+pub use sockets::{socket_bindings, Socket, FIRST_SOCKET_FD};
+pub use trace::HostcallTrace;
+pub use vfs::{VirtualDir, VirtualFs};
 // Wasi-common re-exports:
-pub use wasi_common::{WasiCtx, WasiCtxBuilder, WasiCtxBuilderError};
+pub use wasi_common::WasiCtxBuilderError;
 
 /// Wasi executables export the following symbol for the entry point:
 pub const START_SYMBOL: &str = "_start";
 
 /// Bindings for the hostcalls exposed by this crate. These are identical to the bindings in
 /// `bindings.json`. These are exposed as part of a transition path away from bindings.json files.
+///
+/// They bind both `wasi_snapshot_preview1` and the older `wasi_unstable`, so guests compiled
+/// against either snapshot run with the same runtime.
 pub fn bindings() -> lucet_module::bindings::Bindings {
-    lucet_wiggle::bindings(&wasi_common::wasi::metadata::document())
+    let mut bindings = lucet_wiggle::bindings(&wasi_common::wasi::metadata::document());
+    let unstable = snapshot0::bindings(&bindings);
+    bindings
+        .extend(&unstable)
+        .expect("the snapshots are modules of their own");
+    bindings
 }
 
 /// The witx document for the interface implemented by this crate. This is exposed as part of a
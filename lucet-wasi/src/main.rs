@@ -3,39 +3,33 @@
 #[macro_use]
 extern crate clap;
 
-use anyhow::{format_err, Error};
-use clap::Arg;
+use anyhow::{bail, format_err, Error};
+use clap::{AppSettings, Arg};
+use lucet_run::{Invocation, EXIT_ERROR};
 use lucet_runtime::{self, DlModule, Limits, MmapRegion, Module, PublicKey, Region, RunResult};
 use lucet_wasi::{self, types::Exitcode, WasiCtxBuilder};
 use std::fs::File;
 use std::path::PathBuf;
 use std::sync::Arc;
-use std::thread;
 use std::time::Duration;
 
 struct Config<'a> {
     lucet_module: &'a str,
     guest_args: Vec<&'a str>,
     entrypoint: &'a str,
-    preopen_dirs: Vec<(File, &'a str)>,
+    /// The export to call with the guest arguments, given with `--invoke`.
+    invoke: Option<&'a str>,
+    /// The host and guest paths of the directories given with `--dir`.
+    preopen_dirs: Vec<(&'a str, &'a str)>,
+    /// The guest and host paths of the directories given with `--mapdir`.
+    mapped_dirs: Vec<(&'a str, &'a str)>,
     limits: Limits,
     timeout: Option<Duration>,
+    trace: bool,
     verify: bool,
     pk_path: Option<PathBuf>,
 }
 
-fn parse_humansized(desc: &str) -> Result<u64, Error> {
-    use human_size::{Byte, ParsingError, Size, SpecificSize};
-    match desc.parse::<Size>() {
-        Ok(s) => {
-            let bytes: SpecificSize<Byte> = s.into();
-            Ok(bytes.value() as u64)
-        }
-        Err(ParsingError::MissingMultiple) => Ok(desc.parse::<u64>()?),
-        Err(e) => Err(e.into()),
-    }
-}
-
 fn main() {
     // No-ops, but makes sure the linker doesn't throw away parts
     // of the runtime:
@@ -43,6 +37,7 @@ fn main() {
     lucet_wasi::export_wasi_funcs();
 
     let matches = app_from_crate!()
+        .setting(AppSettings::AllowNegativeNumbers)
         .arg(
             Arg::with_name("entrypoint")
                 .long("entrypoint")
@@ -50,6 +45,23 @@ fn main() {
                 .default_value("_start")
                 .help("Entrypoint to run within the WASI module"),
         )
+        .arg(
+            Arg::with_name("invoke")
+                .long("invoke")
+                .value_name("FUNC")
+                .takes_value(true)
+                .help("Call an exported function with the guest arguments, and print its result")
+                .long_help(
+                    "Instead of the entrypoint, call the exported function FUNC, passing it the \
+                     guest arguments rather than giving them to `main`. Each argument is parsed \
+                     as the i32, i64, f32, or f64 that the function takes in its place, and the \
+                     result, if the function returns one, is printed to stdout.\
+                     \n\n\
+                     For example, `lucet-wasi lib.so --invoke add -- 1 -2` prints `-1` if `add` \
+                     adds two integers. The start function of the module runs first, as it \
+                     would for the entrypoint.",
+                ),
+        )
         .arg(
             Arg::with_name("preopen_dirs")
                 .required(false)
@@ -74,39 +86,39 @@ fn main() {
                 ),
         )
         .arg(
-            Arg::with_name("lucet_module")
-                .required(true)
-                .help("Path to the `lucetc`-compiled WASI module"),
-        )
-        .arg(
-            Arg::with_name("heap_memory_size")
-                .long("max-heap-size")
+            Arg::with_name("mapped_dirs")
+                .required(false)
+                .long("mapdir")
+                .value_name("GUEST_DIR::HOST_DIR")
                 .takes_value(true)
-                .default_value("4 GiB")
-                .help("Maximum heap size (must be a multiple of 4 KiB)"),
+                .multiple(true)
+                .number_of_values(1)
+                .help("A host directory to provide to the WASI guest at another path")
+                .long_help(
+                    "Like --dir, but with the guest path first and separated by `::`, so that \
+                     either path can contain a colon. For example, `--mapdir /app::/srv/guest/app` \
+                     makes `/srv/guest/app` available within the guest as `/app`.\
+                     \n\n\
+                     The mapped directories are provided after those given with --dir.",
+                ),
         )
         .arg(
-            Arg::with_name("heap_address_space_size")
-                .long("heap-address-space")
-                .takes_value(true)
-                .default_value("8 GiB")
-                .help("Maximum heap address space size (must be a multiple of 4 KiB, and >= `max-heap-size`)"),
+            Arg::with_name("lucet_module")
+                .required(true)
+                .help("Path to the `lucetc`-compiled WASI module"),
         )
+        .args(&lucet_run::limit_args())
         .arg(
-            Arg::with_name("stack_size")
-                .long("stack-size")
-                .takes_value(true)
-                .default_value("8 MiB")
-                .help("Maximum stack size (must be a multiple of 4 KiB)"),
+            Arg::with_name("trace")
+                .long("trace")
+                .takes_value(false)
+                .help("Trace the WASI calls of the guest to stderr, as strace would"),
         )
-        .arg(
-            Arg::with_name("timeout").long("timeout").takes_value(true).help("Number of milliseconds the instance will be allowed to run")
-            )
         .arg(
             Arg::with_name("guest_args")
                 .required(false)
                 .multiple(true)
-                .help("Arguments to the WASI `main` function"),
+                .help("Arguments to the WASI `main` function, or to the function given with --invoke"),
         )
         .arg(
             Arg::with_name("verify")
@@ -120,10 +132,17 @@ fn main() {
                 .takes_value(true)
                 .help("Path to the public key to verify the source code signature")
         )
+        .after_help(
+            "The exit code is the guest's own when it exits or returns from its entrypoint, \
+             124 when it runs out of time, 134 when it traps, and 125 when it cannot be run or \
+             fails otherwise.",
+        )
         .get_matches();
 
     let entrypoint = matches.value_of("entrypoint").unwrap();
 
+    let invoke = matches.value_of("invoke");
+
     let lucet_module = matches.value_of("lucet_module").unwrap();
 
     let preopen_dirs = matches
@@ -133,59 +152,47 @@ fn main() {
                 if let [host_path, guest_path] =
                     preopen_dir.split(':').collect::<Vec<&str>>().as_slice()
                 {
-                    let host_dir = File::open(host_path).unwrap();
-                    (host_dir, *guest_path)
+                    (*host_path, *guest_path)
                 } else {
-                    println!("Invalid directory specification: {}", preopen_dir);
-                    println!("{}", matches.usage());
-                    std::process::exit(1);
+                    lucet_run::exit_with_usage(
+                        &matches,
+                        format_err!("Invalid directory specification: {}", preopen_dir),
+                    )
                 }
             })
             .collect()
         })
         .unwrap_or(vec![]);
 
-    let heap_memory_size = matches
-        .value_of("heap_memory_size")
-        .ok_or_else(|| format_err!("missing heap memory size"))
-        .and_then(|v| parse_humansized(v))
-        .unwrap() as usize;
-
-    let heap_address_space_size = matches
-        .value_of("heap_address_space_size")
-        .ok_or_else(|| format_err!("missing heap address space size"))
-        .and_then(|v| parse_humansized(v))
-        .unwrap() as usize;
-
-    if heap_memory_size > heap_address_space_size {
-        println!("`heap-address-space` must be at least as large as `max-heap-size`");
-        println!("{}", matches.usage());
-        std::process::exit(1);
-    }
-
-    let stack_size = matches
-        .value_of("stack_size")
-        .ok_or_else(|| format_err!("missing stack size"))
-        .and_then(|v| parse_humansized(v))
-        .unwrap() as usize;
-
-    let timeout = matches
-        .value_of("timeout")
-        .map(|t| Duration::from_millis(t.parse::<u64>().unwrap()));
+    let mapped_dirs = matches
+        .values_of("mapped_dirs")
+        .map(|vals| {
+            vals.map(|mapped_dir| {
+                match mapped_dir.splitn(2, "::").collect::<Vec<&str>>().as_slice() {
+                    [guest_path, host_path] if !guest_path.is_empty() && !host_path.is_empty() => {
+                        (*guest_path, *host_path)
+                    }
+                    _ => lucet_run::exit_with_usage(
+                        &matches,
+                        format_err!("Invalid directory mapping: {}", mapped_dir),
+                    ),
+                }
+            })
+            .collect()
+        })
+        .unwrap_or(vec![]);
 
-    let limits = Limits {
-        heap_memory_size,
-        heap_address_space_size,
-        stack_size,
-        globals_size: 0, // calculated from module
-        ..Limits::default()
-    };
+    let limits =
+        lucet_run::limits(&matches).unwrap_or_else(|e| lucet_run::exit_with_usage(&matches, e));
+    let timeout =
+        lucet_run::timeout(&matches).unwrap_or_else(|e| lucet_run::exit_with_usage(&matches, e));
 
     let guest_args = matches
         .values_of("guest_args")
         .map(|vals| vals.collect())
         .unwrap_or(vec![]);
 
+    let trace = matches.is_present("trace");
     let verify = matches.is_present("verify");
     let pk_path = matches.value_of("pk_path").map(PathBuf::from);
 
@@ -193,91 +200,119 @@ fn main() {
         lucet_module,
         guest_args,
         entrypoint,
+        invoke,
         preopen_dirs,
+        mapped_dirs,
         limits,
         timeout,
+        trace,
         verify,
         pk_path,
     };
 
-    run(config)
+    // running in a function of its own makes sure everything gets dropped before exiting
+    match run(config) {
+        Ok(exitcode) => std::process::exit(exitcode),
+        Err(e) => {
+            eprintln!("lucet-wasi: {}", e);
+            std::process::exit(EXIT_ERROR);
+        }
+    }
 }
 
-fn run(config: Config<'_>) {
-    let exitcode = {
-        // doing all of this in a block makes sure everything gets dropped before exiting
-        let pk = match (config.verify, config.pk_path) {
-            (false, _) => None,
-            (true, Some(pk_path)) => {
-                Some(PublicKey::from_file(pk_path).expect("public key can be loaded"))
-            }
-            (true, None) => panic!("signature verification requires a public key"),
-        };
-        let module = if let Some(pk) = pk {
-            DlModule::load_and_verify(&config.lucet_module, pk)
-                .expect("signed module can be loaded")
-        } else {
-            DlModule::load(&config.lucet_module).expect("module can be loaded")
-        };
-        let min_globals_size = module.initial_globals_size();
-        let globals_size = ((min_globals_size + 4096 - 1) / 4096) * 4096;
+fn run(config: Config<'_>) -> Result<i32, Error> {
+    let pk = match (config.verify, config.pk_path) {
+        (false, _) => None,
+        (true, Some(pk_path)) => Some(
+            PublicKey::from_file(&pk_path)
+                .map_err(|e| format_err!("cannot read public key {}: {}", pk_path.display(), e))?,
+        ),
+        (true, None) => bail!("signature verification requires a public key"),
+    };
+    let module = if let Some(pk) = pk {
+        DlModule::load_and_verify(&config.lucet_module, pk)?
+    } else {
+        DlModule::load(&config.lucet_module)?
+    };
+    let min_globals_size = module.initial_globals_size();
+    let globals_size = ((min_globals_size + 4096 - 1) / 4096) * 4096;
 
-        let region = MmapRegion::create(
-            1,
-            &Limits {
-                globals_size,
-                ..config.limits
-            },
-        )
-        .expect("region can be created");
+    let region = MmapRegion::create(
+        1,
+        &Limits {
+            globals_size,
+            ..config.limits
+        },
+    )?;
 
-        // put the path to the module on the front for argv[0]
-        let args = std::iter::once(config.lucet_module)
-            .chain(config.guest_args.into_iter())
-            .collect::<Vec<&str>>();
-        let mut ctx = WasiCtxBuilder::new();
-        ctx.args(args.iter());
-        ctx.inherit_stdio();
-        ctx.inherit_env();
-        for (dir, guest_path) in config.preopen_dirs {
-            ctx.preopened_dir(dir, guest_path);
-        }
-        let mut inst = region
-            .new_instance_builder(module as Arc<dyn Module>)
-            .with_embed_ctx(ctx.build().expect("WASI ctx can be created"))
-            .build()
-            .expect("instance can be created");
+    // the guest arguments are for the function to invoke instead, if there is one
+    let invocation = match config.invoke {
+        Some(func) => Some(
+            Invocation::parse(module.as_ref(), func, &config.guest_args)
+                .map_err(|e| format_err!("cannot invoke {}: {}", func, e))?,
+        ),
+        None => None,
+    };
+    let guest_args = if invocation.is_some() {
+        vec![]
+    } else {
+        config.guest_args
+    };
 
-        if let Some(timeout) = config.timeout {
-            let kill_switch = inst.kill_switch();
-            thread::spawn(move || {
-                thread::sleep(timeout);
-                // We may hit this line exactly when the guest exits, so sometimes `terminate` can
-                // fail. That's still acceptable, so just ignore the result.
-                kill_switch.terminate().ok();
-            });
-        }
+    // put the path to the module on the front for argv[0]
+    let args = std::iter::once(config.lucet_module)
+        .chain(guest_args.into_iter())
+        .collect::<Vec<&str>>();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(args.iter());
+    ctx.inherit_stdio();
+    ctx.inherit_env();
+    for (host_path, guest_path) in config.preopen_dirs {
+        let dir = File::open(host_path)
+            .map_err(|e| format_err!("cannot open directory {}: {}", host_path, e))?;
+        ctx.preopened_dir(dir, guest_path);
+    }
+    for (guest_path, host_path) in config.mapped_dirs {
+        ctx.map_dir(guest_path, host_path)
+            .map_err(|e| format_err!("cannot map {} to {}: {}", host_path, guest_path, e))?;
+    }
+    if config.trace {
+        ctx.trace_to(std::io::stderr());
+    }
+    let ctx = ctx
+        .build()
+        .map_err(|e| format_err!("cannot create the WASI context: {}", e))?;
+    let mut inst = region
+        .new_instance_builder(module as Arc<dyn Module>)
+        .with_embed_ctx(ctx)
+        .build()?;
 
-        inst.run_start().expect("Wasm start function runs");
+    if let Some(timeout) = config.timeout {
+        lucet_run::terminate_after(inst.kill_switch(), timeout);
+    }
 
-        match inst.run(config.entrypoint, &[]) {
-            // normal termination implies 0 exit code
-            Ok(RunResult::Returned(_)) => 0,
-            // none of the WASI hostcalls use yield yet, so this shouldn't happen
-            Ok(RunResult::Yielded(_)) => panic!("lucet-wasi unexpectedly yielded"),
-            Err(lucet_runtime::Error::RuntimeTerminated(
-                lucet_runtime::TerminationDetails::Provided(any),
-            )) => *any
-                .downcast_ref::<Exitcode>()
-                .expect("termination yields an exitcode"),
-            Err(lucet_runtime::Error::RuntimeTerminated(
-                lucet_runtime::TerminationDetails::Remote,
-            )) => {
-                println!("Terminated via remote kill switch (likely a timeout)");
-                std::u32::MAX
+    Ok(match (inst.run_start(), invocation) {
+        (Ok(()), None) => exit_code(inst.run(config.entrypoint, &[])),
+        (Ok(()), Some(invocation)) => match inst.run(invocation.func, &invocation.args) {
+            Ok(RunResult::Returned(ret)) => {
+                invocation.print_result(&ret);
+                0
+            }
+            res => exit_code(res),
+        },
+        (Err(e), _) => exit_code(Err(e)),
+    })
+}
+
+/// The exit code for the outcome of running the guest, which is its own if it exits.
+fn exit_code(res: Result<RunResult, lucet_runtime::Error>) -> i32 {
+    lucet_run::exit_code("lucet-wasi", res, |details| {
+        match details.downcast_ref::<Exitcode>() {
+            Some(status) => *status as i32,
+            None => {
+                eprintln!("lucet-wasi: the guest was terminated without an exit code");
+                EXIT_ERROR
             }
-            Err(e) => panic!("lucet-wasi runtime error: {}", e),
         }
-    };
-    std::process::exit(exitcode as i32);
+    })
 }
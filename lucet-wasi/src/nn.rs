@@ -0,0 +1,356 @@
+//! An implementation of wasi-nn, with which guests run inference on machine-learning models.
+//!
+//! lucet-wasi does not run models itself: the embedder provides an [`NnBackend`](trait.NnBackend.html)
+//! that wraps its inference runtime, and adds it to the instance in a
+//! [`WasiNnCtx`](struct.WasiNnCtx.html) embedder context. Guests compiled with
+//! [`nn_bindings()`](fn.nn_bindings.html) can then import the functions of the
+//! `lucet_wasi_nn` module, which return an `NnErrno`, or 0 on success:
+//!
+//! - `load(builder: *const graph_builder, builder_len: u32, encoding: u32, target: u32,
+//!   graph_out: *mut u32) -> u32` loads a graph from the byte arrays of `builder`, each a pointer
+//!   and length, and stores its handle in `graph_out`.
+//! - `init_execution_context(graph: u32, ctx_out: *mut u32) -> u32` creates an execution context
+//!   for a graph, and stores its handle in `ctx_out`.
+//! - `set_input(ctx: u32, index: u32, tensor: *const tensor) -> u32` sets an input of an
+//!   execution context to a tensor, whose dimensions, type, and data are laid out as in wasi-nn.
+//! - `compute(ctx: u32) -> u32` runs the inference.
+//! - `get_output(ctx: u32, index: u32, out: *mut u8, out_max_size: u32,
+//!   bytes_written: *mut u32) -> u32` copies an output of an execution context to `out`, and
+//!   stores its size in `bytes_written`.
+//!
+//! These are simpler than the functions of the proposal, so they are not in its
+//! `wasi_ephemeral_nn` module: a guest built for the proposal fails to link rather than calling
+//! them with the wrong arguments.
+//!
+//! The functions fail with `NnErrno::RuntimeError` in an instance without a `WasiNnCtx`.
+//!
+//! ```no_run
+//! # use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+//! # use lucet_wasi::{NnBackend, WasiNnCtx};
+//! # fn backend() -> impl NnBackend { unimplemented!() }
+//! # let module = DlModule::load("classify.so").unwrap();
+//! # let region = MmapRegion::create(1, &Limits::default()).unwrap();
+//! let mut inst = region
+//!     .new_instance_builder(module)
+//!     .with_embed_ctx(WasiNnCtx::new(backend()))
+//!     .build()
+//!     .unwrap();
+//! ```
+
+use lucet_module::bindings::Bindings;
+use lucet_runtime::lucet_hostcall;
+use lucet_runtime::vmctx::Vmctx;
+use lucet_wiggle::runtime::LucetMemory;
+use lucet_wiggle::{GuestError, GuestPtr};
+use std::collections::HashMap;
+
+/// The format of the builders that a graph is loaded from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GraphEncoding {
+    Openvino,
+    Onnx,
+    Tensorflow,
+    Pytorch,
+}
+
+/// The device that a graph is run on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ExecutionTarget {
+    Cpu,
+    Gpu,
+    Tpu,
+}
+
+/// The type of the elements of a tensor.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TensorType {
+    F16,
+    F32,
+    U8,
+    I32,
+}
+
+/// The errors of the wasi-nn functions, with the values the guest sees.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[repr(u32)]
+pub enum NnErrno {
+    /// An argument is invalid, such as the handle of a graph that was not loaded, or a buffer
+    /// that is too small for an output.
+    InvalidArgument = 1,
+    /// The graph builders are not in the encoding they were loaded with, or it is not supported.
+    InvalidEncoding = 2,
+    /// The backend does not have enough memory.
+    MissingMemory = 3,
+    /// The backend cannot run the operation now.
+    Busy = 4,
+    /// Any other failure of the backend.
+    RuntimeError = 5,
+}
+
+/// A tensor that the guest gives as the input of an execution context.
+#[derive(Clone, Copy, Debug)]
+pub struct Tensor<'a> {
+    pub dimensions: &'a [u32],
+    pub ty: TensorType,
+    /// The elements of the tensor, in row-major order and little-endian.
+    pub data: &'a [u8],
+}
+
+/// An inference runtime that loads the graphs of guests.
+pub trait NnBackend: 'static {
+    /// Load a graph from the `builders` the guest gives, which are in the format `encoding`.
+    fn load(
+        &mut self,
+        builders: &[&[u8]],
+        encoding: GraphEncoding,
+        target: ExecutionTarget,
+    ) -> Result<Box<dyn NnGraph>, NnErrno>;
+}
+
+/// A graph that a backend has loaded.
+pub trait NnGraph: 'static {
+    /// Create a context that runs the graph.
+    fn init_execution_context(&mut self) -> Result<Box<dyn NnExecutionContext>, NnErrno>;
+}
+
+/// A context that runs a graph, with its own inputs and outputs.
+pub trait NnExecutionContext: 'static {
+    /// Set the input `index` to `tensor`.
+    fn set_input(&mut self, index: u32, tensor: &Tensor<'_>) -> Result<(), NnErrno>;
+
+    /// Run the graph on the inputs.
+    fn compute(&mut self) -> Result<(), NnErrno>;
+
+    /// Copy the output `index` of the last computation to the start of `out`, returning its size
+    /// in bytes, or failing with `NnErrno::InvalidArgument` if it does not fit.
+    fn get_output(&mut self, index: u32, out: &mut [u8]) -> Result<u32, NnErrno>;
+}
+
+/// The wasi-nn state of an instance, to add to it as an embedder context.
+///
+/// Graphs and execution contexts live as long as the instance, since wasi-nn has no way for the
+/// guest to release them.
+pub struct WasiNnCtx {
+    backend: Box<dyn NnBackend>,
+    graphs: Vec<Box<dyn NnGraph>>,
+    contexts: Vec<Box<dyn NnExecutionContext>>,
+}
+
+impl WasiNnCtx {
+    /// Create the state of an instance whose graphs are loaded by `backend`.
+    pub fn new(backend: impl NnBackend) -> Self {
+        WasiNnCtx {
+            backend: Box::new(backend),
+            graphs: vec![],
+            contexts: vec![],
+        }
+    }
+
+    fn context(&mut self, ctx: u32) -> Result<&mut dyn NnExecutionContext, NnErrno> {
+        match self.contexts.get_mut(ctx as usize) {
+            Some(ctx) => Ok(ctx.as_mut()),
+            None => Err(NnErrno::InvalidArgument),
+        }
+    }
+}
+
+/// The bindings for the wasi-nn functions, which guests that import them must be compiled with,
+/// in addition to [`bindings()`](../fn.bindings.html).
+pub fn nn_bindings() -> Bindings {
+    let mut funcs = HashMap::new();
+    for name in &[
+        "load",
+        "init_execution_context",
+        "set_input",
+        "compute",
+        "get_output",
+    ] {
+        funcs.insert((*name).to_owned(), format!("lucet_wasi_nn_{}", name));
+    }
+    let mut bindings = HashMap::new();
+    bindings.insert("lucet_wasi_nn".to_owned(), funcs);
+    Bindings::new(bindings)
+}
+
+/// Run a wasi-nn function with the state of the instance, returning its errno.
+fn nn_hostcall(
+    vmctx: &Vmctx,
+    f: impl FnOnce(&mut WasiNnCtx, &LucetMemory) -> Result<(), NnErrno>,
+) -> u32 {
+    if !vmctx.contains_embed_ctx::<WasiNnCtx>() {
+        return NnErrno::RuntimeError as u32;
+    }
+    let memory = LucetMemory::new(vmctx);
+    let mut nn = vmctx.get_embed_ctx_mut::<WasiNnCtx>();
+    match f(&mut nn, &memory) {
+        Ok(()) => 0,
+        Err(e) => e as u32,
+    }
+}
+
+/// Read the `len` words at the guest pointer `ptr`.
+fn read_u32s(memory: &LucetMemory, ptr: u32, len: u32) -> Result<Vec<u32>, NnErrno> {
+    GuestPtr::<u32>::new(memory, ptr)
+        .as_array(len)
+        .iter()
+        .map(|word| word.and_then(|word| word.read()))
+        .collect::<Result<_, _>>()
+        .map_err(guest_errno)
+}
+
+/// Check that the guest pointer `ptr` can be written to, so that a handle is not lost when it is
+/// invalid.
+fn check_out_ptr<'a>(memory: &'a LucetMemory<'_>, ptr: u32) -> Result<GuestPtr<'a, u32>, NnErrno> {
+    let ptr = GuestPtr::<u32>::new(memory, ptr);
+    ptr.read().map_err(guest_errno)?;
+    Ok(ptr)
+}
+
+fn offset(ptr: u32, offset: u32) -> Result<u32, NnErrno> {
+    ptr.checked_add(offset).ok_or(NnErrno::InvalidArgument)
+}
+
+fn guest_errno(_e: GuestError) -> NnErrno {
+    NnErrno::InvalidArgument
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_nn_load(
+    vmctx: &Vmctx,
+    builder: u32,
+    builder_len: u32,
+    encoding: u32,
+    target: u32,
+    graph_out: u32,
+) -> u32 {
+    nn_hostcall(vmctx, |nn, memory| {
+        let encoding = match encoding {
+            0 => GraphEncoding::Openvino,
+            1 => GraphEncoding::Onnx,
+            2 => GraphEncoding::Tensorflow,
+            3 => GraphEncoding::Pytorch,
+            _ => return Err(NnErrno::InvalidEncoding),
+        };
+        let target = match target {
+            0 => ExecutionTarget::Cpu,
+            1 => ExecutionTarget::Gpu,
+            2 => ExecutionTarget::Tpu,
+            _ => return Err(NnErrno::InvalidArgument),
+        };
+        let graph_out = check_out_ptr(memory, graph_out)?;
+        // each builder is a pointer and a length
+        let len = builder_len.checked_mul(2).ok_or(NnErrno::InvalidArgument)?;
+        let words = read_u32s(memory, builder, len)?;
+        let builders = words
+            .chunks(2)
+            .map(|builder| {
+                GuestPtr::<u8>::new(memory, builder[0])
+                    .as_array(builder[1])
+                    .as_slice()
+                    .map_err(guest_errno)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let graph = {
+            let builders = builders.iter().map(|b| &**b).collect::<Vec<&[u8]>>();
+            nn.backend.load(&builders, encoding, target)?
+        };
+        // release the borrows of the builders before writing the handle
+        drop(builders);
+        nn.graphs.push(graph);
+        graph_out
+            .write(nn.graphs.len() as u32 - 1)
+            .map_err(guest_errno)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_nn_init_execution_context(vmctx: &Vmctx, graph: u32, ctx_out: u32) -> u32 {
+    nn_hostcall(vmctx, |nn, memory| {
+        let ctx_out = check_out_ptr(memory, ctx_out)?;
+        let ctx = nn
+            .graphs
+            .get_mut(graph as usize)
+            .ok_or(NnErrno::InvalidArgument)?
+            .init_execution_context()?;
+        nn.contexts.push(ctx);
+        ctx_out
+            .write(nn.contexts.len() as u32 - 1)
+            .map_err(guest_errno)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_nn_set_input(vmctx: &Vmctx, ctx: u32, index: u32, tensor: u32) -> u32 {
+    nn_hostcall(vmctx, |nn, memory| {
+        // the dimensions and data are each a pointer and a length, either side of the type
+        let dimensions = read_u32s(memory, tensor, 2)?;
+        let ty = GuestPtr::<u8>::new(memory, offset(tensor, 8)?)
+            .read()
+            .map_err(guest_errno)?;
+        let data = read_u32s(memory, offset(tensor, 12)?, 2)?;
+        let ty = match ty {
+            0 => TensorType::F16,
+            1 => TensorType::F32,
+            2 => TensorType::U8,
+            3 => TensorType::I32,
+            _ => return Err(NnErrno::InvalidArgument),
+        };
+        let dimensions = read_u32s(memory, dimensions[0], dimensions[1])?;
+        let data = GuestPtr::<u8>::new(memory, data[0])
+            .as_array(data[1])
+            .as_slice()
+            .map_err(guest_errno)?;
+        let tensor = Tensor {
+            dimensions: &dimensions,
+            ty,
+            data: &data,
+        };
+        nn.context(ctx)?.set_input(index, &tensor)
+    })
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_nn_compute(vmctx: &Vmctx, ctx: u32) -> u32 {
+    nn_hostcall(vmctx, |nn, _| nn.context(ctx)?.compute())
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_nn_get_output(
+    vmctx: &Vmctx,
+    ctx: u32,
+    index: u32,
+    out: u32,
+    out_max_size: u32,
+    bytes_written: u32,
+) -> u32 {
+    nn_hostcall(vmctx, |nn, memory| {
+        let bytes_written = check_out_ptr(memory, bytes_written)?;
+        let size = {
+            let mut out = GuestPtr::<u8>::new(memory, out)
+                .as_array(out_max_size)
+                .as_slice()
+                .map_err(guest_errno)?;
+            nn.context(ctx)?.get_output(index, &mut out)?
+        };
+        bytes_written.write(size).map_err(guest_errno)
+    })
+}
+
+/// Make sure the wasi-nn hostcalls are linked, like `export_wasi_funcs()` does for the others.
+pub(crate) fn init() {
+    let funcs: &[*const extern "C" fn()] = &[
+        lucet_wasi_nn_load as _,
+        lucet_wasi_nn_init_execution_context as _,
+        lucet_wasi_nn_set_input as _,
+        lucet_wasi_nn_compute as _,
+        lucet_wasi_nn_get_output as _,
+    ];
+    for func in funcs {
+        assert_ne!(*func, std::ptr::null(), "hostcall address is not null");
+    }
+}
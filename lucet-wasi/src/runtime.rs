@@ -1,8 +1,15 @@
+use crate::async_io::{self, poll_fds, socket_op, Interest, IoWait};
+use crate::ctx::WasiCtx;
+use crate::sockets::io_errno;
+use crate::vfs::{Resolved, VirtualFs};
+use crate::DeterministicEnv;
 use lucet_runtime::{lucet_hostcall_terminate, vmctx::Vmctx};
 use lucet_wiggle::{GuestError, GuestPtr};
-use std::cell::Ref;
+use rand::RngCore;
+use std::cell::{Ref, RefMut};
+use std::os::unix::io::RawFd;
+use std::time::{Duration, Instant};
 use wasi_common::wasi::wasi_snapshot_preview1::WasiSnapshotPreview1;
-use wasi_common::WasiCtx;
 
 lucet_wasi_generate::bindings!({
     // The context type, which we will implement the GuestErrorConversion and
@@ -19,7 +26,28 @@ pub mod types {
 }
 
 pub fn export_wasi_funcs() {
-    hostcalls::init()
+    hostcalls::init();
+    crate::snapshot0::init();
+    crate::sockets::init();
+    #[cfg(feature = "crypto")]
+    crate::crypto::init();
+    #[cfg(feature = "nn")]
+    crate::nn::init();
+}
+
+/// How long a `poll_oneoff()`, or a socket waiting to accept or connect, blocks the thread at a
+/// time, and so how long it can take to notice that its instance has been terminated, in
+/// milliseconds.
+pub(crate) const POLL_SLICE_MS: i32 = 10;
+
+/// What a subscription of a `poll_oneoff()` waits for.
+enum Pollee {
+    /// A clock, which expires at the deadline if it has one.
+    Clock(Option<Instant>),
+    /// A file descriptor of the host, which is ready when the host says it is.
+    Fd(RawFd, Interest),
+    /// A subscription that is ready already, with its error and the bytes there are to read.
+    Ready(types::Errno, types::Filesize),
 }
 
 pub struct LucetWasiCtx<'a> {
@@ -27,9 +55,385 @@ pub struct LucetWasiCtx<'a> {
 }
 
 impl<'a> LucetWasiCtx<'a> {
+    pub(crate) fn new(vmctx: &'a Vmctx) -> Self {
+        LucetWasiCtx { vmctx }
+    }
+
     pub fn wasi(&self) -> Ref<WasiCtx> {
         self.vmctx.get_embed_ctx()
     }
+
+    /// The source of clock readings and random numbers to use instead of the host's, if the
+    /// instance runs in deterministic mode: its own `DeterministicEnv`, or that of its `WasiCtx`.
+    fn deterministic(&self) -> Option<Ref<DeterministicEnv>> {
+        if !self.vmctx.is_deterministic() {
+            None
+        } else if self.vmctx.contains_embed_ctx::<DeterministicEnv>() {
+            Some(self.vmctx.get_embed_ctx())
+        } else {
+            Some(Ref::map(self.wasi(), WasiCtx::deterministic_env))
+        }
+    }
+
+    /// Run `f` on the table that numbers the guest's file descriptors apart from those of the
+    /// `WasiCtx`, if there is one: the virtual filesystem of the instance, or, without one, the
+    /// table the `WasiCtx` starts once the guest renumbers a preopen.
+    fn with_vfs<R>(&self, f: impl FnOnce(&mut VirtualFs) -> R) -> Option<R> {
+        if self.vmctx.contains_embed_ctx::<VirtualFs>() {
+            return Some(f(&mut self.vmctx.get_embed_ctx_mut()));
+        }
+        let wasi = self.wasi();
+        let mut fds = wasi.renumbered_fds().borrow_mut();
+        fds.as_mut().map(f)
+    }
+
+    /// The virtual filesystem of the instance, which `route()` has found it to have.
+    fn virtual_fs(&self) -> RefMut<VirtualFs> {
+        self.vmctx.get_embed_ctx_mut()
+    }
+
+    /// Find where the hostcalls on the guest file descriptor `fd` go. Without a virtual
+    /// filesystem, they all go to the `WasiCtx`, unchanged unless the guest renumbered a preopen.
+    ///
+    /// The hostcalls that support sockets handle them before routing, so the others fail on them.
+    fn route(&self, fd: types::Fd) -> Result<Resolved, types::Errno> {
+        if self.wasi().is_socket(fd) {
+            return Err(types::Errno::Notsup);
+        }
+        self.with_vfs(|vfs| vfs.resolve(&self.wasi(), fd))
+            .unwrap_or(Ok(Resolved::Host(fd)))
+    }
+
+    /// The path to resolve `path` with, as the `PathPolicy` of the guest allows: an absolute path
+    /// is resolved relative to the directory it is looked up in if the policy allows it, and is
+    /// refused with `Errno::Notcapable` otherwise.
+    fn policy_path<'b>(&self, path: &GuestPtr<'b, str>) -> Result<GuestPtr<'b, str>, types::Errno> {
+        let guest_err = |e| types::GuestErrorConversion::into_errno(self, e);
+        let slashes = path
+            .as_str()
+            .map_err(guest_err)?
+            .bytes()
+            .take_while(|&b| b == b'/')
+            .count() as u32;
+        if slashes == 0 {
+            return Ok(*path);
+        }
+        if !self.wasi().path_policy().allows_absolute_paths() {
+            return Err(types::Errno::Notcapable);
+        }
+        // the rest of the path, without copying it out of the guest's memory
+        let (offset, len) = path.offset();
+        Ok(GuestPtr::new(path.mem(), (offset + slashes, len - slashes)))
+    }
+
+    /// The error for a socket operation on `fd`, which is not one of the guest's sockets.
+    ///
+    /// The only other sockets an instance can have are ones inherited from the host as files,
+    /// whose socket operations are not supported.
+    fn not_a_socket(&self, fd: types::Fd) -> types::Errno {
+        match wasi_snapshot_preview1::WasiSnapshotPreview1::fd_fdstat_get(self, fd) {
+            Ok(fdstat) => match fdstat.fs_filetype {
+                types::Filetype::SocketDgram | types::Filetype::SocketStream => {
+                    types::Errno::Notsup
+                }
+                _ => types::Errno::Notsock,
+            },
+            Err(e) => e,
+        }
+    }
+
+    /// Wait until any of the subscriptions `subs` of a `poll_oneoff()` is ready, returning the
+    /// events of the ones that are.
+    pub(crate) fn poll(
+        &self,
+        subs: Vec<types::Subscription>,
+    ) -> Result<Vec<types::Event>, types::Errno> {
+        // the events that are ready depend on how much time has passed
+        self.vmctx.nondeterministic("poll_oneoff");
+        let subs = subs
+            .into_iter()
+            .map(|sub| {
+                let type_ = match sub.u {
+                    types::SubscriptionU::Clock(_) => types::Eventtype::Clock,
+                    types::SubscriptionU::FdRead(_) => types::Eventtype::FdRead,
+                    types::SubscriptionU::FdWrite(_) => types::Eventtype::FdWrite,
+                };
+                (sub.userdata, type_, self.pollee(&sub))
+            })
+            .collect::<Vec<_>>();
+        // the file descriptors of the host to poll, and the subscriptions they are for
+        let (polled, fds): (Vec<usize>, Vec<(RawFd, Interest)>) = subs
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (_, _, pollee))| match pollee {
+                Pollee::Fd(raw_fd, interest) => Some((i, (*raw_fd, *interest))),
+                _ => None,
+            })
+            .unzip();
+        let deadline = subs
+            .iter()
+            .filter_map(|(_, _, pollee)| match pollee {
+                Pollee::Clock(deadline) => *deadline,
+                _ => None,
+            })
+            .min();
+
+        let event = |i: usize, error, fd_readwrite| {
+            let (userdata, type_, _) = &subs[i];
+            types::Event {
+                userdata: *userdata,
+                error,
+                type_: *type_,
+                fd_readwrite,
+            }
+        };
+        let no_bytes = |nbytes| types::EventFdReadwrite {
+            nbytes,
+            flags: types::Eventrwflags::EMPTY_FLAGS,
+        };
+        let mut timeout_ms = 0;
+        loop {
+            let polled_ready = poll_fds(&fds, timeout_ms).map_err(io_errno)?;
+            let now = Instant::now();
+            let mut ready = vec![];
+            for (i, (_, _, pollee)) in subs.iter().enumerate() {
+                match pollee {
+                    Pollee::Ready(error, nbytes) => ready.push(event(i, *error, no_bytes(*nbytes))),
+                    Pollee::Clock(Some(deadline)) if *deadline <= now => {
+                        ready.push(event(i, types::Errno::Success, no_bytes(0)))
+                    }
+                    _ => (),
+                }
+            }
+            for (j, rw) in polled_ready {
+                ready.push(event(polled[j], types::Errno::Success, rw));
+            }
+            if !ready.is_empty() {
+                return Ok(ready);
+            }
+            // the instance is terminated as soon as this returns, so a terminated instance does
+            // not wait out its timeout
+            if self.vmctx.termination_requested() {
+                return Err(types::Errno::Intr);
+            }
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(now));
+            if self.wasi().async_io() {
+                self.vmctx.yield_val(IoWait::new(fds.clone(), remaining));
+                timeout_ms = 0;
+            } else {
+                // poll for a slice of the time at most, so that a termination is noticed
+                timeout_ms = remaining.map_or(POLL_SLICE_MS, |remaining| {
+                    std::cmp::min(async_io::timeout_ms(remaining), POLL_SLICE_MS)
+                });
+            }
+        }
+    }
+
+    /// What the subscription `sub` of a `poll_oneoff()` waits for.
+    fn pollee(&self, sub: &types::Subscription) -> Pollee {
+        let (fd, interest) = match &sub.u {
+            types::SubscriptionU::Clock(clock) => {
+                let now = match wasi_snapshot_preview1::WasiSnapshotPreview1::clock_time_get(
+                    self,
+                    clock.id,
+                    clock.precision,
+                ) {
+                    Ok(now) => now,
+                    Err(e) => return Pollee::Ready(e, 0),
+                };
+                let timeout = if clock
+                    .flags
+                    .contains(&types::Subclockflags::SUBSCRIPTION_CLOCK_ABSTIME)
+                {
+                    clock.timeout.saturating_sub(now)
+                } else {
+                    clock.timeout
+                };
+                // a deadline too far away to represent never passes
+                return Pollee::Clock(Instant::now().checked_add(Duration::from_nanos(timeout)));
+            }
+            types::SubscriptionU::FdRead(rw) => (rw.file_descriptor, Interest::Readable),
+            types::SubscriptionU::FdWrite(rw) => (rw.file_descriptor, Interest::Writable),
+        };
+        if self.wasi().is_socket(fd) {
+            return match self.wasi().sockets().raw_fd(fd) {
+                Ok(raw_fd) => Pollee::Fd(raw_fd, interest),
+                Err(e) => Pollee::Ready(e, 0),
+            };
+        }
+        let host_fd = match self.route(fd) {
+            Ok(Resolved::Host(host_fd)) => host_fd,
+            Ok(Resolved::Virtual) => {
+                return match interest {
+                    Interest::Readable => match self.virtual_fs().readable_bytes(fd) {
+                        Ok(nbytes) => Pollee::Ready(types::Errno::Success, nbytes),
+                        Err(e) => Pollee::Ready(e, 0),
+                    },
+                    Interest::Writable => Pollee::Ready(types::Errno::Success, 0),
+                };
+            }
+            Err(e) => return Pollee::Ready(e, 0),
+        };
+        let wasi = self.wasi();
+        match wasi.fd_fdstat_get(host_fd) {
+            Ok(fdstat)
+                if fdstat
+                    .fs_rights_base
+                    .contains(&types::Rights::POLL_FD_READWRITE) =>
+            {
+                ()
+            }
+            Ok(_) => return Pollee::Ready(types::Errno::Notcapable, 0),
+            Err(e) => return Pollee::Ready(e, 0),
+        }
+        if let Some(raw_fd) = wasi.stdio_fd(host_fd) {
+            return Pollee::Fd(raw_fd, interest);
+        }
+        // the other files of the host are always ready, with the rest of the file to read
+        let nbytes = match interest {
+            Interest::Readable => match (wasi.fd_filestat_get(host_fd), wasi.fd_tell(host_fd)) {
+                (Ok(filestat), Ok(offset)) => filestat.size.saturating_sub(offset),
+                _ => 0,
+            },
+            Interest::Writable => 0,
+        };
+        Pollee::Ready(types::Errno::Success, nbytes)
+    }
+
+    /// Fail with `Rofs` if the guest's filesystem is read-only.
+    fn check_writable(&self) -> Result<(), types::Errno> {
+        if self.wasi().read_only_fs() {
+            Err(types::Errno::Rofs)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Fail with `Rofs` if `fd` is in the guest's filesystem and it is read-only, rather than a
+    /// standard stream or a socket.
+    fn check_writable_fd(&self, fd: types::Fd) -> Result<(), types::Errno> {
+        match self.route(fd) {
+            Ok(Resolved::Host(host_fd)) if self.wasi().is_stdio(host_fd) => Ok(()),
+            Ok(_) => self.check_writable(),
+            // sockets, and file descriptors that are not open, fail in the operation itself
+            Err(_) => Ok(()),
+        }
+    }
+
+    /// Check whether `path_open()` would create, truncate, or write to a file.
+    fn opens_for_writing(
+        oflags: types::Oflags,
+        fs_rights_base: types::Rights,
+        fdflags: types::Fdflags,
+    ) -> bool {
+        let writes = types::Rights::FD_WRITE
+            | types::Rights::FD_ALLOCATE
+            | types::Rights::FD_FILESTAT_SET_SIZE;
+        oflags.contains(&types::Oflags::CREAT)
+            || oflags.contains(&types::Oflags::TRUNC)
+            || (fs_rights_base & writes) != types::Rights::EMPTY_FLAGS
+            || fdflags.contains(&types::Fdflags::APPEND)
+    }
+
+    /// Run `write`, which writes the guest's `ciovs` to the host file descriptor `fd` at `offset`,
+    /// or at its current position if there is none, if their length is within the guest's quota
+    /// for files, and count what it wrote against the quota.
+    ///
+    /// The standard streams are not files, so writes to them are not counted, but writes to a file
+    /// that has been renumbered over one are.
+    fn quota_file_write(
+        &self,
+        fd: types::Fd,
+        ciovs: &types::CiovecArray<'_>,
+        offset: Option<types::Filesize>,
+        write: impl FnOnce() -> Result<types::Size, types::Errno>,
+    ) -> Result<types::Size, types::Errno> {
+        if self.wasi().is_stdio(fd) {
+            return write();
+        }
+        let len = self.ciovs_len(ciovs)?;
+        self.wasi().check_file_write_quota(len)?;
+        let end = |size: types::Filesize| match offset {
+            Some(offset) => offset.saturating_add(len),
+            // appending writes at the end, and other writes at the position, which can be past it
+            None => {
+                let position = self.wasi().fd_tell(fd).unwrap_or(size);
+                std::cmp::max(size, position).saturating_add(len)
+            }
+        };
+        let written = self.temp_dir_resize(fd, end, write)?;
+        self.wasi().wrote_to_file(u64::from(written));
+        Ok(written)
+    }
+
+    /// Run `resize`, which can make the file of the host file descriptor `fd` as large as `end`
+    /// returns for its current size, if the files in the guest's temporary directory stay within
+    /// its size limit, and count the change in its size against the limit.
+    ///
+    /// Files outside the temporary directory are resized without a limit.
+    fn temp_dir_resize<T>(
+        &self,
+        fd: types::Fd,
+        end: impl FnOnce(types::Filesize) -> types::Filesize,
+        resize: impl FnOnce() -> Result<T, types::Errno>,
+    ) -> Result<T, types::Errno> {
+        let size = |wasi: &WasiCtx| wasi.fd_filestat_get(fd).map_or(0, |filestat| filestat.size);
+        let before = {
+            let wasi = self.wasi();
+            match wasi.temp_dir_containing(fd) {
+                Some(temp_dir) => {
+                    let before = size(&wasi);
+                    temp_dir.check_growth(before, end(before))?;
+                    before
+                }
+                None => return resize(),
+            }
+        };
+        let res = resize()?;
+        let wasi = self.wasi();
+        if let Some(temp_dir) = wasi.temp_dir_containing(fd) {
+            temp_dir.resized(before, size(&wasi));
+        }
+        Ok(res)
+    }
+
+    /// The total length of the guest's `ciovs`.
+    fn ciovs_len(&self, ciovs: &types::CiovecArray<'_>) -> Result<u64, types::Errno> {
+        let mut len = 0u64;
+        for ciov in ciovs.iter() {
+            let ciov = ciov
+                .and_then(|ciov| ciov.read())
+                .map_err(|e| types::GuestErrorConversion::into_errno(self, e))?;
+            len += u64::from(ciov.buf_len);
+        }
+        Ok(len)
+    }
+
+    /// Run `create`, which creates a file in a directory of the host, if the guest's quota allows
+    /// another, and count it against the quota.
+    fn quota_create(
+        &self,
+        create: impl FnOnce() -> Result<(), types::Errno>,
+    ) -> Result<(), types::Errno> {
+        self.wasi().check_create_quota()?;
+        create()?;
+        self.wasi().created_file();
+        Ok(())
+    }
+
+    /// Run `write`, which writes the guest's `ciovs`, if their length is within the guest's quota,
+    /// and count what it wrote against the quota.
+    fn quota_write(
+        &self,
+        ciovs: &types::CiovecArray<'_>,
+        write: impl FnOnce() -> Result<types::Size, types::Errno>,
+    ) -> Result<types::Size, types::Errno> {
+        let len = self.ciovs_len(ciovs)?;
+        self.wasi().check_write_quota(len)?;
+        let written = write()?;
+        self.wasi().wrote(u64::from(written));
+        Ok(written)
+    }
 }
 
 impl<'a> types::GuestErrorConversion for LucetWasiCtx<'a> {
@@ -45,11 +449,15 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         argv: &GuestPtr<'b, GuestPtr<'b, u8>>,
         argv_buf: &GuestPtr<'b, u8>,
     ) -> Result<(), types::Errno> {
-        self.wasi().args_get(argv, argv_buf)
+        let wasi = self.wasi();
+        wasi.check_args_env_size()?;
+        wasi.args_get(argv, argv_buf)
     }
 
     fn args_sizes_get(&self) -> Result<(types::Size, types::Size), types::Errno> {
-        self.wasi().args_sizes_get()
+        let wasi = self.wasi();
+        wasi.check_args_env_size()?;
+        wasi.args_sizes_get()
     }
 
     fn environ_get<'b>(
@@ -57,15 +465,26 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         environ: &GuestPtr<'b, GuestPtr<'b, u8>>,
         environ_buf: &GuestPtr<'b, u8>,
     ) -> Result<(), types::Errno> {
-        self.wasi().environ_get(environ, environ_buf)
+        let wasi = self.wasi();
+        wasi.check_args_env_size()?;
+        wasi.environ_get(environ, environ_buf)
     }
 
     fn environ_sizes_get(&self) -> Result<(types::Size, types::Size), types::Errno> {
-        self.wasi().environ_sizes_get()
+        let wasi = self.wasi();
+        wasi.check_args_env_size()?;
+        wasi.environ_sizes_get()
     }
 
     fn clock_res_get(&self, id: types::Clockid) -> Result<types::Timestamp, types::Errno> {
-        self.wasi().clock_res_get(id)
+        if let Some(env) = self.deterministic() {
+            return Ok(env.clock_resolution());
+        }
+        let wasi = self.wasi();
+        match wasi.clock() {
+            Some(clock) => clock.resolution(id),
+            None => wasi.clock_res_get(id),
+        }
     }
 
     fn clock_time_get(
@@ -73,7 +492,14 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         id: types::Clockid,
         precision: types::Timestamp,
     ) -> Result<types::Timestamp, types::Errno> {
-        self.wasi().clock_time_get(id, precision)
+        if let Some(env) = self.deterministic() {
+            return Ok(env.read_clock());
+        }
+        let wasi = self.wasi();
+        match wasi.clock() {
+            Some(clock) => clock.time(id),
+            None => wasi.clock_time_get(id, precision),
+        }
     }
 
     fn fd_advise(
@@ -83,7 +509,10 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         len: types::Filesize,
         advice: types::Advice,
     ) -> Result<(), types::Errno> {
-        self.wasi().fd_advise(fd, offset, len, advice)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_advise(fd, offset, len, advice),
+            Resolved::Virtual => self.virtual_fs().fd_advise(fd, offset, len, advice),
+        }
     }
 
     fn fd_allocate(
@@ -92,19 +521,52 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         offset: types::Filesize,
         len: types::Filesize,
     ) -> Result<(), types::Errno> {
-        self.wasi().fd_allocate(fd, offset, len)
+        self.check_writable_fd(fd)?;
+        match self.route(fd)? {
+            Resolved::Host(fd) => {
+                self.wasi().check_file_write_quota(len)?;
+                self.temp_dir_resize(
+                    fd,
+                    |_| offset.saturating_add(len),
+                    || self.wasi().fd_allocate(fd, offset, len),
+                )?;
+                self.wasi().wrote_to_file(len);
+                Ok(())
+            }
+            Resolved::Virtual => self.virtual_fs().fd_allocate(fd, offset, len),
+        }
     }
 
     fn fd_close(&self, fd: types::Fd) -> Result<(), types::Errno> {
-        self.wasi().fd_close(fd)
+        if self.wasi().is_socket(fd) {
+            return self.wasi().close_socket(fd);
+        }
+        match self.route(fd)? {
+            Resolved::Host(host_fd) => {
+                self.wasi().fd_close(host_fd)?;
+                self.wasi().fd_closed(host_fd);
+                self.with_vfs(|vfs| vfs.remove(fd));
+                Ok(())
+            }
+            Resolved::Virtual => self.virtual_fs().fd_close(fd),
+        }
     }
 
     fn fd_datasync(&self, fd: types::Fd) -> Result<(), types::Errno> {
-        self.wasi().fd_datasync(fd)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_datasync(fd),
+            Resolved::Virtual => self.virtual_fs().fd_datasync(fd),
+        }
     }
 
     fn fd_fdstat_get(&self, fd: types::Fd) -> Result<types::Fdstat, types::Errno> {
-        self.wasi().fd_fdstat_get(fd)
+        if self.wasi().is_socket(fd) {
+            return self.wasi().sockets().fdstat(fd);
+        }
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_fdstat_get(fd),
+            Resolved::Virtual => self.virtual_fs().fd_fdstat_get(fd),
+        }
     }
 
     fn fd_fdstat_set_flags(
@@ -112,7 +574,13 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         fd: types::Fd,
         flags: types::Fdflags,
     ) -> Result<(), types::Errno> {
-        self.wasi().fd_fdstat_set_flags(fd, flags)
+        if self.wasi().is_socket(fd) {
+            return self.wasi().sockets().set_flags(fd, flags);
+        }
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_fdstat_set_flags(fd, flags),
+            Resolved::Virtual => self.virtual_fs().fd_fdstat_set_flags(fd, flags),
+        }
     }
 
     fn fd_fdstat_set_rights(
@@ -121,12 +589,26 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         fs_rights_base: types::Rights,
         fs_rights_inheriting: types::Rights,
     ) -> Result<(), types::Errno> {
-        self.wasi()
-            .fd_fdstat_set_rights(fd, fs_rights_base, fs_rights_inheriting)
+        match self.route(fd)? {
+            Resolved::Host(fd) => {
+                self.wasi()
+                    .fd_fdstat_set_rights(fd, fs_rights_base, fs_rights_inheriting)
+            }
+            Resolved::Virtual => {
+                self.virtual_fs()
+                    .fd_fdstat_set_rights(fd, fs_rights_base, fs_rights_inheriting)
+            }
+        }
     }
 
     fn fd_filestat_get(&self, fd: types::Fd) -> Result<types::Filestat, types::Errno> {
-        self.wasi().fd_filestat_get(fd)
+        if self.wasi().is_socket(fd) {
+            return self.wasi().sockets().filestat(fd);
+        }
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_filestat_get(fd),
+            Resolved::Virtual => self.virtual_fs().fd_filestat_get(fd),
+        }
     }
 
     fn fd_filestat_set_size(
@@ -134,7 +616,13 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         fd: types::Fd,
         size: types::Filesize,
     ) -> Result<(), types::Errno> {
-        self.wasi().fd_filestat_set_size(fd, size)
+        self.check_writable_fd(fd)?;
+        match self.route(fd)? {
+            Resolved::Host(fd) => {
+                self.temp_dir_resize(fd, |_| size, || self.wasi().fd_filestat_set_size(fd, size))
+            }
+            Resolved::Virtual => self.virtual_fs().fd_filestat_set_size(fd, size),
+        }
     }
 
     fn fd_filestat_set_times(
@@ -144,7 +632,13 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         mtim: types::Timestamp,
         fst_flags: types::Fstflags,
     ) -> Result<(), types::Errno> {
-        self.wasi().fd_filestat_set_times(fd, atim, mtim, fst_flags)
+        self.check_writable_fd(fd)?;
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_filestat_set_times(fd, atim, mtim, fst_flags),
+            Resolved::Virtual => self
+                .virtual_fs()
+                .fd_filestat_set_times(fd, atim, mtim, fst_flags),
+        }
     }
 
     fn fd_pread(
@@ -153,11 +647,17 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         iovs: &types::IovecArray<'_>,
         offset: types::Filesize,
     ) -> Result<types::Size, types::Errno> {
-        self.wasi().fd_pread(fd, iovs, offset)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_pread(fd, iovs, offset),
+            Resolved::Virtual => self.virtual_fs().fd_pread(fd, iovs, offset),
+        }
     }
 
     fn fd_prestat_get(&self, fd: types::Fd) -> Result<types::Prestat, types::Errno> {
-        self.wasi().fd_prestat_get(fd)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_prestat_get(fd),
+            Resolved::Virtual => self.virtual_fs().fd_prestat_get(fd),
+        }
     }
 
     fn fd_prestat_dir_name(
@@ -166,7 +666,10 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         path: &GuestPtr<u8>,
         path_len: types::Size,
     ) -> Result<(), types::Errno> {
-        self.wasi().fd_prestat_dir_name(fd, path, path_len)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_prestat_dir_name(fd, path, path_len),
+            Resolved::Virtual => self.virtual_fs().fd_prestat_dir_name(fd, path, path_len),
+        }
     }
 
     fn fd_pwrite(
@@ -175,7 +678,13 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         ciovs: &types::CiovecArray<'_>,
         offset: types::Filesize,
     ) -> Result<types::Size, types::Errno> {
-        self.wasi().fd_pwrite(fd, ciovs, offset)
+        self.check_writable_fd(fd)?;
+        self.quota_write(ciovs, || match self.route(fd)? {
+            Resolved::Host(fd) => self.quota_file_write(fd, ciovs, Some(offset), || {
+                self.wasi().fd_pwrite(fd, ciovs, offset)
+            }),
+            Resolved::Virtual => self.virtual_fs().fd_pwrite(fd, ciovs, offset),
+        })
     }
 
     fn fd_read(
@@ -183,7 +692,17 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         fd: types::Fd,
         iovs: &types::IovecArray<'_>,
     ) -> Result<types::Size, types::Errno> {
-        self.wasi().fd_read(fd, iovs)
+        if self.wasi().is_socket(fd) {
+            let (read, _) = socket_op(self.vmctx, fd, Interest::Readable, |dontwait| {
+                let riflags = types::Riflags::EMPTY_FLAGS;
+                self.wasi().sockets().recv(fd, iovs, riflags, dontwait)
+            })?;
+            return Ok(read);
+        }
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_read(fd, iovs),
+            Resolved::Virtual => self.virtual_fs().fd_read(fd, iovs),
+        }
     }
 
     fn fd_readdir(
@@ -193,11 +712,57 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         buf_len: types::Size,
         cookie: types::Dircookie,
     ) -> Result<types::Size, types::Errno> {
-        self.wasi().fd_readdir(fd, buf, buf_len, cookie)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_readdir(fd, buf, buf_len, cookie),
+            Resolved::Virtual => self.virtual_fs().fd_readdir(fd, buf, buf_len, cookie),
+        }
     }
 
     fn fd_renumber(&self, from: types::Fd, to: types::Fd) -> Result<(), types::Errno> {
-        self.wasi().fd_renumber(from, to)
+        {
+            let wasi = self.wasi();
+            match (wasi.is_socket(from), wasi.is_socket(to)) {
+                (false, false) => (),
+                (true, true) => return wasi.renumber_socket(from, to),
+                // sockets are numbered apart from the other file descriptors
+                _ => return Err(types::Errno::Notsup),
+            }
+        }
+        if self.with_vfs(|_| ()).is_none() {
+            let wasi = self.wasi();
+            // `wasi-common` moves `from` to a `to` that is not open, but WASI only renumbers
+            // over file descriptors that are, as the guest's own table of them expects
+            wasi.fd_fdstat_get(from)?;
+            wasi.fd_fdstat_get(to)?;
+            if from == to {
+                return Ok(());
+            }
+            match wasi.fd_renumber(from, to) {
+                // `wasi-common` refuses to renumber its preopens, or over them, so from now on
+                // the guest's file descriptors are numbered apart from those of the host, as a
+                // `VirtualFs` numbers them
+                Err(types::Errno::Notsup) => {
+                    *wasi.renumbered_fds().borrow_mut() = Some(VirtualFs::new());
+                }
+                result => {
+                    result?;
+                    wasi.fd_renumbered(from, to);
+                    return Ok(());
+                }
+            }
+        }
+        // the guest file descriptors are renumbered in place, so the `WasiCtx` only needs to close
+        // the file that `to` referred to
+        let replaced = self
+            .with_vfs(|vfs| vfs.renumber(&self.wasi(), from, to))
+            .expect("the guest's file descriptors are numbered apart")?;
+        if let Some(replaced) = replaced {
+            // the `WasiCtx` refuses to close its preopens, which are then just unreachable
+            if self.wasi().fd_close(replaced).is_ok() {
+                self.wasi().fd_closed(replaced);
+            }
+        }
+        Ok(())
     }
 
     fn fd_seek(
@@ -206,15 +771,24 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         offset: types::Filedelta,
         whence: types::Whence,
     ) -> Result<types::Filesize, types::Errno> {
-        self.wasi().fd_seek(fd, offset, whence)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_seek(fd, offset, whence),
+            Resolved::Virtual => self.virtual_fs().fd_seek(fd, offset, whence),
+        }
     }
 
     fn fd_sync(&self, fd: types::Fd) -> Result<(), types::Errno> {
-        self.wasi().fd_sync(fd)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_sync(fd),
+            Resolved::Virtual => self.virtual_fs().fd_sync(fd),
+        }
     }
 
     fn fd_tell(&self, fd: types::Fd) -> Result<types::Filesize, types::Errno> {
-        self.wasi().fd_tell(fd)
+        match self.route(fd)? {
+            Resolved::Host(fd) => self.wasi().fd_tell(fd),
+            Resolved::Virtual => self.virtual_fs().fd_tell(fd),
+        }
     }
 
     fn fd_write(
@@ -222,7 +796,24 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         fd: types::Fd,
         ciovs: &types::CiovecArray<'_>,
     ) -> Result<types::Size, types::Errno> {
-        self.wasi().fd_write(fd, ciovs)
+        self.check_writable_fd(fd)?;
+        self.quota_write(ciovs, || {
+            if self.wasi().is_socket(fd) {
+                return socket_op(self.vmctx, fd, Interest::Writable, |dontwait| {
+                    self.wasi().sockets().send(fd, ciovs, dontwait)
+                });
+            }
+            match self.route(fd)? {
+                Resolved::Host(fd) => {
+                    let wasi = self.wasi();
+                    match wasi.write_captured(fd, ciovs) {
+                        Some(res) => res,
+                        None => self.quota_file_write(fd, ciovs, None, || wasi.fd_write(fd, ciovs)),
+                    }
+                }
+                Resolved::Virtual => self.virtual_fs().fd_write(fd, ciovs),
+            }
+        })
     }
 
     fn path_create_directory(
@@ -230,7 +821,14 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         dirfd: types::Fd,
         path: &GuestPtr<'_, str>,
     ) -> Result<(), types::Errno> {
-        self.wasi().path_create_directory(dirfd, path)
+        self.check_writable()?;
+        let path = &self.policy_path(path)?;
+        match self.route(dirfd)? {
+            Resolved::Host(dirfd) => {
+                self.quota_create(|| self.wasi().path_create_directory(dirfd, path))
+            }
+            Resolved::Virtual => self.virtual_fs().path_create_directory(dirfd, path),
+        }
     }
 
     fn path_filestat_get(
@@ -239,7 +837,14 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         flags: types::Lookupflags,
         path: &GuestPtr<'_, str>,
     ) -> Result<types::Filestat, types::Errno> {
-        self.wasi().path_filestat_get(dirfd, flags, path)
+        let path = &self.policy_path(path)?;
+        match self.route(dirfd)? {
+            Resolved::Host(dirfd) => {
+                let wasi = self.wasi();
+                wasi.path_filestat_get(dirfd, wasi.lookupflags(dirfd, flags), path)
+            }
+            Resolved::Virtual => self.virtual_fs().path_filestat_get(dirfd, flags, path),
+        }
     }
 
     fn path_filestat_set_times(
@@ -251,8 +856,18 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         mtim: types::Timestamp,
         fst_flags: types::Fstflags,
     ) -> Result<(), types::Errno> {
-        self.wasi()
-            .path_filestat_set_times(dirfd, flags, path, atim, mtim, fst_flags)
+        self.check_writable()?;
+        let path = &self.policy_path(path)?;
+        match self.route(dirfd)? {
+            Resolved::Host(dirfd) => {
+                let wasi = self.wasi();
+                let flags = wasi.lookupflags(dirfd, flags);
+                wasi.path_filestat_set_times(dirfd, flags, path, atim, mtim, fst_flags)
+            }
+            Resolved::Virtual => self
+                .virtual_fs()
+                .path_filestat_set_times(dirfd, flags, path, atim, mtim, fst_flags),
+        }
     }
 
     fn path_link(
@@ -263,8 +878,20 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         new_fd: types::Fd,
         new_path: &GuestPtr<'_, str>,
     ) -> Result<(), types::Errno> {
-        self.wasi()
-            .path_link(old_fd, old_flags, old_path, new_fd, new_path)
+        self.check_writable()?;
+        let old_path = &self.policy_path(old_path)?;
+        let new_path = &self.policy_path(new_path)?;
+        match (self.route(old_fd)?, self.route(new_fd)?) {
+            (Resolved::Host(old_fd), Resolved::Host(new_fd)) => {
+                let wasi = self.wasi();
+                let old_flags = wasi.lookupflags(old_fd, old_flags);
+                wasi.path_link(old_fd, old_flags, old_path, new_fd, new_path)
+            }
+            (Resolved::Virtual, Resolved::Virtual) => self
+                .virtual_fs()
+                .path_link(old_fd, old_flags, old_path, new_fd, new_path),
+            _ => Err(types::Errno::Xdev),
+        }
     }
 
     fn path_open(
@@ -277,15 +904,64 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         fs_rights_inheriting: types::Rights,
         fdflags: types::Fdflags,
     ) -> Result<types::Fd, types::Errno> {
-        self.wasi().path_open(
-            dirfd,
-            dirflags,
-            path,
-            oflags,
-            fs_rights_base,
-            fs_rights_inheriting,
-            fdflags,
-        )
+        let path = &self.policy_path(path)?;
+        if Self::opens_for_writing(oflags, fs_rights_base, fdflags) {
+            self.check_writable()?;
+        }
+        self.wasi().check_open_quota()?;
+        let fd = match self.route(dirfd)? {
+            Resolved::Host(dirfd) => {
+                let wasi = self.wasi();
+                wasi.check_fd_limit()?;
+                let lookupflags = wasi.lookupflags(dirfd, dirflags);
+                let creates = oflags.contains(&types::Oflags::CREAT)
+                    && wasi.path_filestat_get(dirfd, lookupflags, path).is_err();
+                if creates {
+                    wasi.check_create_quota()?;
+                }
+                let fd = wasi.path_open(
+                    dirfd,
+                    lookupflags,
+                    path,
+                    oflags,
+                    fs_rights_base,
+                    fs_rights_inheriting,
+                    fdflags,
+                )?;
+                wasi.fd_opened(dirfd, fd);
+                if creates {
+                    wasi.created_file();
+                }
+                let guest_fd = self.with_vfs(|vfs| vfs.insert_host(fd)).unwrap_or(fd);
+                if wasi.is_socket(guest_fd) {
+                    // the guest has so many files open that they run into its sockets
+                    self.with_vfs(|vfs| vfs.remove(guest_fd));
+                    wasi.fd_close(fd)?;
+                    wasi.fd_closed(fd);
+                    return Err(types::Errno::Mfile);
+                }
+                guest_fd
+            }
+            Resolved::Virtual => {
+                let mut vfs = self.virtual_fs();
+                let fd = vfs.path_open(
+                    dirfd,
+                    dirflags,
+                    path,
+                    oflags,
+                    fs_rights_base,
+                    fs_rights_inheriting,
+                    fdflags,
+                )?;
+                if self.wasi().is_socket(fd) {
+                    vfs.fd_close(fd)?;
+                    return Err(types::Errno::Mfile);
+                }
+                fd
+            }
+        };
+        self.wasi().opened_file();
+        Ok(fd)
     }
 
     fn path_readlink(
@@ -295,7 +971,11 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         buf: &GuestPtr<u8>,
         buf_len: types::Size,
     ) -> Result<types::Size, types::Errno> {
-        self.wasi().path_readlink(dirfd, path, buf, buf_len)
+        let path = &self.policy_path(path)?;
+        match self.route(dirfd)? {
+            Resolved::Host(dirfd) => self.wasi().path_readlink(dirfd, path, buf, buf_len),
+            Resolved::Virtual => self.virtual_fs().path_readlink(dirfd, path, buf, buf_len),
+        }
     }
 
     fn path_remove_directory(
@@ -303,7 +983,12 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         dirfd: types::Fd,
         path: &GuestPtr<'_, str>,
     ) -> Result<(), types::Errno> {
-        self.wasi().path_remove_directory(dirfd, path)
+        self.check_writable()?;
+        let path = &self.policy_path(path)?;
+        match self.route(dirfd)? {
+            Resolved::Host(dirfd) => self.wasi().path_remove_directory(dirfd, path),
+            Resolved::Virtual => self.virtual_fs().path_remove_directory(dirfd, path),
+        }
     }
 
     fn path_rename(
@@ -313,7 +998,18 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         new_fd: types::Fd,
         new_path: &GuestPtr<'_, str>,
     ) -> Result<(), types::Errno> {
-        self.wasi().path_rename(old_fd, old_path, new_fd, new_path)
+        self.check_writable()?;
+        let old_path = &self.policy_path(old_path)?;
+        let new_path = &self.policy_path(new_path)?;
+        match (self.route(old_fd)?, self.route(new_fd)?) {
+            (Resolved::Host(old_fd), Resolved::Host(new_fd)) => {
+                self.wasi().path_rename(old_fd, old_path, new_fd, new_path)
+            }
+            (Resolved::Virtual, Resolved::Virtual) => self
+                .virtual_fs()
+                .path_rename(old_fd, old_path, new_fd, new_path),
+            _ => Err(types::Errno::Xdev),
+        }
     }
 
     fn path_symlink(
@@ -322,7 +1018,17 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         dirfd: types::Fd,
         new_path: &GuestPtr<'_, str>,
     ) -> Result<(), types::Errno> {
-        self.wasi().path_symlink(old_path, dirfd, new_path)
+        self.check_writable()?;
+        if !self.wasi().path_policy().can_create_symlinks() {
+            return Err(types::Errno::Perm);
+        }
+        let new_path = &self.policy_path(new_path)?;
+        match self.route(dirfd)? {
+            Resolved::Host(dirfd) => {
+                self.quota_create(|| self.wasi().path_symlink(old_path, dirfd, new_path))
+            }
+            Resolved::Virtual => self.virtual_fs().path_symlink(old_path, dirfd, new_path),
+        }
     }
 
     fn path_unlink_file(
@@ -330,7 +1036,12 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         dirfd: types::Fd,
         path: &GuestPtr<'_, str>,
     ) -> Result<(), types::Errno> {
-        self.wasi().path_unlink_file(dirfd, path)
+        self.check_writable()?;
+        let path = &self.policy_path(path)?;
+        match self.route(dirfd)? {
+            Resolved::Host(dirfd) => self.wasi().path_unlink_file(dirfd, path),
+            Resolved::Virtual => self.virtual_fs().path_unlink_file(dirfd, path),
+        }
     }
 
     fn poll_oneoff(
@@ -339,7 +1050,24 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
         out: &GuestPtr<types::Event>,
         nsubscriptions: types::Size,
     ) -> Result<types::Size, types::Errno> {
-        self.wasi().poll_oneoff(in_, out, nsubscriptions)
+        if nsubscriptions == 0 {
+            return Err(types::Errno::Inval);
+        }
+        let guest_err = |e| types::GuestErrorConversion::into_errno(self, e);
+        let subs = in_
+            .as_array(nsubscriptions)
+            .iter()
+            .map(|sub_ptr| sub_ptr.and_then(|sub_ptr| sub_ptr.read()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(guest_err)?;
+        let ready = self.poll(subs)?;
+        let count = ready.len() as types::Size;
+        for (i, event) in ready.into_iter().enumerate() {
+            out.add(i as u32)
+                .and_then(|ptr| ptr.write(event))
+                .map_err(guest_err)?;
+        }
+        Ok(count)
     }
 
     fn proc_exit(&self, rval: types::Exitcode) -> Result<(), ()> {
@@ -347,36 +1075,68 @@ impl<'a> wasi_snapshot_preview1::WasiSnapshotPreview1 for LucetWasiCtx<'a> {
     }
 
     fn proc_raise(&self, _sig: types::Signal) -> Result<(), types::Errno> {
-        Err(types::Errno::Inval)
+        // there is no way for an instance to handle a signal, so raising one is not supported;
+        // guests that want to terminate should use `proc_exit`
+        Err(types::Errno::Notsup)
     }
 
     fn sched_yield(&self) -> Result<(), types::Errno> {
+        std::thread::yield_now();
         Ok(())
     }
 
     fn random_get(&self, buf: &GuestPtr<u8>, buf_len: types::Size) -> Result<(), types::Errno> {
-        self.wasi().random_get(buf, buf_len)
+        // the buffer is checked before it is filled, so its length is never trusted for an
+        // allocation
+        let checked_buf = || {
+            buf.as_array(buf_len)
+                .as_slice()
+                .map_err(|e| types::GuestErrorConversion::into_errno(self, e))
+        };
+        if let Some(env) = self.deterministic() {
+            env.fill_random(&mut checked_buf()?);
+        } else if let Some(mut rng) = self.wasi().rng() {
+            rng.fill_bytes(&mut checked_buf()?);
+        } else {
+            return self.wasi().random_get(buf, buf_len);
+        }
+        Ok(())
     }
 
     fn sock_recv(
         &self,
-        _fd: types::Fd,
-        _ri_data: &types::IovecArray<'_>,
-        _ri_flags: types::Riflags,
+        fd: types::Fd,
+        ri_data: &types::IovecArray<'_>,
+        ri_flags: types::Riflags,
     ) -> Result<(types::Size, types::Roflags), types::Errno> {
-        Err(types::Errno::Inval)
+        if self.wasi().is_socket(fd) {
+            return socket_op(self.vmctx, fd, Interest::Readable, |dontwait| {
+                self.wasi().sockets().recv(fd, ri_data, ri_flags, dontwait)
+            });
+        }
+        Err(self.not_a_socket(fd))
     }
 
     fn sock_send(
         &self,
-        _fd: types::Fd,
-        _si_data: &types::CiovecArray<'_>,
+        fd: types::Fd,
+        si_data: &types::CiovecArray<'_>,
         _si_flags: types::Siflags,
     ) -> Result<types::Size, types::Errno> {
-        Err(types::Errno::Inval)
+        if self.wasi().is_socket(fd) {
+            return self.quota_write(si_data, || {
+                socket_op(self.vmctx, fd, Interest::Writable, |dontwait| {
+                    self.wasi().sockets().send(fd, si_data, dontwait)
+                })
+            });
+        }
+        Err(self.not_a_socket(fd))
     }
 
-    fn sock_shutdown(&self, _fd: types::Fd, _how: types::Sdflags) -> Result<(), types::Errno> {
-        Err(types::Errno::Inval)
+    fn sock_shutdown(&self, fd: types::Fd, how: types::Sdflags) -> Result<(), types::Errno> {
+        if self.wasi().is_socket(fd) {
+            return self.wasi().sockets().shutdown(fd, how);
+        }
+        Err(self.not_a_socket(fd))
     }
 }
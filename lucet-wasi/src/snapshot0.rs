@@ -0,0 +1,262 @@
+//! The `wasi_unstable` snapshot of WASI, which guests compiled before preview1 import.
+//!
+//! [`bindings()`](../fn.bindings.html) binds the `wasi_unstable` module as well as
+//! `wasi_snapshot_preview1`, so guests that import either one, or both, run with the same
+//! [`WasiCtx`](../struct.WasiCtx.html). The two snapshots have the same functions, and most of
+//! them take the same arguments, so they are bound to the same hostcalls. The ones that do not
+//! are translated here:
+//!
+//! - `fd_seek()` numbers `whence` differently, with `WHENCE_CUR` as 0, `WHENCE_END` as 1, and
+//!   `WHENCE_SET` as 2.
+//! - `fd_filestat_get()` and `path_filestat_get()` store a 32-bit `nlink`, which saturates, and
+//!   the fields after it 8 bytes earlier.
+//! - `poll_oneoff()` subscriptions to clocks carry an `identifier` before the clock's id, which
+//!   makes each subscription 56 bytes long.
+
+use crate::runtime::wasi_snapshot_preview1::WasiSnapshotPreview1;
+use crate::runtime::{types, LucetWasiCtx};
+use lucet_module::bindings::Bindings;
+use lucet_runtime::lucet_hostcall;
+use lucet_runtime::vmctx::Vmctx;
+use lucet_wiggle::runtime::{LucetMemory, TraceArg};
+use lucet_wiggle::{GuestError, GuestMemory, GuestPtr};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+/// The functions of `wasi_unstable` whose arguments are laid out differently than in preview1.
+const TRANSLATED: &[&str] = &[
+    "fd_seek",
+    "fd_filestat_get",
+    "path_filestat_get",
+    "poll_oneoff",
+];
+
+/// The size of a `wasi_unstable` subscription in the guest's memory.
+const SUBSCRIPTION_SIZE: u32 = 56;
+
+/// The bindings of the `wasi_unstable` module, given the `preview1` bindings of its functions.
+pub(crate) fn bindings(preview1: &Bindings) -> Bindings {
+    let funcs = preview1.hash_map()["wasi_snapshot_preview1"]
+        .iter()
+        .map(|(name, symbol)| {
+            let symbol = if TRANSLATED.contains(&name.as_str()) {
+                format!("lucet_wasi_unstable_{}", name)
+            } else {
+                symbol.clone()
+            };
+            (name.clone(), symbol)
+        })
+        .collect();
+    let mut bindings = HashMap::new();
+    bindings.insert("wasi_unstable".to_owned(), funcs);
+    Bindings::new(bindings)
+}
+
+/// Run a `wasi_unstable` function, traced like the functions of preview1 are, with `args` to
+/// decode its arguments, returning its errno.
+fn hostcall(
+    vmctx: &Vmctx,
+    name: &'static str,
+    args: impl Fn(&LucetMemory<'_>) -> Vec<(&'static str, TraceArg)>,
+    f: impl FnOnce(&LucetWasiCtx<'_>, &LucetMemory<'_>) -> Result<(), types::Errno>,
+) -> i32 {
+    crate::trace::enter(vmctx);
+    let memory = LucetMemory::new(vmctx);
+    let r = match f(&LucetWasiCtx::new(vmctx), &memory) {
+        Ok(()) => i32::from(u16::from(types::Errno::Success)),
+        Err(e) => i32::from(u16::from(e)),
+    };
+    crate::trace::exit(vmctx, name, &|| args(&memory), &r);
+    r
+}
+
+fn guest_errno(_e: GuestError) -> types::Errno {
+    types::Errno::Inval
+}
+
+/// A pointer to the field at `offset` in the guest structure at `base`.
+fn field<'a, T>(
+    memory: &'a dyn GuestMemory,
+    base: u32,
+    offset: u32,
+) -> Result<GuestPtr<'a, T>, GuestError> {
+    let ptr = base.checked_add(offset).ok_or(GuestError::PtrOverflow)?;
+    Ok(GuestPtr::new(memory, ptr))
+}
+
+/// Store `stat` in the guest at `buf`, laid out as a `wasi_unstable` filestat.
+fn write_filestat(
+    memory: &dyn GuestMemory,
+    buf: u32,
+    stat: &types::Filestat,
+) -> Result<(), GuestError> {
+    let nlink = std::cmp::min(stat.nlink, u64::from(u32::max_value())) as u32;
+    field(memory, buf, 0)?.write(stat.dev)?;
+    field(memory, buf, 8)?.write(stat.ino)?;
+    field(memory, buf, 16)?.write(stat.filetype)?;
+    field(memory, buf, 20)?.write(nlink)?;
+    field(memory, buf, 24)?.write(stat.size)?;
+    field(memory, buf, 32)?.write(stat.atim)?;
+    field(memory, buf, 40)?.write(stat.mtim)?;
+    field(memory, buf, 48)?.write(stat.ctim)
+}
+
+/// Read the `i`th `wasi_unstable` subscription of the array at `subs` in the guest.
+fn read_subscription(
+    memory: &dyn GuestMemory,
+    subs: u32,
+    i: u32,
+) -> Result<types::Subscription, GuestError> {
+    let base = i
+        .checked_mul(SUBSCRIPTION_SIZE)
+        .and_then(|offset| subs.checked_add(offset))
+        .ok_or(GuestError::PtrOverflow)?;
+    let u = match field::<types::Eventtype>(memory, base, 8)?.read()? {
+        // the `identifier` at 16 is only for the guest's own use, since events report the
+        // `userdata` of their subscription
+        types::Eventtype::Clock => types::SubscriptionU::Clock(types::SubscriptionClock {
+            id: field(memory, base, 24)?.read()?,
+            timeout: field(memory, base, 32)?.read()?,
+            precision: field(memory, base, 40)?.read()?,
+            flags: field(memory, base, 48)?.read()?,
+        }),
+        types::Eventtype::FdRead => types::SubscriptionU::FdRead(types::SubscriptionFdReadwrite {
+            file_descriptor: field(memory, base, 16)?.read()?,
+        }),
+        types::Eventtype::FdWrite => {
+            types::SubscriptionU::FdWrite(types::SubscriptionFdReadwrite {
+                file_descriptor: field(memory, base, 16)?.read()?,
+            })
+        }
+    };
+    Ok(types::Subscription {
+        userdata: field(memory, base, 0)?.read()?,
+        u,
+    })
+}
+
+#[lucet_hostcall(deterministic)]
+#[no_mangle]
+pub fn lucet_wasi_unstable_fd_seek(
+    vmctx: &Vmctx,
+    fd: u32,
+    offset: i64,
+    whence: u32,
+    newoffset: u32,
+) -> i32 {
+    let args = |memory: &LucetMemory<'_>| {
+        vec![
+            ("fd", TraceArg::Value(fd as i64)),
+            ("offset", TraceArg::Value(offset)),
+            ("whence", TraceArg::Value(whence as i64)),
+            ("newoffset", TraceArg::out(memory, newoffset, 8)),
+        ]
+    };
+    hostcall(vmctx, "fd_seek", args, |ctx, memory| {
+        let whence = match whence {
+            0 => types::Whence::Cur,
+            1 => types::Whence::End,
+            2 => types::Whence::Set,
+            _ => return Err(types::Errno::Inval),
+        };
+        let pos = ctx.fd_seek(types::Fd::from(fd), offset, whence)?;
+        GuestPtr::<types::Filesize>::new(memory, newoffset)
+            .write(pos)
+            .map_err(guest_errno)
+    })
+}
+
+#[lucet_hostcall(deterministic)]
+#[no_mangle]
+pub fn lucet_wasi_unstable_fd_filestat_get(vmctx: &Vmctx, fd: u32, buf: u32) -> i32 {
+    let args = |_: &LucetMemory<'_>| {
+        vec![
+            ("fd", TraceArg::Value(fd as i64)),
+            ("buf", TraceArg::Ptr(buf)),
+        ]
+    };
+    hostcall(vmctx, "fd_filestat_get", args, |ctx, memory| {
+        let stat = ctx.fd_filestat_get(types::Fd::from(fd))?;
+        write_filestat(memory, buf, &stat).map_err(guest_errno)
+    })
+}
+
+#[lucet_hostcall(deterministic)]
+#[no_mangle]
+pub fn lucet_wasi_unstable_path_filestat_get(
+    vmctx: &Vmctx,
+    fd: u32,
+    flags: u32,
+    path: u32,
+    path_len: u32,
+    buf: u32,
+) -> i32 {
+    let args = |memory: &LucetMemory<'_>| {
+        vec![
+            ("fd", TraceArg::Value(fd as i64)),
+            ("flags", TraceArg::Value(flags as i64)),
+            ("path", TraceArg::str(memory, path, path_len)),
+            ("buf", TraceArg::Ptr(buf)),
+        ]
+    };
+    hostcall(vmctx, "path_filestat_get", args, |ctx, memory| {
+        let flags = types::Lookupflags::try_from(flags).map_err(|_| types::Errno::Inval)?;
+        let path = GuestPtr::<str>::new(memory, (path, path_len));
+        let stat = ctx.path_filestat_get(types::Fd::from(fd), flags, &path)?;
+        write_filestat(memory, buf, &stat).map_err(guest_errno)
+    })
+}
+
+#[lucet_hostcall(deterministic)]
+#[no_mangle]
+pub fn lucet_wasi_unstable_poll_oneoff(
+    vmctx: &Vmctx,
+    in_: u32,
+    out: u32,
+    nsubscriptions: u32,
+    nevents: u32,
+) -> i32 {
+    let args = |memory: &LucetMemory<'_>| {
+        vec![
+            ("in", TraceArg::Ptr(in_)),
+            ("out", TraceArg::Ptr(out)),
+            ("nsubscriptions", TraceArg::Value(nsubscriptions as i64)),
+            ("nevents", TraceArg::out(memory, nevents, 4)),
+        ]
+    };
+    hostcall(vmctx, "poll_oneoff", args, |ctx, memory| {
+        if nsubscriptions == 0 {
+            return Err(types::Errno::Inval);
+        }
+        let subs = (0..nsubscriptions)
+            .map(|i| read_subscription(memory, in_, i))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(guest_errno)?;
+        let ready = ctx.poll(subs)?;
+        let count = ready.len() as types::Size;
+        // events are laid out the same in both snapshots
+        let out = GuestPtr::<types::Event>::new(memory, out);
+        for (i, event) in ready.into_iter().enumerate() {
+            out.add(i as u32)
+                .and_then(|ptr| ptr.write(event))
+                .map_err(guest_errno)?;
+        }
+        GuestPtr::<types::Size>::new(memory, nevents)
+            .write(count)
+            .map_err(guest_errno)
+    })
+}
+
+/// Make sure the `wasi_unstable` hostcalls are linked, like `export_wasi_funcs()` does for the
+/// others.
+pub(crate) fn init() {
+    let funcs: &[*const extern "C" fn()] = &[
+        lucet_wasi_unstable_fd_seek as _,
+        lucet_wasi_unstable_fd_filestat_get as _,
+        lucet_wasi_unstable_path_filestat_get as _,
+        lucet_wasi_unstable_poll_oneoff as _,
+    ];
+    for func in funcs {
+        assert_ne!(*func, std::ptr::null(), "hostcall address is not null");
+    }
+}
@@ -0,0 +1,575 @@
+//! Sockets that guests can use to serve and make network connections.
+//!
+//! WASI preview1 has no way to create sockets, only to receive from, send to, and shut down ones
+//! the guest already has. The embedder gives sockets to a guest with
+//! [`WasiCtxBuilder::preopened_socket()`](../struct.WasiCtxBuilder.html#method.preopened_socket)
+//! before it runs, or [`WasiCtx::grant_socket()`](../struct.WasiCtx.html#method.grant_socket)
+//! while it runs. Their file descriptors start at [`FIRST_SOCKET_FD`](constant.FIRST_SOCKET_FD.html),
+//! in the order they are given, so the embedder can tell the guest about them through its
+//! arguments or environment.
+//!
+//! On top of `sock_recv`, `sock_send`, and `sock_shutdown`, and `fd_read` and `fd_write`, which
+//! work on any socket, guests compiled with [`socket_bindings()`](fn.socket_bindings.html) can
+//! import two functions from the `lucet_wasi_sockets` module, which return an errno:
+//!
+//! - `sock_accept(fd: u32, fd_out: *mut u32) -> u32` accepts a connection on a listening TCP
+//!   socket, and stores the file descriptor of the connection in `fd_out`.
+//! - `sock_connect(addr: *const u8, addr_len: u32, fd_out: *mut u32) -> u32` opens a TCP
+//!   connection to the address `addr`, such as `"127.0.0.1:8080"` or `"[::1]:443"`, and stores
+//!   its file descriptor in `fd_out`. Only the addresses allowed with
+//!   [`WasiCtxBuilder::allow_connect()`](../struct.WasiCtxBuilder.html#method.allow_connect) can
+//!   be connected to; any other fails with `ENOTCAPABLE`.
+//!
+//! Both wait for at most a few milliseconds at a time, so that an instance terminated while it
+//! waits stops, with `EINTR` returned from the hostcall it was in.
+//!
+//! Sockets can be polled with `poll_oneoff()` together with clocks and virtual files, but not
+//! with other host files, whose events then fail with `ENOTSUP`. With
+//! [`WasiCtxBuilder::async_io()`](../struct.WasiCtxBuilder.html#method.async_io), the guest's
+//! blocking operations on sockets yield to the embedder; see [`IoWait`](../struct.IoWait.html).
+
+use crate::async_io::{poll_fds, socket_op, Interest};
+use crate::runtime::{types, POLL_SLICE_MS};
+use crate::vfs::{gather, read_at};
+use lucet_module::bindings::Bindings;
+use lucet_runtime::lucet_hostcall;
+use lucet_runtime::vmctx::Vmctx;
+use lucet_wiggle::runtime::GuestBufs;
+use lucet_wiggle::{GuestError, GuestPtr};
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+
+/// The file descriptor of the first socket given to a guest.
+///
+/// Sockets are numbered apart from the other file descriptors of the guest, so that they can be
+/// known before it runs.
+pub const FIRST_SOCKET_FD: u32 = 1 << 16;
+
+/// The most a `sock_recv()` into buffers that overlap each other receives at once.
+const RECV_BOUNCE_SIZE: usize = 64 * 1024;
+
+/// A socket that can be given to a guest.
+#[derive(Debug)]
+pub enum Socket {
+    /// A listening TCP socket, which the guest can accept connections on.
+    TcpListener(TcpListener),
+    /// A TCP connection.
+    TcpStream(TcpStream),
+    /// A UDP socket, which the guest can only use if it is connected.
+    Udp(UdpSocket),
+}
+
+impl From<TcpListener> for Socket {
+    fn from(listener: TcpListener) -> Self {
+        Socket::TcpListener(listener)
+    }
+}
+
+impl From<TcpStream> for Socket {
+    fn from(stream: TcpStream) -> Self {
+        Socket::TcpStream(stream)
+    }
+}
+
+impl From<UdpSocket> for Socket {
+    fn from(socket: UdpSocket) -> Self {
+        Socket::Udp(socket)
+    }
+}
+
+impl Socket {
+    fn raw_fd(&self) -> RawFd {
+        match self {
+            Socket::TcpListener(listener) => listener.as_raw_fd(),
+            Socket::TcpStream(stream) => stream.as_raw_fd(),
+            Socket::Udp(socket) => socket.as_raw_fd(),
+        }
+    }
+
+    fn filetype(&self) -> types::Filetype {
+        match self {
+            Socket::Udp(_) => types::Filetype::SocketDgram,
+            _ => types::Filetype::SocketStream,
+        }
+    }
+}
+
+/// The rights of a socket's file descriptor.
+fn socket_rights() -> types::Rights {
+    use types::Rights as R;
+    R::FD_READ
+        | R::FD_WRITE
+        | R::FD_FDSTAT_SET_FLAGS
+        | R::FD_FILESTAT_GET
+        | R::POLL_FD_READWRITE
+        | R::SOCK_SHUTDOWN
+}
+
+/// The sockets of a guest, and the addresses it may connect to.
+#[derive(Debug, Default)]
+pub(crate) struct SocketTable {
+    sockets: BTreeMap<u32, Socket>,
+    /// The addresses `connect()` allows, where port 0 allows every port of the address.
+    allowlist: Vec<SocketAddr>,
+}
+
+impl SocketTable {
+    pub(crate) fn new(sockets: Vec<Socket>, allowlist: Vec<SocketAddr>) -> Self {
+        let mut table = SocketTable {
+            sockets: BTreeMap::new(),
+            allowlist,
+        };
+        for socket in sockets {
+            table.insert(socket);
+        }
+        table
+    }
+
+    pub(crate) fn contains(&self, fd: types::Fd) -> bool {
+        self.sockets.contains_key(&u32::from(fd))
+    }
+
+    /// Give a socket to the guest, returning its file descriptor.
+    pub(crate) fn insert(&mut self, socket: Socket) -> types::Fd {
+        let fd = (FIRST_SOCKET_FD..)
+            .find(|fd| !self.sockets.contains_key(fd))
+            .expect("file descriptors are not exhausted");
+        self.sockets.insert(fd, socket);
+        types::Fd::from(fd)
+    }
+
+    fn get(&self, fd: types::Fd) -> Result<&Socket, types::Errno> {
+        self.sockets.get(&u32::from(fd)).ok_or(types::Errno::Badf)
+    }
+
+    /// The file descriptor of the host that the socket `fd` is.
+    pub(crate) fn raw_fd(&self, fd: types::Fd) -> Result<RawFd, types::Errno> {
+        self.get(fd).map(Socket::raw_fd)
+    }
+
+    /// Check whether the guest made the socket `fd` nonblocking.
+    pub(crate) fn is_nonblocking(&self, fd: types::Fd) -> Result<bool, types::Errno> {
+        Ok(fcntl_flags(self.raw_fd(fd)?)? & libc::O_NONBLOCK != 0)
+    }
+
+    pub(crate) fn close(&mut self, fd: types::Fd) -> Result<(), types::Errno> {
+        self.sockets
+            .remove(&u32::from(fd))
+            .map(drop)
+            .ok_or(types::Errno::Badf)
+    }
+
+    /// Move the socket `from` to `to`, which must also be a socket.
+    pub(crate) fn renumber(&mut self, from: types::Fd, to: types::Fd) -> Result<(), types::Errno> {
+        let (from, to) = (u32::from(from), u32::from(to));
+        if !(self.sockets.contains_key(&from) && self.sockets.contains_key(&to)) {
+            return Err(types::Errno::Badf);
+        }
+        if from != to {
+            let socket = self.sockets.remove(&from).expect("socket exists");
+            self.sockets.insert(to, socket);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn fdstat(&self, fd: types::Fd) -> Result<types::Fdstat, types::Errno> {
+        let socket = self.get(fd)?;
+        let mut fs_flags = types::Fdflags::EMPTY_FLAGS;
+        if fcntl_flags(socket.raw_fd())? & libc::O_NONBLOCK != 0 {
+            fs_flags = types::Fdflags::NONBLOCK;
+        }
+        Ok(types::Fdstat {
+            fs_filetype: socket.filetype(),
+            fs_flags,
+            fs_rights_base: socket_rights(),
+            fs_rights_inheriting: types::Rights::EMPTY_FLAGS,
+        })
+    }
+
+    pub(crate) fn set_flags(
+        &self,
+        fd: types::Fd,
+        flags: types::Fdflags,
+    ) -> Result<(), types::Errno> {
+        let socket = self.get(fd)?;
+        if (flags & !types::Fdflags::NONBLOCK) != types::Fdflags::EMPTY_FLAGS {
+            return Err(types::Errno::Notsup);
+        }
+        let raw_fd = socket.raw_fd();
+        let mut fl = fcntl_flags(raw_fd)? & !libc::O_NONBLOCK;
+        if flags.contains(&types::Fdflags::NONBLOCK) {
+            fl |= libc::O_NONBLOCK;
+        }
+        if unsafe { libc::fcntl(raw_fd, libc::F_SETFL, fl) } == -1 {
+            return Err(last_errno());
+        }
+        Ok(())
+    }
+
+    pub(crate) fn filestat(&self, fd: types::Fd) -> Result<types::Filestat, types::Errno> {
+        Ok(types::Filestat {
+            dev: 0,
+            ino: 0,
+            filetype: self.get(fd)?.filetype(),
+            nlink: 1,
+            size: 0,
+            atim: 0,
+            mtim: 0,
+            ctim: 0,
+        })
+    }
+
+    pub(crate) fn recv(
+        &self,
+        fd: types::Fd,
+        iovs: &types::IovecArray<'_>,
+        riflags: types::Riflags,
+        dontwait: bool,
+    ) -> Result<(types::Size, types::Roflags), types::Errno> {
+        let socket = self.get(fd)?;
+        let mut bufs = vec![];
+        let mut len = 0usize;
+        for iov in iovs.iter() {
+            let iov = iov.and_then(|iov| iov.read()).map_err(guest_errno)?;
+            let buf = iov.buf.as_array(iov.buf_len);
+            // each buffer must be in the guest's memory, even if they overlap each other
+            buf.as_slice().map_err(guest_errno)?;
+            bufs.push(buf);
+            len = len.saturating_add(iov.buf_len as usize);
+        }
+        let mut flags = 0;
+        if riflags.contains(&types::Riflags::RECV_PEEK) {
+            flags |= libc::MSG_PEEK;
+        }
+        if riflags.contains(&types::Riflags::RECV_WAITALL) {
+            flags |= libc::MSG_WAITALL;
+        }
+        if dontwait {
+            flags |= libc::MSG_DONTWAIT;
+        }
+        let read = match GuestBufs::borrow(bufs) {
+            Ok(mut borrowed) => {
+                let mut slices = borrowed.io_slices_mut();
+                let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+                // an `IoSliceMut` has the layout of the `iovec` it wraps
+                msg.msg_iov = slices.as_mut_ptr() as *mut libc::iovec;
+                msg.msg_iovlen = slices.len() as _;
+                let received = unsafe { libc::recvmsg(socket.raw_fd(), &mut msg, flags) };
+                if received < 0 {
+                    return Err(last_errno());
+                }
+                received as types::Size
+            }
+            // buffers that overlap cannot be received into at once, so they get a copy in turn,
+            // of at most what a bounce buffer holds
+            Err(GuestError::PtrBorrowed(_)) => {
+                let mut data = vec![0; std::cmp::min(len, RECV_BOUNCE_SIZE)];
+                let received = unsafe {
+                    libc::recv(socket.raw_fd(), data.as_mut_ptr() as _, data.len(), flags)
+                };
+                if received < 0 {
+                    return Err(last_errno());
+                }
+                data.truncate(received as usize);
+                read_at(&data, 0, iovs)?
+            }
+            Err(e) => return Err(guest_errno(e)),
+        };
+        Ok((read, types::Roflags::EMPTY_FLAGS))
+    }
+
+    pub(crate) fn send(
+        &self,
+        fd: types::Fd,
+        ciovs: &types::CiovecArray<'_>,
+        dontwait: bool,
+    ) -> Result<types::Size, types::Errno> {
+        let socket = self.get(fd)?;
+        // a guest writing to a closed connection gets EPIPE rather than killing the process
+        #[cfg(target_os = "linux")]
+        let mut flags = libc::MSG_NOSIGNAL;
+        #[cfg(not(target_os = "linux"))]
+        let mut flags = 0;
+        if dontwait {
+            flags |= libc::MSG_DONTWAIT;
+        }
+        let mut bufs = vec![];
+        for ciov in ciovs.iter() {
+            let ciov = ciov.and_then(|ciov| ciov.read()).map_err(guest_errno)?;
+            bufs.push(ciov.buf.as_array(ciov.buf_len));
+        }
+        let sent = match GuestBufs::borrow(bufs) {
+            Ok(borrowed) => {
+                let slices = borrowed.io_slices();
+                let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+                // an `IoSlice` has the layout of the `iovec` it wraps, which is only read from
+                msg.msg_iov = slices.as_ptr() as *mut libc::iovec;
+                msg.msg_iovlen = slices.len() as _;
+                unsafe { libc::sendmsg(socket.raw_fd(), &msg, flags) }
+            }
+            // buffers that overlap cannot be borrowed at once, so they are sent from a copy
+            Err(GuestError::PtrBorrowed(_)) => {
+                let data = gather(ciovs)?;
+                unsafe { libc::send(socket.raw_fd(), data.as_ptr() as _, data.len(), flags) }
+            }
+            Err(e) => return Err(guest_errno(e)),
+        };
+        if sent < 0 {
+            return Err(last_errno());
+        }
+        Ok(sent as types::Size)
+    }
+
+    pub(crate) fn shutdown(&self, fd: types::Fd, how: types::Sdflags) -> Result<(), types::Errno> {
+        let socket = self.get(fd)?;
+        let how = match (
+            how.contains(&types::Sdflags::RD),
+            how.contains(&types::Sdflags::WR),
+        ) {
+            (true, true) => libc::SHUT_RDWR,
+            (true, false) => libc::SHUT_RD,
+            (false, true) => libc::SHUT_WR,
+            (false, false) => return Err(types::Errno::Inval),
+        };
+        if unsafe { libc::shutdown(socket.raw_fd(), how) } == -1 {
+            return Err(last_errno());
+        }
+        Ok(())
+    }
+
+    /// Accept a connection on the listening socket `fd`, returning the connection's socket.
+    ///
+    /// If `dontwait` is set, this fails with `Again` rather than waiting for a connection.
+    /// Otherwise, it waits until there is a connection, or until `terminated()`.
+    fn accept(
+        &self,
+        fd: types::Fd,
+        dontwait: bool,
+        terminated: &dyn Fn() -> bool,
+    ) -> Result<Socket, types::Errno> {
+        match self.get(fd)? {
+            Socket::TcpListener(_)
+                if dontwait && self.poll(&[(fd, types::Eventtype::FdRead)], 0)?.is_empty() =>
+            {
+                Err(types::Errno::Again)
+            }
+            Socket::TcpListener(listener) => {
+                wait_for(listener.as_raw_fd(), Interest::Readable, terminated)?;
+                let (stream, _) = listener.accept().map_err(io_errno)?;
+                Ok(Socket::TcpStream(stream))
+            }
+            _ => Err(types::Errno::Inval),
+        }
+    }
+
+    /// Connect to `addr`, if it is allowed, waiting until the connection is made or until
+    /// `terminated()`.
+    fn connect(&self, addr: &str, terminated: &dyn Fn() -> bool) -> Result<Socket, types::Errno> {
+        let addr = addr
+            .parse::<SocketAddr>()
+            .map_err(|_| types::Errno::Inval)?;
+        let allowed = self.allowlist.iter().any(|allowed| {
+            allowed.ip() == addr.ip() && (allowed.port() == 0 || allowed.port() == addr.port())
+        });
+        if !allowed {
+            return Err(types::Errno::Notcapable);
+        }
+        let stream = connect_stream(addr, terminated)?;
+        Ok(Socket::TcpStream(stream))
+    }
+
+    /// Poll sockets for the events `subs`, for at most `timeout_ms` milliseconds or forever if it
+    /// is negative, returning the index of each subscription that is ready with its event.
+    pub(crate) fn poll(
+        &self,
+        subs: &[(types::Fd, types::Eventtype)],
+        timeout_ms: i32,
+    ) -> Result<Vec<(usize, types::EventFdReadwrite)>, types::Errno> {
+        let fds = subs
+            .iter()
+            .map(|(fd, type_)| {
+                let interest = match type_ {
+                    types::Eventtype::FdWrite => Interest::Writable,
+                    _ => Interest::Readable,
+                };
+                Ok((self.get(*fd)?.raw_fd(), interest))
+            })
+            .collect::<Result<Vec<_>, types::Errno>>()?;
+        poll_fds(&fds, timeout_ms).map_err(io_errno)
+    }
+}
+
+/// Wait until `raw_fd` is ready for `interest`, polling it a slice of time at a time so that the
+/// wait ends with `Intr` once `terminated()`.
+fn wait_for(
+    raw_fd: RawFd,
+    interest: Interest,
+    terminated: &dyn Fn() -> bool,
+) -> Result<(), types::Errno> {
+    loop {
+        if !poll_fds(&[(raw_fd, interest)], POLL_SLICE_MS)
+            .map_err(io_errno)?
+            .is_empty()
+        {
+            return Ok(());
+        }
+        if terminated() {
+            return Err(types::Errno::Intr);
+        }
+    }
+}
+
+/// Open a TCP connection to `addr` without blocking, then wait for it with `wait_for()`.
+fn connect_stream(
+    addr: SocketAddr,
+    terminated: &dyn Fn() -> bool,
+) -> Result<TcpStream, types::Errno> {
+    use nix::sys::socket::{self, AddressFamily, InetAddr, SockAddr, SockFlag, SockType};
+    let family = match addr {
+        SocketAddr::V4(_) => AddressFamily::Inet,
+        SocketAddr::V6(_) => AddressFamily::Inet6,
+    };
+    let raw_fd =
+        socket::socket(family, SockType::Stream, SockFlag::empty(), None).map_err(nix_errno)?;
+    // the stream owns the socket from here, so it is closed on every error
+    let stream = unsafe { TcpStream::from_raw_fd(raw_fd) };
+    if unsafe { libc::fcntl(raw_fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        return Err(last_errno());
+    }
+    stream.set_nonblocking(true).map_err(io_errno)?;
+    match socket::connect(raw_fd, &SockAddr::new_inet(InetAddr::from_std(&addr))) {
+        Ok(()) => (),
+        Err(e) if e.as_errno() == Some(nix::errno::Errno::EINPROGRESS) => {
+            wait_for(raw_fd, Interest::Writable, terminated)?;
+            if let Some(e) = stream.take_error().map_err(io_errno)? {
+                return Err(io_errno(e));
+            }
+        }
+        Err(e) => return Err(nix_errno(e)),
+    }
+    stream.set_nonblocking(false).map_err(io_errno)?;
+    Ok(stream)
+}
+
+fn fcntl_flags(raw_fd: RawFd) -> Result<libc::c_int, types::Errno> {
+    match unsafe { libc::fcntl(raw_fd, libc::F_GETFL) } {
+        -1 => Err(last_errno()),
+        fl => Ok(fl),
+    }
+}
+
+fn last_errno() -> types::Errno {
+    io_errno(io::Error::last_os_error())
+}
+
+fn nix_errno(e: nix::Error) -> types::Errno {
+    e.as_errno().map_or(types::Errno::Io, |errno| {
+        io_errno(io::Error::from_raw_os_error(errno as i32))
+    })
+}
+
+/// The errno for an error from a socket operation.
+pub(crate) fn io_errno(e: io::Error) -> types::Errno {
+    use types::Errno as E;
+    match e.raw_os_error() {
+        Some(libc::EAGAIN) => E::Again,
+        Some(libc::EADDRNOTAVAIL) => E::Addrnotavail,
+        Some(libc::EBADF) => E::Badf,
+        Some(libc::ECONNABORTED) => E::Connaborted,
+        Some(libc::ECONNREFUSED) => E::Connrefused,
+        Some(libc::ECONNRESET) => E::Connreset,
+        Some(libc::EHOSTUNREACH) => E::Hostunreach,
+        Some(libc::EINTR) => E::Intr,
+        Some(libc::EINVAL) => E::Inval,
+        Some(libc::EMFILE) => E::Mfile,
+        Some(libc::ENETDOWN) => E::Netdown,
+        Some(libc::ENETUNREACH) => E::Netunreach,
+        Some(libc::ENOTCONN) => E::Notconn,
+        Some(libc::EPIPE) => E::Pipe,
+        Some(libc::ETIMEDOUT) => E::Timedout,
+        _ => E::Io,
+    }
+}
+
+/// The errno for an invalid guest pointer, as `LucetWasiCtx::into_errno()` returns.
+fn guest_errno(_e: GuestError) -> types::Errno {
+    types::Errno::Inval
+}
+
+/// The bindings for the socket functions that WASI preview1 lacks, which guests that import them
+/// must be compiled with, in addition to [`bindings()`](../fn.bindings.html).
+pub fn socket_bindings() -> Bindings {
+    let mut funcs = HashMap::new();
+    funcs.insert(
+        "sock_accept".to_owned(),
+        "lucet_wasi_sock_accept".to_owned(),
+    );
+    funcs.insert(
+        "sock_connect".to_owned(),
+        "lucet_wasi_sock_connect".to_owned(),
+    );
+    let mut bindings = HashMap::new();
+    bindings.insert("lucet_wasi_sockets".to_owned(), funcs);
+    Bindings::new(bindings)
+}
+
+/// Run a socket function that gives the guest a new socket, storing its file descriptor at the
+/// guest pointer `fd_out`.
+fn new_socket(
+    vmctx: &Vmctx,
+    fd_out: u32,
+    f: impl FnOnce(&SocketTable, &lucet_wiggle::runtime::LucetMemory) -> Result<Socket, types::Errno>,
+) -> Result<(), types::Errno> {
+    let memory = lucet_wiggle::runtime::LucetMemory::new(vmctx);
+    let wasi = vmctx.get_embed_ctx::<crate::WasiCtx>();
+    let fd_out = GuestPtr::<u32>::new(&memory, fd_out);
+    // check the pointer first, so that a connection is not lost when it is invalid
+    fd_out
+        .read()
+        .map_err(guest_errno)
+        .and_then(|_| wasi.check_fd_limit())
+        .and_then(|_| f(&wasi.sockets(), &memory))
+        .and_then(|socket| {
+            let fd = wasi.grant_socket(socket);
+            fd_out.write(u32::from(fd)).map_err(guest_errno)
+        })
+}
+
+fn errno(res: Result<(), types::Errno>) -> u32 {
+    match res {
+        Ok(()) => u32::from(u16::from(types::Errno::Success)),
+        Err(e) => u32::from(u16::from(e)),
+    }
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_sock_accept(vmctx: &Vmctx, fd: u32, fd_out: u32) -> u32 {
+    let fd = types::Fd::from(fd);
+    errno(socket_op(vmctx, fd, Interest::Readable, |dontwait| {
+        new_socket(vmctx, fd_out, |sockets, _| {
+            sockets.accept(fd, dontwait, &|| vmctx.termination_requested())
+        })
+    }))
+}
+
+#[lucet_hostcall]
+#[no_mangle]
+pub fn lucet_wasi_sock_connect(vmctx: &Vmctx, addr: u32, addr_len: u32, fd_out: u32) -> u32 {
+    errno(new_socket(vmctx, fd_out, |sockets, memory| {
+        let addr = GuestPtr::<str>::new(memory, (addr, addr_len));
+        let addr = addr.as_str().map_err(guest_errno)?;
+        sockets.connect(&*addr, &|| vmctx.termination_requested())
+    }))
+}
+
+/// Make sure the socket hostcalls are linked, like `export_wasi_funcs()` does for the others.
+pub(crate) fn init() {
+    let funcs: &[*const extern "C" fn()] =
+        &[lucet_wasi_sock_accept as _, lucet_wasi_sock_connect as _];
+    for func in funcs {
+        assert_ne!(*func, std::ptr::null(), "hostcall address is not null");
+    }
+}
@@ -0,0 +1,141 @@
+//! Temporary directories of the host that belong to one instance.
+//!
+//! A context built with
+//! [`WasiCtxBuilder::temp_dir()`](struct.WasiCtxBuilder.html#method.temp_dir) preopens an empty
+//! directory of its own, which it deletes with everything in it when it is dropped:
+//!
+//! ```no_run
+//! # use lucet_wasi::WasiCtxBuilder;
+//! let ctx = WasiCtxBuilder::new()
+//!     .temp_dir("/tmp", 64 * 1024 * 1024)
+//!     .expect("the directory can be created")
+//!     .build()
+//!     .unwrap();
+//! println!("the guest's /tmp is {}", ctx.temp_dir().unwrap().display());
+//! ```
+
+use crate::runtime::types;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A directory created for an instance in the temporary directory of the host, whose files can
+/// take up at most `max_bytes`, and which is deleted with them when it is dropped.
+pub(crate) struct TempDir {
+    path: PathBuf,
+    max_bytes: u64,
+    /// The total size of the files in the directory, as far as the guest's writes have changed it.
+    ///
+    /// The guest can also shrink it by deleting files, or grow it by moving them in, so it is
+    /// measured again whenever a write would go over `max_bytes`.
+    used: Cell<u64>,
+    /// The file descriptors that refer to the directory, or to files and directories in it.
+    fds: RefCell<HashSet<u32>>,
+}
+
+impl TempDir {
+    /// Create an empty directory, which the guest will find preopened at `preopen`.
+    pub(crate) fn create(preopen: types::Fd, max_bytes: u64) -> io::Result<Self> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        loop {
+            let name = format!(
+                "lucet-wasi-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            );
+            let path = std::env::temp_dir().join(name);
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => {
+                    return Ok(TempDir {
+                        path,
+                        max_bytes,
+                        used: Cell::new(0),
+                        fds: RefCell::new(std::iter::once(u32::from(preopen)).collect()),
+                    })
+                }
+                // left behind by an earlier process with the same id
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn contains_fd(&self, fd: types::Fd) -> bool {
+        self.fds.borrow().contains(&u32::from(fd))
+    }
+
+    /// Record that `fd` was opened in the directory `dirfd`.
+    pub(crate) fn fd_opened(&self, dirfd: types::Fd, fd: types::Fd) {
+        if self.contains_fd(dirfd) {
+            self.fds.borrow_mut().insert(u32::from(fd));
+        }
+    }
+
+    pub(crate) fn fd_closed(&self, fd: types::Fd) {
+        self.fds.borrow_mut().remove(&u32::from(fd));
+    }
+
+    pub(crate) fn fd_renumbered(&self, from: types::Fd, to: types::Fd) {
+        let mut fds = self.fds.borrow_mut();
+        fds.remove(&u32::from(to));
+        if fds.remove(&u32::from(from)) {
+            fds.insert(u32::from(to));
+        }
+    }
+
+    /// Check that a file of `size` bytes in the directory can grow to `end` bytes without the
+    /// files taking up more than `max_bytes`, failing with `Dquot` otherwise.
+    pub(crate) fn check_growth(&self, size: u64, end: u64) -> Result<(), types::Errno> {
+        let growth = end.saturating_sub(size);
+        if self.used.get().saturating_add(growth) <= self.max_bytes {
+            return Ok(());
+        }
+        self.used.set(dir_size(&self.path));
+        if self.used.get().saturating_add(growth) <= self.max_bytes {
+            Ok(())
+        } else {
+            Err(types::Errno::Dquot)
+        }
+    }
+
+    /// Record that a file in the directory went from `before` bytes to `after`.
+    pub(crate) fn resized(&self, before: u64, after: u64) {
+        let used = self.used.get();
+        self.used.set(if after >= before {
+            used.saturating_add(after - before)
+        } else {
+            used.saturating_sub(before - after)
+        });
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        // the directory is the instance's own, so there is nobody to report a failure to
+        fs::remove_dir_all(&self.path).ok();
+    }
+}
+
+/// The total size of the files under `path`.
+fn dir_size(path: &Path) -> u64 {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(_) => return 0,
+    };
+    entries
+        .filter_map(Result::ok)
+        .map(|entry| match entry.file_type() {
+            Ok(file_type) if file_type.is_dir() => dir_size(&entry.path()),
+            Ok(file_type) if file_type.is_file() => entry.metadata().map_or(0, |m| m.len()),
+            _ => 0,
+        })
+        .sum()
+}
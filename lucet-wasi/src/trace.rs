@@ -0,0 +1,170 @@
+//! Tracing of the WASI calls a guest makes, like `strace` does for the system calls of a process.
+//!
+//! A context built with
+//! [`WasiCtxBuilder::trace()`](struct.WasiCtxBuilder.html#method.trace) hands a
+//! [`HostcallTrace`](struct.HostcallTrace.html) to its callback as each call of
+//! `wasi_snapshot_preview1` or `wasi_unstable` returns; one built with
+//! [`trace_to()`](struct.WasiCtxBuilder.html#method.trace_to) writes them out one per line:
+//!
+//! ```text
+//! fd_write(fd=1, iovs=[13], nwritten=13) = Success <0.000014s>
+//! path_open(fd=3, dirflags=1, path="data/input.txt", oflags=0, ...) = Noent <0.000021s>
+//! ```
+//!
+//! The arguments are decoded from the guest's memory by their witx types, as
+//! [`TraceArg`](enum.TraceArg.html)s: paths are shown as strings, arrays of buffers as the length
+//! of each buffer, and results stored through pointers, such as `nwritten` or the `opened_fd` of
+//! `path_open()`, as the values the call stored. Other pointers are addresses in its memory.
+//! `proc_exit()` does not return, so it is not traced. Tracing can be turned off and on again
+//! while the instance runs with [`WasiCtx::set_tracing()`](struct.WasiCtx.html#method.set_tracing).
+
+use crate::ctx::WasiCtx;
+use crate::runtime::types;
+use lucet_runtime::vmctx::Vmctx;
+use lucet_wiggle::runtime::TraceArg;
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Write;
+use std::time::{Duration, Instant};
+
+/// A WASI call the guest made, and what it returned.
+///
+/// It displays as `strace` would show it, as in
+/// `fd_write(fd=1, iovs=[13], nwritten=13) = Success <0.000014s>`.
+#[derive(Clone, Debug)]
+pub struct HostcallTrace<'a> {
+    name: &'static str,
+    args: &'a [(&'static str, TraceArg)],
+    errno: Option<types::Errno>,
+    elapsed: Duration,
+}
+
+impl<'a> HostcallTrace<'a> {
+    /// The name of the function, such as `fd_write`.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The names of the arguments, and their values, decoded once the call returned.
+    pub fn args(&self) -> &'a [(&'static str, TraceArg)] {
+        self.args
+    }
+
+    /// The error the call returned, which is `Errno::Success` if it succeeded, or `None` for a
+    /// function that returns nothing.
+    pub fn errno(&self) -> Option<types::Errno> {
+        self.errno
+    }
+
+    /// How long the call took, including any time the instance was yielded in it.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+impl<'a> fmt::Display for HostcallTrace<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for (i, (name, value)) in self.args.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        write!(f, ")")?;
+        if let Some(errno) = self.errno {
+            write!(f, " = {:?}", errno)?;
+        }
+        write!(f, " <{:.6}s>", self.elapsed.as_secs_f64())
+    }
+}
+
+/// Where the traces of a context go, and whether it traces at all right now.
+pub(crate) struct Tracer {
+    sink: Box<dyn Fn(&HostcallTrace<'_>)>,
+    enabled: Cell<bool>,
+    /// When the call in progress started, if it is traced.
+    start: Cell<Option<Instant>>,
+}
+
+impl Tracer {
+    pub(crate) fn new(sink: Box<dyn Fn(&HostcallTrace<'_>)>) -> Self {
+        Tracer {
+            sink,
+            enabled: Cell::new(true),
+            start: Cell::new(None),
+        }
+    }
+
+    /// A tracer that writes each trace to `out` on a line of its own.
+    pub(crate) fn to_writer(out: impl Write + 'static) -> Self {
+        let out = RefCell::new(out);
+        Tracer::new(Box::new(move |trace| {
+            // tracing is best-effort, and must not change how the guest runs
+            writeln!(out.borrow_mut(), "{}", trace).ok();
+        }))
+    }
+
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled.get()
+    }
+
+    pub(crate) fn set_enabled(&self, enabled: bool) {
+        self.enabled.set(enabled);
+    }
+}
+
+/// What a hostcall returned, as far as its trace is concerned.
+pub(crate) trait TraceResult {
+    fn errno(&self) -> Option<types::Errno>;
+}
+
+impl TraceResult for () {
+    fn errno(&self) -> Option<types::Errno> {
+        None
+    }
+}
+
+impl TraceResult for i32 {
+    fn errno(&self) -> Option<types::Errno> {
+        types::Errno::try_from(*self).ok()
+    }
+}
+
+/// Called by each WASI hostcall before it runs.
+pub(crate) fn enter(vmctx: &Vmctx) {
+    if let Some(tracer) = vmctx.get_embed_ctx::<WasiCtx>().tracer() {
+        if tracer.enabled() {
+            tracer.start.set(Some(Instant::now()));
+        }
+    }
+}
+
+/// Called by each WASI hostcall when it returns `result`, with `args` to decode its arguments if
+/// the call is traced.
+pub(crate) fn exit(
+    vmctx: &Vmctx,
+    name: &'static str,
+    args: &dyn Fn() -> Vec<(&'static str, TraceArg)>,
+    result: &impl TraceResult,
+) {
+    let wasi = vmctx.get_embed_ctx::<WasiCtx>();
+    let tracer = match wasi.tracer() {
+        Some(tracer) => tracer,
+        None => return,
+    };
+    // a call is traced only if tracing was on both when it started and when it returned
+    let start = match tracer.start.take() {
+        Some(start) if tracer.enabled() => start,
+        _ => return,
+    };
+    // decoding the arguments is not part of the call
+    let elapsed = start.elapsed();
+    (tracer.sink)(&HostcallTrace {
+        name,
+        args: &args(),
+        errno: result.errno(),
+        elapsed,
+    });
+}
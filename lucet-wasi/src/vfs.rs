@@ -0,0 +1,1332 @@
+//! An in-memory filesystem that can be given to guests alongside, or instead of, host directories.
+//!
+//! A [`VirtualFs`](struct.VirtualFs.html) is added as an embedder context to an instance,
+//! alongside its `WasiCtx`. Its directories are preopened for the guest after the preopens of the
+//! `WasiCtx`, and the file hostcalls on them, and on everything opened through them, are served
+//! from memory without touching the host filesystem:
+//!
+//! ```no_run
+//! # use lucet_runtime::{Limits, MmapRegion, Module, Region};
+//! # use lucet_wasi::{VirtualDir, VirtualFs, WasiCtxBuilder};
+//! # fn f(module: std::sync::Arc<dyn Module>) {
+//! let config = VirtualDir::new()
+//!     .with_file("app.toml", "threads = 4\n")
+//!     .with_dir("assets", VirtualDir::new().with_file("index.html", "<html></html>"));
+//! let region = MmapRegion::create(1, &Limits::default()).unwrap();
+//! let inst = region
+//!     .new_instance_builder(module)
+//!     .with_embed_ctx(WasiCtxBuilder::new().build().unwrap())
+//!     .with_embed_ctx(VirtualFs::new().with_preopen("/config", config))
+//!     .build()
+//!     .unwrap();
+//! # }
+//! ```
+//!
+//! Changes the guest makes stay in memory, and can be inspected by the host with
+//! [`VirtualFs::read_file()`](struct.VirtualFs.html#method.read_file).
+//!
+//! A directory can also overlay a host directory, with
+//! [`VirtualDir::from_host()`](struct.VirtualDir.html#method.from_host): the guest sees the files
+//! of the host directory, which are read from the host until the guest first changes them, when
+//! they are copied into memory. The host directory itself is never written to, so a guest that
+//! insists on writing scratch files next to its inputs can run without write access to them.
+//! [`VirtualFs::with_write_limit()`](struct.VirtualFs.html#method.with_write_limit) bounds how
+//! much memory the guest's writes can take up.
+//!
+//! When a `VirtualFs` is present, the file descriptors the guest sees are its own, and are
+//! translated to those of the `WasiCtx` for the hostcalls that go to the host. Virtual
+//! directories do not support symbolic links, and their timestamps start at zero and only change
+//! when the guest sets them.
+
+use crate::runtime::types;
+use lucet_wiggle::{GuestError, GuestPtr};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wasi_common::wasi::wasi_snapshot_preview1::WasiSnapshotPreview1;
+use wasi_common::WasiCtx;
+
+/// A directory to add to a `VirtualFs`, along with its contents.
+#[derive(Clone, Debug, Default)]
+pub struct VirtualDir {
+    entries: BTreeMap<String, VirtualEntry>,
+}
+
+#[derive(Clone, Debug)]
+enum VirtualEntry {
+    File(Vec<u8>),
+    /// A file of the host, whose contents are read from it until they change.
+    HostFile(PathBuf),
+    Dir(VirtualDir),
+}
+
+impl VirtualDir {
+    /// Create an empty directory.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file with the given contents, replacing any entry with the same name.
+    ///
+    /// Panics if `name` is empty, `.`, `..`, or contains `/`.
+    pub fn with_file(mut self, name: &str, contents: impl Into<Vec<u8>>) -> Self {
+        self.entries
+            .insert(entry_name(name), VirtualEntry::File(contents.into()));
+        self
+    }
+
+    /// Add a subdirectory, replacing any entry with the same name.
+    ///
+    /// Panics if `name` is empty, `.`, `..`, or contains `/`.
+    pub fn with_dir(mut self, name: &str, dir: VirtualDir) -> Self {
+        self.entries
+            .insert(entry_name(name), VirtualEntry::Dir(dir));
+        self
+    }
+
+    /// Create a directory that overlays the host directory at `path`, with its files and
+    /// subdirectories as they are now.
+    ///
+    /// The contents of the files are read from the host when the guest reads them, until it
+    /// changes them, and anything the guest adds, changes, or removes only changes the virtual
+    /// directory. Symbolic links, other special files, and entries whose names are not UTF-8 are
+    /// left out.
+    pub fn from_host(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut dir = VirtualDir::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let file_type = entry.file_type()?;
+            let entry = if file_type.is_dir() {
+                VirtualEntry::Dir(VirtualDir::from_host(entry.path())?)
+            } else if file_type.is_file() {
+                VirtualEntry::HostFile(entry.path())
+            } else {
+                continue;
+            };
+            dir.entries.insert(name, entry);
+        }
+        Ok(dir)
+    }
+}
+
+fn entry_name(name: &str) -> String {
+    assert!(
+        !(name.is_empty() || name == "." || name == ".." || name.contains('/')),
+        "invalid virtual directory entry name `{}`",
+        name
+    );
+    name.to_owned()
+}
+
+/// The directories preopened for a guest from memory, and the file descriptors it has open.
+///
+/// See the [module documentation](index.html) for how to use it.
+#[derive(Default)]
+pub struct VirtualFs {
+    preopens: Vec<(String, NodeRef)>,
+    /// The file descriptors of the guest. This mirrors those of the `WasiCtx` on first use.
+    fds: BTreeMap<u32, Slot>,
+    mirrored: bool,
+    next_ino: u64,
+    budget: Rc<WriteBudget>,
+}
+
+type NodeRef = Rc<RefCell<Node>>;
+
+struct Node {
+    ino: u64,
+    nlink: u64,
+    atim: types::Timestamp,
+    mtim: types::Timestamp,
+    kind: NodeKind,
+    /// The file of the host that the contents of a file are read from, until they change.
+    host_file: Option<PathBuf>,
+}
+
+/// The memory the guest's writes have taken up, and how much they may.
+#[derive(Debug, Default)]
+struct WriteBudget {
+    max: Option<u64>,
+    used: Cell<u64>,
+}
+
+enum NodeKind {
+    File(Vec<u8>),
+    Dir(BTreeMap<String, NodeRef>),
+}
+
+enum Slot {
+    Host(types::Fd),
+    Virtual(OpenNode),
+}
+
+/// A file or directory of a `VirtualFs` that the guest has open.
+struct OpenNode {
+    /// The directories from the preopened one down to the open node, which is last.
+    chain: Vec<NodeRef>,
+    /// The guest path of the directory, if it is preopened.
+    preopen: Option<String>,
+    offset: u64,
+    fdflags: types::Fdflags,
+    rights_base: types::Rights,
+    rights_inheriting: types::Rights,
+}
+
+/// Where the hostcalls on a guest file descriptor go.
+pub(crate) enum Resolved {
+    /// To this file descriptor of the `WasiCtx`.
+    Host(types::Fd),
+    /// To the `VirtualFs`.
+    Virtual,
+}
+
+/// The path of a node relative to a directory: the directory containing it, and its name in that
+/// directory, or `None` if the path names the directory itself, like `.` does.
+struct Lookup {
+    parent: Vec<NodeRef>,
+    name: Option<String>,
+    /// Whether the path ends in `/`, so must name a directory.
+    dir_only: bool,
+}
+
+/// The rights that are meaningful for files.
+fn file_rights() -> types::Rights {
+    use types::Rights as R;
+    R::FD_DATASYNC
+        | R::FD_READ
+        | R::FD_SEEK
+        | R::FD_FDSTAT_SET_FLAGS
+        | R::FD_SYNC
+        | R::FD_TELL
+        | R::FD_WRITE
+        | R::FD_ADVISE
+        | R::FD_ALLOCATE
+        | R::FD_FILESTAT_GET
+        | R::FD_FILESTAT_SET_SIZE
+        | R::FD_FILESTAT_SET_TIMES
+        | R::POLL_FD_READWRITE
+}
+
+/// The rights that are meaningful for directories.
+fn dir_rights() -> types::Rights {
+    use types::Rights as R;
+    R::FD_FDSTAT_SET_FLAGS
+        | R::FD_SYNC
+        | R::FD_ADVISE
+        | R::PATH_CREATE_DIRECTORY
+        | R::PATH_CREATE_FILE
+        | R::PATH_LINK_SOURCE
+        | R::PATH_LINK_TARGET
+        | R::PATH_OPEN
+        | R::FD_READDIR
+        | R::PATH_READLINK
+        | R::PATH_RENAME_SOURCE
+        | R::PATH_RENAME_TARGET
+        | R::PATH_FILESTAT_GET
+        | R::PATH_FILESTAT_SET_SIZE
+        | R::PATH_FILESTAT_SET_TIMES
+        | R::FD_FILESTAT_GET
+        | R::FD_FILESTAT_SET_TIMES
+        | R::PATH_SYMLINK
+        | R::PATH_REMOVE_DIRECTORY
+        | R::PATH_UNLINK_FILE
+}
+
+impl VirtualFs {
+    /// Create a filesystem with no directories.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit how many bytes of file contents the guest's writes can add to the memory of the
+    /// filesystem, beyond which they fail with `Errno::Nospc`.
+    ///
+    /// A file of a host directory counts in full once the guest changes it, and files stop
+    /// counting when the guest removes them.
+    pub fn with_write_limit(mut self, max_bytes: u64) -> Self {
+        self.budget = Rc::new(WriteBudget {
+            max: Some(max_bytes),
+            used: Cell::new(0),
+        });
+        self
+    }
+
+    /// Preopen `dir` for the guest at `guest_path`.
+    ///
+    /// Directories are preopened in the order they are added.
+    pub fn with_preopen(mut self, guest_path: &str, dir: VirtualDir) -> Self {
+        let root = self.build_node(VirtualEntry::Dir(dir));
+        self.preopens.push((guest_path.to_owned(), root));
+        self
+    }
+
+    /// Read the contents of a file by its path in the guest, such as `/config/app.toml`.
+    ///
+    /// Returns `None` if there is no such file in the preopened directories.
+    pub fn read_file(&self, guest_path: &str) -> Option<Vec<u8>> {
+        self.preopens.iter().find_map(|(prefix, root)| {
+            if !guest_path.starts_with(prefix.as_str()) {
+                return None;
+            }
+            let rest = &guest_path[prefix.len()..];
+            if !(rest.starts_with('/') || prefix.ends_with('/')) {
+                return None;
+            }
+            let node = lookup(&[root.clone()], rest.trim_start_matches('/'))
+                .and_then(|lookup| lookup.node())
+                .ok()??;
+            let node = node.borrow();
+            match (&node.kind, &node.host_file) {
+                (NodeKind::File(_), Some(host_file)) => fs::read(host_file).ok(),
+                (NodeKind::File(bytes), None) => Some(bytes.clone()),
+                (NodeKind::Dir(_), _) => None,
+            }
+        })
+    }
+
+    fn build_node(&mut self, entry: VirtualEntry) -> NodeRef {
+        let kind = match entry {
+            VirtualEntry::File(bytes) => NodeKind::File(bytes),
+            VirtualEntry::HostFile(path) => {
+                let node = self.new_node(NodeKind::File(vec![]));
+                node.borrow_mut().host_file = Some(path);
+                return node;
+            }
+            VirtualEntry::Dir(dir) => NodeKind::Dir(
+                dir.entries
+                    .into_iter()
+                    .map(|(name, entry)| (name, self.build_node(entry)))
+                    .collect(),
+            ),
+        };
+        self.new_node(kind)
+    }
+
+    fn new_node(&mut self, kind: NodeKind) -> NodeRef {
+        self.next_ino += 1;
+        Rc::new(RefCell::new(Node {
+            ino: self.next_ino,
+            nlink: 1,
+            atim: 0,
+            mtim: 0,
+            kind,
+            host_file: None,
+        }))
+    }
+
+    /// Find where the hostcalls on `fd` go.
+    pub(crate) fn resolve(
+        &mut self,
+        wasi: &WasiCtx,
+        fd: types::Fd,
+    ) -> Result<Resolved, types::Errno> {
+        self.mirror(wasi);
+        match self.fds.get(&u32::from(fd)) {
+            Some(Slot::Host(host_fd)) => Ok(Resolved::Host(*host_fd)),
+            Some(Slot::Virtual(_)) => Ok(Resolved::Virtual),
+            None => Err(types::Errno::Badf),
+        }
+    }
+
+    /// Populate the file descriptors of the guest, if that has not been done yet.
+    fn mirror(&mut self, wasi: &WasiCtx) {
+        if self.mirrored {
+            return;
+        }
+        self.mirrored = true;
+        // the guest finds its preopens by probing the file descriptors after stdio until one is
+        // missing, so the virtual ones go right after those of the `WasiCtx`, which only has its
+        // stdio and preopens open before the guest runs; a table mirrored later, once the guest
+        // renumbers a preopen, takes in every file it has opened since, gaps and all
+        let mut next = 0;
+        loop {
+            if wasi.fd_fdstat_get(types::Fd::from(next)).is_ok() {
+                self.fds.insert(next, Slot::Host(types::Fd::from(next)));
+            } else if next >= 3 && next > wasi.highest_fd() {
+                break;
+            }
+            next += 1;
+        }
+        for (guest_path, root) in &self.preopens {
+            self.fds.insert(
+                next,
+                Slot::Virtual(OpenNode {
+                    chain: vec![root.clone()],
+                    preopen: Some(guest_path.clone()),
+                    offset: 0,
+                    fdflags: types::Fdflags::EMPTY_FLAGS,
+                    rights_base: dir_rights(),
+                    rights_inheriting: dir_rights() | file_rights(),
+                }),
+            );
+            next += 1;
+        }
+    }
+
+    /// Give a guest file descriptor to a file descriptor the `WasiCtx` opened.
+    pub(crate) fn insert_host(&mut self, host_fd: types::Fd) -> types::Fd {
+        self.insert(Slot::Host(host_fd))
+    }
+
+    fn insert(&mut self, slot: Slot) -> types::Fd {
+        // like POSIX, use the lowest free file descriptor
+        let fd = (0..)
+            .find(|fd| !self.fds.contains_key(fd))
+            .expect("file descriptors are not exhausted");
+        self.fds.insert(fd, slot);
+        types::Fd::from(fd)
+    }
+
+    /// Forget a guest file descriptor, once the `WasiCtx` has closed the file descriptor it
+    /// referred to.
+    pub(crate) fn remove(&mut self, fd: types::Fd) {
+        self.fds.remove(&u32::from(fd));
+    }
+
+    /// Move the guest file descriptor `from` to `to`, returning the file descriptor of the
+    /// `WasiCtx` that `to` referred to, if any, which the caller must close.
+    pub(crate) fn renumber(
+        &mut self,
+        wasi: &WasiCtx,
+        from: types::Fd,
+        to: types::Fd,
+    ) -> Result<Option<types::Fd>, types::Errno> {
+        self.mirror(wasi);
+        let (from, to) = (u32::from(from), u32::from(to));
+        if !(self.fds.contains_key(&from) && self.fds.contains_key(&to)) {
+            return Err(types::Errno::Badf);
+        }
+        if from == to {
+            return Ok(None);
+        }
+        let slot = self.fds.remove(&from).expect("file descriptor exists");
+        match self.fds.insert(to, slot) {
+            Some(Slot::Host(host_fd)) => Ok(Some(host_fd)),
+            _ => Ok(None),
+        }
+    }
+
+    /// The number of bytes that can be read from a virtual file descriptor without blocking, for
+    /// `poll_oneoff()`.
+    pub(crate) fn readable_bytes(
+        &mut self,
+        fd: types::Fd,
+    ) -> Result<types::Filesize, types::Errno> {
+        let open = self.open(fd)?;
+        let node = open.node().borrow();
+        Ok(node.size().saturating_sub(open.offset))
+    }
+
+    fn open(&mut self, fd: types::Fd) -> Result<&mut OpenNode, types::Errno> {
+        match self.fds.get_mut(&u32::from(fd)) {
+            Some(Slot::Virtual(open)) => Ok(open),
+            _ => Err(types::Errno::Badf),
+        }
+    }
+
+    /// The virtual file descriptor `fd`, if it has all of the given rights.
+    fn checked(
+        &mut self,
+        fd: types::Fd,
+        rights: types::Rights,
+    ) -> Result<&mut OpenNode, types::Errno> {
+        let open = self.open(fd)?;
+        if open.rights_base.contains(&rights) {
+            Ok(open)
+        } else {
+            Err(types::Errno::Notcapable)
+        }
+    }
+
+    pub(crate) fn fd_advise(
+        &mut self,
+        fd: types::Fd,
+        _offset: types::Filesize,
+        _len: types::Filesize,
+        _advice: types::Advice,
+    ) -> Result<(), types::Errno> {
+        self.checked(fd, types::Rights::FD_ADVISE)?;
+        Ok(())
+    }
+
+    pub(crate) fn fd_allocate(
+        &mut self,
+        fd: types::Fd,
+        offset: types::Filesize,
+        len: types::Filesize,
+    ) -> Result<(), types::Errno> {
+        let budget = self.budget.clone();
+        let open = self.checked(fd, types::Rights::FD_ALLOCATE)?;
+        let end = offset.checked_add(len).ok_or(types::Errno::Fbig)?;
+        let end = usize::try_from(end).map_err(|_| types::Errno::Fbig)?;
+        let mut node = open.node().borrow_mut();
+        let size = std::cmp::max(node.size(), end as u64);
+        node.modify(&budget, size, |bytes| {
+            if bytes.len() < end {
+                bytes.resize(end, 0);
+            }
+        })
+    }
+
+    pub(crate) fn fd_close(&mut self, fd: types::Fd) -> Result<(), types::Errno> {
+        self.open(fd)?;
+        self.remove(fd);
+        Ok(())
+    }
+
+    pub(crate) fn fd_datasync(&mut self, fd: types::Fd) -> Result<(), types::Errno> {
+        self.checked(fd, types::Rights::FD_DATASYNC)?;
+        Ok(())
+    }
+
+    pub(crate) fn fd_fdstat_get(&mut self, fd: types::Fd) -> Result<types::Fdstat, types::Errno> {
+        let open = self.open(fd)?;
+        Ok(types::Fdstat {
+            fs_filetype: open.node().borrow().filetype(),
+            fs_flags: open.fdflags,
+            fs_rights_base: open.rights_base,
+            fs_rights_inheriting: open.rights_inheriting,
+        })
+    }
+
+    pub(crate) fn fd_fdstat_set_flags(
+        &mut self,
+        fd: types::Fd,
+        flags: types::Fdflags,
+    ) -> Result<(), types::Errno> {
+        self.checked(fd, types::Rights::FD_FDSTAT_SET_FLAGS)?
+            .fdflags = flags;
+        Ok(())
+    }
+
+    pub(crate) fn fd_fdstat_set_rights(
+        &mut self,
+        fd: types::Fd,
+        fs_rights_base: types::Rights,
+        fs_rights_inheriting: types::Rights,
+    ) -> Result<(), types::Errno> {
+        let open = self.open(fd)?;
+        // rights can only be dropped
+        if !(open.rights_base.contains(&fs_rights_base)
+            && open.rights_inheriting.contains(&fs_rights_inheriting))
+        {
+            return Err(types::Errno::Notcapable);
+        }
+        open.rights_base = fs_rights_base;
+        open.rights_inheriting = fs_rights_inheriting;
+        Ok(())
+    }
+
+    pub(crate) fn fd_filestat_get(
+        &mut self,
+        fd: types::Fd,
+    ) -> Result<types::Filestat, types::Errno> {
+        let open = self.checked(fd, types::Rights::FD_FILESTAT_GET)?;
+        let filestat = open.node().borrow().filestat();
+        Ok(filestat)
+    }
+
+    pub(crate) fn fd_filestat_set_size(
+        &mut self,
+        fd: types::Fd,
+        size: types::Filesize,
+    ) -> Result<(), types::Errno> {
+        let budget = self.budget.clone();
+        let open = self.checked(fd, types::Rights::FD_FILESTAT_SET_SIZE)?;
+        let len = usize::try_from(size).map_err(|_| types::Errno::Fbig)?;
+        open.node()
+            .borrow_mut()
+            .modify(&budget, size, |bytes| bytes.resize(len, 0))
+    }
+
+    pub(crate) fn fd_filestat_set_times(
+        &mut self,
+        fd: types::Fd,
+        atim: types::Timestamp,
+        mtim: types::Timestamp,
+        fst_flags: types::Fstflags,
+    ) -> Result<(), types::Errno> {
+        let open = self.checked(fd, types::Rights::FD_FILESTAT_SET_TIMES)?;
+        let result = open.node().borrow_mut().set_times(atim, mtim, fst_flags);
+        result
+    }
+
+    pub(crate) fn fd_pread(
+        &mut self,
+        fd: types::Fd,
+        iovs: &types::IovecArray<'_>,
+        offset: types::Filesize,
+    ) -> Result<types::Size, types::Errno> {
+        let open = self.checked(fd, types::Rights::FD_READ | types::Rights::FD_SEEK)?;
+        let node = open.node().borrow();
+        node.read(offset, iovs)
+    }
+
+    pub(crate) fn fd_prestat_get(&mut self, fd: types::Fd) -> Result<types::Prestat, types::Errno> {
+        let name = self.open(fd)?.preopen.as_ref().ok_or(types::Errno::Badf)?;
+        Ok(types::Prestat::Dir(types::PrestatDir {
+            pr_name_len: name.len() as types::Size,
+        }))
+    }
+
+    pub(crate) fn fd_prestat_dir_name(
+        &mut self,
+        fd: types::Fd,
+        path: &GuestPtr<u8>,
+        path_len: types::Size,
+    ) -> Result<(), types::Errno> {
+        let name = self.open(fd)?.preopen.as_ref().ok_or(types::Errno::Badf)?;
+        if (path_len as usize) < name.len() {
+            return Err(types::Errno::Nametoolong);
+        }
+        let mut buf = path
+            .as_array(name.len() as u32)
+            .as_slice()
+            .map_err(guest_errno)?;
+        buf.copy_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    pub(crate) fn fd_pwrite(
+        &mut self,
+        fd: types::Fd,
+        ciovs: &types::CiovecArray<'_>,
+        offset: types::Filesize,
+    ) -> Result<types::Size, types::Errno> {
+        let budget = self.budget.clone();
+        let open = self.checked(fd, types::Rights::FD_WRITE | types::Rights::FD_SEEK)?;
+        let data = gather(ciovs)?;
+        let mut node = open.node().borrow_mut();
+        node.write(&budget, offset, &data)
+    }
+
+    pub(crate) fn fd_read(
+        &mut self,
+        fd: types::Fd,
+        iovs: &types::IovecArray<'_>,
+    ) -> Result<types::Size, types::Errno> {
+        let open = self.checked(fd, types::Rights::FD_READ)?;
+        let read = open.node().borrow().read(open.offset, iovs)?;
+        open.offset += u64::from(read);
+        Ok(read)
+    }
+
+    pub(crate) fn fd_readdir(
+        &mut self,
+        fd: types::Fd,
+        buf: &GuestPtr<u8>,
+        buf_len: types::Size,
+        cookie: types::Dircookie,
+    ) -> Result<types::Size, types::Errno> {
+        let open = self.checked(fd, types::Rights::FD_READDIR)?;
+        let dir = open.node().borrow();
+        // the parent of a preopened directory is outside of the filesystem, so it stands in for
+        // its own parent
+        let parent = match open.chain.len() {
+            1 => open.node(),
+            len => &open.chain[len - 2],
+        };
+        let parent_ino = parent.borrow().ino;
+        let mut entries = vec![
+            (".".to_owned(), dir.ino, types::Filetype::Directory),
+            ("..".to_owned(), parent_ino, types::Filetype::Directory),
+        ];
+        for (name, child) in dir.entries()? {
+            let child = child.borrow();
+            entries.push((name.clone(), child.ino, child.filetype()));
+        }
+
+        // each entry is a `dirent` followed by the name, and the last one is cut off if it does
+        // not fit
+        let mut out = vec![];
+        for (i, (name, ino, filetype)) in entries.iter().enumerate().skip(cookie as usize) {
+            out.extend_from_slice(&(i as u64 + 1).to_le_bytes());
+            out.extend_from_slice(&ino.to_le_bytes());
+            out.extend_from_slice(&(name.len() as u32).to_le_bytes());
+            out.extend_from_slice(&[u8::from(*filetype), 0, 0, 0]);
+            out.extend_from_slice(name.as_bytes());
+            if out.len() >= buf_len as usize {
+                break;
+            }
+        }
+        let len = std::cmp::min(out.len(), buf_len as usize);
+        let mut buf = buf.as_array(len as u32).as_slice().map_err(guest_errno)?;
+        buf.copy_from_slice(&out[..len]);
+        Ok(len as types::Size)
+    }
+
+    pub(crate) fn fd_seek(
+        &mut self,
+        fd: types::Fd,
+        offset: types::Filedelta,
+        whence: types::Whence,
+    ) -> Result<types::Filesize, types::Errno> {
+        // like `fd_tell()`, asking for the current offset only needs the right to tell
+        let rights = match (offset, whence) {
+            (0, types::Whence::Cur) => types::Rights::FD_TELL,
+            _ => types::Rights::FD_SEEK,
+        };
+        let open = self.checked(fd, rights)?;
+        let base = match whence {
+            types::Whence::Set => 0,
+            types::Whence::Cur => open.offset,
+            types::Whence::End => open.node().borrow().size(),
+        };
+        let new = i128::from(base) + i128::from(offset);
+        if new < 0 {
+            return Err(types::Errno::Inval);
+        }
+        open.offset = u64::try_from(new).map_err(|_| types::Errno::Overflow)?;
+        Ok(open.offset)
+    }
+
+    pub(crate) fn fd_sync(&mut self, fd: types::Fd) -> Result<(), types::Errno> {
+        self.checked(fd, types::Rights::FD_SYNC)?;
+        Ok(())
+    }
+
+    pub(crate) fn fd_tell(&mut self, fd: types::Fd) -> Result<types::Filesize, types::Errno> {
+        Ok(self.checked(fd, types::Rights::FD_TELL)?.offset)
+    }
+
+    pub(crate) fn fd_write(
+        &mut self,
+        fd: types::Fd,
+        ciovs: &types::CiovecArray<'_>,
+    ) -> Result<types::Size, types::Errno> {
+        let budget = self.budget.clone();
+        let open = self.checked(fd, types::Rights::FD_WRITE)?;
+        let data = gather(ciovs)?;
+        let node = open.node().clone();
+        let mut node = node.borrow_mut();
+        let offset = if open.fdflags.contains(&types::Fdflags::APPEND) {
+            node.size()
+        } else {
+            open.offset
+        };
+        let written = node.write(&budget, offset, &data)?;
+        open.offset = offset + u64::from(written);
+        Ok(written)
+    }
+
+    pub(crate) fn path_create_directory(
+        &mut self,
+        fd: types::Fd,
+        path: &GuestPtr<'_, str>,
+    ) -> Result<(), types::Errno> {
+        let open = self.checked(fd, types::Rights::PATH_CREATE_DIRECTORY)?;
+        let lookup = lookup(&open.chain, &guest_str(path)?)?;
+        let name = lookup.name.as_ref().ok_or(types::Errno::Exist)?;
+        if child(lookup.dir(), name)?.is_some() {
+            return Err(types::Errno::Exist);
+        }
+        let dir = self.new_node(NodeKind::Dir(BTreeMap::new()));
+        lookup
+            .dir()
+            .borrow_mut()
+            .entries_mut()?
+            .insert(name.clone(), dir);
+        Ok(())
+    }
+
+    pub(crate) fn path_filestat_get(
+        &mut self,
+        fd: types::Fd,
+        _flags: types::Lookupflags,
+        path: &GuestPtr<'_, str>,
+    ) -> Result<types::Filestat, types::Errno> {
+        let open = self.checked(fd, types::Rights::PATH_FILESTAT_GET)?;
+        let node = lookup(&open.chain, &guest_str(path)?)?
+            .node()?
+            .ok_or(types::Errno::Noent)?;
+        let filestat = node.borrow().filestat();
+        Ok(filestat)
+    }
+
+    pub(crate) fn path_filestat_set_times(
+        &mut self,
+        fd: types::Fd,
+        _flags: types::Lookupflags,
+        path: &GuestPtr<'_, str>,
+        atim: types::Timestamp,
+        mtim: types::Timestamp,
+        fst_flags: types::Fstflags,
+    ) -> Result<(), types::Errno> {
+        let open = self.checked(fd, types::Rights::PATH_FILESTAT_SET_TIMES)?;
+        let node = lookup(&open.chain, &guest_str(path)?)?
+            .node()?
+            .ok_or(types::Errno::Noent)?;
+        let result = node.borrow_mut().set_times(atim, mtim, fst_flags);
+        result
+    }
+
+    pub(crate) fn path_link(
+        &mut self,
+        old_fd: types::Fd,
+        _old_flags: types::Lookupflags,
+        old_path: &GuestPtr<'_, str>,
+        new_fd: types::Fd,
+        new_path: &GuestPtr<'_, str>,
+    ) -> Result<(), types::Errno> {
+        let old_dir = self
+            .checked(old_fd, types::Rights::PATH_LINK_SOURCE)?
+            .chain
+            .clone();
+        let new_dir = &self.checked(new_fd, types::Rights::PATH_LINK_TARGET)?.chain;
+        let node = lookup(&old_dir, &guest_str(old_path)?)?
+            .node()?
+            .ok_or(types::Errno::Noent)?;
+        if node.borrow().is_dir() {
+            return Err(types::Errno::Perm);
+        }
+        let new = lookup(new_dir, &guest_str(new_path)?)?;
+        let name = new.name.as_ref().ok_or(types::Errno::Exist)?;
+        if child(new.dir(), name)?.is_some() {
+            return Err(types::Errno::Exist);
+        }
+        node.borrow_mut().nlink += 1;
+        new.dir()
+            .borrow_mut()
+            .entries_mut()?
+            .insert(name.clone(), node);
+        Ok(())
+    }
+
+    pub(crate) fn path_open(
+        &mut self,
+        fd: types::Fd,
+        _dirflags: types::Lookupflags,
+        path: &GuestPtr<'_, str>,
+        oflags: types::Oflags,
+        fs_rights_base: types::Rights,
+        fs_rights_inheriting: types::Rights,
+        fdflags: types::Fdflags,
+    ) -> Result<types::Fd, types::Errno> {
+        let mut required = types::Rights::PATH_OPEN;
+        if oflags.contains(&types::Oflags::CREAT) {
+            required = required | types::Rights::PATH_CREATE_FILE;
+        }
+        if oflags.contains(&types::Oflags::TRUNC) {
+            required = required | types::Rights::PATH_FILESTAT_SET_SIZE;
+        }
+        let open = self.checked(fd, required)?;
+        // the new file descriptor can only have rights its directory can pass on
+        let inheritable = open.rights_inheriting;
+        if !(inheritable.contains(&fs_rights_base) && inheritable.contains(&fs_rights_inheriting)) {
+            return Err(types::Errno::Notcapable);
+        }
+        let lookup = lookup(&open.chain, &guest_str(path)?)?;
+
+        let node = match lookup.node()? {
+            Some(node) => {
+                if oflags.contains(&(types::Oflags::CREAT | types::Oflags::EXCL)) {
+                    return Err(types::Errno::Exist);
+                }
+                let is_dir = node.borrow().is_dir();
+                if oflags.contains(&types::Oflags::DIRECTORY) && !is_dir {
+                    return Err(types::Errno::Notdir);
+                }
+                if oflags.contains(&types::Oflags::TRUNC) {
+                    let budget = self.budget.clone();
+                    node.borrow_mut().modify(&budget, 0, Vec::clear)?;
+                }
+                node
+            }
+            None if oflags.contains(&types::Oflags::CREAT) => {
+                if oflags.contains(&types::Oflags::DIRECTORY) || lookup.dir_only {
+                    return Err(types::Errno::Isdir);
+                }
+                let name = lookup.name.clone().expect("a missing node has a name");
+                let file = self.new_node(NodeKind::File(vec![]));
+                lookup
+                    .dir()
+                    .borrow_mut()
+                    .entries_mut()?
+                    .insert(name, file.clone());
+                file
+            }
+            None => return Err(types::Errno::Noent),
+        };
+
+        let kind_rights = if node.borrow().is_dir() {
+            dir_rights()
+        } else {
+            file_rights()
+        };
+        let mut chain = lookup.parent;
+        if lookup.name.is_some() {
+            chain.push(node);
+        }
+        Ok(self.insert(Slot::Virtual(OpenNode {
+            chain,
+            preopen: None,
+            offset: 0,
+            fdflags,
+            rights_base: fs_rights_base & kind_rights,
+            rights_inheriting: fs_rights_inheriting,
+        })))
+    }
+
+    pub(crate) fn path_readlink(
+        &mut self,
+        fd: types::Fd,
+        path: &GuestPtr<'_, str>,
+        _buf: &GuestPtr<u8>,
+        _buf_len: types::Size,
+    ) -> Result<types::Size, types::Errno> {
+        let open = self.checked(fd, types::Rights::PATH_READLINK)?;
+        match lookup(&open.chain, &guest_str(path)?)?.node()? {
+            // there are no symbolic links
+            Some(_) => Err(types::Errno::Inval),
+            None => Err(types::Errno::Noent),
+        }
+    }
+
+    pub(crate) fn path_remove_directory(
+        &mut self,
+        fd: types::Fd,
+        path: &GuestPtr<'_, str>,
+    ) -> Result<(), types::Errno> {
+        let open = self.checked(fd, types::Rights::PATH_REMOVE_DIRECTORY)?;
+        let lookup = lookup(&open.chain, &guest_str(path)?)?;
+        let name = lookup.name.as_ref().ok_or(types::Errno::Inval)?;
+        let node = child(lookup.dir(), name)?.ok_or(types::Errno::Noent)?;
+        if !node.borrow().entries()?.is_empty() {
+            return Err(types::Errno::Notempty);
+        }
+        lookup.dir().borrow_mut().entries_mut()?.remove(name);
+        Ok(())
+    }
+
+    pub(crate) fn path_rename(
+        &mut self,
+        old_fd: types::Fd,
+        old_path: &GuestPtr<'_, str>,
+        new_fd: types::Fd,
+        new_path: &GuestPtr<'_, str>,
+    ) -> Result<(), types::Errno> {
+        let old_dir = self
+            .checked(old_fd, types::Rights::PATH_RENAME_SOURCE)?
+            .chain
+            .clone();
+        let new_dir = &self
+            .checked(new_fd, types::Rights::PATH_RENAME_TARGET)?
+            .chain;
+        let old = lookup(&old_dir, &guest_str(old_path)?)?;
+        let old_name = old.name.as_ref().ok_or(types::Errno::Inval)?;
+        let node = old.node()?.ok_or(types::Errno::Noent)?;
+        let new = lookup(new_dir, &guest_str(new_path)?)?;
+        let new_name = new.name.as_ref().ok_or(types::Errno::Inval)?;
+        let is_dir = node.borrow().is_dir();
+        if is_dir && new.parent.iter().any(|dir| Rc::ptr_eq(dir, &node)) {
+            // a directory cannot be moved into itself
+            return Err(types::Errno::Inval);
+        }
+        if let Some(replaced) = new.node()? {
+            if Rc::ptr_eq(&replaced, &node) {
+                return Ok(());
+            }
+            let mut replaced = replaced.borrow_mut();
+            match (is_dir, replaced.is_dir()) {
+                (true, false) => return Err(types::Errno::Notdir),
+                (false, true) => return Err(types::Errno::Isdir),
+                (true, true) if !replaced.entries()?.is_empty() => {
+                    return Err(types::Errno::Notempty)
+                }
+                _ => replaced.unlink(&self.budget),
+            }
+        }
+        old.dir().borrow_mut().entries_mut()?.remove(old_name);
+        new.dir()
+            .borrow_mut()
+            .entries_mut()?
+            .insert(new_name.clone(), node);
+        Ok(())
+    }
+
+    pub(crate) fn path_symlink(
+        &mut self,
+        _old_path: &GuestPtr<'_, str>,
+        fd: types::Fd,
+        _new_path: &GuestPtr<'_, str>,
+    ) -> Result<(), types::Errno> {
+        self.checked(fd, types::Rights::PATH_SYMLINK)?;
+        Err(types::Errno::Notsup)
+    }
+
+    pub(crate) fn path_unlink_file(
+        &mut self,
+        fd: types::Fd,
+        path: &GuestPtr<'_, str>,
+    ) -> Result<(), types::Errno> {
+        let open = self.checked(fd, types::Rights::PATH_UNLINK_FILE)?;
+        let lookup = lookup(&open.chain, &guest_str(path)?)?;
+        let name = lookup.name.as_ref().ok_or(types::Errno::Isdir)?;
+        let node = lookup.node()?.ok_or(types::Errno::Noent)?;
+        if node.borrow().is_dir() {
+            return Err(types::Errno::Isdir);
+        }
+        node.borrow_mut().unlink(&self.budget);
+        lookup.dir().borrow_mut().entries_mut()?.remove(name);
+        Ok(())
+    }
+}
+
+impl OpenNode {
+    fn node(&self) -> &NodeRef {
+        self.chain.last().expect("chain is not empty")
+    }
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        match self.kind {
+            NodeKind::Dir(_) => true,
+            NodeKind::File(_) => false,
+        }
+    }
+
+    fn filetype(&self) -> types::Filetype {
+        match self.kind {
+            NodeKind::Dir(_) => types::Filetype::Directory,
+            NodeKind::File(_) => types::Filetype::RegularFile,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match (&self.kind, &self.host_file) {
+            (NodeKind::Dir(_), _) => 0,
+            (NodeKind::File(_), Some(host_file)) => fs::metadata(host_file).map_or(0, |m| m.len()),
+            (NodeKind::File(bytes), None) => bytes.len() as u64,
+        }
+    }
+
+    /// The size of the contents of the file held in memory, which count against the budget.
+    fn size_in_memory(&self) -> u64 {
+        match (&self.kind, &self.host_file) {
+            (NodeKind::File(bytes), None) => bytes.len() as u64,
+            _ => 0,
+        }
+    }
+
+    /// Read the file at `offset` into `iovs`.
+    fn read(
+        &self,
+        offset: types::Filesize,
+        iovs: &types::IovecArray<'_>,
+    ) -> Result<types::Size, types::Errno> {
+        let bytes = match (&self.kind, &self.host_file) {
+            (NodeKind::Dir(_), _) => return Err(types::Errno::Isdir),
+            (NodeKind::File(_), Some(host_file)) => {
+                return read_host_file(host_file, offset, iovs);
+            }
+            (NodeKind::File(bytes), None) => bytes,
+        };
+        read_at(bytes, offset, iovs)
+    }
+
+    /// Write `data` to the file at `offset`, extending it as needed.
+    fn write(
+        &mut self,
+        budget: &WriteBudget,
+        offset: types::Filesize,
+        data: &[u8],
+    ) -> Result<types::Size, types::Errno> {
+        let end = offset
+            .checked_add(data.len() as u64)
+            .ok_or(types::Errno::Fbig)?;
+        let size = std::cmp::max(self.size(), end);
+        self.modify(budget, size, |bytes| write_at(bytes, offset, data))?
+    }
+
+    /// Change the contents of the file with `modify`, which leaves them `size` bytes long, if the
+    /// budget allows; a file of the host is copied into memory first.
+    fn modify<R>(
+        &mut self,
+        budget: &WriteBudget,
+        size: u64,
+        modify: impl FnOnce(&mut Vec<u8>) -> R,
+    ) -> Result<R, types::Errno> {
+        if self.is_dir() {
+            return Err(types::Errno::Isdir);
+        }
+        budget.resize(self.size_in_memory(), size)?;
+        if let Some(host_file) = self.host_file.take() {
+            match fs::read(&host_file) {
+                Ok(bytes) => self.kind = NodeKind::File(bytes),
+                Err(_) => {
+                    budget.resize(size, 0).expect("shrinking fits the budget");
+                    self.host_file = Some(host_file);
+                    return Err(types::Errno::Io);
+                }
+            }
+        }
+        match &mut self.kind {
+            NodeKind::File(bytes) => Ok(modify(bytes)),
+            NodeKind::Dir(_) => unreachable!("directories are rejected above"),
+        }
+    }
+
+    /// Remove a link to the file, giving its memory back to the budget if it was the last.
+    fn unlink(&mut self, budget: &WriteBudget) {
+        self.nlink -= 1;
+        if self.nlink == 0 {
+            budget
+                .resize(self.size_in_memory(), 0)
+                .expect("shrinking fits the budget");
+        }
+    }
+
+    fn entries(&self) -> Result<&BTreeMap<String, NodeRef>, types::Errno> {
+        match &self.kind {
+            NodeKind::Dir(entries) => Ok(entries),
+            NodeKind::File(_) => Err(types::Errno::Notdir),
+        }
+    }
+
+    fn entries_mut(&mut self) -> Result<&mut BTreeMap<String, NodeRef>, types::Errno> {
+        match &mut self.kind {
+            NodeKind::Dir(entries) => Ok(entries),
+            NodeKind::File(_) => Err(types::Errno::Notdir),
+        }
+    }
+
+    fn filestat(&self) -> types::Filestat {
+        types::Filestat {
+            dev: 0,
+            ino: self.ino,
+            filetype: self.filetype(),
+            nlink: self.nlink,
+            size: self.size(),
+            atim: self.atim,
+            mtim: self.mtim,
+            ctim: self.mtim,
+        }
+    }
+
+    fn set_times(
+        &mut self,
+        atim: types::Timestamp,
+        mtim: types::Timestamp,
+        fst_flags: types::Fstflags,
+    ) -> Result<(), types::Errno> {
+        use types::Fstflags as F;
+        if fst_flags.contains(&(F::ATIM | F::ATIM_NOW))
+            || fst_flags.contains(&(F::MTIM | F::MTIM_NOW))
+        {
+            return Err(types::Errno::Inval);
+        }
+        let now = || {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_nanos() as types::Timestamp)
+                .unwrap_or(0)
+        };
+        if fst_flags.contains(&F::ATIM) {
+            self.atim = atim;
+        } else if fst_flags.contains(&F::ATIM_NOW) {
+            self.atim = now();
+        }
+        if fst_flags.contains(&F::MTIM) {
+            self.mtim = mtim;
+        } else if fst_flags.contains(&F::MTIM_NOW) {
+            self.mtim = now();
+        }
+        Ok(())
+    }
+}
+
+impl WriteBudget {
+    /// Account for a file held in memory changing from `old` to `new` bytes, failing if it grows
+    /// beyond the budget.
+    fn resize(&self, old: u64, new: u64) -> Result<(), types::Errno> {
+        if new <= old {
+            self.used.set(self.used.get().saturating_sub(old - new));
+            return Ok(());
+        }
+        let used = self.used.get().saturating_add(new - old);
+        match self.max {
+            Some(max) if used > max => Err(types::Errno::Nospc),
+            _ => {
+                self.used.set(used);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Lookup {
+    fn dir(&self) -> &NodeRef {
+        self.parent.last().expect("parent is not empty")
+    }
+
+    /// The node the path names, if it exists.
+    fn node(&self) -> Result<Option<NodeRef>, types::Errno> {
+        let node = match &self.name {
+            Some(name) => child(self.dir(), name)?,
+            None => Some(self.dir().clone()),
+        };
+        match node {
+            Some(node) if self.dir_only && !node.borrow().is_dir() => Err(types::Errno::Notdir),
+            node => Ok(node),
+        }
+    }
+}
+
+/// Look up `path` relative to the directory at the end of `dir`.
+///
+/// Like the host directories of a `WasiCtx`, paths cannot be absolute or lead out of the
+/// preopened directory.
+fn lookup(dir: &[NodeRef], path: &str) -> Result<Lookup, types::Errno> {
+    if path.is_empty() {
+        return Err(types::Errno::Noent);
+    }
+    if path.starts_with('/') {
+        return Err(types::Errno::Notcapable);
+    }
+    let mut parent = dir.to_vec();
+    let mut name: Option<String> = None;
+    for component in path.split('/') {
+        // the previous component is not the last, so it must be a directory to descend into
+        if let Some(prev) = name.take() {
+            let next = child(parent.last().expect("parent is not empty"), &prev)?
+                .ok_or(types::Errno::Noent)?;
+            if !next.borrow().is_dir() {
+                return Err(types::Errno::Notdir);
+            }
+            parent.push(next);
+        }
+        match component {
+            "" | "." => (),
+            ".." => {
+                if parent.len() == 1 {
+                    return Err(types::Errno::Notcapable);
+                }
+                parent.pop();
+            }
+            _ => name = Some(component.to_owned()),
+        }
+    }
+    Ok(Lookup {
+        parent,
+        name,
+        dir_only: path.ends_with('/'),
+    })
+}
+
+fn child(dir: &NodeRef, name: &str) -> Result<Option<NodeRef>, types::Errno> {
+    Ok(dir.borrow().entries()?.get(name).cloned())
+}
+
+pub(crate) fn read_at(
+    bytes: &[u8],
+    offset: types::Filesize,
+    iovs: &types::IovecArray<'_>,
+) -> Result<types::Size, types::Errno> {
+    let mut remaining = usize::try_from(offset)
+        .ok()
+        .and_then(|offset| bytes.get(offset..))
+        .unwrap_or(&[]);
+    let mut read = 0;
+    for iov in iovs.iter() {
+        if remaining.is_empty() {
+            break;
+        }
+        let iov = iov.and_then(|iov| iov.read()).map_err(guest_errno)?;
+        let len = std::cmp::min(iov.buf_len as usize, remaining.len());
+        let mut buf = iov
+            .buf
+            .as_array(len as u32)
+            .as_slice()
+            .map_err(guest_errno)?;
+        buf.copy_from_slice(&remaining[..len]);
+        remaining = &remaining[len..];
+        read += len;
+    }
+    Ok(read as types::Size)
+}
+
+fn write_at(
+    bytes: &mut Vec<u8>,
+    offset: types::Filesize,
+    data: &[u8],
+) -> Result<types::Size, types::Errno> {
+    let start = usize::try_from(offset).map_err(|_| types::Errno::Fbig)?;
+    let end = start.checked_add(data.len()).ok_or(types::Errno::Fbig)?;
+    if bytes.len() < end {
+        bytes.resize(end, 0);
+    }
+    bytes[start..end].copy_from_slice(data);
+    Ok(data.len() as types::Size)
+}
+
+pub(crate) fn gather(ciovs: &types::CiovecArray<'_>) -> Result<Vec<u8>, types::Errno> {
+    let mut data = vec![];
+    for ciov in ciovs.iter() {
+        let ciov = ciov.and_then(|ciov| ciov.read()).map_err(guest_errno)?;
+        let buf = ciov
+            .buf
+            .as_array(ciov.buf_len)
+            .as_slice()
+            .map_err(guest_errno)?;
+        data.extend_from_slice(&*buf);
+    }
+    Ok(data)
+}
+
+/// Read the host file at `path` from `offset` into `iovs`, each of which is borrowed from the
+/// guest in turn and read into in place.
+fn read_host_file(
+    path: &Path,
+    offset: types::Filesize,
+    iovs: &types::IovecArray<'_>,
+) -> Result<types::Size, types::Errno> {
+    let file = fs::File::open(path).map_err(|_| types::Errno::Io)?;
+    let mut read = 0;
+    for iov in iovs.iter() {
+        let iov = iov.and_then(|iov| iov.read()).map_err(guest_errno)?;
+        let mut buf = iov
+            .buf
+            .as_array(iov.buf_len)
+            .as_slice()
+            .map_err(guest_errno)?;
+        let mut filled = 0;
+        while filled < buf.len() {
+            match file.read_at(
+                &mut buf[filled..],
+                offset.saturating_add((read + filled) as u64),
+            ) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(_) => return Err(types::Errno::Io),
+            }
+        }
+        read += filled;
+        if filled < buf.len() {
+            // the end of the file
+            break;
+        }
+    }
+    Ok(read as types::Size)
+}
+
+fn guest_str(path: &GuestPtr<'_, str>) -> Result<String, types::Errno> {
+    let path = path.as_str().map_err(guest_errno)?;
+    Ok((&*path).to_owned())
+}
+
+/// The errno for an invalid guest pointer, as `LucetWasiCtx::into_errno()` returns.
+fn guest_errno(_e: GuestError) -> types::Errno {
+    types::Errno::Inval
+}
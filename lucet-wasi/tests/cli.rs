@@ -0,0 +1,161 @@
+use lucetc::{Lucetc, LucetcOpts};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/// A WASI guest with an entrypoint for each way a run can end, and one printing the name of the
+/// first preopened directory.
+const GUEST: &str = r#"
+(module
+  (import "wasi_snapshot_preview1" "fd_prestat_dir_name"
+    (func $fd_prestat_dir_name (param i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit" (func $proc_exit (param i32)))
+  (memory (export "memory") 1)
+  (func (export "_start"))
+  (func (export "exit_7")
+    (call $proc_exit (i32.const 7)))
+  (func (export "trap")
+    unreachable)
+  (func (export "spin")
+    (loop (br 0)))
+  (func (export "add") (param i32 i32) (result i32)
+    (i32.add (local.get 0) (local.get 1)))
+  (func (export "print_dir")
+    ;; the name of fd 3, of 4 bytes, to offset 16
+    (if (call $fd_prestat_dir_name (i32.const 3) (i32.const 16) (i32.const 4))
+      (then (call $proc_exit (i32.const 1))))
+    ;; an iovec of it at offset 0
+    (i32.store (i32.const 0) (i32.const 16))
+    (i32.store (i32.const 4) (i32.const 4))
+    (drop (call $fd_write (i32.const 1) (i32.const 0) (i32.const 1) (i32.const 8)))))
+"#;
+
+/// Compile `GUEST` with `lucetc`, with the WASI bindings.
+fn guest_so(tmp: &TempDir) -> PathBuf {
+    let wat = tmp.path().join("guest.wat");
+    std::fs::write(&wat, GUEST).expect("write guest");
+    let so = tmp.path().join("guest.so");
+    Lucetc::new(&wat)
+        .with_bindings(lucet_wasi::bindings())
+        .shared_object_file(&so)
+        .expect("compile guest");
+    so
+}
+
+fn lucet_wasi(module: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lucet-wasi"))
+        .arg(module)
+        .args(args)
+        .output()
+        .expect("run lucet-wasi")
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).into_owned()
+}
+
+#[test]
+fn returning_from_the_entrypoint_exits_0() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let output = lucet_wasi(&guest_so(&tmp), &[]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+}
+
+#[test]
+fn proc_exit_status_is_the_exit_code() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let output = lucet_wasi(&guest_so(&tmp), &["--entrypoint", "exit_7"]);
+    assert_eq!(output.status.code(), Some(7), "{:?}", output);
+}
+
+#[test]
+fn trap_exits_134() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let output = lucet_wasi(&guest_so(&tmp), &["--entrypoint", "trap"]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_TRAP));
+    assert!(stderr(&output).contains("the guest trapped"));
+}
+
+#[test]
+fn timeout_exits_124() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let output = lucet_wasi(
+        &guest_so(&tmp),
+        &["--entrypoint", "spin", "--timeout", "0.1"],
+    );
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_TIMEOUT));
+    assert!(stderr(&output).contains("the guest ran out of time"));
+}
+
+#[test]
+fn load_failure_exits_125() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let not_a_module = tmp.path().join("not_a_module.so");
+    std::fs::write(&not_a_module, "not a shared object").unwrap();
+    let output = lucet_wasi(&not_a_module, &[]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(stderr(&output).starts_with("lucet-wasi: "));
+
+    let output = lucet_wasi(&tmp.path().join("missing.so"), &[]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+}
+
+#[test]
+fn missing_public_key_exits_125() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let output = lucet_wasi(&guest_so(&tmp), &["--signature-verify"]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(stderr(&output).contains("requires a public key"));
+}
+
+#[test]
+fn invoke_prints_the_result() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let output = lucet_wasi(&guest_so(&tmp), &["--invoke", "add", "--", "1", "-2"]);
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-1\n");
+
+    // the arguments must be of the function's signature
+    let output = lucet_wasi(&guest_so(&tmp), &["--invoke", "add", "--", "1"]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(stderr(&output).contains("cannot invoke add"));
+}
+
+#[test]
+fn mapdir_provides_the_guest_path() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let mapping = format!("/app::{}", tmp.path().display());
+    let output = lucet_wasi(
+        &guest_so(&tmp),
+        &["--mapdir", &mapping, "--entrypoint", "print_dir"],
+    );
+    assert_eq!(output.status.code(), Some(0), "{:?}", output);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "/app");
+}
+
+#[test]
+fn bad_directories_exit_125() {
+    let tmp = TempDir::new().expect("create temporary directory");
+    let guest = guest_so(&tmp);
+    let missing = tmp.path().join("missing");
+
+    let output = lucet_wasi(&guest, &["--mapdir", "/app"]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(stderr(&output).contains("Invalid directory mapping"));
+
+    let output = lucet_wasi(&guest, &["--dir", "no-guest-path"]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(stderr(&output).contains("Invalid directory specification"));
+
+    let mapping = format!("/app::{}", missing.display());
+    let output = lucet_wasi(&guest, &["--mapdir", &mapping]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(stderr(&output).contains("cannot map"));
+
+    let dir = format!("{}:/sandbox", missing.display());
+    let output = lucet_wasi(&guest, &["--dir", &dir]);
+    assert_eq!(output.status.code(), Some(lucet_run::EXIT_ERROR));
+    assert!(stderr(&output).contains("cannot open directory"));
+}
@@ -0,0 +1,16 @@
+#include <assert.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+__attribute__((import_module("lucet_wasi_sockets"), import_name("sock_accept"))) uint32_t
+sock_accept(uint32_t fd, uint32_t *fd_out);
+
+int main(void)
+{
+    uint32_t conn;
+
+    // nothing ever connects to the listener, so this only returns when the instance is terminated
+    sock_accept((uint32_t) atoi(getenv("LISTEN_FD")), &conn);
+    assert(0);
+    return 1;
+}
@@ -0,0 +1,20 @@
+#include <assert.h>
+#include <stdio.h>
+#include <unistd.h>
+#include <wasi/api.h>
+
+int main(void)
+{
+    fputs("to stdout\n", stdout);
+    fflush(stdout);
+    fputs("first to stderr\n", stderr);
+    fputs("second to stderr\n", stderr);
+
+    // the capture of stdout follows it when it is renumbered over stderr
+    int res = __wasi_fd_renumber(STDOUT_FILENO, STDERR_FILENO);
+    assert(res == __WASI_ERRNO_SUCCESS);
+    res = (int) write(STDERR_FILENO, "via fd 2\n", 9);
+    assert(res == 9);
+
+    return 0;
+}
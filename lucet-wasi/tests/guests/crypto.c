@@ -0,0 +1,117 @@
+#include <assert.h>
+#include <stdint.h>
+#include <string.h>
+
+#define NONE UINT32_MAX
+#define CRYPTO_ERRNO_PROHIBITED_OPERATION 4
+#define CRYPTO_ERRNO_VERIFICATION_FAILED 10
+#define CRYPTO_ERRNO_INVALID_TAG 21
+
+#define SYMMETRIC(name)                                                              \
+    __attribute__((import_module("lucet_wasi_crypto_symmetric"), import_name(#name)))
+#define SIGNATURES(name)                                                             \
+    __attribute__((import_module("lucet_wasi_crypto_signatures"), import_name(#name)))
+#define COMMON(name)                                                                 \
+    __attribute__((import_module("lucet_wasi_crypto_common"), import_name(#name)))
+
+COMMON(array_output_pull)
+uint32_t array_output_pull(uint32_t output, uint8_t *buf, uint32_t buf_len, uint32_t *size);
+
+SYMMETRIC(symmetric_key_import)
+uint32_t symmetric_key_import(const char *alg, uint32_t alg_len, const uint8_t *raw,
+                              uint32_t raw_len, uint32_t *key);
+SYMMETRIC(symmetric_key_export)
+uint32_t symmetric_key_export(uint32_t key, uint32_t *output);
+SYMMETRIC(symmetric_state_open)
+uint32_t symmetric_state_open(const char *alg, uint32_t alg_len, uint32_t key, uint32_t options,
+                              uint32_t *state);
+SYMMETRIC(symmetric_state_absorb)
+uint32_t symmetric_state_absorb(uint32_t state, const uint8_t *data, uint32_t data_len);
+SYMMETRIC(symmetric_state_squeeze_tag)
+uint32_t symmetric_state_squeeze_tag(uint32_t state, uint32_t *tag);
+SYMMETRIC(symmetric_tag_verify)
+uint32_t symmetric_tag_verify(uint32_t tag, const uint8_t *expected, uint32_t expected_len);
+
+SIGNATURES(signature_keypair_generate)
+uint32_t signature_keypair_generate(const char *alg, uint32_t alg_len, uint32_t options,
+                                    uint32_t *keypair);
+SIGNATURES(signature_keypair_publickey)
+uint32_t signature_keypair_publickey(uint32_t keypair, uint32_t *publickey);
+SIGNATURES(signature_state_open)
+uint32_t signature_state_open(uint32_t keypair, uint32_t *state);
+SIGNATURES(signature_state_update)
+uint32_t signature_state_update(uint32_t state, const uint8_t *data, uint32_t data_len);
+SIGNATURES(signature_state_sign)
+uint32_t signature_state_sign(uint32_t state, uint32_t *output);
+SIGNATURES(signature_verification_state_open)
+uint32_t signature_verification_state_open(uint32_t publickey, uint32_t *state);
+SIGNATURES(signature_verification_state_update)
+uint32_t signature_verification_state_update(uint32_t state, const uint8_t *data,
+                                             uint32_t data_len);
+SIGNATURES(signature_verification_state_verify)
+uint32_t signature_verification_state_verify(uint32_t state, const uint8_t *sig,
+                                             uint32_t sig_len);
+
+static const char *MSG = "The quick brown fox jumps over the lazy dog";
+
+static uint32_t verify(uint32_t publickey, const char *msg, const uint8_t *sig, uint32_t sig_len)
+{
+    uint32_t state;
+    uint32_t res = signature_verification_state_open(publickey, &state);
+    assert(res == 0);
+    res = signature_verification_state_update(state, (const uint8_t *) msg, strlen(msg));
+    assert(res == 0);
+    return signature_verification_state_verify(state, sig, sig_len);
+}
+
+int main(void)
+{
+    // HMAC-SHA256("key", MSG)
+    static const uint8_t expected[32] = {
+        0xf7, 0xbc, 0x83, 0xf4, 0x30, 0x53, 0x84, 0x24, 0xb1, 0x32, 0x98,
+        0xe6, 0xaa, 0x6f, 0xb1, 0x43, 0xef, 0x4d, 0x59, 0xa1, 0x49, 0x46,
+        0x17, 0x59, 0x97, 0x47, 0x9d, 0xbc, 0x2d, 0x1a, 0x3c, 0xd8,
+    };
+    uint8_t  wrong[32];
+    uint8_t  sig[64];
+    uint32_t key, state, tag, output, keypair, publickey, size, res;
+
+    res = symmetric_key_import("HMAC/SHA-256", 12, (const uint8_t *) "key", 3, &key);
+    assert(res == 0);
+    // the key stays in the host
+    res = symmetric_key_export(key, &output);
+    assert(res == CRYPTO_ERRNO_PROHIBITED_OPERATION);
+
+    res = symmetric_state_open("HMAC/SHA-256", 12, key, NONE, &state);
+    assert(res == 0);
+    res = symmetric_state_absorb(state, (const uint8_t *) MSG, strlen(MSG));
+    assert(res == 0);
+    res = symmetric_state_squeeze_tag(state, &tag);
+    assert(res == 0);
+    res = symmetric_tag_verify(tag, expected, sizeof expected);
+    assert(res == 0);
+    memcpy(wrong, expected, sizeof wrong);
+    wrong[31] ^= 1;
+    res = symmetric_tag_verify(tag, wrong, sizeof wrong);
+    assert(res == CRYPTO_ERRNO_INVALID_TAG);
+
+    res = signature_keypair_generate("Ed25519", 7, NONE, &keypair);
+    assert(res == 0);
+    res = signature_keypair_publickey(keypair, &publickey);
+    assert(res == 0);
+    res = signature_state_open(keypair, &state);
+    assert(res == 0);
+    res = signature_state_update(state, (const uint8_t *) MSG, strlen(MSG));
+    assert(res == 0);
+    res = signature_state_sign(state, &output);
+    assert(res == 0);
+    res = array_output_pull(output, sig, sizeof sig, &size);
+    assert(res == 0);
+    assert(size == 64);
+
+    assert(verify(publickey, MSG, sig, size) == 0);
+    assert(verify(publickey, "The quick brown fox jumps over the lazy cat", sig, size) ==
+           CRYPTO_ERRNO_VERIFICATION_FAILED);
+
+    return 0;
+}
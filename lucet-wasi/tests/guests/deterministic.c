@@ -0,0 +1,23 @@
+#include <stdio.h>
+#include <time.h>
+#include <unistd.h>
+
+int main()
+{
+    struct timespec ts1, ts2;
+    clock_gettime(CLOCK_REALTIME, &ts1);
+    clock_gettime(CLOCK_MONOTONIC, &ts2);
+    printf("%lld.%09ld %lld.%09ld\n", (long long) ts1.tv_sec, ts1.tv_nsec, (long long) ts2.tv_sec,
+           ts2.tv_nsec);
+
+    unsigned char buf[16];
+    if (getentropy(buf, sizeof buf) != 0) {
+        return 1;
+    }
+    for (int i = 0; i < sizeof buf; i++) {
+        printf("%02x", buf[i]);
+    }
+    printf("\n");
+
+    return 0;
+}
@@ -0,0 +1,18 @@
+#include <stdio.h>
+#include <stdlib.h>
+
+static void print_var(const char *name)
+{
+    const char *value = getenv(name);
+
+    printf("%s=%s\n", name, value != NULL ? value : "(unset)");
+}
+
+int main(void)
+{
+    print_var("LUCET_WASI_TEST_KEEP");
+    print_var("LUCET_WASI_TEST_PREFIX_A");
+    print_var("LUCET_WASI_TEST_DROP");
+
+    return 0;
+}
@@ -0,0 +1,33 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+int main(void)
+{
+    int  fds[8];
+    int  opened = 0;
+    char path[32];
+
+    // open files until the limit is reached
+    for (int i = 0; i < 8; i++) {
+        snprintf(path, sizeof path, "/sandbox/file%d", i);
+        fds[i] = open(path, O_CREAT | O_WRONLY, 0644);
+        if (fds[i] == -1) {
+            assert(errno == EMFILE);
+            break;
+        }
+        opened++;
+    }
+    assert(opened == 4);
+
+    // closing a file makes room for another
+    assert(close(fds[0]) == 0);
+    fds[0] = open("/sandbox/again", O_CREAT | O_WRONLY, 0644);
+    assert(fds[0] != -1);
+    assert(open("/sandbox/more", O_CREAT | O_WRONLY, 0644) == -1);
+    assert(errno == EMFILE);
+
+    return 0;
+}
@@ -0,0 +1,34 @@
+#include <sys/stat.h>
+
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+int main(void)
+{
+    int fd;
+
+    fd = open("/sandbox/a", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+    assert(write(fd, "12345678", 8) == 8);
+    assert(write(fd, "9", 1) == -1);
+    assert(errno == EDQUOT);
+    assert(close(fd) == 0);
+
+    // opening a file that exists does not create one
+    fd = open("/sandbox/a", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+    assert(close(fd) == 0);
+
+    assert(mkdir("/sandbox/dir", 0755) == 0);
+    assert(open("/sandbox/b", O_CREAT | O_WRONLY, 0644) == -1);
+    assert(errno == EDQUOT);
+    assert(mkdir("/sandbox/dir2", 0755) == -1);
+    assert(errno == EDQUOT);
+
+    // the standard streams are not files
+    printf("ok");
+    return 0;
+}
@@ -0,0 +1,19 @@
+#include <assert.h>
+#include <stdio.h>
+
+int main(void)
+{
+    char  buf[64];
+    FILE *fp;
+    int   res;
+
+    // the guest looks for its files at a fixed absolute path
+    fp = fopen("/app/config/settings.txt", "r");
+    assert(fp != NULL);
+    assert(fgets(buf, sizeof buf, fp) != NULL);
+    res = fclose(fp);
+    assert(res == 0);
+    fputs(buf, stdout);
+
+    return 0;
+}
@@ -0,0 +1,79 @@
+#include <assert.h>
+#include <stdint.h>
+#include <string.h>
+
+#define NN_ERRNO_INVALID_ARGUMENT 1
+#define NN_ERRNO_INVALID_ENCODING 2
+#define NN_ENCODING_ONNX 1
+#define NN_TARGET_CPU 0
+#define NN_TENSOR_F32 1
+
+typedef struct {
+    const uint8_t *buf;
+    uint32_t       len;
+} graph_builder;
+
+typedef struct {
+    const uint32_t *dimensions;
+    uint32_t        dimensions_len;
+    uint8_t         type;
+    const uint8_t * data;
+    uint32_t        data_len;
+} tensor;
+
+__attribute__((import_module("lucet_wasi_nn"), import_name("load"))) uint32_t
+nn_load(const graph_builder *builder, uint32_t builder_len, uint32_t encoding, uint32_t target,
+        uint32_t *graph_out);
+
+__attribute__((import_module("lucet_wasi_nn"),
+               import_name("init_execution_context"))) uint32_t
+nn_init_execution_context(uint32_t graph, uint32_t *ctx_out);
+
+__attribute__((import_module("lucet_wasi_nn"), import_name("set_input"))) uint32_t
+nn_set_input(uint32_t ctx, uint32_t index, const tensor *tensor);
+
+__attribute__((import_module("lucet_wasi_nn"), import_name("compute"))) uint32_t
+nn_compute(uint32_t ctx);
+
+__attribute__((import_module("lucet_wasi_nn"), import_name("get_output"))) uint32_t
+nn_get_output(uint32_t ctx, uint32_t index, uint8_t *out, uint32_t out_max_size,
+              uint32_t *bytes_written);
+
+int main(void)
+{
+    graph_builder  builder = { (const uint8_t *) "sum", 3 };
+    graph_builder  bogus   = { (const uint8_t *) "product", 7 };
+    float          input[] = { 1.0f, 2.0f, 3.5f };
+    uint32_t       dims[]  = { 3 };
+    tensor         t       = { dims, 1, NN_TENSOR_F32, (const uint8_t *) input, sizeof input };
+    float          sum;
+    uint32_t       graph;
+    uint32_t       ctx;
+    uint32_t       written;
+    uint32_t       res;
+
+    // the test backend only knows the "sum" graph
+    res = nn_load(&bogus, 1, NN_ENCODING_ONNX, NN_TARGET_CPU, &graph);
+    assert(res == NN_ERRNO_INVALID_ENCODING);
+    res = nn_load(&builder, 1, NN_ENCODING_ONNX, NN_TARGET_CPU, &graph);
+    assert(res == 0);
+    res = nn_init_execution_context(graph, &ctx);
+    assert(res == 0);
+    res = nn_init_execution_context(graph + 1, &ctx);
+    assert(res == NN_ERRNO_INVALID_ARGUMENT);
+
+    res = nn_set_input(ctx, 0, &t);
+    assert(res == 0);
+    res = nn_compute(ctx);
+    assert(res == 0);
+
+    // the output does not fit in a smaller buffer
+    res = nn_get_output(ctx, 0, (uint8_t *) &sum, 2, &written);
+    assert(res == NN_ERRNO_INVALID_ARGUMENT);
+    res = nn_get_output(ctx, 0, (uint8_t *) &sum, sizeof sum, &written);
+    assert(res == 0);
+    assert(written == sizeof sum);
+    assert(sum == 6.5f);
+
+    return 0;
+}
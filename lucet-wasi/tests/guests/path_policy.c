@@ -0,0 +1,45 @@
+#include <sys/stat.h>
+
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <unistd.h>
+#include <wasi/api.h>
+
+#define DIRFD 3
+
+int main(void)
+{
+    __wasi_filestat_t fst;
+    __wasi_errno_t    err;
+    struct stat       st;
+    int               fd;
+    int               res;
+
+    // symbolic links are not followed anywhere
+    fd = open("/sandbox/link", O_RDONLY);
+    assert(fd == -1);
+    assert(errno == ELOOP);
+    res = stat("/sandbox/link", &st);
+    assert(res == 0);
+    assert(S_ISLNK(st.st_mode));
+
+    // nor can they be created
+    res = symlink("file", "/sandbox/new_link");
+    assert(res == -1);
+    assert(errno == EPERM);
+
+    // an absolute path is resolved within the preopen it is looked up in
+    err = __wasi_path_filestat_get(DIRFD, __WASI_LOOKUPFLAGS_SYMLINK_FOLLOW, "/file", &fst);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(fst.filetype == __WASI_FILETYPE_REGULAR_FILE);
+    assert(fst.size == 4);
+    err = __wasi_path_filestat_get(DIRFD, 0, "//sub/../file", &fst);
+    assert(err == __WASI_ERRNO_SUCCESS);
+
+    // but cannot lead out of it
+    err = __wasi_path_filestat_get(DIRFD, 0, "/../file", &fst);
+    assert(err == __WASI_ERRNO_NOTCAPABLE);
+
+    return 0;
+}
@@ -0,0 +1,14 @@
+#include <assert.h>
+#include <poll.h>
+
+int main(void)
+{
+    struct pollfd fds[1];
+
+    // stdin is a pipe that is never written to or closed, so this only returns when the instance
+    // is terminated
+    fds[0] = (struct pollfd){ .fd = 0, .events = POLLIN, .revents = 0 };
+    poll(fds, 1, -1);
+    assert(0);
+    return 1;
+}
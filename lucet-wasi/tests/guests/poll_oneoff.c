@@ -0,0 +1,96 @@
+#include <assert.h>
+#include <string.h>
+#include <wasi/api.h>
+
+static __wasi_subscription_t clock_sub(__wasi_userdata_t userdata, __wasi_timestamp_t timeout,
+                                       __wasi_subclockflags_t flags)
+{
+    __wasi_subscription_t sub;
+
+    memset(&sub, 0, sizeof sub);
+    sub.userdata             = userdata;
+    sub.u.tag                = __WASI_EVENTTYPE_CLOCK;
+    sub.u.u.clock.id         = __WASI_CLOCKID_MONOTONIC;
+    sub.u.u.clock.timeout    = timeout;
+    sub.u.u.clock.precision  = 0;
+    sub.u.u.clock.flags      = flags;
+    return sub;
+}
+
+static __wasi_subscription_t fd_sub(__wasi_userdata_t userdata, __wasi_eventtype_t type,
+                                    __wasi_fd_t fd)
+{
+    __wasi_subscription_t sub;
+
+    memset(&sub, 0, sizeof sub);
+    sub.userdata = userdata;
+    sub.u.tag    = type;
+    if (type == __WASI_EVENTTYPE_FD_READ) {
+        sub.u.u.fd_read.file_descriptor = fd;
+    } else {
+        sub.u.u.fd_write.file_descriptor = fd;
+    }
+    return sub;
+}
+
+int main(void)
+{
+    __wasi_subscription_t subs[2];
+    __wasi_event_t        events[2];
+    __wasi_size_t         nevents;
+    __wasi_timestamp_t    before, now;
+    __wasi_errno_t        err;
+
+    // the absolute deadline passes before the relative one
+    err = __wasi_clock_time_get(__WASI_CLOCKID_MONOTONIC, 0, &before);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    subs[0] = clock_sub(1, 5000000000ull, 0);
+    subs[1] = clock_sub(2, before + 20000000ull, __WASI_SUBCLOCKFLAGS_SUBSCRIPTION_CLOCK_ABSTIME);
+    err     = __wasi_poll_oneoff(subs, events, 2, &nevents);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(nevents == 1);
+    assert(events[0].userdata == 2);
+    assert(events[0].type == __WASI_EVENTTYPE_CLOCK);
+    assert(events[0].error == __WASI_ERRNO_SUCCESS);
+    err = __wasi_clock_time_get(__WASI_CLOCKID_MONOTONIC, 0, &now);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(now - before >= 20000000ull);
+
+    // a relative timeout
+    subs[0] = clock_sub(3, 10000000ull, 0);
+    err     = __wasi_poll_oneoff(subs, events, 1, &nevents);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(nevents == 1);
+    assert(events[0].userdata == 3);
+    err = __wasi_clock_time_get(__WASI_CLOCKID_MONOTONIC, 0, &before);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(before - now >= 10000000ull);
+
+    // stdin is a pipe with data in it, so it is ready long before the clock expires
+    subs[0] = fd_sub(4, __WASI_EVENTTYPE_FD_READ, 0);
+    subs[1] = clock_sub(5, 5000000000ull, 0);
+    err     = __wasi_poll_oneoff(subs, events, 2, &nevents);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(nevents == 1);
+    assert(events[0].userdata == 4);
+    assert(events[0].type == __WASI_EVENTTYPE_FD_READ);
+    assert(events[0].error == __WASI_ERRNO_SUCCESS);
+    assert(events[0].fd_readwrite.nbytes == 5);
+
+    // stdout can be written to, and a closed descriptor fails in its event
+    subs[0] = fd_sub(6, __WASI_EVENTTYPE_FD_WRITE, 1);
+    subs[1] = fd_sub(7, __WASI_EVENTTYPE_FD_READ, 100);
+    err     = __wasi_poll_oneoff(subs, events, 2, &nevents);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(nevents == 2);
+    assert(events[0].userdata == 6);
+    assert(events[0].error == __WASI_ERRNO_SUCCESS);
+    assert(events[1].userdata == 7);
+    assert(events[1].error == __WASI_ERRNO_BADF);
+
+    // there must be something to wait for
+    err = __wasi_poll_oneoff(subs, events, 0, &nevents);
+    assert(err == __WASI_ERRNO_INVAL);
+
+    return 0;
+}
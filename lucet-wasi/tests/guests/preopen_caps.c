@@ -0,0 +1,70 @@
+#include <sys/stat.h>
+
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <unistd.h>
+
+int main(void)
+{
+    struct stat st;
+    char        buf[8];
+    int         fd;
+    int         res;
+
+    // a read-only directory can be read, but nothing in it can change
+    fd = open("/ro/file", O_RDONLY);
+    assert(fd != -1);
+    res = (int) read(fd, buf, sizeof buf);
+    assert(res == 4);
+    res = close(fd);
+    assert(res == 0);
+
+    fd = open("/ro/file", O_WRONLY);
+    assert(fd == -1);
+    assert(errno == ENOTCAPABLE);
+    fd = open("/ro/new", O_CREAT | O_WRONLY, 0644);
+    assert(fd == -1);
+    assert(errno == ENOTCAPABLE);
+    res = unlink("/ro/file");
+    assert(res == -1);
+    assert(errno == ENOTCAPABLE);
+    res = mkdir("/ro/dir", 0755);
+    assert(res == -1);
+    assert(errno == ENOTCAPABLE);
+
+    // the restrictions carry over to directories opened through the preopen
+    fd = open("/ro/sub/nested", O_RDONLY);
+    assert(fd != -1);
+    res = close(fd);
+    assert(res == 0);
+    fd = open("/ro/sub/new", O_CREAT | O_WRONLY, 0644);
+    assert(fd == -1);
+
+    // existing files can be changed in a no-create directory, but nothing can be added
+    fd = open("/nocreate/file", O_WRONLY | O_APPEND);
+    assert(fd != -1);
+    res = (int) write(fd, "more", 4);
+    assert(res == 4);
+    res = close(fd);
+    assert(res == 0);
+    fd = open("/nocreate/new", O_CREAT | O_WRONLY, 0644);
+    assert(fd == -1);
+    assert(errno == ENOTCAPABLE);
+    res = unlink("/nocreate/file");
+    assert(res == 0);
+
+    // symbolic links are not followed in a no-symlink-follow directory
+    fd = open("/nofollow/link", O_RDONLY);
+    assert(fd == -1);
+    assert(errno == ELOOP);
+    res = stat("/nofollow/link", &st);
+    assert(res == 0);
+    assert(S_ISLNK(st.st_mode));
+    fd = open("/nofollow/file", O_RDONLY);
+    assert(fd != -1);
+    res = close(fd);
+    assert(res == 0);
+
+    return 0;
+}
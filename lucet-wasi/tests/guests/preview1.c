@@ -0,0 +1,123 @@
+#include <sys/stat.h>
+
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <string.h>
+#include <unistd.h>
+#include <wasi/api.h>
+
+#define BASE_DIR "/sandbox"
+#define PATH BASE_DIR "/preview1.txt"
+
+static int count_entries(int dirfd, const char *name)
+{
+    uint8_t             buf[4096];
+    __wasi_size_t       used;
+    __wasi_errno_t      err;
+    __wasi_dircookie_t  cookie = __WASI_DIRCOOKIE_START;
+    size_t              pos;
+    int                 found = 0;
+
+    for (;;) {
+        err = __wasi_fd_readdir(dirfd, buf, sizeof buf, cookie, &used);
+        assert(err == __WASI_ERRNO_SUCCESS);
+        for (pos = 0; pos + sizeof(__wasi_dirent_t) <= used;) {
+            __wasi_dirent_t entry;
+
+            memcpy(&entry, buf + pos, sizeof entry);
+            pos += sizeof entry;
+            if (pos + entry.d_namlen > used) {
+                break;
+            }
+            if (entry.d_namlen == strlen(name) && memcmp(buf + pos, name, entry.d_namlen) == 0) {
+                assert(entry.d_type == __WASI_FILETYPE_REGULAR_FILE);
+                found++;
+            }
+            pos += entry.d_namlen;
+            cookie = entry.d_next;
+        }
+        if (used < sizeof buf) {
+            return found;
+        }
+    }
+}
+
+int main(void)
+{
+    struct stat         st;
+    __wasi_fdstat_t     fdstat;
+    __wasi_filesize_t   offset;
+    __wasi_ciovec_t     iov;
+    __wasi_size_t       sent;
+    __wasi_errno_t      err;
+    int                 dirfd;
+    int                 fd;
+    int                 res;
+
+    fd = open(PATH, O_CREAT | O_RDWR, 0644);
+    assert(fd != -1);
+
+    res = (int) write(fd, "preview1", 8);
+    assert(res == 8);
+
+    err = __wasi_fd_tell(fd, &offset);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(offset == 8);
+
+    err = __wasi_fd_advise(fd, 0, 8, __WASI_ADVICE_SEQUENTIAL);
+    assert(err == __WASI_ERRNO_SUCCESS);
+
+    err = __wasi_fd_allocate(fd, 0, 4096);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    res = fstat(fd, &st);
+    assert(res == 0);
+    assert(st.st_size == 4096);
+
+    // sockets cannot be opened in preview1, so socket calls on files must say so
+    iov.buf = (const uint8_t *) "x";
+    iov.buf_len = 1;
+    err = __wasi_sock_send(fd, &iov, 1, 0, &sent);
+    assert(err == __WASI_ERRNO_NOTSOCK);
+    err = __wasi_sock_shutdown(fd, __WASI_SDFLAGS_WR);
+    assert(err == __WASI_ERRNO_NOTSOCK);
+    err = __wasi_sock_shutdown(1000, __WASI_SDFLAGS_WR);
+    assert(err == __WASI_ERRNO_BADF);
+
+    // dropping a right takes effect immediately, and cannot be undone
+    err = __wasi_fd_fdstat_get(fd, &fdstat);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    assert(fdstat.fs_rights_base & __WASI_RIGHTS_FD_WRITE);
+    err = __wasi_fd_fdstat_set_rights(fd, fdstat.fs_rights_base & ~__WASI_RIGHTS_FD_WRITE,
+                                      fdstat.fs_rights_inheriting);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    res = (int) write(fd, "x", 1);
+    assert(res == -1);
+    assert(errno == ENOTCAPABLE);
+    err = __wasi_fd_fdstat_set_rights(fd, fdstat.fs_rights_base, fdstat.fs_rights_inheriting);
+    assert(err == __WASI_ERRNO_NOTCAPABLE);
+
+    res = close(fd);
+    assert(res == 0);
+
+    dirfd = open(BASE_DIR, O_RDONLY | O_DIRECTORY);
+    assert(dirfd != -1);
+
+    assert(count_entries(dirfd, "preview1.txt") == 1);
+
+    err = __wasi_path_filestat_set_times(dirfd, __WASI_LOOKUPFLAGS_SYMLINK_FOLLOW,
+                                         "preview1.txt", 0, 1557403800000000000ull,
+                                         __WASI_FSTFLAGS_MTIM);
+    assert(err == __WASI_ERRNO_SUCCESS);
+    res = stat(PATH, &st);
+    assert(res == 0);
+    assert(st.st_mtim.tv_sec == 1557403800);
+
+    res = close(dirfd);
+    assert(res == 0);
+
+    err = __wasi_proc_raise(__WASI_SIGNAL_USR1);
+    assert(err == __WASI_ERRNO_NOTSUP);
+
+    return 0;
+}
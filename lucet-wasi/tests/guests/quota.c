@@ -0,0 +1,30 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <unistd.h>
+
+int main(void)
+{
+    int fd;
+
+    fd = open("/sandbox/out", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+
+    // a write over the quota fails without writing anything
+    assert(write(fd, "hello", 5) == 5);
+    assert(write(fd, "world!", 6) == -1);
+    assert(errno == EDQUOT);
+    assert(write(fd, "world", 5) == 5);
+    assert(write(fd, "x", 1) == -1);
+    assert(errno == EDQUOT);
+    assert(close(fd) == 0);
+
+    // closing a file does not give back its open
+    fd = open("/sandbox/second", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+    assert(close(fd) == 0);
+    assert(open("/sandbox/third", O_CREAT | O_WRONLY, 0644) == -1);
+    assert(errno == EDQUOT);
+
+    return 0;
+}
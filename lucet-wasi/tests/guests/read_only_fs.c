@@ -0,0 +1,41 @@
+#include <sys/stat.h>
+
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <unistd.h>
+
+int main(void)
+{
+    char buf[8];
+    int  fd;
+
+    // files can be read, but nothing can change, whatever the rights of the preopen
+    fd = open("/sandbox/file", O_RDONLY);
+    assert(fd != -1);
+    assert(read(fd, buf, sizeof buf) == 4);
+    assert(ftruncate(fd, 0) == -1);
+    assert(errno == EROFS);
+    assert(close(fd) == 0);
+
+    assert(open("/sandbox/file", O_WRONLY) == -1);
+    assert(errno == EROFS);
+    assert(open("/sandbox/file", O_RDONLY | O_TRUNC) == -1);
+    assert(errno == EROFS);
+    assert(open("/sandbox/new", O_CREAT | O_WRONLY, 0644) == -1);
+    assert(errno == EROFS);
+    assert(unlink("/sandbox/file") == -1);
+    assert(errno == EROFS);
+    assert(mkdir("/sandbox/dir", 0755) == -1);
+    assert(errno == EROFS);
+    assert(rename("/sandbox/file", "/sandbox/moved") == -1);
+    assert(errno == EROFS);
+    assert(symlink("file", "/sandbox/link") == -1);
+    assert(errno == EROFS);
+
+    // the standard streams are not part of the filesystem
+    assert(write(STDOUT_FILENO, "ok", 2) == 2);
+
+    return 0;
+}
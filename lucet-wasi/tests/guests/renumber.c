@@ -0,0 +1,55 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <unistd.h>
+#include <wasi/api.h>
+
+#define PREOPEN_FD 3
+
+int main(void)
+{
+    __wasi_fdstat_t  fdstat;
+    __wasi_prestat_t prestat;
+    int              a, b, c;
+    char             buf[2];
+
+    a = open("/sandbox/a", O_CREAT | O_WRONLY, 0644);
+    assert(a != -1);
+    b = open("/sandbox/b", O_CREAT | O_WRONLY, 0644);
+    assert(b != -1);
+    assert(open("/sandbox/c", O_CREAT | O_WRONLY, 0644) == -1);
+    assert(errno == EMFILE);
+
+    // only file descriptors that are open can be renumbered, or renumbered over
+    assert(__wasi_fd_renumber(a, 100) == __WASI_ERRNO_BADF);
+    assert(__wasi_fd_renumber(100, a) == __WASI_ERRNO_BADF);
+    assert(__wasi_fd_renumber(a, a) == __WASI_ERRNO_SUCCESS);
+    assert(__wasi_fd_fdstat_get(a, &fdstat) == __WASI_ERRNO_SUCCESS);
+
+    // renumbering `b` over `a` closes the file `a` referred to, which makes room for another
+    assert(__wasi_fd_renumber(b, a) == __WASI_ERRNO_SUCCESS);
+    assert(__wasi_fd_fdstat_get(b, &fdstat) == __WASI_ERRNO_BADF);
+    c = open("/sandbox/c", O_CREAT | O_WRONLY, 0644);
+    assert(c != -1);
+
+    // a file renumbered over stdout is still a file, so its writes count against the quota
+    assert(__wasi_fd_renumber(c, STDOUT_FILENO) == __WASI_ERRNO_SUCCESS);
+    assert(write(STDOUT_FILENO, "hi", 2) == 2);
+    assert(write(STDOUT_FILENO, "!", 1) == -1);
+    assert(errno == EDQUOT);
+    assert(write(a, "b", 1) == -1);
+    assert(errno == EDQUOT);
+
+    // the preopen can be renumbered, and renumbered over, like any other file descriptor; libc
+    // resolves paths against the preopens it found when the guest started, so this comes last
+    assert(__wasi_fd_renumber(PREOPEN_FD, a) == __WASI_ERRNO_SUCCESS);
+    assert(__wasi_fd_fdstat_get(PREOPEN_FD, &fdstat) == __WASI_ERRNO_BADF);
+    assert(__wasi_fd_prestat_get(a, &prestat) == __WASI_ERRNO_SUCCESS);
+    b = openat(a, "c", O_RDONLY);
+    assert(b != -1);
+    assert(__wasi_fd_renumber(b, a) == __WASI_ERRNO_SUCCESS);
+    assert(__wasi_fd_prestat_get(a, &prestat) != __WASI_ERRNO_SUCCESS);
+    assert(read(a, buf, 2) == 2 && buf[0] == 'h' && buf[1] == 'i');
+
+    return 0;
+}
@@ -0,0 +1,129 @@
+#include <assert.h>
+#include <fcntl.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <string.h>
+#include <unistd.h>
+#include <wasi/api.h>
+
+#define PREOPEN_FD 3
+#define NAME "snapshot0.txt"
+
+// wasi_unstable lays these out differently than preview1 does in <wasi/api.h>
+#define UNSTABLE_WHENCE_CUR 0
+#define UNSTABLE_WHENCE_END 1
+#define UNSTABLE_WHENCE_SET 2
+
+typedef struct {
+    uint64_t dev;
+    uint64_t ino;
+    uint8_t  filetype;
+    uint32_t nlink;
+    uint64_t size;
+    uint64_t atim;
+    uint64_t mtim;
+    uint64_t ctim;
+} unstable_filestat_t;
+
+_Static_assert(sizeof(unstable_filestat_t) == 56, "filestat of wasi_unstable");
+
+typedef struct {
+    uint64_t userdata;
+    uint8_t  type;
+    union {
+        struct {
+            uint64_t identifier;
+            uint32_t clock_id;
+            uint64_t timeout;
+            uint64_t precision;
+            uint16_t flags;
+        } clock;
+        struct {
+            uint32_t fd;
+        } fd_readwrite;
+    } u;
+} unstable_subscription_t;
+
+_Static_assert(sizeof(unstable_subscription_t) == 56, "subscription of wasi_unstable");
+
+#define UNSTABLE(name) __attribute__((import_module("wasi_unstable"), import_name(#name)))
+
+UNSTABLE(fd_write) uint16_t
+unstable_fd_write(uint32_t fd, const __wasi_ciovec_t *iovs, size_t iovs_len, size_t *nwritten);
+
+UNSTABLE(fd_seek) uint16_t
+unstable_fd_seek(uint32_t fd, int64_t offset, uint8_t whence, uint64_t *newoffset);
+
+UNSTABLE(fd_filestat_get) uint16_t unstable_fd_filestat_get(uint32_t fd, unstable_filestat_t *buf);
+
+UNSTABLE(path_filestat_get) uint16_t
+unstable_path_filestat_get(uint32_t fd, uint32_t flags, const char *path, size_t path_len,
+                           unstable_filestat_t *buf);
+
+UNSTABLE(poll_oneoff) uint16_t
+unstable_poll_oneoff(const unstable_subscription_t *in, __wasi_event_t *out, size_t nsubscriptions,
+                     size_t *nevents);
+
+int main(void)
+{
+    unstable_filestat_t     stat;
+    unstable_subscription_t subs[2];
+    __wasi_event_t          events[2];
+    __wasi_ciovec_t         iov = { .buf = (const uint8_t *) "hello world", .buf_len = 11 };
+    size_t                  n;
+    uint64_t                pos;
+    int                     fd;
+
+    // the guest's libc imports preview1, and can share a file with wasi_unstable
+    fd = open("/sandbox/" NAME, O_CREAT | O_RDWR, 0644);
+    assert(fd != -1);
+    assert(unstable_fd_write(fd, &iov, 1, &n) == __WASI_ERRNO_SUCCESS);
+    assert(n == 11);
+
+    assert(unstable_fd_seek(fd, -5, UNSTABLE_WHENCE_END, &pos) == __WASI_ERRNO_SUCCESS);
+    assert(pos == 6);
+    assert(unstable_fd_seek(fd, 2, UNSTABLE_WHENCE_CUR, &pos) == __WASI_ERRNO_SUCCESS);
+    assert(pos == 8);
+    assert(unstable_fd_seek(fd, 0, UNSTABLE_WHENCE_SET, &pos) == __WASI_ERRNO_SUCCESS);
+    assert(pos == 0);
+    assert(unstable_fd_seek(fd, 0, 3, &pos) == __WASI_ERRNO_INVAL);
+
+    memset(&stat, 0xff, sizeof stat);
+    assert(unstable_fd_filestat_get(fd, &stat) == __WASI_ERRNO_SUCCESS);
+    assert(stat.filetype == __WASI_FILETYPE_REGULAR_FILE);
+    assert(stat.nlink == 1);
+    assert(stat.size == 11);
+
+    memset(&stat, 0xff, sizeof stat);
+    assert(unstable_path_filestat_get(PREOPEN_FD, 0, NAME, strlen(NAME), &stat) ==
+           __WASI_ERRNO_SUCCESS);
+    assert(stat.filetype == __WASI_FILETYPE_REGULAR_FILE);
+    assert(stat.size == 11);
+    assert(unstable_path_filestat_get(PREOPEN_FD, 0, "missing", 7, &stat) == __WASI_ERRNO_NOENT);
+
+    // a file is ready at once, before a clock far off
+    memset(subs, 0, sizeof subs);
+    subs[0].userdata           = 1;
+    subs[0].type               = __WASI_EVENTTYPE_CLOCK;
+    subs[0].u.clock.identifier = 42;
+    subs[0].u.clock.clock_id   = __WASI_CLOCKID_MONOTONIC;
+    subs[0].u.clock.timeout    = 10ull * 1000 * 1000 * 1000;
+    subs[1].userdata           = 2;
+    subs[1].type               = __WASI_EVENTTYPE_FD_READ;
+    subs[1].u.fd_readwrite.fd  = (uint32_t) fd;
+    assert(unstable_poll_oneoff(subs, events, 2, &n) == __WASI_ERRNO_SUCCESS);
+    assert(n == 1);
+    assert(events[0].userdata == 2);
+    assert(events[0].type == __WASI_EVENTTYPE_FD_READ);
+    assert(events[0].fd_readwrite.nbytes == 11);
+
+    subs[0].u.clock.timeout = 1000 * 1000;
+    assert(unstable_poll_oneoff(subs, events, 1, &n) == __WASI_ERRNO_SUCCESS);
+    assert(n == 1);
+    assert(events[0].userdata == 1);
+    assert(events[0].error == __WASI_ERRNO_SUCCESS);
+    assert(events[0].type == __WASI_EVENTTYPE_CLOCK);
+
+    assert(close(fd) == 0);
+    return 0;
+}
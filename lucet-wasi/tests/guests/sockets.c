@@ -0,0 +1,60 @@
+#include <sys/socket.h>
+
+#include <assert.h>
+#include <errno.h>
+#include <stdint.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <wasi/api.h>
+
+__attribute__((import_module("lucet_wasi_sockets"), import_name("sock_accept"))) uint32_t
+sock_accept(uint32_t fd, uint32_t *fd_out);
+
+__attribute__((import_module("lucet_wasi_sockets"), import_name("sock_connect"))) uint32_t
+sock_connect(const char *addr, uint32_t addr_len, uint32_t *fd_out);
+
+static void connect_to(const char *addr, uint32_t *fd_out, uint32_t expected)
+{
+    uint32_t res = sock_connect(addr, (uint32_t) strlen(addr), fd_out);
+    assert(res == expected);
+}
+
+int main(void)
+{
+    uint32_t listener = (uint32_t) atoi(getenv("LISTEN_FD"));
+    uint32_t conn;
+    uint32_t out;
+    char     buf[16];
+    ssize_t  n;
+    uint32_t res;
+
+    // serve one connection on the preopened listener
+    res = sock_accept(listener, &conn);
+    assert(res == __WASI_ERRNO_SUCCESS);
+    n = recv((int) conn, buf, sizeof buf, 0);
+    assert(n == 4);
+    assert(memcmp(buf, "ping", 4) == 0);
+    n = send((int) conn, "pong", 4, 0);
+    assert(n == 4);
+    assert(shutdown((int) conn, SHUT_WR) == 0);
+    assert(close((int) conn) == 0);
+
+    // only sockets can accept connections
+    res = sock_accept(STDOUT_FILENO, &conn);
+    assert(res == __WASI_ERRNO_BADF);
+
+    // connect to an allowed address, and talk to it with plain reads and writes
+    connect_to(getenv("ALLOWED_ADDR"), &out, __WASI_ERRNO_SUCCESS);
+    n = write((int) out, "hello", 5);
+    assert(n == 5);
+    n = read((int) out, buf, sizeof buf);
+    assert(n == 5);
+    assert(memcmp(buf, "world", 5) == 0);
+    assert(close((int) out) == 0);
+
+    connect_to(getenv("DENIED_ADDR"), &out, __WASI_ERRNO_NOTCAPABLE);
+    connect_to("not an address", &out, __WASI_ERRNO_INVAL);
+
+    return 0;
+}
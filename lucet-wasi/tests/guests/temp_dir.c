@@ -0,0 +1,40 @@
+#include <sys/stat.h>
+
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <unistd.h>
+
+int main(void)
+{
+    int fd;
+
+    fd = open("/tmp/a", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+    assert(write(fd, "12345678", 8) == 8);
+    // overwriting does not take up more room
+    assert(pwrite(fd, "87", 2, 0) == 2);
+    assert(write(fd, "9ab", 3) == -1);
+    assert(errno == EDQUOT);
+    assert(ftruncate(fd, 11) == -1);
+    assert(errno == EDQUOT);
+    assert(close(fd) == 0);
+
+    // files in subdirectories count too
+    assert(mkdir("/tmp/sub", 0755) == 0);
+    fd = open("/tmp/sub/b", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+    assert(write(fd, "90", 2) == 2);
+    assert(write(fd, "a", 1) == -1);
+    assert(errno == EDQUOT);
+    assert(close(fd) == 0);
+
+    // deleting a file makes room again
+    assert(unlink("/tmp/a") == 0);
+    fd = open("/tmp/c", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+    assert(write(fd, "12345678", 8) == 8);
+    assert(close(fd) == 0);
+
+    return 0;
+}
@@ -0,0 +1,94 @@
+#include <sys/stat.h>
+
+#include <assert.h>
+#include <dirent.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#define BASE_DIR "/config"
+
+int main(void)
+{
+    struct dirent *entry;
+    struct stat    st;
+    char           buf[64];
+    DIR *          dir;
+    FILE *         fp;
+    int            fd;
+    int            res;
+
+    fp = fopen(BASE_DIR "/app.toml", "r");
+    assert(fp != NULL);
+    assert(fgets(buf, sizeof buf, fp) != NULL);
+    assert(strcmp(buf, "threads = 4\n") == 0);
+    res = fclose(fp);
+    assert(res == 0);
+
+    res = stat(BASE_DIR "/assets/index.html", &st);
+    assert(res == 0);
+    assert(S_ISREG(st.st_mode));
+    assert(st.st_size == 13);
+
+    // the filesystem is confined to its preopened directory
+    fd = open(BASE_DIR "/../etc/passwd", O_RDONLY);
+    assert(fd == -1);
+    fd = open(BASE_DIR "/app.toml", O_CREAT | O_EXCL | O_WRONLY, 0644);
+    assert(fd == -1);
+    assert(errno == EEXIST);
+
+    fd = open(BASE_DIR "/app.toml", O_RDONLY);
+    assert(fd != -1);
+    res = (int) write(fd, "x", 1);
+    assert(res == -1);
+    res = close(fd);
+    assert(res == 0);
+
+    res = mkdir(BASE_DIR "/out", 0755);
+    assert(res == 0);
+    fp = fopen(BASE_DIR "/out/log.tmp", "w");
+    assert(fp != NULL);
+    res = fputs("hello from the guest\n", fp);
+    assert(res >= 0);
+    res = fclose(fp);
+    assert(res == 0);
+
+    fd = open(BASE_DIR "/out/log.tmp", O_WRONLY | O_APPEND);
+    assert(fd != -1);
+    res = (int) write(fd, "again\n", 6);
+    assert(res == 6);
+    assert(lseek(fd, 0, SEEK_CUR) == 27);
+    res = close(fd);
+    assert(res == 0);
+
+    res = rename(BASE_DIR "/out/log.tmp", BASE_DIR "/out/log.txt");
+    assert(res == 0);
+    res = access(BASE_DIR "/out/log.tmp", F_OK);
+    assert(res == -1);
+
+    res = rmdir(BASE_DIR "/out");
+    assert(res == -1);
+    assert(errno == ENOTEMPTY);
+
+    dir = opendir(BASE_DIR);
+    assert(dir != NULL);
+    res = 0;
+    while ((entry = readdir(dir)) != NULL) {
+        if (strcmp(entry->d_name, "app.toml") == 0 || strcmp(entry->d_name, "assets") == 0 ||
+            strcmp(entry->d_name, "out") == 0) {
+            res++;
+        }
+    }
+    assert(res == 3);
+    res = closedir(dir);
+    assert(res == 0);
+
+    res = unlink(BASE_DIR "/assets/index.html");
+    assert(res == 0);
+    res = rmdir(BASE_DIR "/assets");
+    assert(res == 0);
+
+    return 0;
+}
@@ -0,0 +1,61 @@
+#include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#define BASE_DIR "/app"
+
+int main(void)
+{
+    char  buf[64];
+    FILE *fp;
+    int   fd;
+    int   res;
+
+    fp = fopen(BASE_DIR "/data/input.txt", "r");
+    assert(fp != NULL);
+    assert(fgets(buf, sizeof buf, fp) != NULL);
+    assert(strcmp(buf, "from the host\n") == 0);
+    res = fclose(fp);
+    assert(res == 0);
+
+    // writes to a file of the host only change the copy of the guest
+    fp = fopen(BASE_DIR "/data/input.txt", "a");
+    assert(fp != NULL);
+    res = fputs("and the guest\n", fp);
+    assert(res >= 0);
+    res = fclose(fp);
+    assert(res == 0);
+
+    fp = fopen(BASE_DIR "/data/input.txt", "r");
+    assert(fp != NULL);
+    assert(fgets(buf, sizeof buf, fp) != NULL);
+    assert(fgets(buf, sizeof buf, fp) != NULL);
+    assert(strcmp(buf, "and the guest\n") == 0);
+    res = fclose(fp);
+    assert(res == 0);
+
+    fp = fopen(BASE_DIR "/scratch.tmp", "w");
+    assert(fp != NULL);
+    res = fputs("scratch\n", fp);
+    assert(res >= 0);
+    res = fclose(fp);
+    assert(res == 0);
+
+    res = unlink(BASE_DIR "/remove-me");
+    assert(res == 0);
+
+    // the writes are limited to 64 bytes
+    fd = open(BASE_DIR "/big.tmp", O_CREAT | O_WRONLY, 0644);
+    assert(fd != -1);
+    memset(buf, 'x', sizeof buf);
+    res = (int) write(fd, buf, sizeof buf);
+    assert(res == -1);
+    assert(errno == ENOSPC);
+    res = close(fd);
+    assert(res == 0);
+
+    return 0;
+}
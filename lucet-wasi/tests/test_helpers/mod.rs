@@ -1,6 +1,9 @@
 use anyhow::{bail, Error};
-use lucet_runtime::{DlModule, Limits, MmapRegion, Module, Region};
-use lucet_wasi::{self, types::Exitcode, WasiCtx, WasiCtxBuilder};
+use lucet_module::bindings::Bindings;
+use lucet_runtime::{DlModule, InstanceHandle, Limits, MmapRegion, Module, Region, RunResult};
+use lucet_wasi::{
+    self, types::Exitcode, DeterministicEnv, IoWait, VirtualFs, WasiCtx, WasiCtxBuilder,
+};
 use lucet_wasi_sdk::{CompileOpts, Link};
 use lucetc::{Lucetc, LucetcOpts};
 use std::fs::File;
@@ -8,6 +11,7 @@ use std::io::Read;
 use std::os::unix::io::{FromRawFd, IntoRawFd};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 use tempfile::TempDir;
 
 pub const LUCET_WASI_ROOT: &str = env!("CARGO_MANIFEST_DIR");
@@ -32,7 +36,12 @@ pub fn guest_file<P: AsRef<Path>>(path: P) -> PathBuf {
 
 pub fn wasi_test<P: AsRef<Path>>(file: P) -> Result<Arc<dyn Module>, Error> {
     let workdir = TempDir::new().expect("create working directory");
+    let wasm_path = wasm_file(&workdir, file)?;
+    wasi_load(&workdir, wasm_path)
+}
 
+/// Compile a test guest to wasm in `workdir`, if it is not wasm already.
+fn wasm_file<P: AsRef<Path>>(workdir: &TempDir, file: P) -> Result<PathBuf, Error> {
     let wasm_path = match file.as_ref().extension().and_then(|x| x.to_str()) {
         Some("c") => {
             // some tests are .c, and must be compiled/linked to .wasm we can run
@@ -57,8 +66,7 @@ pub fn wasi_test<P: AsRef<Path>>(file: P) -> Result<Arc<dyn Module>, Error> {
             panic!("unknown test file, has no extension");
         }
     };
-
-    wasi_load(&workdir, wasm_path)
+    Ok(wasm_path)
 }
 
 pub fn wasi_load<P: AsRef<Path>>(
@@ -82,15 +90,201 @@ pub fn wasi_load<P: AsRef<Path>>(
 }
 
 pub fn run<P: AsRef<Path>>(path: P, ctx: WasiCtx) -> Result<Exitcode, Error> {
+    run_with_env(path, ctx, false, None)
+}
+
+fn run_with_env<P: AsRef<Path>>(
+    path: P,
+    ctx: WasiCtx,
+    deterministic: bool,
+    env: Option<DeterministicEnv>,
+) -> Result<Exitcode, Error> {
+    let region = MmapRegion::create(1, &Limits::default())?;
+    let module = test_module_wasi(path)?;
+
+    let mut builder = region
+        .new_instance_builder(module)
+        .with_embed_ctx(ctx)
+        .with_deterministic_execution(deterministic);
+    if let Some(env) = env {
+        builder = builder.with_embed_ctx(env);
+    }
+    let mut inst = builder.build()?;
+
+    exitcode(inst.run("_start", &[]))
+}
+
+/// Load a guest that imports functions beyond WASI preview1, with their `extra` bindings.
+///
+/// These are not part of preview1, so the guest is not validated against it.
+fn module_with_bindings<P: AsRef<Path>>(
+    path: P,
+    extra: &Bindings,
+) -> Result<Arc<dyn Module>, Error> {
+    let workdir = TempDir::new().expect("create working directory");
+    let wasm_path = wasm_file(&workdir, guest_file(path))?;
+    let mut bindings = lucet_wasi::bindings();
+    bindings.extend(extra)?;
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wasm_path)
+        .with_bindings(bindings)
+        .shared_object_file(so_file.clone())?;
+    let module = DlModule::load(so_file)?;
+    Ok(module as Arc<dyn Module>)
+}
+
+/// Run a guest that imports `wasi_unstable`, which the validator does not know.
+pub fn run_snapshot0<P: AsRef<Path>>(path: P, ctx: WasiCtx) -> Result<Exitcode, Error> {
+    let module = module_with_bindings(path, &Bindings::empty())?;
+    let region = MmapRegion::create(1, &Limits::default())?;
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_embed_ctx(ctx)
+        .build()?;
+
+    exitcode(inst.run("_start", &[]))
+}
+
+/// Run a guest that imports the socket functions of `lucet_wasi::socket_bindings()`.
+pub fn run_with_sockets<P: AsRef<Path>>(path: P, ctx: WasiCtx) -> Result<Exitcode, Error> {
+    let module = module_with_bindings(path, &lucet_wasi::socket_bindings())?;
+    let region = MmapRegion::create(1, &Limits::default())?;
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_embed_ctx(ctx)
+        .build()?;
+
+    exitcode(inst.run("_start", &[]))
+}
+
+/// Run a guest like `run_with_sockets()`, for a context built with `async_io()`, waiting for the
+/// I/O of each yield by blocking, and returning how many times it yielded.
+pub fn run_with_sockets_async<P: AsRef<Path>>(
+    path: P,
+    ctx: WasiCtx,
+) -> Result<(Exitcode, usize), Error> {
+    let module = module_with_bindings(path, &lucet_wasi::socket_bindings())?;
+    let region = MmapRegion::create(1, &Limits::default())?;
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_embed_ctx(ctx)
+        .build()?;
+
+    let mut yields = 0;
+    let mut res = inst.run("_start", &[]);
+    while let Ok(RunResult::Yielded(val)) = &res {
+        val.downcast_ref::<IoWait>()
+            .expect("the guest yields only to wait for I/O")
+            .block()?;
+        yields += 1;
+        res = inst.resume();
+    }
+    Ok((exitcode(res)?, yields))
+}
+
+/// Run a guest that imports the wasi-crypto functions of `lucet_wasi::crypto_bindings()`.
+#[cfg(feature = "crypto")]
+pub fn run_with_crypto<P: AsRef<Path>>(
+    path: P,
+    ctx: WasiCtx,
+    crypto: lucet_wasi::WasiCryptoCtx,
+) -> Result<Exitcode, Error> {
+    let module = module_with_bindings(path, &lucet_wasi::crypto_bindings())?;
+    let region = MmapRegion::create(1, &Limits::default())?;
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_embed_ctx(ctx)
+        .with_embed_ctx(crypto)
+        .build()?;
+
+    exitcode(inst.run("_start", &[]))
+}
+
+/// Run a guest that imports the wasi-nn functions of `lucet_wasi::nn_bindings()`.
+#[cfg(feature = "nn")]
+pub fn run_with_nn<P: AsRef<Path>>(
+    path: P,
+    ctx: WasiCtx,
+    nn: lucet_wasi::WasiNnCtx,
+) -> Result<Exitcode, Error> {
+    let module = module_with_bindings(path, &lucet_wasi::nn_bindings())?;
+    let region = MmapRegion::create(1, &Limits::default())?;
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_embed_ctx(ctx)
+        .with_embed_ctx(nn)
+        .build()?;
+
+    exitcode(inst.run("_start", &[]))
+}
+
+/// Run a guest, terminating it with its `KillSwitch` once `delay` has passed, and returning what
+/// running it returned.
+pub fn run_killed_after<P: AsRef<Path>>(
+    path: P,
+    ctx: WasiCtx,
+    delay: Duration,
+) -> Result<Result<RunResult, lucet_runtime::Error>, Error> {
+    kill_after(test_module_wasi(path)?, ctx, delay)
+}
+
+/// Run a guest like `run_killed_after()`, for a guest that imports the socket functions of
+/// `lucet_wasi::socket_bindings()`.
+pub fn run_with_sockets_killed_after<P: AsRef<Path>>(
+    path: P,
+    ctx: WasiCtx,
+    delay: Duration,
+) -> Result<Result<RunResult, lucet_runtime::Error>, Error> {
+    let module = module_with_bindings(path, &lucet_wasi::socket_bindings())?;
+    kill_after(module, ctx, delay)
+}
+
+fn kill_after(
+    module: Arc<dyn Module>,
+    ctx: WasiCtx,
+    delay: Duration,
+) -> Result<Result<RunResult, lucet_runtime::Error>, Error> {
+    let region = MmapRegion::create(1, &Limits::default())?;
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_embed_ctx(ctx)
+        .build()?;
+
+    let kill_switch = inst.kill_switch();
+    let killer = std::thread::spawn(move || {
+        std::thread::sleep(delay);
+        kill_switch.terminate()
+    });
+    let res = inst.run("_start", &[]);
+    killer
+        .join()
+        .expect("the kill switch thread completes")
+        .expect("the instance can be terminated");
+    Ok(res)
+}
+
+/// Run a guest with a virtual filesystem, returning the instance so that the filesystem can be
+/// inspected afterwards.
+pub fn run_with_vfs<P: AsRef<Path>>(
+    path: P,
+    ctx: WasiCtx,
+    vfs: VirtualFs,
+) -> Result<(Exitcode, InstanceHandle), Error> {
     let region = MmapRegion::create(1, &Limits::default())?;
     let module = test_module_wasi(path)?;
 
     let mut inst = region
         .new_instance_builder(module)
         .with_embed_ctx(ctx)
+        .with_embed_ctx(vfs)
         .build()?;
 
-    match inst.run("_start", &[]) {
+    let exitcode = exitcode(inst.run("_start", &[]))?;
+    Ok((exitcode, inst))
+}
+
+fn exitcode(res: Result<RunResult, lucet_runtime::Error>) -> Result<Exitcode, Error> {
+    match res {
         // normal termination implies 0 exit code
         Ok(_) => Ok(0),
         Err(lucet_runtime::Error::RuntimeTerminated(
@@ -105,13 +299,32 @@ pub fn run<P: AsRef<Path>>(path: P, ctx: WasiCtx) -> Result<Exitcode, Error> {
 pub fn run_with_stdout<P: AsRef<Path>>(
     path: P,
     ctx: &mut WasiCtxBuilder,
+) -> Result<(Exitcode, String), Error> {
+    run_with_env_and_stdout(path, ctx, false, None)
+}
+
+/// Run a guest in deterministic mode, using `env` for its clocks and random numbers, or those of
+/// its `WasiCtx` without one.
+pub fn run_deterministic_with_stdout<P: AsRef<Path>>(
+    path: P,
+    ctx: &mut WasiCtxBuilder,
+    env: Option<DeterministicEnv>,
+) -> Result<(Exitcode, String), Error> {
+    run_with_env_and_stdout(path, ctx, true, env)
+}
+
+fn run_with_env_and_stdout<P: AsRef<Path>>(
+    path: P,
+    ctx: &mut WasiCtxBuilder,
+    deterministic: bool,
+    env: Option<DeterministicEnv>,
 ) -> Result<(Exitcode, String), Error> {
     let (pipe_out, pipe_in) = nix::unistd::pipe()?;
 
     ctx.stdout(unsafe { File::from_raw_fd(pipe_in) });
     let ctx = ctx.build()?;
 
-    let exitcode = run(path, ctx)?;
+    let exitcode = run_with_env(path, ctx, deterministic, env)?;
 
     let mut stdout_file = unsafe { File::from_raw_fd(pipe_out) };
     let mut stdout = String::new();
@@ -1,12 +1,20 @@
 mod test_helpers;
 
 use crate::test_helpers::{
-    lucet_wasi_tests_internal_ensure_linked, run, run_with_null_stdin, run_with_stdout,
-    LUCET_WASI_ROOT,
+    lucet_wasi_tests_internal_ensure_linked, run, run_deterministic_with_stdout, run_killed_after,
+    run_snapshot0, run_with_null_stdin, run_with_sockets, run_with_sockets_async,
+    run_with_sockets_killed_after, run_with_stdout, run_with_vfs, LUCET_WASI_ROOT,
 };
-use lucet_wasi::{WasiCtx, WasiCtxBuilder};
+use lucet_wasi::{
+    DeterministicEnv, FixedClock, OutputCapture, OutputStream, PathPolicy, PreopenCaps,
+    StdioPolicy, VirtualDir, VirtualFs, WasiCtx, WasiCtxBuilder, WasiQuota, FIRST_SOCKET_FD,
+};
+use std::cell::RefCell;
 use std::fs::File;
+use std::os::unix::io::FromRawFd;
 use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 use tempfile::TempDir;
 
 #[test]
@@ -36,6 +44,143 @@ fn hello() {
     assert_eq!(&stdout, "hello, wasi!\n");
 }
 
+#[test]
+fn trace() {
+    let hello = Path::new(LUCET_WASI_ROOT).join("examples").join("hello.c");
+    let traces = Rc::new(RefCell::new(vec![]));
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["hello"].iter());
+    ctx.trace({
+        let traces = traces.clone();
+        move |trace| traces.borrow_mut().push(trace.to_string())
+    });
+
+    let (exitcode, stdout) = run_with_stdout(&hello, &mut ctx).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(&stdout, "hello, wasi!\n");
+    let traces = traces.borrow();
+    assert!(traces.iter().any(|t| t.starts_with("args_sizes_get(")));
+    let write = traces
+        .iter()
+        .find(|t| t.starts_with("fd_write(fd=1, "))
+        .expect("the guest writes to stdout");
+    // the buffers are shown by their lengths, and `nwritten` as the count the call stored
+    assert!(write.contains(", iovs=["), "{}", write);
+    assert!(write.contains(", nwritten=13) = Success <"), "{}", write);
+
+    // tracing can be turned off for an instance
+    let traces = Rc::new(RefCell::new(vec![]));
+    let ctx = WasiCtxBuilder::new()
+        .args(["hello"].iter())
+        .trace({
+            let traces = traces.clone();
+            move |trace| traces.borrow_mut().push(trace.to_string())
+        })
+        .build()
+        .unwrap();
+    assert!(ctx.is_tracing());
+    ctx.set_tracing(false);
+    assert_eq!(run(&hello, ctx).unwrap(), 0);
+    assert!(traces.borrow().is_empty());
+}
+
+#[test]
+fn inherit_env_matching() {
+    std::env::set_var("LUCET_WASI_TEST_KEEP", "kept");
+    std::env::set_var("LUCET_WASI_TEST_PREFIX_A", "prefixed");
+    std::env::set_var("LUCET_WASI_TEST_DROP", "dropped");
+
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["env_filter"].iter());
+    ctx.inherit_env_matching(["LUCET_WASI_TEST_KEEP", "LUCET_WASI_TEST_PREFIX_*"].iter());
+    let (exitcode, stdout) = run_with_stdout("env_filter.c", &mut ctx).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(
+        stdout,
+        "LUCET_WASI_TEST_KEEP=kept\n\
+         LUCET_WASI_TEST_PREFIX_A=prefixed\n\
+         LUCET_WASI_TEST_DROP=(unset)\n"
+    );
+
+    // the startup code of the guest exits with an error when it cannot read its arguments
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["env_filter", "an argument too many"].iter());
+    ctx.max_args_env_size(16);
+    let (exitcode, stdout) = run_with_stdout("env_filter.c", &mut ctx).unwrap();
+    assert_ne!(exitcode, 0);
+    assert_eq!(stdout, "");
+}
+
+#[test]
+fn capture_stdio() {
+    let stdout = OutputCapture::buffer();
+    let stderr = OutputCapture::ring_buffer(20);
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["capture_stdio"].iter());
+    ctx.capture_stdout(stdout.clone());
+    ctx.capture_stderr(stderr.clone());
+    let exitcode = run("capture_stdio.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(
+        String::from_utf8(stdout.take()).unwrap(),
+        "to stdout\nvia fd 2\n"
+    );
+    assert_eq!(
+        String::from_utf8(stderr.contents()).unwrap(),
+        "rr\nsecond to stderr\n"
+    );
+    assert_eq!(stderr.discarded(), 13);
+
+    let writes = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["capture_stdio"].iter());
+    ctx.capture_stderr({
+        let writes = writes.clone();
+        OutputCapture::callback(move |data| {
+            writes
+                .lock()
+                .unwrap()
+                .push(String::from_utf8(data.to_vec()).unwrap())
+        })
+    });
+    let exitcode = run("capture_stdio.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(
+        *writes.lock().unwrap(),
+        vec!["first to stderr\n", "second to stderr\n"]
+    );
+}
+
+#[test]
+fn stream_output() {
+    let writes = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["capture_stdio"].iter());
+    ctx.stream_output("instance-1", {
+        let writes = writes.clone();
+        move |instance, stream, data| {
+            writes.lock().unwrap().push((
+                instance.to_owned(),
+                stream,
+                String::from_utf8(data.to_vec()).unwrap(),
+            ))
+        }
+    });
+    let exitcode = run("capture_stdio.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+    let write = |stream, data: &str| ("instance-1".to_owned(), stream, data.to_owned());
+    assert_eq!(
+        *writes.lock().unwrap(),
+        vec![
+            write(OutputStream::Stdout, "to stdout\n"),
+            write(OutputStream::Stderr, "first to stderr\n"),
+            write(OutputStream::Stderr, "second to stderr\n"),
+            // stdout was renumbered over stderr
+            write(OutputStream::Stdout, "via fd 2\n"),
+        ]
+    );
+}
+
 #[test]
 fn hello_args() {
     let mut ctx = WasiCtxBuilder::new();
@@ -112,10 +257,86 @@ fn getentropy() {
     assert_eq!(exitcode, 0);
 }
 
+#[test]
+fn deterministic_clocks_and_random() {
+    let run_once = |seed| {
+        let mut ctx = WasiCtxBuilder::new();
+        ctx.args(["deterministic"].iter());
+        let env = DeterministicEnv::new(seed)
+            .with_start_time(1_500_000_000_000_000_000)
+            .with_clock_step(1_000);
+        let (exitcode, stdout) =
+            run_deterministic_with_stdout("deterministic.c", &mut ctx, Some(env)).unwrap();
+        assert_eq!(exitcode, 0);
+        stdout
+    };
+
+    let stdout = run_once(42);
+    assert!(stdout.starts_with("1500000000.000000000 1500000000.000001000\n"));
+    assert_eq!(stdout, run_once(42));
+    assert_ne!(stdout, run_once(43));
+}
+
+#[test]
+fn deterministic_env_of_the_context() {
+    let run_once = |env: Option<DeterministicEnv>, ctx_env: Option<DeterministicEnv>| {
+        let mut ctx = WasiCtxBuilder::new();
+        ctx.args(["deterministic"].iter());
+        if let Some(ctx_env) = ctx_env {
+            ctx.deterministic_env(ctx_env);
+        }
+        let (exitcode, stdout) =
+            run_deterministic_with_stdout("deterministic.c", &mut ctx, env).unwrap();
+        assert_eq!(exitcode, 0);
+        stdout
+    };
+
+    // without an environment of its own, the instance uses the default one of its context
+    let stdout = run_once(None, None);
+    assert!(stdout.starts_with("0.000000000 0.000001000\n"));
+    assert_eq!(stdout, run_once(Some(DeterministicEnv::default()), None));
+
+    // which an environment of the instance takes the place of
+    let ctx_env = || DeterministicEnv::new(7).with_start_time(1_000_000_000);
+    let stdout = run_once(None, Some(ctx_env()));
+    assert!(stdout.starts_with("1.000000000 1.000001000\n"));
+    assert_eq!(stdout, run_once(Some(ctx_env()), None));
+    assert_eq!(
+        run_once(Some(DeterministicEnv::default()), Some(ctx_env())),
+        run_once(None, None)
+    );
+}
+
+#[test]
+fn fixed_clock() {
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["deterministic"].iter());
+    ctx.clock(FixedClock::new(1_500_000_000_123_456_789));
+    let (exitcode, stdout) = run_with_stdout("deterministic.c", &mut ctx).unwrap();
+    assert_eq!(exitcode, 0);
+    assert!(stdout.starts_with("1500000000.123456789 1500000000.123456789\n"));
+}
+
+#[test]
+fn seeded_random() {
+    // the second line of the output is the random bytes
+    let run_once = |seed| {
+        let mut ctx = WasiCtxBuilder::new();
+        ctx.args(["deterministic"].iter());
+        ctx.random_seed(seed);
+        let (exitcode, stdout) = run_with_stdout("deterministic.c", &mut ctx).unwrap();
+        assert_eq!(exitcode, 0);
+        stdout.lines().nth(1).unwrap().to_owned()
+    };
+
+    let random = run_once(42);
+    assert_eq!(random, run_once(42));
+    assert_ne!(random, run_once(43));
+}
+
 #[test]
 fn stdin() {
     use std::io::Write;
-    use std::os::unix::io::FromRawFd;
 
     let (pipe_out, pipe_in) = nix::unistd::pipe().expect("can create pipe");
 
@@ -133,6 +354,30 @@ fn stdin() {
     assert_eq!(&stdout, "hello from stdin!");
 }
 
+#[test]
+fn stdio_policies() {
+    use std::io::{Read, Write};
+
+    let (stdin, mut stdin_host) = StdioPolicy::input_pipe().expect("can create pipe");
+    let (stdout, mut stdout_host) = StdioPolicy::output_pipe().expect("can create pipe");
+    write!(stdin_host, "hello from a pipe!").expect("pipe write succeeds");
+    drop(stdin_host);
+
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["stdin"].iter());
+    ctx.stdin_policy(stdin);
+    ctx.stdout_policy(stdout);
+    ctx.stderr_policy(StdioPolicy::Null);
+    let exitcode = run("stdin.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+
+    let mut output = String::new();
+    stdout_host
+        .read_to_string(&mut output)
+        .expect("pipe read succeeds");
+    assert_eq!(output, "hello from a pipe!");
+}
+
 #[test]
 fn preopen_populates() {
     let tmpdir = TempDir::new().unwrap();
@@ -381,6 +626,42 @@ fn poll() {
     assert_eq!(exitcode, 0);
 }
 
+#[test]
+fn poll_oneoff() {
+    let (pipe_out, pipe_in) = nix::unistd::pipe().unwrap();
+    nix::unistd::write(pipe_in, b"hello").unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["poll_oneoff"].iter());
+    ctx.stdin(unsafe { File::from_raw_fd(pipe_out) });
+    let exitcode = run("poll_oneoff.c", ctx.build().unwrap()).unwrap();
+    nix::unistd::close(pipe_in).unwrap();
+    assert_eq!(exitcode, 0);
+}
+
+#[test]
+fn poll_oneoff_terminated() {
+    let (pipe_out, pipe_in) = nix::unistd::pipe().unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["poll_forever"].iter());
+    ctx.stdin(unsafe { File::from_raw_fd(pipe_out) });
+    let start = Instant::now();
+    let res = run_killed_after(
+        "poll_forever.c",
+        ctx.build().unwrap(),
+        Duration::from_millis(100),
+    )
+    .unwrap();
+    nix::unistd::close(pipe_in).unwrap();
+    // the poll notices the termination instead of blocking until stdin is ready
+    assert!(matches!(
+        res,
+        Err(lucet_runtime::Error::RuntimeTerminated(
+            lucet_runtime::TerminationDetails::Remote
+        ))
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
 #[test]
 fn stat() {
     let tmpdir = TempDir::new().unwrap();
@@ -408,3 +689,453 @@ fn fs() {
     let exitcode = run("fs.c", ctx).unwrap();
     assert_eq!(exitcode, 0);
 }
+
+#[test]
+fn preview1() {
+    let tmpdir = TempDir::new().unwrap();
+    let preopen_host_path = tmpdir.path().join("preopen");
+    std::fs::create_dir(&preopen_host_path).unwrap();
+    let preopen_dir = File::open(&preopen_host_path).unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["preview1"].iter());
+    ctx.preopened_dir(preopen_dir, "/sandbox");
+    let ctx = ctx.build().expect("can build WasiCtx");
+    let exitcode = run("preview1.c", ctx).unwrap();
+    assert_eq!(exitcode, 0);
+}
+
+#[test]
+fn snapshot0() {
+    let tmpdir = TempDir::new().unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["snapshot0"].iter());
+    ctx.preopened_dir(File::open(tmpdir.path()).unwrap(), "/sandbox");
+    let exitcode = run_snapshot0("snapshot0.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(
+        std::fs::read_to_string(tmpdir.path().join("snapshot0.txt")).unwrap(),
+        "hello world"
+    );
+}
+
+#[test]
+fn vfs() {
+    let config = VirtualDir::new()
+        .with_file("app.toml", "threads = 4\n")
+        .with_dir(
+            "assets",
+            VirtualDir::new().with_file("index.html", "<html></html>"),
+        );
+    let vfs = VirtualFs::new().with_preopen("/config", config);
+    let ctx = WasiCtx::new(["vfs"].iter()).unwrap();
+    let (exitcode, inst) = run_with_vfs("vfs.c", ctx, vfs).unwrap();
+    assert_eq!(exitcode, 0);
+
+    let vfs = inst.get_embed_ctx::<VirtualFs>().unwrap().unwrap();
+    assert_eq!(
+        vfs.read_file("/config/out/log.txt").as_deref(),
+        Some(&b"hello from the guest\nagain\n"[..])
+    );
+    assert_eq!(vfs.read_file("/config/out/log.tmp"), None);
+    assert_eq!(vfs.read_file("/config/assets/index.html"), None);
+    assert_eq!(
+        vfs.read_file("/config/app.toml").as_deref(),
+        Some(&b"threads = 4\n"[..])
+    );
+}
+
+#[test]
+fn vfs_overlay() {
+    let tmpdir = TempDir::new().unwrap();
+    std::fs::create_dir(tmpdir.path().join("data")).unwrap();
+    std::fs::write(tmpdir.path().join("data/input.txt"), "from the host\n").unwrap();
+    std::fs::write(tmpdir.path().join("remove-me"), "").unwrap();
+
+    let vfs = VirtualFs::new()
+        .with_write_limit(64)
+        .with_preopen("/app", VirtualDir::from_host(tmpdir.path()).unwrap());
+    let ctx = WasiCtx::new(["vfs_overlay"].iter()).unwrap();
+    let (exitcode, inst) = run_with_vfs("vfs_overlay.c", ctx, vfs).unwrap();
+    assert_eq!(exitcode, 0);
+
+    let vfs = inst.get_embed_ctx::<VirtualFs>().unwrap().unwrap();
+    assert_eq!(
+        vfs.read_file("/app/data/input.txt").as_deref(),
+        Some(&b"from the host\nand the guest\n"[..])
+    );
+    assert_eq!(
+        vfs.read_file("/app/scratch.tmp").as_deref(),
+        Some(&b"scratch\n"[..])
+    );
+    assert_eq!(vfs.read_file("/app/remove-me"), None);
+
+    // the host directory is left as it was
+    assert_eq!(
+        std::fs::read_to_string(tmpdir.path().join("data/input.txt")).unwrap(),
+        "from the host\n"
+    );
+    assert!(tmpdir.path().join("remove-me").exists());
+    assert!(!tmpdir.path().join("scratch.tmp").exists());
+}
+
+#[test]
+fn map_dir() {
+    let tmpdir = TempDir::new().unwrap();
+    std::fs::create_dir(tmpdir.path().join("config")).unwrap();
+    std::fs::write(tmpdir.path().join("config/settings.txt"), "mapped\n").unwrap();
+
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.map_dir("/app", tmpdir.path()).unwrap();
+    let (exitcode, stdout) = run_with_stdout("map_dir.c", &mut ctx).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(stdout, "mapped\n");
+
+    let err = WasiCtxBuilder::new()
+        .map_dir("/app", tmpdir.path().join("config/settings.txt"))
+        .err()
+        .expect("a file cannot be mapped");
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn preopen_caps() {
+    let tmpdir = TempDir::new().unwrap();
+    let dir = |name: &str| {
+        let path = tmpdir.path().join(name);
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("file"), "file").unwrap();
+        path
+    };
+    let ro = dir("ro");
+    std::fs::create_dir(ro.join("sub")).unwrap();
+    std::fs::write(ro.join("sub").join("nested"), "nested").unwrap();
+    let nocreate = dir("nocreate");
+    let nofollow = dir("nofollow");
+    std::os::unix::fs::symlink("file", nofollow.join("link")).unwrap();
+
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["preopen_caps"].iter());
+    ctx.preopened_dir_with_caps(
+        File::open(&ro).unwrap(),
+        "/ro",
+        PreopenCaps::new().read_only(),
+    );
+    ctx.preopened_dir_with_caps(
+        File::open(&nocreate).unwrap(),
+        "/nocreate",
+        PreopenCaps::new().no_create(),
+    );
+    ctx.preopened_dir_with_caps(
+        File::open(&nofollow).unwrap(),
+        "/nofollow",
+        PreopenCaps::new().no_symlink_follow(),
+    );
+    let ctx = ctx.build().expect("can build WasiCtx");
+    let exitcode = run("preopen_caps.c", ctx).unwrap();
+    assert_eq!(exitcode, 0);
+
+    assert_eq!(std::fs::read_to_string(ro.join("file")).unwrap(), "file");
+    assert!(!ro.join("new").exists());
+    assert!(!nocreate.join("file").exists());
+    assert!(!nocreate.join("new").exists());
+}
+
+#[test]
+fn path_policy() {
+    let tmpdir = TempDir::new().unwrap();
+    let sandbox = tmpdir.path().join("sandbox");
+    std::fs::create_dir(&sandbox).unwrap();
+    std::fs::create_dir(sandbox.join("sub")).unwrap();
+    std::fs::write(sandbox.join("file"), "file").unwrap();
+    std::os::unix::fs::symlink("file", sandbox.join("link")).unwrap();
+
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["path_policy"].iter());
+    ctx.preopened_dir(File::open(&sandbox).unwrap(), "/sandbox");
+    ctx.path_policy(
+        PathPolicy::new()
+            .no_symlink_follow()
+            .no_symlink_create()
+            .allow_absolute_paths(),
+    );
+    let ctx = ctx.build().expect("can build WasiCtx");
+    let exitcode = run("path_policy.c", ctx).unwrap();
+    assert_eq!(exitcode, 0);
+
+    assert!(std::fs::symlink_metadata(sandbox.join("new_link")).is_err());
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn crypto() {
+    use crate::test_helpers::run_with_crypto;
+    use lucet_wasi::{RingProvider, WasiCryptoCtx};
+
+    let ctx = WasiCtx::new(["crypto"].iter()).unwrap();
+    let crypto = WasiCryptoCtx::new(RingProvider::new());
+    let exitcode = run_with_crypto("crypto.c", ctx, crypto).unwrap();
+    assert_eq!(exitcode, 0);
+}
+
+#[cfg(feature = "nn")]
+#[test]
+fn nn() {
+    use crate::test_helpers::run_with_nn;
+    use lucet_wasi::{
+        ExecutionTarget, GraphEncoding, NnBackend, NnErrno, NnExecutionContext, NnGraph, Tensor,
+        TensorType, WasiNnCtx,
+    };
+
+    /// A backend with one graph, which sums its input of `f32`s.
+    struct SumBackend;
+    struct SumGraph;
+    struct SumContext {
+        input: Vec<f32>,
+        sum: Option<f32>,
+    }
+
+    impl NnBackend for SumBackend {
+        fn load(
+            &mut self,
+            builders: &[&[u8]],
+            encoding: GraphEncoding,
+            target: ExecutionTarget,
+        ) -> Result<Box<dyn NnGraph>, NnErrno> {
+            assert_eq!(encoding, GraphEncoding::Onnx);
+            assert_eq!(target, ExecutionTarget::Cpu);
+            match builders {
+                [b"sum"] => Ok(Box::new(SumGraph)),
+                _ => Err(NnErrno::InvalidEncoding),
+            }
+        }
+    }
+
+    impl NnGraph for SumGraph {
+        fn init_execution_context(&mut self) -> Result<Box<dyn NnExecutionContext>, NnErrno> {
+            Ok(Box::new(SumContext {
+                input: vec![],
+                sum: None,
+            }))
+        }
+    }
+
+    impl NnExecutionContext for SumContext {
+        fn set_input(&mut self, index: u32, tensor: &Tensor<'_>) -> Result<(), NnErrno> {
+            if index != 0 || tensor.ty != TensorType::F32 || tensor.dimensions.len() != 1 {
+                return Err(NnErrno::InvalidArgument);
+            }
+            self.input = tensor
+                .data
+                .chunks(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect();
+            Ok(())
+        }
+
+        fn compute(&mut self) -> Result<(), NnErrno> {
+            self.sum = Some(self.input.iter().sum());
+            Ok(())
+        }
+
+        fn get_output(&mut self, index: u32, out: &mut [u8]) -> Result<u32, NnErrno> {
+            let sum = self.sum.ok_or(NnErrno::RuntimeError)?;
+            if index != 0 || out.len() < 4 {
+                return Err(NnErrno::InvalidArgument);
+            }
+            out[..4].copy_from_slice(&sum.to_le_bytes());
+            Ok(4)
+        }
+    }
+
+    let ctx = WasiCtx::new(["nn"].iter()).unwrap();
+    let exitcode = run_with_nn("nn.c", ctx, WasiNnCtx::new(SumBackend)).unwrap();
+    assert_eq!(exitcode, 0);
+}
+
+/// Run `sockets.c` against a client of its listener and the upstream it connects to, which are
+/// slow to talk if `delay` is set, returning how many times the guest yielded with `async_io`.
+fn run_sockets_guest(async_io: bool, delay: Duration) -> usize {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let listen_addr = listener.local_addr().unwrap();
+    let upstream = TcpListener::bind("127.0.0.1:0").unwrap();
+    let upstream_addr = upstream.local_addr().unwrap();
+    let denied = TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let client = std::thread::spawn(move || {
+        std::thread::sleep(delay);
+        let mut stream = TcpStream::connect(listen_addr).unwrap();
+        std::thread::sleep(delay);
+        stream.write_all(b"ping").unwrap();
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply).unwrap();
+        reply
+    });
+    let server = std::thread::spawn(move || {
+        let (mut stream, _) = upstream.accept().unwrap();
+        let mut request = [0; 5];
+        stream.read_exact(&mut request).unwrap();
+        std::thread::sleep(delay);
+        stream.write_all(b"world").unwrap();
+        request
+    });
+
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["sockets"].iter());
+    ctx.env("LISTEN_FD", FIRST_SOCKET_FD.to_string().as_str());
+    ctx.env("ALLOWED_ADDR", upstream_addr.to_string().as_str());
+    ctx.env(
+        "DENIED_ADDR",
+        denied.local_addr().unwrap().to_string().as_str(),
+    );
+    ctx.preopened_socket(listener);
+    ctx.allow_connect(upstream_addr);
+    let (exitcode, yields) = if async_io {
+        ctx.async_io();
+        run_with_sockets_async("sockets.c", ctx.build().unwrap()).unwrap()
+    } else {
+        (
+            run_with_sockets("sockets.c", ctx.build().unwrap()).unwrap(),
+            0,
+        )
+    };
+    assert_eq!(exitcode, 0);
+
+    assert_eq!(client.join().unwrap(), "pong");
+    assert_eq!(&server.join().unwrap(), b"hello");
+    yields
+}
+
+#[test]
+fn sockets() {
+    run_sockets_guest(false, Duration::from_secs(0));
+}
+
+#[test]
+fn sock_accept_terminated() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["accept_forever"].iter());
+    ctx.env("LISTEN_FD", FIRST_SOCKET_FD.to_string().as_str());
+    ctx.preopened_socket(listener);
+    let start = Instant::now();
+    let res = run_with_sockets_killed_after(
+        "accept_forever.c",
+        ctx.build().unwrap(),
+        Duration::from_millis(100),
+    )
+    .unwrap();
+    // the accept notices the termination instead of blocking until a connection comes
+    assert!(matches!(
+        res,
+        Err(lucet_runtime::Error::RuntimeTerminated(
+            lucet_runtime::TerminationDetails::Remote
+        ))
+    ));
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn sockets_async() {
+    // the guest yields rather than blocking for the connection, the ping, and the reply
+    let yields = run_sockets_guest(true, Duration::from_millis(100));
+    assert!(yields >= 3, "the guest yielded {} times", yields);
+}
+
+#[test]
+fn read_only_fs() {
+    let tmpdir = TempDir::new().unwrap();
+    std::fs::write(tmpdir.path().join("file"), "file").unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["read_only_fs"].iter());
+    ctx.preopened_dir(File::open(tmpdir.path()).unwrap(), "/sandbox");
+    ctx.read_only_fs();
+    let (exitcode, stdout) = run_with_stdout("read_only_fs.c", &mut ctx).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(stdout, "ok");
+    assert_eq!(std::fs::read(tmpdir.path().join("file")).unwrap(), b"file");
+    assert_eq!(std::fs::read_dir(tmpdir.path()).unwrap().count(), 1);
+}
+
+#[test]
+fn quota() {
+    let tmpdir = TempDir::new().unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["quota"].iter());
+    ctx.preopened_dir(File::open(tmpdir.path()).unwrap(), "/sandbox");
+    ctx.quota(WasiQuota::new().max_bytes_written(10).max_files_opened(2));
+    let exitcode = run("quota.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(
+        std::fs::read(tmpdir.path().join("out")).unwrap(),
+        b"helloworld"
+    );
+    assert!(!tmpdir.path().join("third").exists());
+}
+
+#[test]
+fn fs_quota() {
+    let tmpdir = TempDir::new().unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["fs_quota"].iter());
+    ctx.preopened_dir(File::open(tmpdir.path()).unwrap(), "/sandbox");
+    ctx.quota(
+        WasiQuota::new()
+            .max_file_bytes_written(8)
+            .max_files_created(2),
+    );
+    let (exitcode, stdout) = run_with_stdout("fs_quota.c", &mut ctx).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(stdout, "ok");
+    assert_eq!(std::fs::read(tmpdir.path().join("a")).unwrap(), b"12345678");
+    assert!(tmpdir.path().join("dir").is_dir());
+    assert!(!tmpdir.path().join("b").exists());
+    assert!(!tmpdir.path().join("dir2").exists());
+}
+
+#[test]
+fn renumber() {
+    let tmpdir = TempDir::new().unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["renumber"].iter());
+    ctx.preopened_dir(File::open(tmpdir.path()).unwrap(), "/sandbox");
+    // stdio and the preopen leave room for 2 more
+    ctx.max_open_fds(6);
+    ctx.quota(WasiQuota::new().max_file_bytes_written(2));
+    let exitcode = run("renumber.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+    assert_eq!(std::fs::read(tmpdir.path().join("c")).unwrap(), b"hi");
+    assert_eq!(std::fs::read(tmpdir.path().join("b")).unwrap(), b"");
+}
+
+#[test]
+fn temp_dir() {
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["temp_dir"].iter());
+    ctx.temp_dir("/tmp", 10).unwrap();
+    assert!(ctx.temp_dir("/tmp2", 10).is_err());
+    let ctx = ctx.build().unwrap();
+    let host_path = ctx.temp_dir().unwrap().to_owned();
+    assert!(host_path.is_dir());
+    assert_eq!(std::fs::read_dir(&host_path).unwrap().count(), 0);
+    let exitcode = run("temp_dir.c", ctx).unwrap();
+    assert_eq!(exitcode, 0);
+    // the directory went with the instance
+    assert!(!host_path.exists());
+}
+
+#[test]
+fn fd_limit() {
+    let tmpdir = TempDir::new().unwrap();
+    let mut ctx = WasiCtxBuilder::new();
+    ctx.args(["fd_limit"].iter());
+    ctx.preopened_dir(File::open(tmpdir.path()).unwrap(), "/sandbox");
+    // stdio and the preopen leave room for 4 more
+    ctx.max_open_fds(8);
+    let exitcode = run("fd_limit.c", ctx.build().unwrap()).unwrap();
+    assert_eq!(exitcode, 0);
+    assert!(tmpdir.path().join("again").exists());
+    assert!(!tmpdir.path().join("more").exists());
+}
@@ -0,0 +1,350 @@
+use heck::{ShoutySnakeCase, SnakeCase};
+use std::fmt::Write;
+use witx::Layout;
+
+/// A C header for guests, declaring the types of `doc` and, for each of its functions, the import
+/// of the function with its core wasm signature, along with a `static inline` stub that calls the
+/// import with the types of the witx.
+///
+/// The names declared start with `prefix`: types are `<prefix>_<type>_t`, their values
+/// `<PREFIX>_<TYPE>_<VALUE>`, imports `__<prefix>_<module>_<function>`, and stubs
+/// `<prefix>_<function>`, so the stubs of functions of the same name in two modules of `doc`
+/// conflict.
+pub fn c_header(doc: &witx::Document, prefix: &str) -> String {
+    let mut header = Header {
+        prefix,
+        out: String::new(),
+    };
+    let guard = format!("{}_H", prefix.to_shouty_snake_case());
+    header.line("/**");
+    header.line(" * The types and functions of a witx document, for guests.");
+    header.line(" *");
+    header.line(" * Generated by lucet-wiggle. Do not edit.");
+    header.line(" */");
+    header.line(&format!("#ifndef {}", guard));
+    header.line(&format!("#define {}", guard));
+    header.line("");
+    header.line("#include <stddef.h>");
+    header.line("#include <stdint.h>");
+    header.line("");
+    header.line("#ifdef __cplusplus");
+    header.line("extern \"C\" {");
+    header.line("#endif");
+    for nt in doc.typenames() {
+        header.line("");
+        header.define(&nt);
+    }
+    for m in doc.modules() {
+        for f in m.funcs() {
+            header.line("");
+            header.declare(&m, &f);
+        }
+    }
+    header.line("");
+    header.line("#ifdef __cplusplus");
+    header.line("}");
+    header.line("#endif");
+    header.line("");
+    header.line(&format!("#endif /* {} */", guard));
+    header.out
+}
+
+struct Header<'a> {
+    prefix: &'a str,
+    out: String,
+}
+
+impl<'a> Header<'a> {
+    fn line(&mut self, line: &str) {
+        writeln!(self.out, "{}", line).unwrap();
+    }
+
+    fn docs(&mut self, indent: &str, docs: &str) {
+        if docs.trim().is_empty() {
+            return;
+        }
+        self.line(&format!("{}/**", indent));
+        for line in docs.trim().lines() {
+            self.line(&format!("{} * {}", indent, line.trim()).trim_end());
+        }
+        self.line(&format!("{} */", indent));
+    }
+
+    fn type_name(&self, name: &witx::Id) -> String {
+        format!("{}_{}_t", self.prefix, name.as_str().to_snake_case())
+    }
+
+    fn value_name(&self, ty: &witx::Id, value: &witx::Id) -> String {
+        format!("{}_{}_{}", self.prefix, ty.as_str(), value.as_str()).to_shouty_snake_case()
+    }
+
+    /// The C type of `tref`, as a member or a parameter passed by value.
+    fn ctype(&self, tref: &witx::TypeRef) -> String {
+        let ty = match tref {
+            witx::TypeRef::Name(nt) => return self.type_name(&nt.name),
+            witx::TypeRef::Value(ty) => ty,
+        };
+        match &**ty {
+            witx::Type::Builtin(b) => builtin(b).to_owned(),
+            witx::Type::Pointer(t) | witx::Type::Array(t) => format!("{} *", self.ctype(t)),
+            witx::Type::ConstPointer(t) => format!("const {} *", self.ctype(t)),
+            witx::Type::Enum(e) => int_repr(e.repr).to_owned(),
+            witx::Type::Int(i) => int_repr(i.repr).to_owned(),
+            witx::Type::Flags(f) => int_repr(f.repr).to_owned(),
+            witx::Type::Handle(_) => "uint32_t".to_owned(),
+            witx::Type::Struct(_) | witx::Type::Union(_) => {
+                unreachable!("witx only declares structs and unions as typenames")
+            }
+        }
+    }
+
+    /// The C type of the elements `tref` points to when it is passed as a pointer and a length.
+    fn element(&self, tref: &witx::TypeRef) -> String {
+        match &*tref.type_() {
+            witx::Type::Builtin(witx::BuiltinType::String) => "const char".to_owned(),
+            witx::Type::Array(t) => self.ctype(t),
+            _ => self.ctype(tref),
+        }
+    }
+
+    fn define(&mut self, nt: &witx::NamedType) {
+        self.docs("", &nt.docs);
+        let name = self.type_name(&nt.name);
+        let ty = match &nt.tref {
+            witx::TypeRef::Name(other) => {
+                self.line(&format!(
+                    "typedef {} {};",
+                    self.type_name(&other.name),
+                    name
+                ));
+                return;
+            }
+            witx::TypeRef::Value(ty) => ty,
+        };
+        match &**ty {
+            witx::Type::Enum(e) => {
+                self.line(&format!("typedef {} {};", int_repr(e.repr), name));
+                for (i, v) in e.variants.iter().enumerate() {
+                    self.docs("", &v.docs);
+                    let value = self.value_name(&nt.name, &v.name);
+                    self.line(&format!("#define {} (({}){})", value, name, i));
+                }
+            }
+            witx::Type::Int(int) => {
+                self.line(&format!("typedef {} {};", int_repr(int.repr), name));
+                for c in int.consts.iter() {
+                    self.docs("", &c.docs);
+                    let value = self.value_name(&nt.name, &c.name);
+                    self.line(&format!("#define {} (({}){}ull)", value, name, c.value));
+                }
+            }
+            witx::Type::Flags(f) => {
+                self.line(&format!("typedef {} {};", int_repr(f.repr), name));
+                for (i, flag) in f.flags.iter().enumerate() {
+                    self.docs("", &flag.docs);
+                    let value = self.value_name(&nt.name, &flag.name);
+                    self.line(&format!("#define {} (({})(1ull << {}))", value, name, i));
+                }
+            }
+            witx::Type::Struct(s) => {
+                self.line(&format!("typedef struct {} {{", name));
+                for m in s.members.iter() {
+                    self.docs("    ", &m.docs);
+                    let member = format!("    {} {};", self.ctype(&m.tref), m.name.as_str());
+                    self.line(&member);
+                }
+                self.line(&format!("}} {};", name));
+                self.assert_layout(nt, &name);
+            }
+            witx::Type::Union(u) => {
+                self.line(&format!("typedef struct {} {{", name));
+                self.line(&format!("    {} tag;", self.type_name(&u.tag.name)));
+                let variants: Vec<_> = u
+                    .variants
+                    .iter()
+                    .filter_map(|v| v.tref.as_ref().map(|tref| (v, tref)))
+                    .collect();
+                if !variants.is_empty() {
+                    self.line("    union {");
+                    for (v, tref) in variants {
+                        self.docs("        ", &v.docs);
+                        let variant = format!("        {} {};", self.ctype(tref), v.name.as_str());
+                        self.line(&variant);
+                    }
+                    self.line("    } u;");
+                }
+                self.line(&format!("}} {};", name));
+                self.assert_layout(nt, &name);
+            }
+            witx::Type::Array(_) | witx::Type::Builtin(witx::BuiltinType::String) => {
+                // there is no C type to pass as a pointer and a length at once
+                self.line(&format!(
+                    "/* `{}` is passed as a pointer and a length */",
+                    nt.name.as_str()
+                ));
+            }
+            _ => {
+                self.line(&format!("typedef {} {};", self.ctype(&nt.tref), name));
+            }
+        }
+    }
+
+    /// Check that the C compiler lays out the type `name` as witx does.
+    fn assert_layout(&mut self, nt: &witx::NamedType, name: &str) {
+        let layout = nt.mem_size_align();
+        self.line(&format!(
+            "_Static_assert(sizeof({}) == {}, \"witx calculated size\");",
+            name, layout.size
+        ));
+        self.line(&format!(
+            "_Static_assert(_Alignof({}) == {}, \"witx calculated align\");",
+            name, layout.align
+        ));
+    }
+
+    fn declare(&mut self, m: &witx::Module, f: &witx::InterfaceFunc) {
+        let coretype = f.core_type();
+        let import = format!(
+            "__{}_{}_{}",
+            self.prefix,
+            m.name.as_str().to_snake_case(),
+            f.name.as_str().to_snake_case()
+        );
+        let stub = format!("{}_{}", self.prefix, f.name.as_str().to_snake_case());
+
+        // the import takes exactly the core wasm types the host was built with
+        let import_ret = coretype.ret.as_ref().map_or("void", |r| atom(r.repr()));
+        let import_params = coretype
+            .args
+            .iter()
+            .map(|a| format!("{} {}", atom(a.repr()), core_arg(a)))
+            .collect::<Vec<_>>();
+        self.line(&format!(
+            "{} {}({}) __attribute__((__import_module__(\"{}\"), __import_name__(\"{}\")));",
+            import_ret,
+            import,
+            params(import_params),
+            m.name.as_str(),
+            f.name.as_str()
+        ));
+
+        // the stub takes the types of the witx, and casts them to the core types
+        let stub_ret = coretype
+            .ret
+            .as_ref()
+            .map_or("void".to_owned(), |r| self.ctype(&r.param.tref));
+        let stub_params = coretype
+            .args
+            .iter()
+            .map(|a| format!("{} {}", self.core_arg_type(a), core_arg(a)))
+            .collect::<Vec<_>>();
+        let call_args = coretype
+            .args
+            .iter()
+            .map(|a| {
+                let is_pointer = match a.signifies {
+                    witx::CoreParamSignifies::PointerTo { .. } => true,
+                    witx::CoreParamSignifies::LengthOf { .. } => false,
+                    witx::CoreParamSignifies::Value { .. } => matches!(
+                        &*a.param.tref.type_(),
+                        witx::Type::Pointer(_) | witx::Type::ConstPointer(_)
+                    ),
+                };
+                if is_pointer {
+                    format!("({})(uintptr_t){}", atom(a.repr()), core_arg(a))
+                } else {
+                    format!("({}){}", atom(a.repr()), core_arg(a))
+                }
+            })
+            .collect::<Vec<_>>();
+        self.docs("", &f.docs);
+        self.line(&format!(
+            "static inline {} {}({}) {{",
+            stub_ret,
+            stub,
+            params(stub_params)
+        ));
+        let call = format!("{}({})", import, call_args.join(", "));
+        if coretype.ret.is_some() {
+            self.line(&format!("    return ({}){};", stub_ret, call));
+        } else {
+            self.line(&format!("    {};", call));
+        }
+        self.line("}");
+    }
+
+    /// The C type of the stub parameter for the core argument `a`.
+    fn core_arg_type(&self, a: &witx::CoreParamType) -> String {
+        match a.signifies {
+            witx::CoreParamSignifies::Value { .. } => self.ctype(&a.param.tref),
+            witx::CoreParamSignifies::LengthOf { .. } => "size_t".to_owned(),
+            witx::CoreParamSignifies::PointerTo { .. } => match a.param.position {
+                witx::InterfaceFuncParamPosition::Result(_) => {
+                    format!("{} *", self.ctype(&a.param.tref))
+                }
+                witx::InterfaceFuncParamPosition::Param(_) => {
+                    match a.param.tref.type_().passed_by() {
+                        witx::TypePassedBy::PointerLengthPair { .. } => {
+                            format!("{} *", self.element(&a.param.tref))
+                        }
+                        _ => format!("const {} *", self.ctype(&a.param.tref)),
+                    }
+                }
+            },
+        }
+    }
+}
+
+/// The name of the core argument `a`, which is the name of its parameter, with `_len` after it for
+/// the length of a pointer and length pair.
+fn core_arg(a: &witx::CoreParamType) -> String {
+    let name = a.param.name.as_str().to_snake_case();
+    match a.signifies {
+        witx::CoreParamSignifies::LengthOf { .. } => format!("{}_len", name),
+        _ => name,
+    }
+}
+
+fn params(params: Vec<String>) -> String {
+    if params.is_empty() {
+        "void".to_owned()
+    } else {
+        params.join(", ")
+    }
+}
+
+fn atom(atom: witx::AtomType) -> &'static str {
+    match atom {
+        witx::AtomType::I32 => "int32_t",
+        witx::AtomType::I64 => "int64_t",
+        witx::AtomType::F32 => "float",
+        witx::AtomType::F64 => "double",
+    }
+}
+
+fn int_repr(repr: witx::IntRepr) -> &'static str {
+    match repr {
+        witx::IntRepr::U8 => "uint8_t",
+        witx::IntRepr::U16 => "uint16_t",
+        witx::IntRepr::U32 => "uint32_t",
+        witx::IntRepr::U64 => "uint64_t",
+    }
+}
+
+fn builtin(b: &witx::BuiltinType) -> &'static str {
+    match b {
+        witx::BuiltinType::String => "char *",
+        witx::BuiltinType::Char8 => "char",
+        witx::BuiltinType::USize => "size_t",
+        witx::BuiltinType::U8 => "uint8_t",
+        witx::BuiltinType::U16 => "uint16_t",
+        witx::BuiltinType::U32 => "uint32_t",
+        witx::BuiltinType::U64 => "uint64_t",
+        witx::BuiltinType::S8 => "int8_t",
+        witx::BuiltinType::S16 => "int16_t",
+        witx::BuiltinType::S32 => "int32_t",
+        witx::BuiltinType::S64 => "int64_t",
+        witx::BuiltinType::F32 => "float",
+        witx::BuiltinType::F64 => "double",
+    }
+}
@@ -1,9 +1,10 @@
-use proc_macro2::{Span, TokenStream};
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
 use syn::{
     braced,
     parse::{Parse, ParseStream},
     punctuated::Punctuated,
-    Error, Result, Token,
+    Error, LitStr, Path, Result, Token,
 };
 use wiggle_generate::config as w;
 
@@ -15,6 +16,9 @@ mod kw {
     syn::custom_keyword!(constructor);
     syn::custom_keyword!(pre_hook);
     syn::custom_keyword!(post_hook);
+    syn::custom_keyword!(mock);
+    syn::custom_keyword!(features);
+    syn::custom_keyword!(deterministic);
 }
 
 #[derive(Debug, Clone)]
@@ -23,6 +27,14 @@ pub struct Config {
     pub constructor: TokenStream,
     pub pre_hook: Option<TokenStream>,
     pub post_hook: Option<TokenStream>,
+    /// The modules whose functions are implemented as futures.
+    pub async_modules: Vec<Ident>,
+    /// The modules to generate traits for fakes of.
+    pub mock_modules: Vec<Ident>,
+    /// The functions that are only generated when a Cargo feature is enabled.
+    pub features: Features,
+    /// The functions whose hostcalls run in deterministic mode.
+    pub deterministic: Deterministic,
 }
 
 #[derive(Debug, Clone)]
@@ -31,6 +43,10 @@ pub enum ConfigField {
     Constructor(TokenStream),
     PreHook(TokenStream),
     PostHook(TokenStream),
+    Async(Vec<Ident>),
+    Mock(Vec<Ident>),
+    Features(Features),
+    Deterministic(Deterministic),
 }
 
 impl Parse for ConfigField {
@@ -54,6 +70,28 @@ impl Parse for ConfigField {
             let contents;
             let _lbrace = braced!(contents in input);
             Ok(ConfigField::PostHook(contents.parse()?))
+        } else if lookahead.peek(Token![async]) {
+            input.parse::<Token![async]>()?;
+            input.parse::<Token![:]>()?;
+            let contents;
+            let _lbrace = braced!(contents in input);
+            let modules: Punctuated<Ident, Token![,]> = contents.parse_terminated(Ident::parse)?;
+            Ok(ConfigField::Async(modules.into_iter().collect()))
+        } else if lookahead.peek(kw::mock) {
+            input.parse::<kw::mock>()?;
+            input.parse::<Token![:]>()?;
+            let contents;
+            let _lbrace = braced!(contents in input);
+            let modules: Punctuated<Ident, Token![,]> = contents.parse_terminated(Ident::parse)?;
+            Ok(ConfigField::Mock(modules.into_iter().collect()))
+        } else if lookahead.peek(kw::features) {
+            input.parse::<kw::features>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Features(input.parse()?))
+        } else if lookahead.peek(kw::deterministic) {
+            input.parse::<kw::deterministic>()?;
+            input.parse::<Token![:]>()?;
+            Ok(ConfigField::Deterministic(input.parse()?))
         } else if lookahead.peek(kw::witx) {
             input.parse::<kw::witx>()?;
             input.parse::<Token![:]>()?;
@@ -92,6 +130,10 @@ impl Config {
         let mut constructor = None;
         let mut pre_hook = None;
         let mut post_hook = None;
+        let mut async_modules = vec![];
+        let mut mock_modules = vec![];
+        let mut features = Features::default();
+        let mut deterministic = Deterministic::default();
         for f in fields {
             match f {
                 ConfigField::Constructor(c) => {
@@ -103,6 +145,18 @@ impl Config {
                 ConfigField::PostHook(c) => {
                     post_hook = Some(c);
                 }
+                ConfigField::Async(modules) => {
+                    async_modules.extend(modules);
+                }
+                ConfigField::Mock(modules) => {
+                    mock_modules.extend(modules);
+                }
+                ConfigField::Features(f) => {
+                    features.gates.extend(f.gates);
+                }
+                ConfigField::Deterministic(d) => {
+                    deterministic.paths.extend(d.paths);
+                }
                 ConfigField::Wiggle { .. } => {} // Ignore
             }
         }
@@ -113,6 +167,10 @@ impl Config {
                 .ok_or_else(|| Error::new(err_loc, "`constructor` field required"))?,
             pre_hook,
             post_hook,
+            async_modules,
+            mock_modules,
+            features,
+            deterministic,
         })
     }
 }
@@ -126,3 +184,136 @@ impl Parse for Config {
         Ok(Config::build(fields.into_iter(), input.span())?)
     }
 }
+
+/// The functions of a witx document that are only generated when a Cargo feature of the crate
+/// invoking the macro is enabled, written as `"feature": { module::func, module, .. }`, where a
+/// module stands for all of its functions.
+#[derive(Debug, Clone, Default)]
+pub struct Features {
+    pub gates: Vec<(LitStr, Vec<Path>)>,
+}
+
+impl Features {
+    /// Check that each module and function named is in `doc`.
+    pub fn validate(&self, doc: &witx::Document) -> Result<()> {
+        validate_paths(self.gates.iter().flat_map(|(_, paths)| paths), doc)
+    }
+
+    /// The features `f` of module `m` needs, in the order they were written.
+    pub fn of(&self, m: &witx::Module, f: &witx::InterfaceFunc) -> Vec<&LitStr> {
+        self.gates
+            .iter()
+            .filter(|(_, paths)| paths.iter().any(|path| path_names(path, m, f)))
+            .map(|(feature, _)| feature)
+            .collect()
+    }
+
+    /// The attribute that only keeps an item for `f` when its features are enabled.
+    pub fn cfg(&self, m: &witx::Module, f: &witx::InterfaceFunc) -> TokenStream {
+        let features = self.of(m, f);
+        if features.is_empty() {
+            quote!()
+        } else {
+            quote!(#[cfg(all(#(feature = #features),*))])
+        }
+    }
+}
+
+impl Parse for Features {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let contents;
+        let _lbrace = braced!(contents in input);
+        let gates: Punctuated<(LitStr, Vec<Path>), Token![,]> =
+            contents.parse_terminated(|input| {
+                let feature: LitStr = input.parse()?;
+                input.parse::<Token![:]>()?;
+                Ok((feature, parse_paths(input)?))
+            })?;
+        Ok(Features {
+            gates: gates.into_iter().collect(),
+        })
+    }
+}
+
+/// The functions of a witx document whose hostcalls are declared `#[lucet_hostcall(deterministic)]`,
+/// and so are called by instances running in deterministic mode, written as
+/// `{ module::func, module, .. }`, where a module stands for all of its functions.
+///
+/// Hostcalls of the other functions terminate those instances instead.
+#[derive(Debug, Clone, Default)]
+pub struct Deterministic {
+    pub paths: Vec<Path>,
+}
+
+impl Deterministic {
+    /// Every function of every module of `doc`.
+    pub fn all(doc: &witx::Document) -> Self {
+        Deterministic {
+            paths: doc
+                .modules()
+                .map(|m| Path::from(Ident::new(m.name.as_str(), Span::call_site())))
+                .collect(),
+        }
+    }
+
+    /// Check that each module and function named is in `doc`.
+    pub fn validate(&self, doc: &witx::Document) -> Result<()> {
+        validate_paths(self.paths.iter(), doc)
+    }
+
+    /// Returns `true` if `f` of module `m` is deterministic.
+    pub fn contains(&self, m: &witx::Module, f: &witx::InterfaceFunc) -> bool {
+        self.paths.iter().any(|path| path_names(path, m, f))
+    }
+}
+
+impl Parse for Deterministic {
+    fn parse(input: ParseStream) -> Result<Self> {
+        Ok(Deterministic {
+            paths: parse_paths(input)?,
+        })
+    }
+}
+
+/// Parse `{ module::func, module, .. }`.
+fn parse_paths(input: ParseStream) -> Result<Vec<Path>> {
+    let contents;
+    let _lbrace = braced!(contents in input);
+    let paths: Punctuated<Path, Token![,]> = contents.parse_terminated(Path::parse_mod_style)?;
+    for path in paths.iter() {
+        if path.leading_colon.is_some() || path.segments.len() > 2 {
+            return Err(Error::new_spanned(
+                path,
+                "expected `module` or `module::func`",
+            ));
+        }
+    }
+    Ok(paths.into_iter().collect())
+}
+
+/// Check that each of `paths` names a module or function in `doc`.
+fn validate_paths<'a>(paths: impl Iterator<Item = &'a Path>, doc: &witx::Document) -> Result<()> {
+    for path in paths {
+        let segments: Vec<String> = path.segments.iter().map(|s| s.ident.to_string()).collect();
+        let m = doc.module(&witx::Id::new(&segments[0]));
+        let found = match (m, segments.len()) {
+            (Some(_), 1) => true,
+            (Some(m), 2) => m.func(&witx::Id::new(&segments[1])).is_some(),
+            _ => false,
+        };
+        if !found {
+            return Err(Error::new_spanned(
+                path,
+                "no such module or function in the witx document",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` if `path` names `f` of module `m`, or all of `m`.
+fn path_names(path: &Path, m: &witx::Module, f: &witx::InterfaceFunc) -> bool {
+    let mut segments = path.segments.iter().map(|s| s.ident.to_string());
+    segments.next().as_deref() == Some(m.name.as_str())
+        && segments.next().map_or(true, |func| func == f.name.as_str())
+}
@@ -1,10 +1,13 @@
+mod c_header;
 pub mod config;
-pub use config::Config;
+pub use c_header::c_header;
+pub use config::{Config, Deterministic, Features};
+pub use lucet_module::bindings::Bindings;
 
 use heck::SnakeCase;
-use lucet_module::bindings::Bindings;
 use proc_macro2::{Ident, TokenStream};
 use quote::{format_ident, quote};
+use syn::parse::Parser;
 
 pub fn hostcall_name(m: &witx::Module, f: &witx::InterfaceFunc) -> String {
     format!(
@@ -14,12 +17,18 @@ pub fn hostcall_name(m: &witx::Module, f: &witx::InterfaceFunc) -> String {
     )
 }
 pub fn bindings(doc: &witx::Document) -> Bindings {
+    bindings_without(doc, &[])
+}
+
+/// The bindings of `doc`, without the `(module, function)` pairs in `disabled`.
+pub fn bindings_without(doc: &witx::Document, disabled: &[(&str, &str)]) -> Bindings {
     let bs = doc
         .modules()
         .map(|m| {
             (
                 m.name.as_str().to_owned(),
                 m.funcs()
+                    .filter(|f| !disabled.contains(&(m.name.as_str(), f.name.as_str())))
                     .map(|f| (f.name.as_str().to_owned(), hostcall_name(&m, &f)))
                     .collect(),
             )
@@ -35,11 +44,19 @@ pub fn generate(
     wiggle_mod_path: &TokenStream,
     pre_hook: &TokenStream,
     post_hook: &TokenStream,
+    features: &Features,
+    deterministic: &Deterministic,
 ) -> TokenStream {
     let names = wiggle_generate::Names::new(ctx_type, quote!(lucet_wiggle));
     let fs = doc.modules().map(|m| {
         let fs = m.funcs().map(|f| {
             let name = format_ident!("{}", hostcall_name(&m, &f));
+            let cfg = features.cfg(&m, &f);
+            let hostcall_attr = if deterministic.contains(&m, &f) {
+                quote!(#[lucet_hostcall(deterministic)])
+            } else {
+                quote!(#[lucet_hostcall])
+            };
             let coretype = f.core_type();
             let func_args = coretype.args.iter().map(|a| {
                 let name = names.func_core_arg(a);
@@ -50,6 +67,8 @@ pub fn generate(
                 let name = names.func_core_arg(a);
                 quote!(#name)
             });
+            let func_name = f.name.as_str();
+            let trace_args = trace_args(&names, &coretype);
             let rets = coretype
                 .ret
                 .as_ref()
@@ -60,14 +79,31 @@ pub fn generate(
                 .unwrap_or(quote!(()));
             let mod_name = names.module(&m.name);
             let method_name = names.func(&f.name);
+            let (span, record) = if cfg!(feature = "tracing") {
+                span(m.name.as_str(), func_name, coretype.ret.is_some())
+            } else {
+                (quote!(), quote!())
+            };
             quote! {
-                #[lucet_hostcall]
+                #cfg
+                #hostcall_attr
                 #[no_mangle]
                 pub fn #name(vmctx: &lucet_runtime::vmctx::Vmctx, #(#func_args),*) -> #rets {
-                    { #pre_hook }
                     let memory = lucet_wiggle::runtime::LucetMemory::new(vmctx);
+                    // the hooks can refer to the name of the function, and decode its arguments
+                    // with `hostcall_args()`, which reads the out-values in the guest's memory
+                    // as they are when it is called
+                    #[allow(unused_variables)]
+                    let hostcall_name: &'static str = #func_name;
+                    #[allow(unused_variables)]
+                    let hostcall_args = || -> Vec<(&'static str, lucet_wiggle::runtime::TraceArg)> {
+                        vec![#(#trace_args),*]
+                    };
+                    #span
+                    { #pre_hook }
                     let mut ctx: #ctx_type = #ctx_constructor;
                     let r = super::#mod_name::#method_name(&ctx, &memory, #(#call_args),*);
+                    #record
                     { #post_hook }
                     r
                 }
@@ -79,9 +115,29 @@ pub fn generate(
     let init = doc.modules().map(|m| {
         let fs = m.funcs().map(|f| {
             let name = format_ident!("{}", hostcall_name(&m, &f));
-            quote!(#name as _)
+            let cfg = features.cfg(&m, &f);
+            quote! {
+                #cfg
+                funcs.push(#name as _);
+            }
         });
-        quote!(#(#fs),*)
+        quote!(#(#fs)*)
+    });
+
+    let disabled = doc.modules().map(|m| {
+        let fs = m.funcs().filter_map(|f| {
+            let features = features.of(&m, &f);
+            if features.is_empty() {
+                return None;
+            }
+            let mod_name = m.name.as_str();
+            let func_name = f.name.as_str();
+            Some(quote! {
+                #[cfg(not(all(#(feature = #features),*)))]
+                disabled.push((#mod_name, #func_name));
+            })
+        });
+        quote!(#(#fs)*)
     });
 
     quote! {
@@ -95,13 +151,507 @@ pub fn generate(
             /// each hostcall is reachable and not garbage-collected by the
             /// compile-time linker (ld).
             pub fn init() {
-                let funcs: &[*const extern "C" fn()] = &[
-                    #(#init),*
-                ];
+                let mut funcs: Vec<*const extern "C" fn()> = vec![];
+                #(#init)*
                 for func in funcs {
-                    assert_ne!(*func, std::ptr::null(), "hostcall address is not null");
+                    assert_ne!(func, std::ptr::null(), "hostcall address is not null");
                 }
             }
+            /// The bindings of the hostcalls this build has, which leave out the functions
+            /// whose features are not enabled.
+            pub fn bindings() -> lucet_wiggle::generate::Bindings {
+                #[allow(unused_mut)]
+                let mut disabled: Vec<(&str, &str)> = vec![];
+                #(#disabled)*
+                lucet_wiggle::generate::bindings_without(&super::metadata::document(), &disabled)
+            }
         }
     }
 }
+
+/// The statements that enter a `tracing` span for the hostcall of `func_name` in `module_name`,
+/// and that record the arguments of the call and its result in the span once it returns.
+///
+/// The arguments are recorded after the call, so the values it stores through its result
+/// pointers are decoded, and only if the span is enabled, as decoding reads the guest's memory.
+fn span(module_name: &str, func_name: &str, returns: bool) -> (TokenStream, TokenStream) {
+    let span = quote! {
+        let span = lucet_wiggle::tracing::span!(
+            lucet_wiggle::tracing::Level::TRACE,
+            #func_name,
+            module = #module_name,
+            args = lucet_wiggle::tracing::field::Empty,
+            result = lucet_wiggle::tracing::field::Empty,
+        );
+        let _enter = span.enter();
+    };
+    let result = if returns {
+        quote!(span.record("result", &r);)
+    } else {
+        quote!()
+    };
+    let record = quote! {
+        if !span.is_disabled() {
+            span.record("args", &lucet_wiggle::tracing::field::debug(hostcall_args()));
+            #result
+        }
+    };
+    (span, record)
+}
+
+/// The arguments of the hostcall of `coretype`, as `(name, TraceArg)` pairs decoded from the
+/// guest's memory by their witx types.
+///
+/// A string or an array of `iovec`s, passed as a pointer and a length, is one argument, named
+/// after the pointer. A pointer to a result of an integer type, such as a handle or a size, is
+/// shown as the value stored through it.
+fn trace_args(names: &wiggle_generate::Names, coretype: &witx::CoreFuncType) -> Vec<TokenStream> {
+    let decoded_len = |a: &witx::CoreParamType| {
+        matches!(a.param.position, witx::InterfaceFuncParamPosition::Param(_))
+            && pair_kind(&a.param.tref).is_some()
+    };
+    coretype
+        .args
+        .iter()
+        .filter_map(|a| {
+            let name = names.func_core_arg(a);
+            let arg_name = name.to_string().trim_start_matches("r#").to_owned();
+            // pointers and lengths are unsigned, so they should not come out negative
+            let value = match a.repr() {
+                witx::AtomType::I32 => quote!(#name as u32 as i64),
+                _ => quote!(#name as i64),
+            };
+            let ptr = quote!(lucet_wiggle::runtime::TraceArg::Ptr(#name as u32));
+            let arg = match a.signifies {
+                witx::CoreParamSignifies::LengthOf { .. } if decoded_len(a) => return None,
+                witx::CoreParamSignifies::LengthOf { .. } => {
+                    quote!(lucet_wiggle::runtime::TraceArg::Value(#value))
+                }
+                witx::CoreParamSignifies::Value { .. } => match &*a.param.tref.type_() {
+                    witx::Type::Pointer(_) | witx::Type::ConstPointer(_) => ptr,
+                    _ => quote!(lucet_wiggle::runtime::TraceArg::Value(#value)),
+                },
+                witx::CoreParamSignifies::PointerTo { .. } => match a.param.position {
+                    witx::InterfaceFuncParamPosition::Result(_) => match out_size(&a.param.tref) {
+                        Some(size) => quote! {
+                            lucet_wiggle::runtime::TraceArg::out(&memory, #name as u32, #size)
+                        },
+                        None => ptr,
+                    },
+                    witx::InterfaceFuncParamPosition::Param(_) => {
+                        let len = coretype
+                            .args
+                            .iter()
+                            .find(|b| {
+                                matches!(b.signifies, witx::CoreParamSignifies::LengthOf { .. })
+                                    && b.param.name == a.param.name
+                            })
+                            .map(|b| names.func_core_arg(b));
+                        match (pair_kind(&a.param.tref), len) {
+                            (Some(decode), Some(len)) => quote! {
+                                lucet_wiggle::runtime::TraceArg::#decode(
+                                    &memory,
+                                    #name as u32,
+                                    #len as u32,
+                                )
+                            },
+                            _ => ptr,
+                        }
+                    }
+                },
+            };
+            Some(quote!((#arg_name, #arg)))
+        })
+        .collect()
+}
+
+/// The `TraceArg` constructor that decodes a pointer and length pair of type `tref`: `str` for a
+/// string, `bufs` for an array of buffers, each a pointer and a `buf_len`, or `None` for any other
+/// array, which is shown as its pointer and its length.
+fn pair_kind(tref: &witx::TypeRef) -> Option<Ident> {
+    match &*tref.type_() {
+        witx::Type::Builtin(witx::BuiltinType::String) => Some(format_ident!("str")),
+        witx::Type::Array(elem) => match &*elem.type_() {
+            witx::Type::Struct(s)
+                if s.members.len() == 2
+                    && s.members[0].name.as_str() == "buf"
+                    && s.members[1].name.as_str() == "buf_len" =>
+            {
+                Some(format_ident!("bufs"))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The size in bytes of a result of type `tref` that is shown as its value: an unsigned integer,
+/// or a type represented by one, such as a handle, an enum, or flags.
+fn out_size(tref: &witx::TypeRef) -> Option<u32> {
+    let repr = |repr: witx::IntRepr| match repr {
+        witx::IntRepr::U8 => 1,
+        witx::IntRepr::U16 => 2,
+        witx::IntRepr::U32 => 4,
+        witx::IntRepr::U64 => 8,
+    };
+    match &*tref.type_() {
+        witx::Type::Builtin(b) => match b {
+            witx::BuiltinType::U8 | witx::BuiltinType::Char8 => Some(1),
+            witx::BuiltinType::U16 => Some(2),
+            witx::BuiltinType::U32 | witx::BuiltinType::USize => Some(4),
+            witx::BuiltinType::U64 => Some(8),
+            _ => None,
+        },
+        witx::Type::Int(i) => Some(repr(i.repr)),
+        witx::Type::Enum(e) => Some(repr(e.repr)),
+        witx::Type::Flags(f) => Some(repr(f.repr)),
+        witx::Type::Handle(_) => Some(4),
+        _ => None,
+    }
+}
+
+/// Put the `cfg` attributes of `features` on the methods of the module traits, and on the
+/// functions that call them, in `tokens`, which `wiggle_generate::generate()` made of `doc`.
+pub fn gate_module_traits(
+    tokens: TokenStream,
+    doc: &witx::Document,
+    ctx_type: &Ident,
+    features: &Features,
+) -> TokenStream {
+    if features.gates.is_empty() {
+        return tokens;
+    }
+    let names = wiggle_generate::Names::new(ctx_type, quote!(lucet_wiggle));
+    let mut file: syn::File = match syn::parse2(tokens) {
+        Ok(file) => file,
+        Err(e) => return e.to_compile_error(),
+    };
+    for item in file.items.iter_mut() {
+        let module = match item {
+            syn::Item::Mod(module) => module,
+            _ => continue,
+        };
+        let m = match doc
+            .modules()
+            .find(|m| names.module(&m.name) == module.ident)
+        {
+            Some(m) => m,
+            None => continue,
+        };
+        let items = match &mut module.content {
+            Some((_, items)) => items,
+            None => continue,
+        };
+        for f in m.funcs() {
+            let attrs = syn::Attribute::parse_outer
+                .parse2(features.cfg(&m, &f))
+                .expect("cfg attribute");
+            if attrs.is_empty() {
+                continue;
+            }
+            let func_name = names.func(&f.name);
+            for item in items.iter_mut() {
+                match item {
+                    syn::Item::Fn(func) if func.sig.ident == func_name => {
+                        func.attrs.extend(attrs.iter().cloned());
+                    }
+                    syn::Item::Trait(t) => {
+                        for trait_item in t.items.iter_mut() {
+                            match trait_item {
+                                syn::TraitItem::Method(method) if method.sig.ident == func_name => {
+                                    method.attrs.extend(attrs.iter().cloned());
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    quote!(#file)
+}
+
+/// Generate, for each module of `doc` named in `async_modules`, a trait whose methods return
+/// futures, named like the module trait with `Async` after it, in a module named like the
+/// module's with `_async` after it. The module trait is implemented for `ctx_type` by running
+/// those futures with `lucet_wiggle::runtime::block_on()`, so `ctx_type` must implement the
+/// async trait and `lucet_wiggle::runtime::AsyncCtx` instead.
+pub fn generate_async(
+    doc: &witx::Document,
+    ctx_type: &Ident,
+    async_modules: &[Ident],
+    errxform: &wiggle_generate::ErrorTransform,
+    features: &Features,
+) -> TokenStream {
+    let names = wiggle_generate::Names::new(ctx_type, quote!(lucet_wiggle));
+    let modules = async_modules.iter().map(|module| {
+        let m = match doc.module(&witx::Id::new(module.to_string())) {
+            Some(m) => m,
+            None => {
+                return syn::Error::new(module.span(), "no such module in the witx document")
+                    .to_compile_error()
+            }
+        };
+        let mod_name = names.module(&m.name);
+        let async_mod_name = format_ident!("{}_async", mod_name);
+        let trait_name = names.trait_name(&m.name);
+        let async_trait_name = format_ident!("{}Async", trait_name);
+        let (async_methods, sync_methods): (Vec<_>, Vec<_>) = m
+            .funcs()
+            .map(|f| {
+                let cfg = features.cfg(&m, &f);
+                let (async_method, sync_method) =
+                    define_async_method(&names, &async_trait_name, &f, errxform);
+                (quote!(#cfg #async_method), quote!(#cfg #sync_method))
+            })
+            .unzip();
+        quote! {
+            pub mod #async_mod_name {
+                use super::#ctx_type;
+                use super::types::*;
+
+                pub trait #async_trait_name {
+                    #(#async_methods)*
+                }
+
+                impl super::#mod_name::#trait_name for #ctx_type {
+                    #(#sync_methods)*
+                }
+            }
+        }
+    });
+    quote!(#(#modules)*)
+}
+
+/// Generate, for each module of `doc` named in `mock_modules`, a trait for fakes of the module,
+/// named like the module trait with `Mock` after it, in a module named like the module's with
+/// `_mock` after it, along with an `Unimplemented` fake. Each method of the trait panics unless
+/// a fake overrides it, and the trait can be made an object of. The module trait is implemented
+/// for `ctx_type` by calling the fake it dereferences to, so `ctx_type` must implement `Deref`
+/// with a target that implements the mock trait, such as `dyn` the mock trait.
+pub fn generate_mocks(
+    doc: &witx::Document,
+    ctx_type: &Ident,
+    mock_modules: &[Ident],
+    errxform: &wiggle_generate::ErrorTransform,
+    features: &Features,
+) -> TokenStream {
+    let names = wiggle_generate::Names::new(ctx_type, quote!(lucet_wiggle));
+    let modules = mock_modules.iter().map(|module| {
+        let m = match doc.module(&witx::Id::new(module.to_string())) {
+            Some(m) => m,
+            None => {
+                return syn::Error::new(module.span(), "no such module in the witx document")
+                    .to_compile_error()
+            }
+        };
+        let mod_name = names.module(&m.name);
+        let mock_mod_name = format_ident!("{}_mock", mod_name);
+        let trait_name = names.trait_name(&m.name);
+        let mock_trait_name = format_ident!("{}Mock", trait_name);
+        let (mock_methods, sync_methods): (Vec<_>, Vec<_>) = m
+            .funcs()
+            .map(|f| {
+                let method = Method::new(&names, &f, errxform);
+                let func_name = method.name();
+                let sig = method.sync_sig();
+                let args = method.args();
+                let unimplemented = format!("{}::{}", m.name.as_str(), f.name.as_str());
+                let cfg = features.cfg(&m, &f);
+                (
+                    quote! {
+                        #cfg
+                        #[allow(unused_variables)]
+                        #sig { unimplemented!(#unimplemented) }
+                    },
+                    quote!(#cfg #sig { #mock_trait_name::#func_name(&**self, #(#args),*) }),
+                )
+            })
+            .unzip();
+        quote! {
+            pub mod #mock_mod_name {
+                use super::#ctx_type;
+                use super::types::*;
+
+                pub trait #mock_trait_name {
+                    #(#mock_methods)*
+                }
+
+                /// A fake that implements none of the functions.
+                pub struct Unimplemented;
+
+                impl #mock_trait_name for Unimplemented {}
+
+                impl super::#mod_name::#trait_name for #ctx_type {
+                    #(#sync_methods)*
+                }
+            }
+        }
+    });
+    quote!(#(#modules)*)
+}
+
+/// The method of the async trait for `f`, and the method of the module trait that blocks on it.
+fn define_async_method(
+    names: &wiggle_generate::Names,
+    async_trait_name: &Ident,
+    f: &witx::InterfaceFunc,
+    errxform: &wiggle_generate::ErrorTransform,
+) -> (TokenStream, TokenStream) {
+    let method = Method::new(names, f, errxform);
+    let func_name = method.name();
+
+    // the future borrows everything the method is passed, for as long as it is pending
+    let async_params = method.params(quote!('a), quote!(&'a));
+    let async_result = method.result(quote!('a));
+    let async_method = quote! {
+        fn #func_name<'a>(&'a self, #(#async_params),*)
+            -> lucet_wiggle::runtime::BoxFuture<'a, #async_result>;
+    };
+
+    let sig = method.sync_sig();
+    let args = method.args();
+    // every pointer of a hostcall refers to the same memory, which the future must not borrow
+    // from when it yields
+    let memory = match method.guest_ptr() {
+        Some(ptr) => quote!(Some(#ptr.mem())),
+        None => quote!(None),
+    };
+    let sync_method = quote! {
+        #sig {
+            lucet_wiggle::runtime::block_on(
+                lucet_wiggle::runtime::AsyncCtx::vmctx(self),
+                #memory,
+                #async_trait_name::#func_name(self, #(#args),*),
+            )
+        }
+    };
+    (async_method, sync_method)
+}
+
+/// The method of a module trait for the function `f`.
+struct Method<'a> {
+    names: &'a wiggle_generate::Names,
+    f: &'a witx::InterfaceFunc,
+    errxform: &'a wiggle_generate::ErrorTransform,
+}
+
+impl<'a> Method<'a> {
+    fn new(
+        names: &'a wiggle_generate::Names,
+        f: &'a witx::InterfaceFunc,
+        errxform: &'a wiggle_generate::ErrorTransform,
+    ) -> Self {
+        Method { names, f, errxform }
+    }
+
+    fn name(&self) -> Ident {
+        self.names.func(&self.f.name)
+    }
+
+    /// The parameters, with their types borrowing for `lifetime`, and those passed by pointer
+    /// behind `reference`.
+    fn params(&self, lifetime: TokenStream, reference: TokenStream) -> Vec<TokenStream> {
+        self.f
+            .params
+            .iter()
+            .map(|param| {
+                let name = self.names.func_param(&param.name);
+                let type_name = self.names.type_ref(&param.tref, lifetime.clone());
+                match param.tref.type_().passed_by() {
+                    witx::TypePassedBy::Value { .. } => quote!(#name: #type_name),
+                    witx::TypePassedBy::Pointer { .. }
+                    | witx::TypePassedBy::PointerLengthPair { .. } => {
+                        quote!(#name: #reference #type_name)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// The name of the first parameter that is a `GuestPtr`, if any: a string, an array, or a
+    /// pointer.
+    fn guest_ptr(&self) -> Option<Ident> {
+        self.f
+            .params
+            .iter()
+            .find(|param| {
+                matches!(
+                    &*param.tref.type_(),
+                    witx::Type::Builtin(witx::BuiltinType::String)
+                        | witx::Type::Array(_)
+                        | witx::Type::Pointer(_)
+                        | witx::Type::ConstPointer(_)
+                )
+            })
+            .map(|param| self.names.func_param(&param.name))
+    }
+
+    /// The names of the parameters, to pass them on.
+    fn args(&self) -> Vec<Ident> {
+        self.f
+            .params
+            .iter()
+            .map(|param| self.names.func_param(&param.name))
+            .collect()
+    }
+
+    fn result(&self, lifetime: TokenStream) -> TokenStream {
+        let rets = self
+            .f
+            .results
+            .iter()
+            .skip(1)
+            .map(|ret| self.names.type_ref(&ret.tref, lifetime.clone()));
+        let err = self
+            .f
+            .results
+            .get(0)
+            .map(|err| match self.errxform.for_abi_error(&err.tref) {
+                Some(custom_err) => {
+                    let type_name = custom_err.typename();
+                    quote!(super::#type_name)
+                }
+                None => self.names.type_ref(&err.tref, lifetime.clone()),
+            })
+            .unwrap_or(quote!(()));
+        quote!(Result<(#(#rets),*), #err>)
+    }
+
+    /// The signature the module trait declares the method with.
+    fn sync_sig(&self) -> TokenStream {
+        // the module trait only declares a lifetime for the functions whose types need one
+        let needs_lifetime = self
+            .f
+            .params
+            .iter()
+            .chain(self.f.results.iter())
+            .any(|p| needs_lifetime(&p.tref));
+        let (lifetime, generics) = if needs_lifetime {
+            (quote!('a), quote!(<'a>))
+        } else {
+            (quote!('_), quote!())
+        };
+        let name = self.name();
+        let params = self.params(lifetime.clone(), quote!(&));
+        let result = self.result(lifetime);
+        quote!(fn #name #generics(&self, #(#params),*) -> #result)
+    }
+}
+
+/// Whether the Rust type of `tref` borrows the guest's memory, as wiggle decides when it declares
+/// the methods of a module trait.
+fn needs_lifetime(tref: &witx::TypeRef) -> bool {
+    match &*tref.type_() {
+        witx::Type::Builtin(b) => matches!(b, witx::BuiltinType::String),
+        witx::Type::Struct(s) => s.members.iter().any(|m| needs_lifetime(&m.tref)),
+        witx::Type::Union(u) => u
+            .variants
+            .iter()
+            .any(|v| v.tref.as_ref().map_or(false, needs_lifetime)),
+        witx::Type::Pointer(_) | witx::Type::ConstPointer(_) | witx::Type::Array(_) => true,
+        _ => false,
+    }
+}
@@ -3,6 +3,32 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::parse_macro_input;
 
+/// Generate the types, module traits, and hostcalls of a witx document.
+///
+/// It takes the fields of wiggle's `from_witx!`, along with:
+///
+/// - `constructor: { .. }`, the expression that builds the `ctx` type each time a hostcall is
+///   called, in which `vmctx: &Vmctx` is in scope;
+/// - `pre_hook: { .. }` and `post_hook: { .. }`, which run before and after each hostcall;
+/// - `async: { module, .. }`, the modules whose functions are implemented as futures;
+/// - `mock: { module, .. }`, the modules whose functions are implemented by a fake the `ctx`
+///   type dereferences to, for tests.
+///
+/// With `features: { "feature": { module::func, module, .. }, .. }`, the functions named, or all
+/// the functions of the modules named, are only in the module traits, the traits above, and the
+/// hostcalls when the crate invoking the macro has the feature enabled. Build guests with the
+/// bindings of `hostcalls::bindings()`, which leave out the functions this build lacks, so the
+/// guest cannot import a function the host does not have.
+///
+/// With `deterministic: { module::func, module, .. }`, the hostcalls of the functions named, or of
+/// all the functions of the modules named, are `#[lucet_hostcall(deterministic)]`, and are called
+/// by instances running in deterministic mode; the others terminate those instances. The
+/// implementations of the deterministic functions can call `Vmctx::nondeterministic()` for the
+/// arguments they cannot serve deterministically.
+///
+/// With `errors: { errno => MyError }`, the methods of the module traits return `MyError` in place
+/// of the `errno` type, and the `ctx` type implements `types::UserErrorConversion` to turn each
+/// one into the errno the guest gets.
 #[proc_macro]
 pub fn from_witx(args: TokenStream) -> TokenStream {
     let mut config = parse_macro_input!(args as lucet_wiggle_generate::Config);
@@ -13,9 +39,23 @@ pub fn from_witx(args: TokenStream) -> TokenStream {
     let doc = config.wiggle.load_document();
 
     let names = wiggle_generate::Names::new(&config.wiggle.ctx.name, quote!(lucet_wiggle));
-    let error_transform = wiggle_generate::ErrorTransform::new(&config.wiggle.errors, &doc)
-        .expect("validating error transform");
-    let mut ts = wiggle_generate::generate(&doc, &names, &error_transform);
+    // an `errors` field naming a type the document lacks is reported where it was written
+    let error_transform = match wiggle_generate::ErrorTransform::new(&config.wiggle.errors, &doc) {
+        Ok(error_transform) => error_transform,
+        Err(e) => return TokenStream::from(e.to_compile_error()),
+    };
+    if let Err(e) = config.features.validate(&doc) {
+        return TokenStream::from(e.to_compile_error());
+    }
+    if let Err(e) = config.deterministic.validate(&doc) {
+        return TokenStream::from(e.to_compile_error());
+    }
+    let mut ts = lucet_wiggle_generate::gate_module_traits(
+        wiggle_generate::generate(&doc, &names, &error_transform),
+        &doc,
+        &config.wiggle.ctx.name,
+        &config.features,
+    );
     ts.extend(wiggle_generate::generate_metadata(&doc, &names));
     ts.extend(lucet_wiggle_generate::generate(
         &doc,
@@ -24,6 +64,22 @@ pub fn from_witx(args: TokenStream) -> TokenStream {
         &quote!(super),
         &config.pre_hook.unwrap_or(quote!()),
         &config.post_hook.unwrap_or(quote!()),
+        &config.features,
+        &config.deterministic,
+    ));
+    ts.extend(lucet_wiggle_generate::generate_async(
+        &doc,
+        &config.wiggle.ctx.name,
+        &config.async_modules,
+        &error_transform,
+        &config.features,
+    ));
+    ts.extend(lucet_wiggle_generate::generate_mocks(
+        &doc,
+        &config.wiggle.ctx.name,
+        &config.mock_modules,
+        &error_transform,
+        &config.features,
     ));
     TokenStream::from(ts)
 }
@@ -0,0 +1,54 @@
+//! Guest buffers borrowed in place, for vectored reads and writes that do not copy them.
+//!
+//! `lucet-wasi` uses these for sockets. Its reads and writes of host files go through
+//! `wasi-common`, which borrows the guest's buffers in place on its own, while those of a
+//! `VirtualFs` or a captured output copy between the guest and the host's memory by design.
+
+use std::io::{IoSlice, IoSliceMut};
+use wiggle::{GuestError, GuestPtr, GuestSlice};
+
+/// The guest's buffers for a vectored read or write, borrowed from its memory all at once.
+///
+/// Each buffer is borrowed through the `BorrowChecker` of the memory. Buffers that overlap each
+/// other, or overlap a buffer the hostcall has already borrowed, are refused with
+/// `GuestError::PtrBorrowed` rather than aliased. A hostcall that must handle overlapping buffers
+/// can fall back to copying them. The buffers stay borrowed until the `GuestBufs` is dropped.
+pub struct GuestBufs<'a> {
+    slices: Vec<GuestSlice<'a, u8>>,
+}
+
+impl<'a> GuestBufs<'a> {
+    /// Borrow each of `bufs`, in order.
+    pub fn borrow(bufs: impl IntoIterator<Item = GuestPtr<'a, [u8]>>) -> Result<Self, GuestError> {
+        let slices = bufs
+            .into_iter()
+            .map(|buf| buf.as_slice())
+            .collect::<Result<_, _>>()?;
+        Ok(GuestBufs { slices })
+    }
+
+    /// The total length of the buffers.
+    pub fn len(&self) -> usize {
+        self.slices.iter().map(|slice| slice.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The buffers to write from, such as with `Write::write_vectored()`.
+    pub fn io_slices(&self) -> Vec<IoSlice<'_>> {
+        self.slices
+            .iter()
+            .map(|slice| IoSlice::new(slice))
+            .collect()
+    }
+
+    /// The buffers to read into, such as with `Read::read_vectored()`.
+    pub fn io_slices_mut(&mut self) -> Vec<IoSliceMut<'_>> {
+        self.slices
+            .iter_mut()
+            .map(|slice| IoSliceMut::new(slice))
+            .collect()
+    }
+}
@@ -5,11 +5,20 @@ pub use wiggle::{
     GuestStr, GuestType, GuestTypeTransparent, Pointee,
 };
 
+mod bufs;
+mod pending;
+mod shared;
+mod trace;
+
 pub mod generate {
     pub use lucet_wiggle_generate::*;
 }
 
 pub mod runtime {
+    pub use crate::bufs::GuestBufs;
+    pub use crate::pending::{block_on, AsyncCtx, BoxFuture, Pending};
+    pub use crate::shared::{copy_from_guest, copy_str_from_guest, copy_to_guest};
+    pub use crate::trace::TraceArg;
     use lucet_runtime::vmctx::Vmctx;
     use wiggle::{BorrowChecker, GuestMemory};
 
@@ -0,0 +1,147 @@
+//! Hostcalls implemented as futures, which yield their instance while they are pending.
+
+use lucet_runtime::vmctx::Vmctx;
+use std::future::Future;
+use std::mem::ManuallyDrop;
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex};
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use wiggle::GuestMemory;
+
+/// The future an async hostcall method returns, which borrows its arguments.
+///
+/// The embedder can change the guest's memory while the instance is yielded, so the future must
+/// not hold a `GuestSlice` or `GuestStr` of it across an `.await` that is pending; copy out of the
+/// memory instead. A hostcall whose future tries to yield with such a borrow outstanding panics.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// The context of hostcalls implemented as futures, which has to know the instance to yield
+/// while they are pending.
+pub trait AsyncCtx {
+    fn vmctx(&self) -> &Vmctx;
+}
+
+/// A hostcall whose future is pending, which its instance yields until the future is woken.
+///
+/// The embedder resumes the instance with `resume()` once the wait is over, when the future is
+/// polled again; resuming it earlier only makes it yield again. An embedder with an executor can
+/// `.await` it; one without can `wait()` on it instead.
+#[derive(Clone)]
+pub struct Pending {
+    signal: Arc<Signal>,
+}
+
+impl Pending {
+    /// Whether the future has been woken, so that the instance can be resumed.
+    pub fn is_woken(&self) -> bool {
+        self.signal.state.lock().unwrap().woken
+    }
+
+    /// Block the thread until the future is woken.
+    pub fn wait(&self) {
+        let mut state = self.signal.state.lock().unwrap();
+        while !state.woken {
+            state = self.signal.cond.wait(state).unwrap();
+        }
+    }
+}
+
+impl Future for Pending {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let mut state = self.signal.state.lock().unwrap();
+        if state.woken {
+            Poll::Ready(())
+        } else {
+            state.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl std::fmt::Debug for Pending {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Pending")
+            .field("woken", &self.is_woken())
+            .finish()
+    }
+}
+
+/// What a hostcall future is woken through.
+#[derive(Default)]
+struct Signal {
+    state: Mutex<State>,
+    cond: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    woken: bool,
+    /// The waker of whoever `.await`s the `Pending` of the instance.
+    waker: Option<Waker>,
+}
+
+impl Signal {
+    fn wake(&self) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            state.woken = true;
+            state.waker.take()
+        };
+        self.cond.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, wake, wake_by_ref, drop);
+
+fn raw_waker(signal: Arc<Signal>) -> RawWaker {
+    RawWaker::new(Arc::into_raw(signal) as *const (), &VTABLE)
+}
+
+unsafe fn clone(ptr: *const ()) -> RawWaker {
+    let signal = ManuallyDrop::new(Arc::from_raw(ptr as *const Signal));
+    raw_waker(Arc::clone(&signal))
+}
+
+unsafe fn wake(ptr: *const ()) {
+    Arc::from_raw(ptr as *const Signal).wake();
+}
+
+unsafe fn wake_by_ref(ptr: *const ()) {
+    ManuallyDrop::new(Arc::from_raw(ptr as *const Signal)).wake();
+}
+
+unsafe fn drop(ptr: *const ()) {
+    std::mem::drop(Arc::from_raw(ptr as *const Signal));
+}
+
+/// Run the future `f` of a hostcall to completion, yielding a [`Pending`](struct.Pending.html)
+/// from the instance each time it cannot make progress.
+///
+/// `memory` is the guest memory the hostcall's pointers refer to, if it has any. The instance is
+/// not yielded while the future borrows from it, as the embedder could then change what the
+/// borrow refers to; this panics instead, which terminates the instance.
+pub fn block_on<F: Future>(vmctx: &Vmctx, memory: Option<&dyn GuestMemory>, f: F) -> F::Output {
+    let mut f = Box::pin(f);
+    loop {
+        let signal = Arc::new(Signal::default());
+        // the waker owns a reference to the signal, which `raw_waker()` hands over to it
+        let waker = unsafe { Waker::from_raw(raw_waker(Arc::clone(&signal))) };
+        if let Poll::Ready(output) = f.as_mut().poll(&mut Context::from_waker(&waker)) {
+            return output;
+        }
+        if memory.map_or(false, |memory| {
+            memory.borrow_checker().has_outstanding_borrows()
+        }) {
+            panic!("a hostcall future cannot yield while it borrows the guest's memory");
+        }
+        let pending = Pending { signal };
+        while !pending.is_woken() {
+            vmctx.yield_val(pending.clone());
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! Copies in and out of guest memory that other threads may be changing at the same time.
+//!
+//! A `GuestSlice` or `GuestStr` borrows the guest's memory as a Rust slice, which is only sound
+//! while nothing else can change that memory. A memory shared between the threads of a guest
+//! can change at any time, so these functions copy instead: they check the bounds against the
+//! size the memory has at the time of the copy, and move each byte with an atomic access, which
+//! a concurrent write cannot turn into undefined behavior. A memory can only grow, so a copy in
+//! bounds when it starts stays in bounds.
+//!
+//! These are for the accesses a hostcall implementation makes itself. The glue `from_witx!`
+//! generates still reads each hostcall's arguments with wiggle's accessors, which read and borrow
+//! the memory in place, so a hostcall is only fully sound on a shared memory once wiggle's
+//! accessors copy the same way these do.
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU8, Ordering};
+use wiggle::{GuestError, GuestMemory, Region};
+
+/// The guest's bytes from `ptr` to `ptr + len`, as a pointer to the first of them.
+fn bytes(memory: &dyn GuestMemory, ptr: u32, len: u32) -> Result<*const AtomicU8, GuestError> {
+    let (base, size) = memory.base();
+    let end = ptr.checked_add(len).ok_or(GuestError::PtrOverflow)?;
+    if end > size {
+        return Err(GuestError::PtrOutOfBounds(Region::new(ptr, len)));
+    }
+    // an `AtomicU8` has the same layout as the `u8` it replaces
+    Ok(unsafe { base.add(ptr as usize) } as *const AtomicU8)
+}
+
+/// Copy `len` bytes out of the guest's memory at `ptr`.
+pub fn copy_from_guest(
+    memory: &dyn GuestMemory,
+    ptr: u32,
+    len: u32,
+) -> Result<Vec<u8>, GuestError> {
+    let src = bytes(memory, ptr, len)?;
+    Ok((0..len as usize)
+        .map(|i| unsafe { (*src.add(i)).load(Ordering::Relaxed) })
+        .collect())
+}
+
+/// Copy the string of `len` bytes at `ptr` out of the guest's memory.
+///
+/// The bytes are checked to be UTF-8 once they are copied, so a concurrent write cannot make them
+/// invalid after the check.
+pub fn copy_str_from_guest(
+    memory: &dyn GuestMemory,
+    ptr: u32,
+    len: u32,
+) -> Result<String, GuestError> {
+    let bytes = copy_from_guest(memory, ptr, len)?;
+    String::from_utf8(bytes).map_err(|e| GuestError::InvalidUtf8(e.utf8_error()))
+}
+
+/// Copy `data` into the guest's memory at `ptr`.
+pub fn copy_to_guest(memory: &dyn GuestMemory, ptr: u32, data: &[u8]) -> Result<(), GuestError> {
+    let len = u32::try_from(data.len()).map_err(|_| GuestError::PtrOverflow)?;
+    let dst = bytes(memory, ptr, len)?;
+    for (i, byte) in data.iter().enumerate() {
+        unsafe { (*dst.add(i)).store(*byte, Ordering::Relaxed) };
+    }
+    Ok(())
+}
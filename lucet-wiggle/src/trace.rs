@@ -0,0 +1,81 @@
+//! The arguments of a hostcall, decoded by their witx types for traces.
+//!
+//! The generated hostcalls hand their hooks `hostcall_args`, a closure that decodes each argument
+//! from the guest's memory when it is called: strings such as paths are read out, arrays of
+//! buffers are shown as the length of each buffer, and the values a hostcall stores through its
+//! result pointers, such as the `nwritten` of `fd_write()` or the `opened_fd` of `path_open()`,
+//! are read back. The memory is copied rather than borrowed, as `copy_from_guest()` does, so
+//! decoding works for shared memories too.
+
+use crate::shared::copy_from_guest;
+use std::fmt;
+use wiggle::GuestMemory;
+
+/// An argument of a hostcall, as its witx type says to show it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TraceArg {
+    /// A value passed as it is, such as a file descriptor, flags, or a length.
+    Value(i64),
+    /// A pointer to a structure, or to an array that is not of buffers, as an address in the
+    /// guest's memory.
+    Ptr(u32),
+    /// A string passed as a pointer and a length, or `None` if it is out of bounds or not UTF-8.
+    Str(Option<String>),
+    /// The lengths of the buffers of an array of `iovec`s or `ciovec`s passed as a pointer and a
+    /// length, or `None` if the array is out of bounds.
+    Bufs(Option<Vec<u32>>),
+    /// The value the hostcall stored through a result pointer, read once it returned, or `None`
+    /// if the pointer is out of bounds.
+    Out(Option<u64>),
+}
+
+impl TraceArg {
+    /// The string of `len` bytes at `ptr`.
+    pub fn str(memory: &dyn GuestMemory, ptr: u32, len: u32) -> Self {
+        TraceArg::Str(
+            copy_from_guest(memory, ptr, len)
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok()),
+        )
+    }
+
+    /// The lengths of the `len` buffers at `ptr`, each a 32-bit pointer followed by a 32-bit
+    /// length.
+    pub fn bufs(memory: &dyn GuestMemory, ptr: u32, len: u32) -> Self {
+        TraceArg::Bufs(
+            len.checked_mul(8)
+                .and_then(|size| copy_from_guest(memory, ptr, size).ok())
+                .map(|bytes| {
+                    bytes
+                        .chunks_exact(8)
+                        .map(|buf| u32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]))
+                        .collect()
+                }),
+        )
+    }
+
+    /// The little-endian unsigned integer of `size` bytes, at most 8, at `ptr`.
+    pub fn out(memory: &dyn GuestMemory, ptr: u32, size: u32) -> Self {
+        TraceArg::Out(copy_from_guest(memory, ptr, size).ok().map(|bytes| {
+            bytes
+                .iter()
+                .rev()
+                .fold(0, |value, byte| (value << 8) | u64::from(*byte))
+        }))
+    }
+}
+
+impl fmt::Display for TraceArg {
+    /// Values and out-values as numbers, pointers in hex, strings quoted, buffers as a list of
+    /// their lengths, and whatever could not be read as `?`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceArg::Value(value) => write!(f, "{}", value),
+            TraceArg::Ptr(ptr) => write!(f, "{:#x}", ptr),
+            TraceArg::Str(Some(s)) => write!(f, "{:?}", s),
+            TraceArg::Bufs(Some(lens)) => write!(f, "{:?}", lens),
+            TraceArg::Out(Some(value)) => write!(f, "{}", value),
+            TraceArg::Str(None) | TraceArg::Bufs(None) | TraceArg::Out(None) => write!(f, "?"),
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use lucet_runtime::vmctx::Vmctx;
+use lucet_runtime::{DlModule, InstanceHandle, Limits, MmapRegion, Region};
+use lucet_wiggle::runtime::{AsyncCtx, BoxFuture, Pending};
+use lucet_wiggle::{GuestError, GuestErrorType, GuestPtr};
+use lucetc::{Lucetc, LucetcOpts};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::task::{Context, Poll, Waker};
+use tempfile::TempDir;
+
+pub struct Ctx<'a> {
+    vmctx: &'a Vmctx,
+}
+
+impl<'a> AsyncCtx for Ctx<'a> {
+    fn vmctx(&self) -> &Vmctx {
+        self.vmctx
+    }
+}
+
+/// Embedding ctx object, through which the test hands the hostcall the factor it waits for.
+pub struct TestCtx {
+    factor: Option<u32>,
+    waker: Option<Waker>,
+}
+
+// The functions of `calc` are implemented by the `CalcAsync` trait, which the generated code
+// defines in `calc_async`, along with an implementation of `calc::Calc` that blocks on it.
+lucet_wiggle::from_witx!({
+    witx_literal: "
+        (typename $errno (enum u32 $ok $overflow))
+        (module $calc
+          (@interface func (export \"scale\")
+            (param $x u32)
+            (result $error $errno)
+            (result $scaled u32))
+          (@interface func (export \"sum\")
+            (param $bytes (array u8))
+            (result $error $errno)
+            (result $sum u32)))
+    ",
+    ctx: Ctx,
+    constructor: { Ctx { vmctx } },
+    async: { calc },
+});
+
+impl GuestErrorType for types::Errno {
+    fn success() -> types::Errno {
+        types::Errno::Ok
+    }
+}
+
+impl<'a> types::GuestErrorConversion for Ctx<'a> {
+    fn into_errno(&self, e: GuestError) -> types::Errno {
+        panic!("unexpected guest error: {:?}", e)
+    }
+}
+
+/// The factor the host has yet to decide on.
+struct Factor<'a>(&'a Vmctx);
+
+impl<'a> Future for Factor<'a> {
+    type Output = u32;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<u32> {
+        // the embed ctx must not stay borrowed while the instance yields
+        let mut test_ctx = self.0.get_embed_ctx_mut::<TestCtx>();
+        match test_ctx.factor {
+            Some(factor) => Poll::Ready(factor),
+            None => {
+                test_ctx.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<'c> calc_async::CalcAsync for Ctx<'c> {
+    fn scale<'a>(&'a self, x: u32) -> BoxFuture<'a, Result<u32, types::Errno>> {
+        Box::pin(async move {
+            let factor = Factor(self.vmctx).await;
+            x.checked_mul(factor).ok_or(types::Errno::Overflow)
+        })
+    }
+
+    fn sum<'a>(
+        &'a self,
+        bytes: &'a GuestPtr<'a, [u8]>,
+    ) -> BoxFuture<'a, Result<u32, types::Errno>> {
+        Box::pin(async move {
+            // the slice borrows the guest's memory while the future is pending
+            let bytes = bytes.as_slice().map_err(|_| types::Errno::Overflow)?;
+            let factor = Factor(self.vmctx).await;
+            Ok(bytes.iter().map(|b| u32::from(*b)).sum::<u32>() * factor)
+        })
+    }
+}
+
+const GUEST: &str = r#"
+(module
+  (import "calc" "scale" (func $scale (param i32 i32) (result i32)))
+  (import "calc" "sum" (func $sum (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "run") (param $x i32) (result i32)
+    (if (call $scale (local.get $x) (i32.const 0))
+      (then (return (i32.const -1))))
+    (i32.load (i32.const 0)))
+  (func (export "sum") (result i32)
+    (call $sum (i32.const 16) (i32.const 4) (i32.const 0))))
+"#;
+
+fn instance() -> InstanceHandle {
+    crate::hostcalls::init();
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let workdir = TempDir::new().expect("create working directory");
+    let wat_file = workdir.path().join("guest.wat");
+    std::fs::write(&wat_file, GUEST).expect("write guest");
+    let bindings = lucet_wiggle::generate::bindings(&crate::metadata::document());
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wat_file)
+        .with_bindings(bindings)
+        .shared_object_file(so_file.clone())
+        .expect("build so");
+    let module = DlModule::load(so_file).expect("load so");
+    let region = MmapRegion::create(1, &Limits::default()).expect("create region");
+    let mut inst = region.new_instance(module).expect("create instance");
+    inst.insert_embed_ctx(TestCtx {
+        factor: None,
+        waker: None,
+    });
+    inst
+}
+
+#[test]
+fn async_hostcall() {
+    let mut inst = instance();
+
+    // the hostcall yields while its future is pending
+    let yielded = inst
+        .run("run", &[21u32.into()])
+        .expect("run")
+        .unwrap_yielded();
+    let pending = yielded
+        .downcast_ref::<Pending>()
+        .expect("the hostcall yields a Pending")
+        .clone();
+    assert!(!pending.is_woken());
+
+    // resuming too early only yields again
+    let yielded = inst.resume().expect("resume").unwrap_yielded();
+    assert!(yielded.downcast_ref::<Pending>().is_some());
+
+    let waker = {
+        let mut test_ctx = inst
+            .get_embed_ctx_mut::<TestCtx>()
+            .expect("get test ctx")
+            .expect("borrow");
+        test_ctx.factor = Some(2);
+        test_ctx.waker.take().expect("the future is waiting")
+    };
+    waker.wake();
+    assert!(pending.is_woken());
+    pending.wait();
+
+    let res = inst.resume().expect("resume").unwrap_returned();
+    assert_eq!(res.as_u32(), 42);
+}
+
+#[test]
+fn async_hostcall_does_not_yield_while_borrowing() {
+    let mut inst = instance();
+    let payload = match std::panic::catch_unwind(AssertUnwindSafe(|| inst.run("sum", &[]))) {
+        Ok(res) => panic!("the hostcall yields while borrowing: {:?}", res.map(|_| ())),
+        Err(payload) => payload,
+    };
+    assert_eq!(
+        payload.downcast_ref::<&str>(),
+        Some(&"a hostcall future cannot yield while it borrows the guest's memory")
+    );
+}
@@ -0,0 +1,95 @@
+use lucet_runtime::vmctx::Vmctx;
+use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+use lucet_wasi_sdk::{CompileOpts, Link, LinkOpt, LinkOpts};
+use lucet_wiggle::{GuestError, GuestErrorType};
+use lucetc::{Lucetc, LucetcOpts};
+use tempfile::TempDir;
+
+pub struct Ctx<'a> {
+    _vmctx: &'a Vmctx,
+}
+
+lucet_wiggle::from_witx!({
+    witx_literal: "
+        (typename $errno (enum u32 $success $not_found))
+        (typename $point (struct (field $x u8) (field $y u32)))
+        (module $kv
+          (@interface func (export \"get\")
+            (param $key u32)
+            (result $error $errno)
+            (result $value $point)))
+    ",
+    ctx: Ctx,
+    constructor: { Ctx { _vmctx: vmctx } },
+});
+
+impl GuestErrorType for types::Errno {
+    fn success() -> types::Errno {
+        types::Errno::Success
+    }
+}
+
+impl<'a> types::GuestErrorConversion for Ctx<'a> {
+    fn into_errno(&self, e: GuestError) -> types::Errno {
+        panic!("unexpected guest error: {:?}", e)
+    }
+}
+
+impl<'a> kv::Kv for Ctx<'a> {
+    fn get(&self, key: u32) -> Result<types::Point, types::Errno> {
+        match key {
+            1 => Ok(types::Point { x: 3, y: 4 }),
+            _ => Err(types::Errno::NotFound),
+        }
+    }
+}
+
+#[test]
+fn header_declares_witx() {
+    let header = lucet_wiggle::generate::c_header(&crate::metadata::document(), "kv");
+    assert!(header.contains("typedef uint32_t kv_errno_t;"));
+    assert!(header.contains("#define KV_ERRNO_NOT_FOUND ((kv_errno_t)1)"));
+    assert!(header.contains("_Static_assert(sizeof(kv_point_t) == 8, \"witx calculated size\");"));
+    assert!(header.contains(
+        "int32_t __kv_kv_get(int32_t key, int32_t value) \
+         __attribute__((__import_module__(\"kv\"), __import_name__(\"get\")));"
+    ));
+    assert!(header.contains("static inline kv_errno_t kv_get(uint32_t key, kv_point_t * value) {"));
+}
+
+#[test]
+fn guest_calls_through_header() {
+    crate::hostcalls::init();
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    // the guest includes the header, and compiles against nothing else
+    let workdir = TempDir::new().expect("create working directory");
+    let header = lucet_wiggle::generate::c_header(&crate::metadata::document(), "kv");
+    std::fs::write(workdir.path().join("kv.h"), header).expect("write header");
+    let wasm_build = Link::new(&["tests/c_header_guest.c"])
+        .with_include(workdir.path())
+        .with_cflag("-nostartfiles")
+        .with_link_opt(LinkOpt::NoDefaultEntryPoint)
+        .with_link_opt(LinkOpt::AllowUndefinedAll)
+        .with_link_opt(LinkOpt::ExportAll);
+    let wasm_file = workdir.path().join("out.wasm");
+    wasm_build.link(wasm_file.clone()).expect("link wasm");
+
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wasm_file)
+        .with_bindings(crate::hostcalls::bindings())
+        .shared_object_file(so_file.clone())
+        .expect("build so");
+    let module = DlModule::load(so_file).expect("load so");
+    let region = MmapRegion::create(1, &Limits::default()).expect("create region");
+    let mut inst = region.new_instance(module).expect("create instance");
+
+    let mut get_point = |key: u32| {
+        inst.run("get_point", &[key.into()])
+            .expect("run get_point")
+            .unwrap_returned()
+            .as_i32()
+    };
+    assert_eq!(get_point(1), 304);
+    assert_eq!(get_point(2), -1);
+}
@@ -0,0 +1,10 @@
+#include "kv.h"
+
+int get_point(uint32_t key) {
+    kv_point_t point;
+    kv_errno_t err = kv_get(key, &point);
+    if (err != KV_ERRNO_SUCCESS) {
+        return -1;
+    }
+    return (int) point.x * 100 + (int) point.y;
+}
@@ -0,0 +1,103 @@
+use lucet_runtime::vmctx::Vmctx;
+use lucet_runtime::{DlModule, Error, Limits, MmapRegion, Region, TerminationDetails};
+use lucet_wiggle::{GuestError, GuestErrorType};
+use lucetc::{Lucetc, LucetcOpts};
+use tempfile::TempDir;
+
+pub struct Ctx<'a> {
+    _vmctx: &'a Vmctx,
+}
+
+// `math::double` is called in deterministic mode, and `math::now` is not.
+lucet_wiggle::from_witx!({
+    witx_literal: "
+        (typename $errno (enum u32 $success))
+        (module $math
+          (@interface func (export \"double\")
+            (param $x u32)
+            (result $error $errno)
+            (result $doubled u32))
+          (@interface func (export \"now\")
+            (result $error $errno)
+            (result $time u32)))
+    ",
+    ctx: Ctx,
+    constructor: { Ctx { _vmctx: vmctx } },
+    deterministic: { math::double },
+});
+
+impl GuestErrorType for types::Errno {
+    fn success() -> types::Errno {
+        types::Errno::Success
+    }
+}
+
+impl<'a> types::GuestErrorConversion for Ctx<'a> {
+    fn into_errno(&self, e: GuestError) -> types::Errno {
+        panic!("unexpected guest error: {:?}", e)
+    }
+}
+
+impl<'a> math::Math for Ctx<'a> {
+    fn double(&self, x: u32) -> Result<u32, types::Errno> {
+        Ok(x * 2)
+    }
+
+    fn now(&self) -> Result<u32, types::Errno> {
+        Ok(std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as u32)
+    }
+}
+
+const GUEST: &str = r#"
+(module
+  (import "math" "double" (func $double (param i32 i32) (result i32)))
+  (import "math" "now" (func $now (param i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "double") (param $x i32) (result i32)
+    (drop (call $double (local.get $x) (i32.const 0)))
+    (i32.load (i32.const 0)))
+  (func (export "now") (result i32)
+    (drop (call $now (i32.const 0)))
+    (i32.load (i32.const 0))))
+"#;
+
+#[test]
+fn only_deterministic_functions_run_in_deterministic_mode() {
+    crate::hostcalls::init();
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let workdir = TempDir::new().expect("create working directory");
+    let wat_file = workdir.path().join("guest.wat");
+    std::fs::write(&wat_file, GUEST).expect("write guest");
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wat_file)
+        .with_bindings(crate::hostcalls::bindings())
+        .shared_object_file(so_file.clone())
+        .expect("build so");
+    let module = DlModule::load(so_file).expect("load so");
+    let region = MmapRegion::create(1, &Limits::default()).expect("create region");
+    let mut inst = region
+        .new_instance_builder(module)
+        .with_deterministic_execution(true)
+        .build()
+        .expect("create instance");
+
+    let retval = inst
+        .run("double", &[21u32.into()])
+        .expect("run double")
+        .unwrap_returned();
+    assert_eq!(retval.as_i32(), 42);
+
+    match inst.run("now", &[]) {
+        Err(Error::RuntimeTerminated(details)) => {
+            assert_eq!(
+                details,
+                TerminationDetails::Nondeterministic("hostcall_math_now")
+            );
+        }
+        res => panic!("unexpected result: {:?}", res),
+    }
+}
@@ -0,0 +1,102 @@
+use lucet_runtime::vmctx::Vmctx;
+use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+use lucet_wiggle::{GuestError, GuestErrorType};
+use lucetc::{Lucetc, LucetcOpts};
+use std::convert::TryInto;
+use tempfile::TempDir;
+
+pub struct Ctx<'a> {
+    _vmctx: &'a Vmctx,
+}
+
+/// The rich error of the hostcall implementations, which the guest gets as an `errno`.
+#[derive(Debug)]
+pub enum MathError {
+    DivideByZero,
+    Overflow,
+}
+
+// The methods of `math::Math` return `MathError` where the witx says `$errno`.
+lucet_wiggle::from_witx!({
+    witx_literal: "
+        (typename $errno (enum u32 $success $div_by_zero $overflow))
+        (module $math
+          (@interface func (export \"divide\")
+            (param $a s32)
+            (param $b s32)
+            (result $error $errno)
+            (result $quotient s32)))
+    ",
+    ctx: Ctx,
+    constructor: { Ctx { _vmctx: vmctx } },
+    errors: { errno => MathError },
+});
+
+impl GuestErrorType for types::Errno {
+    fn success() -> types::Errno {
+        types::Errno::Success
+    }
+}
+
+impl<'a> types::GuestErrorConversion for Ctx<'a> {
+    fn into_errno(&self, e: GuestError) -> types::Errno {
+        panic!("unexpected guest error: {:?}", e)
+    }
+}
+
+impl<'a> types::UserErrorConversion for Ctx<'a> {
+    fn errno_from_math_error(&self, e: MathError) -> types::Errno {
+        match e {
+            MathError::DivideByZero => types::Errno::DivByZero,
+            MathError::Overflow => types::Errno::Overflow,
+        }
+    }
+}
+
+impl<'a> math::Math for Ctx<'a> {
+    fn divide(&self, a: i32, b: i32) -> Result<i32, MathError> {
+        if b == 0 {
+            return Err(MathError::DivideByZero);
+        }
+        a.checked_div(b).ok_or(MathError::Overflow)
+    }
+}
+
+const GUEST: &str = r#"
+(module
+  (import "math" "divide" (func $divide (param i32 i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "divide") (param $a i32) (param $b i32) (result i32)
+    (call $divide (local.get $a) (local.get $b) (i32.const 0))))
+"#;
+
+#[test]
+fn user_errors() {
+    crate::hostcalls::init();
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let workdir = TempDir::new().expect("create working directory");
+    let wat_file = workdir.path().join("guest.wat");
+    std::fs::write(&wat_file, GUEST).expect("write guest");
+    let bindings = lucet_wiggle::generate::bindings(&crate::metadata::document());
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wat_file)
+        .with_bindings(bindings)
+        .shared_object_file(so_file.clone())
+        .expect("build so");
+    let module = DlModule::load(so_file).expect("load so");
+    let region = MmapRegion::create(1, &Limits::default()).expect("create region");
+    let mut inst = region.new_instance(module).expect("create instance");
+
+    let mut divide = |a: i32, b: i32| {
+        inst.run("divide", &[a.into(), b.into()])
+            .expect("run divide")
+            .unwrap_returned()
+            .as_u32()
+    };
+    assert_eq!(divide(i32::min_value(), -1), types::Errno::Overflow as u32);
+    assert_eq!(divide(1, 0), types::Errno::DivByZero as u32);
+    assert_eq!(divide(-42, 2), types::Errno::Success as u32);
+    let quotient = i32::from_le_bytes(inst.heap()[0..4].try_into().unwrap());
+    assert_eq!(quotient, -21);
+}
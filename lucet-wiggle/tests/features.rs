@@ -0,0 +1,98 @@
+use lucet_runtime::vmctx::Vmctx;
+use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+use lucet_wiggle::{GuestError, GuestErrorType};
+use lucetc::{Lucetc, LucetcOpts};
+use tempfile::TempDir;
+
+pub struct Ctx<'a> {
+    _vmctx: &'a Vmctx,
+}
+
+// lucet-wiggle has no `kv-put` feature, so `kv::put` is left out of this build.
+lucet_wiggle::from_witx!({
+    witx_literal: "
+        (typename $errno (enum u32 $success $not_found))
+        (module $kv
+          (@interface func (export \"get\")
+            (param $key u32)
+            (result $error $errno)
+            (result $value u32))
+          (@interface func (export \"put\")
+            (param $key u32)
+            (param $value u32)
+            (result $error $errno)))
+    ",
+    ctx: Ctx,
+    constructor: { Ctx { _vmctx: vmctx } },
+    features: { "kv-put": { kv::put } },
+});
+
+impl GuestErrorType for types::Errno {
+    fn success() -> types::Errno {
+        types::Errno::Success
+    }
+}
+
+impl<'a> types::GuestErrorConversion for Ctx<'a> {
+    fn into_errno(&self, e: GuestError) -> types::Errno {
+        panic!("unexpected guest error: {:?}", e)
+    }
+}
+
+// only the functions this build has are implemented
+impl<'a> kv::Kv for Ctx<'a> {
+    fn get(&self, key: u32) -> Result<u32, types::Errno> {
+        match key {
+            1 => Ok(100),
+            _ => Err(types::Errno::NotFound),
+        }
+    }
+}
+
+const GUEST: &str = r#"
+(module
+  (import "kv" "get" (func $get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "get") (param $key i32) (result i32)
+    (if (call $get (local.get $key) (i32.const 0))
+      (then (return (i32.const -1))))
+    (i32.load (i32.const 0))))
+"#;
+
+#[test]
+fn bindings_leave_out_disabled_functions() {
+    let bindings = crate::hostcalls::bindings();
+    assert_eq!(bindings.translate("kv", "get").unwrap(), "hostcall_kv_get");
+    assert!(bindings.translate("kv", "put").is_err());
+
+    // the document still has every function
+    let all = lucet_wiggle::generate::bindings(&crate::metadata::document());
+    assert_eq!(all.translate("kv", "put").unwrap(), "hostcall_kv_put");
+}
+
+#[test]
+fn enabled_functions_are_callable() {
+    crate::hostcalls::init();
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let workdir = TempDir::new().expect("create working directory");
+    let wat_file = workdir.path().join("guest.wat");
+    std::fs::write(&wat_file, GUEST).expect("write guest");
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wat_file)
+        .with_bindings(crate::hostcalls::bindings())
+        .shared_object_file(so_file.clone())
+        .expect("build so");
+    let module = DlModule::load(so_file).expect("load so");
+    let region = MmapRegion::create(1, &Limits::default()).expect("create region");
+    let mut inst = region.new_instance(module).expect("create instance");
+
+    let mut get = |key: u32| {
+        inst.run("get", &[key.into()])
+            .expect("run get")
+            .unwrap_returned()
+            .as_i32()
+    };
+    assert_eq!(get(1), 100);
+    assert_eq!(get(2), -1);
+}
@@ -0,0 +1,54 @@
+mod test_helpers;
+
+use crate::test_helpers::HostMemory;
+use lucet_wiggle::runtime::GuestBufs;
+use lucet_wiggle::GuestError;
+use std::io::{Read, Write};
+
+#[test]
+fn reads_and_writes_in_place() {
+    let memory = HostMemory::new(16);
+    let mut bufs = GuestBufs::borrow(vec![memory.buf(8, 3), memory.buf(0, 4)]).unwrap();
+    assert_eq!(bufs.len(), 7);
+    let read = (&b"abcdefgh"[..])
+        .read_vectored(&mut bufs.io_slices_mut())
+        .unwrap();
+    assert_eq!(read, 7);
+
+    let mut out = vec![];
+    out.write_vectored(&bufs.io_slices()).unwrap();
+    assert_eq!(out, b"abcdefg");
+    drop(bufs);
+
+    let all = memory.buf(0, 11).as_slice().unwrap();
+    assert_eq!(&*all, b"defg\0\0\0\0abc");
+}
+
+#[test]
+fn overlapping_bufs_are_refused() {
+    let memory = HostMemory::new(16);
+    assert!(matches!(
+        GuestBufs::borrow(vec![memory.buf(0, 4), memory.buf(2, 4)]),
+        Err(GuestError::PtrBorrowed(_))
+    ));
+
+    // the buffers are borrowed for as long as the `GuestBufs` lives
+    let bufs = GuestBufs::borrow(vec![memory.buf(0, 4)]).unwrap();
+    assert!(matches!(
+        GuestBufs::borrow(vec![memory.buf(3, 1)]),
+        Err(GuestError::PtrBorrowed(_))
+    ));
+    drop(bufs);
+    assert!(GuestBufs::borrow(vec![memory.buf(0, 4), memory.buf(4, 4)]).is_ok());
+}
+
+#[test]
+fn out_of_bounds_bufs_are_refused() {
+    let memory = HostMemory::new(16);
+    assert!(matches!(
+        GuestBufs::borrow(vec![memory.buf(0, 4), memory.buf(14, 4)]),
+        Err(GuestError::PtrOutOfBounds(_))
+    ));
+    // nothing stays borrowed after an error
+    assert!(GuestBufs::borrow(vec![memory.buf(0, 4)]).is_ok());
+}
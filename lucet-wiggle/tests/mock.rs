@@ -0,0 +1,115 @@
+use lucet_runtime::{DlModule, InstanceHandle, Limits, MmapRegion, Region};
+use lucet_wiggle::{GuestError, GuestErrorType};
+use lucetc::{Lucetc, LucetcOpts};
+use std::cell::Ref;
+use std::ops::Deref;
+use tempfile::TempDir;
+
+// The functions of `kv` are implemented by the fake that `FakeCtx` dereferences to, which the
+// test puts in the embed ctx of the instance as a `Box<dyn KvMock>`.
+lucet_wiggle::from_witx!({
+    witx_literal: "
+        (typename $errno (enum u32 $success $not_found))
+        (module $kv
+          (@interface func (export \"get\")
+            (param $key u32)
+            (result $error $errno)
+            (result $value u32))
+          (@interface func (export \"put\")
+            (param $key u32)
+            (param $value u32)
+            (result $error $errno)))
+    ",
+    ctx: FakeCtx,
+    constructor: { FakeCtx { fake: vmctx.get_embed_ctx() } },
+    mock: { kv },
+});
+
+pub struct FakeCtx<'a> {
+    fake: Ref<'a, Box<dyn kv_mock::KvMock>>,
+}
+
+impl<'a> Deref for FakeCtx<'a> {
+    type Target = dyn kv_mock::KvMock;
+
+    fn deref(&self) -> &Self::Target {
+        &**self.fake
+    }
+}
+
+impl GuestErrorType for types::Errno {
+    fn success() -> types::Errno {
+        types::Errno::Success
+    }
+}
+
+impl<'a> types::GuestErrorConversion for FakeCtx<'a> {
+    fn into_errno(&self, e: GuestError) -> types::Errno {
+        panic!("unexpected guest error: {:?}", e)
+    }
+}
+
+/// A fake with one key, and no way to put more.
+struct OneKey;
+
+impl kv_mock::KvMock for OneKey {
+    fn get(&self, key: u32) -> Result<u32, types::Errno> {
+        match key {
+            1 => Ok(100),
+            _ => Err(types::Errno::NotFound),
+        }
+    }
+}
+
+const GUEST: &str = r#"
+(module
+  (import "kv" "get" (func $get (param i32 i32) (result i32)))
+  (import "kv" "put" (func $put (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "get") (param $key i32) (result i32)
+    (if (call $get (local.get $key) (i32.const 0))
+      (then (return (i32.const -1))))
+    (i32.load (i32.const 0)))
+  (func (export "put") (param $key i32) (param $value i32) (result i32)
+    (call $put (local.get $key) (local.get $value))))
+"#;
+
+fn instance(fake: Box<dyn kv_mock::KvMock>) -> InstanceHandle {
+    crate::hostcalls::init();
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let workdir = TempDir::new().expect("create working directory");
+    let wat_file = workdir.path().join("guest.wat");
+    std::fs::write(&wat_file, GUEST).expect("write guest");
+    let bindings = lucet_wiggle::generate::bindings(&crate::metadata::document());
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wat_file)
+        .with_bindings(bindings)
+        .shared_object_file(so_file.clone())
+        .expect("build so");
+    let module = DlModule::load(so_file).expect("load so");
+    let region = MmapRegion::create(1, &Limits::default()).expect("create region");
+    let mut inst = region.new_instance(module).expect("create instance");
+    inst.insert_embed_ctx(fake);
+    inst
+}
+
+#[test]
+fn fake_implements_some_functions() {
+    let mut inst = instance(Box::new(OneKey));
+    let mut get = |key: u32| {
+        inst.run("get", &[key.into()])
+            .expect("run get")
+            .unwrap_returned()
+            .as_i32()
+    };
+    assert_eq!(get(1), 100);
+    assert_eq!(get(2), -1);
+}
+
+#[test]
+#[should_panic(expected = "kv::put")]
+fn unimplemented_functions_panic() {
+    let mut inst = instance(Box::new(kv_mock::Unimplemented));
+    inst.run("put", &[1u32.into(), 2u32.into()]).ok();
+}
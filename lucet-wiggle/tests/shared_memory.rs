@@ -0,0 +1,80 @@
+mod test_helpers;
+
+use crate::test_helpers::HostMemory;
+use lucet_wiggle::runtime::{copy_from_guest, copy_str_from_guest, copy_to_guest, TraceArg};
+use lucet_wiggle::GuestError;
+
+#[test]
+fn copies_round_trip() {
+    let memory = HostMemory::new(16);
+    copy_to_guest(&memory, 4, b"hello").unwrap();
+    assert_eq!(copy_from_guest(&memory, 4, 5).unwrap(), b"hello");
+    assert_eq!(copy_str_from_guest(&memory, 4, 5).unwrap(), "hello");
+    assert_eq!(copy_from_guest(&memory, 3, 3).unwrap(), b"\0he");
+    assert_eq!(copy_from_guest(&memory, 16, 0).unwrap(), b"");
+}
+
+#[test]
+fn copies_stay_in_bounds() {
+    let memory = HostMemory::new(16);
+    assert!(matches!(
+        copy_from_guest(&memory, 12, 5),
+        Err(GuestError::PtrOutOfBounds(_))
+    ));
+    assert!(matches!(
+        copy_to_guest(&memory, 16, b"x"),
+        Err(GuestError::PtrOutOfBounds(_))
+    ));
+    assert!(matches!(
+        copy_from_guest(&memory, u32::max_value(), 2),
+        Err(GuestError::PtrOverflow)
+    ));
+    // nothing is written by a copy that does not fit
+    assert_eq!(copy_from_guest(&memory, 0, 16).unwrap(), vec![0; 16]);
+}
+
+#[test]
+fn strings_are_utf8() {
+    let memory = HostMemory::new(16);
+    copy_to_guest(&memory, 0, &[0xff, 0xfe]).unwrap();
+    assert!(matches!(
+        copy_str_from_guest(&memory, 0, 2),
+        Err(GuestError::InvalidUtf8(_))
+    ));
+}
+
+#[test]
+fn trace_args_decode() {
+    let memory = HostMemory::new(32);
+    copy_to_guest(&memory, 0, b"path").unwrap();
+    // two iovecs, of 5 and 3 bytes
+    copy_to_guest(
+        &memory,
+        8,
+        &[16, 0, 0, 0, 5, 0, 0, 0, 24, 0, 0, 0, 3, 0, 0, 0],
+    )
+    .unwrap();
+    copy_to_guest(&memory, 24, &[0x2a, 0x01, 0, 0]).unwrap();
+
+    assert_eq!(
+        TraceArg::str(&memory, 0, 4),
+        TraceArg::Str(Some("path".to_owned()))
+    );
+    assert_eq!(
+        TraceArg::bufs(&memory, 8, 2),
+        TraceArg::Bufs(Some(vec![5, 3]))
+    );
+    assert_eq!(TraceArg::out(&memory, 24, 4), TraceArg::Out(Some(0x12a)));
+    assert_eq!(TraceArg::out(&memory, 24, 1), TraceArg::Out(Some(0x2a)));
+
+    // what is out of bounds shows as unknown
+    assert_eq!(TraceArg::str(&memory, 30, 4), TraceArg::Str(None));
+    assert_eq!(
+        TraceArg::bufs(&memory, 8, u32::max_value()),
+        TraceArg::Bufs(None)
+    );
+    assert_eq!(TraceArg::out(&memory, 30, 4).to_string(), "?");
+    assert_eq!(TraceArg::bufs(&memory, 8, 2).to_string(), "[5, 3]");
+    assert_eq!(TraceArg::str(&memory, 0, 4).to_string(), "\"path\"");
+    assert_eq!(TraceArg::Ptr(4096).to_string(), "0x1000");
+}
@@ -0,0 +1,35 @@
+use lucet_wiggle::{BorrowChecker, GuestMemory, GuestPtr};
+use std::cell::UnsafeCell;
+
+/// A guest memory of a few bytes in the host's own memory.
+pub struct HostMemory {
+    bytes: UnsafeCell<Vec<u8>>,
+    bc: BorrowChecker,
+}
+
+impl HostMemory {
+    pub fn new(len: usize) -> Self {
+        HostMemory {
+            bytes: UnsafeCell::new(vec![0; len]),
+            // there is only ever one borrow checker for the memory
+            bc: unsafe { BorrowChecker::new() },
+        }
+    }
+
+    // not every test uses buffers
+    #[allow(dead_code)]
+    pub fn buf(&self, ptr: u32, len: u32) -> GuestPtr<'_, [u8]> {
+        GuestPtr::new(self, (ptr, len))
+    }
+}
+
+unsafe impl GuestMemory for HostMemory {
+    fn base(&self) -> (*mut u8, u32) {
+        let bytes = unsafe { &mut *self.bytes.get() };
+        (bytes.as_mut_ptr(), bytes.len() as u32)
+    }
+
+    fn borrow_checker(&self) -> &BorrowChecker {
+        &self.bc
+    }
+}
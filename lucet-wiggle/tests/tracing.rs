@@ -0,0 +1,147 @@
+//! Run with `--features tracing`, so the hostcalls open spans.
+#![cfg(feature = "tracing")]
+
+use lucet_runtime::vmctx::Vmctx;
+use lucet_runtime::{DlModule, Limits, MmapRegion, Region};
+use lucet_wiggle::tracing::field::{Field, Visit};
+use lucet_wiggle::tracing::{span, Event, Metadata, Subscriber};
+use lucet_wiggle::{GuestError, GuestErrorType};
+use lucetc::{Lucetc, LucetcOpts};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+pub struct Ctx<'a> {
+    _vmctx: &'a Vmctx,
+}
+
+lucet_wiggle::from_witx!({
+    witx_literal: "
+        (typename $errno (enum u32 $success $not_found))
+        (module $kv
+          (@interface func (export \"get\")
+            (param $key u32)
+            (result $error $errno)
+            (result $value u32)))
+    ",
+    ctx: Ctx,
+    constructor: { Ctx { _vmctx: vmctx } },
+});
+
+impl GuestErrorType for types::Errno {
+    fn success() -> types::Errno {
+        types::Errno::Success
+    }
+}
+
+impl<'a> types::GuestErrorConversion for Ctx<'a> {
+    fn into_errno(&self, e: GuestError) -> types::Errno {
+        panic!("unexpected guest error: {:?}", e)
+    }
+}
+
+impl<'a> kv::Kv for Ctx<'a> {
+    fn get(&self, _key: u32) -> Result<u32, types::Errno> {
+        Err(types::Errno::NotFound)
+    }
+}
+
+const GUEST: &str = r#"
+(module
+  (import "kv" "get" (func $get (param i32 i32) (result i32)))
+  (memory (export "memory") 1)
+  (func (export "get") (param $key i32) (result i32)
+    (call $get (local.get $key) (i32.const 8))))
+"#;
+
+/// A span, with the fields recorded in it.
+#[derive(Debug, Default)]
+struct Recorded {
+    name: &'static str,
+    fields: Vec<(&'static str, String)>,
+}
+
+impl Visit for Recorded {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.fields.push((field.name(), format!("{:?}", value)));
+    }
+}
+
+/// A subscriber that keeps every span it is told of.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<Recorded>>>);
+
+impl Subscriber for Recorder {
+    fn enabled(&self, _metadata: &Metadata<'_>) -> bool {
+        true
+    }
+
+    fn new_span(&self, attrs: &span::Attributes<'_>) -> span::Id {
+        let mut spans = self.0.lock().unwrap();
+        let mut recorded = Recorded {
+            name: attrs.metadata().name(),
+            ..Recorded::default()
+        };
+        attrs.record(&mut recorded);
+        spans.push(recorded);
+        span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &span::Id, values: &span::Record<'_>) {
+        let mut spans = self.0.lock().unwrap();
+        values.record(&mut spans[id.into_u64() as usize - 1]);
+    }
+
+    fn record_follows_from(&self, _span: &span::Id, _follows: &span::Id) {}
+
+    fn event(&self, _event: &Event<'_>) {}
+
+    fn enter(&self, _span: &span::Id) {}
+
+    fn exit(&self, _span: &span::Id) {}
+}
+
+#[test]
+fn hostcalls_open_spans() {
+    crate::hostcalls::init();
+    lucet_runtime::lucet_internal_ensure_linked();
+
+    let workdir = TempDir::new().expect("create working directory");
+    let wat_file = workdir.path().join("guest.wat");
+    std::fs::write(&wat_file, GUEST).expect("write guest");
+    let so_file = workdir.path().join("out.so");
+    Lucetc::new(wat_file)
+        .with_bindings(crate::hostcalls::bindings())
+        .shared_object_file(so_file.clone())
+        .expect("build so");
+    let module = DlModule::load(so_file).expect("load so");
+    let region = MmapRegion::create(1, &Limits::default()).expect("create region");
+    let mut inst = region.new_instance(module).expect("create instance");
+
+    let recorder = Recorder::default();
+    let res = lucet_wiggle::tracing::subscriber::with_default(recorder.clone(), || {
+        inst.run("get", &[7u32.into()]).expect("run get")
+    });
+    assert_eq!(
+        res.unwrap_returned().as_u32(),
+        types::Errno::NotFound as u32
+    );
+
+    let spans = recorder.0.lock().unwrap();
+    let span = spans
+        .iter()
+        .find(|span| span.name == "get")
+        .expect("the hostcall opens a span named after its function");
+    assert_eq!(
+        span.fields,
+        vec![
+            ("module", "\"kv\"".to_owned()),
+            // `get` failed, so it stored nothing through the result pointer
+            (
+                "args",
+                "[(\"key\", Value(7)), (\"value\", Out(Some(0)))]".to_owned()
+            ),
+            ("result", "1".to_owned()),
+        ]
+    );
+}
@@ -66,6 +66,11 @@ pub fn run(opts: &Options) -> Result<(), Error> {
         return Ok(());
     }
 
+    if opts.codegen == CodegenOutput::CHeader {
+        c_header(opts)?;
+        return Ok(());
+    }
+
     let input = &match opts.input.len() {
         0 => Err(format_err!("must provide at least one input")),
         1 => Ok(opts.input[0].clone()),
@@ -123,6 +128,10 @@ pub fn run(opts: &Options) -> Result<(), Error> {
         c.guard_size(guard_size);
     }
 
+    if let Some(chunk_size) = opts.sparse_data_chunk_size {
+        c.sparse_data_chunk_size(chunk_size);
+    }
+
     if let Some(pk_path) = &opts.pk_path {
         c.pk(PublicKey::from_file(pk_path)?);
     }
@@ -143,11 +152,40 @@ pub fn run(opts: &Options) -> Result<(), Error> {
         c.count_instructions(true);
     }
 
+    if opts.canonicalize_nans {
+        c.canonicalize_nans(true);
+    }
+
+    if opts.compress_data {
+        c.compress_data(true);
+    }
+
+    for name in &opts.preserve_custom_sections {
+        c.preserve_custom_section(name.clone());
+    }
+
+    if let Some(symbol_prefix) = &opts.symbol_prefix {
+        c.symbol_prefix(symbol_prefix.clone());
+    }
+
     match opts.codegen {
         CodegenOutput::Obj => c.object_file(&opts.output)?,
         CodegenOutput::SharedObj => c.shared_object_file(&opts.output)?,
         CodegenOutput::Clif => c.clif_ir(&opts.output)?,
+        CodegenOutput::CHeader => unreachable!("C headers are written without compiling"),
+    }
+    Ok(())
+}
+
+fn c_header(opts: &Options) -> Result<(), Error> {
+    if opts.witx_specs.is_empty() {
+        return Err(format_err!(
+            "Generating a C header requires --witx to specify the interface to declare"
+        ));
     }
+    let validator = Validator::load(&opts.witx_specs)?;
+    let header = lucet_wiggle_generate::c_header(validator.doc(), &opts.header_prefix);
+    std::fs::write(&opts.output, header)?;
     Ok(())
 }
 
@@ -1,6 +1,7 @@
 use anyhow::Error;
 use clap::{Arg, ArgMatches, Values};
 use lucetc::{CpuFeatures, HeapSettings, OptLevel, SpecificFeature, TargetCpu};
+use std::convert::TryFrom;
 use std::path::PathBuf;
 use std::str::FromStr;
 use target_lexicon::{Architecture, Triple};
@@ -10,6 +11,7 @@ pub enum CodegenOutput {
     Clif,
     Obj,
     SharedObj,
+    CHeader,
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -112,6 +114,7 @@ pub struct Options {
     pub max_reserved_size: Option<u64>,
     pub reserved_size: Option<u64>,
     pub guard_size: Option<u64>,
+    pub sparse_data_chunk_size: Option<u32>,
     pub opt_level: OptLevel,
     pub cpu_features: CpuFeatures,
     pub keygen: bool,
@@ -120,6 +123,11 @@ pub struct Options {
     pub pk_path: Option<PathBuf>,
     pub sk_path: Option<PathBuf>,
     pub count_instructions: bool,
+    pub canonicalize_nans: bool,
+    pub compress_data: bool,
+    pub preserve_custom_sections: Vec<String>,
+    pub symbol_prefix: Option<String>,
+    pub header_prefix: String,
     pub error_style: ErrorStyle,
     pub target: Triple,
 }
@@ -153,6 +161,7 @@ impl Options {
             Some("clif") => CodegenOutput::Clif,
             Some("obj") => CodegenOutput::Obj,
             Some("so") => CodegenOutput::SharedObj,
+            Some("c-header") => CodegenOutput::CHeader,
             Some(_) => panic!("unknown value for emit"),
         };
 
@@ -180,6 +189,13 @@ impl Options {
             None
         };
 
+        let sparse_data_chunk_size =
+            if let Some(chunk_size_str) = m.value_of("sparse_data_chunk_size") {
+                Some(u32::try_from(parse_humansized(chunk_size_str)?)?)
+            } else {
+                None
+            };
+
         let opt_level = match m.value_of("opt_level") {
             None => OptLevel::SpeedAndSize,
             Some("0") | Some("none") => OptLevel::None,
@@ -211,6 +227,15 @@ impl Options {
         let sk_path = m.value_of("sk_path").map(PathBuf::from);
         let pk_path = m.value_of("pk_path").map(PathBuf::from);
         let count_instructions = m.is_present("count_instructions");
+        let canonicalize_nans = m.is_present("canonicalize_nans");
+        let compress_data = m.is_present("compress_data");
+        let preserve_custom_sections = m
+            .values_of("preserve_custom_sections")
+            .unwrap_or_default()
+            .map(str::to_owned)
+            .collect();
+        let symbol_prefix = m.value_of("symbol_prefix").map(str::to_owned);
+        let header_prefix = m.value_of("header_prefix").unwrap_or("witx").to_owned();
 
         let error_style = match m.value_of("error_style") {
             None => ErrorStyle::default(),
@@ -231,6 +256,7 @@ impl Options {
             max_reserved_size,
             reserved_size,
             guard_size,
+            sparse_data_chunk_size,
             opt_level,
             cpu_features,
             keygen,
@@ -239,6 +265,11 @@ impl Options {
             sk_path,
             pk_path,
             count_instructions,
+            canonicalize_nans,
+            compress_data,
+            preserve_custom_sections,
+            symbol_prefix,
+            header_prefix,
             error_style,
             target,
         })
@@ -256,8 +287,8 @@ impl Options {
                 Arg::with_name("emit")
                     .long("emit")
                     .takes_value(true)
-                    .possible_values(&["obj", "so", "clif"])
-                    .help("type of code to generate (default: so)"),
+                    .possible_values(&["obj", "so", "clif", "c-header"])
+                    .help("type of code to generate (default: so). c-header is a C header for guests of the --witx specs, and needs no input"),
             )
             .arg(
                 Arg::with_name("output")
@@ -403,6 +434,16 @@ SSE3 but not AVX:
                         humansized(HeapSettings::default().guard_size)
                     )),
             )
+            .arg(
+                Arg::with_name("sparse_data_chunk_size")
+                    .long("--sparse-data-chunk-size")
+                    .takes_value(true)
+                    .multiple(false)
+                    .help(&format!(
+                        "size of the chunks the initial contents of linear memory are recorded in. must be a power of two from 512 to 64k. default: {}",
+                        humansized(HeapSettings::default().sparse_data_chunk_size as u64)
+                    )),
+            )
             .arg(
                 Arg::with_name("input")
                     .multiple(false)
@@ -452,6 +493,38 @@ SSE3 but not AVX:
                     .takes_value(false)
                     .help("Instrument the produced binary to count the number of wasm operations the translated program executes")
             )
+            .arg(
+                Arg::with_name("canonicalize_nans")
+                    .long("--canonicalize-nans")
+                    .takes_value(false)
+                    .help("Canonicalize the NaNs produced by floating-point operations, so that the program computes identical results on every host")
+            )
+            .arg(
+                Arg::with_name("compress_data")
+                    .long("--compress-data")
+                    .takes_value(false)
+                    .help("Compress the initial contents of the linear memory with zstd, for a smaller object file at the cost of decompressing them when the module is loaded")
+            )
+            .arg(
+                Arg::with_name("preserve_custom_sections")
+                    .long("--preserve-custom-section")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Name of a custom section of the module to preserve, so that the runtime can read its contents")
+            )
+            .arg(
+                Arg::with_name("symbol_prefix")
+                    .long("--symbol-prefix")
+                    .takes_value(true)
+                    .help("Prefix for the symbols exported from the object file, so that several modules can be linked into one executable (the module is then `<prefix>lucet_module`); only with `--emit obj`")
+            )
+            .arg(
+                Arg::with_name("header_prefix")
+                    .long("--header-prefix")
+                    .takes_value(true)
+                    .help("Prefix for the names a C header declares (default: witx)")
+            )
             .arg(
                 Arg::with_name("error_style")
                     .long("error-style")
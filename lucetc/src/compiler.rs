@@ -64,6 +64,9 @@ pub struct CompilerBuilder {
     count_instructions: bool,
     canonicalize_nans: bool,
     validator: Option<Validator>,
+    symbol_prefix: String,
+    compress_data: bool,
+    preserve_custom_sections: Vec<String>,
 }
 
 impl CompilerBuilder {
@@ -76,6 +79,9 @@ impl CompilerBuilder {
             count_instructions: false,
             canonicalize_nans: false,
             validator: None,
+            symbol_prefix: String::new(),
+            compress_data: false,
+            preserve_custom_sections: vec![],
         }
     }
 
@@ -83,6 +89,10 @@ impl CompilerBuilder {
         &self.target
     }
 
+    pub(crate) fn symbol_prefix_ref(&self) -> &str {
+        &self.symbol_prefix
+    }
+
     pub fn target(&mut self, target: Triple) {
         self.target = target;
     }
@@ -154,6 +164,39 @@ impl CompilerBuilder {
         self
     }
 
+    /// Prefix the symbols exported from the object, so that object files for several modules can
+    /// be linked into the same executable.
+    pub fn symbol_prefix(&mut self, symbol_prefix: String) {
+        self.symbol_prefix = symbol_prefix;
+    }
+
+    pub fn with_symbol_prefix(mut self, symbol_prefix: String) -> Self {
+        self.symbol_prefix(symbol_prefix);
+        self
+    }
+
+    /// Compress the pages of the initial heap with zstd, for a smaller shared object at the cost
+    /// of decompressing them when the module is loaded.
+    pub fn compress_data(&mut self, compress_data: bool) {
+        self.compress_data = compress_data;
+    }
+
+    pub fn with_compress_data(mut self, compress_data: bool) -> Self {
+        self.compress_data(compress_data);
+        self
+    }
+
+    /// Preserve the custom sections named `name` in the module data, where the runtime can read
+    /// them. Other custom sections are dropped.
+    pub fn preserve_custom_section(&mut self, name: String) {
+        self.preserve_custom_sections.push(name);
+    }
+
+    pub fn with_preserve_custom_section(mut self, name: String) -> Self {
+        self.preserve_custom_section(name);
+        self
+    }
+
     pub fn create<'a>(
         &'a self,
         wasm_binary: &'a [u8],
@@ -169,6 +212,9 @@ impl CompilerBuilder {
             self.count_instructions,
             &self.validator,
             self.canonicalize_nans,
+            &self.symbol_prefix,
+            self.compress_data,
+            &self.preserve_custom_sections,
         )
     }
 }
@@ -182,6 +228,8 @@ pub struct Compiler<'a> {
     count_instructions: bool,
     module_translation_state: ModuleTranslationState,
     canonicalize_nans: bool,
+    symbol_prefix: String,
+    compress_data: bool,
 }
 
 impl<'a> Compiler<'a> {
@@ -195,6 +243,9 @@ impl<'a> Compiler<'a> {
         count_instructions: bool,
         validator: &Option<Validator>,
         canonicalize_nans: bool,
+        symbol_prefix: &str,
+        compress_data: bool,
+        preserve_custom_sections: &[String],
     ) -> Result<Self, Error> {
         let isa = Self::target_isa(target.clone(), opt_level, &cpu_features, canonicalize_nans)?;
 
@@ -222,6 +273,11 @@ impl<'a> Compiler<'a> {
                 WasmError::Unsupported(s) => Error::Unsupported(s),
                 WasmError::ImplLimitExceeded { .. } => Error::ClifWasmError(e),
             })?;
+        module_info.custom_sections.retain(|section| {
+            preserve_custom_sections
+                .iter()
+                .any(|name| name == section.name)
+        });
 
         let libcalls = Box::new(move |libcall| match libcall {
             ir::LibCall::Probestack => stack_probe::STACK_PROBE_SYM.to_owned(),
@@ -239,6 +295,7 @@ impl<'a> Compiler<'a> {
             bindings,
             runtime,
             heap_settings,
+            symbol_prefix,
         )?;
 
         Ok(Self {
@@ -250,6 +307,8 @@ impl<'a> Compiler<'a> {
             module_translation_state,
             target,
             canonicalize_nans,
+            symbol_prefix: symbol_prefix.to_owned(),
+            compress_data,
         })
     }
 
@@ -260,6 +319,7 @@ impl<'a> Compiler<'a> {
     pub fn module_features(&self) -> ModuleFeatures {
         let mut mf: ModuleFeatures = (&self.cpu_features).into();
         mf.instruction_count = self.count_instructions;
+        mf.nan_canonicalization = self.canonicalize_nans;
         mf
     }
 
@@ -324,7 +384,12 @@ impl<'a> Compiler<'a> {
 
         function_map.insert(probe_func_id, (size, trap_data_id, stack_probe_traps.len()));
 
-        let module_data_bytes = self.module_data()?.serialize()?;
+        let module_data = self.module_data()?;
+        let module_data_bytes = if self.compress_data {
+            module_data.serialize_compressed()?
+        } else {
+            module_data.serialize()?
+        };
 
         let module_data_len = module_data_bytes.len();
 
@@ -384,7 +449,7 @@ impl<'a> Compiler<'a> {
             Cursor::new(Vec::with_capacity(std::mem::size_of::<SerializedModule>()));
         let mut native_data_ctx = ClifDataContext::new();
         let native_data_id = self.clif_module.declare_data(
-            LUCET_MODULE_SYM,
+            &format!("{}{}", self.symbol_prefix, LUCET_MODULE_SYM),
             ClifLinkage::Export,
             false,
             false,
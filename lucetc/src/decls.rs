@@ -4,7 +4,7 @@ pub use crate::module::{Exportable, TableElems};
 use crate::module::{ModuleInfo, UniqueFuncIndex};
 use crate::name::Name;
 use crate::runtime::{Runtime, RuntimeFunc};
-use crate::table::TABLE_SYM;
+use crate::table::{table_spec, TABLE_SYM};
 use crate::types::to_lucet_signature;
 use cranelift_codegen::entity::{EntityRef, PrimaryMap};
 use cranelift_codegen::ir;
@@ -17,9 +17,9 @@ use cranelift_wasm::{
 use lucet_module::bindings::Bindings;
 use lucet_module::ModuleFeatures;
 use lucet_module::{
-    owned::OwnedLinearMemorySpec, ExportFunction, FunctionIndex as LucetFunctionIndex,
+    owned::OwnedLinearMemorySpec, ExportFunction, ExportTable, FunctionIndex as LucetFunctionIndex,
     FunctionMetadata, Global as GlobalVariant, GlobalDef, GlobalSpec, HeapSpec, ImportFunction,
-    ModuleData, Signature as LucetSignature, UniqueSignatureIndex,
+    ModuleData, Signature as LucetSignature, UniqueSignatureIndex, ValueType,
 };
 use std::collections::HashMap;
 use wasmparser::FuncType;
@@ -84,6 +84,7 @@ impl<'a> ModuleDecls<'a> {
         bindings: &'a Bindings,
         runtime: Runtime,
         heap_settings: HeapSettings,
+        symbol_prefix: &str,
     ) -> Result<Self, Error> {
         let imports: Vec<ImportFunction<'a>> = Vec::with_capacity(info.imported_funcs.len());
         let (tables_list_name, table_names) = Self::declare_tables(&info, clif_module)?;
@@ -101,7 +102,7 @@ impl<'a> ModuleDecls<'a> {
             linear_memory_spec,
         };
 
-        Self::declare_funcs(&mut decls, clif_module, bindings)?;
+        Self::declare_funcs(&mut decls, clif_module, bindings, symbol_prefix)?;
         Self::declare_runtime(&mut decls, clif_module, runtime)?;
 
         Ok(decls)
@@ -113,6 +114,7 @@ impl<'a> ModuleDecls<'a> {
         decls: &mut ModuleDecls<'a>,
         clif_module: &mut ClifModule<B>,
         bindings: &'a Bindings,
+        symbol_prefix: &str,
     ) -> Result<(), Error> {
         // Get the name for this function from the module names section, if it exists.
         // Because names have to be unique, we append the index value (ix) to the name.
@@ -131,6 +133,7 @@ impl<'a> ModuleDecls<'a> {
         fn export_name_for<'a>(
             func_ix: UniqueFuncIndex,
             decls: &mut ModuleDecls<'a>,
+            symbol_prefix: &str,
         ) -> Option<String> {
             let export = decls.info.functions.get(func_ix).unwrap();
             if !export.export_names.is_empty() {
@@ -138,7 +141,10 @@ impl<'a> ModuleDecls<'a> {
                     fn_idx: LucetFunctionIndex::from_u32(decls.function_names.len() as u32),
                     names: export.export_names.clone(),
                 });
-                Some(format!("guest_func_{}", export.export_names[0]))
+                Some(format!(
+                    "{}guest_func_{}",
+                    symbol_prefix, export.export_names[0]
+                ))
             } else {
                 None
             }
@@ -165,7 +171,7 @@ impl<'a> ModuleDecls<'a> {
         for ix in 0..decls.info.functions.len() {
             let func_index = UniqueFuncIndex::new(ix);
             let import_info = import_name_for(func_index, decls, bindings)?;
-            let export_info = export_name_for(func_index, decls);
+            let export_info = export_name_for(func_index, decls, symbol_prefix);
 
             match (import_info, export_info) {
                 (Some(import_sym), _) => {
@@ -293,12 +299,14 @@ impl<'a> ModuleDecls<'a> {
         heap_settings: HeapSettings,
     ) -> Result<Option<OwnedLinearMemorySpec>, Error> {
         use crate::sparsedata::owned_sparse_data_from_initializers;
+        let chunk_size = heap_settings.sparse_data_chunk_size;
         if let Some(heap_spec) = Self::build_heap_spec(info, heap_settings)? {
             let data_initializers = info
                 .data_initializers
                 .get(&MemoryIndex::new(0))
                 .expect("heap spec implies data initializers should exist");
-            let sparse_data = owned_sparse_data_from_initializers(data_initializers, &heap_spec)?;
+            let sparse_data = owned_sparse_data_from_initializers(data_initializers, &heap_spec)?
+                .with_chunk_size(chunk_size)?;
 
             Ok(Some(OwnedLinearMemorySpec {
                 heap: heap_spec,
@@ -349,7 +357,22 @@ impl<'a> ModuleDecls<'a> {
                 }
             }?;
 
-            globals.push(GlobalSpec::new(global, g_decl.export_names.clone()));
+            let imported = matches!(global, GlobalVariant::Import { .. });
+            let spec = GlobalSpec::new(global, g_decl.export_names.clone())
+                .with_mutability(g_decl.entity.mutability);
+            let spec = if imported {
+                let import_type = match g_decl.entity.ty {
+                    ir::types::I32 => ValueType::I32,
+                    ir::types::I64 => ValueType::I64,
+                    ir::types::F32 => ValueType::F32,
+                    ir::types::F64 => ValueType::F64,
+                    _ => return Err(Error::GlobalUnsupported(ix.as_u32())),
+                };
+                spec.with_import_type(import_type)
+            } else {
+                spec
+            };
+            globals.push(spec);
         }
         Ok(globals)
     }
@@ -540,6 +563,36 @@ impl<'a> ModuleDecls<'a> {
             })
             .collect::<Result<Vec<LucetSignature>, Error>>()?;
 
+        let export_memory_names = self
+            .info
+            .memories
+            .get(MemoryIndex::new(0))
+            .map(|memory| memory.export_names.clone())
+            .unwrap_or_default();
+
+        let export_tables = self
+            .info
+            .tables
+            .iter()
+            .filter(|(_, table)| !table.export_names.is_empty())
+            .map(|(table_index, table)| ExportTable {
+                table_idx: table_index.index() as u32,
+                names: table.export_names.clone(),
+            })
+            .collect();
+
+        // as in `write_table_data`, only the tables whose elements this module declares
+        let tables = self
+            .info
+            .tables
+            .keys()
+            .filter_map(|table_index| {
+                self.get_table(table_index)
+                    .ok()
+                    .map(|decl| table_spec(table_index, &decl))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
         Ok(ModuleData::new(
             linear_memory,
             self.globals_spec.clone(),
@@ -549,6 +602,10 @@ impl<'a> ModuleDecls<'a> {
             signatures,
             features,
             start_func,
-        ))
+        )
+        .with_export_memory_names(export_memory_names)
+        .with_export_tables(export_tables)
+        .with_tables(tables)
+        .with_custom_sections(self.info.custom_sections.clone()))
     }
 }
@@ -3,6 +3,9 @@ pub struct HeapSettings {
     pub min_reserved_size: u64,
     pub max_reserved_size: u64,
     pub guard_size: u64,
+    /// The size of the chunks the module records the initial heap in, a power of two from 512
+    /// bytes to 64 KiB.
+    pub sparse_data_chunk_size: u32,
 }
 
 impl Default for HeapSettings {
@@ -11,6 +14,7 @@ impl Default for HeapSettings {
             min_reserved_size: 4 * 1024 * 1024,
             max_reserved_size: 6 * 1024 * 1024 * 1024,
             guard_size: 4 * 1024 * 1024,
+            sparse_data_chunk_size: 4096,
         }
     }
 }
@@ -26,6 +26,7 @@ pub use crate::{
     load::read_module,
 };
 pub use lucet_module::bindings::Bindings;
+use lucet_module::ModuleIntegrity;
 pub use lucet_validate::Validator;
 use signature::{PublicKey, SecretKey};
 use std::env;
@@ -91,6 +92,12 @@ pub trait LucetcOpts {
 
     fn guard_size(&mut self, guard_size: u64);
     fn with_guard_size(self, guard_size: u64) -> Self;
+    /// Record the initial heap in chunks of `chunk_size` bytes, a power of two from 512 bytes to
+    /// 64 KiB, rather than in 4 KiB pages.
+    fn sparse_data_chunk_size(&mut self, chunk_size: u32);
+    /// Record the initial heap in chunks of `chunk_size` bytes, a power of two from 512 bytes to
+    /// 64 KiB, rather than in 4 KiB pages.
+    fn with_sparse_data_chunk_size(self, chunk_size: u32) -> Self;
 
     fn pk(&mut self, pk: PublicKey);
     fn with_pk(self, pk: PublicKey) -> Self;
@@ -104,6 +111,24 @@ pub trait LucetcOpts {
     fn with_count_instructions(self, enable_count: bool) -> Self;
     fn canonicalize_nans(&mut self, enable_canonicalize_nans: bool);
     fn with_canonicalize_nans(self, enable_canonicalize_nans: bool) -> Self;
+    /// Prefix the symbols exported from the object, so that object files for several modules can
+    /// be linked into the same executable.
+    fn symbol_prefix(&mut self, symbol_prefix: String);
+    /// Prefix the symbols exported from the object, so that object files for several modules can
+    /// be linked into the same executable.
+    fn with_symbol_prefix(self, symbol_prefix: String) -> Self;
+    /// Compress the pages of the initial heap with zstd, for a smaller shared object at the cost
+    /// of decompressing them when the module is loaded.
+    fn compress_data(&mut self, compress_data: bool);
+    /// Compress the pages of the initial heap with zstd, for a smaller shared object at the cost
+    /// of decompressing them when the module is loaded.
+    fn with_compress_data(self, compress_data: bool) -> Self;
+    /// Preserve the custom sections named `name` in the module data, where the runtime can read
+    /// them.
+    fn preserve_custom_section(&mut self, name: String);
+    /// Preserve the custom sections named `name` in the module data, where the runtime can read
+    /// them.
+    fn with_preserve_custom_section(self, name: String) -> Self;
 }
 
 impl<T: AsLucetc> LucetcOpts for T {
@@ -201,6 +226,18 @@ impl<T: AsLucetc> LucetcOpts for T {
         self
     }
 
+    fn sparse_data_chunk_size(&mut self, chunk_size: u32) {
+        self.as_lucetc()
+            .builder
+            .heap_settings_mut()
+            .sparse_data_chunk_size = chunk_size;
+    }
+
+    fn with_sparse_data_chunk_size(mut self, chunk_size: u32) -> Self {
+        self.sparse_data_chunk_size(chunk_size);
+        self
+    }
+
     fn pk(&mut self, pk: PublicKey) {
         self.as_lucetc().pk = Some(pk);
     }
@@ -258,6 +295,33 @@ impl<T: AsLucetc> LucetcOpts for T {
         self.canonicalize_nans(enable_nans_canonicalization);
         self
     }
+
+    fn symbol_prefix(&mut self, symbol_prefix: String) {
+        self.as_lucetc().builder.symbol_prefix(symbol_prefix);
+    }
+
+    fn with_symbol_prefix(mut self, symbol_prefix: String) -> Self {
+        self.symbol_prefix(symbol_prefix);
+        self
+    }
+
+    fn compress_data(&mut self, compress_data: bool) {
+        self.as_lucetc().builder.compress_data(compress_data);
+    }
+
+    fn with_compress_data(mut self, compress_data: bool) -> Self {
+        self.compress_data(compress_data);
+        self
+    }
+
+    fn preserve_custom_section(&mut self, name: String) {
+        self.as_lucetc().builder.preserve_custom_section(name);
+    }
+
+    fn with_preserve_custom_section(mut self, name: String) -> Self {
+        self.preserve_custom_section(name);
+        self
+    }
 }
 
 impl Lucetc {
@@ -322,10 +386,18 @@ impl Lucetc {
     }
 
     pub fn shared_object_file(&self, output: impl AsRef<Path>) -> Result<(), Error> {
+        // the runtime finds a shared object's module by the unprefixed `lucet_module` symbol
+        if !self.builder.symbol_prefix_ref().is_empty() {
+            return Err(Error::Input(
+                "a symbol prefix is only for object files to link into an executable".to_string(),
+            ));
+        }
         let dir = tempfile::Builder::new().prefix("lucetc").tempdir()?;
         let objpath = dir.path().join("tmp.o");
         self.object_file(objpath.clone())?;
         link_so(objpath, self.builder.target_ref(), &output)?;
+        // the signature covers the integrity hash, so it must be recorded first
+        ModuleIntegrity::embed(&output)?;
         if self.sign {
             let sk = self.sk.as_ref().ok_or(Error::Signature(
                 "signing requires a secret key".to_string(),
@@ -22,7 +22,13 @@ pub fn read_module(
 }
 
 pub fn read_bytes(bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
-    if wasm_preamble(&bytes) {
+    if component_preamble(&bytes) {
+        Err(Error::Unsupported(
+            "input is a WebAssembly component; lucetc only compiles core modules, such as \
+             WASI preview1 programs"
+                .to_owned(),
+        ))
+    } else if wasm_preamble(&bytes) {
         Ok(bytes)
     } else {
         wat2wasm(bytes).map_err(|err| {
@@ -44,6 +50,12 @@ pub fn read_bytes(bytes: Vec<u8>) -> Result<Vec<u8>, Error> {
     }
 }
 
+/// Whether `buf` starts like a component of the component model, whose version is followed by a
+/// layer of 1, where that of a core module is 0.
+pub fn component_preamble(buf: &[u8]) -> bool {
+    buf.len() >= 8 && buf[0..4] == [0, 97, 115, 109] && buf[6..8] == [1, 0]
+}
+
 pub fn wasm_preamble(buf: &[u8]) -> bool {
     if buf.len() > 4 {
         buf[0..4] == [0, 97, 115, 109]
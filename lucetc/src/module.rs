@@ -9,7 +9,7 @@ use cranelift_wasm::{
     ModuleTranslationState, SignatureIndex, Table, TableElementType, TableIndex, TargetEnvironment,
     WasmResult,
 };
-use lucet_module::UniqueSignatureIndex;
+use lucet_module::{CustomSection, UniqueSignatureIndex};
 use std::collections::{hash_map::Entry, HashMap};
 use wasmparser::FuncType;
 
@@ -92,6 +92,9 @@ pub struct ModuleInfo<'a> {
 
     /// Data initializers: local only
     pub data_initializers: HashMap<MemoryIndex, Vec<DataInitializer<'a>>>,
+
+    /// Provided by `custom_section`, except for the name section
+    pub custom_sections: Vec<CustomSection<'a>>,
 }
 
 impl<'a> ModuleInfo<'a> {
@@ -114,6 +117,7 @@ impl<'a> ModuleInfo<'a> {
             function_bodies: HashMap::new(),
             table_elems: HashMap::new(),
             data_initializers: HashMap::new(),
+            custom_sections: vec![],
         }
     }
 
@@ -432,4 +436,9 @@ impl<'a> ModuleEnvironment<'a> for ModuleInfo<'a> {
     fn declare_passive_data(&mut self, _data_index: DataIndex, _data: &'a [u8]) -> WasmResult<()> {
         unimplemented!();
     }
+
+    fn custom_section(&mut self, name: &'a str, data: &'a [u8]) -> WasmResult<()> {
+        self.custom_sections.push(CustomSection { name, data });
+        Ok(())
+    }
 }
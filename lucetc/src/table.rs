@@ -6,6 +6,7 @@ use byteorder::{LittleEndian, WriteBytesExt};
 use cranelift_codegen::entity::EntityRef;
 use cranelift_module::{Backend as ClifBackend, DataContext, DataId, Module as ClifModule};
 use cranelift_wasm::{TableElementType, TableIndex};
+use lucet_module::{ElementSegment, FunctionIndex, TableSpec};
 use std::io::Cursor;
 
 /// This symbol will be used to reference the `tables` field in `Module` - a sequence of tables.
@@ -60,6 +61,42 @@ fn table_elements(decl: &TableDecl<'_>) -> Result<Vec<Elem>, Error> {
     Ok(elems)
 }
 
+fn lucet_function_index(func_index: UniqueFuncIndex) -> FunctionIndex {
+    // the functions of the module data are in the order of their unique indices
+    FunctionIndex::from_u32(func_index.as_u32())
+}
+
+/// Describe a table for the module data: its limits, its element segments, and the function in
+/// each of its initial elements.
+pub fn table_spec(table_index: TableIndex, decl: &TableDecl<'_>) -> Result<TableSpec, Error> {
+    let elements = table_elements(decl)?
+        .into_iter()
+        .map(|elem| match elem {
+            Elem::Func(func_index) => Some(lucet_function_index(func_index)),
+            Elem::Empty => None,
+        })
+        .collect();
+    let segments = decl
+        .elems
+        .iter()
+        .map(|initializer| ElementSegment {
+            offset: initializer.offset as u32,
+            functions: initializer
+                .elements
+                .iter()
+                .map(|func_index| lucet_function_index(*func_index))
+                .collect(),
+        })
+        .collect();
+    Ok(TableSpec {
+        table_idx: table_index.as_u32(),
+        minimum: decl.table.minimum,
+        maximum: decl.table.maximum,
+        segments,
+        elements,
+    })
+}
+
 pub fn write_table_data<B: ClifBackend>(
     clif_module: &mut ClifModule<B>,
     decls: &ModuleDecls<'_>,
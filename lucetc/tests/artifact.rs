@@ -0,0 +1,159 @@
+use lucet_module::bindings::Bindings;
+use lucet_module::{Error, Global, GlobalDef, ModuleArtifact, MODULE_FORMAT_VERSION};
+use lucetc::signature::{KeyPair, SecretKey};
+use lucetc::{Lucetc, LucetcOpts};
+use std::collections::HashMap;
+use tempfile::TempDir;
+
+const GUEST: &str = r#"
+(module
+  (import "env" "inc" (func $inc (param i32) (result i32)))
+  (memory (export "memory") 1 2)
+  (global $count (export "count") (mut i64) (i64.const 7))
+  (data (i32.const 0) "hello")
+  (func $twice (export "twice") (export "double") (param $x i32) (result i32)
+    (call $inc (i32.add (local.get $x) (local.get $x)))))
+"#;
+
+const TABLE_GUEST: &str = r#"
+(module
+  (table (export "table") 4 funcref)
+  (elem (i32.const 1) $one $two)
+  (elem (i32.const 3) $one)
+  (func $one (result i32) (i32.const 1))
+  (func $two (result i32) (i32.const 2)))
+"#;
+
+/// Compile the guest to a shared object, signed with `sk` if there is one, and return its bytes.
+fn compile(sk: Option<SecretKey>) -> Vec<u8> {
+    compile_wat(GUEST, sk)
+}
+
+fn compile_wat(wat: &str, sk: Option<SecretKey>) -> Vec<u8> {
+    let workdir = TempDir::new().expect("create working directory");
+    let wat_file = workdir.path().join("guest.wat");
+    std::fs::write(&wat_file, wat).expect("write guest");
+    let bindings = Bindings::env(
+        vec![("inc".to_owned(), "host_inc".to_owned())]
+            .into_iter()
+            .collect::<HashMap<_, _>>(),
+    );
+    let mut lucetc = Lucetc::new(wat_file).with_bindings(bindings);
+    if let Some(sk) = sk {
+        lucetc = lucetc.with_sk(sk).with_sign();
+    }
+    let so_file = workdir.path().join("out.so");
+    lucetc.shared_object_file(&so_file).expect("build so");
+    std::fs::read(so_file).expect("read so")
+}
+
+#[test]
+fn read_artifact() {
+    let bytes = compile(None);
+    let artifact = ModuleArtifact::parse(&bytes).expect("parse artifact");
+    assert_eq!(artifact.version().format_version(), MODULE_FORMAT_VERSION);
+
+    let module_data = artifact.module_data();
+    let imports = module_data.import_functions();
+    assert_eq!(imports.len(), 1);
+    assert_eq!((imports[0].module, imports[0].name), ("env", "inc"));
+
+    let export = module_data
+        .export_functions()
+        .iter()
+        .find(|export| export.names.contains(&"twice"))
+        .expect("twice is exported");
+    assert!(export.names.contains(&"double"));
+    let signature = module_data.get_signature(export.fn_idx);
+    assert_eq!(signature.to_string(), "(I32) -> I32");
+
+    let heap = module_data.heap_spec().expect("module has a heap");
+    assert_eq!(heap.initial_size, 64 * 1024);
+    assert_eq!(heap.max_size, Some(2 * 64 * 1024));
+    let page = module_data.sparse_data().unwrap().get_page(0).unwrap();
+    assert_eq!(&page[..5], b"hello");
+    assert_eq!(module_data.export_memory_names(), ["memory"]);
+
+    let global = &module_data.globals_spec()[0];
+    assert_eq!(global.global(), &Global::Def(GlobalDef::I64(7)));
+    assert_eq!(global.export_names(), ["count"]);
+
+    assert_eq!(
+        artifact.function_manifest().len(),
+        module_data.function_info().len()
+    );
+    assert!(artifact
+        .function_manifest()
+        .iter()
+        .any(|f| f.code_len() > 0));
+
+    assert!(!artifact.is_signed());
+    assert_eq!(artifact.signer_key_id(), None);
+    artifact.check_integrity().expect("integrity hash matches");
+}
+
+#[test]
+fn check_artifact_signatures() {
+    let KeyPair { pk, sk } = KeyPair::generate_unencrypted_keypair().expect("generate key pair");
+    let bytes = compile(Some(sk));
+    let artifact = ModuleArtifact::parse(&bytes).expect("parse artifact");
+    assert!(artifact.is_signed());
+    assert!(artifact.signer_key_id().is_some());
+    artifact.verify_signature(&pk).expect("signature is valid");
+
+    let other = KeyPair::generate_unencrypted_keypair().expect("generate key pair");
+    assert!(artifact.verify_signature(&other.pk).is_err());
+
+    // the signature covers the integrity hash, and the hash does not change with the signature
+    assert!(artifact.module_data().has_integrity_hash());
+    artifact.check_integrity().expect("integrity hash matches");
+}
+
+#[test]
+fn check_artifact_integrity() {
+    let mut bytes = compile(None);
+    assert!(ModuleArtifact::parse(&bytes)
+        .expect("parse artifact")
+        .module_data()
+        .has_integrity_hash());
+
+    // the file is still a valid shared object, but not the one `lucetc` wrote
+    bytes.push(0);
+    let artifact = ModuleArtifact::parse(&bytes).expect("parse artifact");
+    assert!(matches!(
+        artifact.check_integrity(),
+        Err(Error::IntegrityHashMismatch)
+    ));
+}
+
+#[test]
+fn read_table_elements() {
+    let bytes = compile_wat(TABLE_GUEST, None);
+    let artifact = ModuleArtifact::parse(&bytes).expect("parse artifact");
+    let module_data = artifact.module_data();
+
+    let table = module_data.get_table(0).expect("table is described");
+    assert_eq!((table.minimum, table.maximum), (4, None));
+    let offsets = table
+        .segments
+        .iter()
+        .map(|segment| (segment.offset, segment.functions.len()))
+        .collect::<Vec<_>>();
+    assert_eq!(offsets, [(1, 2), (3, 1)]);
+
+    let name = |elem_idx| {
+        table
+            .element(elem_idx)
+            .and_then(|func| module_data.function_info()[func.as_u32() as usize].name)
+    };
+    assert_eq!(table.element(0), None);
+    assert_eq!(table.element(1), table.element(3));
+    assert_ne!(table.element(1), table.element(2));
+    assert!(name(1).unwrap().contains("one"));
+    assert!(name(2).unwrap().contains("two"));
+}
+
+#[test]
+fn reject_other_objects() {
+    assert!(ModuleArtifact::parse(b"not an object").is_err());
+}
@@ -58,6 +58,9 @@ mod module_data {
             false,
             &None,
             false,
+            "",
+            false,
+            &[],
         )
         .expect("compiling exported_import");
         let mdata = c.module_data().unwrap();
@@ -278,6 +281,7 @@ mod module_data {
             }
             _ => panic!("global should be an import"),
         }
+        assert_eq!(gspec[0].import_type(), Some(lucet_module::ValueType::I32));
     }
 
     #[test]
@@ -573,6 +577,9 @@ mod validate {
             false,
             &Some(v),
             false,
+            "",
+            false,
+            &[],
         )
         .expect("compile");
         let _obj = c.object_file().expect("codegen");
@@ -613,3 +620,18 @@ mod validate {
         let _obj = c.object_file().expect("codegen");
     }
 }
+
+mod load {
+    use lucetc::{Error, Lucetc};
+
+    #[test]
+    fn component_is_unsupported() {
+        // the preamble of a component, with no sections
+        let component = b"\0asm\x0d\0\x01\0";
+        match Lucetc::try_from_bytes(&component[..]) {
+            Err(Error::Unsupported(msg)) => assert!(msg.contains("component")),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("component is compiled"),
+        }
+    }
+}